@@ -0,0 +1,66 @@
+//! Benchmarks for the counting routines in `ngs::utils::vectorized` against the
+//! per-base loops they replace.
+//!
+//! Run with `cargo bench --bench counting`.
+
+mod support;
+
+use std::hint::black_box;
+
+use ngs::utils::vectorized::{count_bases, sum_quality_scores};
+use noodles::sam::record::{quality_scores::Score, sequence::Base};
+
+use support::{bench, Synthetic};
+
+/// The number of records in the batch.
+const RECORDS: usize = 10_000;
+
+/// The number of times each routine is run over the batch in a round.
+const ITERATIONS: usize = 20;
+
+/// The per-base loop that `count_bases` replaces.
+fn count_bases_per_base(bases: &[Base]) -> (usize, usize, usize) {
+    let (mut gc, mut at, mut other) = (0, 0, 0);
+    for base in bases {
+        match base {
+            Base::C | Base::G => gc += 1,
+            Base::A | Base::T => at += 1,
+            _ => other += 1,
+        }
+    }
+    (gc, at, other)
+}
+
+/// The per-base loop that `sum_quality_scores` replaces.
+fn sum_quality_scores_per_base(scores: &[Score]) -> usize {
+    scores.iter().map(|score| u8::from(*score) as usize).sum()
+}
+
+fn main() -> anyhow::Result<()> {
+    let synthetic = Synthetic::generate(RECORDS)?;
+    let records = &synthetic.records;
+    let bases = synthetic.bases() * ITERATIONS;
+
+    let run = |f: &dyn Fn(&noodles::sam::alignment::Record) -> usize| {
+        for _ in 0..ITERATIONS {
+            for record in records {
+                black_box(f(black_box(record)));
+            }
+        }
+    };
+
+    bench("count_bases (per base)", bases, "bases", || {
+        run(&|r| count_bases_per_base(r.sequence().as_ref()).0)
+    });
+    bench("count_bases (vectorized)", bases, "bases", || {
+        run(&|r| count_bases(r.sequence().as_ref()).gc)
+    });
+    bench("sum_quality_scores (per base)", bases, "bases", || {
+        run(&|r| sum_quality_scores_per_base(r.quality_scores().as_ref()))
+    });
+    bench("sum_quality_scores (vectorized)", bases, "bases", || {
+        run(&|r| sum_quality_scores(r.quality_scores().as_ref()))
+    });
+
+    Ok(())
+}
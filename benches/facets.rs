@@ -0,0 +1,101 @@
+//! Benchmarks for the `process` method of each of the quality control facets,
+//! over a batch of synthetic records.
+//!
+//! Run with `cargo bench --bench facets`. Every facet that can be loaded
+//! without external files is benchmarked, along with the facets that need a
+//! reference FASTA or a sites VCF (which are generated to match the records).
+//! For the sequence-based facets, only the processing of the records is timed
+//! (not setting up or tearing down the sequence).
+
+mod support;
+
+use std::rc::Rc;
+
+use ngs::{
+    qc::{get_qc_facets, FacetOptions},
+    utils::genome::get_reference_genome,
+};
+
+use support::{bench, Synthetic};
+
+/// The number of records in the batch.
+const RECORDS: usize = 50_000;
+
+fn main() -> anyhow::Result<()> {
+    let synthetic = Synthetic::generate(RECORDS)?;
+    let fasta = synthetic.write_fasta("facets")?;
+    let sites = synthetic.write_sites_vcf("facets")?;
+
+    let genome = get_reference_genome("GRCh38_no_alt_AnalysisSet")
+        .expect("the GRCh38 reference genome to be supported");
+    let options = FacetOptions::default();
+
+    // The facets are created once for each of the rounds, as they accumulate
+    // state as records are processed.
+    let facets = || {
+        get_qc_facets(
+            None,
+            None,
+            Some(&synthetic.header),
+            Some(fasta.clone()),
+            Some(sites.clone()),
+            Rc::new(get_reference_genome(genome.name()).unwrap()),
+            None,
+            &options,
+        )
+    };
+
+    let (record_facets, sequence_facets) = facets()?;
+    let records = &synthetic.records;
+
+    //=====================//
+    // Record-based facets //
+    //=====================//
+
+    for (i, facet) in record_facets.iter().enumerate() {
+        let name = facet.name();
+        let mut rounds = Vec::new();
+        for _ in 0..support::ROUNDS {
+            rounds.push(facets()?.0.remove(i));
+        }
+
+        bench(name, records.len(), "records", || {
+            let mut facet = rounds.pop().unwrap();
+            for record in records {
+                facet.process(record).unwrap();
+            }
+        });
+    }
+
+    //=======================//
+    // Sequence-based facets //
+    //=======================//
+
+    let (_, seq) = synthetic
+        .header
+        .reference_sequences()
+        .first()
+        .expect("the synthetic header to have a sequence");
+
+    for (i, facet) in sequence_facets.iter().enumerate() {
+        let name = facet.name();
+        let mut rounds = Vec::new();
+        for _ in 0..support::ROUNDS {
+            let mut facet = facets()?.1.remove(i);
+            facet.setup(seq)?;
+            rounds.push(facet);
+        }
+
+        bench(name, records.len(), "records", || {
+            let mut facet = rounds.pop().unwrap();
+            for record in records {
+                facet.process(seq, record).unwrap();
+            }
+        });
+    }
+
+    std::fs::remove_file(fasta)?;
+    std::fs::remove_file(sites)?;
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+//! Benchmarks for the reading layer: BGZF decompression and BAM record
+//! decoding (with and without reusing records), over an in-memory BAM file of
+//! synthetic records.
+//!
+//! Run with `cargo bench --bench readers`.
+
+mod support;
+
+use std::io::{Cursor, Read};
+
+use ngs::utils::formats::bam::for_each_record;
+use noodles::{bam, bgzf};
+
+use support::{bench, Synthetic};
+
+/// The number of records in the file.
+const RECORDS: usize = 200_000;
+
+fn main() -> anyhow::Result<()> {
+    let synthetic = Synthetic::generate(RECORDS)?;
+
+    let mut writer = bam::Writer::new(Vec::new());
+    writer.write_header(&synthetic.header)?;
+    writer.write_reference_sequences(synthetic.header.reference_sequences())?;
+    for record in &synthetic.records {
+        writer.write_record(&synthetic.header, record)?;
+    }
+    let data = writer.into_inner().finish()?;
+
+    let mut decompressed = Vec::new();
+    bgzf::Reader::new(Cursor::new(&data)).read_to_end(&mut decompressed)?;
+
+    println!(
+        "{} records: {} bytes compressed, {} bytes decompressed",
+        RECORDS,
+        data.len(),
+        decompressed.len()
+    );
+
+    bench("BGZF decompression", decompressed.len(), "bytes", || {
+        let mut buf = Vec::with_capacity(decompressed.len());
+        bgzf::Reader::new(Cursor::new(&data))
+            .read_to_end(&mut buf)
+            .unwrap();
+    });
+
+    for (name, reuse) in [
+        ("BAM records (reused)", true),
+        ("BAM records (allocated)", false),
+    ] {
+        bench(name, RECORDS, "records", || {
+            let mut reader = bam::Reader::new(Cursor::new(&data));
+            reader.read_header().unwrap();
+            reader.read_reference_sequences().unwrap();
+
+            let mut n = 0;
+            for_each_record(&mut reader, reuse, |_| {
+                n += 1;
+                Ok(false)
+            })
+            .unwrap();
+            assert_eq!(n, RECORDS);
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,214 @@
+//! Shared support for the benchmarks: a minimal timing harness and generators
+//! for synthetic records.
+//!
+//! The benchmarks are plain binaries (`harness = false`), as they only need to
+//! report reasonably stable timings. Each benchmark is run for several rounds,
+//! and the fastest round is reported to reduce noise from the rest of the
+//! system.
+
+// Not every benchmark uses every helper.
+#![allow(dead_code)]
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use noodles::{
+    core::Position,
+    sam::{
+        self,
+        alignment::Record,
+        header::record::value::{
+            map::{ReadGroup, ReferenceSequence},
+            Map,
+        },
+        record::{
+            data::field::{Field, Tag, Value},
+            Data, Flags, MappingQuality, QualityScores, ReadName, Sequence,
+        },
+    },
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// The number of rounds each benchmark is run for.
+pub const ROUNDS: usize = 5;
+
+/// The name of the sequence the synthetic records are aligned to. The
+/// mitochondrial genome is used as it is small, so the sequence-based facets
+/// (which allocate per position) are cheap to set up.
+pub const SEQUENCE_NAME: &str = "chrM";
+
+/// The length of the sequence the synthetic records are aligned to (as in
+/// GRCh38).
+pub const SEQUENCE_LENGTH: usize = 16_569;
+
+/// The length of each synthetic read.
+pub const READ_LENGTH: usize = 100;
+
+/// Runs `f` for [`ROUNDS`] rounds and reports the fastest, along with the
+/// throughput of `units` (e.g., records or bases) per second.
+pub fn bench<F: FnMut()>(name: &str, units: usize, unit: &str, mut f: F) {
+    let mut best = Duration::MAX;
+
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        f();
+        best = best.min(start.elapsed());
+    }
+
+    println!(
+        "{:<40} {:>10.2?} ({:.2} M{}/s)",
+        name,
+        best,
+        units as f64 / best.as_secs_f64() / 1e6,
+        unit
+    );
+}
+
+/// A synthetic data set: a reference sequence and records aligned to it.
+pub struct Synthetic {
+    /// The header for the records.
+    pub header: sam::Header,
+
+    /// The reference sequence the records are aligned to.
+    pub reference: Vec<u8>,
+
+    /// The records, which are paired and sorted by position.
+    pub records: Vec<Record>,
+}
+
+impl Synthetic {
+    /// Generates `n` records (deterministically) aligned to a random reference
+    /// sequence. About one in fifty bases is a mismatch.
+    pub fn generate(n: usize) -> anyhow::Result<Self> {
+        let mut rng = StdRng::seed_from_u64(0x6e6773);
+        let bases = b"ACGT";
+
+        let reference: Vec<u8> = (0..SEQUENCE_LENGTH)
+            .map(|_| bases[rng.gen_range(0..4)])
+            .collect();
+
+        let header = sam::Header::builder()
+            .add_reference_sequence(Map::<ReferenceSequence>::new(
+                SEQUENCE_NAME.parse()?,
+                SEQUENCE_LENGTH,
+            )?)
+            .add_read_group(
+                Map::<ReadGroup>::builder()
+                    .set_id("rg0")
+                    .set_sample("synthetic")
+                    .build()?,
+            )
+            .build();
+
+        let mut starts: Vec<usize> = (0..n)
+            .map(|_| rng.gen_range(1..=SEQUENCE_LENGTH - READ_LENGTH + 1))
+            .collect();
+        starts.sort_unstable();
+
+        let mut records = Vec::with_capacity(n);
+        for (i, start) in starts.into_iter().enumerate() {
+            let mut sequence = reference[start - 1..start - 1 + READ_LENGTH].to_vec();
+            let mut mismatches = 0;
+            for base in sequence.iter_mut() {
+                if rng.gen_range(0..50) == 0 {
+                    *base = bases[(bases.iter().position(|b| b == base).unwrap() + 1) % 4];
+                    mismatches += 1;
+                }
+            }
+
+            let quality_scores: String = (0..READ_LENGTH)
+                .map(|_| (b'!' + rng.gen_range(2..=40)) as char)
+                .collect();
+
+            let read = if i % 2 == 0 {
+                Flags::FIRST_SEGMENT | Flags::MATE_REVERSE_COMPLEMENTED
+            } else {
+                Flags::LAST_SEGMENT | Flags::REVERSE_COMPLEMENTED
+            };
+
+            let data = Data::try_from(vec![
+                Field::new(Tag::ReadGroup, Value::String(String::from("rg0"))),
+                Field::new(Tag::EditDistance, Value::Int32(mismatches)),
+                Field::new(
+                    Tag::AlignmentScore,
+                    Value::Int32(READ_LENGTH as i32 - 5 * mismatches),
+                ),
+            ])?;
+
+            records.push(
+                Record::builder()
+                    .set_read_name(
+                        format!("SYN:1:FC1:1:{}:{}:{}", 1101 + i % 4, i / 2, i % 1000)
+                            .parse::<ReadName>()?,
+                    )
+                    .set_flags(Flags::SEGMENTED | Flags::PROPERLY_ALIGNED | read)
+                    .set_reference_sequence_id(0)
+                    .set_alignment_start(Position::try_from(start)?)
+                    .set_mapping_quality(MappingQuality::try_from(60)?)
+                    .set_cigar(format!("{}M", READ_LENGTH).parse()?)
+                    .set_mate_reference_sequence_id(0)
+                    .set_mate_alignment_start(Position::try_from(start)?)
+                    .set_sequence(String::from_utf8(sequence)?.parse::<Sequence>()?)
+                    .set_quality_scores(quality_scores.parse::<QualityScores>()?)
+                    .set_data(data)
+                    .build(),
+            );
+        }
+
+        Ok(Self {
+            header,
+            reference,
+            records,
+        })
+    }
+
+    /// The total number of bases in the records.
+    pub fn bases(&self) -> usize {
+        self.records.len() * READ_LENGTH
+    }
+
+    /// Writes the reference sequence to a FASTA file in the temporary
+    /// directory, returning its path.
+    pub fn write_fasta(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let path = temp_path(name, "fa");
+        let mut file = std::fs::File::create(&path)?;
+        writeln!(file, ">{}", SEQUENCE_NAME)?;
+        for line in self.reference.chunks(60) {
+            file.write_all(line)?;
+            writeln!(file)?;
+        }
+        Ok(path)
+    }
+
+    /// Writes a sites VCF with a site every 100 bases of the reference
+    /// sequence to the temporary directory, returning its path.
+    pub fn write_sites_vcf(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let path = temp_path(name, "vcf");
+        let mut file = std::fs::File::create(&path)?;
+        writeln!(file, "##fileformat=VCFv4.2")?;
+        writeln!(file, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+        for position in (50..SEQUENCE_LENGTH).step_by(100) {
+            let reference = self.reference[position - 1] as char;
+            let alternate = if reference == 'A' { 'G' } else { 'A' };
+            writeln!(
+                file,
+                "{}\t{}\t.\t{}\t{}\t.\tPASS\t.",
+                SEQUENCE_NAME, position, reference, alternate
+            )?;
+        }
+        Ok(path)
+    }
+}
+
+/// A path within the temporary directory that is unique to this process.
+fn temp_path(name: &str, extension: &str) -> PathBuf {
+    Path::new(&std::env::temp_dir()).join(format!(
+        "ngs-bench-{}-{}.{}",
+        name,
+        std::process::id(),
+        extension
+    ))
+}
@@ -0,0 +1,32 @@
+//! Fuzzes the parsing and classification of read names by `ngs derive`.
+//!
+//! Run with `cargo +nightly fuzz run illumina_read_name` from the root of the
+//! repository.
+
+#![no_main]
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+use ngs::derive::{instrument::reads::IlluminaReadName, readname_format::ReadNameClassifier};
+
+/// The classifier is only built once, as compiling its patterns is slow.
+static CLASSIFIER: OnceLock<ReadNameClassifier> = OnceLock::new();
+
+fuzz_target!(|data: &[u8]| {
+    let name = String::from_utf8_lossy(data);
+    let _ = name.parse::<IlluminaReadName>();
+
+    // Any read name that is classified as supporting instrument derivation
+    // must be parseable by `ngs derive instrument`.
+    let format = CLASSIFIER.get_or_init(ReadNameClassifier::new).classify(&name);
+    if format.supports_instrument_derivation() {
+        let name = name.split(char::is_whitespace).next().unwrap();
+        assert!(
+            name.parse::<IlluminaReadName>().is_ok(),
+            "{:?} was classified as {:?} but could not be parsed",
+            name,
+            format
+        );
+    }
+});
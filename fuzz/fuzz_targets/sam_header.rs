@@ -0,0 +1,14 @@
+//! Fuzzes the parsing (and correction) of SAM/BAM/CRAM headers.
+//!
+//! Run with `cargo +nightly fuzz run sam_header` from the root of the
+//! repository.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ngs::utils::formats::sam::parse_header;
+
+fuzz_target!(|data: &[u8]| {
+    // Malformed headers must be reported as errors rather than panicking.
+    let _ = parse_header(String::from_utf8_lossy(data).into_owned());
+});
@@ -0,0 +1,4 @@
+//! Functionality related to the `ngs check` command.
+
+pub mod bgzf;
+pub mod command;
@@ -0,0 +1,242 @@
+//! Verifies the integrity of BGZF-compressed files (e.g., BAM files).
+//!
+//! A BGZF file is a series of gzip members (blocks), each of which records
+//! its own size, the CRC32 of its uncompressed data, and the size of its
+//! uncompressed data. Every block is decompressed and checked against those
+//! fields. Complete files end with an empty block (the end-of-file marker):
+//! a file that is missing it was almost certainly truncated.
+
+use std::io::{self, Read};
+
+use flate2::{read::DeflateDecoder, Crc};
+use serde::{Deserialize, Serialize};
+
+/// The empty block that terminates every complete BGZF file.
+pub const EOF_MARKER: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// The size of the fixed portion of the header of a gzip member.
+const HEADER_SIZE: usize = 12;
+
+/// The size of the trailer (the CRC32 and the uncompressed size) of a gzip
+/// member.
+const TRAILER_SIZE: usize = 8;
+
+/// A problem found within a block.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockError {
+    /// The offset of the start of the block within the file.
+    pub offset: u64,
+
+    /// A description of the problem.
+    pub message: String,
+}
+
+/// The result of verifying a BGZF file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BgzfReport {
+    /// The number of blocks read.
+    pub blocks: usize,
+
+    /// The total size of the (compressed) blocks.
+    pub compressed_bytes: u64,
+
+    /// The total size of the uncompressed data.
+    pub uncompressed_bytes: u64,
+
+    /// The number of blocks whose data did not match their CRC32 (or their
+    /// recorded uncompressed size).
+    pub crc_mismatches: usize,
+
+    /// Whether the file ends with the end-of-file marker.
+    pub eof_marker: bool,
+
+    /// The first problem found within the file, if any. Reading stops at
+    /// malformed blocks, since the position of the next block is unknown, but
+    /// continues past blocks that fail their CRC32.
+    pub first_error: Option<BlockError>,
+}
+
+impl BgzfReport {
+    /// Records a problem, keeping only the first.
+    fn error(&mut self, offset: u64, message: impl Into<String>) {
+        if self.first_error.is_none() {
+            self.first_error = Some(BlockError {
+                offset,
+                message: message.into(),
+            });
+        }
+    }
+}
+
+/// Reads until `buf` is full or the end of the input is reached, returning
+/// the number of bytes read.
+fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(filled)
+}
+
+/// Finds the size of the block (`BSIZE + 1`) within the extra field of the
+/// header of a gzip member.
+fn block_size(extra: &[u8]) -> Option<usize> {
+    let mut extra = extra;
+
+    while extra.len() >= 4 {
+        let length = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        let data = extra.get(4..4 + length)?;
+
+        if &extra[..2] == b"BC" && length == 2 {
+            return Some(u16::from_le_bytes([data[0], data[1]]) as usize + 1);
+        }
+
+        extra = &extra[4 + length..];
+    }
+
+    None
+}
+
+/// Verifies every block of a BGZF file. Only errors reading the input itself
+/// are returned as errors: problems with the file are recorded in the report.
+pub fn verify<R: Read>(mut reader: R) -> io::Result<BgzfReport> {
+    let mut report = BgzfReport::default();
+    let mut offset = 0;
+    let mut block = Vec::new();
+    let mut data = Vec::new();
+
+    loop {
+        // (1) The fixed portion of the header. The end of the input is only
+        // expected at a block boundary.
+        let mut header = [0; HEADER_SIZE];
+        match read_fully(&mut reader, &mut header)? {
+            0 => break,
+            HEADER_SIZE => {}
+            _ => {
+                report.error(offset, "truncated block header");
+                report.eof_marker = false;
+                break;
+            }
+        }
+
+        if header[..4] != [0x1f, 0x8b, 0x08, 0x04] {
+            report.error(offset, "not a BGZF block (invalid gzip header)");
+            report.eof_marker = false;
+            break;
+        }
+
+        // (2) The extra field, which holds the size of the block.
+        let extra_length = u16::from_le_bytes([header[10], header[11]]) as usize;
+        let mut extra = vec![0; extra_length];
+        let size = match read_fully(&mut reader, &mut extra)? == extra_length {
+            true => block_size(&extra),
+            false => None,
+        };
+
+        let size = match size {
+            Some(size) if size >= HEADER_SIZE + extra_length + TRAILER_SIZE => size,
+            _ => {
+                report.error(offset, "not a BGZF block (missing or invalid block size)");
+                report.eof_marker = false;
+                break;
+            }
+        };
+
+        // (3) The compressed data and the trailer.
+        block.resize(size - HEADER_SIZE - extra_length, 0);
+        if read_fully(&mut reader, &mut block)? != block.len() {
+            report.error(offset, "truncated block");
+            report.eof_marker = false;
+            break;
+        }
+
+        let (cdata, trailer) = block.split_at(block.len() - TRAILER_SIZE);
+        let expected_crc32 = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+        let expected_size = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+
+        report.blocks += 1;
+        report.compressed_bytes += size as u64;
+
+        // (4) The uncompressed data must match the trailer.
+        data.clear();
+        match DeflateDecoder::new(cdata).read_to_end(&mut data) {
+            Ok(_) => {
+                let mut crc = Crc::new();
+                crc.update(&data);
+
+                if crc.sum() != expected_crc32 || data.len() as u32 != expected_size {
+                    report.crc_mismatches += 1;
+                    report.error(offset, "CRC32 mismatch");
+                }
+            }
+            Err(e) => {
+                report.crc_mismatches += 1;
+                report.error(offset, format!("could not decompress block: {}", e));
+            }
+        }
+        report.uncompressed_bytes += data.len() as u64;
+
+        report.eof_marker = size == EOF_MARKER.len()
+            && header[..] == EOF_MARKER[..HEADER_SIZE]
+            && extra[..] == EOF_MARKER[HEADER_SIZE..HEADER_SIZE + extra_length]
+            && block[..] == EOF_MARKER[HEADER_SIZE + extra_length..];
+
+        offset += size as u64;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use noodles::bgzf;
+
+    use super::*;
+
+    #[test]
+    pub fn it_detects_truncated_and_corrupted_files() -> anyhow::Result<()> {
+        let mut writer = bgzf::Writer::new(Vec::new());
+        writer.write_all(b"ACGT".repeat(100_000).as_slice())?;
+        let data = writer.finish()?;
+
+        let report = verify(&data[..])?;
+        assert!(report.blocks > 2);
+        assert_eq!(report.uncompressed_bytes, 400_000);
+        assert_eq!(report.crc_mismatches, 0);
+        assert!(report.eof_marker);
+        assert_eq!(report.first_error, None);
+
+        // Missing the end-of-file marker.
+        let report = verify(&data[..data.len() - EOF_MARKER.len()])?;
+        assert!(!report.eof_marker);
+        assert_eq!(report.first_error, None);
+
+        // Truncated within a block.
+        let report = verify(&data[..data.len() / 2])?;
+        assert!(!report.eof_marker);
+        assert_eq!(report.first_error.unwrap().message, "truncated block");
+
+        // A corrupted CRC32 in the first block.
+        let size = block_size(&data[HEADER_SIZE..HEADER_SIZE + 6]).unwrap();
+        let mut corrupted = data.clone();
+        corrupted[size - TRAILER_SIZE] ^= 0xff;
+        let report = verify(&corrupted[..])?;
+        assert_eq!(report.crc_mismatches, 1);
+        assert!(report.eof_marker);
+        assert_eq!(report.first_error.unwrap().offset, 0);
+
+        Ok(())
+    }
+}
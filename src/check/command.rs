@@ -0,0 +1,348 @@
+//! Functionality related to the `ngs check` command itself.
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::utils::{
+    checksum::{self, InputChecksum},
+    formats::cram,
+    provenance::Provenance,
+};
+
+use super::bgzf::{self, BgzfReport};
+
+//========================//
+// Command-line arguments //
+//========================//
+
+/// Command line arguments for `ngs check`.
+#[derive(Args)]
+pub struct CheckArgs {
+    /// Path to the file to check.
+    #[arg(value_name = "BAM/CRAM/BGZF")]
+    src: PathBuf,
+}
+
+//=========//
+// Verdict //
+//=========//
+
+/// The overall verdict for a file, from most to least severe.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// The file is malformed or failed a checksum.
+    Corrupt,
+
+    /// The file is missing its end-of-file marker.
+    Truncated,
+
+    /// The file is intact, but at least one of its indices is older than it.
+    StaleIndex,
+
+    /// No problems were found.
+    Ok,
+}
+
+/// The result of checking a CRAM file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CramReport {
+    /// The number of containers read (including the file header container).
+    pub containers: usize,
+
+    /// Whether the file ends with the end-of-file container.
+    pub eof_container: bool,
+
+    /// The problem that stopped the containers from being read, if any.
+    pub error: Option<String>,
+}
+
+/// The freshness of an index of the file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexFreshness {
+    /// The path to the index.
+    pub path: PathBuf,
+
+    /// Whether the index was last modified before the file was (and, as such,
+    /// may not describe the current contents of the file).
+    pub stale: bool,
+}
+
+/// The machine-readable verdict printed by `ngs check`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Verdict {
+    /// The path to the file that was checked.
+    pub file: PathBuf,
+
+    /// The overall verdict.
+    pub status: Status,
+
+    /// A description of each problem that was found.
+    pub problems: Vec<String>,
+
+    /// The result of verifying the blocks of a BGZF file.
+    pub bgzf: Option<BgzfReport>,
+
+    /// The result of reading the containers of a CRAM file.
+    pub cram: Option<CramReport>,
+
+    /// The freshness of each index found alongside the file.
+    pub indices: Vec<IndexFreshness>,
+
+    /// The checksum of the file (only computed with `--checksums`).
+    pub input_checksum: Option<InputChecksum>,
+
+    /// The invocation of `ngs` that produced the verdict.
+    pub provenance: Option<Provenance>,
+}
+
+impl Verdict {
+    /// Determines the overall verdict (and describes each problem) from the
+    /// individual checks.
+    fn new(
+        file: PathBuf,
+        bgzf: Option<BgzfReport>,
+        cram: Option<CramReport>,
+        indices: Vec<IndexFreshness>,
+    ) -> Self {
+        let mut problems = Vec::new();
+        let mut status = Status::Ok;
+        let mut flag = |severity: Status, problem: String| {
+            status = match (status, severity) {
+                (Status::Corrupt, _) | (_, Status::Corrupt) => Status::Corrupt,
+                (Status::Truncated, _) | (_, Status::Truncated) => Status::Truncated,
+                (Status::StaleIndex, _) | (_, Status::StaleIndex) => Status::StaleIndex,
+                _ => Status::Ok,
+            };
+            problems.push(problem);
+        };
+
+        if let Some(report) = &bgzf {
+            if let Some(error) = &report.first_error {
+                let severity = match error.message.starts_with("truncated") {
+                    true => Status::Truncated,
+                    false => Status::Corrupt,
+                };
+                flag(
+                    severity,
+                    format!("{} (block at offset {})", error.message, error.offset),
+                );
+            }
+
+            if report.crc_mismatches > 1 {
+                flag(
+                    Status::Corrupt,
+                    format!("{} blocks failed their CRC32", report.crc_mismatches),
+                );
+            }
+
+            if !report.eof_marker {
+                flag(
+                    Status::Truncated,
+                    String::from("missing the BGZF EOF marker"),
+                );
+            }
+        }
+
+        if let Some(report) = &cram {
+            if let Some(error) = &report.error {
+                flag(Status::Corrupt, error.clone());
+            }
+
+            if !report.eof_container {
+                flag(
+                    Status::Truncated,
+                    String::from("missing the CRAM EOF container"),
+                );
+            }
+        }
+
+        for index in indices.iter().filter(|index| index.stale) {
+            flag(
+                Status::StaleIndex,
+                format!("index is older than the file: {}", index.path.display()),
+            );
+        }
+
+        Self {
+            file,
+            status,
+            problems,
+            bgzf,
+            cram,
+            indices,
+            input_checksum: None,
+            provenance: None,
+        }
+    }
+}
+
+//========//
+// Checks //
+//========//
+
+/// Reads the containers of a CRAM file until the end of the file or the first
+/// problem.
+fn check_cram<R: Read>(reader: R) -> anyhow::Result<CramReport> {
+    let mut reader = cram::Reader::new(reader);
+    let mut report = CramReport::default();
+
+    if let Err(e) = reader.read_file_definition() {
+        report.error = Some(format!("invalid file definition: {}", e));
+        return Ok(report);
+    }
+
+    loop {
+        match reader.read_container() {
+            Ok(Some(container)) => {
+                report.containers += 1;
+                report.eof_container = container.is_eof();
+            }
+            Ok(None) => break,
+            Err(e) => {
+                report.eof_container = false;
+                report.error = Some(format!(
+                    "could not read container {}: {}",
+                    report.containers + 1,
+                    e
+                ));
+                break;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Finds the indices alongside a file (e.g., `sample.bam.bai` or
+/// `sample.bai` for `sample.bam`) and checks whether each is older than the
+/// file.
+fn check_indices(src: &Path) -> anyhow::Result<Vec<IndexFreshness>> {
+    let modified = fs::metadata(src)?.modified()?;
+    let mut indices = Vec::new();
+
+    let extensions = ["bai", "csi", "crai", "tbi", "gzi"];
+    let candidates = extensions
+        .iter()
+        .map(|extension| {
+            let mut path = src.as_os_str().to_owned();
+            path.push(format!(".{}", extension));
+            PathBuf::from(path)
+        })
+        .chain(
+            ["bai", "crai"]
+                .iter()
+                .map(|extension| src.with_extension(extension)),
+        );
+
+    for path in candidates {
+        if path == src || indices.iter().any(|i: &IndexFreshness| i.path == path) {
+            continue;
+        }
+
+        if let Ok(metadata) = fs::metadata(&path) {
+            indices.push(IndexFreshness {
+                stale: metadata.modified()? < modified,
+                path,
+            });
+        }
+    }
+
+    Ok(indices)
+}
+
+//==============//
+// Main command //
+//==============//
+
+/// Main method for the `ngs check` subcommand.
+pub fn check(args: CheckArgs) -> anyhow::Result<()> {
+    let src = args.src;
+    info!("Checking {}...", src.display());
+
+    // (1) Detect the kind of file from its contents rather than its extension,
+    // since the extension of a damaged file cannot be trusted either.
+    let mut magic = [0; 4];
+    let read = File::open(&src)
+        .and_then(|mut file| file.read(&mut magic))
+        .with_context(|| format!("Could not open file: {}", src.display()))?;
+
+    let (input, input_checksum) = checksum::open(&src)?;
+    let input = BufReader::new(input);
+
+    let (bgzf, cram) = match &magic[..read] {
+        b"CRAM" => (None, Some(check_cram(input)?)),
+        [0x1f, 0x8b, ..] => (Some(bgzf::verify(input)?), None),
+        _ => bail!(
+            "{} is neither a BGZF-compressed file (e.g., BAM) nor a CRAM file.",
+            src.display()
+        ),
+    };
+
+    // (2) Render the verdict and print it to stdout as JSON.
+    let mut verdict = Verdict::new(src.clone(), bgzf, cram, check_indices(&src)?);
+    verdict.input_checksum = input_checksum.finish()?;
+    verdict.provenance = Some(Provenance::now());
+    println!("{}", serde_json::to_string_pretty(&verdict)?);
+
+    if verdict.status != Status::Ok {
+        bail!(
+            "{} failed the integrity check: {}.",
+            src.display(),
+            verdict.problems.join("; ")
+        );
+    }
+
+    info!("No problems were found.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_reports_the_most_severe_problem() {
+        let intact = BgzfReport {
+            eof_marker: true,
+            ..Default::default()
+        };
+        let stale = vec![IndexFreshness {
+            path: PathBuf::from("sample.bam.bai"),
+            stale: true,
+        }];
+
+        let verdict = Verdict::new(PathBuf::new(), Some(intact.clone()), None, Vec::new());
+        assert_eq!(verdict.status, Status::Ok);
+        assert!(verdict.problems.is_empty());
+
+        let verdict = Verdict::new(PathBuf::new(), Some(intact), None, stale.clone());
+        assert_eq!(verdict.status, Status::StaleIndex);
+
+        let verdict = Verdict::new(
+            PathBuf::new(),
+            Some(BgzfReport::default()),
+            None,
+            stale.clone(),
+        );
+        assert_eq!(verdict.status, Status::Truncated);
+        assert_eq!(verdict.problems.len(), 2);
+
+        let cram = CramReport {
+            containers: 2,
+            eof_container: false,
+            error: Some(String::from("could not read container 3")),
+        };
+        let verdict = Verdict::new(PathBuf::new(), None, Some(cram), stale);
+        assert_eq!(verdict.status, Status::Corrupt);
+        assert_eq!(verdict.problems.len(), 3);
+    }
+}
@@ -0,0 +1,4 @@
+//! Functionality related to the `ngs depth` command.
+
+pub mod command;
+pub mod output;
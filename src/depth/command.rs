@@ -0,0 +1,255 @@
+//! Functionality related to the `ngs depth` command itself.
+
+use std::{fs::File, num::NonZeroUsize, path::PathBuf};
+
+use anyhow::{bail, Context};
+use clap::{builder::PossibleValuesParser, Args};
+use noodles::{bam, sam::alignment::Record};
+use tracing::{debug, info};
+
+use crate::utils::{
+    checksum, formats::bam::for_each_record, intervals::read_intervals_from_path, outputs,
+    pileup::Pileup, provenance::Provenance,
+};
+
+use super::output::{DepthWriter, Regions};
+
+//========================//
+// Command-line arguments //
+//========================//
+
+/// Command line arguments for `ngs depth`.
+#[derive(Args)]
+pub struct DepthArgs {
+    /// Source BAM file (which must be coordinate-sorted).
+    #[arg(value_name = "BAM")]
+    src: PathBuf,
+
+    /// Directory to output files to. Defaults to current working directory.
+    #[arg(short = 'o', long, value_name = "PATH")]
+    output_directory: Option<PathBuf>,
+
+    /// Output prefix for the files that will be created. Defaults to the name
+    /// of the file.
+    #[arg(short = 'p', long, value_name = "STRING")]
+    output_prefix: Option<String>,
+
+    /// Summarizes the mean depth within windows of the provided size or
+    /// within the intervals of a BED file or interval list.
+    #[arg(long, value_name = "WINDOW/BED")]
+    by: Option<String>,
+
+    /// Comma-separated depths for which the number of positions reaching that
+    /// depth within each window or interval are reported.
+    #[arg(long, value_name = "DEPTHS", value_delimiter = ',', requires = "by")]
+    thresholds: Vec<u32>,
+
+    /// Skips writing the depth at every position.
+    #[arg(long)]
+    no_per_base: bool,
+
+    /// Minimum mapping quality for a record to be counted.
+    #[arg(long, value_name = "U8", default_value_t = 0)]
+    min_mapq: u8,
+
+    /// Whether records marked as duplicate (`0x400`) should be counted.
+    /// Unmapped, secondary, and QC-failed records are never counted.
+    #[arg(long, value_name = "yes/no", default_value = "no")]
+    #[arg(value_parser = PossibleValuesParser::new(["yes", "no"]))]
+    count_duplicates: String,
+}
+
+//===========//
+// Streaming //
+//===========//
+
+/// Whether a record contributes to the depth.
+fn is_counted(record: &Record, min_mapq: u8, count_duplicates: bool) -> bool {
+    let flags = record.flags();
+
+    if flags.is_unmapped() || flags.is_secondary() || flags.is_qc_fail() {
+        return false;
+    }
+
+    if flags.is_duplicate() && !count_duplicates {
+        return false;
+    }
+
+    match record.mapping_quality() {
+        Some(mapq) => u8::from(mapq) >= min_mapq,
+        None => min_mapq == 0,
+    }
+}
+
+/// Piles up the records of a coordinate-sorted file one sequence at a time,
+/// writing each sequence as soon as the records move past it. Only the depth
+/// of the current sequence is held in memory.
+struct Stream {
+    /// The name and length of each reference sequence, in header order.
+    sequences: Vec<(String, usize)>,
+
+    /// The index of the current sequence and its pileup.
+    current: Option<(usize, Pileup)>,
+
+    /// The index of the next sequence to be written.
+    next: usize,
+
+    /// Writes each completed sequence.
+    writer: DepthWriter,
+}
+
+impl Stream {
+    /// Writes every sequence before `end` that has not been written yet. The
+    /// current sequence is written from its pileup; the others have no
+    /// records, so their depth is zero throughout.
+    fn write_through(&mut self, end: usize) -> anyhow::Result<()> {
+        while self.next < end {
+            let pileup = match self.current.take() {
+                Some((id, pileup)) if id == self.next => pileup,
+                current => {
+                    self.current = current;
+                    Pileup::new(self.sequences[self.next].1)
+                }
+            };
+
+            let name = &self.sequences[self.next].0;
+            self.writer.write_sequence(name, &pileup.finish())?;
+            self.next += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Adds a record aligned to the sequence with the provided index.
+    fn add(&mut self, id: usize, record: &Record) -> anyhow::Result<()> {
+        if self.current.as_ref().map(|(current, _)| *current) != Some(id) {
+            if id < self.next || id >= self.sequences.len() {
+                bail!(
+                    "Records are not coordinate-sorted (or refer to a reference \
+                    sequence that is not in the header). Please sort the file \
+                    with `samtools sort` and try again."
+                );
+            }
+
+            self.write_through(id)?;
+            self.current = Some((id, Pileup::new(self.sequences[id].1)));
+        }
+
+        if let Some((_, pileup)) = &mut self.current {
+            pileup.add_record(record);
+        }
+
+        Ok(())
+    }
+}
+
+//==============//
+// Main command //
+//==============//
+
+/// Main method for the `ngs depth` subcommand.
+pub fn depth(args: DepthArgs) -> anyhow::Result<()> {
+    info!("Starting depth command...");
+
+    //======================//
+    // Regions to Summarize //
+    //======================//
+
+    let regions = match &args.by {
+        Some(by) => Some(match by.parse::<NonZeroUsize>() {
+            Ok(size) => Regions::Windows(size),
+            Err(_) => Regions::Intervals(read_intervals_from_path(by, &[])?),
+        }),
+        None => None,
+    };
+
+    if args.no_per_base && regions.is_none() {
+        bail!("Nothing to do: --no-per-base was provided without --by.");
+    }
+
+    //================//
+    // Output Options //
+    //================//
+
+    let src = args.src;
+    let output_prefix = args.output_prefix.unwrap_or_else(|| {
+        src.file_name()
+            .unwrap()
+            .to_os_string()
+            .into_string()
+            .unwrap()
+    });
+    let output_directory = match args.output_directory {
+        Some(p) => p,
+        None => std::env::current_dir()?,
+    };
+    debug!("  [*] Output prefix: {}", output_prefix);
+    debug!("  [*] Output directory: {}", output_directory.display());
+
+    //========//
+    // Pileup //
+    //========//
+
+    let (input, input_checksum) = checksum::open(&src)
+        .with_context(|| format!("Could not open BAM file: {}", src.display()))?;
+    let mut reader = bam::Reader::new(input);
+    reader.read_header()?;
+    let reference_sequences = reader.read_reference_sequences()?;
+
+    let mut stream = Stream {
+        sequences: reference_sequences
+            .values()
+            .map(|sequence| (sequence.name().to_string(), usize::from(sequence.length())))
+            .collect(),
+        current: None,
+        next: 0,
+        writer: DepthWriter::try_new(
+            &output_directory,
+            &output_prefix,
+            !args.no_per_base,
+            regions,
+            args.thresholds,
+        )?,
+    };
+
+    let count_duplicates = args.count_duplicates == "yes";
+    for_each_record(&mut reader, true, |record| {
+        // Unmapped records are sorted to the end of the file, so there are no
+        // more records to pile up once unplaced records are reached.
+        let id = match record.reference_sequence_id() {
+            Some(id) => id,
+            None => return Ok(true),
+        };
+
+        if is_counted(record, args.min_mapq, count_duplicates) {
+            stream.add(id, record)?;
+        }
+
+        Ok(false)
+    })?;
+
+    let sequences = stream.sequences.len();
+    stream.write_through(sequences)?;
+    stream
+        .writer
+        .finish(&output_directory.join(format!("{}.summary.txt", output_prefix)))?;
+
+    // The checksum of the input and the provenance of the outputs are written
+    // alongside the outputs, as none of them have anywhere to record either.
+    if let Some(checksum) = input_checksum.finish()? {
+        let path = output_directory.join(format!("{}.checksums.json", output_prefix));
+        let file = File::create(&path)
+            .with_context(|| format!("Could not create file: {}", path.display()))?;
+        serde_json::to_writer_pretty(file, &[checksum])?;
+        outputs::record(&path, "checksums");
+    }
+
+    let path = output_directory.join(format!("{}.provenance.json", output_prefix));
+    let file = File::create(&path)
+        .with_context(|| format!("Could not create file: {}", path.display()))?;
+    serde_json::to_writer_pretty(file, &Provenance::now())?;
+    outputs::record(&path, "provenance");
+
+    info!("Done.");
+    Ok(())
+}
@@ -0,0 +1,333 @@
+//! The files written by `ngs depth`.
+//!
+//! Every BED file is bgzipped and uses zero-based, half-open coordinates, as
+//! is conventional for BED (and as written by mosdepth):
+//!
+//! * `<prefix>.per-base.bed.gz`: the depth at every position of every
+//!   sequence, with consecutive positions of the same depth merged into one
+//!   line.
+//! * `<prefix>.regions.bed.gz`: the mean depth within each window or interval
+//!   (only when `--by` is provided).
+//! * `<prefix>.thresholds.bed.gz`: the number of positions within each window
+//!   or interval that reach each of the depth thresholds (only when
+//!   `--thresholds` is provided).
+//! * `<prefix>.summary.txt`: the length, aligned bases, and mean, minimum, and
+//!   maximum depth of each sequence, followed by the total across sequences.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    num::NonZeroUsize,
+    path::Path,
+};
+
+use anyhow::Context;
+use noodles::bgzf;
+
+use crate::utils::{
+    intervals::IntervalSet,
+    outputs,
+    pileup::{DepthSummary, Depths},
+};
+
+//=========//
+// Regions //
+//=========//
+
+/// The regions within which the depth is summarized.
+pub enum Regions {
+    /// Consecutive windows of the provided size along each sequence. The last
+    /// window of each sequence may be shorter.
+    Windows(NonZeroUsize),
+
+    /// The intervals within a BED file or interval list.
+    Intervals(IntervalSet),
+}
+
+/// A one-based, inclusive region on a sequence, with its name (if any).
+type Region = (usize, usize, Option<String>);
+
+impl Regions {
+    /// Gets the regions on a sequence of the provided length, in order.
+    /// Regions beyond the end of the sequence are clipped.
+    fn on(&self, sequence: &str, length: usize) -> Vec<Region> {
+        match self {
+            Self::Windows(size) => (1..=length)
+                .step_by(size.get())
+                .map(|start| (start, usize::min(start + size.get() - 1, length), None))
+                .collect(),
+            Self::Intervals(intervals) => intervals
+                .get(sequence)
+                .into_iter()
+                .flat_map(|lapper| lapper.iter())
+                .filter(|interval| interval.start <= length)
+                .map(|interval| {
+                    (
+                        interval.start,
+                        usize::min(interval.stop - 1, length),
+                        interval.val.name.clone(),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+//=========//
+// Writers //
+//=========//
+
+/// Opens a bgzipped file of the provided type for writing.
+fn create_bgzf(path: &Path, kind: &'static str) -> anyhow::Result<bgzf::Writer<File>> {
+    let writer = File::create(path)
+        .map(bgzf::Writer::new)
+        .with_context(|| format!("Could not create output file: {}", path.display()))?;
+    outputs::record(path, kind);
+    Ok(writer)
+}
+
+/// Writes the depth of each sequence, as it is completed, to the output files.
+pub struct DepthWriter {
+    /// The per-base depth, if requested.
+    per_base: Option<bgzf::Writer<File>>,
+
+    /// The regions to summarize the depth within, and the mean depth within
+    /// each of them.
+    regions: Option<(Regions, bgzf::Writer<File>)>,
+
+    /// The depth thresholds, and the number of positions reaching each of
+    /// them within each region.
+    thresholds: Option<(Vec<u32>, bgzf::Writer<File>)>,
+
+    /// The summary of each sequence, in the order they were written.
+    summaries: Vec<(String, DepthSummary)>,
+}
+
+impl DepthWriter {
+    /// Creates the output files within the directory, named with the prefix.
+    /// Thresholds are summarized within the regions, so they are only written
+    /// when regions are provided.
+    pub fn try_new(
+        directory: &Path,
+        prefix: &str,
+        per_base: bool,
+        regions: Option<Regions>,
+        thresholds: Vec<u32>,
+    ) -> anyhow::Result<Self> {
+        let path = |suffix: &str| directory.join(format!("{}.{}", prefix, suffix));
+
+        let per_base = match per_base {
+            true => Some(create_bgzf(&path("per-base.bed.gz"), "per-base-depth")?),
+            false => None,
+        };
+
+        let thresholds = match (&regions, thresholds.is_empty()) {
+            (Some(_), false) => {
+                let mut writer = create_bgzf(&path("thresholds.bed.gz"), "threshold-depth")?;
+                let columns: Vec<_> = thresholds.iter().map(|t| format!("{}X", t)).collect();
+                writeln!(writer, "#chrom\tstart\tend\tregion\t{}", columns.join("\t"))?;
+                Some((thresholds, writer))
+            }
+            _ => None,
+        };
+
+        let regions = match regions {
+            Some(regions) => Some((
+                regions,
+                create_bgzf(&path("regions.bed.gz"), "region-depth")?,
+            )),
+            None => None,
+        };
+
+        Ok(Self {
+            per_base,
+            regions,
+            thresholds,
+            summaries: Vec::new(),
+        })
+    }
+
+    /// Writes the depth of a completed sequence.
+    pub fn write_sequence(&mut self, name: &str, depths: &Depths) -> io::Result<()> {
+        // (1) The per-base depth, with runs of the same depth merged.
+        if let Some(writer) = &mut self.per_base {
+            for run in depths.runs() {
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}\t{}",
+                    name,
+                    run.start - 1,
+                    run.end,
+                    run.depth
+                )?;
+            }
+        }
+
+        // (2) The mean depth, and the positions reaching each threshold,
+        // within each region.
+        if let Some((regions, writer)) = &mut self.regions {
+            for (start, end, region_name) in regions.on(name, depths.len()) {
+                let mean = depths.summarize(start, end).mean();
+                match &region_name {
+                    Some(region_name) => writeln!(
+                        writer,
+                        "{}\t{}\t{}\t{}\t{:.2}",
+                        name,
+                        start - 1,
+                        end,
+                        region_name,
+                        mean
+                    )?,
+                    None => writeln!(writer, "{}\t{}\t{}\t{:.2}", name, start - 1, end, mean)?,
+                }
+
+                if let Some((thresholds, writer)) = &mut self.thresholds {
+                    let counts: Vec<_> = depths
+                        .positions_at_or_above(start, end, thresholds)
+                        .iter()
+                        .map(|count| count.to_string())
+                        .collect();
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{}\t{}\t{}",
+                        name,
+                        start - 1,
+                        end,
+                        region_name.as_deref().unwrap_or("unknown"),
+                        counts.join("\t")
+                    )?;
+                }
+            }
+        }
+
+        // (3) The summary of the entire sequence.
+        self.summaries
+            .push((name.to_string(), depths.summarize(1, depths.len())));
+
+        Ok(())
+    }
+
+    /// Finishes the bgzipped files and writes the summary.
+    pub fn finish(self, summary: &Path) -> anyhow::Result<()> {
+        let writers = self
+            .per_base
+            .into_iter()
+            .chain(self.regions.map(|(_, writer)| writer))
+            .chain(self.thresholds.map(|(_, writer)| writer));
+
+        for writer in writers {
+            writer.finish()?;
+        }
+
+        let file = File::create(summary)
+            .with_context(|| format!("Could not create output file: {}", summary.display()))?;
+        write_summary(BufWriter::new(file), &self.summaries)?;
+        outputs::record(summary, "depth-summary");
+
+        Ok(())
+    }
+}
+
+/// Writes the summary of each sequence, followed by the total across every
+/// sequence.
+fn write_summary<W: Write>(mut writer: W, summaries: &[(String, DepthSummary)]) -> io::Result<()> {
+    writeln!(writer, "chrom\tlength\tbases\tmean\tmin\tmax")?;
+
+    let mut total = DepthSummary {
+        length: 0,
+        bases: 0,
+        min: u32::MAX,
+        max: 0,
+    };
+
+    for (name, summary) in summaries {
+        write_summary_line(&mut writer, name, summary)?;
+
+        total.length += summary.length;
+        total.bases += summary.bases;
+        if summary.length > 0 {
+            total.min = u32::min(total.min, summary.min);
+            total.max = u32::max(total.max, summary.max);
+        }
+    }
+
+    if total.length == 0 {
+        total.min = 0;
+    }
+
+    write_summary_line(&mut writer, "total", &total)?;
+    writer.flush()
+}
+
+/// Writes the summary of one sequence.
+fn write_summary_line<W: Write>(
+    writer: &mut W,
+    name: &str,
+    summary: &DepthSummary,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        "{}\t{}\t{}\t{:.2}\t{}\t{}",
+        name,
+        summary.length,
+        summary.bases,
+        summary.mean(),
+        summary.min,
+        summary.max
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::{intervals::Region as Interval, pileup::Pileup};
+
+    use super::*;
+
+    #[test]
+    pub fn it_summarizes_the_depth_within_each_region() -> anyhow::Result<()> {
+        let windows = Regions::Windows(NonZeroUsize::new(4).unwrap());
+        assert_eq!(
+            windows.on("chr1", 10),
+            vec![(1, 4, None), (5, 8, None), (9, 10, None)]
+        );
+
+        let intervals = Regions::Intervals(IntervalSet::from_intervals(vec![(
+            String::from("chr1"),
+            8,
+            20,
+            Interval {
+                name: Some(String::from("target")),
+                strand: None,
+            },
+        )]));
+        assert_eq!(
+            intervals.on("chr1", 10),
+            vec![(8, 10, Some(String::from("target")))]
+        );
+        assert_eq!(intervals.on("chr2", 10), vec![]);
+
+        let mut pileup = Pileup::new(10);
+        pileup.add(1, 6);
+        pileup.add(5, 6);
+        let mut summary = Vec::new();
+        write_summary(
+            &mut summary,
+            &[
+                (String::from("chr1"), pileup.finish().summarize(1, 10)),
+                (
+                    String::from("chr2"),
+                    Pileup::new(0).finish().summarize(1, 0),
+                ),
+            ],
+        )?;
+        assert_eq!(
+            String::from_utf8(summary)?,
+            "chrom\tlength\tbases\tmean\tmin\tmax\n\
+            chr1\t10\t8\t0.80\t0\t2\n\
+            chr2\t0\t0\t0.00\t0\t0\n\
+            total\t10\t8\t0.80\t0\t2\n"
+        );
+
+        Ok(())
+    }
+}
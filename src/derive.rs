@@ -1,4 +1,7 @@
 //! Functionality related to `ngs derive`.
 
 pub mod command;
+pub mod early_stop;
 pub mod instrument;
+pub mod readname_format;
+pub mod writers;
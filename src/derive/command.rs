@@ -1,8 +1,20 @@
 //! Functionality related to the `ngs derive` subcommand itself.
 
 pub mod instrument;
+pub mod readname_format;
 
-use clap::{Args, Subcommand};
+use std::{fs::File, num::NonZeroUsize, path::PathBuf};
+
+use clap::{builder::PossibleValuesParser, Args, Subcommand};
+use noodles::sam;
+
+use crate::utils::{
+    args::{parse_num_records, NumberOfRecords},
+    checksum::{self, Checksum, ChecksumReader},
+    formats::{alignment::AlignmentReader, detect::detect},
+};
+
+use super::{early_stop::EarlyStop, writers::OutputFormat};
 
 //===============//
 // Command setup //
@@ -21,4 +33,81 @@ pub struct DeriveArgs {
 pub enum DeriveSubcommand {
     /// Derives the instrument used to produce the file.
     Instrument(self::instrument::DeriveInstrumentArgs),
+
+    /// Derives the read-naming convention(s) used within the file.
+    ReadnameFormat(self::readname_format::DeriveReadNameFormatArgs),
+}
+
+/// Main function for the `ngs derive` subcommand.
+pub fn derive(args: DeriveArgs) -> anyhow::Result<()> {
+    match args.subcommand {
+        DeriveSubcommand::Instrument(args) => self::instrument::derive(args),
+        DeriveSubcommand::ReadnameFormat(args) => self::readname_format::derive(args),
+    }
+}
+
+//================//
+// Shared options //
+//================//
+
+/// Command line arguments shared by every `ngs derive` subcommand.
+#[derive(Args)]
+pub struct DeriveCommonArgs {
+    /// Source SAM, BAM, or CRAM file (detected by its contents).
+    #[arg(value_name = "SAM/BAM/CRAM")]
+    pub src: PathBuf,
+
+    /// Reference FASTA file, which is required to decode CRAM files.
+    #[arg(short, long, value_name = "PATH")]
+    pub reference_fasta: Option<PathBuf>,
+
+    /// Only examine the first n records in the file. Defaults to all records.
+    #[arg(short, long, value_name = "USIZE", value_parser = parse_num_records)]
+    pub num_records: Option<NonZeroUsize>,
+
+    /// Stop examining records once the prediction has been stable over this
+    /// many records (defaults to one million when no value is given). `derive
+    /// instrument` examines at least 10,000 records before stopping, so that
+    /// the confidence of its prediction is not lowered.
+    #[arg(long, value_name = "USIZE", value_parser = parse_num_records)]
+    #[arg(num_args = 0..=1, default_missing_value = "1000000")]
+    pub early_stop: Option<NonZeroUsize>,
+
+    /// The format of the result.
+    #[arg(long, value_name = "FORMAT", default_value = "json")]
+    #[arg(value_parser = PossibleValuesParser::new(["json", "jsonl", "tsv"]))]
+    pub format: String,
+
+    /// Writes the result to the provided path rather than to stdout.
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+}
+
+impl DeriveCommonArgs {
+    /// The number of records to examine.
+    pub fn num_records(&self) -> NumberOfRecords {
+        NumberOfRecords::from(self.num_records)
+    }
+
+    /// The early stopping criteria, if enabled.
+    pub fn early_stop(&self) -> Option<EarlyStop> {
+        self.early_stop.map(EarlyStop::new)
+    }
+
+    /// The format of the result.
+    pub fn format(&self) -> anyhow::Result<OutputFormat> {
+        self.format.parse()
+    }
+
+    /// Opens the source file (whether a SAM, BAM, or CRAM file) and reads its
+    /// header.
+    pub fn open(
+        &self,
+    ) -> anyhow::Result<(AlignmentReader<ChecksumReader<File>>, sam::Header, Checksum)> {
+        let format = detect(&self.src)?;
+        let (input, input_checksum) = checksum::open(&self.src)?;
+        let mut reader = AlignmentReader::new(input, format, self.reference_fasta.as_deref())?;
+        let (header, _) = reader.read_header()?;
+        Ok((reader, header, input_checksum))
+    }
 }
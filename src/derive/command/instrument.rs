@@ -1,37 +1,38 @@
 //! Functionality relating to the `ngs derive instrument` subcommand itself.
 
 use anyhow::bail;
-use std::{collections::HashSet, fs::File, path::PathBuf, thread};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use clap::Args;
-use noodles::bam;
-use tracing::info;
-
-use crate::derive::instrument::{compute, reads::IlluminaReadName};
+use noodles::sam::record::data::field::Tag;
+use tracing::{info, warn};
+
+use crate::{
+    derive::{
+        command::DeriveCommonArgs,
+        instrument::{compute, platform_units, reads::IlluminaReadName},
+        writers,
+    },
+    utils::{provenance::Provenance, threads},
+};
 
 /// Clap arguments for the `ngs derive instrument` subcommand.
 #[derive(Args)]
 pub struct DeriveInstrumentArgs {
-    // Source BAM.
-    #[arg(value_name = "BAM")]
-    src: PathBuf,
-
-    /// Only examine the first n records in the file.
-    #[arg(short, long, value_name = "USIZE")]
-    num_records: Option<usize>,
-
-    /// Use a specific number of threads.
-    #[arg(short, long, value_name = "USIZE")]
-    threads: Option<usize>,
+    #[command(flatten)]
+    common: DeriveCommonArgs,
+
+    /// When the instrument ids and flowcell ids are mutually exclusive, report
+    /// the union of the possible instruments with low confidence instead of
+    /// failing.
+    #[arg(long)]
+    allow_union: bool,
 }
 
 /// Entrypoint for the `ngs derive instrument` subcommand.
 pub fn derive(args: DeriveInstrumentArgs) -> anyhow::Result<()> {
-    let first_n_reads: Option<usize> = args.num_records;
-    let threads = match args.threads {
-        Some(t) => t,
-        None => thread::available_parallelism().map(usize::from)?,
-    };
+    // The number of threads is provided with the global `--threads` option.
+    let threads = usize::from(threads::requested_or_available()?);
 
     info!(
         "Starting derive instrument subcommand with {} threads.",
@@ -42,40 +43,72 @@ pub fn derive(args: DeriveInstrumentArgs) -> anyhow::Result<()> {
         .worker_threads(threads)
         .build()?;
 
-    rt.block_on(app(args.src, first_n_reads))
+    rt.block_on(app(args.common, args.allow_union))
 }
 
 /// Main function for the `ngs derive instrument` subcommand.
-async fn app(src: PathBuf, first_n_reads: Option<usize>) -> anyhow::Result<()> {
+async fn app(common: DeriveCommonArgs, allow_union: bool) -> anyhow::Result<()> {
+    let num_records = common.num_records();
+    let format = common.format()?;
+    // Stopping before the confidence of the prediction is no longer lowered
+    // for being based on too few records would trade confidence for speed.
+    let mut early_stop = common
+        .early_stop()
+        .map(|early_stop| early_stop.with_min_records(compute::SPARSE_RECORDS));
+
     let mut instrument_names = HashSet::new();
     let mut flowcell_names = HashSet::new();
-
-    let mut reader = File::open(src).map(bam::Reader::new)?;
-    reader.read_header()?;
-    reader.read_reference_sequences()?;
-
-    // (1) Collect instrument names and flowcell names from reads within the
-    // file. Support for sampling only a portion of the reads is provided.
-    let mut samples = 0;
-    let mut sample_max = 0;
-
-    if let Some(s) = first_n_reads {
-        sample_max = s;
+    let mut read_groups: HashMap<Option<String>, compute::ReadGroupIds> = HashMap::new();
+
+    let (mut reader, header, input_checksum) = common.open()?;
+
+    // (1) Collect flowcell names from the platform unit of each read group in
+    // the header. These are still available when the read names have been
+    // rewritten, so unparseable read names are tolerated when any are found.
+    let platform_unit_flowcells = platform_units::flowcells_from_header(&header);
+    for (read_group, flowcell) in &platform_unit_flowcells {
+        read_groups
+            .entry(Some(read_group.clone()))
+            .or_default()
+            .flowcell_names
+            .insert(flowcell.clone());
+        flowcell_names.insert(flowcell.clone());
     }
 
-    for result in reader.records() {
-        let record = result?;
+    // (2) Collect instrument names and flowcell names from reads within the
+    // file. Support for sampling only a portion of the reads is provided, as
+    // is stopping once no new instrument names or flowcell names are found.
+    let mut records_processed = 0;
+    let mut unparsed_read_names = 0;
+    let mut stopped_early = false;
+
+    reader.for_each_record(&header, false, |record| {
+        records_processed += 1;
 
         if let Some(read_name) = record.read_name() {
             let name: &str = read_name.as_ref();
 
             match name.parse::<IlluminaReadName>() {
                 Ok(read) => {
+                    let read_group = record
+                        .data()
+                        .get(Tag::ReadGroup)
+                        .and_then(|field| field.value().as_str())
+                        .map(|id| id.to_string());
+                    let ids = read_groups.entry(read_group).or_default();
+
+                    let mut changed = ids.instrument_names.insert(read.instrument_name.clone());
                     instrument_names.insert(read.instrument_name);
                     if let Some(fc) = read.flowcell {
+                        changed |= ids.flowcell_names.insert(fc.clone());
                         flowcell_names.insert(fc);
                     }
+
+                    if let (true, Some(early_stop)) = (changed, early_stop.as_mut()) {
+                        early_stop.changed(records_processed);
+                    }
                 }
+                Err(_) if !platform_unit_flowcells.is_empty() => unparsed_read_names += 1,
                 Err(_) => {
                     bail!(
                         "Could not parse Illumina-formatted query names for read: {}",
@@ -85,22 +118,57 @@ async fn app(src: PathBuf, first_n_reads: Option<usize>) -> anyhow::Result<()> {
             }
         }
 
-        if sample_max > 0 {
-            samples += 1;
-            if samples > sample_max {
-                break;
-            }
+        if num_records.is_reached(records_processed) {
+            return Ok(true);
         }
+
+        if early_stop.is_some_and(|e| e.is_stable(records_processed)) {
+            info!(
+                "Prediction was stable after {} records: stopping early.",
+                records_processed
+            );
+            stopped_early = true;
+            return Ok(true);
+        }
+
+        Ok(false)
+    })?;
+
+    if records_processed == 0 {
+        warn!("No records were found in the file: it appears to be empty or header-only.");
+    }
+
+    if unparsed_read_names > 0 {
+        warn!(
+            "{} read names were not Illumina-formatted: the flowcell ids are only \
+            from the platform units of the read groups.",
+            unparsed_read_names
+        );
     }
 
-    // (2) Derive the predict instrument results based on these detected
-    // instrument names and flowcell names.
-    let result = compute::predict(instrument_names, flowcell_names);
+    // (3) Derive the predict instrument results based on these detected
+    // instrument names and flowcell names, weighting the confidence by how
+    // many records were scanned. If the evidence conflicts, the candidates for
+    // each read group are included to help triage the file.
+    let mut result = compute::predict(instrument_names, flowcell_names, allow_union);
+    compute::calibrate_confidence(&mut result, records_processed);
+    result.records_processed = records_processed;
+    result.stopped_early = stopped_early;
+    result.input_checksum = input_checksum.finish()?;
+    result.provenance = Some(Provenance::now());
+    result.evidence.flowcell_ids_from_platform_units = platform_unit_flowcells
+        .into_iter()
+        .map(|(_, flowcell)| flowcell)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    if let Some(conflict) = result.conflict.as_mut() {
+        conflict.read_groups = compute::read_group_candidates(&read_groups);
+    }
 
-    // (3) Print the output to stdout as JSON (more support for different output
-    // types may be added in the future, but for now, only JSON).
-    let output = serde_json::to_string_pretty(&result).unwrap();
-    print!("{}", output);
+    // (4) Write the output in the requested format.
+    writers::write_result(format, "instrument", &result, common.output.as_deref())?;
 
     Ok(())
 }
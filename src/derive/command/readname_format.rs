@@ -0,0 +1,88 @@
+//! Functionality relating to the `ngs derive readname-format` subcommand itself.
+
+use clap::Args;
+use tracing::{info, warn};
+
+use crate::{
+    derive::{
+        command::DeriveCommonArgs,
+        readname_format::{DerivedReadNameFormatResult, ReadNameClassifier},
+        writers,
+    },
+    utils::provenance::Provenance,
+};
+
+/// Clap arguments for the `ngs derive readname-format` subcommand.
+#[derive(Args)]
+pub struct DeriveReadNameFormatArgs {
+    #[command(flatten)]
+    common: DeriveCommonArgs,
+}
+
+/// Entrypoint for the `ngs derive readname-format` subcommand.
+pub fn derive(args: DeriveReadNameFormatArgs) -> anyhow::Result<()> {
+    info!("Starting derive readname-format subcommand.");
+
+    let common = args.common;
+    let num_records = common.num_records();
+    let format = common.format()?;
+    let mut early_stop = common.early_stop();
+    let classifier = ReadNameClassifier::new();
+    let mut result = DerivedReadNameFormatResult::default();
+
+    let (mut reader, header, input_checksum) = common.open()?;
+
+    // (1) Classify the read names of the records within the file. Support for
+    // sampling only a portion of the reads is provided, as is stopping once
+    // no new formats are found and the predominant format is unchanged.
+    let mut predominant = None;
+
+    reader.for_each_record(&header, false, |record| {
+        result.records_processed += 1;
+
+        match record.read_name() {
+            Some(read_name) => {
+                let name: &str = read_name.as_ref();
+                let formats = result.formats.len();
+                result.update(classifier.classify(name), name);
+
+                if let Some(early_stop) = early_stop.as_mut() {
+                    let current = result.predominant();
+                    if result.formats.len() != formats || current != predominant {
+                        early_stop.changed(result.records_processed);
+                        predominant = current;
+                    }
+                }
+            }
+            None => result.records_without_read_name += 1,
+        }
+
+        if num_records.is_reached(result.records_processed) {
+            return Ok(true);
+        }
+
+        if early_stop.is_some_and(|e| e.is_stable(result.records_processed)) {
+            info!(
+                "Prediction was stable after {} records: stopping early.",
+                result.records_processed
+            );
+            result.stopped_early = true;
+            return Ok(true);
+        }
+
+        Ok(false)
+    })?;
+
+    if result.records_processed == 0 {
+        warn!("No records were found in the file: it appears to be empty or header-only.");
+    }
+
+    // (2) Summarize the formats and write the output in the requested format.
+    result.summarize();
+    result.input_checksum = input_checksum.finish()?;
+    result.provenance = Some(Provenance::now());
+
+    writers::write_result(format, "readname-format", &result, common.output.as_deref())?;
+
+    Ok(())
+}
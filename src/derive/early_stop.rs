@@ -0,0 +1,117 @@
+//! Functionality related to stopping `ngs derive` subcommands early once their
+//! prediction is stable.
+//!
+//! The evidence that `ngs derive` subcommands use for their predictions (e.g.,
+//! the instrument names and flowcell names seen) tends to saturate quickly, so
+//! scanning every record of a large file rarely changes the prediction. When
+//! early stopping is enabled, the subcommand stops once the evidence has not
+//! changed over a window of records and reports how many records were needed.
+//!
+//! Only whether the evidence changed is considered: the confidence of the
+//! prediction is not re-evaluated as records are read. Instead, a subcommand
+//! may require a minimum number of records before stopping. `ngs derive
+//! instrument` requires the number of records below which the confidence of its
+//! prediction is lowered (see [`SPARSE_RECORDS`]), so stopping early never
+//! lowers the confidence.
+//!
+//! [`SPARSE_RECORDS`]: crate::derive::instrument::compute::SPARSE_RECORDS
+
+use std::num::NonZeroUsize;
+
+/// Tracks whether the evidence for a prediction has been stable for long
+/// enough to stop early.
+///
+/// ```
+/// use std::num::NonZeroUsize;
+/// use ngs::derive::early_stop::EarlyStop;
+///
+/// let mut early_stop = EarlyStop::new(NonZeroUsize::new(10).unwrap());
+/// early_stop.changed(5);
+/// assert!(!early_stop.is_stable(14));
+/// assert!(early_stop.is_stable(15));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct EarlyStop {
+    /// The number of records over which the evidence must remain unchanged.
+    window: NonZeroUsize,
+
+    /// The number of records that must be seen before stopping.
+    min_records: usize,
+
+    /// The number of records that had been seen when the evidence last
+    /// changed.
+    last_change: usize,
+}
+
+impl EarlyStop {
+    /// Creates a new [`EarlyStop`] with the provided window.
+    pub fn new(window: NonZeroUsize) -> Self {
+        Self {
+            window,
+            min_records: 0,
+            last_change: 0,
+        }
+    }
+
+    /// Requires at least `min_records` records to be seen before stopping,
+    /// however stable the evidence.
+    pub fn with_min_records(self, min_records: usize) -> Self {
+        Self {
+            min_records,
+            ..self
+        }
+    }
+
+    /// Records that the evidence changed after `records_seen` records.
+    pub fn changed(&mut self, records_seen: usize) {
+        self.last_change = records_seen;
+    }
+
+    /// Whether the evidence has remained unchanged over the window (and the
+    /// minimum number of records have been seen), having seen `records_seen`
+    /// records.
+    pub fn is_stable(&self, records_seen: usize) -> bool {
+        records_seen >= self.min_records
+            && records_seen.saturating_sub(self.last_change) >= self.window.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds records to the early stopping criteria, noting a change to the
+    /// evidence at each of `changes`, and returns the number of records seen
+    /// when it stopped (if it did).
+    fn run(mut early_stop: EarlyStop, records: usize, changes: &[usize]) -> Option<usize> {
+        (1..=records).find(|records_seen| {
+            if changes.contains(records_seen) {
+                early_stop.changed(*records_seen);
+            }
+            early_stop.is_stable(*records_seen)
+        })
+    }
+
+    #[test]
+    pub fn it_stops_once_the_evidence_is_stable_over_the_window() {
+        let early_stop = EarlyStop::new(NonZeroUsize::new(10).unwrap());
+        assert_eq!(run(early_stop, 100, &[1]), Some(11));
+        assert_eq!(run(early_stop, 5, &[1]), None);
+    }
+
+    #[test]
+    pub fn it_resets_the_window_when_the_evidence_changes() {
+        let early_stop = EarlyStop::new(NonZeroUsize::new(10).unwrap());
+        assert_eq!(run(early_stop, 100, &[1, 8, 15]), Some(25));
+        // A change on the last record of the window restarts it.
+        assert_eq!(run(early_stop, 100, &[1, 10, 19]), Some(29));
+    }
+
+    #[test]
+    pub fn it_waits_for_the_minimum_number_of_records() {
+        let early_stop = EarlyStop::new(NonZeroUsize::new(10).unwrap()).with_min_records(50);
+        assert_eq!(run(early_stop, 100, &[1]), Some(50));
+        assert_eq!(run(early_stop, 100, &[1, 45]), Some(55));
+        assert_eq!(run(early_stop, 40, &[1]), None);
+    }
+}
@@ -1,6 +1,8 @@
 //! Supporting functionality for the `ngs derive instrument` subcommand.
 
+pub mod chemistry;
 pub mod compute;
 pub mod flowcells;
 pub mod instruments;
+pub mod platform_units;
 pub mod reads;
@@ -0,0 +1,240 @@
+//! Knowledge about which flowcell patterns map to which flowcell types,
+//! sequencing chemistries, and periods of use.
+//!
+//! The periods of use are approximate and are based on when each flowcell type
+//! was commercially available. They are intended to help sanity check the age
+//! of a dataset rather than to pinpoint the date of a run.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+/// An approximate range of years during which a flowcell type was in use.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+pub struct RunDateRange {
+    /// The first year the flowcell type was available.
+    pub earliest_year: u16,
+
+    /// The last year the flowcell type was available, if it has been
+    /// discontinued.
+    pub latest_year: Option<u16>,
+}
+
+/// What is known about the chemistry of a flowcell type.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+pub struct FlowcellChemistry {
+    /// The type of the flowcell (e.g., `S4`).
+    pub flowcell_type: &'static str,
+
+    /// The sequencing chemistry used with the flowcell.
+    pub chemistry: &'static str,
+
+    /// Whether the flowcell is patterned (as opposed to a random cluster
+    /// flowcell). Patterned flowcells are prone to pad hopping and index
+    /// hopping artifacts.
+    pub patterned: bool,
+
+    /// The approximate range of years during which the flowcell was in use.
+    pub run_date_range: RunDateRange,
+}
+
+/// Utility function to construct a [`FlowcellChemistry`].
+const fn chemistry(
+    flowcell_type: &'static str,
+    chemistry: &'static str,
+    patterned: bool,
+    earliest_year: u16,
+    latest_year: Option<u16>,
+) -> FlowcellChemistry {
+    FlowcellChemistry {
+        flowcell_type,
+        chemistry,
+        patterned,
+        run_date_range: RunDateRange {
+            earliest_year,
+            latest_year,
+        },
+    }
+}
+
+/// Encapsulates the knowledge we currently have on which flowcell patterns map
+/// to which flowcell chemistries as a [`HashMap`]. The patterns are the same as
+/// those in [`build_flowcell_lookup_table`](super::flowcells::build_flowcell_lookup_table).
+pub fn build_flowcell_chemistry_lookup_table() -> HashMap<&'static str, FlowcellChemistry> {
+    HashMap::from([
+        (
+            "^C[A-Z0-9]{4}ANXX$",
+            chemistry("High Output v4", "HiSeq SBS v4", false, 2014, Some(2019)),
+        ),
+        (
+            "^C[A-Z0-9]{4}ACXX$",
+            chemistry("High Output v3", "TruSeq SBS v3", false, 2011, Some(2016)),
+        ),
+        (
+            "^D[A-Z0-9]{4}ACXX$",
+            chemistry("High Output v3", "TruSeq SBS v3", false, 2011, Some(2016)),
+        ),
+        (
+            "^H[A-Z0-9]{4}ADXX$",
+            chemistry("Rapid Run v1", "TruSeq Rapid SBS", false, 2012, Some(2015)),
+        ),
+        (
+            "^H[A-Z0-9]{4}BCXX$",
+            chemistry(
+                "Rapid Run v2",
+                "HiSeq Rapid SBS v2",
+                false,
+                2014,
+                Some(2019),
+            ),
+        ),
+        (
+            "^H[A-Z0-9]{4}BCXY$",
+            chemistry(
+                "Rapid Run v2",
+                "HiSeq Rapid SBS v2",
+                false,
+                2014,
+                Some(2019),
+            ),
+        ),
+        (
+            "^H[A-Z0-9]{4}BBXX$",
+            chemistry(
+                "HiSeq 3000/4000",
+                "HiSeq 3000/4000 SBS",
+                true,
+                2015,
+                Some(2022),
+            ),
+        ),
+        (
+            "^H[A-Z0-9]{4}BBXY$",
+            chemistry(
+                "HiSeq 3000/4000",
+                "HiSeq 3000/4000 SBS",
+                true,
+                2015,
+                Some(2022),
+            ),
+        ),
+        (
+            "^H[A-Z0-9]{4}CCXX$",
+            chemistry("HiSeq X v2", "HiSeq X SBS v2/v2.5", true, 2015, Some(2022)),
+        ),
+        (
+            "^H[A-Z0-9]{4}CCXY$",
+            chemistry("HiSeq X v2", "HiSeq X SBS v2/v2.5", true, 2015, Some(2022)),
+        ),
+        (
+            "^H[A-Z0-9]{4}ALXX$",
+            chemistry("HiSeq X v1", "HiSeq X SBS v1", true, 2014, Some(2015)),
+        ),
+        (
+            "^H[A-Z0-9]{4}BGX[A-Z,0-9]$",
+            chemistry(
+                "High Output",
+                "NextSeq v2/v2.5 (two-color)",
+                false,
+                2014,
+                None,
+            ),
+        ),
+        (
+            "^H[A-Z0-9]{4}AFXX$",
+            chemistry(
+                "Mid Output",
+                "NextSeq v2/v2.5 (two-color)",
+                false,
+                2014,
+                None,
+            ),
+        ),
+        (
+            "^000H[A-Z0-9]{5}$",
+            chemistry(
+                "High or Mid Output",
+                "MiniSeq (two-color)",
+                false,
+                2016,
+                None,
+            ),
+        ),
+        (
+            // P1, P2, and P3 flowcells share the same pattern.
+            "^AA[A-Z0-9]{5}M5$",
+            chemistry(
+                "P1, P2, or P3",
+                "NextSeq 1000/2000 (two-color)",
+                true,
+                2020,
+                None,
+            ),
+        ),
+        (
+            // S1 and SP flowcells share the same pattern.
+            "^H[A-Z0-9]{5}RXX$",
+            chemistry("S1 or SP", "NovaSeq (two-color)", true, 2017, None),
+        ),
+        (
+            "^H[A-Z0-9]{5}MXX$",
+            chemistry("S2", "NovaSeq (two-color)", true, 2017, None),
+        ),
+        (
+            "^H[A-Z0-9]{5}SXX$",
+            chemistry("S4", "NovaSeq (two-color)", true, 2017, None),
+        ),
+        (
+            "^2[A-Z0-9]{5}LT1$",
+            chemistry("1.5B", "XLEAP-SBS (two-color)", true, 2023, None),
+        ),
+        (
+            "^2[A-Z0-9]{5}LT3$",
+            chemistry("10B", "XLEAP-SBS (two-color)", true, 2023, None),
+        ),
+        (
+            "^2[A-Z0-9]{5}LT4$",
+            chemistry("25B", "XLEAP-SBS (two-color)", true, 2023, None),
+        ),
+        (
+            "^A[A-Z0-9]{4}$",
+            chemistry("Standard", "MiSeq", false, 2011, None),
+        ),
+        (
+            "^B[A-Z0-9]{4}$",
+            chemistry("Standard", "MiSeq", false, 2011, None),
+        ),
+        (
+            "^D[A-Z0-9]{4}$",
+            chemistry("Nano", "MiSeq", false, 2013, None),
+        ),
+        (
+            "^G[A-Z0-9]{4}$",
+            chemistry("Micro", "MiSeq", false, 2015, None),
+        ),
+        (
+            "^BPL[0-9]{5}-[0-9]{4}$",
+            chemistry("i1", "iSeq 100 (one-color)", true, 2018, None),
+        ),
+        (
+            "^FFA[0-9]{5}-[0-9]{4}$",
+            chemistry("i1", "iSeq 100 (one-color)", true, 2018, None),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::derive::instrument::flowcells::build_flowcell_lookup_table;
+
+    use super::*;
+
+    #[test]
+    pub fn it_describes_the_chemistry_of_every_known_flowcell_pattern() {
+        let chemistries = build_flowcell_chemistry_lookup_table();
+
+        for pattern in build_flowcell_lookup_table().keys() {
+            assert!(chemistries.contains_key(pattern), "{}", pattern);
+        }
+    }
+}
@@ -1,12 +1,110 @@
 //! Combines the flowcell and instrument checks into a single workflow.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use regex::Regex;
 use serde::Serialize;
 use tracing::info;
 
-use super::{flowcells, instruments};
+use crate::utils::{checksum::InputChecksum, provenance::Provenance};
+
+use super::{
+    chemistry::{self, FlowcellChemistry},
+    flowcells, instruments,
+};
+
+/// The evidence gathered for a single query (an instrument id or a flowcell
+/// id).
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct QueryEvidence {
+    /// The query that was looked up.
+    pub query: String,
+
+    /// The patterns in the lookup table that matched the query, in sorted
+    /// order.
+    pub matched_patterns: Vec<String>,
+
+    /// The instruments that could have produced the query, in sorted order.
+    pub instruments: Vec<String>,
+}
+
+/// Structured evidence describing why a prediction was made.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Evidence {
+    /// The kinds of ids the prediction is based on (e.g., `instrument and
+    /// flowcell id`), if any.
+    pub basis: Option<String>,
+
+    /// A general comment describing the prediction, if available.
+    pub comment: Option<String>,
+
+    /// The evidence for each instrument id detected, in sorted order.
+    pub instrument_ids: Vec<QueryEvidence>,
+
+    /// The evidence for each flowcell id detected, in sorted order.
+    pub flowcell_ids: Vec<QueryEvidence>,
+
+    /// The flowcell ids that were parsed from the platform unit (`PU`) of the
+    /// read groups in the header rather than from the read names, in sorted
+    /// order.
+    pub flowcell_ids_from_platform_units: Vec<String>,
+
+    /// The number of records that were scanned for ids.
+    pub records_scanned: usize,
+
+    /// The number of distinct ids (instrument ids and flowcell ids) that were
+    /// looked up.
+    pub distinct_queries: usize,
+}
+
+/// The instrument ids and flowcell ids detected within a single read group.
+#[derive(Clone, Debug, Default)]
+pub struct ReadGroupIds {
+    /// The instrument ids detected within the read group.
+    pub instrument_names: HashSet<String>,
+
+    /// The flowcell ids detected within the read group.
+    pub flowcell_names: HashSet<String>,
+}
+
+/// The candidate instruments for a single read group.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ReadGroupCandidates {
+    /// The id of the read group (or `None` for records without an `RG` tag).
+    pub read_group: Option<String>,
+
+    /// The possible instruments according to the instrument ids, in sorted
+    /// order.
+    pub instruments_by_instrument_id: Vec<String>,
+
+    /// The possible instruments according to the flowcell ids, in sorted
+    /// order.
+    pub instruments_by_flowcell_id: Vec<String>,
+}
+
+/// The details of a conflict between the instrument id and flowcell id
+/// predictions, which is used to triage files that likely contain data from
+/// more than one run.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Conflict {
+    /// The possible instruments according to the instrument ids, in sorted
+    /// order.
+    pub instruments_by_instrument_id: Vec<String>,
+
+    /// The possible instruments according to the flowcell ids, in sorted
+    /// order.
+    pub instruments_by_flowcell_id: Vec<String>,
+
+    /// The candidate instruments for each read group, in sorted order.
+    pub read_groups: Vec<ReadGroupCandidates>,
+}
+
+/// Utility function to convert a set of instruments to a sorted [`Vec`].
+fn sorted(instruments: &HashSet<String>) -> Vec<String> {
+    let mut instruments: Vec<String> = instruments.iter().cloned().collect();
+    instruments.sort();
+    instruments
+}
 
 /// Generalized struct for holding instrument detection results.
 #[derive(Debug, Default, Serialize)]
@@ -15,6 +113,8 @@ pub struct InstrumentDetectionResults {
     pub possible_instruments: Option<HashSet<String>>,
     /// Whether or not at least one machine has been detected.
     pub detected_at_least_one_machine: bool,
+    /// The evidence for each query that was looked up, in sorted order.
+    pub queries: Vec<QueryEvidence>,
 }
 
 impl InstrumentDetectionResults {
@@ -48,35 +148,64 @@ pub struct DerivedInstrumentResult {
     pub succeeded: bool,
 
     /// The possible instruments detected by `ngs derive instrument`, if
-    /// available.
-    pub instruments: Option<HashSet<String>>,
+    /// available, in sorted order.
+    pub instruments: Option<BTreeSet<String>>,
 
     /// The level of confidence that the tool has concerning these results.
     pub confidence: String,
 
-    /// Status of the evidence that supports (or lack thereof) these predicted
-    /// instruments, if available.  
-    pub evidence: Option<String>,
+    /// The evidence that supports (or lack thereof) these predicted
+    /// instruments.
+    pub evidence: Evidence,
 
-    /// A general comment field, if available.
-    pub comment: Option<String>,
+    /// The candidate instruments from the instrument ids and the flowcell ids
+    /// when they are mutually exclusive.
+    pub conflict: Option<Conflict>,
+
+    /// The number of records that were examined to produce this result.
+    pub records_processed: usize,
+
+    /// Whether the records were no longer examined once the prediction was
+    /// stable (with `--early-stop`), in which case `records_processed` is the
+    /// number of records that were needed.
+    pub stopped_early: bool,
+
+    /// The likely flowcell type, chemistry, and approximate run date range,
+    /// if every flowcell detected shares the same chemistry.
+    pub chemistry: Option<FlowcellChemistry>,
+
+    /// The checksum of the file (only computed with `--checksums`).
+    pub input_checksum: Option<InputChecksum>,
+
+    /// The invocation of `ngs` that produced the results.
+    pub provenance: Option<Provenance>,
 }
 
 impl DerivedInstrumentResult {
-    /// Creates a new [`DerivedInstrumentResult`].
+    /// Creates a new [`DerivedInstrumentResult`]. The evidence for each query
+    /// is filled in separately.
     pub fn new(
         succeeded: bool,
         instruments: Option<HashSet<String>>,
         confidence: String,
-        evidence: Option<String>,
+        basis: Option<String>,
         comment: Option<String>,
     ) -> Self {
         DerivedInstrumentResult {
             succeeded,
-            instruments,
+            instruments: instruments.map(|i| i.into_iter().collect()),
             confidence,
-            evidence,
-            comment,
+            evidence: Evidence {
+                basis,
+                comment,
+                ..Default::default()
+            },
+            conflict: None,
+            records_processed: 0,
+            stopped_early: false,
+            chemistry: None,
+            input_checksum: None,
+            provenance: None,
         }
     }
 }
@@ -102,18 +231,41 @@ pub fn possible_instruments_for_query(
     query: String,
     lookup_table: &HashMap<&'static str, HashSet<&'static str>>,
 ) -> HashSet<String> {
-    let mut result: HashSet<String> = HashSet::new();
+    evidence_for_query(query, lookup_table)
+        .instruments
+        .into_iter()
+        .collect()
+}
+
+/// Looks up a query in the lookup table as in
+/// [`possible_instruments_for_query`], recording which patterns matched along
+/// with the possible instruments.
+pub fn evidence_for_query(
+    query: String,
+    lookup_table: &HashMap<&'static str, HashSet<&'static str>>,
+) -> QueryEvidence {
+    let mut matched_patterns = Vec::new();
+    let mut instruments: HashSet<String> = HashSet::new();
 
     for (pattern, machines) in lookup_table {
         let re = Regex::new(pattern).unwrap();
         if re.is_match(query.as_str()) {
-            let matching_machines: Vec<String> = machines.iter().map(|x| x.to_string()).collect();
-            result.extend(matching_machines);
+            matched_patterns.push(pattern.to_string());
+            instruments.extend(machines.iter().map(|x| x.to_string()));
         }
     }
 
-    info!(" [*] {}, Possible Instruments: {:?}", query, result);
-    result
+    info!(" [*] {}, Possible Instruments: {:?}", query, instruments);
+
+    matched_patterns.sort();
+    let mut instruments: Vec<String> = instruments.into_iter().collect();
+    instruments.sort();
+
+    QueryEvidence {
+        query,
+        matched_patterns,
+        instruments,
+    }
 }
 
 /// Given a HashSet of unique queries (usually a instrument ID or flowcell ID
@@ -143,18 +295,65 @@ pub fn predict_instrument(
     let mut result = InstrumentDetectionResults::default();
 
     for name in queries {
-        let derived = possible_instruments_for_query(name, lookup_table);
+        let evidence = evidence_for_query(name, lookup_table);
+        let derived: HashSet<String> = evidence.instruments.iter().cloned().collect();
         result.update_instruments(&derived);
+        result.queries.push(evidence);
+    }
+
+    result.queries.sort_by(|a, b| a.query.cmp(&b.query));
+    result
+}
+
+/// Predicts the flowcell chemistry for a set of flowcell ids. A chemistry is
+/// only returned if every flowcell id matches exactly the same chemistry.
+pub fn predict_chemistry(
+    flowcell_names: &HashSet<String>,
+    lookup_table: &HashMap<&'static str, FlowcellChemistry>,
+) -> Option<FlowcellChemistry> {
+    let mut result: Option<FlowcellChemistry> = None;
+
+    for name in flowcell_names {
+        let matches: HashSet<FlowcellChemistry> = lookup_table
+            .iter()
+            .filter(|(pattern, _)| Regex::new(pattern).unwrap().is_match(name))
+            .map(|(_, chemistry)| *chemistry)
+            .collect();
+
+        match (matches.len(), result) {
+            (1, None) => result = matches.into_iter().next(),
+            (1, Some(previous)) if matches.contains(&previous) => {}
+            _ => return None,
+        }
     }
 
     result
 }
 
 /// Combines evidence from the instrument id detection and flowcell id detection
-/// to produce a final [`DerivedInstrumentResult`].
+/// to produce a final [`DerivedInstrumentResult`]. If `allow_union` is set and
+/// the two are mutually exclusive, the union of the possible instruments is
+/// reported with low confidence instead of failing.
 pub fn resolve_instrument_prediction(
+    mut iid_results: InstrumentDetectionResults,
+    mut fcid_results: InstrumentDetectionResults,
+    allow_union: bool,
+) -> DerivedInstrumentResult {
+    let instrument_ids = std::mem::take(&mut iid_results.queries);
+    let flowcell_ids = std::mem::take(&mut fcid_results.queries);
+
+    let mut result = resolve_instruments(iid_results, fcid_results, allow_union);
+    result.evidence.instrument_ids = instrument_ids;
+    result.evidence.flowcell_ids = flowcell_ids;
+    result
+}
+
+/// Resolves the possible instruments from the instrument id detection and
+/// flowcell id detection.
+fn resolve_instruments(
     iid_results: InstrumentDetectionResults,
     fcid_results: InstrumentDetectionResults,
+    allow_union: bool,
 ) -> DerivedInstrumentResult {
     let possible_instruments_by_iid = iid_results.possible_instruments.unwrap_or_default();
     let possible_instruments_by_fcid = fcid_results.possible_instruments.unwrap_or_default();
@@ -241,18 +440,49 @@ pub fn resolve_instrument_prediction(
         .cloned()
         .collect();
 
+    // (6) If the results are mutually exclusive, the file needs triaging. The
+    // candidate instruments from each source are reported so that the
+    // conflict can be audited.
     if overlapping_instruments.is_empty() {
-        return DerivedInstrumentResult::new(
-            false,
-            None,
-            "high".to_string(),
-            Some("instrument and flowcell id".to_string()),
-            Some(
-                "Case needs triaging, results from instrument id and \
+        let conflict = Conflict {
+            instruments_by_instrument_id: sorted(&possible_instruments_by_iid),
+            instruments_by_flowcell_id: sorted(&possible_instruments_by_fcid),
+            read_groups: Vec::new(),
+        };
+
+        let mut result = if allow_union {
+            let instruments = possible_instruments_by_iid
+                .union(&possible_instruments_by_fcid)
+                .cloned()
+                .collect();
+
+            DerivedInstrumentResult::new(
+                true,
+                Some(instruments),
+                "low".to_string(),
+                Some("instrument and flowcell id".to_string()),
+                Some(
+                    "results from instrument id and flowcell id are mutually \
+                    exclusive, so the union of the possible instruments is reported."
+                        .to_string(),
+                ),
+            )
+        } else {
+            DerivedInstrumentResult::new(
+                false,
+                None,
+                "high".to_string(),
+                Some("instrument and flowcell id".to_string()),
+                Some(
+                    "Case needs triaging, results from instrument id and \
                          flowcell id are mutually exclusive."
-                    .to_string(),
-            ),
-        );
+                        .to_string(),
+                ),
+            )
+        };
+
+        result.conflict = Some(conflict);
+        return result;
     }
 
     DerivedInstrumentResult::new(
@@ -264,20 +494,106 @@ pub fn resolve_instrument_prediction(
     )
 }
 
+/// Computes the candidate instruments for each read group, which is useful to
+/// triage files where the instrument ids and flowcell ids conflict (e.g.,
+/// because read groups from different runs were merged).
+pub fn read_group_candidates(
+    read_groups: &HashMap<Option<String>, ReadGroupIds>,
+) -> Vec<ReadGroupCandidates> {
+    let instruments = instruments::build_instrument_lookup_table();
+    let flowcells = flowcells::build_flowcell_lookup_table();
+
+    let mut candidates: Vec<ReadGroupCandidates> = read_groups
+        .iter()
+        .map(|(read_group, ids)| {
+            let iid_results = predict_instrument(ids.instrument_names.clone(), &instruments);
+            let fcid_results = predict_instrument(ids.flowcell_names.clone(), &flowcells);
+
+            ReadGroupCandidates {
+                read_group: read_group.clone(),
+                instruments_by_instrument_id: sorted(
+                    &iid_results.possible_instruments.unwrap_or_default(),
+                ),
+                instruments_by_flowcell_id: sorted(
+                    &fcid_results.possible_instruments.unwrap_or_default(),
+                ),
+            }
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| a.read_group.cmp(&b.read_group));
+    candidates
+}
+
+/// The number of records below which a prediction is considered to be based
+/// on too few records to be trusted at face value.
+pub const SPARSE_RECORDS: usize = 10_000;
+
+/// The levels of confidence, from least to most confident.
+const CONFIDENCE_LEVELS: [&str; 3] = ["low", "medium", "high"];
+
+/// Weights the confidence of a successful prediction by the amount of data it
+/// is based on. One level of confidence is lost when fewer than
+/// [`SPARSE_RECORDS`] records were scanned, and another when, in addition, only
+/// a single distinct id supports the prediction (e.g., one flowcell id from
+/// 500 reads is much weaker evidence than the same flowcell id from 50 million
+/// reads). The confidence never drops below `low`.
+pub fn calibrate_confidence(result: &mut DerivedInstrumentResult, records_scanned: usize) {
+    let distinct_queries =
+        result.evidence.instrument_ids.len() + result.evidence.flowcell_ids.len();
+    result.evidence.records_scanned = records_scanned;
+    result.evidence.distinct_queries = distinct_queries;
+
+    let level = match CONFIDENCE_LEVELS
+        .iter()
+        .position(|level| *level == result.confidence)
+    {
+        Some(level) if result.succeeded => level,
+        _ => return,
+    };
+
+    let penalty = match (records_scanned < SPARSE_RECORDS, distinct_queries <= 1) {
+        (true, true) => 2,
+        (true, false) => 1,
+        (false, _) => 0,
+    };
+
+    let calibrated = level.saturating_sub(penalty);
+    if calibrated == level {
+        return;
+    }
+
+    result.confidence = CONFIDENCE_LEVELS[calibrated].to_string();
+    let reason = format!(
+        "confidence was lowered from {} because only {} records were scanned",
+        CONFIDENCE_LEVELS[level], records_scanned
+    );
+    result.evidence.comment = Some(match result.evidence.comment.take() {
+        Some(comment) => format!("{} ({})", comment, reason),
+        None => reason,
+    });
+}
+
 /// Main method to evaluate the detected instrument names and flowcell names and
 /// return a result for the derived instruments. This may fail, and the
-/// resulting [`DerivedInstrumentResult`] should be evaluated accordingly.
+/// resulting [`DerivedInstrumentResult`] should be evaluated accordingly. See
+/// [`resolve_instrument_prediction`] for details on `allow_union`.
 pub fn predict(
     instrument_names: HashSet<String>,
     flowcell_names: HashSet<String>,
+    allow_union: bool,
 ) -> DerivedInstrumentResult {
     let instruments = instruments::build_instrument_lookup_table();
     let flowcells = flowcells::build_flowcell_lookup_table();
+    let chemistries = chemistry::build_flowcell_chemistry_lookup_table();
 
+    let chemistry = predict_chemistry(&flowcell_names, &chemistries);
     let iid_results = predict_instrument(instrument_names, &instruments);
     let fcid_results = predict_instrument(flowcell_names, &flowcells);
 
-    resolve_instrument_prediction(iid_results, fcid_results)
+    let mut result = resolve_instrument_prediction(iid_results, fcid_results, allow_union);
+    result.chemistry = chemistry;
+    result
 }
 
 #[cfg(test)]
@@ -299,6 +615,15 @@ mod tests {
         assert!(result.contains("NovaSeq"));
     }
 
+    #[test]
+    fn test_derive_instrument_from_valid_novaseq_x_instrument_name() {
+        let instruments = instruments::build_instrument_lookup_table();
+        let result = possible_instruments_for_query(String::from("LH00000"), &instruments);
+        assert_eq!(result.len(), 2);
+        assert!(result.contains("NovaSeq X"));
+        assert!(result.contains("NovaSeq X Plus"));
+    }
+
     #[test]
     fn test_derive_instrument_from_invalid_flowcell_name() {
         let flowcells = flowcells::build_flowcell_lookup_table();
@@ -314,37 +639,88 @@ mod tests {
         assert!(result.contains("NovaSeq"));
     }
 
+    #[test]
+    fn test_derive_instrument_from_valid_novaseq_x_flowcell_names() {
+        let flowcells = flowcells::build_flowcell_lookup_table();
+
+        for name in ["22CFFYLT1", "22CFFYLT3"] {
+            let result = possible_instruments_for_query(String::from(name), &flowcells);
+            assert_eq!(result.len(), 2, "{}", name);
+            assert!(result.contains("NovaSeq X"));
+            assert!(result.contains("NovaSeq X Plus"));
+        }
+
+        let result = possible_instruments_for_query(String::from("22CFFYLT4"), &flowcells);
+        assert_eq!(result.len(), 1);
+        assert!(result.contains("NovaSeq X Plus"));
+    }
+
     #[test]
     fn test_derive_instrument_novaseq_succesfully() {
         let detected_iids = HashSet::from(["A00000".to_string()]);
         let detected_fcids = HashSet::from(["H00000RXX".to_string()]);
-        let result = predict(detected_iids, detected_fcids);
+        let result = predict(detected_iids, detected_fcids, false);
+
+        assert!(result.succeeded);
+        assert_eq!(
+            result.instruments,
+            Some(BTreeSet::from(["NovaSeq".to_string()]))
+        );
+        assert_eq!(result.confidence, "high".to_string());
+        assert_eq!(
+            result.evidence.basis,
+            Some("instrument and flowcell id".to_string())
+        );
+        assert_eq!(result.evidence.comment, None);
+        assert_eq!(
+            result.evidence.instrument_ids,
+            vec![QueryEvidence {
+                query: "A00000".to_string(),
+                matched_patterns: vec!["^A[0-9]{5}$".to_string()],
+                instruments: vec!["NovaSeq".to_string()],
+            }]
+        );
+        assert_eq!(result.evidence.flowcell_ids.len(), 1);
+    }
+
+    #[test]
+    fn test_derive_instrument_novaseq_x_plus_succesfully() {
+        let detected_iids = HashSet::from(["LH00000".to_string()]);
+        let detected_fcids = HashSet::from(["22CFFYLT4".to_string()]);
+        let result = predict(detected_iids, detected_fcids, false);
 
         assert!(result.succeeded);
         assert_eq!(
             result.instruments,
-            Some(HashSet::from(["NovaSeq".to_string()]))
+            Some(BTreeSet::from(["NovaSeq X Plus".to_string()]))
         );
         assert_eq!(result.confidence, "high".to_string());
         assert_eq!(
-            result.evidence,
+            result.evidence.basis,
             Some("instrument and flowcell id".to_string())
         );
-        assert_eq!(result.comment, None);
+        assert_eq!(result.chemistry.unwrap().flowcell_type, "25B");
+
+        // The NovaSeq X and X Plus both accept 10B flowcells.
+        let detected_iids = HashSet::from(["LH00000".to_string()]);
+        let detected_fcids = HashSet::from(["22CFFYLT3".to_string()]);
+        let result = predict(detected_iids, detected_fcids, false);
+        assert_eq!(result.instruments.unwrap().len(), 2);
+        assert_eq!(result.confidence, "high".to_string());
     }
 
     #[test]
     fn test_derive_instrument_conflicting_instrument_ids() {
         let detected_iids = HashSet::from(["A00000".to_string(), "D00000".to_string()]);
         let detected_fcids = HashSet::from(["H00000RXX".to_string()]);
-        let result = predict(detected_iids, detected_fcids);
+        let result = predict(detected_iids, detected_fcids, false);
 
         assert!(!result.succeeded);
         assert_eq!(result.instruments, None);
         assert_eq!(result.confidence, "unknown".to_string());
-        assert_eq!(result.evidence, Some("instrument id".to_string()));
+        assert_eq!(result.evidence.basis, Some("instrument id".to_string()));
         assert_eq!(
-            result.comment,
+            result.evidence.comment,
             Some(
                 "multiple instruments were detected in this file via the instrument id".to_string()
             )
@@ -355,14 +731,14 @@ mod tests {
     fn test_derive_instrument_conflicting_flowcell_ids() {
         let detected_iids = HashSet::from(["A00000".to_string()]);
         let detected_fcids = HashSet::from(["H00000RXX".to_string(), "B0000".to_string()]);
-        let result = predict(detected_iids, detected_fcids);
+        let result = predict(detected_iids, detected_fcids, false);
 
         assert!(!result.succeeded);
         assert_eq!(result.instruments, None);
         assert_eq!(result.confidence, "unknown".to_string());
-        assert_eq!(result.evidence, Some("flowcell id".to_string()));
+        assert_eq!(result.evidence.basis, Some("flowcell id".to_string()));
         assert_eq!(
-            result.comment,
+            result.evidence.comment,
             Some("multiple instruments were detected in this file via the flowcell id".to_string())
         );
     }
@@ -371,101 +747,260 @@ mod tests {
     fn test_derive_instrument_medium_instrument_evidence() {
         let detected_iids = HashSet::from(["A00000".to_string()]);
         let detected_fcids = HashSet::new();
-        let result = predict(detected_iids, detected_fcids);
+        let result = predict(detected_iids, detected_fcids, false);
 
         assert!(result.succeeded);
         assert_eq!(
             result.instruments,
-            Some(HashSet::from(["NovaSeq".to_string()]))
+            Some(BTreeSet::from(["NovaSeq".to_string()]))
         );
         assert_eq!(result.confidence, "medium".to_string());
-        assert_eq!(result.evidence, Some("instrument id".to_string()));
-        assert_eq!(result.comment, None);
+        assert_eq!(result.evidence.basis, Some("instrument id".to_string()));
+        assert_eq!(result.evidence.comment, None);
     }
 
     #[test]
     fn test_derive_instrument_low_instrument_evidence() {
         let detected_iids = HashSet::from(["K00000".to_string()]);
         let detected_fcids = HashSet::new();
-        let result = predict(detected_iids, detected_fcids);
+        let result = predict(detected_iids, detected_fcids, false);
 
         assert!(result.succeeded);
         assert_eq!(
             result.instruments,
-            Some(HashSet::from([
+            Some(BTreeSet::from([
                 "HiSeq 4000".to_string(),
                 "HiSeq 3000".to_string()
             ]))
         );
         assert_eq!(result.confidence, "low".to_string());
-        assert_eq!(result.evidence, Some("instrument id".to_string()));
-        assert_eq!(result.comment, None);
+        assert_eq!(result.evidence.basis, Some("instrument id".to_string()));
+        assert_eq!(result.evidence.comment, None);
     }
 
     #[test]
     fn test_derive_instrument_medium_flowcell_evidence() {
         let detected_iids = HashSet::new();
         let detected_fcids = HashSet::from(["H00000RXX".to_string()]);
-        let result = predict(detected_iids, detected_fcids);
+        let result = predict(detected_iids, detected_fcids, false);
 
         assert!(result.succeeded);
         assert_eq!(
             result.instruments,
-            Some(HashSet::from(["NovaSeq".to_string()]))
+            Some(BTreeSet::from(["NovaSeq".to_string()]))
         );
         assert_eq!(result.confidence, "medium".to_string());
-        assert_eq!(result.evidence, Some("flowcell id".to_string()));
-        assert_eq!(result.comment, None);
+        assert_eq!(result.evidence.basis, Some("flowcell id".to_string()));
+        assert_eq!(result.evidence.comment, None);
     }
 
     #[test]
     fn test_derive_instrument_low_flowcell_evidence() {
         let detected_iids = HashSet::new();
         let detected_fcids = HashSet::from(["H0000ADXX".to_string()]);
-        let result = predict(detected_iids, detected_fcids);
+        let result = predict(detected_iids, detected_fcids, false);
 
         assert!(result.succeeded);
         assert_eq!(
             result.instruments,
-            Some(HashSet::from([
+            Some(BTreeSet::from([
                 "HiSeq 2000".to_string(),
                 "HiSeq 1500".to_string(),
                 "HiSeq 2500".to_string()
             ]))
         );
         assert_eq!(result.confidence, "low".to_string());
-        assert_eq!(result.evidence, Some("flowcell id".to_string()));
-        assert_eq!(result.comment, None);
+        assert_eq!(result.evidence.basis, Some("flowcell id".to_string()));
+        assert_eq!(result.evidence.comment, None);
     }
 
     #[test]
     fn test_derive_instrument_conflicting_flowcell_and_instrument_evidence() {
         let detected_iids = HashSet::from(["K00000".to_string()]);
         let detected_fcids = HashSet::from(["H00000RXX".to_string()]);
-        let result = predict(detected_iids, detected_fcids);
+        let result = predict(detected_iids, detected_fcids, false);
 
         assert!(!result.succeeded);
         assert_eq!(result.instruments, None);
         assert_eq!(result.confidence, "high".to_string());
         assert_eq!(
-            result.evidence,
+            result.evidence.basis,
             Some("instrument and flowcell id".to_string())
         );
-        assert_eq!(result.comment, Some("Case needs triaging, results from instrument id and flowcell id are mutually exclusive.".to_string()));
+        assert_eq!(result.evidence.comment, Some("Case needs triaging, results from instrument id and flowcell id are mutually exclusive.".to_string()));
+
+        let conflict = result.conflict.unwrap();
+        assert_eq!(
+            conflict.instruments_by_instrument_id,
+            vec!["HiSeq 3000", "HiSeq 4000"]
+        );
+        assert_eq!(conflict.instruments_by_flowcell_id, vec!["NovaSeq"]);
+    }
+
+    #[test]
+    fn test_derive_instrument_conflicting_evidence_with_union() {
+        let detected_iids = HashSet::from(["K00000".to_string()]);
+        let detected_fcids = HashSet::from(["H00000RXX".to_string()]);
+        let result = predict(detected_iids, detected_fcids, true);
+
+        assert!(result.succeeded);
+        assert_eq!(
+            result.instruments,
+            Some(BTreeSet::from([
+                "HiSeq 3000".to_string(),
+                "HiSeq 4000".to_string(),
+                "NovaSeq".to_string()
+            ]))
+        );
+        assert_eq!(result.confidence, "low".to_string());
+        assert!(result.conflict.is_some());
+    }
+
+    #[test]
+    fn test_read_group_candidates() {
+        let read_groups = HashMap::from([
+            (
+                Some("rg2".to_string()),
+                ReadGroupIds {
+                    instrument_names: HashSet::from(["K00000".to_string()]),
+                    flowcell_names: HashSet::new(),
+                },
+            ),
+            (
+                Some("rg1".to_string()),
+                ReadGroupIds {
+                    instrument_names: HashSet::from(["A00000".to_string()]),
+                    flowcell_names: HashSet::from(["H00000RXX".to_string()]),
+                },
+            ),
+        ]);
+
+        let candidates = read_group_candidates(&read_groups);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].read_group, Some("rg1".to_string()));
+        assert_eq!(candidates[0].instruments_by_flowcell_id, vec!["NovaSeq"]);
+        assert_eq!(
+            candidates[1].instruments_by_instrument_id,
+            vec!["HiSeq 3000", "HiSeq 4000"]
+        );
+        assert!(candidates[1].instruments_by_flowcell_id.is_empty());
+    }
+
+    #[test]
+    fn test_derive_chemistry_from_flowcell_ids() {
+        let detected_iids = HashSet::from(["A00000".to_string()]);
+        let detected_fcids = HashSet::from(["H0000DSXX".to_string(), "H1111DSXX".to_string()]);
+        let result = predict(detected_iids, detected_fcids, false);
+
+        let chemistry = result.chemistry.unwrap();
+        assert_eq!(chemistry.flowcell_type, "S4");
+        assert!(chemistry.patterned);
+        assert_eq!(chemistry.run_date_range.earliest_year, 2017);
+
+        // Flowcells with different chemistries do not produce a prediction.
+        let detected_fcids = HashSet::from(["H0000DSXX".to_string(), "H0000ADXX".to_string()]);
+        let chemistries = chemistry::build_flowcell_chemistry_lookup_table();
+        assert_eq!(predict_chemistry(&detected_fcids, &chemistries), None);
+    }
+
+    #[test]
+    fn test_derive_benchtop_instruments() {
+        let cases = [
+            ("FS10000123", "BPL20314-1824", vec!["iSeq 100"], "i1"),
+            ("FS10000456", "FFA12345-0001", vec!["iSeq 100"], "i1"),
+            (
+                "MN00123",
+                "000H5CY5J",
+                vec!["MiniSeq"],
+                "High or Mid Output",
+            ),
+            (
+                "VL00123",
+                "AAAHHMYM5",
+                vec!["NextSeq 1000"],
+                "P1, P2, or P3",
+            ),
+            (
+                "VH00123",
+                "AACCKW2M5",
+                vec!["NextSeq 2000"],
+                "P1, P2, or P3",
+            ),
+        ];
+
+        for (iid, fcid, instruments, flowcell_type) in cases {
+            let detected_iids = HashSet::from([iid.to_string()]);
+            let detected_fcids = HashSet::from([fcid.to_string()]);
+            let result = predict(detected_iids, detected_fcids, false);
+
+            assert!(result.succeeded, "{} {}", iid, fcid);
+            assert_eq!(result.confidence, "high".to_string());
+            assert_eq!(
+                result.instruments,
+                Some(instruments.iter().map(|i| i.to_string()).collect())
+            );
+            assert_eq!(result.evidence.flowcell_ids[0].matched_patterns.len(), 1);
+            assert_eq!(result.chemistry.unwrap().flowcell_type, flowcell_type);
+        }
+
+        // The flowcell ids alone cannot tell the NextSeq 1000 and 2000 apart.
+        let flowcells = flowcells::build_flowcell_lookup_table();
+        let result = possible_instruments_for_query(String::from("AAAHHMYM5"), &flowcells);
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_calibrate_confidence() {
+        let detected_iids = HashSet::from(["A00000".to_string()]);
+        let detected_fcids = HashSet::from(["H00000RXX".to_string()]);
+
+        let mut result = predict(detected_iids.clone(), detected_fcids.clone(), false);
+        calibrate_confidence(&mut result, 50_000_000);
+        assert_eq!(result.confidence, "high".to_string());
+        assert_eq!(result.evidence.records_scanned, 50_000_000);
+        assert_eq!(result.evidence.distinct_queries, 2);
+        assert_eq!(result.evidence.comment, None);
+
+        let mut result = predict(detected_iids, detected_fcids.clone(), false);
+        calibrate_confidence(&mut result, 500);
+        assert_eq!(result.confidence, "medium".to_string());
+        assert_eq!(
+            result.evidence.comment,
+            Some(
+                "confidence was lowered from high because only 500 records were scanned"
+                    .to_string()
+            )
+        );
+
+        // A single flowcell id from a handful of records is weak evidence.
+        let mut result = predict(HashSet::new(), detected_fcids.clone(), false);
+        assert_eq!(result.confidence, "medium".to_string());
+        calibrate_confidence(&mut result, 500);
+        assert_eq!(result.confidence, "low".to_string());
+        assert_eq!(result.evidence.distinct_queries, 1);
+
+        let mut result = predict(HashSet::new(), detected_fcids, false);
+        calibrate_confidence(&mut result, 50_000_000);
+        assert_eq!(result.confidence, "medium".to_string());
+
+        // Failed predictions are left as they are.
+        let mut result = predict(HashSet::new(), HashSet::new(), false);
+        calibrate_confidence(&mut result, 500);
+        assert_eq!(result.confidence, "unknown".to_string());
     }
 
     #[test]
     fn test_derive_instrument_no_matches() {
         let detected_iids = HashSet::from(["QQQQQ".to_string()]);
         let detected_fcids = HashSet::from(["ZZZZZZ".to_string()]);
-        let result = predict(detected_iids, detected_fcids);
+        let result = predict(detected_iids, detected_fcids, false);
 
         assert!(!result.succeeded);
         assert_eq!(result.instruments, None);
         assert_eq!(result.confidence, "unknown".to_string());
-        assert_eq!(result.evidence, None);
+        assert_eq!(result.evidence.basis, None);
         assert_eq!(
-            result.comment,
+            result.evidence.comment,
             Some("no matching instruments were found".to_string())
         );
     }
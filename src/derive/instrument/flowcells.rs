@@ -71,6 +71,17 @@ pub fn build_flowcell_lookup_table() -> HashMap<&'static str, HashSet<&'static s
             "^H[A-Z0-9]{4}AFXX$",
             HashSet::from(["NextSeq"]),
         ),
+        (
+            // High and mid output flow cells
+            "^000H[A-Z0-9]{5}$",
+            HashSet::from(["MiniSeq"]),
+        ),
+        (
+            // P1, P2, and P3 flow cells (P3 flow cells are only supported on
+            // the NextSeq 2000)
+            "^AA[A-Z0-9]{5}M5$",
+            HashSet::from(["NextSeq 1000", "NextSeq 2000"]),
+        ),
         (
             // S1 flow cell
             "^H[A-Z0-9]{5}RXX$",
@@ -91,6 +102,21 @@ pub fn build_flowcell_lookup_table() -> HashMap<&'static str, HashSet<&'static s
             "^H[A-Z0-9]{5}SXX$",
             HashSet::from(["NovaSeq"]),
         ),
+        (
+            // NovaSeq X 1.5B flow cell
+            "^2[A-Z0-9]{5}LT1$",
+            HashSet::from(["NovaSeq X", "NovaSeq X Plus"]),
+        ),
+        (
+            // NovaSeq X 10B flow cell
+            "^2[A-Z0-9]{5}LT3$",
+            HashSet::from(["NovaSeq X", "NovaSeq X Plus"]),
+        ),
+        (
+            // NovaSeq X 25B flow cell
+            "^2[A-Z0-9]{5}LT4$",
+            HashSet::from(["NovaSeq X Plus"]),
+        ),
         (
             // MiSeq flow cell
             "^A[A-Z0-9]{4}$",
@@ -116,5 +142,15 @@ pub fn build_flowcell_lookup_table() -> HashMap<&'static str, HashSet<&'static s
             "^G[A-Z0-9]{4}$",
             HashSet::from(["MiSeq"]),
         ),
+        (
+            // iSeq 100 i1 cartridge flow cell
+            "^BPL[0-9]{5}-[0-9]{4}$",
+            HashSet::from(["iSeq 100"]),
+        ),
+        (
+            // iSeq 100 i1 cartridge flow cell
+            "^FFA[0-9]{5}-[0-9]{4}$",
+            HashSet::from(["iSeq 100"]),
+        ),
     ])
 }
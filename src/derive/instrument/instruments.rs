@@ -20,6 +20,10 @@ pub fn build_instrument_lookup_table() -> HashMap<&'static str, HashSet<&'static
             HashSet::from(["HiSeq 2000", "HiSeq 2500"]),
         ),
         ("^A[0-9]{5}$", HashSet::from(["NovaSeq"])),
+        (
+            "^LH[0-9]{5}$",
+            HashSet::from(["NovaSeq X", "NovaSeq X Plus"]),
+        ),
         ("^D[0-9]{5}$", HashSet::from(["HiSeq 2500"])),
         ("^J[0-9]{5}$", HashSet::from(["HiSeq 3000"])),
         ("^K[0-9]{5}$", HashSet::from(["HiSeq 3000", "HiSeq 4000"])),
@@ -28,5 +32,8 @@ pub fn build_instrument_lookup_table() -> HashMap<&'static str, HashSet<&'static
         ("^NB[0-9]{6}$", HashSet::from(["NextSeq"])),
         ("^NS[0-9]{6}$", HashSet::from(["NextSeq"])),
         ("^MN[0-9]{5}$", HashSet::from(["MiniSeq"])),
+        ("^FS[0-9]{8}$", HashSet::from(["iSeq 100"])),
+        ("^VL[0-9]{5}$", HashSet::from(["NextSeq 1000"])),
+        ("^VH[0-9]{5}$", HashSet::from(["NextSeq 2000"])),
     ])
 }
@@ -0,0 +1,76 @@
+//! Functionality related to parsing the platform unit (`PU`) of read groups.
+//!
+//! The platform unit conventionally encodes where the reads in a read group
+//! were sequenced as `FLOWCELL.LANE[.BARCODE]`. Unlike read names, which are
+//! often rewritten by upstream tools (e.g., SRA), the read groups in the header
+//! tend to survive, so the flowcell id within the platform unit is a useful
+//! source of evidence in its own right.
+
+use noodles::sam;
+
+/// Parses the flowcell id from a platform unit of the form
+/// `FLOWCELL.LANE[.BARCODE]`. `None` is returned if the platform unit does not
+/// follow this convention.
+pub fn flowcell_from_platform_unit(platform_unit: &str) -> Option<String> {
+    let mut segments = platform_unit.split('.');
+    let flowcell = segments.next()?;
+    let lane = segments.next()?;
+
+    if segments.count() > 1 {
+        return None;
+    }
+
+    let valid_flowcell =
+        !flowcell.is_empty() && flowcell.chars().all(|c| c.is_ascii_alphanumeric());
+    let valid_lane = !lane.is_empty() && lane.chars().all(|c| c.is_ascii_digit());
+
+    (valid_flowcell && valid_lane).then(|| flowcell.to_string())
+}
+
+/// Collects the flowcell id from the platform unit of each read group in the
+/// header, keyed by the id of the read group. Read groups without a platform
+/// unit (or with one that does not follow the convention) are skipped.
+pub fn flowcells_from_header(header: &sam::Header) -> Vec<(String, String)> {
+    header
+        .read_groups()
+        .iter()
+        .filter_map(|(id, read_group)| {
+            read_group
+                .platform_unit()
+                .and_then(flowcell_from_platform_unit)
+                .map(|flowcell| (id.to_string(), flowcell))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_parses_the_flowcell_from_platform_units() -> anyhow::Result<()> {
+        assert_eq!(
+            flowcell_from_platform_unit("HNGSSYSXX.1.ACGTACGT"),
+            Some(String::from("HNGSSYSXX"))
+        );
+        assert_eq!(
+            flowcell_from_platform_unit("C1234ACXX.8"),
+            Some(String::from("C1234ACXX"))
+        );
+        assert_eq!(flowcell_from_platform_unit("HNGSSYSXX"), None);
+        assert_eq!(flowcell_from_platform_unit("HNGSSYSXX.L001"), None);
+        assert_eq!(flowcell_from_platform_unit("unit-1.1"), None);
+        assert_eq!(flowcell_from_platform_unit("A.1.B.C"), None);
+
+        let header: sam::Header = "@RG\tID:rg0\tPU:HNGSSYSXX.2.ACGT\n\
+            @RG\tID:rg1\tPU:unit1\n\
+            @RG\tID:rg2\n"
+            .parse()?;
+        assert_eq!(
+            flowcells_from_header(&header),
+            vec![(String::from("rg0"), String::from("HNGSSYSXX"))]
+        );
+
+        Ok(())
+    }
+}
@@ -65,6 +65,8 @@ impl FromStr for IlluminaReadName {
 
 #[cfg(test)]
 mod tests {
+    use crate::utils::arbitrary::{self, check};
+
     use super::*;
 
     #[test]
@@ -102,4 +104,45 @@ mod tests {
         let result = "MACHINE:0:".parse::<IlluminaReadName>();
         assert!(result.is_err());
     }
+
+    #[test]
+    pub fn it_parses_generated_illumina_read_names() {
+        check(0, arbitrary::illumina_1_4_name, |name| {
+            let read_name = name.parse::<IlluminaReadName>()?;
+            match name.starts_with(&format!(
+                "{}:{}:",
+                read_name.instrument_name, read_name.lane
+            )) {
+                true if read_name.run.is_none() => Ok(()),
+                _ => Err(format!("parsed as {:?}", read_name)),
+            }
+        });
+
+        // Read names within BAM files do not include the comment.
+        let without_comment = |rng: &mut _| {
+            let name = arbitrary::illumina_1_8_name(rng);
+            name.split(' ').next().unwrap().to_string()
+        };
+        check(1, without_comment, |name| {
+            let read_name = name.parse::<IlluminaReadName>()?;
+            let segments: Vec<&str> = name.split(':').collect();
+            match (read_name.run.as_deref(), read_name.flowcell.as_deref()) {
+                (Some(run), Some(flowcell)) if run == segments[1] && flowcell == segments[2] => {
+                    Ok(())
+                }
+                _ => Err(format!("parsed as {:?}", read_name)),
+            }
+        });
+    }
+
+    #[test]
+    pub fn it_only_parses_read_names_with_five_or_seven_segments() {
+        check(2, arbitrary::malformed_name, |name| {
+            let segments = name.split(':').count();
+            match (name.parse::<IlluminaReadName>(), segments) {
+                (Ok(_), 5 | 7) | (Err(_), _) => Ok(()),
+                (Ok(read_name), _) => Err(format!("parsed as {:?}", read_name)),
+            }
+        });
+    }
 }
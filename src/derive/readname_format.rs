@@ -0,0 +1,332 @@
+//! Supporting functionality for the `ngs derive readname-format` subcommand.
+//!
+//! Read names are classified into one of the following conventions, where
+//! `[]` denotes optional sections of the name.
+//!
+//! * Illumina 1.4: `INSTRUMENT:LANE:TILE:X:Y[#INDEX][/READ]`
+//! * Illumina 1.8: `INSTRUMENT:RUN:FLOWCELL:LANE:TILE:X:Y`
+//! * Illumina 1.8 with a comment: `INSTRUMENT:RUN:FLOWCELL:LANE:TILE:X:Y READ:FILTERED:CONTROL[:INDEX]`
+//! * SRA: `SRR000000.1[.1][ ORIGINAL NAME]` (or with an `ERR` or `DRR` prefix)
+//! * PacBio: `MOVIE/HOLE[/ccs|/START_END]`
+//! * ONT: a UUID (`xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`)
+//!
+//! Anything else is considered to be a custom read name.
+
+use std::collections::BTreeMap;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::utils::{checksum::InputChecksum, provenance::Provenance};
+
+/// A read-naming convention.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum ReadNameFormat {
+    /// Illumina 1.4 read names.
+    #[serde(rename = "Illumina 1.4")]
+    Illumina14,
+
+    /// Illumina 1.8 read names without a comment.
+    #[serde(rename = "Illumina 1.8")]
+    Illumina18,
+
+    /// Illumina 1.8 read names with a comment.
+    #[serde(rename = "Illumina 1.8 with comment")]
+    Illumina18WithComment,
+
+    /// Read names assigned by the Sequence Read Archive.
+    #[serde(rename = "SRA")]
+    Sra,
+
+    /// PacBio read names.
+    #[serde(rename = "PacBio")]
+    PacBio,
+
+    /// Oxford Nanopore read names (UUIDs).
+    #[serde(rename = "ONT UUID")]
+    OntUuid,
+
+    /// Read names that do not follow any known convention.
+    #[serde(rename = "custom")]
+    Custom,
+}
+
+impl ReadNameFormat {
+    /// Whether the `ngs derive instrument` subcommand can derive the
+    /// instrument from read names with this format.
+    pub fn supports_instrument_derivation(&self) -> bool {
+        matches!(
+            self,
+            ReadNameFormat::Illumina14
+                | ReadNameFormat::Illumina18
+                | ReadNameFormat::Illumina18WithComment
+        )
+    }
+}
+
+/// Classifies read names into a [`ReadNameFormat`].
+pub struct ReadNameClassifier {
+    /// The pattern for Illumina 1.4 read names.
+    illumina_1_4: Regex,
+
+    /// The pattern for Illumina 1.8 read names.
+    illumina_1_8: Regex,
+
+    /// The pattern for the comment of Illumina 1.8 read names.
+    illumina_1_8_comment: Regex,
+
+    /// The pattern for SRA read names.
+    sra: Regex,
+
+    /// The pattern for PacBio read names.
+    pacbio: Regex,
+
+    /// The pattern for ONT read names.
+    ont: Regex,
+}
+
+impl Default for ReadNameClassifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReadNameClassifier {
+    /// Creates a new [`ReadNameClassifier`].
+    pub fn new() -> Self {
+        Self {
+            illumina_1_4: Regex::new(r"^[^:\s]+:\d+:\d+:\d+:\d+(#[^/:\s]*)?(/[12])?$").unwrap(),
+            illumina_1_8: Regex::new(r"^[^:\s]+:\d+:[^:\s]+:\d+:\d+:\d+:\d+$").unwrap(),
+            illumina_1_8_comment: Regex::new(r"^[12]:[YN]:\d+(:\S*)?$").unwrap(),
+            sra: Regex::new(r"^[SED]RR\d+\.\d+(\.\d+)?$").unwrap(),
+            pacbio: Regex::new(r"^m[0-9A-Za-z_]+/\d+(/(ccs|\d+_\d+))?$").unwrap(),
+            ont: Regex::new(r"^[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$")
+                .unwrap(),
+        }
+    }
+
+    /// Classifies a single read name. Anything following the first whitespace
+    /// character is treated as a comment.
+    pub fn classify(&self, name: &str) -> ReadNameFormat {
+        let (name, comment) = match name.split_once(char::is_whitespace) {
+            Some((name, comment)) => (name, Some(comment.trim())),
+            None => (name, None),
+        };
+
+        if self.illumina_1_8.is_match(name) {
+            return match comment {
+                Some(c) if self.illumina_1_8_comment.is_match(c) => {
+                    ReadNameFormat::Illumina18WithComment
+                }
+                _ => ReadNameFormat::Illumina18,
+            };
+        }
+
+        if self.illumina_1_4.is_match(name) {
+            ReadNameFormat::Illumina14
+        } else if self.sra.is_match(name) {
+            ReadNameFormat::Sra
+        } else if self.pacbio.is_match(name) {
+            ReadNameFormat::PacBio
+        } else if self.ont.is_match(name) {
+            ReadNameFormat::OntUuid
+        } else {
+            ReadNameFormat::Custom
+        }
+    }
+}
+
+/// The number of read names with a given format along with an example.
+#[derive(Clone, Debug, Serialize)]
+pub struct FormatCount {
+    /// The number of read names with this format.
+    pub count: usize,
+
+    /// The first read name encountered with this format.
+    pub example: String,
+}
+
+/// Struct holding the final results for an `ngs derive readname-format`
+/// subcommand call.
+#[derive(Debug, Default, Serialize)]
+pub struct DerivedReadNameFormatResult {
+    /// The format of the majority of the read names, if any were examined.
+    pub predominant_format: Option<ReadNameFormat>,
+
+    /// Whether every read name examined can be used by `ngs derive instrument`.
+    pub supports_instrument_derivation: bool,
+
+    /// The counts for each format that was encountered.
+    pub formats: BTreeMap<ReadNameFormat, FormatCount>,
+
+    /// The number of records without a read name.
+    pub records_without_read_name: usize,
+
+    /// The number of records that were examined to produce this result.
+    pub records_processed: usize,
+
+    /// Whether the records were no longer examined once the prediction was
+    /// stable (with `--early-stop`), in which case `records_processed` is the
+    /// number of records that were needed.
+    pub stopped_early: bool,
+
+    /// The checksum of the file (only computed with `--checksums`).
+    pub input_checksum: Option<InputChecksum>,
+
+    /// The invocation of `ngs` that produced the results.
+    pub provenance: Option<Provenance>,
+}
+
+impl DerivedReadNameFormatResult {
+    /// Tallies a single read name.
+    pub fn update(&mut self, format: ReadNameFormat, name: &str) {
+        self.formats
+            .entry(format)
+            .or_insert_with(|| FormatCount {
+                count: 0,
+                example: name.to_string(),
+            })
+            .count += 1;
+    }
+
+    /// Gets the format of the majority of the read names tallied so far.
+    pub fn predominant(&self) -> Option<ReadNameFormat> {
+        self.formats
+            .iter()
+            .max_by(|(a, x), (b, y)| x.count.cmp(&y.count).then(b.cmp(a)))
+            .map(|(format, _)| *format)
+    }
+
+    /// Computes the predominant format and whether instrument derivation is
+    /// supported from the tallied formats.
+    pub fn summarize(&mut self) {
+        self.predominant_format = self.predominant();
+
+        self.supports_instrument_derivation = !self.formats.is_empty()
+            && self
+                .formats
+                .keys()
+                .all(|format| format.supports_instrument_derivation());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        derive::instrument::reads::IlluminaReadName,
+        utils::arbitrary::{self, check},
+    };
+
+    use super::*;
+
+    #[test]
+    pub fn it_classifies_read_names() {
+        let classifier = ReadNameClassifier::new();
+
+        let cases = [
+            (
+                "HWUSI-EAS100R:6:73:941:1973#0/1",
+                ReadNameFormat::Illumina14,
+            ),
+            ("MACHINE:0:1234:55555:66666", ReadNameFormat::Illumina14),
+            (
+                "A00000:1:H00000RXX:1:1101:1000:1000",
+                ReadNameFormat::Illumina18,
+            ),
+            (
+                "A00000:1:H00000RXX:1:1101:1000:1000 1:N:0:ATCACG",
+                ReadNameFormat::Illumina18WithComment,
+            ),
+            ("SRR000001.1", ReadNameFormat::Sra),
+            ("ERR000001.12.2 original-name", ReadNameFormat::Sra),
+            ("m64011_190830_220126/101/ccs", ReadNameFormat::PacBio),
+            (
+                "m54006_160504_020705/4194370/0_1000",
+                ReadNameFormat::PacBio,
+            ),
+            (
+                "0b9bd4a4-5e4b-4e9b-8c9a-0c0e7e0e2f11",
+                ReadNameFormat::OntUuid,
+            ),
+            ("read_1", ReadNameFormat::Custom),
+        ];
+
+        for (name, format) in cases {
+            assert_eq!(classifier.classify(name), format, "{}", name);
+        }
+    }
+
+    #[test]
+    pub fn it_summarizes_the_formats() {
+        let mut result = DerivedReadNameFormatResult::default();
+        result.update(ReadNameFormat::Illumina18, "a");
+        result.update(ReadNameFormat::Illumina18, "b");
+        result.update(ReadNameFormat::Illumina14, "c");
+        result.summarize();
+
+        assert_eq!(result.predominant_format, Some(ReadNameFormat::Illumina18));
+        assert!(result.supports_instrument_derivation);
+        assert_eq!(result.formats[&ReadNameFormat::Illumina18].example, "a");
+
+        result.update(ReadNameFormat::Custom, "d");
+        result.summarize();
+        assert!(!result.supports_instrument_derivation);
+    }
+
+    #[test]
+    pub fn it_classifies_generated_read_names() {
+        let classifier = ReadNameClassifier::new();
+        let classified_as = |formats: &'static [ReadNameFormat]| {
+            let classifier = &classifier;
+            move |name: &String| {
+                let format = classifier.classify(name);
+                match formats.contains(&format) {
+                    true => Ok(()),
+                    false => Err(format!("classified as {:?}", format)),
+                }
+            }
+        };
+
+        check(
+            0,
+            arbitrary::illumina_1_4_name,
+            classified_as(&[ReadNameFormat::Illumina14]),
+        );
+        check(
+            1,
+            arbitrary::illumina_1_8_name,
+            classified_as(&[
+                ReadNameFormat::Illumina18,
+                ReadNameFormat::Illumina18WithComment,
+            ]),
+        );
+        check(
+            2,
+            arbitrary::pacbio_name,
+            classified_as(&[ReadNameFormat::PacBio]),
+        );
+        check(
+            3,
+            arbitrary::ont_name,
+            classified_as(&[ReadNameFormat::OntUuid]),
+        );
+    }
+
+    #[test]
+    pub fn it_only_supports_instrument_derivation_for_parseable_read_names() {
+        let classifier = ReadNameClassifier::new();
+
+        check(4, arbitrary::malformed_name, |name| {
+            let format = classifier.classify(name);
+            let name = name.split(char::is_whitespace).next().unwrap();
+
+            match format.supports_instrument_derivation() {
+                true => name
+                    .parse::<IlluminaReadName>()
+                    .map(|_| ())
+                    .map_err(|e| format!("classified as {:?}, but {}", format, e)),
+                false => Ok(()),
+            }
+        });
+    }
+}
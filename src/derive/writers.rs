@@ -0,0 +1,231 @@
+//! Functionality related to writing the results of `ngs derive` subcommands.
+//!
+//! Every `ngs derive` subcommand writes a single result (to stdout, unless
+//! `--output` is provided). The format of that result is selected with
+//! `--format` and is shared by all of the subcommands, which only need to
+//! hand their (serializable) result to [`write_result`]. Supporting a new
+//! format is a matter of implementing [`ResultWriter`] and adding it to
+//! [`OutputFormat`].
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::utils::outputs;
+
+//===============//
+// Output format //
+//===============//
+
+/// The formats that the result of an `ngs derive` subcommand can be written
+/// in.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// A pretty-printed JSON document.
+    #[default]
+    Json,
+
+    /// A single line of compact JSON, tagged with the subcommand, so that the
+    /// results of several subcommands can be concatenated into a JSON lines
+    /// file.
+    JsonLines,
+
+    /// One tab-delimited key and value per line, where the keys are the dotted
+    /// paths to each value within the result.
+    Tsv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "jsonl" => Ok(Self::JsonLines),
+            "tsv" => Ok(Self::Tsv),
+            _ => bail!("unknown output format: {}", s),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// The writer for this format.
+    pub fn writer(&self) -> Box<dyn ResultWriter> {
+        match self {
+            Self::Json => Box::new(PrettyJson),
+            Self::JsonLines => Box::new(JsonLines),
+            Self::Tsv => Box::new(KeyValueTsv),
+        }
+    }
+}
+
+//=========//
+// Writers //
+//=========//
+
+/// Writes the result of an `ngs derive` subcommand.
+pub trait ResultWriter {
+    /// Writes the result (already converted to JSON) of the named subcommand.
+    fn write(&self, subcommand: &str, result: &Value, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Writes the result as a pretty-printed JSON document.
+pub struct PrettyJson;
+
+impl ResultWriter for PrettyJson {
+    fn write(&self, _: &str, result: &Value, out: &mut dyn Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *out, result)?;
+        Ok(())
+    }
+}
+
+/// Writes the result as a single line of compact JSON with the name of the
+/// subcommand as its first key.
+pub struct JsonLines;
+
+impl ResultWriter for JsonLines {
+    fn write(&self, subcommand: &str, result: &Value, out: &mut dyn Write) -> io::Result<()> {
+        let mut object = Map::new();
+        object.insert(
+            String::from("subcommand"),
+            Value::String(subcommand.to_string()),
+        );
+
+        match result {
+            Value::Object(fields) => object.extend(fields.clone()),
+            other => {
+                object.insert(String::from("result"), other.clone());
+            }
+        }
+
+        serde_json::to_writer(&mut *out, &Value::Object(object))?;
+        writeln!(out)
+    }
+}
+
+/// Writes each value of the result on its own line as a tab-delimited key and
+/// value. Nested keys are joined with `.` (with array elements keyed by their
+/// index) and missing values are left empty.
+pub struct KeyValueTsv;
+
+impl ResultWriter for KeyValueTsv {
+    fn write(&self, subcommand: &str, result: &Value, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "subcommand\t{}", subcommand)?;
+
+        let mut rows = Vec::new();
+        flatten(String::new(), result, &mut rows);
+        for (key, value) in rows {
+            writeln!(out, "{}\t{}", key, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Flattens a JSON value into dotted keys and their (scalar) values.
+fn flatten(prefix: String, value: &Value, rows: &mut Vec<(String, String)>) {
+    let key = |name: &str| match prefix.is_empty() {
+        true => name.to_string(),
+        false => format!("{}.{}", prefix, name),
+    };
+
+    match value {
+        Value::Object(fields) => {
+            for (name, value) in fields {
+                flatten(key(name), value, rows);
+            }
+        }
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                flatten(key(&i.to_string()), value, rows);
+            }
+        }
+        Value::Null => rows.push((prefix, String::new())),
+        Value::String(s) => rows.push((prefix, s.replace(['\t', '\n'], " "))),
+        other => rows.push((prefix, other.to_string())),
+    }
+}
+
+/// Writes the result of the named `ngs derive` subcommand in the provided
+/// format, either to the provided path or (if none is provided) to stdout.
+pub fn write_result<T: Serialize>(
+    format: OutputFormat,
+    subcommand: &str,
+    result: &T,
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let result = serde_json::to_value(result)?;
+    let writer = format.writer();
+
+    match output {
+        Some(path) => {
+            let mut out = BufWriter::new(
+                File::create(path)
+                    .with_context(|| format!("Could not create file: {}", path.display()))?,
+            );
+            writer.write(subcommand, &result, &mut out)?;
+            out.flush()?;
+            outputs::record(path, "derive");
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            writer.write(subcommand, &result, &mut out)?;
+            out.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    pub fn it_writes_results_in_each_format() -> anyhow::Result<()> {
+        let result = json!({
+            "succeeded": true,
+            "instruments": ["NovaSeq"],
+            "evidence": { "basis": "flowcell id", "comment": null },
+        });
+        let written = |format: &str| -> anyhow::Result<String> {
+            let mut out = Vec::new();
+            format
+                .parse::<OutputFormat>()?
+                .writer()
+                .write("instrument", &result, &mut out)?;
+            Ok(String::from_utf8(out)?)
+        };
+
+        let pretty: Value = serde_json::from_str(&written("json")?)?;
+        assert_eq!(pretty, result);
+
+        assert_eq!(
+            written("jsonl")?,
+            "{\"subcommand\":\"instrument\",\"succeeded\":true,\"instruments\":[\"NovaSeq\"],\
+            \"evidence\":{\"basis\":\"flowcell id\",\"comment\":null}}\n"
+        );
+
+        assert_eq!(
+            written("tsv")?,
+            "subcommand\tinstrument\n\
+            succeeded\ttrue\n\
+            instruments.0\tNovaSeq\n\
+            evidence.basis\tflowcell id\n\
+            evidence.comment\t\n"
+        );
+
+        assert!("xml".parse::<OutputFormat>().is_err());
+        Ok(())
+    }
+}
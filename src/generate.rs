@@ -2,4 +2,5 @@
 
 pub mod command;
 pub mod providers;
+pub mod synthetic;
 pub mod utils;
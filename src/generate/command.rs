@@ -3,13 +3,16 @@
 use std::path::PathBuf;
 
 use anyhow::Context;
-use clap::{ArgGroup, Args};
+use clap::{builder::PossibleValuesParser, ArgGroup, Args, Subcommand};
 use indicatif::{ProgressBar, ProgressStyle};
 use rand::prelude::*;
 use tracing::info;
 
 use crate::{
-    generate::providers::{reference_provider::ReferenceGenomeSequenceProvider, SequenceProvider},
+    generate::{
+        providers::{reference_provider::ReferenceGenomeSequenceProvider, SequenceProvider},
+        synthetic::{SyntheticDataSet, SyntheticOptions},
+    },
     utils::formats,
 };
 
@@ -29,12 +32,19 @@ pub fn error_rate_in_range(error_rate_raw: &str) -> Result<f32, String> {
 /// Command line arguments for `ngs generate`.
 #[derive(Args)]
 #[command(group(ArgGroup::new("record-count").required(true).args(["coverage", "num_records"])))]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct GenerateArgs {
+    /// Generates a synthetic data set instead (see `ngs generate synthetic --help`).
+    #[command(subcommand)]
+    pub subcommand: Option<GenerateSubcommand>,
+
     /// Destination for the FASTQ file containing all read ones.
-    read_ones_file: PathBuf,
+    #[arg(required = true)]
+    read_ones_file: Option<PathBuf>,
 
     /// Destination for the FASTQ file containing all read twos.
-    read_twos_file: PathBuf,
+    #[arg(required = true)]
+    read_twos_file: Option<PathBuf>,
 
     /// One or more reference FASTAs to generate the data based off of.
     #[arg(required = true)] // required implies one or more
@@ -54,8 +64,148 @@ pub struct GenerateArgs {
     coverage: Option<usize>,
 }
 
+/// Subcommands for `ngs generate`.
+#[derive(Subcommand)]
+pub enum GenerateSubcommand {
+    /// Generates a small synthetic data set (BAM and/or FASTQ files) from a
+    /// random reference genome, without needing any real data.
+    Synthetic(SyntheticArgs),
+}
+
+/// Command line arguments for `ngs generate synthetic`.
+#[derive(Args)]
+#[command(group(ArgGroup::new("outputs").required(true).multiple(true).args(["bam", "fastq"])))]
+pub struct SyntheticArgs {
+    /// Destination for a coordinate-sorted BAM file of the aligned records.
+    #[arg(long, value_name = "PATH")]
+    bam: Option<PathBuf>,
+
+    /// Destinations for the FASTQ files containing all read ones and all read
+    /// twos (optionally gzipped).
+    #[arg(long, value_names = ["READ_ONES", "READ_TWOS"], num_args = 2)]
+    fastq: Option<Vec<PathBuf>>,
+
+    /// Destination for a FASTA file of the random reference genome.
+    #[arg(long, value_name = "PATH")]
+    fasta: Option<PathBuf>,
+
+    /// The number of sequences (named `chr1`, `chr2`, ...) in the reference genome.
+    #[arg(long, value_name = "USIZE", default_value_t = 2)]
+    sequences: usize,
+
+    /// The length of each sequence in the reference genome.
+    #[arg(long, value_name = "USIZE", default_value_t = 100_000)]
+    sequence_length: usize,
+
+    /// The length of each read.
+    #[arg(short, long, value_name = "USIZE", default_value_t = 150)]
+    read_length: usize,
+
+    /// The read-name convention of the reads.
+    #[arg(long, value_name = "PATTERN", default_value = "illumina-1.8")]
+    #[arg(value_parser = PossibleValuesParser::new(["illumina-1.8", "illumina-1.4", "sra"]))]
+    read_names: String,
+
+    /// The instrument name used in Illumina read names.
+    #[arg(long, value_name = "NAME", default_value = "A00001")]
+    instrument: String,
+
+    /// The flowcell ID used in Illumina 1.8 read names.
+    #[arg(long, value_name = "ID", default_value = "HNGSSYSXX")]
+    flowcell: String,
+
+    /// The fraction of read pairs that are duplicates of an earlier pair.
+    #[arg(short, long, value_name = "F32", default_value = "0.0")]
+    duplication_rate: f32,
+
+    /// Flags duplicate read pairs as duplicates in the BAM file.
+    #[arg(long)]
+    mark_duplicates: bool,
+
+    /// How the read pairs are distributed along each sequence.
+    #[arg(long, value_name = "PROFILE", default_value = "uniform")]
+    #[arg(value_parser = PossibleValuesParser::new(["uniform", "gradient", "gaps"]))]
+    coverage_profile: String,
+
+    /// The error rate for the sequencer as a fraction between [0.0, 1.0] (per base).
+    #[arg(short, long, value_name = "F32", default_value = "0.001")]
+    #[arg(value_parser = error_rate_in_range)]
+    error_rate: f32,
+
+    /// Specifies the number of read pairs to generate.
+    #[arg(short, long, value_name = "USIZE", conflicts_with = "coverage")]
+    num_pairs: Option<usize>,
+
+    /// Calculates the number of read pairs needed for a particular mean
+    /// coverage (defaults to 10 if `--num-pairs` is not given).
+    #[arg(short, long, value_name = "USIZE", conflicts_with = "num_pairs")]
+    coverage: Option<usize>,
+
+    /// The seed for the random number generator. The same arguments and seed
+    /// always produce the same data set.
+    #[arg(long, value_name = "U64", default_value_t = 0)]
+    seed: u64,
+}
+
+/// Main function for the `ngs generate synthetic` subcommand.
+pub fn synthetic(args: SyntheticArgs) -> anyhow::Result<()> {
+    // (1) Collect the options for the data set.
+    let mut options = SyntheticOptions {
+        sequences: args.sequences,
+        sequence_length: args.sequence_length,
+        read_length: args.read_length,
+        read_name_pattern: args.read_names.parse()?,
+        instrument: args.instrument,
+        flowcell: args.flowcell,
+        duplication_rate: args.duplication_rate as f64,
+        mark_duplicates: args.mark_duplicates,
+        coverage_profile: args.coverage_profile.parse()?,
+        error_rate: args.error_rate as f64,
+        seed: args.seed,
+        ..Default::default()
+    };
+
+    options.pairs = match args.num_pairs {
+        Some(pairs) => pairs,
+        None => options.pairs_for_coverage(args.coverage.unwrap_or(10)),
+    };
+
+    // (2) Generate the data set.
+    info!(
+        "Generating {} read pairs across {} sequence(s)...",
+        options.pairs, options.sequences
+    );
+    let data = SyntheticDataSet::generate(options)?;
+
+    // (3) Write the requested outputs.
+    if let Some(bam) = args.bam {
+        data.write_bam(&bam)?;
+        info!("Wrote records to {}.", bam.display());
+    }
+
+    if let Some(fastqs) = args.fastq {
+        data.write_fastqs(&fastqs[0], &fastqs[1])?;
+        info!(
+            "Wrote reads to {} and {}.",
+            fastqs[0].display(),
+            fastqs[1].display()
+        );
+    }
+
+    if let Some(fasta) = args.fasta {
+        data.write_fasta(&fasta)?;
+        info!("Wrote the reference genome to {}.", fasta.display());
+    }
+
+    Ok(())
+}
+
 /// Main function for the `ngs generate` subcommand.
 pub fn generate(args: GenerateArgs) -> anyhow::Result<()> {
+    if let Some(GenerateSubcommand::Synthetic(args)) = args.subcommand {
+        return synthetic(args);
+    }
+
     // (0) Parse arguments needed for subcommand.
     let result: anyhow::Result<Vec<_>> = args
         .reference_providers
@@ -64,8 +214,9 @@ pub fn generate(args: GenerateArgs) -> anyhow::Result<()> {
         .collect();
     let reference_providers = result.with_context(|| "issue parsing reference providers")?;
 
-    let reads_one_file = args.read_ones_file;
-    let reads_two_file = args.read_twos_file;
+    // Both are required by clap unless a subcommand is given.
+    let reads_one_file = args.read_ones_file.expect("read ones file to be provided");
+    let reads_two_file = args.read_twos_file.expect("read twos file to be provided");
 
     info!("Starting generate command...");
     let mut writer_read_one = formats::fastq::writer(&reads_one_file).with_context(|| {
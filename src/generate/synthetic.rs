@@ -0,0 +1,628 @@
+//! Generation of small synthetic data sets for `ngs generate synthetic`.
+//!
+//! Unlike the reference-based generator, nothing is read from disk: a random
+//! reference genome is generated and read pairs are simulated from it with
+//! controllable properties (read length, read-name convention, duplication
+//! rate, and coverage profile). The generator is seeded, so the same options
+//! always produce the same data set, which makes it suitable for tests and for
+//! reproducing issues without sharing real data.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use noodles::{
+    bam, fastq,
+    sam::{
+        self,
+        alignment::Record,
+        record::{
+            data::field::{Field, Tag, Value},
+            Data, Flags, MappingQuality, QualityScores, ReadName, Sequence,
+        },
+    },
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+
+use crate::generate::utils;
+use crate::utils::outputs;
+
+/// The nucleobases the reference genome is made up of.
+const BASES: &[u8; 4] = b"ACGT";
+
+/// The ID of the read group all of the records are assigned to.
+const READ_GROUP_ID: &str = "synthetic";
+
+/// The number of windows each sequence is divided into for the `gaps`
+/// coverage profile.
+const GAP_WINDOWS: usize = 10;
+
+//==================//
+// Coverage profile //
+//==================//
+
+/// How the read pairs are distributed along each sequence.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CoverageProfile {
+    /// Read pairs start uniformly at random along the sequence.
+    Uniform,
+
+    /// Coverage increases linearly from the start to the end of the sequence.
+    Gradient,
+
+    /// Each sequence is divided into ten windows and every other window
+    /// (starting with the second) receives no read pairs.
+    Gaps,
+}
+
+impl FromStr for CoverageProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(Self::Uniform),
+            "gradient" => Ok(Self::Gradient),
+            "gaps" => Ok(Self::Gaps),
+            _ => bail!("unknown coverage profile: {}", s),
+        }
+    }
+}
+
+//==================//
+// Read-name format //
+//==================//
+
+/// The instrument read-name convention of the generated reads.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReadNamePattern {
+    /// Illumina 1.8 read names: `INSTRUMENT:RUN:FLOWCELL:LANE:TILE:X:Y`.
+    Illumina18,
+
+    /// Illumina 1.4 read names: `INSTRUMENT:LANE:TILE:X:Y#0`.
+    Illumina14,
+
+    /// Read names assigned by the Sequence Read Archive: `SRR000000.N`.
+    Sra,
+}
+
+impl FromStr for ReadNamePattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "illumina-1.8" => Ok(Self::Illumina18),
+            "illumina-1.4" => Ok(Self::Illumina14),
+            "sra" => Ok(Self::Sra),
+            _ => bail!("unknown read name pattern: {}", s),
+        }
+    }
+}
+
+//=========//
+// Options //
+//=========//
+
+/// Options that control the properties of a synthetic data set.
+#[derive(Clone, Debug)]
+pub struct SyntheticOptions {
+    /// The number of sequences in the reference genome.
+    pub sequences: usize,
+
+    /// The length of each sequence in the reference genome.
+    pub sequence_length: usize,
+
+    /// The number of read pairs to generate.
+    pub pairs: usize,
+
+    /// The length of each read.
+    pub read_length: usize,
+
+    /// The read-name convention.
+    pub read_name_pattern: ReadNamePattern,
+
+    /// The instrument name used in Illumina read names.
+    pub instrument: String,
+
+    /// The flowcell ID used in Illumina 1.8 read names.
+    pub flowcell: String,
+
+    /// The fraction of read pairs that are duplicates of an earlier pair.
+    pub duplication_rate: f64,
+
+    /// Whether duplicate read pairs are flagged as duplicates in the BAM file.
+    pub mark_duplicates: bool,
+
+    /// How the read pairs are distributed along each sequence.
+    pub coverage_profile: CoverageProfile,
+
+    /// The per-base sequencing error rate.
+    pub error_rate: f64,
+
+    /// The seed for the random number generator.
+    pub seed: u64,
+}
+
+impl Default for SyntheticOptions {
+    fn default() -> Self {
+        Self {
+            sequences: 2,
+            sequence_length: 100_000,
+            pairs: 10_000,
+            read_length: 150,
+            read_name_pattern: ReadNamePattern::Illumina18,
+            instrument: String::from("A00001"),
+            flowcell: String::from("HNGSSYSXX"),
+            duplication_rate: 0.0,
+            mark_duplicates: false,
+            coverage_profile: CoverageProfile::Uniform,
+            error_rate: 0.001,
+            seed: 0,
+        }
+    }
+}
+
+impl SyntheticOptions {
+    /// The number of read pairs needed for a mean coverage of `coverage`
+    /// across the reference genome.
+    pub fn pairs_for_coverage(&self, coverage: usize) -> usize {
+        let bases = coverage * self.sequences * self.sequence_length;
+        bases.div_ceil(2 * self.read_length)
+    }
+}
+
+//===========//
+// Generator //
+//===========//
+
+/// A read pair that was simulated from the reference genome.
+#[derive(Debug)]
+pub struct SimulatedPair {
+    /// The name of both reads.
+    pub name: String,
+
+    /// The index of the sequence the fragment was taken from.
+    pub sequence_id: usize,
+
+    /// The 1-based start of the read aligned to the forward strand.
+    pub forward_start: usize,
+
+    /// The 1-based start of the read aligned to the reverse strand.
+    pub reverse_start: usize,
+
+    /// Whether the first read of the pair is the one on the reverse strand.
+    pub first_is_reverse: bool,
+
+    /// Whether this pair is a duplicate of an earlier pair.
+    pub duplicate: bool,
+
+    /// The bases of the forward and reverse reads, in reference orientation.
+    pub bases: [Vec<u8>; 2],
+
+    /// The quality scores of the forward and reverse reads (Phred, not
+    /// offset), in reference orientation.
+    pub quality_scores: [Vec<u8>; 2],
+
+    /// The number of mismatches in the forward and reverse reads.
+    pub mismatches: [usize; 2],
+}
+
+/// A synthetic data set.
+#[derive(Debug)]
+pub struct SyntheticDataSet {
+    /// The options the data set was generated with.
+    pub options: SyntheticOptions,
+
+    /// The names and bases of the sequences in the reference genome.
+    pub reference: Vec<(String, Vec<u8>)>,
+
+    /// The read pairs in the order they were generated.
+    pub pairs: Vec<SimulatedPair>,
+}
+
+impl SyntheticDataSet {
+    /// Generates a synthetic data set with the provided options.
+    pub fn generate(options: SyntheticOptions) -> anyhow::Result<Self> {
+        // (1) Validate the options.
+        if options.sequences == 0 {
+            bail!("at least one sequence must be generated");
+        }
+
+        if options.read_length == 0 {
+            bail!("the read length must be greater than zero");
+        }
+
+        // Fragments are at most five read lengths long.
+        if options.sequence_length < 5 * options.read_length {
+            bail!(
+                "the sequence length ({}) must be at least five times the read length ({})",
+                options.sequence_length,
+                options.read_length
+            );
+        }
+
+        if options.coverage_profile == CoverageProfile::Gaps
+            && options.sequence_length / GAP_WINDOWS < 5 * options.read_length
+        {
+            bail!(
+                "the sequence length ({}) must be at least fifty times the read length ({}) \
+                for the gaps coverage profile",
+                options.sequence_length,
+                options.read_length
+            );
+        }
+
+        if !(0.0..=1.0).contains(&options.duplication_rate) {
+            bail!("the duplication rate must be between 0.0 and 1.0");
+        }
+
+        if !(0.0..=1.0).contains(&options.error_rate) {
+            bail!("the error rate must be between 0.0 and 1.0");
+        }
+
+        let mut rng = StdRng::seed_from_u64(options.seed);
+
+        // (2) Generate the reference genome.
+        let reference: Vec<(String, Vec<u8>)> = (0..options.sequences)
+            .map(|i| {
+                let bases = (0..options.sequence_length)
+                    .map(|_| BASES[rng.gen_range(0..4)])
+                    .collect();
+                (format!("chr{}", i + 1), bases)
+            })
+            .collect();
+
+        // (3) Simulate the read pairs. Fragment lengths are normally
+        // distributed around three read lengths.
+        let read_length = options.read_length;
+        let fragment_lengths = Normal::new(3.0 * read_length as f64, read_length as f64 / 3.0)?;
+
+        let mut pairs: Vec<SimulatedPair> = Vec::with_capacity(options.pairs);
+        for i in 0..options.pairs {
+            let name = read_name(&options, i, &mut rng);
+
+            // Duplicates are taken from the same fragment as an earlier pair,
+            // but are sequenced (and therefore have errors) independently.
+            let duplicate_of = match pairs.is_empty() {
+                false if rng.gen_bool(options.duplication_rate) => {
+                    Some(rng.gen_range(0..pairs.len()))
+                }
+                _ => None,
+            };
+
+            let (sequence_id, forward_start, reverse_start, first_is_reverse) = match duplicate_of {
+                Some(j) => (
+                    pairs[j].sequence_id,
+                    pairs[j].forward_start,
+                    pairs[j].reverse_start,
+                    pairs[j].first_is_reverse,
+                ),
+                None => {
+                    let sequence_id = rng.gen_range(0..options.sequences);
+                    let fragment_length = (fragment_lengths.sample(&mut rng).round() as usize)
+                        .clamp(read_length, 5 * read_length);
+                    let start = fragment_start(&options, fragment_length, &mut rng);
+                    let reverse_start = start + fragment_length - read_length;
+                    (sequence_id, start, reverse_start, rng.gen_bool(0.5))
+                }
+            };
+
+            let sequence = &reference[sequence_id].1;
+            let mut bases = [Vec::new(), Vec::new()];
+            let mut quality_scores = [Vec::new(), Vec::new()];
+            let mut mismatches = [0, 0];
+
+            for (k, start) in [forward_start, reverse_start].into_iter().enumerate() {
+                for &base in &sequence[start - 1..start - 1 + read_length] {
+                    if rng.gen_bool(options.error_rate) {
+                        let offset = rng.gen_range(1..4);
+                        let index = BASES.iter().position(|b| *b == base).unwrap();
+                        bases[k].push(BASES[(index + offset) % 4]);
+                        quality_scores[k].push(rng.gen_range(2..=15));
+                        mismatches[k] += 1;
+                    } else {
+                        bases[k].push(base);
+                        quality_scores[k].push(rng.gen_range(25..=40));
+                    }
+                }
+            }
+
+            pairs.push(SimulatedPair {
+                name,
+                sequence_id,
+                forward_start,
+                reverse_start,
+                first_is_reverse,
+                duplicate: duplicate_of.is_some(),
+                bases,
+                quality_scores,
+                mismatches,
+            });
+        }
+
+        Ok(Self {
+            options,
+            reference,
+            pairs,
+        })
+    }
+
+    /// The header for the BAM file of this data set.
+    pub fn header(&self) -> anyhow::Result<sam::Header> {
+        let mut header = String::from("@HD\tVN:1.6\tSO:coordinate\n");
+        for (name, bases) in &self.reference {
+            header.push_str(&format!("@SQ\tSN:{}\tLN:{}\n", name, bases.len()));
+        }
+        header.push_str(&format!(
+            "@RG\tID:{}\tSM:{}\tPL:ILLUMINA\n",
+            READ_GROUP_ID, READ_GROUP_ID
+        ));
+        header.push_str("@PG\tID:ngs\tPN:ngs\tCL:ngs generate synthetic\n");
+
+        header
+            .parse()
+            .with_context(|| "could not build the header for the synthetic data set")
+    }
+
+    /// The aligned records of this data set, sorted by position.
+    pub fn records(&self) -> anyhow::Result<Vec<Record>> {
+        let read_length = self.options.read_length;
+        let mut records = Vec::with_capacity(self.pairs.len() * 2);
+
+        for pair in &self.pairs {
+            let template_length = (pair.reverse_start + read_length - pair.forward_start) as i32;
+
+            for k in 0..2 {
+                let (reverse, start, mate_start) = match k {
+                    0 => (false, pair.forward_start, pair.reverse_start),
+                    _ => (true, pair.reverse_start, pair.forward_start),
+                };
+
+                let mut flags = Flags::SEGMENTED | Flags::PROPERLY_ALIGNED;
+                flags |= match reverse == pair.first_is_reverse {
+                    true => Flags::FIRST_SEGMENT,
+                    false => Flags::LAST_SEGMENT,
+                };
+                flags |= match reverse {
+                    true => Flags::REVERSE_COMPLEMENTED,
+                    false => Flags::MATE_REVERSE_COMPLEMENTED,
+                };
+                if pair.duplicate && self.options.mark_duplicates {
+                    flags |= Flags::DUPLICATE;
+                }
+
+                let mismatches = pair.mismatches[k] as i32;
+                let data = Data::try_from(vec![
+                    Field::new(Tag::ReadGroup, Value::String(String::from(READ_GROUP_ID))),
+                    Field::new(Tag::EditDistance, Value::Int32(mismatches)),
+                    Field::new(
+                        Tag::AlignmentScore,
+                        Value::Int32(read_length as i32 - 5 * mismatches),
+                    ),
+                ])?;
+
+                records.push(
+                    Record::builder()
+                        .set_read_name(pair.name.parse::<ReadName>()?)
+                        .set_flags(flags)
+                        .set_reference_sequence_id(pair.sequence_id)
+                        .set_alignment_start(start.try_into()?)
+                        .set_mapping_quality(MappingQuality::try_from(60)?)
+                        .set_cigar(format!("{}M", read_length).parse()?)
+                        .set_mate_reference_sequence_id(pair.sequence_id)
+                        .set_mate_alignment_start(mate_start.try_into()?)
+                        .set_template_length(match reverse {
+                            true => -template_length,
+                            false => template_length,
+                        })
+                        .set_sequence(Sequence::try_from(pair.bases[k].clone())?)
+                        .set_quality_scores(QualityScores::try_from(
+                            pair.quality_scores[k].clone(),
+                        )?)
+                        .set_data(data)
+                        .build(),
+                );
+            }
+        }
+
+        records.sort_by_key(|record| (record.reference_sequence_id(), record.alignment_start()));
+        Ok(records)
+    }
+
+    /// Writes the records of this data set to a BAM file.
+    pub fn write_bam<P: AsRef<Path>>(&self, dst: P) -> anyhow::Result<()> {
+        let path = dst.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("could not create BAM file: {}", path.display()))?;
+
+        let header = self.header()?;
+        let mut writer = bam::Writer::new(BufWriter::new(file));
+        writer.write_header(&header)?;
+        writer.write_reference_sequences(header.reference_sequences())?;
+
+        for record in self.records()? {
+            writer.write_record(&header, &record)?;
+        }
+
+        writer.try_finish()?;
+        outputs::record(path, "bam");
+        Ok(())
+    }
+
+    /// Writes the reads of this data set to a pair of FASTQ files (optionally
+    /// gzipped), in the orientation they were sequenced in.
+    pub fn write_fastqs<P: AsRef<Path>>(&self, read_ones: P, read_twos: P) -> anyhow::Result<()> {
+        let mut writers = [
+            crate::utils::formats::fastq::writer(&read_ones)?,
+            crate::utils::formats::fastq::writer(&read_twos)?,
+        ];
+
+        let suffixes = match self.options.read_name_pattern {
+            ReadNamePattern::Illumina14 => ["/1", "/2"],
+            _ => ["", ""],
+        };
+
+        for pair in &self.pairs {
+            for k in 0..2 {
+                let read = match (k == 0) != pair.first_is_reverse {
+                    true => 0,
+                    false => 1,
+                };
+
+                // The read on the reverse strand was sequenced as the reverse
+                // complement of the reference.
+                let (bases, quality_scores) = match k {
+                    0 => (
+                        pair.bases[k].clone(),
+                        pair.quality_scores[k].iter().map(|q| q + b'!').collect(),
+                    ),
+                    _ => {
+                        let mut quality_scores: Vec<u8> =
+                            pair.quality_scores[k].iter().map(|q| q + b'!').collect();
+                        quality_scores.reverse();
+                        (
+                            utils::reverse_compliment(&pair.bases[k])
+                                .expect("bases to be nucleobases"),
+                            quality_scores,
+                        )
+                    }
+                };
+
+                writers[read].write_record(&fastq::Record::new(
+                    format!("{}{}", pair.name, suffixes[read]),
+                    bases,
+                    quality_scores,
+                ))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the reference genome of this data set to a FASTA file.
+    pub fn write_fasta<P: AsRef<Path>>(&self, dst: P) -> anyhow::Result<()> {
+        let path = dst.as_ref();
+        let mut writer = File::create(path)
+            .map(BufWriter::new)
+            .with_context(|| format!("could not create FASTA file: {}", path.display()))?;
+        outputs::record(path, "fasta");
+
+        for (name, bases) in &self.reference {
+            writeln!(writer, ">{}", name)?;
+            for line in bases.chunks(60) {
+                writer.write_all(line)?;
+                writeln!(writer)?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Picks the 1-based start of a fragment according to the coverage profile.
+fn fragment_start(options: &SyntheticOptions, fragment_length: usize, rng: &mut StdRng) -> usize {
+    let positions = options.sequence_length - fragment_length + 1;
+
+    match options.coverage_profile {
+        CoverageProfile::Uniform => rng.gen_range(1..=positions),
+        CoverageProfile::Gradient => {
+            // The square root of a uniform sample has a linearly increasing
+            // density.
+            let u: f64 = rng.gen();
+            1 + ((u.sqrt() * positions as f64) as usize).min(positions - 1)
+        }
+        CoverageProfile::Gaps => {
+            let window = options.sequence_length / GAP_WINDOWS;
+            loop {
+                let start = rng.gen_range(1..=positions);
+                let end = start + fragment_length - 1;
+                let window_index = (start - 1) / window;
+                if window_index.is_multiple_of(2) && (end - 1) / window == window_index {
+                    break start;
+                }
+            }
+        }
+    }
+}
+
+/// Generates the name of the `i`th read pair.
+fn read_name(options: &SyntheticOptions, i: usize, rng: &mut StdRng) -> String {
+    let tile = 1101 + rng.gen_range(0..12);
+    let x = rng.gen_range(1000..30000);
+
+    // The pair index is used as the Y coordinate so that names are unique.
+    match options.read_name_pattern {
+        ReadNamePattern::Illumina18 => format!(
+            "{}:1:{}:1:{}:{}:{}",
+            options.instrument, options.flowcell, tile, x, i
+        ),
+        ReadNamePattern::Illumina14 => {
+            format!("{}:1:{}:{}:{}#0", options.instrument, tile, x, i)
+        }
+        ReadNamePattern::Sra => format!("SRR000001.{}", i + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_generates_reproducible_data_sets_with_the_requested_properties() -> anyhow::Result<()>
+    {
+        let options = SyntheticOptions {
+            sequence_length: 50_000,
+            pairs: 2_000,
+            read_length: 100,
+            duplication_rate: 0.25,
+            mark_duplicates: true,
+            coverage_profile: CoverageProfile::Gaps,
+            ..Default::default()
+        };
+
+        let data = SyntheticDataSet::generate(options.clone())?;
+        let records = data.records()?;
+        assert_eq!(records.len(), 4_000);
+
+        // The same seed produces the same data set.
+        let again = SyntheticDataSet::generate(options)?;
+        assert_eq!(again.records()?, records);
+
+        // About a quarter of the pairs are (marked) duplicates.
+        let duplicates = records.iter().filter(|r| r.flags().is_duplicate()).count();
+        assert!((800..1_200).contains(&duplicates), "{}", duplicates);
+
+        // Every other window receives no reads (as fragments fit within a
+        // window), and the records are sorted.
+        for record in &records {
+            let start = usize::from(record.alignment_start().unwrap());
+            assert_eq!(((start - 1) / 5_000) % 2, 0);
+        }
+        assert!(records
+            .windows(2)
+            .all(|w| (w[0].reference_sequence_id(), w[0].alignment_start())
+                <= (w[1].reference_sequence_id(), w[1].alignment_start())));
+
+        // Read names follow the Illumina 1.8 convention.
+        let name = records[0].read_name().unwrap().to_string();
+        assert!(name.starts_with("A00001:1:HNGSSYSXX:1:"), "{}", name);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_rejects_sequences_too_short_for_the_read_length() {
+        let options = SyntheticOptions {
+            sequence_length: 400,
+            read_length: 100,
+            ..Default::default()
+        };
+
+        assert!(SyntheticDataSet::generate(options).is_err());
+    }
+}
@@ -8,11 +8,12 @@ use anyhow::{bail, Context};
 use noodles::bam::{self as bam, bai};
 use noodles::csi::index::reference_sequence::bin::Chunk;
 use noodles::sam::{self as sam, alignment::Record, header::record::value::map::header::SortOrder};
-use num_format::{Locale, ToFormattedString};
 use std::{fs::File, io, path::PathBuf};
 use tracing::{debug, info};
 
+use crate::utils::display;
 use crate::utils::formats::sam::parse_header;
+use crate::utils::outputs;
 use crate::utils::pathbuf::AppendExtension;
 
 //==================================//
@@ -55,7 +56,7 @@ pub fn index(src: PathBuf) -> anyhow::Result<()> {
 
     // (3) Read the header and reference sequences.
     let ht = reader.read_header().with_context(|| "reading header")?;
-    let header = parse_header(ht);
+    let header = parse_header(ht)?;
     reader
         .read_reference_sequences()
         .with_context(|| "reading reference sequences")?;
@@ -97,7 +98,7 @@ pub fn index(src: PathBuf) -> anyhow::Result<()> {
         if records_processed % 1_000_000 == 0 {
             debug!(
                 "  [*] Processed {} records.",
-                records_processed.to_formatted_string(&Locale::en),
+                display::count(records_processed),
             );
         }
     }
@@ -106,9 +107,10 @@ pub fn index(src: PathBuf) -> anyhow::Result<()> {
 
     // (6) Write the index to disk.
     debug!("writing the BAM index to disk");
-    let mut writer = File::create(bai)
+    let mut writer = File::create(&bai)
         .map(bai::Writer::new)
         .with_context(|| "creating BAM index output file")?;
+    outputs::record(&bai, "bai");
 
     writer
         .write_header()
@@ -6,6 +6,7 @@ use anyhow::{bail, Context};
 use noodles::cram::{self as cram, crai};
 use tracing::{debug, info};
 
+use crate::utils::outputs;
 use crate::utils::pathbuf::AppendExtension;
 
 /// Main method for CRAM indexing.
@@ -32,9 +33,10 @@ pub fn index(src: PathBuf) -> anyhow::Result<()> {
 
     // (3) Write the index to disk.
     debug!("writing the CRAM index to disk");
-    let mut writer = File::create(crai)
+    let mut writer = File::create(&crai)
         .map(crai::Writer::new)
         .with_context(|| "creating CRAM index output file")?;
+    outputs::record(&crai, "crai");
 
     writer.write_index(&index)?;
 
@@ -4,6 +4,7 @@
 //! noodles. You can find that source code in the `fasta_index.rs` example of the
 //! `noodles_fasta` crate at commit 44b7d19.
 
+use crate::utils::outputs;
 use crate::utils::pathbuf::AppendExtension;
 use std::{fs::File, path::PathBuf};
 
@@ -35,9 +36,10 @@ pub fn index(src: PathBuf) -> anyhow::Result<()> {
 
     // (3) Write the index to disk.
     debug!("writing the FASTA index to disk");
-    let mut writer = File::create(fai)
+    let mut writer = File::create(&fai)
         .map(fai::Writer::new)
         .with_context(|| "initializing the new FASTA index file")?;
+    outputs::record(&fai, "fai");
     writer
         .write_index(&index)
         .with_context(|| "writing the FASTA index file")?;
@@ -10,11 +10,14 @@
 #![warn(rust_2018_idioms)]
 #![warn(rust_2021_compatibility)]
 
+pub mod check;
+pub mod depth;
 pub mod derive;
 pub mod generate;
 pub mod index;
 pub mod list;
 pub mod plot;
 pub mod qc;
+pub mod triage;
 pub mod utils;
 pub mod view;
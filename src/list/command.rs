@@ -1,12 +1,15 @@
 //! Functionality related to the `ngs list` command itself.
 
 use anyhow::bail;
-use clap::{builder::PossibleValuesParser, Args};
+use clap::{builder::PossibleValuesParser, Args, Command, Subcommand};
 
-use prettytable::{row, Table};
+use prettytable::{Row, Table};
+use serde_json::{Map, Value};
 
 use crate::{
+    derive::command::DeriveSubcommand,
     plot::command::{get_all_cohort_plots, get_all_sample_plots},
+    qc::get_all_qc_facets,
     utils::genome::get_all_reference_genomes,
 };
 
@@ -18,67 +21,236 @@ use crate::{
 #[derive(Args)]
 pub struct ListArgs {
     /// The subject which you want to list values for.
-    #[arg(value_parser = PossibleValuesParser::new(["genomes", "plots"]))]
+    #[arg(value_parser = PossibleValuesParser::new(["genomes", "plots", "qc-facets", "derive-subcommands"]))]
     subject: String,
+
+    /// The format in which to print the listing.
+    #[arg(long, value_name = "FORMAT", default_value = "table")]
+    #[arg(value_parser = PossibleValuesParser::new(["table", "json", "tsv"]))]
+    format: String,
 }
 
-//==============//
-// Main command //
-//==============//
+//==========//
+// Listings //
+//==========//
 
-/// Main method for the `ngs list` subcommand.
-pub fn list(args: ListArgs) -> anyhow::Result<()> {
-    match args.subject.as_str() {
+/// A titled group of rows that is printed by `ngs list`.
+pub struct Listing {
+    /// An optional title printed above the table.
+    pub title: Option<&'static str>,
+
+    /// The names of the columns.
+    pub columns: Vec<&'static str>,
+
+    /// The rows of values, each having one value per column.
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Listing {
+    /// Creates a new, empty [`Listing`] with the provided columns.
+    pub fn new(title: Option<&'static str>, columns: Vec<&'static str>) -> Self {
+        Self {
+            title,
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row to the [`Listing`].
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+}
+
+/// Converts a human readable column name (e.g., `Triplet ID`) into a key that
+/// is suitable for machine-readable output (e.g., `triplet_id`).
+fn column_key(column: &str) -> String {
+    column.to_lowercase().replace(' ', "_")
+}
+
+/// Renders the listings as one or more tables.
+fn render_table(listings: &[Listing]) -> String {
+    let mut rendered = Vec::new();
+
+    for listing in listings {
+        let mut table = Table::new();
+        table.add_row(Row::from(&listing.columns));
+        for row in &listing.rows {
+            table.add_row(Row::from(row));
+        }
+
+        let mut section = String::new();
+        if let Some(title) = listing.title {
+            section.push_str(&format!("{}:\n\n", title));
+        }
+        section.push_str(&table.to_string());
+        rendered.push(section);
+    }
+
+    rendered.join("\n")
+}
+
+/// Renders the rows of all listings as a single JSON array of objects keyed by
+/// column name.
+fn render_json(listings: &[Listing]) -> anyhow::Result<String> {
+    let mut objects = Vec::new();
+
+    for listing in listings {
+        for row in &listing.rows {
+            let object: Map<String, Value> = listing
+                .columns
+                .iter()
+                .zip(row)
+                .map(|(column, value)| (column_key(column), Value::String(value.clone())))
+                .collect();
+            objects.push(Value::Object(object));
+        }
+    }
+
+    Ok(serde_json::to_string_pretty(&objects)? + "\n")
+}
+
+/// Renders the rows of all listings as tab-delimited values under a single
+/// header line. All listings must share the same columns.
+fn render_tsv(listings: &[Listing]) -> anyhow::Result<String> {
+    let columns = match listings.first() {
+        Some(listing) => &listing.columns,
+        None => return Ok(String::new()),
+    };
+
+    if listings.iter().any(|listing| &listing.columns != columns) {
+        bail!("Listings with differing columns cannot be rendered as a single TSV.");
+    }
+
+    let mut lines = vec![columns.iter().map(|c| column_key(c)).collect::<Vec<_>>()];
+    for listing in listings {
+        lines.extend(listing.rows.iter().cloned());
+    }
+
+    Ok(lines
+        .iter()
+        .map(|line| line.join("\t") + "\n")
+        .collect::<String>())
+}
+
+/// Gets the listings for a particular subject.
+fn get_listings(subject: &str) -> anyhow::Result<Vec<Listing>> {
+    match subject {
         "genomes" => {
-            let mut table = Table::new();
+            let mut listing = Listing::new(None, vec!["Name", "Triplet ID", "Source", "Basis"]);
 
-            table.add_row(row!["Name", "Triplet ID", "Source", "Basis"]);
             for reference in get_all_reference_genomes() {
-                table.add_row(row![
-                    reference.name(),
-                    reference.triplet_id(),
-                    reference.source(),
-                    reference.basis(),
+                listing.add_row(vec![
+                    reference.name().to_string(),
+                    reference.triplet_id().to_string(),
+                    reference.source().to_string(),
+                    reference.basis().to_string(),
                 ]);
             }
 
-            table.printstd();
-
-            Ok(())
+            Ok(vec![listing])
         }
         "plots" => {
-            let mut sample_table = Table::new();
-            sample_table.add_row(row!["Name", "Type", "Description"]);
+            let columns = vec!["Name", "Type", "Description"];
 
+            let mut sample_listing = Listing::new(Some("Sample Plots"), columns.clone());
             for sample_plot in get_all_sample_plots(None)? {
-                sample_table.add_row(row![
-                    sample_plot.name(),
-                    "Sample",
-                    sample_plot.description()
+                sample_listing.add_row(vec![
+                    sample_plot.name().to_string(),
+                    String::from("Sample"),
+                    sample_plot.description().to_string(),
                 ]);
             }
 
-            println!("Sample Plots:");
-            println!();
-            sample_table.printstd();
-            println!();
-
-            let mut cohort_table = Table::new();
-            cohort_table.add_row(row!["Name", "Type", "Description"]);
+            let mut cohort_listing = Listing::new(Some("Cohort Plots"), columns);
             for cohort_plot in get_all_cohort_plots(None)? {
-                cohort_table.add_row(row![
-                    cohort_plot.name(),
-                    "Cohort",
-                    cohort_plot.description()
+                cohort_listing.add_row(vec![
+                    cohort_plot.name().to_string(),
+                    String::from("Cohort"),
+                    cohort_plot.description().to_string(),
                 ]);
             }
 
-            println!("Cohort Plots:");
-            println!();
-            cohort_table.printstd();
+            Ok(vec![sample_listing, cohort_listing])
+        }
+        "qc-facets" => {
+            let mut listing = Listing::new(None, vec!["Name", "Pass", "Load", "Required Inputs"]);
+
+            for facet in get_all_qc_facets() {
+                let required_inputs = match facet.required_inputs.is_empty() {
+                    true => String::from("None"),
+                    false => facet.required_inputs.join(", "),
+                };
 
-            Ok(())
+                listing.add_row(vec![
+                    facet.name.to_string(),
+                    facet.pass.to_string(),
+                    format!("{:?}", facet.computational_load),
+                    required_inputs,
+                ]);
+            }
+
+            Ok(vec![listing])
+        }
+        "derive-subcommands" => {
+            let mut listing = Listing::new(None, vec!["Name", "Description"]);
+
+            // The subcommands are read directly from the `ngs derive` command
+            // line definition so that this list always matches what is
+            // available.
+            for subcommand in
+                DeriveSubcommand::augment_subcommands(Command::new("derive")).get_subcommands()
+            {
+                listing.add_row(vec![
+                    subcommand.get_name().to_string(),
+                    subcommand
+                        .get_about()
+                        .map(|about| about.to_string())
+                        .unwrap_or_default(),
+                ]);
+            }
+
+            Ok(vec![listing])
         }
         s => bail!("Unsupported subject: {}", s),
     }
 }
+
+//==============//
+// Main command //
+//==============//
+
+/// Main method for the `ngs list` subcommand.
+pub fn list(args: ListArgs) -> anyhow::Result<()> {
+    let listings = get_listings(args.subject.as_str())?;
+
+    let output = match args.format.as_str() {
+        "table" => render_table(&listings),
+        "json" => render_json(&listings)?,
+        "tsv" => render_tsv(&listings)?,
+        s => bail!("Unsupported format: {}", s),
+    };
+
+    print!("{}", output);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_renders_listings_in_machine_readable_formats() -> anyhow::Result<()> {
+        let mut listing = Listing::new(None, vec!["Name", "Triplet ID"]);
+        listing.add_row(vec![String::from("hg38"), String::from("a-b-c")]);
+        let listings = vec![listing];
+
+        assert_eq!(render_tsv(&listings)?, "name\ttriplet_id\nhg38\ta-b-c\n");
+
+        let json: Value = serde_json::from_str(&render_json(&listings)?)?;
+        assert_eq!(json[0]["triplet_id"], "a-b-c");
+
+        Ok(())
+    }
+}
@@ -1,10 +1,22 @@
 #![warn(rust_2018_idioms)]
 #![warn(rust_2021_compatibility)]
 
-use clap::{Parser, Subcommand};
+use std::{net::SocketAddr, num::NonZeroUsize, path::PathBuf};
+
+use clap::{builder::PossibleValuesParser, Parser, Subcommand};
 
 use git_testament::{git_testament, render_testament};
-use ngs::{derive, generate, index, list, plot, qc, view};
+use ngs::{
+    check, depth, derive, generate, index, list, plot,
+    qc::{self, gate::GateFailure},
+    triage,
+    utils::{
+        checksum, display,
+        logging::{JsonFormat, LogFormat},
+        metrics, outputs, progress, provenance, threads,
+    },
+    view,
+};
 
 #[derive(Parser)]
 #[command(author, version = render_testament!(TESTAMENT), propagate_version = true, about, long_about = None)]
@@ -13,16 +25,60 @@ struct Cli {
     pub subcommand: Subcommands,
 
     /// Only errors are printed to the stderr stream.
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub quiet: bool,
 
     /// All available information, including debug information, is printed to stderr.
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub verbose: bool,
+
+    /// The format of the logs: human-readable text, or a JSON object per line
+    /// (in which counts are always written as plain digits).
+    #[arg(long, global = true, value_name = "FORMAT", default_value = "text")]
+    #[arg(value_parser = PossibleValuesParser::new(["text", "json"]))]
+    pub log_format: String,
+
+    /// The locale whose digit grouping is used for the counts in the logs
+    /// (e.g., `en` for `1,000,000` or `de` for `1.000.000`), or `none` to write
+    /// them as plain digits.
+    #[arg(long, global = true, value_name = "LOCALE", default_value = "en")]
+    pub locale: String,
+
+    /// The number of threads to use. For `ngs qc`, this is the number of
+    /// workers that decompress the BAM file during the first pass (by default,
+    /// one).
+    #[arg(short, long, global = true, value_name = "USIZE")]
+    pub threads: Option<NonZeroUsize>,
+
+    /// Records the MD5 and SHA-256 of each input file in the output.
+    #[arg(long, global = true)]
+    pub checksums: bool,
+
+    /// Writes a JSON manifest of every file written (with its type and
+    /// checksums) to the provided path.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub outputs_json: Option<PathBuf>,
+
+    /// Serves Prometheus metrics at `/metrics` on the provided address (e.g.,
+    /// `0.0.0.0:9898`) while `ngs` runs.
+    #[arg(long, global = true, value_name = "ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Writes machine-parsable progress events (one JSON object per line) to
+    /// the provided path (e.g., a named pipe or `/dev/fd/3`).
+    #[arg(long, global = true, value_name = "PATH")]
+    pub progress_events: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Subcommands {
+    /// Checks the integrity of BGZF-compressed (e.g., BAM) and CRAM files.
+    Check(check::command::CheckArgs),
+
+    /// Computes the depth at each position (or within windows) of a BAM file.
+    Depth(depth::command::DepthArgs),
+
     /// Forensic analysis tool for next-generation sequencing data.
     Derive(derive::command::DeriveArgs),
 
@@ -41,6 +97,10 @@ enum Subcommands {
     /// Generates quality control metrics for BAM files.
     Qc(qc::command::QcArgs),
 
+    /// Quickly profiles a sample of a BAM file (flags, endedness, quality
+    /// encoding, instrument, and light quality control metrics).
+    Triage(triage::command::TriageArgs),
+
     /// Views various next-generation sequencing files, sometimes with a query region.
     View(view::command::ViewArgs),
 }
@@ -48,6 +108,7 @@ enum Subcommands {
 git_testament!(TESTAMENT);
 
 fn main() -> anyhow::Result<()> {
+    provenance::start();
     let cli = Cli::parse();
 
     //===========//
@@ -62,34 +123,108 @@ fn main() -> anyhow::Result<()> {
         tracing::Level::INFO
     };
 
-    let subscriber = tracing_subscriber::fmt::Subscriber::builder()
+    // All logging is written to stderr: stdout is reserved for data (e.g., the
+    // JSON written by `ngs derive` and `ngs qc fastq-pairs`).
+    let builder = tracing_subscriber::fmt::Subscriber::builder()
         .with_max_level(level)
-        .with_writer(std::io::stderr)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)?;
+        .with_writer(std::io::stderr);
+
+    let log_format: LogFormat = cli.log_format.parse()?;
+    match log_format {
+        LogFormat::Text => tracing::subscriber::set_global_default(builder.finish())?,
+        LogFormat::Json => {
+            tracing::subscriber::set_global_default(builder.event_format(JsonFormat).finish())?
+        }
+    }
+
+    //========//
+    // Locale //
+    //========//
+
+    // Structured logs are parsed by machines, so their counts are always
+    // written as plain digits.
+    match log_format {
+        LogFormat::Text => display::set_locale(display::parse_locale(&cli.locale)?),
+        LogFormat::Json => display::set_locale(None),
+    }
+
+    //===========//
+    // Checksums //
+    //===========//
+
+    checksum::set_enabled(cli.checksums);
+
+    //=========//
+    // Threads //
+    //=========//
+
+    threads::set_threads(cli.threads);
+
+    //=================//
+    // Progress events //
+    //=================//
+
+    if let Some(path) = &cli.progress_events {
+        progress::set_sink(path)?;
+    }
+
+    //=========//
+    // Metrics //
+    //=========//
+
+    if let Some(addr) = cli.metrics_addr {
+        let bound = metrics::serve(addr)?;
+        tracing::info!("Serving metrics at http://{}/metrics.", bound);
+    }
 
     //=====================//
     // Subcommand matching //
     //=====================//
 
-    match cli.subcommand {
-        Subcommands::Derive(args) => match args.subcommand {
-            derive::command::DeriveSubcommand::Instrument(args) => {
-                derive::command::instrument::derive(args)?
-            }
-        },
-        Subcommands::Generate(args) => generate::command::generate(args)?,
-        Subcommands::Index(args) => index::command::index(args)?,
-        Subcommands::List(args) => list::command::list(args)?,
-        Subcommands::Plot(args) => match args.subcommand {
-            plot::command::PlotSubcommand::Cohort(args) => plot::cohort::plot(args)?,
-            plot::command::PlotSubcommand::Sample(args) => plot::sample::plot(args)?,
-        },
-        Subcommands::Qc(args) => qc::command::qc(args)?,
-        Subcommands::View(args) => view::command::view(args)?,
-    };
+    let result = run(cli.subcommand);
+    if result.is_err() {
+        metrics::failure();
+    }
+
+    // Results that failed the quality control gate (`ngs qc --strict-qc`)
+    // were still written, so they are included in the manifest.
+    let gate_failure = result
+        .as_ref()
+        .err()
+        .and_then(|e| e.downcast_ref::<GateFailure>())
+        .map(|failure| (failure.status, failure.to_string()));
 
-    Ok(())
+    //==================//
+    // Outputs manifest //
+    //==================//
+
+    if let (Some(path), true) = (cli.outputs_json, result.is_ok() || gate_failure.is_some()) {
+        outputs::write_manifest(path)?;
+    }
+
+    if let Some((status, message)) = gate_failure {
+        tracing::error!("{}", message);
+        std::process::exit(status.exit_code());
+    }
+
+    result
+}
+
+/// Runs the subcommand. Every subcommand dispatches its own subcommands (if
+/// any), so each is run the same way.
+fn run(subcommand: Subcommands) -> anyhow::Result<()> {
+    match subcommand {
+        Subcommands::Check(args) => check::command::check(args),
+        Subcommands::Depth(args) => depth::command::depth(args),
+        Subcommands::Derive(args) => derive::command::derive(args),
+        Subcommands::Generate(args) => generate::command::generate(args),
+        Subcommands::Index(args) => index::command::index(args),
+        Subcommands::List(args) => list::command::list(args),
+        Subcommands::Plot(args) => plot::command::plot(args),
+        Subcommands::Qc(args) => qc::command::qc(args),
+        Subcommands::Triage(args) => triage::command::triage(args),
+        Subcommands::View(args) => view::command::view(args),
+    }
 }
 
 #[cfg(test)]
@@ -11,6 +11,7 @@ use clap::Args;
 use crate::{
     plot::command::{get_all_cohort_plots, FilepathResults},
     qc::results::Results,
+    utils::outputs,
 };
 
 /// Clap arguments for the `ngs plot cohort` subcommand.
@@ -67,7 +68,8 @@ pub fn plot(args: PlotCohortArgs) -> anyhow::Result<()> {
         filename.push(String::from(p.filename()) + ".cohort.html");
 
         info!("  [*] Writing {} to {}", p.name(), filename.display());
-        plot.write_html(filename);
+        plot.write_html(&filename);
+        outputs::record(&filename, "plot");
     }
 
     Ok(())
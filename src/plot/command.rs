@@ -3,7 +3,7 @@
 use std::path::PathBuf;
 
 use anyhow::bail;
-use clap::{command, Args, Subcommand};
+use clap::{Args, Subcommand};
 use itertools::Itertools;
 use plotly::common::Title;
 
@@ -36,6 +36,14 @@ pub enum PlotSubcommand {
     Sample(PlotSampleArgs),
 }
 
+/// Main function for the `ngs plot` subcommand.
+pub fn plot(args: PlotArgs) -> anyhow::Result<()> {
+    match args.subcommand {
+        PlotSubcommand::Cohort(args) => cohort::plot(args),
+        PlotSubcommand::Sample(args) => sample::plot(args),
+    }
+}
+
 /// Utility struct for passing around a combination of the filepath and
 /// the loaded `ngs qc` results. This is useful when passing around this tuple
 /// of arguments to various functions in the `ngs plot` subcommand(s).
@@ -93,6 +101,7 @@ pub fn get_all_sample_plots(
 ) -> anyhow::Result<Vec<Box<dyn SamplePlot>>> {
     let mut results: Vec<Box<dyn SamplePlot>> = vec![
         Box::new(sample::quality_score_distribution::QualityScoreDistributionPlot),
+        Box::new(sample::quality_score_heatmap::QualityScoreHeatmapPlot),
         Box::new(sample::gc_content_distribution::GCContentDistributionPlot),
     ];
 
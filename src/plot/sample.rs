@@ -2,6 +2,7 @@
 
 pub mod gc_content_distribution;
 pub mod quality_score_distribution;
+pub mod quality_score_heatmap;
 
 use std::path::PathBuf;
 
@@ -13,6 +14,7 @@ use tracing::{debug, info};
 use crate::{
     plot::command::{get_all_sample_plots, FilepathResults},
     qc::results::Results,
+    utils::outputs,
 };
 
 /// Clap arguments for the `ngs plot sample` subcommand.
@@ -80,7 +82,8 @@ pub fn plot(args: PlotSampleArgs) -> anyhow::Result<()> {
         filename.push(String::from(p.filename()) + ".sample.html");
 
         info!("  [*] Writing {} to {}", p.name(), filename.display());
-        plot.write_html(filename);
+        plot.write_html(&filename);
+        outputs::record(&filename, "plot");
     }
 
     Ok(())
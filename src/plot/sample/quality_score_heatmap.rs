@@ -0,0 +1,74 @@
+//! Quality score heatmap for a single sample.
+
+use anyhow::bail;
+use plotly::{common::Title, layout::Axis, HeatMap, Layout};
+
+use crate::{
+    plot::command::{FilepathResults, SamplePlot},
+    qc::record_based::quality_scores::QualityScoreMatrix,
+};
+
+/// Struct that represents a quality score heatmap for a single sample.
+pub struct QualityScoreHeatmapPlot;
+
+impl SamplePlot for QualityScoreHeatmapPlot {
+    fn name(&self) -> &'static str {
+        "Quality Score Heatmap"
+    }
+
+    fn description(&self) -> &'static str {
+        "Shows the number of bases with each quality score at each position \
+        across a read."
+    }
+
+    fn filename(&self) -> &'static str {
+        "quality-score-heatmap"
+    }
+
+    fn generate(
+        &self,
+        filepath_results: &FilepathResults,
+        title: Title,
+    ) -> anyhow::Result<plotly::Plot> {
+        let mut plot = plotly::Plot::new();
+        let FilepathResults(filepath, results) = filepath_results;
+
+        // (1) Check to make sure that the results files have the necessary
+        // keys to plot the data. If they don't then we need to fail as there
+        // will be nothing to plot.
+        let quality_scores = match &results.quality_scores {
+            Some(qs) => qs,
+            None => bail!(
+                "File {} has no quality score information!",
+                filepath.display()
+            ),
+        };
+
+        // (2) Use the count matrix if it was reported. Otherwise, it can be
+        // derived from the per-position histograms.
+        let matrix = match &quality_scores.matrix {
+            Some(matrix) => matrix.clone(),
+            None => QualityScoreMatrix::from_scores(&quality_scores.scores),
+        };
+
+        // (3) The heatmap expects one row per quality score, so the dense
+        // matrix (which has one row per position) is transposed.
+        let dense = matrix.to_dense();
+        let z: Vec<Vec<usize>> = (0..matrix.scores)
+            .map(|score| dense.iter().map(|row| row[score]).collect())
+            .collect();
+        let x: Vec<usize> = (1..=matrix.positions).collect();
+        let y: Vec<usize> = (0..matrix.scores).collect();
+
+        plot.add_trace(HeatMap::new(x, y, z));
+
+        // (4) Configure the graph for plotting and return.
+        let layout = Layout::new()
+            .title(title)
+            .x_axis(Axis::new().title(Title::new("Position")))
+            .y_axis(Axis::new().title(Title::new("Quality Score")));
+        plot.set_layout(layout);
+
+        Ok(plot)
+    }
+}
@@ -1,6 +1,6 @@
 //! Functionality related to the `ngs qc` subcommand.
 
-use std::{num::NonZeroUsize, path::PathBuf, rc::Rc};
+use std::{collections::HashSet, num::NonZeroUsize, path::PathBuf, rc::Rc};
 
 use anyhow::bail;
 use itertools::Itertools;
@@ -10,24 +10,69 @@ use noodles::sam::{
     Header,
 };
 use sam::alignment::Record;
+use tracing::{info_span, Span};
 
-use crate::utils::genome::ReferenceGenome;
+use crate::utils::{formats, genome::ReferenceGenome};
 
 use self::{
+    examples::Example,
+    profile::QcProfile,
     record_based::{
-        features::{FeatureNames, GenomicFeaturesFacet},
+        adapters::AdapterContentFacet,
+        alignment_summary::AlignmentSummaryFacet,
+        contamination::{ContaminationFacet, SketchSet},
+        duplication::DuplicationFacet,
+        features::{counts::GeneCountingOptions, FeatureNames, GenomicFeaturesFacet},
+        fragmentomics::FragmentomicsFacet,
         gc_content::GCContentFacet,
         general::GeneralMetricsFacet,
+        long_reads::LongReadsFacet,
+        mapping_quality::MappingQualityFacet,
+        mates::MateConsistencyFacet,
+        methylation::MethylationFacet,
         quality_scores::QualityScoreFacet,
+        read_groups::ReadGroupsFacet,
+        read_length::ReadLengthFacet,
+        read_names::ReadNameCollisionsFacet,
+        repeats::{RepeatAnnotation, RepeatsFacet},
+        single_cell::SingleCellFacet,
+        species::SpeciesFacet,
+        spike_ins::SpikeInsFacet,
         template_length::TemplateLengthFacet,
+        tiles::TilesFacet,
+        unmapped::UnmappedFacet,
+    },
+    sequence_based::{
+        allele_balance::AlleleBalanceFacet,
+        artifacts::SequencingArtifactsFacet,
+        coverage::{
+            territories::{GapSource, TerritoryDefinition},
+            CoverageFacet, UsableDepthThresholds,
+        },
+        edits::EditsFacet,
+        gc_content::ReferenceGCContentFacet,
+        oxog::OxoGFacet,
     },
-    sequence_based::{coverage::CoverageFacet, edits::EditsFacet},
 };
 
+pub mod baseline;
 pub mod command;
+pub mod container_based;
+pub mod estimate;
+pub mod examples;
+pub mod facet_set;
+pub mod fastq_pairs;
+pub mod gate;
+pub mod histograms;
+pub mod prefetch;
+pub mod profile;
 pub mod record_based;
 pub mod results;
+pub mod runtime;
 pub mod sequence_based;
+pub mod shard;
+pub mod split;
+pub mod strata;
 
 //==============================================//
 // Dynamic allocation of quality control facets //
@@ -43,28 +88,49 @@ type SequenceBasedQualityControlFacetBoxedVec<'a> =
 /// This method starts by defining the base set of QC facets that will be run
 /// based on the arguments provided on the command line. Next, filtering is done
 /// based on the arguments provided on the command line.
+#[allow(clippy::too_many_arguments)]
 pub fn get_qc_facets<'a>(
     features_gff: Option<PathBuf>,
     feature_names: Option<&'a FeatureNames>,
     header: Option<&'a Header>,
     reference_fasta: Option<PathBuf>,
+    sites_vcf: Option<PathBuf>,
     reference_genome: Rc<Box<dyn ReferenceGenome>>,
     only_facet: Option<String>,
+    options: &FacetOptions,
 ) -> anyhow::Result<(
-    RecordBasedQualityControlFacetBoxedVec<'_>,
-    SequenceBasedQualityControlFacetBoxedVec<'_>,
+    RecordBasedQualityControlFacetBoxedVec<'a>,
+    SequenceBasedQualityControlFacetBoxedVec<'a>,
 )> {
+    let count_duplicates = options.count_duplicates;
+
     // (1) Define the full list of facets that are supported for the
     // record-based quality control facets.
 
     // Default facets that are loaded within the qc subcommand.
     let mut record_based_facets: Vec<Box<dyn RecordBasedQualityControlFacet>> = vec![
         Box::new(GeneralMetricsFacet::default()),
-        Box::new(TemplateLengthFacet::with_capacity(1024)),
-        Box::new(GCContentFacet::default()),
-        Box::new(QualityScoreFacet::default()),
+        Box::new(TemplateLengthFacet::from_options(options)),
+        Box::new(GCContentFacet::from_options(options)),
+        Box::new(QualityScoreFacet::from_options(options)),
+        Box::new(MateConsistencyFacet::default()),
+        Box::new(ReadNameCollisionsFacet::default()),
+        Box::new(TilesFacet::new(count_duplicates)),
+        Box::new(ReadLengthFacet::from_options(options)),
+        Box::new(AdapterContentFacet::from_options(options)),
+        Box::new(DuplicationFacet::default()),
+        Box::new(AlignmentSummaryFacet::from_options(options)),
+        Box::new(MappingQualityFacet::from_options(options)),
+        Box::new(MethylationFacet::from_options(options)),
+        Box::new(SingleCellFacet::default()),
+        Box::new(UnmappedFacet::new(header, options)),
     ];
 
+    // Optionally load the Read Groups facet if the header is provided.
+    if let Some(header) = header {
+        record_based_facets.push(Box::new(ReadGroupsFacet::new(header)));
+    }
+
     // Optionally load the Genomic Features facet if the GFF file is provided.
     if let Some(features_src) = features_gff {
         if let Some(feature_names) = feature_names {
@@ -74,56 +140,605 @@ pub fn get_qc_facets<'a>(
                     feature_names,
                     header,
                     Rc::clone(&reference_genome),
+                    options,
                 )?));
             }
         }
     }
 
+    // Optionally load the Fragmentomics facet if it was requested.
+    if options.fragmentomics {
+        record_based_facets.push(Box::new(FragmentomicsFacet::from_options(options)));
+    }
+
+    // Optionally load the Long Reads facet if the long-read profile is used.
+    if options.profile == QcProfile::LongRead {
+        record_based_facets.push(Box::new(LongReadsFacet::new(header, options)));
+    }
+
+    // Optionally load the Species facet if the reference genome is a hybrid
+    // genome (e.g., of human and mouse).
+    if let Some(header) = header {
+        if reference_genome.species().len() > 1 {
+            record_based_facets.push(Box::new(SpeciesFacet::new(
+                reference_genome.as_ref().as_ref(),
+                header,
+                options,
+            )));
+        }
+    }
+
+    // Optionally load the Spike-ins facet if the reference contains spike-in
+    // sequences or a FASTA of spike-in sequences is provided.
+    if let Some(facet) = SpikeInsFacet::try_new(header, options)? {
+        record_based_facets.push(Box::new(facet));
+    }
+
+    // Optionally load the Contamination facet if genomes to screen against are
+    // provided.
+    if let Some(screen_src) = &options.contamination_screen {
+        let sketches = SketchSet::read(screen_src)?;
+        record_based_facets.push(Box::new(ContaminationFacet::new(sketches, options)));
+    }
+
+    // Optionally load the Repeats facet if a repeat annotation is provided.
+    // The repeats are read once and also define the Coverage facet's
+    // territories within and outside of the repeats.
+    let mut repeat_territories = Vec::new();
+    if let Some(repeats_src) = &options.repeats {
+        if let Some(header) = header {
+            let annotation = RepeatAnnotation::read(repeats_src)?;
+            repeat_territories = annotation.territories();
+            record_based_facets.push(Box::new(RepeatsFacet::new(annotation, header, options)));
+        }
+    }
+
     // (2) Define the full list of facets that are supported for the
     // sequence-based quality control facets.
 
-    // Default facets that are loaded within the qc subcommand.
+    // Default facets that are loaded within the qc subcommand. The coverage
+    // territories (and gaps) are resolved up front, as some of them require
+    // reading the reference FASTA.
+    let territories = options
+        .coverage_territories
+        .iter()
+        .map(|territory| {
+            territory.resolve(
+                reference_genome.as_ref().as_ref(),
+                reference_fasta.as_deref(),
+            )
+        })
+        .chain(repeat_territories.into_iter().map(Ok))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if let Some(name) = territories.iter().map(|t| &t.name).duplicates().next() {
+        bail!("More than one coverage territory is named {}.", name);
+    }
+
+    let mut coverage = CoverageFacet::new(
+        Rc::clone(&reference_genome),
+        options.coverage_bin_size,
+        options,
+    )
+    .with_territories(territories);
+    if let Some(gaps) = &options.coverage_gaps {
+        coverage = coverage.with_gaps(gaps.resolve(reference_fasta.as_deref())?);
+    }
+
     let mut sequence_based_facets: Vec<Box<dyn SequenceBasedQualityControlFacet>> =
-        vec![Box::new(CoverageFacet::new(
-            Rc::clone(&reference_genome),
-            NonZeroUsize::new(50_000).unwrap(),
-        ))];
+        vec![Box::new(coverage)];
 
-    // Optionally load the Edits facet if a reference FASTA is provided.
+    // The sites VCF is read once and shared by the facets that use it.
+    let sites = sites_vcf
+        .map(formats::vcf::read_sites_from_path)
+        .transpose()?;
+
+    // Optionally load the Edits, Reference GC Content, OxoG, and Sequencing
+    // Artifacts facets if a reference FASTA is provided.
     if let Some(fasta) = reference_fasta {
-        sequence_based_facets.push(Box::new(EditsFacet::try_from(fasta)?))
+        sequence_based_facets.push(Box::new(EditsFacet::try_from(
+            fasta.clone(),
+            sites.as_ref(),
+            options,
+        )?));
+        sequence_based_facets.push(Box::new(ReferenceGCContentFacet::try_from(
+            fasta.clone(),
+            count_duplicates,
+        )?));
+        sequence_based_facets.push(Box::new(OxoGFacet::try_from(fasta.clone(), options)?));
+        sequence_based_facets.push(Box::new(SequencingArtifactsFacet::try_from(
+            fasta, options,
+        )?));
+    }
+
+    // Optionally load the Allele Balance facet if a sites VCF is provided.
+    if let Some(sites) = sites {
+        sequence_based_facets.push(Box::new(AlleleBalanceFacet::new(sites.sequences, options)));
+    }
+
+    // (3) Drop the facets that are not run within the profile.
+    record_based_facets.retain(|facet| options.profile.includes(facet.name()));
+
+    // (4) If `only_facet` is provided, filter the facets down to the one that
+    // was requested.
+    match only_facet {
+        Some(only) => select_only_facet(&only, record_based_facets, sequence_based_facets),
+        None => Ok((record_based_facets, sequence_based_facets)),
+    }
+}
+
+/// Dynamically compiles the quality control facets that should be run for a
+/// FASTQ file (`ngs qc fastq`). Only the record-based facets that do not depend
+/// on alignment information are supported.
+pub fn get_fastq_qc_facets<'a>(
+    only_facet: Option<String>,
+    options: &FacetOptions,
+) -> anyhow::Result<RecordBasedQualityControlFacetBoxedVec<'a>> {
+    let record_based_facets: Vec<Box<dyn RecordBasedQualityControlFacet>> = vec![
+        Box::new(GCContentFacet::from_options(options)),
+        Box::new(QualityScoreFacet::from_options(options)),
+        Box::new(TilesFacet::new(options.count_duplicates)),
+        Box::new(ReadLengthFacet::from_options(options)),
+        Box::new(AdapterContentFacet::from_options(options)),
+        Box::new(DuplicationFacet::default()),
+    ];
+
+    match only_facet {
+        Some(only) => {
+            select_only_facet(&only, record_based_facets, Vec::new()).map(|(facets, _)| facets)
+        }
+        None => Ok(record_based_facets),
+    }
+}
+
+/// Dynamically compiles the quality control facets that are run by `ngs
+/// triage`. Only the light record-based facets are included, so that a sample
+/// of a file can be profiled quickly.
+pub fn get_triage_qc_facets<'a>(
+    header: &'a Header,
+    options: &FacetOptions,
+) -> RecordBasedQualityControlFacetBoxedVec<'a> {
+    vec![
+        Box::new(GeneralMetricsFacet::default()),
+        Box::new(MappingQualityFacet::from_options(options)),
+        Box::new(ReadLengthFacet::from_options(options)),
+        Box::new(TemplateLengthFacet::from_options(options)),
+        Box::new(ReadGroupsFacet::new(header)),
+    ]
+}
+
+/// Filters the quality control facets down to the one named `only`, which is
+/// what the `--only` flag does. This (a) filters out all of the quality control
+/// facets except the one that is provided, (b) errors out if no quality control
+/// facets match the provided argument, and (c) returns the limited list
+/// otherwise.
+fn select_only_facet<'a>(
+    only: &str,
+    record_based_facets: RecordBasedQualityControlFacetBoxedVec<'a>,
+    sequence_based_facets: SequenceBasedQualityControlFacetBoxedVec<'a>,
+) -> anyhow::Result<(
+    RecordBasedQualityControlFacetBoxedVec<'a>,
+    SequenceBasedQualityControlFacetBoxedVec<'a>,
+)> {
+    let record_based_filtered = record_based_facets
+        .into_iter()
+        .filter(|x| x.name().eq_ignore_ascii_case(only))
+        .collect_vec();
+
+    let sequence_based_filtered = sequence_based_facets
+        .into_iter()
+        .filter(|x| x.name().eq_ignore_ascii_case(only))
+        .collect_vec();
+
+    let selected_facets_count = record_based_filtered.len() + sequence_based_filtered.len();
+
+    match selected_facets_count {
+        0 => bail!("No facets matched the specified `--only` flag: {}", only),
+        1 => Ok((record_based_filtered, sequence_based_filtered)),
+        _ => bail!(
+            "Too many facets matched the specified `--only` flag: {}. This is a \
+            very strange error, and it should be reported on the Github issues page.",
+            only
+        ),
+    }
+}
+
+/// Creates the span that a quality control facet's work is done within, so
+/// that anything the facet logs is attributed to it.
+pub fn facet_span(name: &'static str) -> Span {
+    info_span!("facet", name)
+}
+
+//===============//
+// Facet options //
+//===============//
+
+/// Default maximum template length tracked by the Template Length facet.
+pub const DEFAULT_TEMPLATE_LENGTH_MAX: usize = 1024;
+
+/// Default number of bins in each of the Quality Score facet's histograms
+/// (one for every quality score supported by the SAM specification).
+pub const DEFAULT_QUALITY_BINS: usize = record_based::quality_scores::MAX_SCORE + 1;
+
+/// Default maximum coverage tracked by the Coverage facet.
+pub const DEFAULT_COVERAGE_CAP: usize = 1024;
+
+/// Default size of the bins within which the Coverage facet computes the mean
+/// coverage.
+pub const DEFAULT_COVERAGE_BIN_SIZE: usize = 50_000;
+
+/// Default minimum mapping quality for a record to contribute to the usable
+/// depth computed by the Coverage facet.
+pub const DEFAULT_USABLE_DEPTH_MIN_MAPPING_QUALITY: u8 = 20;
+
+/// Default minimum base quality for a base to contribute to the usable depth
+/// computed by the Coverage facet (if `--min-base-quality` is not provided).
+pub const DEFAULT_USABLE_DEPTH_MIN_BASE_QUALITY: u8 = 20;
+
+/// A utility struct for passing options from the command line that configure
+/// the behavior of the quality control facets.
+#[derive(Debug)]
+pub struct FacetOptions {
+    /// Whether records marked as duplicate (`0x400`) should be counted. This is
+    /// threaded to every facet that tallies records so that duplicate handling
+    /// is consistent across both passes. The General facet is the exception:
+    /// it always sees duplicates, as counting them is part of its purpose.
+    pub count_duplicates: bool,
+
+    /// The maximum template length tracked by the Template Length facet. In
+    /// long-read mode, this is only the starting range for the histogram.
+    pub template_length_max: usize,
+
+    /// Whether long-read mode is enabled. In long-read mode, histograms for
+    /// lengths grow automatically and unpaired records contribute their read
+    /// length as their template length.
+    pub long_reads: bool,
+
+    /// Whether the Quality Scores facet should report the full position ×
+    /// quality score count matrix in addition to the per-position histograms.
+    pub quality_score_matrix: bool,
+
+    /// The number of bins in each of the Quality Score facet's histograms.
+    /// Quality scores beyond the last bin are tallied in the last bin.
+    pub quality_bins: NonZeroUsize,
+
+    /// The maximum coverage tracked by the Coverage facet. Positions with
+    /// coverage beyond this are tallied as `pileup_too_large_positions`.
+    pub coverage_cap: usize,
+
+    /// The size of the bins within which the Coverage facet computes the mean
+    /// coverage.
+    pub coverage_bin_size: NonZeroUsize,
+
+    /// The minimum base quality for a base to be counted by the Coverage,
+    /// Edits, and Allele Balance facets. Bases with a quality below this (as
+    /// well as deletions, which have no quality) are excluded. If not provided,
+    /// the Allele Balance facet uses its own default and the other facets count
+    /// every base.
+    pub min_base_quality: Option<u8>,
+
+    /// The thresholds for the usable depth, if the Coverage facet should
+    /// report it alongside the raw coverage.
+    pub usable_depth: Option<UsableDepthThresholds>,
+
+    /// The territories (in addition to the whole genome) over which the
+    /// Coverage facet summarizes the coverage.
+    pub coverage_territories: Vec<TerritoryDefinition>,
+
+    /// Where the gaps that the Coverage facet masks from its statistics are
+    /// read from, if gaps should be masked.
+    pub coverage_gaps: Option<GapSource>,
+
+    /// Whether the GC Content, Edits, and Coverage facets should additionally
+    /// report their metrics for each combination of read-in-pair and strand.
+    pub stratify_by_strand: bool,
+
+    /// How the Genomic Features facet should count records for each gene, if
+    /// per-gene counting is enabled.
+    pub gene_counting: Option<GeneCountingOptions>,
+
+    /// The reference sequences that are analyzed (e.g., those within a
+    /// shard), if not every sequence in the header is. The Genomic Features
+    /// facet only loads the features on these sequences.
+    pub analyzed_sequences: Option<HashSet<String>>,
+
+    /// The most memory (in bytes) that the features loaded from the GFF by
+    /// the Genomic Features facet may use, if capped.
+    pub features_memory_cap: Option<usize>,
+
+    /// The repeat annotation (a BED, interval list, or GFF file) read by the
+    /// Repeats facet, if one was provided. The Coverage facet additionally
+    /// summarizes the coverage within and outside of the repeats.
+    pub repeats: Option<PathBuf>,
+
+    /// Whether the Fragmentomics facet (fragment-length and end-motif metrics
+    /// for cell-free DNA) should be run.
+    pub fragmentomics: bool,
+
+    /// The sequencing technology that the facets are tailored to. The
+    /// long-read profile adds the Long Reads facet and drops the facets that
+    /// only make sense for short reads.
+    pub profile: QcProfile,
+
+    /// The FASTA of spike-in sequences (PhiX and/or ERCC) that the Spike-ins
+    /// facet screens unmapped reads against, if one was provided. Only the
+    /// kinds of spike-ins that are missing from the reference are screened.
+    pub spike_ins: Option<PathBuf>,
+
+    /// The FASTA of genomes (one species per description) that the
+    /// Contamination facet screens a sample of the unmapped reads against, if
+    /// one was provided.
+    pub contamination_screen: Option<PathBuf>,
+
+    /// The most examples of each kind of problem that the facets which flag
+    /// problems keep (see [`examples`]). With zero, no examples are kept.
+    pub examples: usize,
+}
+
+impl Default for FacetOptions {
+    fn default() -> Self {
+        Self {
+            count_duplicates: true,
+            template_length_max: DEFAULT_TEMPLATE_LENGTH_MAX,
+            long_reads: false,
+            quality_score_matrix: false,
+            quality_bins: NonZeroUsize::new(DEFAULT_QUALITY_BINS).unwrap(),
+            coverage_cap: DEFAULT_COVERAGE_CAP,
+            coverage_bin_size: NonZeroUsize::new(DEFAULT_COVERAGE_BIN_SIZE).unwrap(),
+            min_base_quality: None,
+            usable_depth: None,
+            coverage_territories: Vec::new(),
+            coverage_gaps: None,
+            stratify_by_strand: false,
+            gene_counting: None,
+            analyzed_sequences: None,
+            features_memory_cap: None,
+            repeats: None,
+            fragmentomics: false,
+            profile: QcProfile::default(),
+            spike_ins: None,
+            contamination_screen: None,
+            examples: 0,
+        }
     }
+}
+
+//===============//
+// Facet catalog //
+//===============//
+
+/// The pass of the `ngs qc` subcommand in which a quality control facet runs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FacetPass {
+    /// Runs in the first pass, which processes every record in the file.
+    RecordBased,
 
-    // (3) If `only_facet` is provided, we need to (a) filter out all of the
-    // quality control facets except the one that is provided, (b) error out if
-    // no quality control facets match the provided argument, and (c) return
-    // with the limited list otherwise.
-
-    if let Some(only) = only_facet {
-        let record_based_filtered = record_based_facets
-            .into_iter()
-            .filter(|x| x.name().eq_ignore_ascii_case(&only))
-            .collect_vec();
-
-        let sequence_based_filtered = sequence_based_facets
-            .into_iter()
-            .filter(|x| x.name().eq_ignore_ascii_case(&only))
-            .collect_vec();
-
-        let selected_facets_count = record_based_filtered.len() + sequence_based_filtered.len();
-
-        match selected_facets_count {
-            0 => bail!("No facets matched the specified `--only` flag: {}", only),
-            1 => return Ok((record_based_filtered, sequence_based_filtered)),
-            _ => bail!(
-                "Too many facets matched the specified `--only` flag: {}. This is a \
-                very strange error, and it should be reported on the Github issues page.",
-                only
-            ),
+    /// Runs in the second pass, which queries every reference sequence.
+    SequenceBased,
+
+    /// Runs over the container structure of CRAM files, without decoding any
+    /// records.
+    ContainerBased,
+}
+
+impl std::fmt::Display for FacetPass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FacetPass::RecordBased => write!(f, "Record-based"),
+            FacetPass::SequenceBased => write!(f, "Sequence-based"),
+            FacetPass::ContainerBased => write!(f, "Container-based"),
         }
     }
+}
+
+/// A description of a quality control facet that is supported by `ngs qc`.
+#[derive(Debug)]
+pub struct FacetDescription {
+    /// Name of the quality control facet (as used with `--only`).
+    pub name: &'static str,
+
+    /// The pass in which this quality control facet runs.
+    pub pass: FacetPass,
 
-    Ok((record_based_facets, sequence_based_facets))
+    /// Computational load of the quality control facet.
+    pub computational_load: ComputationalLoad,
+
+    /// Optional inputs which must be provided for the facet to be loaded.
+    pub required_inputs: &'static [&'static str],
+}
+
+/// Gets a description of all of the quality control facets supported by `ngs
+/// qc`. This catalog is checked against the facets that [`get_qc_facets`]
+/// loads in the tests below, so the two cannot drift apart unnoticed.
+pub fn get_all_qc_facets() -> Vec<FacetDescription> {
+    let describe = |name, pass, computational_load, required_inputs| FacetDescription {
+        name,
+        pass,
+        computational_load,
+        required_inputs,
+    };
+
+    vec![
+        describe(
+            "General",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &[],
+        ),
+        describe(
+            "Template Length",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &[],
+        ),
+        describe(
+            "GC Content",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &[],
+        ),
+        describe(
+            "Quality Score",
+            FacetPass::RecordBased,
+            ComputationalLoad::Moderate,
+            &[],
+        ),
+        describe(
+            "Mate Consistency",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &[],
+        ),
+        describe(
+            "Read Name Collisions",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &[],
+        ),
+        describe(
+            "Tiles",
+            FacetPass::RecordBased,
+            ComputationalLoad::Moderate,
+            &[],
+        ),
+        describe(
+            "Read Length",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &[],
+        ),
+        describe(
+            "Adapter Content",
+            FacetPass::RecordBased,
+            ComputationalLoad::Moderate,
+            &[],
+        ),
+        describe(
+            "Duplication",
+            FacetPass::RecordBased,
+            ComputationalLoad::Moderate,
+            &[],
+        ),
+        describe(
+            "Alignment Summary",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &[],
+        ),
+        describe(
+            "Mapping Quality",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &[],
+        ),
+        describe(
+            "Methylation",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &[],
+        ),
+        describe(
+            "Single Cell",
+            FacetPass::RecordBased,
+            ComputationalLoad::Moderate,
+            &[],
+        ),
+        describe(
+            "Unmapped Reads",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &[],
+        ),
+        describe(
+            "Read Groups",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &[],
+        ),
+        describe(
+            "Genomic Features",
+            FacetPass::RecordBased,
+            ComputationalLoad::Moderate,
+            &["--features-gff"],
+        ),
+        describe(
+            "Repeats",
+            FacetPass::RecordBased,
+            ComputationalLoad::Moderate,
+            &["--repeats"],
+        ),
+        describe(
+            "Fragmentomics",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &["--fragmentomics"],
+        ),
+        describe(
+            "Long Reads",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &["--profile long-read"],
+        ),
+        describe(
+            "Species",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &["hybrid reference genome"],
+        ),
+        describe(
+            "Spike-ins",
+            FacetPass::RecordBased,
+            ComputationalLoad::Light,
+            &["--spike-ins"],
+        ),
+        describe(
+            "Contamination",
+            FacetPass::RecordBased,
+            ComputationalLoad::Moderate,
+            &["--contamination-screen"],
+        ),
+        describe(
+            "Coverage",
+            FacetPass::SequenceBased,
+            ComputationalLoad::Moderate,
+            &[],
+        ),
+        describe(
+            "Edit",
+            FacetPass::SequenceBased,
+            ComputationalLoad::Heavy,
+            &["--reference-fasta"],
+        ),
+        describe(
+            "Reference GC Content",
+            FacetPass::SequenceBased,
+            ComputationalLoad::Moderate,
+            &["--reference-fasta"],
+        ),
+        describe(
+            "OxoG",
+            FacetPass::SequenceBased,
+            ComputationalLoad::Moderate,
+            &["--reference-fasta"],
+        ),
+        describe(
+            "Sequencing Artifacts",
+            FacetPass::SequenceBased,
+            ComputationalLoad::Moderate,
+            &["--reference-fasta"],
+        ),
+        describe(
+            "Allele Balance",
+            FacetPass::SequenceBased,
+            ComputationalLoad::Moderate,
+            &["--sites-vcf"],
+        ),
+        describe(
+            "CRAM",
+            FacetPass::ContainerBased,
+            ComputationalLoad::Light,
+            &["CRAM input"],
+        ),
+    ]
 }
 
 //====================//
@@ -131,7 +746,7 @@ pub fn get_qc_facets<'a>(
 //====================//
 
 /// An indicator of how computationally heavy an analysis is.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ComputationalLoad {
     /// Less than 1 second per million records processed.
     Light,
@@ -174,6 +789,13 @@ pub trait RecordBasedQualityControlFacet {
     /// Adds the results of this quality control facet to the global
     /// [`results::Results`] object for writing to a file.
     fn aggregate(&self, results: &mut results::Results);
+
+    /// Gets the examples of the records that this quality control facet
+    /// flagged as problems (see [`examples`]). Facets that do not flag
+    /// problems have none.
+    fn examples(&self) -> Vec<Example> {
+        Vec::new()
+    }
 }
 
 //======================================//
@@ -205,11 +827,7 @@ pub trait SequenceBasedQualityControlFacet {
     fn setup(&mut self, sequence: &Map<ReferenceSequence>) -> anyhow::Result<()>;
 
     /// Processes a sequence for a quality control facet.
-    fn process<'b>(
-        &mut self,
-        seq: &'b Map<ReferenceSequence>,
-        record: &Record,
-    ) -> anyhow::Result<()>;
+    fn process(&mut self, seq: &Map<ReferenceSequence>, record: &Record) -> anyhow::Result<()>;
 
     /// Tears down any machinery that was built up for this sequence within the
     /// quality control facet.
@@ -218,6 +836,13 @@ pub trait SequenceBasedQualityControlFacet {
     /// Adds the results of this quality control facet to the global
     /// [`results::Results`] object for writing to a file.
     fn aggregate(&mut self, results: &mut results::Results);
+
+    /// Gets the examples of the records that this quality control facet
+    /// flagged as problems (see [`examples`]). Facets that do not flag
+    /// problems have none.
+    fn examples(&self) -> Vec<Example> {
+        Vec::new()
+    }
 }
 
 #[cfg(test)]
@@ -234,15 +859,90 @@ mod tests {
             None,
             None,
             None,
+            None,
             Rc::new(get_reference_genome("GRCh38_no_alt_AnalysisSet").unwrap()),
             None,
+            &FacetOptions::default(),
         )
         .unwrap();
 
-        assert_eq!(record_based.len(), 4);
+        assert_eq!(record_based.len(), 15);
         assert_eq!(sequence_based.len(), 1);
     }
 
+    #[test]
+    pub fn it_tailors_the_facets_to_the_long_read_profile() {
+        let options = FacetOptions {
+            profile: "long-read".parse().unwrap(),
+            ..Default::default()
+        };
+        let (record_based, _) = get_qc_facets(
+            None,
+            None,
+            None,
+            None,
+            None,
+            Rc::new(get_reference_genome("GRCh38_no_alt_AnalysisSet").unwrap()),
+            None,
+            &options,
+        )
+        .unwrap();
+
+        let names = record_based.iter().map(|f| f.name()).collect::<Vec<_>>();
+        assert_eq!(names.len(), 12);
+        assert!(names.contains(&"Long Reads"));
+        assert!(!names.contains(&"Template Length"));
+        assert!("short-read".parse::<QcProfile>().is_ok());
+        assert!("nanopore".parse::<QcProfile>().is_err());
+    }
+
+    #[test]
+    pub fn it_only_loads_alignment_independent_facets_for_fastq_files() {
+        let facets = get_fastq_qc_facets(None, &FacetOptions::default()).unwrap();
+        assert_eq!(facets.len(), 6);
+        assert!(!facets.iter().any(|facet| facet.name() == "General"));
+
+        let facets = get_fastq_qc_facets(
+            Some(String::from("adapter content")),
+            &FacetOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(facets.len(), 1);
+    }
+
+    #[test]
+    pub fn it_describes_every_loaded_facet_in_the_catalog() {
+        let header = Header::default();
+        let (record_based, sequence_based) = get_qc_facets(
+            None,
+            None,
+            Some(&header),
+            None,
+            None,
+            Rc::new(get_reference_genome("GRCh38_no_alt_AnalysisSet").unwrap()),
+            None,
+            &FacetOptions::default(),
+        )
+        .unwrap();
+
+        let catalog = get_all_qc_facets();
+        let loaded = record_based
+            .iter()
+            .map(|f| (f.name(), FacetPass::RecordBased, f.computational_load()))
+            .chain(
+                sequence_based
+                    .iter()
+                    .map(|f| (f.name(), FacetPass::SequenceBased, f.computational_load())),
+            );
+
+        for (name, pass, load) in loaded {
+            let description = catalog.iter().find(|d| d.name == name).unwrap();
+            assert_eq!(description.pass, pass);
+            assert_eq!(description.computational_load, load);
+            assert!(description.required_inputs.is_empty());
+        }
+    }
+
     #[test]
     pub fn it_returns_the_correct_number_of_facets_when_only_is_specified() {
         let (record_based, sequence_based) = get_qc_facets(
@@ -250,13 +950,15 @@ mod tests {
             None,
             None,
             None,
+            None,
             Rc::new(get_reference_genome("GRCh38_no_alt_AnalysisSet").unwrap()),
             Some(String::from("GC Content")),
+            &FacetOptions::default(),
         )
         .unwrap();
 
         assert_eq!(record_based.len(), 1);
         assert_eq!(sequence_based.len(), 0);
-        assert!(record_based.get(0).unwrap().name() == "GC Content");
+        assert!(record_based.first().unwrap().name() == "GC Content");
     }
 }
@@ -0,0 +1,363 @@
+//! Comparison of `ngs qc` results against a baseline.
+//!
+//! When a pipeline is re-run, the flag counts of its output (the counts that
+//! `samtools flagstat` reports, which are computed by the General facet under
+//! `general.records`) are expected to match those of the original run. With
+//! `--diff`, each count within the results of a previous run (the baseline) is
+//! compared to the same count within the new results, and any count that has
+//! drifted by more than its tolerance is reported.
+//!
+//! Tolerances are percentages of the baseline count. They may be set for
+//! every count (`--diff-tolerance 0.5`) or for a single count, named by its
+//! path within `general.records` (`--diff-tolerance designation.secondary=5`).
+//! By default, the counts must match exactly.
+
+use std::{collections::BTreeMap, fs, path::Path, str::FromStr};
+
+use anyhow::{bail, Context};
+use serde_json::Value;
+
+use super::{gate::Status, results::Results};
+
+/// The path to the flag counts within the results.
+const COUNTS: &str = "general.records";
+
+//============//
+// Tolerances //
+//============//
+
+/// A tolerance provided with `--diff-tolerance`, either for every count or
+/// for a single count.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tolerance {
+    /// The count the tolerance applies to (relative to `general.records`), or
+    /// `None` if it applies to every count.
+    pub metric: Option<String>,
+
+    /// The tolerance, as a percentage of the baseline count.
+    pub pct: f64,
+}
+
+impl FromStr for Tolerance {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (metric, pct) = match s.rsplit_once('=') {
+            Some((metric, pct)) => (Some(metric.to_string()), pct),
+            None => (None, s),
+        };
+
+        let pct: f64 = pct
+            .parse()
+            .with_context(|| format!("Invalid tolerance: {}", s))?;
+        if !pct.is_finite() || pct < 0.0 {
+            bail!("Tolerance must be a non-negative percentage: {}", s);
+        }
+
+        Ok(Self { metric, pct })
+    }
+}
+
+/// The tolerances for each count.
+#[derive(Clone, Debug, Default)]
+pub struct Tolerances {
+    /// The tolerance for counts without their own tolerance.
+    default: f64,
+
+    /// The tolerances for individual counts, keyed by their path relative to
+    /// `general.records`.
+    metrics: BTreeMap<String, f64>,
+}
+
+impl Tolerances {
+    /// Collects the tolerances. For any count, the last tolerance provided
+    /// for it takes precedence.
+    pub fn new(tolerances: impl IntoIterator<Item = Tolerance>) -> Self {
+        let mut result = Self::default();
+
+        for tolerance in tolerances {
+            match tolerance.metric {
+                Some(metric) => {
+                    result.metrics.insert(metric, tolerance.pct);
+                }
+                None => result.default = tolerance.pct,
+            }
+        }
+
+        result
+    }
+
+    /// The tolerance for a count (relative to `general.records`).
+    fn get(&self, metric: &str) -> f64 {
+        self.metrics.get(metric).copied().unwrap_or(self.default)
+    }
+}
+
+//==========//
+// Baseline //
+//==========//
+
+/// Collects the numeric leaves of a JSON value, keyed by their dotted path.
+fn flatten(prefix: &str, value: &Value, leaves: &mut BTreeMap<String, f64>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = match prefix {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", prefix, key),
+                };
+                flatten(&path, value, leaves);
+            }
+        }
+        _ => {
+            if let Some(n) = value.as_f64() {
+                leaves.insert(prefix.to_string(), n);
+            }
+        }
+    }
+}
+
+/// Gets the flag counts within a results document, keyed by their path
+/// relative to `general.records`.
+fn counts(document: &Value) -> Option<BTreeMap<String, f64>> {
+    let records = COUNTS
+        .split('.')
+        .try_fold(document, |value, key| value.get(key))?;
+
+    let mut counts = BTreeMap::new();
+    flatten("", records, &mut counts);
+    Some(counts)
+}
+
+/// The flag counts of a previous run of `ngs qc`.
+#[derive(Clone, Debug)]
+pub struct Baseline {
+    /// The flag counts, keyed by their path relative to `general.records`.
+    counts: BTreeMap<String, f64>,
+}
+
+impl Baseline {
+    /// Reads the baseline from a results file.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read baseline file: {}", path.display()))?;
+        let document: Value = serde_json::from_str(&contents)
+            .with_context(|| format!("Could not parse baseline file: {}", path.display()))?;
+
+        match counts(&document) {
+            Some(counts) if !counts.is_empty() => Ok(Self { counts }),
+            _ => bail!(
+                "The baseline file does not contain any flag counts ({}): {}",
+                COUNTS,
+                path.display()
+            ),
+        }
+    }
+
+    /// Checks that every count with its own tolerance is in the baseline, so
+    /// that a misspelled count is not silently ignored.
+    pub fn validate(&self, tolerances: &Tolerances) -> anyhow::Result<()> {
+        for metric in tolerances.metrics.keys() {
+            if !self.counts.contains_key(metric) {
+                bail!(
+                    "Tolerance provided for an unknown count: {} (expected one of {})",
+                    metric,
+                    self.counts
+                        .keys()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compares the flag counts of the results against the baseline.
+    pub fn compare(
+        &self,
+        name: &str,
+        results: &Results,
+        tolerances: &Tolerances,
+    ) -> anyhow::Result<Comparison> {
+        let document = serde_json::to_value(results)?;
+        let current = counts(&document).unwrap_or_default();
+
+        let deltas = self
+            .counts
+            .iter()
+            .map(|(metric, &baseline)| {
+                let tolerance_pct = tolerances.get(metric);
+                let current = current.get(metric).copied();
+                let within_tolerance = current.is_some_and(|current| {
+                    (current - baseline).abs() <= baseline.abs() * tolerance_pct / 100.0
+                });
+
+                Delta {
+                    metric: format!("{}.{}", COUNTS, metric),
+                    baseline,
+                    current,
+                    tolerance_pct,
+                    within_tolerance,
+                }
+            })
+            .collect();
+
+        Ok(Comparison {
+            name: name.to_string(),
+            deltas,
+        })
+    }
+}
+
+//============//
+// Comparison //
+//============//
+
+/// The difference between a single count and its baseline.
+#[derive(Clone, Debug)]
+pub struct Delta {
+    /// The dotted path to the count.
+    pub metric: String,
+
+    /// The count within the baseline.
+    pub baseline: f64,
+
+    /// The count within the results, if it was found.
+    pub current: Option<f64>,
+
+    /// The tolerance, as a percentage of the baseline count.
+    pub tolerance_pct: f64,
+
+    /// Whether the count is within its tolerance of the baseline.
+    pub within_tolerance: bool,
+}
+
+impl Delta {
+    /// Describes the difference briefly (e.g., `general.records.duplicate:
+    /// 110 -> 112 (+2, +1.82%)`).
+    pub fn describe(&self) -> String {
+        match self.current {
+            None => format!("{} missing (baseline {})", self.metric, self.baseline),
+            Some(current) => {
+                let difference = current - self.baseline;
+                let pct = if self.baseline == 0.0 {
+                    String::new()
+                } else {
+                    format!(", {:+.2}%", difference / self.baseline * 100.0)
+                };
+                format!(
+                    "{}: {} -> {} ({:+}{})",
+                    self.metric, self.baseline, current, difference, pct
+                )
+            }
+        }
+    }
+}
+
+/// The result of comparing the flag counts of a results document against the
+/// baseline.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    /// The name of the results document (i.e., its output prefix).
+    pub name: String,
+
+    /// The difference of each count in the baseline.
+    pub deltas: Vec<Delta>,
+}
+
+impl Comparison {
+    /// The overall status of the comparison: counts that drifted fail, and
+    /// counts that were not computed are missing.
+    pub fn status(&self) -> Status {
+        let drifted = || self.deltas.iter().filter(|delta| !delta.within_tolerance);
+
+        if drifted().any(|delta| delta.current.is_some()) {
+            Status::Fail
+        } else if drifted().next().is_some() {
+            Status::Missing
+        } else {
+            Status::Pass
+        }
+    }
+
+    /// Each count that drifted from (or is missing relative to) the
+    /// baseline, described briefly.
+    pub fn problems(&self) -> Vec<String> {
+        self.deltas
+            .iter()
+            .filter(|delta| !delta.within_tolerance)
+            .map(Delta::describe)
+            .collect()
+    }
+
+    /// The one-line summary printed for each results document.
+    pub fn summary(&self) -> String {
+        match self.status() {
+            Status::Pass => format!(
+                "PASS {}: {} of {} counts match the baseline",
+                self.name,
+                self.deltas.len(),
+                self.deltas.len()
+            ),
+            _ => format!("FAIL {}: {}", self.name, self.problems().join("; ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::qc::record_based::general::metrics::GeneralMetrics;
+
+    use super::*;
+
+    #[test]
+    pub fn it_reports_counts_that_drifted_from_the_baseline() -> anyhow::Result<()> {
+        let results = Results {
+            general: Some(GeneralMetrics::default()),
+            ..Default::default()
+        };
+
+        let mut document = serde_json::to_value(&results)?;
+        document["general"]["records"]["total"] = Value::from(100);
+        document["general"]["records"]["duplicate"] = Value::from(10);
+        let baseline = Baseline {
+            counts: counts(&document).unwrap(),
+        };
+
+        let comparison = baseline.compare("sample", &results, &Tolerances::default())?;
+        assert_eq!(comparison.status(), Status::Fail);
+        assert_eq!(
+            comparison.problems(),
+            [
+                "general.records.duplicate: 10 -> 0 (-10, -100.00%)",
+                "general.records.total: 100 -> 0 (-100, -100.00%)"
+            ]
+        );
+
+        // The tolerance of a single count takes precedence over the default.
+        let tolerances = Tolerances::new(["100".parse()?, "total=99".parse()?]);
+        let comparison = baseline.compare("sample", &results, &tolerances)?;
+        assert_eq!(comparison.problems().len(), 1);
+
+        // Counts that were not computed are missing.
+        let comparison = baseline.compare("sample", &Results::default(), &tolerances)?;
+        assert_eq!(comparison.status(), Status::Missing);
+
+        let unchanged = Baseline {
+            counts: counts(&serde_json::to_value(&results)?).unwrap(),
+        };
+        let comparison = unchanged.compare("sample", &results, &Tolerances::default())?;
+        assert_eq!(comparison.status(), Status::Pass);
+
+        assert!(baseline.validate(&tolerances).is_ok());
+        let misspelled = Tolerances::new(["totl=1".parse()?]);
+        assert!(baseline.validate(&misspelled).is_err());
+
+        assert!("-1".parse::<Tolerance>().is_err());
+        assert!("total=x".parse::<Tolerance>().is_err());
+
+        Ok(())
+    }
+}
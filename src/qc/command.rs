@@ -1,40 +1,71 @@
 //! Functionality related to the `ngs qc` command itself.
 
-use std::{fs::File, path::PathBuf, rc::Rc};
+use std::{
+    fs::File,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use anyhow::{bail, Context};
-use clap::Args;
+use clap::{builder::PossibleValuesParser, Args, Subcommand};
 use noodles::bam::{self as bam, bai};
 use noodles::core::{Position, Region};
-use num_format::{Locale, ToFormattedString};
-use tracing::{debug, info};
-
-use crate::qc::get_qc_facets;
+use noodles::sam::alignment::Record;
+use tracing::{debug, info, info_span, warn};
+
+use crate::qc::{
+    get_fastq_qc_facets, get_qc_facets,
+    profile::QcProfile,
+    sequence_based::coverage::{
+        territories::{GapSource, TerritoryDefinition},
+        UsableDepthThresholds,
+    },
+    FacetOptions, DEFAULT_COVERAGE_BIN_SIZE, DEFAULT_COVERAGE_CAP, DEFAULT_QUALITY_BINS,
+    DEFAULT_TEMPLATE_LENGTH_MAX, DEFAULT_USABLE_DEPTH_MIN_BASE_QUALITY,
+    DEFAULT_USABLE_DEPTH_MIN_MAPPING_QUALITY,
+};
 use crate::{
-    qc::results::Results,
+    qc::{
+        baseline::{Baseline, Tolerance, Tolerances},
+        container_based::cram::CramFacet,
+        estimate::{choose_sequence, EstimateMetrics, DEFAULT_ESTIMATE_RECORDS},
+        examples,
+        facet_set::FacetSet,
+        fastq_pairs::check_pairs,
+        gate::{GateFailure, Status, Thresholds},
+        histograms::{self, HistogramFormat},
+        prefetch::{Prefetcher, DEFAULT_BATCHES_IN_FLIGHT, DEFAULT_BATCH_SIZE},
+        results::{Progress, Results},
+        runtime::{FacetTimer, RuntimeMetrics},
+        shard::Shard,
+        split::{file_name_safe, Router, LANE_FACETS},
+    },
     utils::{
-        formats::sam::parse_header,
-        genome::{get_all_sequences, get_reference_genome, ReferenceGenome},
+        args::{parse_num_records, NumberOfRecords},
+        checksum::{self, Checksum},
+        display,
+        filter::ReadFilter,
+        formats::{
+            alignment::{AlignmentFormat, AlignmentIndex, AlignmentReader},
+            bai::{self as stale, StaleIndexPolicy},
+            bam::{for_each_query_record, sequential_reader},
+            detect::detect,
+            fastq::to_unmapped_record,
+        },
+        genome::{get_reference_genome, normalize_sequence_name, ReferenceGenome},
+        merge::Merge,
+        metrics, outputs,
+        progress::ProgressEvent,
+        provenance::{self, Provenance},
     },
 };
 
-use super::record_based::features::FeatureNames;
-
-//====================================//
-// Command line parsing utility types //
-//====================================//
-
-/// Utility enum to designate whether we are reviewing all records in the file
-/// or just some of them. TODO: this may be generally useful, so we may want to
-/// pull this out into its own utility module.
-pub enum NumberOfRecords {
-    /// Designates that we should review _all_ of the records in the file.
-    All,
-
-    /// Designates that we should review _some_ of the records in the file. The
-    /// exact count of records is stored in the `usize`.
-    Some(usize),
-}
+use super::record_based::features::{
+    counts::{GeneCountingOptions, DEFAULT_GENE_ID_ATTRIBUTE},
+    loader::BYTES_PER_MIB,
+    FeatureNames,
+};
 
 //========================//
 // Command line arguments //
@@ -42,21 +73,33 @@ pub enum NumberOfRecords {
 
 /// Clap arguments for the `ngs qc` subcommand.
 #[derive(Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct QcArgs {
-    /// Source BAM file.
-    #[arg(value_name = "BAM")]
-    src: PathBuf,
-
-    /// Supported reference genome used as the basis for analysis.
-    reference_genome: String,
+    /// Subcommands that operate on the results of `ngs qc`.
+    #[command(subcommand)]
+    pub subcommand: Option<QcSubcommand>,
+
+    /// Source SAM, BAM, or CRAM file (detected by its contents). The records
+    /// of a CRAM file are decoded against the reference FASTA: without one,
+    /// only the CRAM facet is run. SAM files are not indexed, so only the
+    /// first pass facets are run for them.
+    #[arg(value_name = "SAM/BAM/CRAM", required = true)]
+    src: Option<PathBuf>,
+
+    /// Supported reference genome used as the basis for analysis. Hybrid
+    /// genomes (e.g., for xenografts) are named by their components joined
+    /// with `+`, each optionally labeled by its species (e.g.,
+    /// `human_GRCh38+mouse_GRCm39`).
+    #[arg(required = true)]
+    reference_genome: Option<String>,
 
     /// Features GFF file (some metrics only supported if present).
     #[arg(short = 'f', long, value_name = "PATH")]
     features_gff: Option<PathBuf>,
 
-    /// Number of records to process in the first pass.
-    #[arg(short = 'n', long, value_name = "USIZE")]
-    num_records: Option<usize>,
+    /// Number of records to process in the first pass. Defaults to all records.
+    #[arg(short = 'n', long, value_name = "USIZE", value_parser = parse_num_records)]
+    num_records: Option<NonZeroUsize>,
 
     /// Directory to output files to. Defaults to current working directory.
     #[arg(short = 'o', long, value_name = "PATH")]
@@ -67,14 +110,253 @@ pub struct QcArgs {
     #[arg(short = 'p', long, value_name = "STRING")]
     output_prefix: Option<String>,
 
-    /// Reference FASTA file (some metrics only supported if present).
+    /// Reference FASTA file (some metrics only supported if present). Required
+    /// to decode the records of CRAM files.
     #[arg(short = 'r', long, value_name = "PATH")]
     reference_fasta: Option<PathBuf>,
 
+    /// Repeat annotation (a BED, interval list, or GFF file) used to report the
+    /// reads, bases, and coverage within each class of repeats (some metrics
+    /// only supported if present).
+    #[arg(long, value_name = "PATH")]
+    repeats: Option<PathBuf>,
+
+    /// FASTA of spike-in sequences (PhiX and/or ERCC). Unmapped reads are
+    /// screened against those spike-ins that the reference does not contain,
+    /// and the fraction of reads from each spike-in is reported.
+    #[arg(long, value_name = "PATH")]
+    spike_ins: Option<PathBuf>,
+
+    /// FASTA of genomes to screen a sample of the unmapped reads against for
+    /// contamination (e.g., E. coli, yeast, and mycoplasma), where the
+    /// description of each sequence names its species. The species that hit
+    /// the most k-mers are reported as likely contaminants.
+    #[arg(long, value_name = "PATH")]
+    contamination_screen: Option<PathBuf>,
+
+    /// VCF of known sites (e.g., common SNPs) at which allele balance is
+    /// computed (some metrics only supported if present).
+    #[arg(long, value_name = "PATH")]
+    sites_vcf: Option<PathBuf>,
+
     /// Only process one QC facet (specify the name of the facet).
     #[arg(long = "only", value_name = "FACET")]
     only_facet: Option<String>,
 
+    /// Only process a shard of the reference sequences, specified as `i/N`
+    /// (e.g., `1/4`). The partial results can be combined with `ngs qc merge`.
+    #[arg(long, value_name = "i/N")]
+    shard: Option<Shard>,
+
+    /// Quick preview mode: only processes the first records in the file (one
+    /// million, unless `--num-records` is provided) and the smallest autosome,
+    /// then extrapolates the headline metrics with confidence intervals.
+    #[arg(long, conflicts_with = "shard")]
+    estimate: bool,
+
+    /// Reads the first pass through an asynchronous I/O path, which buffers
+    /// reads ahead of the facets so that I/O overlaps with computation. This
+    /// requires `ngs` to be built with the `async-io` feature.
+    #[arg(long, conflicts_with = "shard")]
+    async_io: bool,
+
+    /// Disables reading the records of the second pass ahead of the facets on
+    /// a background thread. Prefetching hides the latency of seeking between
+    /// sequences (e.g., on network file systems) at the cost of a second file
+    /// handle and a bounded number of buffered records.
+    #[arg(long)]
+    no_prefetch: bool,
+
+    /// Allocates a new record for every record read, rather than reusing
+    /// record buffers across iterations. This is intended for debugging.
+    #[arg(long)]
+    no_reuse: bool,
+
+    /// What to do when the BAM index appears to be stale (i.e., it disagrees
+    /// with the header, size, modification time, or number of records of the
+    /// BAM file): warn and use it anyway, fail, or skip the checks entirely.
+    #[arg(long, value_name = "POLICY", default_value = "warn")]
+    #[arg(value_parser = PossibleValuesParser::new(["warn", "fail", "ignore"]))]
+    stale_index: String,
+
+    /// Only records matching this filter expression are seen by the facets
+    /// (e.g., "mapq>=20 && !secondary && rg=='L1'"). Expressions combine flags
+    /// (e.g., `duplicate`) and comparisons of `mapq`, `tlen`, `len`, `start`,
+    /// `rg`, `ref`, and `name` with `&&`, `||`, `!`, and parentheses.
+    #[arg(long, value_name = "EXPRESSION")]
+    filter: Option<ReadFilter>,
+
+    /// Produces a separate results document for each sample (as specified by
+    /// the `SM` field of the read groups) or for each flowcell lane (as
+    /// specified by the Illumina read names), written to `<prefix>.<split>.*`.
+    /// When splitting by lane, only the Quality Score, GC Content, and
+    /// Duplication facets are run.
+    #[arg(long, value_name = "STRATUM")]
+    #[arg(value_parser = PossibleValuesParser::new(["sample", "lane"]))]
+    split_by: Option<String>,
+
+    /// JSON file of the minimum and/or maximum values of metrics within the
+    /// results, keyed by the dotted path to the metric (e.g.,
+    /// `{"general.summary.mapped_pct": {"min": 90}}`). Metrics outside of
+    /// their thresholds are reported as warnings.
+    #[arg(long, value_name = "JSON")]
+    thresholds: Option<PathBuf>,
+
+    /// Prints a one-line PASS/FAIL summary of the `--thresholds` for each
+    /// results document to stdout, exiting with 3 if any metric is outside of
+    /// its thresholds or 4 if any metric was not computed.
+    #[arg(long, requires = "thresholds")]
+    strict_qc: bool,
+
+    /// Results file of a previous run to compare the flag counts
+    /// (`general.records`) against. A one-line PASS/FAIL summary for each
+    /// results document is logged (to stderr), and `ngs` exits with 3 if any
+    /// count drifted from the baseline by more than its tolerance or 4 if any
+    /// count was not computed.
+    #[arg(long, value_name = "JSON")]
+    diff: Option<PathBuf>,
+
+    /// Tolerance for `--diff`, as a percentage of the baseline count: either
+    /// for every count (`PCT`) or for a single count named by its path within
+    /// `general.records` (`METRIC=PCT`, e.g., `designation.secondary=5`). May
+    /// be repeated. By default, the counts must match exactly.
+    #[arg(long, value_name = "[METRIC=]PCT", requires = "diff")]
+    diff_tolerance: Vec<String>,
+
+    /// Whether records marked as duplicate (`0x400`) should be counted by the
+    /// quality control facets. This is applied consistently across both
+    /// passes. The Coverage facet reports the raw and deduplicated mean
+    /// coverage either way.
+    #[arg(long, value_name = "yes/no", default_value = "yes")]
+    #[arg(value_parser = PossibleValuesParser::new(["yes", "no"]))]
+    count_duplicates: String,
+
+    /// Maximum template length tracked by the Template Length facet. Records
+    /// with larger template lengths are tallied as ignored.
+    #[arg(long, value_name = "USIZE", default_value_t = DEFAULT_TEMPLATE_LENGTH_MAX)]
+    template_length_max: usize,
+
+    /// Enables long-read mode (e.g., for PacBio or ONT data). Length
+    /// histograms grow automatically and unpaired records contribute their
+    /// read length to the template length distribution.
+    #[arg(long)]
+    long_reads: bool,
+
+    /// Tailors the facets to the sequencing technology. The long-read profile
+    /// enables long-read mode (as with `--long-reads`), adds the Long Reads
+    /// facet (alignment identity, supplementary-alignment chains, and
+    /// per-read qualities), and drops the facets that only make sense for
+    /// short reads (Template Length, Mate Consistency, Tiles, and Adapter
+    /// Content).
+    #[arg(long, value_name = "PROFILE", default_value = "short-read")]
+    #[arg(value_parser = PossibleValuesParser::new(["short-read", "long-read"]))]
+    profile: String,
+
+    /// Reports the full position × quality score count matrix (run-length
+    /// encoded) from the Quality Score facet, which is useful for producing
+    /// heatmaps.
+    #[arg(long)]
+    quality_score_matrix: bool,
+
+    /// Number of bins in each of the Quality Score facet's histograms. Quality
+    /// scores beyond the last bin are tallied within the last bin.
+    #[arg(long, value_name = "USIZE", default_value_t = NonZeroUsize::new(DEFAULT_QUALITY_BINS).unwrap())]
+    quality_bins: NonZeroUsize,
+
+    /// Maximum coverage tracked by the Coverage facet. Positions with greater
+    /// coverage are excluded from the coverage distributions and tallied as
+    /// ignored.
+    #[arg(long, value_name = "USIZE", default_value_t = DEFAULT_COVERAGE_CAP)]
+    coverage_cap: usize,
+
+    /// Size of the bins (in base pairs) within which the Coverage facet
+    /// computes the mean coverage.
+    #[arg(long, value_name = "USIZE", default_value_t = NonZeroUsize::new(DEFAULT_COVERAGE_BIN_SIZE).unwrap())]
+    coverage_bin_size: NonZeroUsize,
+
+    /// Additionally summarizes the coverage computed by the Coverage facet
+    /// over a territory: the autosomes, or the primary assembly without the
+    /// gaps (runs of `N`) in the reference FASTA (which requires
+    /// `--reference-fasta`). Can be provided more than once.
+    #[arg(long, value_name = "TERRITORY")]
+    #[arg(value_parser = PossibleValuesParser::new(["autosomes", "primary-assembly-without-gaps"]))]
+    coverage_territory: Vec<String>,
+
+    /// Additionally summarizes the coverage computed by the Coverage facet
+    /// over the intervals within a BED file or interval list, reported under
+    /// the name of the file. Can be provided more than once.
+    #[arg(long, value_name = "PATH")]
+    coverage_territory_intervals: Vec<PathBuf>,
+
+    /// Masks the gaps (runs of `N`) in the reference FASTA from the coverage
+    /// statistics computed by the Coverage facet, so that the means and
+    /// medians are computed over the same territory as Picard's
+    /// `CollectWgsMetrics`. Requires `--reference-fasta`.
+    #[arg(long, conflicts_with = "gap_intervals")]
+    mask_gaps: bool,
+
+    /// Masks the intervals within a BED file or interval list (e.g., the gap
+    /// table for the reference genome) from the coverage statistics computed
+    /// by the Coverage facet, as with `--mask-gaps`.
+    #[arg(long, value_name = "PATH")]
+    gap_intervals: Option<PathBuf>,
+
+    /// Additionally writes the mean coverage for each bin computed by the
+    /// Coverage facet to `<prefix>.coverage_bins.bed`.
+    #[arg(long)]
+    coverage_bed: bool,
+
+    /// Additionally writes up to this many examples of each kind of problem
+    /// flagged by the facets (e.g., nonsensical records, inconsistent mates,
+    /// or invalid methylation tags) to `<prefix>.examples`, with the read name
+    /// and location of each offending record.
+    #[arg(long, value_name = "N")]
+    examples: Option<usize>,
+
+    /// Additionally exports every histogram in the results document in a long
+    /// format (one row per bin, with the `histogram`, `bin_start`, `bin_end`
+    /// (exclusive), and `count`) that plotting tools can read directly, to
+    /// `<prefix>.histograms.tsv` or `<prefix>.histograms.json`.
+    #[arg(long, value_name = "FORMAT")]
+    #[arg(value_parser = PossibleValuesParser::new(["tsv", "json"]))]
+    histogram_format: Option<String>,
+
+    /// Minimum base quality for a base to be counted by the Coverage, Edits, and
+    /// Allele Balance facets. Bases below this quality (and deletions) are
+    /// excluded. By default, the Allele Balance facet requires a base quality
+    /// of 20 and the other facets count every base.
+    #[arg(long, value_name = "U8")]
+    min_base_quality: Option<u8>,
+
+    /// Additionally reports the usable depth (counting only the bases from
+    /// records with a mapping quality of at least `--usable-depth-min-mapq`
+    /// and with a base quality of at least `--min-base-quality`, or 20) for
+    /// each sequence and bin within the Coverage facet.
+    #[arg(long)]
+    usable_depth: bool,
+
+    /// Minimum mapping quality for a record to contribute to the usable depth.
+    #[arg(long, value_name = "U8", default_value_t = DEFAULT_USABLE_DEPTH_MIN_MAPPING_QUALITY)]
+    #[arg(requires = "usable_depth")]
+    usable_depth_min_mapq: u8,
+
+    /// Additionally reports base composition, mismatch rates, and coverage for
+    /// each combination of read-in-pair and strand, which can expose
+    /// strand-specific chemistry artifacts.
+    #[arg(long)]
+    stratify_by_strand: bool,
+
+    /// Additionally runs the Fragmentomics facet, which reports the
+    /// nucleosomal periodicity of the fragment lengths, the fraction of short
+    /// fragments, and the end motifs of the fragments (for cell-free DNA).
+    #[arg(long)]
+    fragmentomics: bool,
+
+    /// Fails (rather than exhausting the memory of the machine) if the features
+    /// loaded from the GFF are estimated to use more than this many MiB.
+    #[arg(long, value_name = "MIB", requires = "features_gff")]
+    features_memory_cap: Option<usize>,
+
     /// Name of the feature that represents a five prime UTR region in the GFF
     /// file. Defaults to the respective GENCODE feature name.
     #[arg(long, value_name = "STRING", default_value = "five_prime_UTR")]
@@ -99,6 +381,104 @@ pub struct QcArgs {
     /// Defaults to the repective GENCODE feature name.
     #[arg(long, value_name = "STRING", default_value = "gene")]
     gene_feature_name: String,
+
+    /// Counts records for each gene in the GFF file (assigning records by the
+    /// exons they overlap in `union` mode or are contained by in `strict`
+    /// mode), writing the counts to `<prefix>.gene_counts.tsv`.
+    #[arg(long, value_name = "MODE")]
+    #[arg(value_parser = PossibleValuesParser::new(["union", "strict"]))]
+    gene_counts: Option<String>,
+
+    /// Strandedness of the library, used when counting records for each gene.
+    #[arg(long, value_name = "STRANDEDNESS", default_value = "unstranded")]
+    #[arg(value_parser = PossibleValuesParser::new(["unstranded", "forward", "reverse"]))]
+    strandedness: String,
+
+    /// GFF attribute that exons are grouped into genes by when counting
+    /// records for each gene.
+    #[arg(long, value_name = "STRING", default_value = DEFAULT_GENE_ID_ATTRIBUTE)]
+    gene_id_attribute: String,
+}
+
+/// All possible subcommands for `ngs qc`.
+#[derive(Subcommand)]
+pub enum QcSubcommand {
+    /// Merges the partial results from sharded `ngs qc` runs.
+    Merge(QcMergeArgs),
+
+    /// Runs the quality control facets that do not require alignments directly
+    /// on a FASTQ file.
+    Fastq(QcFastqArgs),
+
+    /// Checks that paired FASTQ files are synchronized (i.e., that the mates
+    /// of each template are at the same position within each file).
+    FastqPairs(QcFastqPairsArgs),
+}
+
+/// Clap arguments for the `ngs qc merge` subcommand.
+#[derive(Args)]
+pub struct QcMergeArgs {
+    /// Partial results files produced by `ngs qc --shard`.
+    #[arg(value_name = "JSON", required = true)]
+    results: Vec<PathBuf>,
+
+    /// Directory to output files to. Defaults to current working directory.
+    #[arg(short = 'o', long, value_name = "PATH")]
+    output_directory: Option<PathBuf>,
+
+    /// Output prefix for the merged results file.
+    #[arg(short = 'p', long, value_name = "STRING", default_value = "merged")]
+    output_prefix: String,
+}
+
+/// Clap arguments for the `ngs qc fastq` subcommand.
+#[derive(Args)]
+pub struct QcFastqArgs {
+    /// Source FASTQ file (optionally gzipped).
+    #[arg(value_name = "FASTQ")]
+    src: PathBuf,
+
+    /// Number of records to process. Defaults to all records.
+    #[arg(short = 'n', long, value_name = "USIZE", value_parser = parse_num_records)]
+    num_records: Option<NonZeroUsize>,
+
+    /// Directory to output files to. Defaults to current working directory.
+    #[arg(short = 'o', long, value_name = "PATH")]
+    output_directory: Option<PathBuf>,
+
+    /// Output prefix for the files that will be created. Defaults to the name
+    /// of the file.
+    #[arg(short = 'p', long, value_name = "STRING")]
+    output_prefix: Option<String>,
+
+    /// Only process one QC facet (specify the name of the facet).
+    #[arg(long = "only", value_name = "FACET")]
+    only_facet: Option<String>,
+
+    /// Reports the full position × quality score count matrix (run-length
+    /// encoded) from the Quality Score facet, which is useful for producing
+    /// heatmaps.
+    #[arg(long)]
+    quality_score_matrix: bool,
+
+    /// Number of bins in each of the Quality Score facet's histograms. Quality
+    /// scores beyond the last bin are tallied within the last bin.
+    #[arg(long, value_name = "USIZE", default_value_t = NonZeroUsize::new(DEFAULT_QUALITY_BINS).unwrap())]
+    quality_bins: NonZeroUsize,
+}
+
+/// Clap arguments for the `ngs qc fastq-pairs` subcommand.
+#[derive(Args)]
+pub struct QcFastqPairsArgs {
+    /// FASTQ file containing the first reads of each pair (optionally
+    /// gzipped).
+    #[arg(value_name = "R1")]
+    read_one: PathBuf,
+
+    /// FASTQ file containing the second reads of each pair (optionally
+    /// gzipped).
+    #[arg(value_name = "R2")]
+    read_two: PathBuf,
 }
 
 //==============================//
@@ -107,6 +487,13 @@ pub struct QcArgs {
 
 /// Prepares the arguments for running the main `qc` subcommand.
 pub fn qc(args: QcArgs) -> anyhow::Result<()> {
+    match args.subcommand {
+        Some(QcSubcommand::Merge(args)) => return merge(args),
+        Some(QcSubcommand::Fastq(args)) => return fastq(args),
+        Some(QcSubcommand::FastqPairs(args)) => return fastq_pairs(args),
+        None => {}
+    }
+
     info!("Starting qc command...");
     debug!("Arguments:");
 
@@ -114,14 +501,16 @@ pub fn qc(args: QcArgs) -> anyhow::Result<()> {
     // Source Path //
     //=============//
 
-    let src: PathBuf = args.src;
+    // Both the source and the reference genome are required by clap unless a
+    // subcommand is provided.
+    let src: PathBuf = args.src.unwrap();
     debug!("  [*] Source: {}", src.display());
 
     //==================//
     // Reference Genome //
     //==================//
 
-    let provided_reference_genome = args.reference_genome;
+    let provided_reference_genome = args.reference_genome.unwrap();
 
     let reference_genome = match get_reference_genome(&provided_reference_genome) {
         Some(s) => Rc::new(s),
@@ -148,19 +537,77 @@ pub fn qc(args: QcArgs) -> anyhow::Result<()> {
     let features_gff = args.features_gff;
     debug!("  [*] Features GFF : {:?}", features_gff);
 
+    //===========//
+    // Sites VCF //
+    //===========//
+
+    let sites_vcf = args.sites_vcf;
+    debug!("  [*] Sites VCF: {:?}", sites_vcf);
+
+    //============//
+    // Thresholds //
+    //============//
+
+    // The thresholds are read up front so that a malformed file is reported
+    // before any records are processed.
+    let thresholds = args
+        .thresholds
+        .as_deref()
+        .map(Thresholds::read)
+        .transpose()?;
+    if let Some(path) = &args.thresholds {
+        provenance::config("thresholds", path)?;
+    }
+    let strict_qc = args.strict_qc;
+    debug!("  [*] Thresholds: {:?}", args.thresholds);
+
+    //==========//
+    // Baseline //
+    //==========//
+
+    // Likewise, the baseline (and the tolerances for it) are read up front.
+    let baseline = match args.diff.as_deref() {
+        Some(path) => {
+            let baseline = Baseline::read(path)?;
+            provenance::config("baseline", path)?;
+            let tolerances = Tolerances::new(
+                args.diff_tolerance
+                    .iter()
+                    .map(|s| s.parse())
+                    .collect::<anyhow::Result<Vec<Tolerance>>>()?,
+            );
+            baseline.validate(&tolerances)?;
+            Some((baseline, tolerances))
+        }
+        None => None,
+    };
+    debug!("  [*] Baseline: {:?}", args.diff);
+
     //===============//
     // Output Prefix //
     //===============//
 
-    // Default is the name of the file.
-    let output_prefix = args.output_prefix.unwrap_or_else(|| {
+    // Default is the name of the file. If the analysis is sharded, the shard
+    // is appended so that the partial results from each shard do not collide.
+    let mut output_prefix = args.output_prefix.unwrap_or_else(|| {
         src.file_name()
             .unwrap()
             .to_os_string()
             .into_string()
             .unwrap()
     });
+
+    let shard = args.shard;
+    if let Some(shard) = shard {
+        output_prefix = format!(
+            "{}.shard-{}-of-{}",
+            output_prefix,
+            shard.index(),
+            shard.total()
+        );
+    }
     debug!("  [*] Output prefix: {}", output_prefix);
+    debug!("  [*] Shard: {:?}", shard);
 
     //==========================//
     // Feature GFF Column Names //
@@ -184,6 +631,23 @@ pub fn qc(args: QcArgs) -> anyhow::Result<()> {
     };
     debug!("  [*] Output directory: {}", output_directory.display());
 
+    //============//
+    // CRAM Input //
+    //============//
+
+    // The records of a CRAM file can only be decoded against the reference
+    // FASTA, so without one, only the CRAM facet (which inspects the container
+    // structure of the file) is run.
+    if detect(&src)? == AlignmentFormat::Cram && reference_fasta.is_none() {
+        let documents = cram(&src, output_prefix, output_directory)?;
+        return gate(
+            thresholds.as_ref(),
+            strict_qc,
+            baseline.as_ref(),
+            &documents,
+        );
+    }
+
     //============//
     // Only Facet //
     //============//
@@ -191,66 +655,270 @@ pub fn qc(args: QcArgs) -> anyhow::Result<()> {
     let only_facet = args.only_facet;
     debug!("  [*] Only facet: {:?}", only_facet);
 
+    //===============//
+    // Facet Options //
+    //===============//
+
+    let profile: QcProfile = args.profile.parse()?;
+    let facet_options = FacetOptions {
+        count_duplicates: args.count_duplicates == "yes",
+        template_length_max: args.template_length_max,
+        long_reads: args.long_reads || profile == QcProfile::LongRead,
+        quality_score_matrix: args.quality_score_matrix,
+        quality_bins: args.quality_bins,
+        coverage_cap: args.coverage_cap,
+        coverage_bin_size: args.coverage_bin_size,
+        min_base_quality: args.min_base_quality,
+        usable_depth: args.usable_depth.then(|| UsableDepthThresholds {
+            min_mapping_quality: args.usable_depth_min_mapq,
+            min_base_quality: args
+                .min_base_quality
+                .unwrap_or(DEFAULT_USABLE_DEPTH_MIN_BASE_QUALITY),
+        }),
+        coverage_territories: args
+            .coverage_territory
+            .iter()
+            .map(|territory| territory.parse())
+            .chain(
+                args.coverage_territory_intervals
+                    .into_iter()
+                    .map(|path| Ok(TerritoryDefinition::Intervals(path))),
+            )
+            .collect::<anyhow::Result<_>>()?,
+        coverage_gaps: match (args.mask_gaps, args.gap_intervals) {
+            (_, Some(path)) => Some(GapSource::Intervals(path)),
+            (true, None) => Some(GapSource::ReferenceFasta),
+            (false, None) => None,
+        },
+        stratify_by_strand: args.stratify_by_strand,
+        gene_counting: match args.gene_counts {
+            Some(mode) => Some(GeneCountingOptions {
+                mode: mode.parse()?,
+                strandedness: args.strandedness.parse()?,
+                gene_id_attribute: args.gene_id_attribute,
+            }),
+            None => None,
+        },
+        // The sequences within the shard (if any) are only known once the
+        // header has been read.
+        analyzed_sequences: None,
+        features_memory_cap: args
+            .features_memory_cap
+            .map(|mib| mib.saturating_mul(BYTES_PER_MIB)),
+        repeats: args.repeats,
+        spike_ins: args.spike_ins,
+        contamination_screen: args.contamination_screen,
+        examples: args.examples.unwrap_or_default(),
+        fragmentomics: args.fragmentomics,
+        profile,
+    };
+    debug!("  [*] Facet options: {:?}", facet_options);
+
     //===================//
     // Number of Records //
     //===================//
 
-    let num_records = match args.num_records {
-        Some(n) => {
-            debug!("Reading a maximum of {} records in the first pass.", n);
-            NumberOfRecords::Some(n)
-        }
-        None => {
-            debug!("Reading all available records in the first pass.");
-            NumberOfRecords::All
-        }
+    // In estimate mode, only a sample of the records is processed by default.
+    let num_records = match (args.num_records, args.estimate) {
+        (None, true) => NumberOfRecords::Some(NonZeroUsize::new(DEFAULT_ESTIMATE_RECORDS).unwrap()),
+        (n, _) => NumberOfRecords::from(n),
     };
+    match num_records {
+        NumberOfRecords::Some(n) => {
+            debug!("Reading a maximum of {} records in the first pass.", n)
+        }
+        NumberOfRecords::All => debug!("Reading all available records in the first pass."),
+    }
 
-    app(
+    let documents = app(
         src,
         reference_fasta,
         features_gff,
+        sites_vcf,
         reference_genome,
         output_prefix,
         output_directory,
         num_records,
         feature_names,
         only_facet,
+        shard,
+        facet_options,
+        args.estimate,
+        args.async_io,
+        !args.no_prefetch,
+        !args.no_reuse,
+        args.stale_index.parse()?,
+        args.filter,
+        args.split_by,
+        args.coverage_bed,
+        args.histogram_format
+            .map(|format| format.parse())
+            .transpose()?,
+    )?;
+
+    metrics::file_processed();
+    for (_, results) in &documents {
+        if let Some(runtime) = &results.runtime {
+            runtime.publish();
+        }
+    }
+
+    gate(
+        thresholds.as_ref(),
+        strict_qc,
+        baseline.as_ref(),
+        &documents,
     )
 }
 
+/// Checks each results document against the thresholds (if provided). With
+/// `--strict-qc`, a one-line summary of each is printed to stdout and a
+/// [`GateFailure`] is returned unless every document passes. Each document is
+/// also compared against the baseline (if provided), which logs a one-line
+/// summary (leaving stdout to machine-readable output) and fails unless every
+/// document matches.
+fn gate(
+    thresholds: Option<&Thresholds>,
+    strict_qc: bool,
+    baseline: Option<&(Baseline, Tolerances)>,
+    documents: &[(String, Results)],
+) -> anyhow::Result<()> {
+    let mut status = Status::Pass;
+
+    if let Some(thresholds) = thresholds {
+        for (name, results) in documents {
+            let evaluation = thresholds.evaluate(name, results)?;
+
+            for problem in evaluation.problems() {
+                warn!("{}: {}", name, problem);
+            }
+
+            if strict_qc {
+                println!("{}", evaluation.summary());
+            }
+
+            status = status.max(evaluation.status());
+        }
+    }
+
+    // Only the thresholds fail the run without `--strict-qc`.
+    if !strict_qc {
+        status = Status::Pass;
+    }
+
+    if let Some((baseline, tolerances)) = baseline {
+        for (name, results) in documents {
+            let comparison = baseline.compare(name, results, tolerances)?;
+
+            for problem in comparison.problems() {
+                warn!("{}: {}", name, problem);
+            }
+
+            info!("{}", comparison.summary());
+            status = status.max(comparison.status());
+        }
+    }
+
+    if status != Status::Pass {
+        return Err(GateFailure { status }.into());
+    }
+
+    Ok(())
+}
+
 //==============//
 // Main program //
 //==============//
 
-/// Runs the main program for the `qc` subcommand.
+/// Runs the main program for the `qc` subcommand, returning each results
+/// document that was written along with its output prefix.
 #[allow(clippy::too_many_arguments)]
 fn app(
     src: PathBuf,
     reference_fasta: Option<PathBuf>,
     features_gff: Option<PathBuf>,
+    sites_vcf: Option<PathBuf>,
     reference_genome: Rc<Box<dyn ReferenceGenome>>,
     output_prefix: String,
     output_directory: PathBuf,
     num_records: NumberOfRecords,
     feature_names: FeatureNames,
     only_facet: Option<String>,
-) -> anyhow::Result<()> {
+    shard: Option<Shard>,
+    mut facet_options: FacetOptions,
+    estimate: bool,
+    async_io: bool,
+    prefetch: bool,
+    reuse: bool,
+    stale_index: StaleIndexPolicy,
+    filter: Option<ReadFilter>,
+    split_by: Option<String>,
+    coverage_bed: bool,
+    histogram_format: Option<HistogramFormat>,
+) -> anyhow::Result<Vec<(String, Results)>> {
     //=====================================================//
     // Preprocessing: set up file handles and prepare file //
     //=====================================================//
 
-    let mut reader = File::open(&src).map(bam::Reader::new)?;
-    // This check is here simply so that, if the BAM index does not exist, we
+    let format = detect(&src)?;
+    if format != AlignmentFormat::Bam {
+        if shard.is_some() {
+            bail!("Sharding (--shard) is only supported for BAM files.");
+        }
+
+        if async_io {
+            warn!(
+                "Asynchronous I/O is only supported for BAM files: reading the {} file synchronously.",
+                format
+            );
+        }
+    }
+
+    let (input, mut input_checksum) = checksum::open(&src)?;
+    // When sharded, the records are read through the index, which seeks within
+    // the file, so the blocks cannot be decompressed ahead of time.
+    let mut reader = match (format, shard) {
+        (AlignmentFormat::Sam, _) => AlignmentReader::new(input, format, None)?,
+        (AlignmentFormat::Cram, _) => AlignmentReader::cram(input, reference_fasta.as_deref())?,
+        (AlignmentFormat::Bam, Some(_)) => AlignmentReader::Bam(bam::Reader::new(input)),
+        (AlignmentFormat::Bam, None) => AlignmentReader::Bam(sequential_reader(input)),
+    };
+    // This check is here simply so that, if the index does not exist, we
     // don't complete the first pass before erroring out. It's not strictly
     // needed for this first pass as we aren't doing random access throughout
     // the file.
-    let _ = bai::read(&src.with_extension("bam.bai")).with_context(|| "bam index")?;
+    //
+    // SAM files are not indexed, so only the first pass is run for them.
+    let bai_path = src.with_extension("bam.bai");
+    let index = match format {
+        AlignmentFormat::Sam => {
+            warn!("SAM files are not indexed: only the first pass facets will be run.");
+            None
+        }
+        _ => Some(AlignmentIndex::read(&src, format)?),
+    };
+
+    let (header, reference_sequences) = reader.read_header()?;
 
-    let ht = reader.read_header()?;
-    let header = parse_header(ht);
+    // The index is also checked against the BAM file up front, so that a
+    // stale index is reported before the first pass rather than after it.
+    if let (true, Some(AlignmentIndex::Bai(index))) = (stale_index.checks(), &index) {
+        let problems = stale::structural_problems(index, &header, &src, &bai_path)?;
+        stale_index.enforce(&bai_path, &problems)?;
+    }
 
-    let reference_sequences = reader.read_reference_sequences()?;
+    // When sharded, only the sequences within the shard are analyzed.
+    if let Some(shard) = shard {
+        facet_options.analyzed_sequences = Some(
+            header
+                .reference_sequences()
+                .keys()
+                .enumerate()
+                .filter(|(position, _)| shard.contains_sequence(*position))
+                .map(|(_, name)| name.to_string())
+                .collect(),
+        );
+    }
 
     if !output_directory.exists() {
         std::fs::create_dir_all(output_directory.clone())
@@ -261,13 +929,19 @@ fn app(
     // Preprocessing: reference sequence concordance check //
     //=====================================================//
 
-    let supported_sequences = get_all_sequences(Rc::clone(&reference_genome));
+    // Pangenome sequence names (e.g., `GRCh38#0#chr1`) are checked by their
+    // contig.
+    if reference_sequences
+        .keys()
+        .any(|name| normalize_sequence_name(name) != name.as_str())
+    {
+        info!("Pangenome sequence names found in the header: matching them by their contig.");
+    }
 
     for (sequence, _) in reference_sequences {
-        if !supported_sequences
-            .iter()
-            .map(|s| s.name())
-            .any(|x| x == sequence)
+        if reference_genome
+            .species_of(normalize_sequence_name(sequence.as_str()))
+            .is_none()
         {
             bail!(
                 "Sequence \"{}\" not found in specified reference genome. \
@@ -277,27 +951,143 @@ fn app(
         }
     }
 
+    //=====================================================================//
+    // Preprocessing: choose the sequence that is sampled in estimate mode //
+    //=====================================================================//
+
+    let estimate_sequence = if estimate {
+        let sequence = choose_sequence(&header, Rc::clone(&reference_genome));
+        match &sequence {
+            Some(name) => info!(
+                "Estimate mode: sampling sequence {} in the second pass.",
+                name
+            ),
+            None => warn!("Estimate mode: no sequences in the header to sample."),
+        }
+        sequence
+    } else {
+        None
+    };
+
     //=================================================================//
     // Preprocessing: calculate which quality check facets we will run //
     //=================================================================//
 
-    let (mut record_facets, mut sequence_facets) = get_qc_facets(
-        features_gff,
-        Some(&feature_names),
-        Some(&header),
-        reference_fasta,
-        Rc::clone(&reference_genome),
-        only_facet,
-    )?;
+    // When splitting, each split gets its own set of facets (and results
+    // document) and records are routed to the set for their split.
+    let mut router = split_by
+        .as_deref()
+        .map(|stratum| Router::try_new(stratum, &header))
+        .transpose()?;
+    let split_by_lane = matches!(router, Some(Router::Lane(_)));
+
+    let make_set = |name: Option<String>| -> anyhow::Result<FacetSet<'_>> {
+        let (mut record_facets, mut sequence_facets) = get_qc_facets(
+            features_gff.clone(),
+            Some(&feature_names),
+            Some(&header),
+            reference_fasta.clone(),
+            sites_vcf.clone(),
+            Rc::clone(&reference_genome),
+            only_facet.clone(),
+            &facet_options,
+        )?;
+
+        // The sequence-based facets query the records through the index.
+        if index.is_none() {
+            sequence_facets.clear();
+        }
+
+        if split_by_lane {
+            record_facets.retain(|f| LANE_FACETS.contains(&f.name()));
+            sequence_facets.clear();
+
+            if record_facets.is_empty() {
+                bail!(
+                    "None of the requested facets can be split by lane. The \
+                    supported facets are: {}.",
+                    LANE_FACETS.join(", ")
+                );
+            }
+        }
+
+        Ok(FacetSet::new(name, record_facets, sequence_facets))
+    };
+
+    let mut facet_sets = match &router {
+        Some(router) => {
+            if !router.names().is_empty() {
+                info!(
+                    "Splitting the results by {}: {}.",
+                    router.stratum(),
+                    router.names().join(", ")
+                );
+            }
+            router
+                .names()
+                .iter()
+                .map(|name| make_set(Some(name.clone())))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        }
+        None => vec![make_set(None)?],
+    };
+
+    // Lanes are only discovered as the records are read, so the facets that
+    // will be run are described by a set that is not used for processing.
+    let (record_facet_loads, sequence_facet_loads) = {
+        let template;
+        let representative = match facet_sets.first() {
+            Some(set) => set,
+            None => {
+                template = make_set(None)?;
+                &template
+            }
+        };
+
+        (
+            representative
+                .record_facets()
+                .iter()
+                .map(|f| (f.name(), f.computational_load()))
+                .collect::<Vec<_>>(),
+            representative
+                .sequence_facets()
+                .iter()
+                .map(|f| (f.name(), f.computational_load()))
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    // Records that do not belong to any of the splits are not seen by any of
+    // the facets.
+    let mut records_without_split = 0;
+
+    // Records that do not match the filter (if provided) are counted but are
+    // not seen by any of the facets.
+    let passes_filter =
+        |record: &Record| filter.as_ref().is_none_or(|f| f.matches(record, &header));
+
+    // The output prefix for each of the splits.
+    let prefix_for = |set: &FacetSet<'_>| match set.name() {
+        Some(name) => format!("{}.{}", output_prefix, file_name_safe(name)),
+        None => output_prefix.clone(),
+    };
+
+    let has_record_facets = !record_facet_loads.is_empty();
+    let has_sequence_facets = !sequence_facet_loads.is_empty();
+
+    // The number of records in the file, which is only known if the first pass
+    // read the whole file.
+    let mut records_in_file = None;
 
-    if !record_facets.is_empty() {
+    if has_record_facets {
         //===========================================================//
         // First pass: print out which facets we're going to analyze //
         //===========================================================//
 
         info!("First pass with the following facets enabled:");
-        for facet in &record_facets {
-            info!("  [*] {}, {:?}", facet.name(), facet.computational_load());
+        for (name, load) in &record_facet_loads {
+            info!("  [*] {}, {:?}", name, load);
         }
 
         //====================================================================//
@@ -306,54 +1096,127 @@ fn app(
 
         info!("Starting first pass for QC stats.");
         let mut record_count = 0;
+        let limit = match num_records {
+            NumberOfRecords::Some(n) => Some(n.get()),
+            NumberOfRecords::All => None,
+        };
+
+        // Returns whether the maximum number of records has been reached.
+        let mut process_record = |record: &Record| -> anyhow::Result<bool> {
+            let index = match &mut router {
+                Some(router) => router.route(record),
+                None => Some(0),
+            };
+
+            match index {
+                Some(index) => {
+                    // A split that was discovered by this record (i.e., a new
+                    // lane) gets its set of facets the first time it is seen.
+                    if index == facet_sets.len() {
+                        let name = router.as_ref().map(|r| r.names()[index].clone());
+                        facet_sets.push(make_set(name)?);
+                    }
 
-        for result in reader.records() {
-            let record = result?;
-
-            for facet in &mut record_facets {
-                facet.process(&record)?;
+                    facet_sets[index].process(record, passes_filter(record))?
+                }
+                None => records_without_split += 1,
             }
 
             record_count += 1;
             if record_count % 1_000_000 == 0 {
-                info!(
-                    "  [*] Processed {} records.",
-                    record_count.to_formatted_string(&Locale::en),
-                );
+                info!("  [*] Processed {} records.", display::count(record_count),);
+                ProgressEvent::new("first-pass", record_count)
+                    .complete(record_count, limit)
+                    .emit();
+            }
+
+            Ok(num_records.is_reached(record_count))
+        };
+
+        match shard {
+            None if async_io && format == AlignmentFormat::Bam => {
+                input_checksum = read_records_async(&src, reuse, &mut process_record)?
             }
+            None => reader.for_each_record(&header, reuse, &mut process_record)?,
+            Some(shard) => {
+                // When sharded, only the records for the reference sequences
+                // in this shard are read (via the index).
+                let index =
+                    bai::read(src.with_extension("bam.bai")).with_context(|| "bam index")?;
+                let reader = match &mut reader {
+                    AlignmentReader::Bam(reader) => reader,
+                    // SAFETY: sharding is rejected for SAM and CRAM files above.
+                    AlignmentReader::Sam(..) | AlignmentReader::Cram(..) => unreachable!(),
+                };
+                let mut reached = false;
+
+                'sequences: for (position, (name, seq)) in
+                    header.reference_sequences().iter().enumerate()
+                {
+                    if !shard.contains_sequence(position) {
+                        continue;
+                    }
+
+                    let end = Position::try_from(usize::from(seq.length()))?;
+                    for_each_query_record(
+                        reader,
+                        header.reference_sequences(),
+                        &index,
+                        &Region::new(name, Position::MIN..=end),
+                        reuse,
+                        |record| {
+                            reached = process_record(record)?;
+                            Ok(reached)
+                        },
+                    )?;
+
+                    if reached {
+                        break 'sequences;
+                    }
+                }
 
-            if let NumberOfRecords::Some(n) = num_records {
-                if record_count > n {
-                    break;
+                if shard.is_last() && !reached {
+                    for result in reader.query_unmapped(&index)? {
+                        if process_record(&result?)? {
+                            break;
+                        }
+                    }
                 }
             }
         }
 
         info!(
             "Processed {} records in the first pass.",
-            record_count.to_formatted_string(&Locale::en)
+            display::count(record_count)
         );
+        ProgressEvent::new("first-pass", record_count)
+            .complete(record_count, Some(record_count))
+            .emit();
+
+        if shard.is_none() && !num_records.is_reached(record_count) {
+            records_in_file = Some(record_count as u64);
+        }
 
         //================================//
         // First pass: summarize qc stats //
         //================================//
 
         info!("Summarizing quality control facets for the first pass.");
-        for facet in &mut record_facets {
-            facet.summarize()?;
+        for set in &mut facet_sets {
+            set.summarize()?;
         }
     } else {
         info!("No facets specified that require first pass. Skipping...");
     }
 
-    if !sequence_facets.is_empty() {
+    if has_sequence_facets {
         //============================================================//
         // Second pass: print out which facets we're going to analyze //
         //============================================================//
 
         info!("Second pass with the following facets enabled:");
-        for facet in &sequence_facets {
-            info!("  [*] {}, {:?}", facet.name(), facet.computational_load());
+        for (name, load) in &sequence_facet_loads {
+            info!("  [*] {}, {:?}", name, load);
         }
 
         //===================================================//
@@ -361,52 +1224,158 @@ fn app(
         //===================================================//
 
         info!("Starting second pass for QC stats.");
-        let mut reader = File::open(&src).map(bam::Reader::new)?;
-        let index = bai::read(&src.with_extension("bam.bai")).with_context(|| "bam index")?;
+        let index = index
+            .as_ref()
+            .with_context(|| "the second pass requires an indexed BAM or CRAM file")?;
+        let mut reader =
+            AlignmentReader::new(File::open(&src)?, format, reference_fasta.as_deref())?;
+
+        // (Now that the number of records in the file may be known, the index
+        // is checked against it before it is relied on.)
+        if let (true, Some(records), AlignmentIndex::Bai(index)) =
+            (stale_index.checks(), records_in_file, index)
+        {
+            let problems = stale::record_count_problem(index, records)
+                .into_iter()
+                .collect::<Vec<_>>();
+            stale_index.enforce(&bai_path, &problems)?;
+        }
+
+        // Only the sequences in the shard (or, in estimate mode, the sampled
+        // sequence) are processed.
+        let in_scope = |position: usize, name: &str| {
+            let in_shard = match shard {
+                Some(shard) => shard.contains_sequence(position),
+                None => true,
+            };
+            let sampled = !estimate || estimate_sequence.as_deref() == Some(name);
+            in_shard && sampled
+        };
+
+        let mut progress = Progress {
+            sequences_completed: Vec::new(),
+            sequences_total: header
+                .reference_sequences()
+                .keys()
+                .enumerate()
+                .filter(|(position, name)| in_scope(*position, name.as_str()))
+                .count(),
+        };
+
+        // The records of the sequences are read ahead of the facets on a
+        // background thread (in the same order as they are processed below).
+        // Only the records of BAM files are prefetched.
+        let mut prefetcher = (prefetch && format == AlignmentFormat::Bam).then(|| {
+            let sequences = header
+                .reference_sequences()
+                .keys()
+                .enumerate()
+                .filter(|(position, name)| in_scope(*position, name.as_str()))
+                .map(|(_, name)| name.to_string())
+                .collect();
+            Prefetcher::spawn(
+                src.clone(),
+                sequences,
+                DEFAULT_BATCH_SIZE,
+                DEFAULT_BATCHES_IN_FLIGHT,
+                reuse,
+            )
+        });
+
+        for (position, (name, seq)) in header.reference_sequences().iter().enumerate() {
+            if !in_scope(position, name.as_str()) {
+                continue;
+            }
 
-        for (name, seq) in header.reference_sequences() {
             let start = Position::MIN;
             let end = Position::try_from(usize::from(seq.length()))?;
 
             info!("  [*] Starting sequence {} ", name);
+            let _sequence_span = info_span!("sequence", name = name.as_str()).entered();
             let mut processed = 0;
+            let completed = progress.sequences_completed.len();
+            let total = Some(progress.sequences_total);
+            ProgressEvent::new("second-pass", 0)
+                .sequence(name)
+                .complete(completed, total)
+                .emit();
 
             debug!("    [*] Setting up sequence.");
-            for facet in &mut sequence_facets {
-                if facet.supports_sequence_name(name) {
-                    facet.setup(seq)?;
-                }
+            let mut supported = false;
+            for set in &mut facet_sets {
+                supported |= set.setup(seq)?;
             }
 
-            let query = reader.query(
-                header.reference_sequences(),
-                &index,
-                &Region::new(name, start..=end),
-            )?;
+            let mut process_sequence_record = |record: &Record| -> anyhow::Result<()> {
+                processed += 1;
 
-            debug!("    [*] Processing records from sequence.");
-            for result in query {
-                let record = result?;
-                for facet in &mut sequence_facets {
-                    if facet.supports_sequence_name(name) {
-                        facet.process(seq, &record)?;
-                    }
+                // Only splits known up front (i.e., samples) are run in the
+                // second pass, so there are no new splits to discover here.
+                let index = match &mut router {
+                    Some(router) => router.route(record),
+                    None => Some(0),
+                };
+
+                match index {
+                    Some(index) => facet_sets[index].process_sequence_record(
+                        seq,
+                        record,
+                        passes_filter(record),
+                    )?,
+                    // Records without a split are already tallied when the
+                    // first pass is run.
+                    None if !has_record_facets => records_without_split += 1,
+                    None => {}
                 }
 
-                processed += 1;
-
                 if processed % 1_000_000 == 0 {
                     info!(
                         "    [*] Processed {} records for this sequence.",
-                        processed.to_formatted_string(&Locale::en),
+                        display::count(processed),
                     );
+                    ProgressEvent::new("second-pass", processed)
+                        .sequence(name)
+                        .complete(completed, total)
+                        .emit();
+                }
+
+                Ok(())
+            };
+
+            debug!("    [*] Processing records from sequence.");
+            match &mut prefetcher {
+                Some(prefetcher) => {
+                    prefetcher.for_each_sequence_record(&mut process_sequence_record)?
                 }
+                None => reader.for_each_query_record(
+                    &header,
+                    index,
+                    &Region::new(name, start..=end),
+                    reuse,
+                    |record| process_sequence_record(record).map(|_| false),
+                )?,
             }
 
             debug!("    [*] Tearing down sequence.");
-            for facet in &mut sequence_facets {
-                if facet.supports_sequence_name(name) {
-                    facet.teardown(seq)?;
+            for set in &mut facet_sets {
+                set.teardown(seq)?;
+            }
+
+            progress.sequences_completed.push(name.to_string());
+            ProgressEvent::new("second-pass", processed)
+                .sequence(name)
+                .complete(completed + 1, total)
+                .emit();
+
+            // Partial results are written after each sequence so that long
+            // running analyses which are killed still leave usable results
+            // behind. Sequences not supported by any facet do not change the
+            // results, so there is no need to write them out again.
+            if supported {
+                debug!("    [*] Writing partial results.");
+                for set in &mut facet_sets {
+                    let partial = set.results(shard, filter.as_ref(), Some(progress.clone()));
+                    partial.write_partial(&prefix_for(set), &output_directory)?;
                 }
             }
         }
@@ -414,21 +1383,427 @@ fn app(
         info!("No facets specified that require second pass. Skipping...");
     }
 
+    if records_without_split > 0 {
+        let reason = match &router {
+            Some(Router::Lane(_)) => "have an Illumina read name with a lane",
+            _ => "belong to a read group with a sample",
+        };
+        warn!(
+            "{} records did not {} and were excluded from the results.",
+            display::count(records_without_split),
+            reason
+        );
+    }
+
+    if let Some(router @ Router::Lane(_)) = &router {
+        info!(
+            "Split the results by {}: {}.",
+            router.stratum(),
+            router.names().join(", ")
+        );
+    }
+
     //=====================================//
     // Finalize: write all results to file //
     //=====================================//
 
-    let mut results = Results::default();
+    let input_checksums = input_checksum.finish()?.map(|checksum| vec![checksum]);
+    let mut documents = Vec::new();
+
+    // For CRAM files, the container structure is reported alongside the other
+    // facets.
+    let cram_facet = match format {
+        AlignmentFormat::Cram => {
+            let mut facet = CramFacet::default();
+            facet.process_file(&src)?;
+            facet.summarize();
+            Some(facet)
+        }
+        AlignmentFormat::Sam | AlignmentFormat::Bam => None,
+    };
 
-    for facet in &record_facets {
-        facet.aggregate(&mut results);
+    for set in &mut facet_sets {
+        let prefix = prefix_for(set);
+        let mut results = set.results(shard, filter.as_ref(), None);
+        results.input_checksums = input_checksums.clone();
+        if let Some(facet) = &cram_facet {
+            facet.aggregate(&mut results);
+        }
+
+        if results.records_processed == 0 {
+            match set.name() {
+                Some(name) => warn!(
+                    "No records were processed for {} {}. All metrics will be \
+                    reported as zero.",
+                    router.as_ref().map_or("split", |r| r.stratum()),
+                    name
+                ),
+                None => warn!(
+                    "No records were processed: {} appears to be empty or header-only. \
+                    All metrics will be reported as zero.",
+                    src.display()
+                ),
+            }
+        }
+
+        if estimate {
+            results.estimate = Some(EstimateMetrics::from_results(
+                &results,
+                estimate_sequence.clone(),
+            ));
+        }
+
+        results.write(prefix.clone(), &output_directory)?;
+
+        if facet_options.examples > 0 {
+            let mut filepath = output_directory.clone();
+            filepath.push(format!("{}.examples", prefix));
+            examples::write(&filepath, &header, set.examples(), facet_options.examples)
+                .with_context(|| format!("writing {}", filepath.display()))?;
+            outputs::record(&filepath, "examples");
+        }
+
+        if let Some(format) = histogram_format {
+            let mut filepath = output_directory.clone();
+            filepath.push(format!("{}.histograms.{}", prefix, format.extension()));
+            histograms::write(&filepath, &results, format)
+                .with_context(|| format!("writing {}", filepath.display()))?;
+            outputs::record(&filepath, "histograms");
+        }
+
+        if coverage_bed {
+            match &results.coverage {
+                Some(coverage) => {
+                    let mut filepath = output_directory.clone();
+                    filepath.push(format!("{}.coverage_bins.bed", prefix));
+                    coverage
+                        .write_bed(&filepath)
+                        .with_context(|| format!("writing {}", filepath.display()))?;
+                    outputs::record(&filepath, "coverage-bed");
+                }
+                None => warn!("--coverage-bed was provided, but the Coverage facet did not run."),
+            }
+        }
+
+        documents.push((prefix, results));
+    }
+
+    Ok(documents)
+}
+
+/// Reads every record in the file through the asynchronous I/O path (see
+/// [`crate::utils::bridge`]), handing each to `process_record` until it
+/// reports that the maximum number of records has been reached. The checksum
+/// of the file as read through the bridge is returned.
+#[cfg(feature = "async-io")]
+fn read_records_async<F>(
+    src: &Path,
+    reuse: bool,
+    process_record: &mut F,
+) -> anyhow::Result<Checksum>
+where
+    F: FnMut(&Record) -> anyhow::Result<bool>,
+{
+    use crate::utils::{
+        bridge::BufferedBridge, checksum::ChecksumReader, formats::bam::for_each_record,
+    };
+
+    // A single worker is enough, as it only shuttles chunks of the file.
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .build()?;
+
+    let bridge = BufferedBridge::open(runtime.handle(), src)
+        .with_context(|| format!("opening {}", src.display()))?;
+    let (bridge, checksum) = ChecksumReader::new(bridge, src);
+    let mut reader = sequential_reader(bridge);
+    reader.read_header()?;
+    reader.read_reference_sequences()?;
+
+    for_each_record(&mut reader, reuse, process_record)?;
+    Ok(checksum)
+}
+
+/// Without the `async-io` feature, the asynchronous I/O path is unavailable.
+#[cfg(not(feature = "async-io"))]
+fn read_records_async<F>(_: &Path, _: bool, _: &mut F) -> anyhow::Result<Checksum>
+where
+    F: FnMut(&Record) -> anyhow::Result<bool>,
+{
+    bail!("--async-io requires ngs to be built with the `async-io` feature.")
+}
+
+//===========================//
+// The `qc fastq` subcommand //
+//===========================//
+
+/// Runs the quality control facets that do not require alignments on a FASTQ
+/// file. The results are written in the same format as the `qc` subcommand.
+fn fastq(args: QcFastqArgs) -> anyhow::Result<()> {
+    info!("Starting qc fastq command...");
+
+    let src = args.src;
+    let output_prefix = args.output_prefix.unwrap_or_else(|| {
+        src.file_name()
+            .unwrap()
+            .to_os_string()
+            .into_string()
+            .unwrap()
+    });
+    let output_directory = match args.output_directory {
+        Some(p) => p,
+        None => std::env::current_dir()?,
+    };
+    let num_records = NumberOfRecords::from(args.num_records);
+
+    // Records from FASTQ files are never marked as duplicates, so duplicates
+    // are always counted.
+    let facet_options = FacetOptions {
+        quality_score_matrix: args.quality_score_matrix,
+        quality_bins: args.quality_bins,
+        ..Default::default()
+    };
+    debug!("  [*] Facet options: {:?}", facet_options);
+
+    let mut facets = get_fastq_qc_facets(args.only_facet, &facet_options)?;
+    let mut timers: Vec<FacetTimer> = facets.iter().map(|f| FacetTimer::new(f.name())).collect();
+
+    info!("Processing FASTQ with the following facets enabled:");
+    for facet in &facets {
+        info!("  [*] {}, {:?}", facet.name(), facet.computational_load());
+    }
+
+    // (1) Convert each FASTQ record into an unmapped record and process it
+    // with each of the facets.
+    let (mut reader, input_checksum) = crate::utils::formats::fastq::open(&src)?;
+    let mut records_processed = 0;
+
+    for result in reader.records() {
+        let record = to_unmapped_record(&result?)?;
+
+        for (facet, timer) in facets.iter_mut().zip(&mut timers) {
+            timer.time_record(|| facet.process(&record))?;
+        }
+
+        records_processed += 1;
+        if records_processed % 1_000_000 == 0 {
+            info!(
+                "  [*] Processed {} records.",
+                display::count(records_processed),
+            );
+            ProgressEvent::new("fastq", records_processed).emit();
+        }
+
+        if num_records.is_reached(records_processed) {
+            break;
+        }
+    }
+
+    info!("Processed {} records.", display::count(records_processed));
+    ProgressEvent::new("fastq", records_processed)
+        .complete(records_processed, Some(records_processed))
+        .emit();
+
+    if records_processed == 0 {
+        warn!(
+            "No records were processed: {} appears to be empty. \
+            All metrics will be reported as zero.",
+            src.display()
+        );
     }
 
-    for facet in &mut sequence_facets {
+    // (2) Summarize the facets and write the results to file.
+    let mut results = Results {
+        records_processed,
+        input_checksums: input_checksum.finish()?.map(|checksum| vec![checksum]),
+        provenance: Some(Provenance::now()),
+        ..Default::default()
+    };
+
+    let mut runtime = RuntimeMetrics::default();
+    for (facet, timer) in facets.iter_mut().zip(&mut timers) {
+        timer.time(|| facet.summarize())?;
         facet.aggregate(&mut results);
+        timer.aggregate(&mut runtime);
+    }
+    runtime.publish();
+    results.runtime = Some(runtime);
+
+    if !output_directory.exists() {
+        std::fs::create_dir_all(output_directory.clone())
+            .expect("Could not create output directory.");
     }
 
     results.write(output_prefix, &output_directory)?;
+    metrics::file_processed();
+
+    Ok(())
+}
+
+//============//
+// CRAM files //
+//============//
+
+/// Runs the CRAM facet over the container structure of a CRAM file and writes
+/// the results to file.
+fn cram(
+    src: &Path,
+    output_prefix: String,
+    output_directory: PathBuf,
+) -> anyhow::Result<Vec<(String, Results)>> {
+    warn!(
+        "Only the CRAM facet is run for CRAM files without a reference FASTA. \
+        The other facets require the records to be decoded: provide the \
+        reference FASTA with `--reference-fasta`."
+    );
+
+    let mut facet = CramFacet::default();
+    let input_checksum = facet.process_file(src)?;
+    facet.summarize();
+
+    let mut results = Results {
+        input_checksums: input_checksum.finish()?.map(|checksum| vec![checksum]),
+        provenance: Some(Provenance::now()),
+        ..Default::default()
+    };
+    facet.aggregate(&mut results);
+
+    if !output_directory.exists() {
+        std::fs::create_dir_all(output_directory.clone())
+            .expect("Could not create output directory.");
+    }
+
+    results.write(output_prefix.clone(), &output_directory)?;
+
+    Ok(vec![(output_prefix, results)])
+}
+
+//=================================//
+// The `qc fastq-pairs` subcommand //
+//=================================//
+
+/// Checks that paired FASTQ files are synchronized, printing the results to
+/// stdout as JSON. An error is returned if the files are not synchronized.
+fn fastq_pairs(args: QcFastqPairsArgs) -> anyhow::Result<()> {
+    info!("Starting qc fastq-pairs command...");
+
+    let (read_one, read_one_checksum) = crate::utils::formats::fastq::open(&args.read_one)?;
+    let (read_two, read_two_checksum) = crate::utils::formats::fastq::open(&args.read_two)?;
+
+    let mut result = check_pairs(read_one, read_two).with_context(|| {
+        format!(
+            "Could not read FASTQ files: {} and {}",
+            args.read_one.display(),
+            args.read_two.display()
+        )
+    })?;
+
+    result.input_checksums = [read_one_checksum.finish()?, read_two_checksum.finish()?]
+        .into_iter()
+        .collect();
+    result.provenance = Some(Provenance::now());
+
+    let output = serde_json::to_string_pretty(&result)?;
+    println!("{}", output);
+
+    if !result.synchronized {
+        bail!(
+            "{} and {} are not synchronized.",
+            args.read_one.display(),
+            args.read_two.display()
+        );
+    }
+
+    Ok(())
+}
+
+//===========================//
+// The `qc merge` subcommand //
+//===========================//
+
+/// Merges the partial results from sharded runs of the `qc` subcommand.
+fn merge(args: QcMergeArgs) -> anyhow::Result<()> {
+    info!("Starting qc merge command...");
+
+    let output_directory = match args.output_directory {
+        Some(p) => p,
+        None => std::env::current_dir()?,
+    };
+
+    // (1) Read all of the partial results, checking that the shards are
+    // consistent with one another.
+    let mut seen_shards = Vec::new();
+    let mut merged: Option<Results> = None;
+
+    for path in &args.results {
+        debug!("  [*] Reading results: {}", path.display());
+        let results = Results::read(path)?;
+
+        match results.shard {
+            Some(shard) => {
+                if seen_shards.contains(&shard) {
+                    bail!("Shard {} was provided more than once.", shard);
+                }
+
+                if let Some(first) = seen_shards.first() {
+                    if first.total() != shard.total() {
+                        bail!(
+                            "Results were sharded differently ({} and {}).",
+                            first,
+                            shard
+                        );
+                    }
+                }
+
+                seen_shards.push(shard);
+            }
+            None => warn!(
+                "{} was not produced by a sharded run. Merging it all the same.",
+                path.display()
+            ),
+        }
+
+        match merged.as_mut() {
+            Some(merged) => merged
+                .merge(results)
+                .with_context(|| format!("Could not merge results file: {}", path.display()))?,
+            None => {
+                merged = Some(Results {
+                    shard: None,
+                    ..results
+                })
+            }
+        }
+    }
+
+    // (2) Warn if any shards are missing: the merged results are still
+    // written, but they will not cover the entire file.
+    if let Some(total) = seen_shards.first().map(|shard| shard.total()) {
+        let missing: Vec<String> = (1..=total)
+            .filter(|i| !seen_shards.iter().any(|shard| shard.index() == *i))
+            .map(|i| format!("{}/{}", i, total))
+            .collect();
+
+        if !missing.is_empty() {
+            warn!(
+                "The following shards were not provided: {}. The merged results \
+                will not cover the entire file.",
+                missing.join(", ")
+            );
+        }
+    }
+
+    // (3) Write the merged results to file.
+    if !output_directory.exists() {
+        std::fs::create_dir_all(output_directory.clone())
+            .expect("Could not create output directory.");
+    }
+
+    // Clap requires at least one results file, so there is always something
+    // that was merged. The merged results are attributed to this invocation.
+    let mut merged = merged.unwrap();
+    merged.provenance = Some(Provenance::now());
+    merged.write(args.output_prefix, &output_directory)?;
 
     Ok(())
 }
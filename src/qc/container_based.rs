@@ -0,0 +1,6 @@
+//! All container-based quality control facets.
+//!
+//! Rather than processing records, these facets process the structure the
+//! records are stored in (e.g., the containers of a CRAM file).
+
+pub mod cram;
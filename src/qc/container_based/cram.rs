@@ -0,0 +1,459 @@
+//! Functionality related to the CRAM quality control facet.
+//!
+//! This facet reports how a CRAM file is stored rather than what it contains:
+//! the codecs its blocks are compressed with and its data series are encoded
+//! with, whether reference-based compression was used and, if so, whether the
+//! reference is embedded within the file or must be provided externally. CRAMs
+//! that require an external reference whose `@SQ` lines have no `M5` field
+//! cannot be decoded anywhere the exact reference is not at hand, and CRAMs
+//! compressed with the slower codecs (e.g., bzip2, LZMA, or fqzcomp) are much
+//! slower to decode. Both are common sources of trouble that are otherwise
+//! only discovered once decoding fails (or crawls).
+
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad},
+    utils::{
+        checksum::{self, Checksum},
+        formats::cram::{Block, CompressionHeader, Container, ContentType, Reader, SliceHeader},
+        merge::Merge,
+    },
+};
+
+/// The approximate cost of decoding each (uncompressed) byte of a block
+/// compressed with a method, relative to gzip. These are derived from the
+/// typical single-threaded decompression throughput of each codec and are
+/// only meant to rank files against one another.
+fn relative_decode_cost(method: &str) -> f64 {
+    match method {
+        "raw" => 0.1,
+        "rans4x8" | "ransNx16" => 0.5,
+        "gzip" => 1.0,
+        "arith" | "tok3" => 2.0,
+        "bzip2" => 4.0,
+        "fqzcomp" => 5.0,
+        "lzma" => 6.0,
+        _ => 1.0,
+    }
+}
+
+/// Gets the name of a data series codec from its ID.
+fn codec_name(id: i32) -> String {
+    match id {
+        0 => String::from("NULL"),
+        1 => String::from("EXTERNAL"),
+        2 => String::from("GOLOMB"),
+        3 => String::from("HUFFMAN"),
+        4 => String::from("BYTE_ARRAY_LEN"),
+        5 => String::from("BYTE_ARRAY_STOP"),
+        6 => String::from("BETA"),
+        7 => String::from("SUBEXP"),
+        8 => String::from("GOLOMB_RICE"),
+        9 => String::from("GAMMA"),
+        n => format!("UNKNOWN({})", n),
+    }
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// The blocks compressed with a compression method.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompressionMethodMetrics {
+    /// The number of blocks.
+    pub blocks: usize,
+
+    /// The total size of the blocks, as stored.
+    pub compressed_bytes: usize,
+
+    /// The total size of the blocks once decompressed.
+    pub uncompressed_bytes: usize,
+}
+
+/// Summary statistics for the CRAM quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// Whether any of the containers use reference-based compression.
+    pub reference_based: bool,
+
+    /// Whether any of the slices require the reference to be provided
+    /// externally (i.e., the slice is reference-based and the reference is
+    /// not embedded).
+    pub requires_external_reference: bool,
+
+    /// Whether the file requires an external reference that cannot be
+    /// verified, because some `@SQ` lines have no `M5` field.
+    pub non_portable: bool,
+
+    /// The estimated cost of decoding the file per uncompressed byte,
+    /// relative to a file compressed entirely with gzip.
+    pub estimated_decode_cost: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding the structure of CRAM files.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CramMetrics {
+    /// The version of the CRAM format (e.g., `3.0`).
+    pub version: String,
+
+    /// The number of data containers (excluding the file header and
+    /// end-of-file containers).
+    pub containers: usize,
+
+    /// The number of slices.
+    pub slices: usize,
+
+    /// The number of records.
+    pub records: usize,
+
+    /// The number of bases.
+    pub bases: usize,
+
+    /// The blocks compressed with each compression method.
+    pub compression_methods: BTreeMap<String, CompressionMethodMetrics>,
+
+    /// The number of data series encoded with each codec, summed across the
+    /// containers.
+    pub data_series_codecs: BTreeMap<String, usize>,
+
+    /// The number of containers that use reference-based compression.
+    pub reference_based_containers: usize,
+
+    /// The number of containers whose bases are stored without reference to
+    /// the reference sequence.
+    pub reference_free_containers: usize,
+
+    /// The number of slices in which the reference bases are embedded.
+    pub embedded_reference_slices: usize,
+
+    /// The number of reference-based slices that require the reference to be
+    /// provided externally.
+    pub external_reference_slices: usize,
+
+    /// The number of slices containing records on multiple sequences.
+    pub multi_reference_slices: usize,
+
+    /// The number of `@SQ` lines in the header without an `M5` field.
+    pub sequences_without_md5: usize,
+
+    /// The number of header blocks that could not be inspected because they
+    /// are compressed with a method other than raw or gzip.
+    pub uninspected_header_blocks: usize,
+
+    /// Whether the end-of-file container is missing, which usually means the
+    /// file was truncated.
+    pub missing_eof_container: bool,
+
+    /// Summary statistics for the CRAM quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl CramMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let (cost, uncompressed) = self.compression_methods.iter().fold(
+            (0.0, 0),
+            |(cost, uncompressed), (method, metrics)| {
+                (
+                    cost + relative_decode_cost(method) * metrics.uncompressed_bytes as f64,
+                    uncompressed + metrics.uncompressed_bytes,
+                )
+            },
+        );
+
+        let requires_external_reference = self.external_reference_slices > 0;
+
+        self.summary = Some(SummaryMetrics {
+            reference_based: self.reference_based_containers > 0,
+            requires_external_reference,
+            non_portable: requires_external_reference && self.sequences_without_md5 > 0,
+            estimated_decode_cost: (uncompressed > 0).then(|| cost / uncompressed as f64),
+        });
+    }
+}
+
+impl Merge for CompressionMethodMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.blocks += other.blocks;
+        self.compressed_bytes += other.compressed_bytes;
+        self.uncompressed_bytes += other.uncompressed_bytes;
+        Ok(())
+    }
+}
+
+impl Merge for CramMetrics {
+    /// The header is the same for every portion of a file, so the number of
+    /// `@SQ` lines without an `M5` field is not summed.
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.containers += other.containers;
+        self.slices += other.slices;
+        self.records += other.records;
+        self.bases += other.bases;
+        Merge::merge(&mut self.compression_methods, other.compression_methods)?;
+        Merge::merge(&mut self.data_series_codecs, other.data_series_codecs)?;
+        self.reference_based_containers += other.reference_based_containers;
+        self.reference_free_containers += other.reference_free_containers;
+        self.embedded_reference_slices += other.embedded_reference_slices;
+        self.external_reference_slices += other.external_reference_slices;
+        self.multi_reference_slices += other.multi_reference_slices;
+        self.sequences_without_md5 =
+            usize::max(self.sequences_without_md5, other.sequences_without_md5);
+        self.uninspected_header_blocks += other.uninspected_header_blocks;
+        self.missing_eof_container |= other.missing_eof_container;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the CRAM quality control facet.
+#[derive(Default)]
+pub struct CramFacet {
+    /// Metrics related to the CRAM quality control facet.
+    metrics: CramMetrics,
+
+    /// Whether the current container is reference-based (as declared by its
+    /// compression header).
+    reference_required: bool,
+}
+
+impl CramFacet {
+    /// Name of the quality control facet.
+    pub fn name(&self) -> &'static str {
+        "CRAM"
+    }
+
+    /// Computational load of the quality control facet.
+    pub fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    /// Reads the containers of the CRAM file at the provided path, returning
+    /// the checksum of the file.
+    pub fn process_file(&mut self, src: &Path) -> anyhow::Result<Checksum> {
+        let (file, checksum) = checksum::open(src)
+            .with_context(|| format!("Could not open CRAM file: {}", src.display()))?;
+
+        self.process(BufReader::new(file))
+            .with_context(|| format!("Could not read CRAM file: {}", src.display()))?;
+
+        Ok(checksum)
+    }
+
+    /// Reads the containers of a CRAM file.
+    pub fn process<R: Read>(&mut self, reader: R) -> anyhow::Result<()> {
+        let mut reader = Reader::new(reader);
+
+        let version = reader.read_file_definition()?;
+        self.metrics.version = format!("{}.{}", version.major, version.minor);
+
+        let mut eof = false;
+        while let Some(container) = reader.read_container()? {
+            eof = container.is_eof();
+            self.process_container(&container)?;
+        }
+
+        self.metrics.missing_eof_container = !eof;
+        Ok(())
+    }
+
+    /// Processes a container.
+    pub fn process_container(&mut self, container: &Container) -> anyhow::Result<()> {
+        // (1) The file header container only holds the SAM header, which is
+        // checked for `@SQ` lines without an `M5` field.
+        if container.is_file_header() {
+            if let Some(data) = container.blocks[0].decompress()? {
+                let text = String::from_utf8_lossy(data.get(4..).unwrap_or_default());
+                self.metrics.sequences_without_md5 = text
+                    .lines()
+                    .filter(|line| line.starts_with("@SQ") && !line.contains("\tM5:"))
+                    .count();
+            }
+            return Ok(());
+        }
+
+        if container.is_eof() {
+            return Ok(());
+        }
+
+        self.metrics.containers += 1;
+        self.metrics.slices += container.slice_count;
+        self.metrics.records += usize::try_from(container.record_count).unwrap_or_default();
+        self.metrics.bases += usize::try_from(container.base_count).unwrap_or_default();
+
+        // (2) Each of the blocks, of which only the headers are inspected.
+        for block in &container.blocks {
+            self.process_block(block)?;
+        }
+
+        Ok(())
+    }
+
+    /// Processes a block within a data container.
+    fn process_block(&mut self, block: &Block) -> anyhow::Result<()> {
+        let method = self
+            .metrics
+            .compression_methods
+            .entry(block.method.to_string())
+            .or_default();
+        method.blocks += 1;
+        method.compressed_bytes += block.data.len();
+        method.uncompressed_bytes += block.uncompressed_size;
+
+        let inspected = matches!(
+            block.content_type,
+            ContentType::CompressionHeader | ContentType::SliceHeader
+        );
+        let data = match (inspected, block.decompress()?) {
+            (true, Some(data)) => data,
+            (true, None) => {
+                self.metrics.uninspected_header_blocks += 1;
+                return Ok(());
+            }
+            (false, _) => return Ok(()),
+        };
+
+        match block.content_type {
+            ContentType::CompressionHeader => {
+                let header = CompressionHeader::parse(&data)
+                    .with_context(|| "Could not parse CRAM compression header.")?;
+
+                self.reference_required = header.reference_required;
+                match header.reference_required {
+                    true => self.metrics.reference_based_containers += 1,
+                    false => self.metrics.reference_free_containers += 1,
+                }
+
+                for (_, codec) in header.data_series_encodings {
+                    *self
+                        .metrics
+                        .data_series_codecs
+                        .entry(codec_name(codec))
+                        .or_default() += 1;
+                }
+            }
+            ContentType::SliceHeader => {
+                let header = SliceHeader::parse(&data)
+                    .with_context(|| "Could not parse CRAM slice header.")?;
+
+                if header.reference_sequence_id == -2 {
+                    self.metrics.multi_reference_slices += 1;
+                }
+
+                // Unmapped slices do not need a reference at all.
+                if header.embedded_reference_content_id >= 0 {
+                    self.metrics.embedded_reference_slices += 1;
+                } else if self.reference_required && header.reference_sequence_id != -1 {
+                    self.metrics.external_reference_slices += 1;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Summarizes the results of the quality control facet.
+    pub fn summarize(&mut self) {
+        self.metrics.summarize();
+    }
+
+    /// Adds the results of this quality control facet to the global
+    /// [`results::Results`] object for writing to a file.
+    pub fn aggregate(&self, results: &mut results::Results) {
+        results.cram = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{
+        cram, fasta,
+        sam::{
+            self,
+            header::record::value::{map::ReferenceSequence, Map},
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    pub fn it_reports_the_structure_of_a_cram_file() -> anyhow::Result<()> {
+        let header = sam::Header::builder()
+            .add_reference_sequence(
+                Map::<ReferenceSequence>::builder()
+                    .set_name("chr1".parse()?)
+                    .set_length(8)
+                    .set_md5_checksum("cc0af3a4fedb18378b4b57b98068e69f".parse()?)
+                    .build()?,
+            )
+            .build();
+        let repository = fasta::Repository::new(vec![fasta::Record::new(
+            fasta::record::Definition::new("chr1", None),
+            fasta::record::Sequence::from(b"ACGTACGT".to_vec()),
+        )]);
+
+        let mut writer = cram::writer::Builder::default()
+            .set_reference_sequence_repository(repository)
+            .build_with_writer(Vec::new());
+        writer.write_file_definition()?;
+        writer.write_file_header(&header)?;
+
+        let record = sam::alignment::Record::builder()
+            .set_reference_sequence_id(0)
+            .set_alignment_start(1.try_into()?)
+            .set_cigar("4M".parse()?)
+            .set_sequence("ACGT".parse()?)
+            .set_quality_scores("NNNN".parse()?)
+            .build();
+        for _ in 0..3 {
+            writer.write_record(
+                &header,
+                cram::Record::try_from_alignment_record(&header, &record)?,
+            )?;
+        }
+        writer.try_finish(&header)?;
+
+        let mut facet = CramFacet::default();
+        facet.process(&writer.get_ref()[..])?;
+        facet.summarize();
+
+        let metrics = &facet.metrics;
+        assert_eq!(metrics.version, "3.0");
+        assert_eq!(metrics.containers, 1);
+        assert_eq!(metrics.slices, 1);
+        assert_eq!(metrics.records, 3);
+        assert_eq!(metrics.reference_based_containers, 1);
+        assert_eq!(metrics.external_reference_slices, 1);
+        assert_eq!(metrics.sequences_without_md5, 0);
+        assert!(!metrics.missing_eof_container);
+        assert!(!metrics.data_series_codecs.is_empty());
+
+        let summary = metrics.summary.as_ref().unwrap();
+        assert!(summary.requires_external_reference);
+        assert!(!summary.non_portable);
+        assert!(summary.estimated_decode_cost.is_some());
+
+        // A file truncated at a container boundary (here, missing the 38-byte
+        // end-of-file container) is flagged.
+        let data = writer.get_ref();
+        let mut facet = CramFacet::default();
+        facet.process(&data[..data.len() - 38])?;
+        assert_eq!(facet.metrics.records, 3);
+        assert!(facet.metrics.missing_eof_container);
+
+        Ok(())
+    }
+}
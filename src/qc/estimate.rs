@@ -0,0 +1,265 @@
+//! Functionality related to the quick preview mode (`--estimate`) of the `ngs
+//! qc` subcommand.
+//!
+//! In this mode, the first pass only processes the first records in the file
+//! and the second pass only processes the smallest autosome present in the
+//! header. The headline metrics (coverage, duplication, and template length)
+//! are then extrapolated from this sample and reported with 95% confidence
+//! intervals, which is useful for triaging a file before committing to a full
+//! run.
+//!
+//! Note that the confidence intervals only reflect the sampling error within
+//! the sample: records at the start of a file are rarely a random sample of
+//! the file (e.g., a coordinate-sorted file starts with the first reference
+//! sequence), and coverage is assumed to be uniform across the genome.
+
+use std::rc::Rc;
+
+use noodles::sam::Header;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::genome::ReferenceGenome;
+
+use super::{results::Results, sequence_based::coverage::CoverageBin};
+
+/// Default number of records processed in the first pass in estimate mode.
+pub const DEFAULT_ESTIMATE_RECORDS: usize = 1_000_000;
+
+/// The critical value of the standard normal distribution for a 95% confidence
+/// interval.
+const Z_95: f64 = 1.959964;
+
+//=========//
+// Metrics //
+//=========//
+
+/// An extrapolated value along with its 95% confidence interval.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Estimate {
+    /// The estimated value.
+    pub value: f64,
+
+    /// The lower bound of the 95% confidence interval.
+    pub lower: f64,
+
+    /// The upper bound of the 95% confidence interval.
+    pub upper: f64,
+}
+
+impl Estimate {
+    /// Estimates a mean from the mean and standard deviation of `n` samples.
+    pub fn from_mean(mean: f64, standard_deviation: f64, n: usize) -> Option<Self> {
+        if n == 0 {
+            return None;
+        }
+
+        let margin = Z_95 * standard_deviation / (n as f64).sqrt();
+        Some(Self {
+            value: mean,
+            lower: (mean - margin).max(0.0),
+            upper: mean + margin,
+        })
+    }
+
+    /// Estimates a percentage from `successes` out of `n` trials, using the
+    /// Wilson score interval (which behaves well for percentages close to 0%
+    /// or 100%).
+    ///
+    /// ```
+    /// use ngs::qc::estimate::Estimate;
+    ///
+    /// let estimate = Estimate::from_proportion(25, 100).unwrap();
+    /// assert_eq!(estimate.value, 25.0);
+    /// assert!(estimate.lower < 25.0 && estimate.upper > 25.0);
+    /// assert_eq!(Estimate::from_proportion(0, 0), None);
+    /// ```
+    pub fn from_proportion(successes: usize, n: usize) -> Option<Self> {
+        if n == 0 {
+            return None;
+        }
+
+        let n = n as f64;
+        let p = successes as f64 / n;
+        let z2 = Z_95 * Z_95;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let margin = Z_95 / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+
+        Some(Self {
+            value: p * 100.0,
+            lower: (center - margin).max(0.0) * 100.0,
+            upper: (center + margin).min(1.0) * 100.0,
+        })
+    }
+}
+
+/// Headline metrics extrapolated from the sample processed in estimate mode.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EstimateMetrics {
+    /// The number of records sampled in the first pass.
+    pub records_sampled: usize,
+
+    /// The reference sequence sampled in the second pass.
+    pub sequence_sampled: Option<String>,
+
+    /// The estimated mean coverage, extrapolated from the mean coverage of the
+    /// bins on the sampled reference sequence.
+    pub mean_coverage: Option<Estimate>,
+
+    /// The estimated percentage of records marked as duplicate.
+    pub duplication_pct: Option<Estimate>,
+
+    /// The estimated mean template length of records with a known template
+    /// length.
+    pub mean_template_length: Option<Estimate>,
+}
+
+impl EstimateMetrics {
+    /// Extrapolates the headline metrics from the results computed over the
+    /// sample.
+    pub fn from_results(results: &Results, sequence_sampled: Option<String>) -> Self {
+        let mean_coverage = sequence_sampled.as_ref().and_then(|name| {
+            results
+                .coverage
+                .as_ref()
+                .and_then(|coverage| coverage.mean_coverage_per_bin.get(name))
+                .and_then(|bins| estimate_mean_coverage(bins))
+        });
+
+        let duplication_pct = results.general.as_ref().and_then(|general| {
+            Estimate::from_proportion(general.records.duplicate, general.records.total)
+        });
+
+        // Records with an unknown template length are tallied in the first bin
+        // of the histogram and are excluded.
+        let mean_template_length = results.template_length.as_ref().and_then(|facet| {
+            let samples = facet
+                .histogram
+                .values()
+                .iter()
+                .enumerate()
+                .skip(1)
+                .map(|(length, count)| (length as f64, *count));
+            estimate_weighted_mean(samples)
+        });
+
+        Self {
+            records_sampled: results.records_processed,
+            sequence_sampled,
+            mean_coverage,
+            duplication_pct,
+            mean_template_length,
+        }
+    }
+}
+
+/// Estimates the mean coverage from the mean coverage of each bin, weighting
+/// the bins by their length.
+fn estimate_mean_coverage(bins: &[CoverageBin]) -> Option<Estimate> {
+    let (mean, standard_deviation, _) = weighted_mean_and_standard_deviation(
+        bins.iter().map(|bin| (bin.mean, bin.end - bin.start + 1)),
+    )?;
+    Estimate::from_mean(mean, standard_deviation, bins.len())
+}
+
+/// Estimates a mean from values that were each observed `count` times.
+fn estimate_weighted_mean(samples: impl Iterator<Item = (f64, usize)>) -> Option<Estimate> {
+    let (mean, standard_deviation, n) = weighted_mean_and_standard_deviation(samples)?;
+    Estimate::from_mean(mean, standard_deviation, n)
+}
+
+/// Computes the mean, the (population) standard deviation, and the total
+/// weight of weighted values. If the total weight is zero, [`None`] is
+/// returned.
+fn weighted_mean_and_standard_deviation(
+    samples: impl Iterator<Item = (f64, usize)>,
+) -> Option<(f64, f64, usize)> {
+    let mut n = 0;
+    let mut sum = 0.0;
+    let mut sum_of_squares = 0.0;
+
+    for (value, weight) in samples {
+        n += weight;
+        sum += value * weight as f64;
+        sum_of_squares += value * value * weight as f64;
+    }
+
+    if n == 0 {
+        return None;
+    }
+
+    let mean = sum / n as f64;
+    let variance = (sum_of_squares / n as f64 - mean * mean).max(0.0);
+    Some((mean, variance.sqrt(), n))
+}
+
+//=================//
+// Sequence choice //
+//=================//
+
+/// Chooses the reference sequence sampled in the second pass: the smallest
+/// autosome of the reference genome that is present in the header. If none of
+/// the autosomes are present, the smallest sequence in the header is chosen.
+pub fn choose_sequence(
+    header: &Header,
+    reference_genome: Rc<Box<dyn ReferenceGenome>>,
+) -> Option<String> {
+    let autosomes = reference_genome.autosomes().unwrap_or_default();
+    let is_autosome = |name: &str| autosomes.iter().any(|s| s.name() == name);
+
+    let smallest = |autosomes_only: bool| {
+        header
+            .reference_sequences()
+            .iter()
+            .filter(|(name, _)| !autosomes_only || is_autosome(name.as_str()))
+            .min_by_key(|(_, sequence)| usize::from(sequence.length()))
+            .map(|(name, _)| name.to_string())
+    };
+
+    smallest(true).or_else(|| smallest(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::header::record::value::{map::ReferenceSequence, Map};
+
+    use crate::utils::genome::ncbi::grch38_no_alt::GRCh38NoAltAnalysisSet;
+
+    use super::*;
+
+    #[test]
+    pub fn it_chooses_the_smallest_autosome() -> anyhow::Result<()> {
+        let header = Header::builder()
+            .add_reference_sequence(Map::<ReferenceSequence>::new("chr1".parse()?, 1000)?)
+            .add_reference_sequence(Map::<ReferenceSequence>::new("chr2".parse()?, 500)?)
+            .add_reference_sequence(Map::<ReferenceSequence>::new("chrM".parse()?, 100)?)
+            .build();
+        let reference_genome: Rc<Box<dyn ReferenceGenome>> =
+            Rc::new(Box::new(GRCh38NoAltAnalysisSet));
+
+        assert_eq!(
+            choose_sequence(&header, reference_genome),
+            Some(String::from("chr2"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_estimates_means_with_confidence_intervals() {
+        let bin = |mean| CoverageBin {
+            seqname: String::from("chr1"),
+            start: 1,
+            end: 100,
+            mean,
+        };
+
+        let estimate =
+            estimate_mean_coverage(&[bin(10.0), bin(20.0), bin(30.0), bin(40.0)]).unwrap();
+        assert_eq!(estimate.value, 25.0);
+        assert!(estimate.lower < 25.0 && estimate.upper > 25.0);
+
+        let estimate = estimate_weighted_mean([(300.0, 10)].into_iter()).unwrap();
+        assert_eq!((estimate.lower, estimate.upper), (300.0, 300.0));
+        assert_eq!(estimate_weighted_mean(std::iter::empty()), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! Examples of the records that the quality control facets flag as problems.
+//!
+//! Counts of nonsensical records or invalid tags say that something is wrong,
+//! but not where. With `--examples <N>`, the facets that flag problems keep up
+//! to `N` offending records for each kind of problem, and these are written to
+//! `<prefix>.examples` so that the issues can be debugged without rerunning
+//! with filters. The file is tab-delimited, with the facet, the problem, the
+//! read name, and the position the record is aligned to (if any).
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use noodles::sam::{self, alignment::Record};
+
+/// A record that was flagged as a problem by a quality control facet.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Example {
+    /// The kind of problem (e.g., `nonsensical_record`).
+    pub problem: String,
+
+    /// The read name of the record.
+    pub read_name: String,
+
+    /// The reference sequence (by id) that the record is aligned to, if any.
+    pub reference_sequence_id: Option<usize>,
+
+    /// The (one-based) position that the record is aligned to, if any.
+    pub alignment_start: Option<usize>,
+}
+
+impl Example {
+    /// Creates an example of a problem from the record that exhibits it.
+    pub fn new(problem: &str, record: &Record) -> Self {
+        Self {
+            problem: problem.to_string(),
+            read_name: record
+                .read_name()
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| String::from("*")),
+            reference_sequence_id: record.reference_sequence_id(),
+            alignment_start: record.alignment_start().map(usize::from),
+        }
+    }
+}
+
+/// Keeps up to a limited number of examples of each kind of problem.
+#[derive(Debug, Default)]
+pub struct Examples {
+    /// The most examples that are kept for each kind of problem. With a limit
+    /// of zero, no examples are kept.
+    limit: usize,
+
+    /// The number of examples kept for each kind of problem.
+    counts: HashMap<String, usize>,
+
+    /// The examples, in the order they were found.
+    examples: Vec<Example>,
+}
+
+impl Examples {
+    /// Creates a new, empty [`Examples`] that keeps up to `limit` examples of
+    /// each kind of problem.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    /// Keeps the record as an example of the problem, unless the limit for
+    /// the problem has been reached.
+    pub fn record(&mut self, problem: &str, record: &Record) {
+        let count = self.counts.get(problem).copied().unwrap_or_default();
+        if count >= self.limit {
+            return;
+        }
+
+        self.counts.insert(problem.to_string(), count + 1);
+        self.examples.push(Example::new(problem, record));
+    }
+
+    /// Gets the examples that were kept.
+    pub fn examples(&self) -> &[Example] {
+        &self.examples
+    }
+}
+
+/// Writes the examples from each facet (keeping up to `limit` examples of each
+/// kind of problem from each facet) to a tab-delimited file. The names of the
+/// reference sequences are resolved from the header.
+pub fn write<'a, I>(path: &Path, header: &sam::Header, examples: I, limit: usize) -> io::Result<()>
+where
+    I: IntoIterator<Item = (&'a str, Example)>,
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "facet\tproblem\tread_name\tlocation")?;
+
+    let mut counts: HashMap<(&str, String), usize> = HashMap::new();
+    for (facet, example) in examples {
+        let count = counts.entry((facet, example.problem.clone())).or_default();
+        if *count >= limit {
+            continue;
+        }
+        *count += 1;
+
+        let name = example
+            .reference_sequence_id
+            .and_then(|id| header.reference_sequences().get_index(id))
+            .map(|(name, _)| name.to_string());
+        let location = match (name, example.alignment_start) {
+            (Some(name), Some(start)) => format!("{}:{}", name, start),
+            (Some(name), None) => name,
+            _ => String::from("*"),
+        };
+
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            facet, example.problem, example.read_name, location
+        )?;
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{
+        core::Position,
+        sam::record::{Flags, ReadName},
+    };
+
+    use super::*;
+
+    #[test]
+    pub fn it_keeps_and_writes_a_limited_number_of_examples() -> anyhow::Result<()> {
+        let record = |name: &str| -> anyhow::Result<Record> {
+            Ok(Record::builder()
+                .set_read_name(name.parse::<ReadName>()?)
+                .set_flags(Flags::empty())
+                .set_reference_sequence_id(1)
+                .set_alignment_start(Position::try_from(42)?)
+                .build())
+        };
+
+        let mut examples = Examples::new(2);
+        for name in ["a", "b", "c"] {
+            examples.record("nonsensical_record", &record(name)?);
+        }
+        examples.record("malformed", &record("d")?);
+        assert_eq!(examples.examples().len(), 3);
+        assert_eq!(examples.examples()[2].read_name, "d");
+        assert!(Examples::default().examples().is_empty());
+
+        let header: sam::Header = "@SQ\tSN:chr1\tLN:100\n@SQ\tSN:chr2\tLN:100\n".parse()?;
+        let path = std::env::temp_dir().join(format!("ngs-{}.examples", std::process::id()));
+        let rows = examples.examples().iter().cloned().map(|e| ("Coverage", e));
+        write(&path, &header, rows, 1)?;
+
+        let contents = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            contents,
+            "facet\tproblem\tread_name\tlocation\n\
+            Coverage\tnonsensical_record\ta\tchr2:42\n\
+            Coverage\tmalformed\td\tchr2:42\n"
+        );
+
+        Ok(())
+    }
+}
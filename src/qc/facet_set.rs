@@ -0,0 +1,262 @@
+//! Functionality related to a set of quality control facets that produce a
+//! single results document.
+//!
+//! Usually, a single set of facets sees every record in the file. When the
+//! results are split (e.g., with `--split-by sample`), each split has its own
+//! set of facets, and each record is only processed by the set it belongs to.
+
+use noodles::sam::{
+    alignment::Record,
+    header::record::value::{map::ReferenceSequence, Map},
+};
+
+use super::{
+    examples::Example,
+    results::{FilterMetrics, Progress, Results},
+    runtime::{FacetTimer, RuntimeMetrics},
+    shard::Shard,
+    RecordBasedQualityControlFacet, SequenceBasedQualityControlFacet,
+};
+use crate::utils::{filter::ReadFilter, provenance::Provenance};
+
+/// The number of records seen by a [`FacetSet`] during a pass.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RecordCounts {
+    /// The number of records that were read.
+    pub processed: usize,
+
+    /// The number of records that did not match the filter expression (if one
+    /// was provided) and, as such, were not processed by the facets.
+    pub excluded: usize,
+}
+
+/// A set of quality control facets (along with their timers) that produce a
+/// single results document.
+pub struct FacetSet<'a> {
+    /// The name of the split these facets belong to, if the results are split.
+    name: Option<String>,
+
+    /// The record-based facets, which are run in the first pass.
+    record_facets: Vec<Box<dyn RecordBasedQualityControlFacet + 'a>>,
+
+    /// The sequence-based facets, which are run in the second pass.
+    sequence_facets: Vec<Box<dyn SequenceBasedQualityControlFacet + 'a>>,
+
+    /// Timers (and spans) for each of the record-based facets.
+    record_timers: Vec<FacetTimer>,
+
+    /// Timers (and spans) for each of the sequence-based facets.
+    sequence_timers: Vec<FacetTimer>,
+
+    /// The records seen during the first pass.
+    first_pass: RecordCounts,
+
+    /// The records seen during the second pass.
+    second_pass: RecordCounts,
+}
+
+impl<'a> FacetSet<'a> {
+    /// Creates a new [`FacetSet`]. The timers are created once up front, as
+    /// entering an existing span is much cheaper than creating one for every
+    /// record.
+    pub fn new(
+        name: Option<String>,
+        record_facets: Vec<Box<dyn RecordBasedQualityControlFacet + 'a>>,
+        sequence_facets: Vec<Box<dyn SequenceBasedQualityControlFacet + 'a>>,
+    ) -> Self {
+        let record_timers = record_facets
+            .iter()
+            .map(|f| FacetTimer::new(f.name()))
+            .collect();
+        let sequence_timers = sequence_facets
+            .iter()
+            .map(|f| FacetTimer::new(f.name()))
+            .collect();
+
+        Self {
+            name,
+            record_facets,
+            sequence_facets,
+            record_timers,
+            sequence_timers,
+            first_pass: RecordCounts::default(),
+            second_pass: RecordCounts::default(),
+        }
+    }
+
+    /// Gets the name of the split these facets belong to, if the results are
+    /// split.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Gets the record-based facets.
+    pub fn record_facets(&self) -> &[Box<dyn RecordBasedQualityControlFacet + 'a>] {
+        &self.record_facets
+    }
+
+    /// Gets the sequence-based facets.
+    pub fn sequence_facets(&self) -> &[Box<dyn SequenceBasedQualityControlFacet + 'a>] {
+        &self.sequence_facets
+    }
+
+    /// Gets the examples of the records flagged as problems by each of the
+    /// facets, along with the name of the facet that flagged them.
+    pub fn examples(&self) -> Vec<(&'static str, Example)> {
+        let record_based = self
+            .record_facets
+            .iter()
+            .flat_map(|facet| facet.examples().into_iter().map(|e| (facet.name(), e)));
+        let sequence_based = self
+            .sequence_facets
+            .iter()
+            .flat_map(|facet| facet.examples().into_iter().map(|e| (facet.name(), e)));
+
+        record_based.chain(sequence_based).collect()
+    }
+
+    /// Gets the records seen by this set of facets. The first pass, which is
+    /// run if there are any record-based facets, is the canonical count.
+    pub fn counts(&self) -> RecordCounts {
+        if self.record_facets.is_empty() {
+            self.second_pass
+        } else {
+            self.first_pass
+        }
+    }
+
+    //============//
+    // First pass //
+    //============//
+
+    /// Processes a record with each of the record-based facets. If the record
+    /// did not match the filter expression, it is only counted.
+    pub fn process(&mut self, record: &Record, passes_filter: bool) -> anyhow::Result<()> {
+        self.first_pass.processed += 1;
+
+        if !passes_filter {
+            self.first_pass.excluded += 1;
+            return Ok(());
+        }
+
+        for (facet, timer) in self.record_facets.iter_mut().zip(&mut self.record_timers) {
+            timer.time_record(|| facet.process(record))?;
+        }
+
+        Ok(())
+    }
+
+    /// Summarizes each of the record-based facets.
+    pub fn summarize(&mut self) -> anyhow::Result<()> {
+        for (facet, timer) in self.record_facets.iter_mut().zip(&mut self.record_timers) {
+            timer.time(|| facet.summarize())?;
+        }
+
+        Ok(())
+    }
+
+    //=============//
+    // Second pass //
+    //=============//
+
+    /// Sets up each of the sequence-based facets that support the sequence.
+    /// Returns whether any of the facets support the sequence.
+    pub fn setup(&mut self, seq: &Map<ReferenceSequence>) -> anyhow::Result<bool> {
+        let mut supported = false;
+        for (facet, timer) in self
+            .sequence_facets
+            .iter_mut()
+            .zip(&mut self.sequence_timers)
+        {
+            if facet.supports_sequence_name(seq.name()) {
+                timer.time(|| facet.setup(seq))?;
+                supported = true;
+            }
+        }
+
+        Ok(supported)
+    }
+
+    /// Processes a record with each of the sequence-based facets that support
+    /// the sequence. If the record did not match the filter expression, it is
+    /// only counted.
+    pub fn process_sequence_record(
+        &mut self,
+        seq: &Map<ReferenceSequence>,
+        record: &Record,
+        passes_filter: bool,
+    ) -> anyhow::Result<()> {
+        self.second_pass.processed += 1;
+
+        if !passes_filter {
+            self.second_pass.excluded += 1;
+            return Ok(());
+        }
+
+        for (facet, timer) in self
+            .sequence_facets
+            .iter_mut()
+            .zip(&mut self.sequence_timers)
+        {
+            if facet.supports_sequence_name(seq.name()) {
+                timer.time_record(|| facet.process(seq, record))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Tears down each of the sequence-based facets that support the sequence.
+    pub fn teardown(&mut self, seq: &Map<ReferenceSequence>) -> anyhow::Result<()> {
+        for (facet, timer) in self
+            .sequence_facets
+            .iter_mut()
+            .zip(&mut self.sequence_timers)
+        {
+            if facet.supports_sequence_name(seq.name()) {
+                timer.time(|| facet.teardown(seq))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    //=========//
+    // Results //
+    //=========//
+
+    /// Aggregates the results of each of the facets (and their runtimes).
+    pub fn results(
+        &mut self,
+        shard: Option<Shard>,
+        filter: Option<&ReadFilter>,
+        progress: Option<Progress>,
+    ) -> Results {
+        let counts = self.counts();
+        let mut runtime = RuntimeMetrics::default();
+        let mut results = Results {
+            records_processed: counts.processed,
+            filter: filter.map(|f| FilterMetrics {
+                expression: f.to_string(),
+                records_excluded: counts.excluded,
+            }),
+            progress,
+            shard,
+            provenance: Some(Provenance::now()),
+            ..Default::default()
+        };
+
+        for (facet, timer) in self.record_facets.iter().zip(&self.record_timers) {
+            facet.aggregate(&mut results);
+            timer.aggregate(&mut runtime);
+        }
+
+        for (facet, timer) in self.sequence_facets.iter_mut().zip(&self.sequence_timers) {
+            facet.aggregate(&mut results);
+            timer.aggregate(&mut runtime);
+        }
+
+        results.runtime = Some(runtime);
+        results
+    }
+}
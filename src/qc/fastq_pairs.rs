@@ -0,0 +1,173 @@
+//! Functionality related to checking that paired FASTQ files are synchronized.
+//!
+//! Paired-end FASTQ files are expected to contain the mates of each template at
+//! the same position within each file. Aligners generally assume (and do not
+//! verify) this, so files that have fallen out of sync (e.g., because one file
+//! was truncated or filtered independently of the other) silently produce
+//! nonsensical alignments.
+
+use std::io;
+
+use noodles::fastq;
+use serde::Serialize;
+
+use crate::utils::{checksum::InputChecksum, provenance::Provenance};
+
+/// Gets the name of the template a FASTQ record belongs to by removing the
+/// comment and any `/1` or `/2` mate suffix from the record's name.
+///
+/// ```
+/// use ngs::qc::fastq_pairs::template_name;
+///
+/// assert_eq!(template_name(b"read1/1"), b"read1");
+/// assert_eq!(template_name(b"read1 1:N:0:ACGT"), b"read1");
+/// assert_eq!(template_name(b"read1"), b"read1");
+/// ```
+pub fn template_name(name: &[u8]) -> &[u8] {
+    let name = match name.iter().position(|b| b.is_ascii_whitespace()) {
+        Some(i) => &name[..i],
+        None => name,
+    };
+
+    match name {
+        [rest @ .., b'/', b'1' | b'2'] => rest,
+        _ => name,
+    }
+}
+
+/// The first pair of records whose names did not match.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Divergence {
+    /// The (one-based) number of the record within each file.
+    pub record_number: usize,
+
+    /// The name of the record in the first file, if the file had not ended.
+    pub read_one_name: Option<String>,
+
+    /// The name of the record in the second file, if the file had not ended.
+    pub read_two_name: Option<String>,
+}
+
+/// The results of checking two paired FASTQ files.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PairCheckResult {
+    /// Whether the files are synchronized.
+    pub synchronized: bool,
+
+    /// The number of records in the first file.
+    pub read_one_records: usize,
+
+    /// The number of records in the second file.
+    pub read_two_records: usize,
+
+    /// The number of pairs of records whose names did not match.
+    pub mismatched_pairs: usize,
+
+    /// The first divergence between the files, if any. If one file has more
+    /// records than the other, the first record without a mate counts as a
+    /// divergence.
+    pub first_divergence: Option<Divergence>,
+
+    /// The checksums of the files (only computed with `--checksums`).
+    pub input_checksums: Option<Vec<InputChecksum>>,
+
+    /// The invocation of `ngs` that produced the results.
+    pub provenance: Option<Provenance>,
+}
+
+/// Checks that the records within paired FASTQ files are synchronized: each
+/// file must contain the same number of records and the record at each
+/// position must belong to the same template.
+pub fn check_pairs<A, B>(
+    mut read_one: fastq::Reader<A>,
+    mut read_two: fastq::Reader<B>,
+) -> io::Result<PairCheckResult>
+where
+    A: io::BufRead,
+    B: io::BufRead,
+{
+    let mut result = PairCheckResult::default();
+    let mut first = fastq::Record::default();
+    let mut second = fastq::Record::default();
+
+    loop {
+        let has_first = read_one.read_record(&mut first)? > 0;
+        let has_second = read_two.read_record(&mut second)? > 0;
+
+        if !has_first && !has_second {
+            break;
+        }
+
+        result.read_one_records += usize::from(has_first);
+        result.read_two_records += usize::from(has_second);
+
+        let matches =
+            has_first && has_second && template_name(first.name()) == template_name(second.name());
+
+        if !matches {
+            if has_first && has_second {
+                result.mismatched_pairs += 1;
+            }
+
+            if result.first_divergence.is_none() {
+                let name = |present: bool, record: &fastq::Record| {
+                    present.then(|| String::from_utf8_lossy(record.name()).to_string())
+                };
+
+                result.first_divergence = Some(Divergence {
+                    record_number: result.read_one_records.max(result.read_two_records),
+                    read_one_name: name(has_first, &first),
+                    read_two_name: name(has_second, &second),
+                });
+            }
+        }
+    }
+
+    result.synchronized = result.first_divergence.is_none();
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(names: &[&str]) -> fastq::Reader<io::Cursor<Vec<u8>>> {
+        let contents: String = names
+            .iter()
+            .map(|name| format!("@{}\nACGT\n+\nIIII\n", name))
+            .collect();
+        fastq::Reader::new(io::Cursor::new(contents.into_bytes()))
+    }
+
+    #[test]
+    pub fn it_checks_synchronized_pairs() -> io::Result<()> {
+        let result = check_pairs(reader(&["r1/1", "r2/1"]), reader(&["r1/2", "r2/2"]))?;
+        assert!(result.synchronized);
+        assert_eq!(result.read_one_records, 2);
+        assert_eq!(result.read_two_records, 2);
+        assert_eq!(result.first_divergence, None);
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_reports_the_first_divergence_and_counts() -> io::Result<()> {
+        let result = check_pairs(
+            reader(&["r1 1:N:0:A", "r2 1:N:0:A", "r3 1:N:0:A"]),
+            reader(&["r1 2:N:0:A", "r3 2:N:0:A"]),
+        )?;
+
+        assert!(!result.synchronized);
+        assert_eq!(result.read_one_records, 3);
+        assert_eq!(result.read_two_records, 2);
+        assert_eq!(result.mismatched_pairs, 1);
+        assert_eq!(
+            result.first_divergence,
+            Some(Divergence {
+                record_number: 2,
+                read_one_name: Some(String::from("r2 1:N:0:A")),
+                read_two_name: Some(String::from("r3 2:N:0:A")),
+            })
+        );
+        Ok(())
+    }
+}
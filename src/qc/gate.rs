@@ -0,0 +1,296 @@
+//! Gating of `ngs qc` results against thresholds.
+//!
+//! A thresholds file is a JSON object mapping metrics (the dotted path to the
+//! metric within the results, e.g., `general.summary.mapped_pct`) to their
+//! bounds:
+//!
+//! ```json
+//! {
+//!   "general.summary.mapped_pct": { "min": 90 },
+//!   "general.summary.duplication_pct": { "max": 30 }
+//! }
+//! ```
+//!
+//! With `--strict-qc`, a one-line summary for each results document is
+//! printed to stdout and `ngs` exits with a code that identifies the kind of
+//! failure (see [`Status::exit_code`]), so that workflow engines can gate
+//! downstream steps without parsing the results.
+
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::results::Results;
+
+//============//
+// Thresholds //
+//============//
+
+/// The bounds that a single metric must fall within (inclusive).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bounds {
+    /// The minimum value of the metric, if any.
+    pub min: Option<f64>,
+
+    /// The maximum value of the metric, if any.
+    pub max: Option<f64>,
+}
+
+impl fmt::Display for Bounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => write!(f, "{} to {}", min, max),
+            (Some(min), None) => write!(f, "min {}", min),
+            (None, Some(max)) => write!(f, "max {}", max),
+            (None, None) => write!(f, "unbounded"),
+        }
+    }
+}
+
+/// The thresholds for each metric, keyed by the dotted path to the metric.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Thresholds(pub BTreeMap<String, Bounds>);
+
+impl Thresholds {
+    /// Reads the thresholds from a JSON file.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read thresholds file: {}", path.display()))?;
+        let thresholds: Self = serde_json::from_str(&contents)
+            .with_context(|| format!("Could not parse thresholds file: {}", path.display()))?;
+
+        for (metric, bounds) in &thresholds.0 {
+            match (bounds.min, bounds.max) {
+                (None, None) => bail!("Threshold for {} has neither a min nor a max.", metric),
+                (Some(min), Some(max)) if min > max => {
+                    bail!("Threshold for {} has a min greater than its max.", metric)
+                }
+                _ => {}
+            }
+        }
+
+        Ok(thresholds)
+    }
+
+    /// Checks each metric of the results against its bounds.
+    pub fn evaluate(&self, name: &str, results: &Results) -> anyhow::Result<Evaluation> {
+        let document = serde_json::to_value(results)?;
+
+        let checks = self
+            .0
+            .iter()
+            .map(|(metric, bounds)| {
+                let value = metric
+                    .split('.')
+                    .try_fold(&document, |value, key| value.get(key))
+                    .and_then(Value::as_f64);
+
+                let outcome = match value {
+                    None => Outcome::Missing,
+                    Some(v) if bounds.min.is_some_and(|min| v < min) => Outcome::Fail,
+                    Some(v) if bounds.max.is_some_and(|max| v > max) => Outcome::Fail,
+                    Some(_) => Outcome::Pass,
+                };
+
+                MetricCheck {
+                    metric: metric.clone(),
+                    value,
+                    bounds: bounds.clone(),
+                    outcome,
+                }
+            })
+            .collect();
+
+        Ok(Evaluation {
+            name: name.to_string(),
+            checks,
+        })
+    }
+}
+
+//============//
+// Evaluation //
+//============//
+
+/// The outcome of checking a single metric.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The metric was within its bounds.
+    Pass,
+
+    /// The metric was outside of its bounds.
+    Fail,
+
+    /// The metric was not found in the results (e.g., because the facet that
+    /// computes it did not run) or was not a number.
+    Missing,
+}
+
+/// The result of checking a single metric.
+#[derive(Clone, Debug)]
+pub struct MetricCheck {
+    /// The dotted path to the metric.
+    pub metric: String,
+
+    /// The value of the metric, if it was found.
+    pub value: Option<f64>,
+
+    /// The bounds of the metric.
+    pub bounds: Bounds,
+
+    /// The outcome of the check.
+    pub outcome: Outcome,
+}
+
+/// The overall status of one or more results documents, from least to most
+/// severe.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Status {
+    /// Every metric was within its bounds.
+    Pass,
+
+    /// No metric was outside of its bounds, but at least one was missing.
+    Missing,
+
+    /// At least one metric was outside of its bounds.
+    Fail,
+}
+
+impl Status {
+    /// The code `ngs` exits with. These are distinct from the code for any
+    /// other error (1) and for invalid arguments (2).
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Status::Pass => 0,
+            Status::Fail => 3,
+            Status::Missing => 4,
+        }
+    }
+}
+
+/// The result of checking the metrics of a results document.
+#[derive(Clone, Debug)]
+pub struct Evaluation {
+    /// The name of the results document (i.e., its output prefix).
+    pub name: String,
+
+    /// The check of each metric.
+    pub checks: Vec<MetricCheck>,
+}
+
+impl Evaluation {
+    /// The overall status of the results document.
+    pub fn status(&self) -> Status {
+        let outcomes = || self.checks.iter().map(|check| check.outcome);
+
+        if outcomes().any(|o| o == Outcome::Fail) {
+            Status::Fail
+        } else if outcomes().any(|o| o == Outcome::Missing) {
+            Status::Missing
+        } else {
+            Status::Pass
+        }
+    }
+
+    /// Each metric that did not pass, described briefly.
+    pub fn problems(&self) -> Vec<String> {
+        self.checks
+            .iter()
+            .filter_map(|check| match (check.outcome, check.value) {
+                (Outcome::Fail, Some(value)) => {
+                    Some(format!("{} = {} ({})", check.metric, value, check.bounds))
+                }
+                (Outcome::Missing, _) => Some(format!("{} missing", check.metric)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The one-line summary printed with `--strict-qc`.
+    pub fn summary(&self) -> String {
+        match self.status() {
+            Status::Pass => format!(
+                "PASS {}: {} of {} metrics within thresholds",
+                self.name,
+                self.checks.len(),
+                self.checks.len()
+            ),
+            _ => format!("FAIL {}: {}", self.name, self.problems().join("; ")),
+        }
+    }
+}
+
+/// The error returned when results do not pass their thresholds with
+/// `--strict-qc` (or do not match their baseline with `--diff`, see
+/// [`baseline`](super::baseline)). `ngs` exits with the code for its status.
+#[derive(Debug)]
+pub struct GateFailure {
+    /// The most severe status across the results documents.
+    pub status: Status,
+}
+
+impl fmt::Display for GateFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            Status::Missing => write!(f, "one or more gated metrics were not computed"),
+            _ => write!(
+                f,
+                "one or more metrics were outside of their thresholds or drifted from the baseline"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GateFailure {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_checks_metrics_against_their_thresholds() -> anyhow::Result<()> {
+        let results = Results {
+            records_processed: 100,
+            ..Default::default()
+        };
+        let bounds = |min: Option<f64>, max: Option<f64>| Bounds { min, max };
+
+        let thresholds = Thresholds(BTreeMap::from([(
+            String::from("records_processed"),
+            bounds(Some(50.0), None),
+        )]));
+        let evaluation = thresholds.evaluate("sample", &results)?;
+        assert_eq!(evaluation.status(), Status::Pass);
+        assert_eq!(
+            evaluation.summary(),
+            "PASS sample: 1 of 1 metrics within thresholds"
+        );
+
+        let thresholds = Thresholds(BTreeMap::from([
+            (String::from("records_processed"), bounds(None, Some(50.0))),
+            (
+                String::from("general.summary.mapped_pct"),
+                bounds(Some(90.0), None),
+            ),
+        ]));
+        let evaluation = thresholds.evaluate("sample", &results)?;
+        assert_eq!(evaluation.status(), Status::Fail);
+        assert_eq!(
+            evaluation.summary(),
+            "FAIL sample: general.summary.mapped_pct missing; records_processed = 100 (max 50)"
+        );
+
+        let thresholds = Thresholds(BTreeMap::from([(
+            String::from("general.summary.mapped_pct"),
+            bounds(Some(90.0), None),
+        )]));
+        let evaluation = thresholds.evaluate("sample", &results)?;
+        assert_eq!(evaluation.status(), Status::Missing);
+        assert_eq!(evaluation.status().exit_code(), 4);
+
+        Ok(())
+    }
+}
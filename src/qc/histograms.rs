@@ -0,0 +1,224 @@
+//! Exporting the histograms within a results document for plotting tools.
+//!
+//! Within the results document, each histogram is serialized as its internal
+//! structure (a range and a vector of values, which may be run-length
+//! encoded), which plotting tools cannot read without a format-specific shim.
+//! With `--histogram-format`, every histogram is additionally exported in a
+//! common "long" format, with one row per bin:
+//!
+//! * `histogram`, the path to the histogram within the results document
+//!   (e.g., `quality_scores.mean_score_histogram`).
+//! * `bin_start` and `bin_end`, the (half-open) range of values in the bin.
+//! * `count`, the number of values in the bin.
+//!
+//! Bins with no values are omitted, so that sparse histograms (e.g., of long
+//! read lengths) stay small.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::bail;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::utils::histogram::{run_length_encoded, Histogram};
+
+/// The format that the histograms are exported in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HistogramFormat {
+    /// A tab-delimited file with a header row.
+    Tsv,
+
+    /// A JSON array of objects.
+    Json,
+}
+
+impl FromStr for HistogramFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tsv" => Ok(Self::Tsv),
+            "json" => Ok(Self::Json),
+            _ => bail!("unknown histogram format: {}", s),
+        }
+    }
+}
+
+impl HistogramFormat {
+    /// The file extension for the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Tsv => "tsv",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// A single bin of a histogram, in long format.
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct Bin {
+    /// The path to the histogram within the results document.
+    pub histogram: String,
+
+    /// The first value in the bin.
+    pub bin_start: usize,
+
+    /// The value just past the last value in the bin.
+    pub bin_end: usize,
+
+    /// The number of values in the bin.
+    pub count: usize,
+}
+
+/// Attempts to read a histogram (in either its plain or run-length encoded
+/// form) from a value within the results document.
+fn as_histogram(value: &Value) -> Option<Histogram> {
+    let object = value.as_object()?;
+    if !object.contains_key("range_start") || !object.contains_key("range_stop") {
+        return None;
+    }
+
+    match (object.contains_key("values"), object.contains_key("runs")) {
+        (true, false) => serde_json::from_value(value.clone()).ok(),
+        (false, true) => run_length_encoded::deserialize(value.clone()).ok(),
+        _ => None,
+    }
+}
+
+/// Collects the bins of every histogram found beneath a value, where `path`
+/// is the path to the value within the results document.
+fn collect(path: &str, value: &Value, bins: &mut Vec<Bin>) {
+    if let Some(histogram) = as_histogram(value) {
+        let start = histogram.range_start();
+        for (i, count) in histogram.values().iter().enumerate() {
+            if *count > 0 {
+                bins.push(Bin {
+                    histogram: path.to_string(),
+                    bin_start: start + i,
+                    bin_end: start + i + 1,
+                    count: *count,
+                });
+            }
+        }
+        return;
+    }
+
+    let join = |key: &str| match path {
+        "" => key.to_string(),
+        _ => format!("{}.{}", path, key),
+    };
+
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                collect(&join(key), value, bins);
+            }
+        }
+        Value::Array(array) => {
+            for (i, value) in array.iter().enumerate() {
+                collect(&join(&i.to_string()), value, bins);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Gets the bins of every histogram within a (serialized) results document.
+pub fn bins<T: Serialize>(results: &T) -> anyhow::Result<Vec<Bin>> {
+    let mut bins = Vec::new();
+    collect("", &serde_json::to_value(results)?, &mut bins);
+    Ok(bins)
+}
+
+/// Writes the bins of every histogram within a results document to a file in
+/// the provided format.
+pub fn write<T: Serialize>(
+    path: &Path,
+    results: &T,
+    format: HistogramFormat,
+) -> anyhow::Result<()> {
+    let bins = bins(results)?;
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match format {
+        HistogramFormat::Tsv => {
+            writeln!(writer, "histogram\tbin_start\tbin_end\tcount")?;
+            for bin in &bins {
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}\t{}",
+                    bin.histogram, bin.bin_start, bin.bin_end, bin.count
+                )?;
+            }
+        }
+        HistogramFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &bins)?;
+            writeln!(writer)?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_exports_the_histograms_in_long_format() -> anyhow::Result<()> {
+        #[derive(Serialize)]
+        struct Document {
+            plain: Histogram,
+            #[serde(with = "run_length_encoded")]
+            encoded: Histogram,
+            nested: Vec<Option<Histogram>>,
+            other: usize,
+        }
+
+        let mut plain = Histogram::zero_based_with_capacity(3);
+        plain.increment_by(1, 5).unwrap();
+        plain.increment(3).unwrap();
+        let mut encoded = Histogram::zero_based_with_capacity(100);
+        encoded.increment(42).unwrap();
+
+        let document = Document {
+            plain,
+            encoded: encoded.clone(),
+            nested: vec![None, Some(encoded)],
+            other: 7,
+        };
+
+        let bins = bins(&document)?;
+        let rows = bins
+            .iter()
+            .map(|bin| {
+                (
+                    bin.histogram.as_str(),
+                    bin.bin_start,
+                    bin.bin_end,
+                    bin.count,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            [
+                ("plain", 1, 2, 5),
+                ("plain", 3, 4, 1),
+                ("encoded", 42, 43, 1),
+                ("nested.1", 42, 43, 1),
+            ]
+        );
+
+        assert_eq!("json".parse::<HistogramFormat>()?, HistogramFormat::Json);
+        assert!("csv".parse::<HistogramFormat>().is_err());
+
+        Ok(())
+    }
+}
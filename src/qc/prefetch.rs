@@ -0,0 +1,214 @@
+//! Functionality related to prefetching the records of the second pass.
+//!
+//! The second pass queries the records of each sequence through the index. On
+//! network file systems (e.g., Lustre or NFS), each seek is expensive, and the
+//! facets sit idle while the reader waits on the file system. The
+//! [`Prefetcher`] reads the records for each of the sequences on a background
+//! thread with its own reader, sending them to the facets in batches through a
+//! bounded channel. This keeps reads (and the seek to the next sequence)
+//! running while the facets process the records that have already been read,
+//! while bounding the number of records held in memory. Consumed batches are
+//! sent back to the background thread and decoded into again, so records are
+//! not allocated for every read.
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
+    thread,
+};
+
+use anyhow::{bail, Context};
+use noodles::{
+    bam::{self, bai},
+    core::{Position, Region},
+    sam::alignment::Record,
+};
+
+use crate::utils::formats::bam::QueryCursor;
+
+/// The default number of records in each batch sent to the facets.
+pub const DEFAULT_BATCH_SIZE: usize = 4096;
+
+/// The default number of batches that are read ahead of the facets.
+pub const DEFAULT_BATCHES_IN_FLIGHT: usize = 8;
+
+/// A message sent from the background reader to the facets.
+enum Message {
+    /// A batch of records for the current sequence.
+    Records(Vec<Record>),
+
+    /// All of the records for the current sequence have been sent.
+    EndOfSequence,
+
+    /// Reading failed, after which no more messages are sent.
+    Error(anyhow::Error),
+}
+
+/// Reads the records of a list of sequences on a background thread.
+pub struct Prefetcher {
+    /// The messages sent by the background reader.
+    receiver: Receiver<Message>,
+
+    /// Returns consumed batches to the background reader so that the records
+    /// (and their buffers) are reused, if enabled.
+    recycle: Option<Sender<Vec<Record>>>,
+}
+
+impl Prefetcher {
+    /// Spawns a background thread that reads the records of each of the
+    /// sequences (in order) from the BAM file at `src`, reading at most
+    /// `batches_in_flight` batches of `batch_size` records ahead of the
+    /// consumer. If `reuse` is `true`, consumed batches are returned to the
+    /// background thread and decoded into again.
+    pub fn spawn(
+        src: PathBuf,
+        sequences: Vec<String>,
+        batch_size: usize,
+        batches_in_flight: usize,
+        reuse: bool,
+    ) -> Self {
+        let (sender, receiver) = sync_channel(batches_in_flight);
+        let (recycle, recycled) = channel();
+
+        thread::spawn(move || {
+            if let Err(e) = read_sequences(&src, &sequences, batch_size, &sender, &recycled) {
+                let _ = sender.send(Message::Error(e));
+            }
+        });
+
+        Self {
+            receiver,
+            recycle: reuse.then_some(recycle),
+        }
+    }
+
+    /// Calls `f` with each of the records of the next sequence.
+    pub fn for_each_sequence_record<F>(&mut self, mut f: F) -> anyhow::Result<()>
+    where
+        F: FnMut(&Record) -> anyhow::Result<()>,
+    {
+        loop {
+            match self.receiver.recv() {
+                Ok(Message::Records(batch)) => {
+                    for record in &batch {
+                        f(record)?;
+                    }
+
+                    if let Some(recycle) = &self.recycle {
+                        // The background reader may already be done.
+                        let _ = recycle.send(batch);
+                    }
+                }
+                Ok(Message::EndOfSequence) => return Ok(()),
+                Ok(Message::Error(e)) => return Err(e),
+                Err(_) => bail!("The prefetching thread stopped unexpectedly."),
+            }
+        }
+    }
+}
+
+/// Reads the records of each of the sequences, sending them in batches. Batches
+/// returned by the consumer are decoded into again rather than allocating new
+/// records. Reading stops early (without error) if the consumer has gone away.
+fn read_sequences(
+    src: &Path,
+    sequences: &[String],
+    batch_size: usize,
+    sender: &SyncSender<Message>,
+    recycled: &Receiver<Vec<Record>>,
+) -> anyhow::Result<()> {
+    let mut reader = File::open(src).map(bam::Reader::new)?;
+    reader.read_header()?;
+    let reference_sequences = reader.read_reference_sequences()?;
+    let index = bai::read(src.with_extension("bam.bai")).with_context(|| "bam index")?;
+
+    let next_batch = || {
+        recycled
+            .try_recv()
+            .unwrap_or_else(|_| Vec::with_capacity(batch_size))
+    };
+
+    for name in sequences {
+        let seq = reference_sequences
+            .get(name)
+            .with_context(|| format!("sequence {} not found in the header", name))?;
+        let end = Position::try_from(usize::from(seq.length()))?;
+        let region = Region::new(name.as_str(), Position::MIN..=end);
+        let mut cursor = QueryCursor::try_new(&mut reader, &reference_sequences, &index, &region)?;
+
+        let mut batch = next_batch();
+        let mut filled = 0;
+        loop {
+            if filled == batch.len() {
+                batch.push(Record::default());
+            }
+
+            if !cursor.read_record(&mut batch[filled])? {
+                break;
+            }
+
+            filled += 1;
+            if filled == batch_size {
+                let full = std::mem::replace(&mut batch, next_batch());
+                if sender.send(Message::Records(full)).is_err() {
+                    return Ok(());
+                }
+                filled = 0;
+            }
+        }
+
+        batch.truncate(filled);
+        if !batch.is_empty() && sender.send(Message::Records(batch)).is_err() {
+            return Ok(());
+        }
+
+        if sender.send(Message::EndOfSequence).is_err() {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_splits_the_prefetched_records_by_sequence() -> anyhow::Result<()> {
+        let (sender, receiver) = sync_channel(8);
+        let (recycle, recycled) = channel();
+        let mut prefetcher = Prefetcher {
+            receiver,
+            recycle: Some(recycle),
+        };
+
+        sender.send(Message::Records(vec![Record::default(); 2]))?;
+        sender.send(Message::Records(vec![Record::default()]))?;
+        sender.send(Message::EndOfSequence)?;
+        sender.send(Message::EndOfSequence)?;
+        sender.send(Message::Records(vec![Record::default()]))?;
+        sender.send(Message::Error(anyhow::anyhow!("truncated file")))?;
+
+        let mut count = 0;
+        prefetcher.for_each_sequence_record(|_| {
+            count += 1;
+            Ok(())
+        })?;
+        assert_eq!(count, 3);
+        assert_eq!(recycled.try_iter().count(), 2);
+
+        prefetcher.for_each_sequence_record(|_| panic!("the sequence is empty"))?;
+
+        let mut count = 0;
+        let result = prefetcher.for_each_sequence_record(|_| {
+            count += 1;
+            Ok(())
+        });
+        assert_eq!(count, 1);
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+//! Profiles that tailor the quality control facets to a sequencing technology.
+//!
+//! By default, `ngs qc` runs the facets that suit short-read (Illumina) data.
+//! With `--profile long-read`, the facets that only make sense for short reads
+//! are dropped, the Long Reads facet is added, and long-read mode is enabled
+//! (as with `--long-reads`).
+
+use std::str::FromStr;
+
+use anyhow::bail;
+
+/// The facets that only make sense for short-read data: long reads are not
+/// paired, are not sequenced on Illumina flowcells, and are not expected to
+/// contain Illumina adapters.
+pub const SHORT_READ_ONLY_FACETS: [&str; 4] = [
+    "Template Length",
+    "Mate Consistency",
+    "Tiles",
+    "Adapter Content",
+];
+
+/// The sequencing technology that the quality control facets are tailored to.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum QcProfile {
+    /// Short-read (e.g., Illumina) data.
+    #[default]
+    ShortRead,
+
+    /// Long-read (e.g., PacBio or ONT) data.
+    LongRead,
+}
+
+impl FromStr for QcProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "short-read" => Ok(Self::ShortRead),
+            "long-read" => Ok(Self::LongRead),
+            _ => bail!("unknown profile: {}", s),
+        }
+    }
+}
+
+impl QcProfile {
+    /// Whether the named facet is run within this profile. Facets that are
+    /// only loaded for some inputs are still subject to those inputs.
+    pub fn includes(&self, facet: &str) -> bool {
+        match self {
+            Self::ShortRead => facet != "Long Reads",
+            Self::LongRead => !SHORT_READ_ONLY_FACETS.contains(&facet),
+        }
+    }
+}
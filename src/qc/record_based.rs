@@ -1,7 +1,25 @@
 //! All record-based quality control facets.
 
+pub mod adapters;
+pub mod alignment_summary;
+pub mod contamination;
+pub mod duplication;
 pub mod features;
+pub mod fragmentomics;
 pub mod gc_content;
 pub mod general;
+pub mod long_reads;
+pub mod mapping_quality;
+pub mod mates;
+pub mod methylation;
 pub mod quality_scores;
+pub mod read_groups;
+pub mod read_length;
+pub mod read_names;
+pub mod repeats;
+pub mod single_cell;
+pub mod species;
+pub mod spike_ins;
 pub mod template_length;
+pub mod tiles;
+pub mod unmapped;
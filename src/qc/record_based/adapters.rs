@@ -0,0 +1,253 @@
+//! Functionality related to the Adapter Content quality control facet.
+//!
+//! Adapter read-through occurs when the insert is shorter than the read length,
+//! so the end of the read contains adapter sequence. As in FastQC, each record
+//! is searched for the first occurrence of a short prefix of each of the
+//! [`KNOWN_ADAPTERS`] (in the orientation the record was sequenced in) and the
+//! position of that occurrence is tallied. The cumulative distribution of these
+//! positions gives the fraction of reads that would be affected by adapter
+//! contamination if trimmed to a given length.
+
+use std::collections::BTreeMap;
+
+use noodles::sam::alignment::Record;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet},
+    utils::{alignment::sequenced_bases, histogram::Histogram, math::percentage, merge::Merge},
+};
+
+/// The adapters that are searched for, along with the prefix of each that is
+/// matched against records.
+pub const KNOWN_ADAPTERS: [(&str, &str); 5] = [
+    ("Illumina Universal Adapter", "AGATCGGAAGAG"),
+    ("Illumina Small RNA 3' Adapter", "TGGAATTCTCGG"),
+    ("Illumina Small RNA 5' Adapter", "GATCGTCGGACT"),
+    ("Nextera Transposase Sequence", "CTGTCTCTTATA"),
+    ("SOLID Small RNA Adapter", "CGCCTTGGCCGT"),
+];
+
+/// The initial capacity of each adapter position histogram. The histograms
+/// grow automatically to fit longer reads.
+const INITIAL_POSITION_CAPACITY: usize = 256;
+
+//=========//
+// Metrics //
+//=========//
+
+/// The tallies for a single adapter.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdapterTallies {
+    /// The sequence that was searched for.
+    pub sequence: String,
+
+    /// The number of records containing the adapter.
+    pub records: usize,
+
+    /// The distribution of the (zero-based) position at which the adapter
+    /// first occurs within the records containing it.
+    pub positions: Histogram,
+}
+
+impl AdapterTallies {
+    /// Creates a new, empty [`AdapterTallies`] for an adapter sequence.
+    pub fn new(sequence: &str) -> Self {
+        Self {
+            sequence: sequence.to_string(),
+            records: 0,
+            positions: Histogram::zero_based_with_capacity(INITIAL_POSITION_CAPACITY),
+        }
+    }
+}
+
+/// Summary statistics for the Adapter Content quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The percentage of records containing each adapter.
+    pub adapter_content_pct: BTreeMap<String, Option<f64>>,
+
+    /// The percentage of records containing any of the adapters.
+    pub any_adapter_pct: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding adapter content.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AdapterMetrics {
+    /// The number of records that were searched.
+    pub records: usize,
+
+    /// The number of records that contained at least one of the adapters.
+    pub records_with_adapter: usize,
+
+    /// The tallies for each adapter, keyed by the name of the adapter.
+    pub adapters: BTreeMap<String, AdapterTallies>,
+
+    /// Summary statistics for the Adapter Content quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl AdapterMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let adapter_content_pct = self
+            .adapters
+            .iter()
+            .map(|(name, tallies)| (name.clone(), percentage(tallies.records, self.records)))
+            .collect();
+
+        self.summary = Some(SummaryMetrics {
+            adapter_content_pct,
+            any_adapter_pct: percentage(self.records_with_adapter, self.records),
+        });
+    }
+}
+
+impl Merge for AdapterTallies {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records += other.records;
+        self.positions.merge(other.positions)
+    }
+}
+
+impl Merge for AdapterMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records += other.records;
+        self.records_with_adapter += other.records_with_adapter;
+        Merge::merge(&mut self.adapters, other.adapters)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Adapter Content quality control facet.
+pub struct AdapterContentFacet {
+    /// The main metric counting struct.
+    pub metrics: AdapterMetrics,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+}
+
+impl AdapterContentFacet {
+    /// Creates a new [`AdapterContentFacet`] configured by the provided
+    /// [`FacetOptions`].
+    pub fn from_options(options: &FacetOptions) -> Self {
+        let adapters = KNOWN_ADAPTERS
+            .iter()
+            .map(|(name, sequence)| (name.to_string(), AdapterTallies::new(sequence)))
+            .collect();
+
+        Self {
+            metrics: AdapterMetrics {
+                adapters,
+                ..Default::default()
+            },
+            count_duplicates: options.count_duplicates,
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for AdapterContentFacet {
+    fn name(&self) -> &'static str {
+        "Adapter Content"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Moderate
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Each read is only searched once, so secondary and supplementary
+        // records are skipped, as are duplicates if they aren't being counted.
+        let flags = record.flags();
+        if flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        // (2) Search for the first occurrence of each adapter in the record.
+        let bases = sequenced_bases(record);
+        let mut found_any = false;
+
+        for tallies in self.metrics.adapters.values_mut() {
+            let adapter = tallies.sequence.as_bytes();
+            let position = bases
+                .windows(adapter.len())
+                .position(|window| window == adapter);
+
+            if let Some(position) = position {
+                if !tallies.positions.in_range(position) {
+                    tallies.positions.grow_to(position.next_power_of_two());
+                }
+
+                tallies.positions.increment(position).unwrap();
+                tallies.records += 1;
+                found_any = true;
+            }
+        }
+
+        self.metrics.records += 1;
+        if found_any {
+            self.metrics.records_with_adapter += 1;
+        }
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.adapter_content = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::record::Flags;
+
+    use super::*;
+
+    #[test]
+    pub fn it_finds_adapters_in_the_sequenced_orientation() -> anyhow::Result<()> {
+        let mut facet = AdapterContentFacet::from_options(&FacetOptions::default());
+
+        let forward = Record::builder()
+            .set_sequence("ACGTACGTAGATCGGAAGAGCACA".parse()?)
+            .build();
+        // The reverse complement of a read containing the Nextera sequence at
+        // position 4.
+        let reverse = Record::builder()
+            .set_flags(Flags::REVERSE_COMPLEMENTED)
+            .set_sequence("TATAAGAGACAGACGT".parse()?)
+            .build();
+        let neither = Record::builder().set_sequence("ACGTACGT".parse()?).build();
+
+        for record in [forward, reverse, neither] {
+            facet.process(&record)?;
+        }
+        facet.summarize()?;
+
+        let universal = &facet.metrics.adapters["Illumina Universal Adapter"];
+        assert_eq!(universal.records, 1);
+        assert_eq!(universal.positions.get(8), 1);
+
+        let nextera = &facet.metrics.adapters["Nextera Transposase Sequence"];
+        assert_eq!(nextera.records, 1);
+        assert_eq!(nextera.positions.get(4), 1);
+
+        assert_eq!(facet.metrics.records, 3);
+        assert_eq!(facet.metrics.records_with_adapter, 2);
+
+        Ok(())
+    }
+}
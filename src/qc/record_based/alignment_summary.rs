@@ -0,0 +1,463 @@
+//! Functionality related to the Alignment Summary quality control facet.
+//!
+//! This facet reports the headline alignment metrics of Picard's
+//! `CollectAlignmentSummaryMetrics` for each of the categories Picard reports
+//! them for: the first and second reads of pairs, both reads of pairs
+//! combined, and unpaired reads. Only primary records are considered, and
+//! records that fail the platform's quality checks (`0x200`) are counted
+//! towards the total but are, as in Picard, otherwise excluded ("PF" stands
+//! for "passing filter").
+//!
+//! Picard computes the mismatch rate by comparing each read against the
+//! reference. As this facet runs in the first pass, it instead derives the
+//! number of mismatches from the edit distance (`NM` tag) of each record,
+//! less the inserted and deleted bases. Records without an `NM` tag do not
+//! contribute to the mismatch rate.
+
+use noodles::sam::{
+    alignment::Record,
+    record::{cigar::op::Kind, data::field::Tag},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet},
+    utils::{alignment::sequenced_bases, math::percentage, merge::Merge},
+};
+
+/// The fraction of reads with a no-call (`N`) at a cycle for that cycle to be
+/// considered bad (as in Picard).
+pub const BAD_CYCLE_NO_CALL_FRACTION: f64 = 0.8;
+
+/// The maximum number of cycles that are tracked. Bases beyond this cycle are
+/// not considered when counting bad cycles.
+pub const MAX_CYCLES: usize = 1_000_000;
+
+//=========//
+// Metrics //
+//=========//
+
+/// Summary statistics for one category of reads.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CategorySummary {
+    /// Percentage of reads passing filter that are aligned
+    /// (`PCT_PF_READS_ALIGNED`).
+    pub pf_reads_aligned_pct: Option<f64>,
+
+    /// Mismatched bases per aligned base for the aligned reads passing filter
+    /// with an `NM` tag (`PF_MISMATCH_RATE`).
+    pub pf_mismatch_rate: Option<f64>,
+
+    /// The mean length of the reads passing filter (`MEAN_READ_LENGTH`).
+    pub mean_read_length: Option<f64>,
+
+    /// Percentage of aligned reads passing filter whose mate is also aligned
+    /// (`PCT_READS_ALIGNED_IN_PAIRS`).
+    pub reads_aligned_in_pairs_pct: Option<f64>,
+
+    /// The fraction of aligned reads passing filter that are aligned to the
+    /// forward strand (`STRAND_BALANCE`).
+    pub strand_balance: Option<f64>,
+
+    /// The number of cycles in which at least 80% of the reads passing filter
+    /// had a no-call (`BAD_CYCLES`).
+    pub bad_cycles: usize,
+}
+
+/// Alignment metrics for one category of reads.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CategoryMetrics {
+    /// The number of primary reads (`TOTAL_READS`).
+    pub total_reads: usize,
+
+    /// The number of reads passing filter (`PF_READS`).
+    pub pf_reads: usize,
+
+    /// The number of reads passing filter that are aligned
+    /// (`PF_READS_ALIGNED`).
+    pub pf_reads_aligned: usize,
+
+    /// The number of bases within the reads passing filter.
+    pub pf_bases: usize,
+
+    /// The number of bases aligned to the reference (`M`, `=`, and `X`) within
+    /// the aligned reads passing filter (`PF_ALIGNED_BASES`).
+    pub pf_aligned_bases: usize,
+
+    /// The number of aligned bases within the aligned reads passing filter
+    /// that have an `NM` tag, over which mismatches were counted.
+    pub pf_mismatch_evaluated_bases: usize,
+
+    /// The number of mismatched bases within the aligned reads passing filter
+    /// that have an `NM` tag.
+    pub pf_mismatches: usize,
+
+    /// The number of aligned reads passing filter whose mate is also aligned
+    /// (`READS_ALIGNED_IN_PAIRS`).
+    pub reads_aligned_in_pairs: usize,
+
+    /// The number of aligned reads passing filter that are aligned to the
+    /// forward strand.
+    pub pf_reads_aligned_forward: usize,
+
+    /// The number of reads passing filter that reached each cycle.
+    pub pf_reads_by_cycle: Vec<usize>,
+
+    /// The number of reads passing filter with a no-call at each cycle.
+    pub pf_no_calls_by_cycle: Vec<usize>,
+
+    /// Summary statistics for this category of reads.
+    pub summary: Option<CategorySummary>,
+}
+
+impl CategoryMetrics {
+    /// Tallies the bases of a read passing filter (in the orientation it was
+    /// sequenced in) for each cycle.
+    fn tally_cycles(&mut self, bases: &[u8]) {
+        let cycles = usize::min(bases.len(), MAX_CYCLES);
+        if self.pf_reads_by_cycle.len() < cycles {
+            self.pf_reads_by_cycle.resize(cycles, 0);
+            self.pf_no_calls_by_cycle.resize(cycles, 0);
+        }
+
+        for (cycle, base) in bases.iter().take(cycles).enumerate() {
+            self.pf_reads_by_cycle[cycle] += 1;
+            if base.eq_ignore_ascii_case(&b'N') {
+                self.pf_no_calls_by_cycle[cycle] += 1;
+            }
+        }
+    }
+
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let ratio = |numerator: usize, denominator: usize| {
+            (denominator > 0).then(|| numerator as f64 / denominator as f64)
+        };
+
+        let bad_cycles = self
+            .pf_reads_by_cycle
+            .iter()
+            .zip(&self.pf_no_calls_by_cycle)
+            .filter(|(reads, no_calls)| {
+                **reads > 0 && **no_calls as f64 / **reads as f64 >= BAD_CYCLE_NO_CALL_FRACTION
+            })
+            .count();
+
+        self.summary = Some(CategorySummary {
+            pf_reads_aligned_pct: percentage(self.pf_reads_aligned, self.pf_reads),
+            pf_mismatch_rate: ratio(self.pf_mismatches, self.pf_mismatch_evaluated_bases),
+            mean_read_length: ratio(self.pf_bases, self.pf_reads),
+            reads_aligned_in_pairs_pct: percentage(
+                self.reads_aligned_in_pairs,
+                self.pf_reads_aligned,
+            ),
+            strand_balance: ratio(self.pf_reads_aligned_forward, self.pf_reads_aligned),
+            bad_cycles,
+        });
+    }
+}
+
+impl Merge for CategoryMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.total_reads += other.total_reads;
+        self.pf_reads += other.pf_reads;
+        self.pf_reads_aligned += other.pf_reads_aligned;
+        self.pf_bases += other.pf_bases;
+        self.pf_aligned_bases += other.pf_aligned_bases;
+        self.pf_mismatch_evaluated_bases += other.pf_mismatch_evaluated_bases;
+        self.pf_mismatches += other.pf_mismatches;
+        self.reads_aligned_in_pairs += other.reads_aligned_in_pairs;
+        self.pf_reads_aligned_forward += other.pf_reads_aligned_forward;
+
+        for (cycles, others) in [
+            (&mut self.pf_reads_by_cycle, other.pf_reads_by_cycle),
+            (&mut self.pf_no_calls_by_cycle, other.pf_no_calls_by_cycle),
+        ] {
+            if cycles.len() < others.len() {
+                cycles.resize(others.len(), 0);
+            }
+            for (count, other) in cycles.iter_mut().zip(others) {
+                *count += other;
+            }
+        }
+
+        self.summarize();
+        Ok(())
+    }
+}
+
+/// Primary struct used to compile alignment summary metrics.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AlignmentSummaryMetrics {
+    /// Metrics for the first reads of pairs (`FIRST_OF_PAIR`).
+    pub first_of_pair: CategoryMetrics,
+
+    /// Metrics for the second reads of pairs (`SECOND_OF_PAIR`).
+    pub second_of_pair: CategoryMetrics,
+
+    /// Metrics for both reads of pairs combined (`PAIR`). This is computed from
+    /// the first and second reads of pairs when the metrics are summarized.
+    pub pair: CategoryMetrics,
+
+    /// Metrics for unpaired reads (`UNPAIRED`).
+    pub unpaired: CategoryMetrics,
+}
+
+impl AlignmentSummaryMetrics {
+    /// Tallies a single primary record.
+    pub fn tally(&mut self, record: &Record) {
+        let flags = record.flags();
+
+        let category = if !flags.is_segmented() {
+            &mut self.unpaired
+        } else if flags.is_first_segment() {
+            &mut self.first_of_pair
+        } else if flags.is_last_segment() {
+            &mut self.second_of_pair
+        } else {
+            // Segmented records that are neither the first nor the last
+            // segment are not attributed to any category (nor are they by
+            // Picard).
+            return;
+        };
+
+        category.total_reads += 1;
+        if flags.is_qc_fail() {
+            return;
+        }
+
+        let bases = sequenced_bases(record);
+        category.pf_reads += 1;
+        category.pf_bases += bases.len();
+        category.tally_cycles(&bases);
+
+        if flags.is_unmapped() {
+            return;
+        }
+
+        let (mut aligned, mut indels) = (0, 0);
+        for op in record.cigar().iter() {
+            match op.kind() {
+                Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch => aligned += op.len(),
+                Kind::Insertion | Kind::Deletion => indels += op.len(),
+                _ => {}
+            }
+        }
+
+        category.pf_reads_aligned += 1;
+        category.pf_aligned_bases += aligned;
+
+        if let Some(edit_distance) = record
+            .data()
+            .get(Tag::EditDistance)
+            .and_then(|field| field.value().as_int())
+        {
+            let edit_distance = usize::try_from(edit_distance).unwrap_or_default();
+            category.pf_mismatch_evaluated_bases += aligned;
+            category.pf_mismatches += edit_distance.saturating_sub(indels);
+        }
+
+        if flags.is_segmented() && !flags.is_mate_unmapped() {
+            category.reads_aligned_in_pairs += 1;
+        }
+
+        if !flags.is_reverse_complemented() {
+            category.pf_reads_aligned_forward += 1;
+        }
+    }
+
+    /// Computes the combined metrics for pairs and the summary statistics for
+    /// each of the categories.
+    pub fn summarize(&mut self) {
+        let mut pair = self.first_of_pair.clone();
+        // Merging per-category counts cannot fail.
+        pair.merge(self.second_of_pair.clone()).unwrap();
+        self.pair = pair;
+
+        self.first_of_pair.summarize();
+        self.second_of_pair.summarize();
+        self.pair.summarize();
+        self.unpaired.summarize();
+    }
+}
+
+impl Merge for AlignmentSummaryMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.first_of_pair.merge(other.first_of_pair)?;
+        self.second_of_pair.merge(other.second_of_pair)?;
+        self.unpaired.merge(other.unpaired)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Alignment Summary quality control facet.
+#[derive(Default)]
+pub struct AlignmentSummaryFacet {
+    /// The main metric counting struct.
+    pub metrics: AlignmentSummaryMetrics,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+}
+
+impl AlignmentSummaryFacet {
+    /// Creates a new [`AlignmentSummaryFacet`] configured by the provided
+    /// [`FacetOptions`].
+    pub fn from_options(options: &FacetOptions) -> Self {
+        Self {
+            metrics: AlignmentSummaryMetrics::default(),
+            count_duplicates: options.count_duplicates,
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for AlignmentSummaryFacet {
+    fn name(&self) -> &'static str {
+        "Alignment Summary"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        let flags = record.flags();
+        if flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        self.metrics.tally(record);
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.alignment_summary = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{
+        core::Position,
+        sam::record::{
+            data::field::{Field, Value},
+            Data, Flags,
+        },
+    };
+
+    use super::*;
+
+    fn record(flags: Flags, sequence: &str, edit_distance: Option<i32>) -> anyhow::Result<Record> {
+        let mut builder = Record::builder()
+            .set_flags(flags)
+            .set_sequence(sequence.parse()?)
+            .set_quality_scores("I".repeat(sequence.len()).parse()?);
+
+        if !flags.is_unmapped() {
+            builder = builder
+                .set_reference_sequence_id(0)
+                .set_alignment_start(Position::try_from(1)?)
+                .set_cigar(format!("2S{}M1I", sequence.len() - 3).parse()?);
+        }
+
+        if let Some(edit_distance) = edit_distance {
+            builder = builder.set_data(Data::try_from(vec![Field::new(
+                Tag::EditDistance,
+                Value::from(edit_distance),
+            )])?);
+        }
+
+        Ok(builder.build())
+    }
+
+    #[test]
+    pub fn it_summarizes_alignments_by_category() -> anyhow::Result<()> {
+        let mut facet = AlignmentSummaryFacet::from_options(&FacetOptions::default());
+        let paired = Flags::SEGMENTED;
+
+        // Read ones: two aligned (one reverse), one QC fail.
+        facet.process(&record(
+            paired | Flags::FIRST_SEGMENT,
+            "NACGTACGTACG",
+            Some(3),
+        )?)?;
+        facet.process(&record(
+            paired | Flags::FIRST_SEGMENT | Flags::REVERSE_COMPLEMENTED | Flags::MATE_UNMAPPED,
+            "ACGTACGTACGN",
+            None,
+        )?)?;
+        facet.process(&record(
+            paired | Flags::FIRST_SEGMENT | Flags::QC_FAIL,
+            "ACGTACGTACGT",
+            None,
+        )?)?;
+
+        // Read two: unmapped.
+        facet.process(&record(
+            paired | Flags::LAST_SEGMENT | Flags::UNMAPPED,
+            "ACGTAC",
+            None,
+        )?)?;
+
+        // Secondary records are not considered.
+        facet.process(&record(
+            paired | Flags::LAST_SEGMENT | Flags::SECONDARY,
+            "ACGTAC",
+            None,
+        )?)?;
+
+        facet.summarize()?;
+        let metrics = &facet.metrics;
+
+        let first = &metrics.first_of_pair;
+        assert_eq!(first.total_reads, 3);
+        assert_eq!(first.pf_reads, 2);
+        assert_eq!(first.pf_reads_aligned, 2);
+        assert_eq!(first.pf_aligned_bases, 18);
+        assert_eq!(first.pf_mismatch_evaluated_bases, 9);
+        assert_eq!(first.pf_mismatches, 2);
+        assert_eq!(first.reads_aligned_in_pairs, 1);
+
+        let summary = first.summary.as_ref().unwrap();
+        assert_eq!(summary.pf_reads_aligned_pct, Some(100.0));
+        assert_eq!(summary.pf_mismatch_rate, Some(2.0 / 9.0));
+        assert_eq!(summary.mean_read_length, Some(12.0));
+        assert_eq!(summary.reads_aligned_in_pairs_pct, Some(50.0));
+        assert_eq!(summary.strand_balance, Some(0.5));
+        // Both reads have a no-call at the first cycle once the reverse read
+        // is returned to the orientation it was sequenced in.
+        assert_eq!(summary.bad_cycles, 1);
+
+        let pair = &metrics.pair;
+        assert_eq!(pair.total_reads, 4);
+        assert_eq!(pair.pf_reads, 3);
+        assert_eq!(pair.pf_reads_aligned, 2);
+        assert_eq!(pair.summary.as_ref().unwrap().mean_read_length, Some(10.0));
+
+        assert_eq!(metrics.unpaired.total_reads, 0);
+        assert_eq!(
+            metrics
+                .unpaired
+                .summary
+                .as_ref()
+                .unwrap()
+                .pf_reads_aligned_pct,
+            None
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,366 @@
+//! Functionality related to the Contamination quality control facet.
+//!
+//! Reads from another species (e.g., bacterial, mycoplasma, or a sample swap
+//! between human and mouse) do not align to the reference, so they show up as
+//! unmapped reads. This facet screens a sample of the unmapped reads (the
+//! first [`SCREENED_READS`] of them) against a set of k-mer sketches, one per
+//! species, and reports the fraction of the sampled k-mers that hit each
+//! species.
+//!
+//! The sketches are built from a FASTA of genomes (`--contamination-screen`),
+//! where the description of each sequence names its species (e.g.,
+//! `>NC_000913.3 Escherichia coli`); sequences without a description are
+//! named by their own name. Only the k-mers whose hash falls below
+//! `u64::MAX / SKETCH_SCALE` are kept (a "FracMinHash" sketch), so that even
+//! a mammalian genome only takes up a few million k-mers. Because the same
+//! k-mers are kept from the reads, the fraction of a read's k-mers that hit a
+//! species is estimated without bias.
+//!
+//! A k-mer that is shared by several species (e.g., in conserved regions of
+//! human and mouse) counts as a hit for each of them.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use noodles::sam::alignment::Record;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet},
+    utils::{alignment::sequenced_bases, formats, kmers, math::percentage, merge::Merge},
+};
+
+/// The length of the k-mers within the sketches.
+pub const KMER_LENGTH: usize = 21;
+
+/// One in this many k-mers (by hash) is kept within the sketches.
+pub const SKETCH_SCALE: u64 = 1_000;
+
+/// The number of unmapped reads that are screened.
+pub const SCREENED_READS: usize = 100_000;
+
+/// The percentage of the sampled k-mers that must hit a species for it to be
+/// reported as a likely contaminant.
+pub const MIN_CONTAMINANT_HIT_PCT: f64 = 1.0;
+
+/// The most species that a sketch set can hold.
+pub const MAX_SPECIES: usize = 32;
+
+/// Whether a k-mer (by its hash) is kept within the sketches.
+fn is_sketched(hash: u64) -> bool {
+    hash < u64::MAX / SKETCH_SCALE
+}
+
+/// Gets the hashes of the sketched k-mers of a sequence.
+fn sketched_hashes(bases: &[u8]) -> impl Iterator<Item = u64> + '_ {
+    kmers::canonical(bases, KMER_LENGTH)
+        .map(kmers::hash)
+        .filter(|hash| is_sketched(*hash))
+}
+
+//==========//
+// Sketches //
+//==========//
+
+/// The k-mer sketches of a set of species.
+#[derive(Debug, Default)]
+pub struct SketchSet {
+    /// The name of each species.
+    pub species: Vec<String>,
+
+    /// The species (as a bit set of their indices) that each sketched k-mer
+    /// (by its hash) is found within.
+    pub hashes: HashMap<u64, u32>,
+}
+
+impl SketchSet {
+    /// Adds the sketched k-mers of a sequence to the sketch of a species.
+    pub fn add(&mut self, species: &str, bases: &[u8]) -> anyhow::Result<()> {
+        let index = match self.species.iter().position(|s| s == species) {
+            Some(index) => index,
+            None if self.species.len() < MAX_SPECIES => {
+                self.species.push(species.to_string());
+                self.species.len() - 1
+            }
+            None => bail!(
+                "The contamination screen supports at most {} species.",
+                MAX_SPECIES
+            ),
+        };
+
+        for hash in sketched_hashes(bases) {
+            *self.hashes.entry(hash).or_insert(0) |= 1 << index;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the sketches from a FASTA of genomes, where the description of
+    /// each sequence (or, if it has none, its name) names its species.
+    pub fn read<P>(src: P) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let src = src.as_ref();
+        let mut reader = formats::fasta::open(src)
+            .with_context(|| format!("opening the contamination FASTA: {}", src.display()))?;
+
+        let mut sketches = Self::default();
+        for result in reader.records() {
+            let record = result
+                .with_context(|| format!("reading the contamination FASTA: {}", src.display()))?;
+            let species = record.description().unwrap_or(record.name());
+            sketches.add(species, record.sequence().as_ref())?;
+        }
+
+        Ok(sketches)
+    }
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// The hits of the sampled k-mers to a species.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SpeciesHits {
+    /// The number of sampled k-mers found within the species' sketch.
+    pub hits: usize,
+
+    /// The percentage of the sampled k-mers found within the species' sketch.
+    pub hit_pct: Option<f64>,
+}
+
+/// Summary statistics for the Contamination quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The species with at least [`MIN_CONTAMINANT_HIT_PCT`] of the sampled
+    /// k-mers, from the most hits to the fewest.
+    pub likely_contaminants: Vec<String>,
+
+    /// The percentage of the sampled k-mers that did not hit any species.
+    pub unclassified_pct: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding contamination.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ContaminationMetrics {
+    /// The number of unmapped reads.
+    pub unmapped_records: usize,
+
+    /// The number of unmapped reads that were screened.
+    pub screened_records: usize,
+
+    /// The number of sketched k-mers within the screened reads.
+    pub sampled_kmers: usize,
+
+    /// The number of sampled k-mers that did not hit any species.
+    pub unclassified_kmers: usize,
+
+    /// The hits to each species.
+    pub species: BTreeMap<String, SpeciesHits>,
+
+    /// Summary statistics for the Contamination quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl ContaminationMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        for hits in self.species.values_mut() {
+            hits.hit_pct = percentage(hits.hits, self.sampled_kmers);
+        }
+
+        let mut likely = self
+            .species
+            .iter()
+            .filter(|(_, hits)| hits.hit_pct.unwrap_or_default() >= MIN_CONTAMINANT_HIT_PCT)
+            .collect::<Vec<_>>();
+        likely.sort_by_key(|(_, hits)| std::cmp::Reverse(hits.hits));
+
+        self.summary = Some(SummaryMetrics {
+            likely_contaminants: likely.into_iter().map(|(name, _)| name.clone()).collect(),
+            unclassified_pct: percentage(self.unclassified_kmers, self.sampled_kmers),
+        });
+    }
+}
+
+impl Merge for ContaminationMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.unmapped_records += other.unmapped_records;
+        self.screened_records += other.screened_records;
+        self.sampled_kmers += other.sampled_kmers;
+        self.unclassified_kmers += other.unclassified_kmers;
+        for (name, hits) in other.species {
+            self.species.entry(name).or_default().hits += hits.hits;
+        }
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Contamination quality control facet.
+pub struct ContaminationFacet {
+    /// The main metric counting struct.
+    pub metrics: ContaminationMetrics,
+
+    /// The sketches that the unmapped reads are screened against.
+    pub sketches: SketchSet,
+
+    /// The number of sampled k-mers that hit each species (by index).
+    hits: Vec<usize>,
+
+    /// Whether records marked as duplicate should be counted.
+    count_duplicates: bool,
+}
+
+impl ContaminationFacet {
+    /// Creates a new [`ContaminationFacet`] that screens against the provided
+    /// sketches.
+    pub fn new(sketches: SketchSet, options: &FacetOptions) -> Self {
+        Self {
+            metrics: ContaminationMetrics::default(),
+            hits: vec![0; sketches.species.len()],
+            sketches,
+            count_duplicates: options.count_duplicates,
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for ContaminationFacet {
+    fn name(&self) -> &'static str {
+        "Contamination"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Moderate
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Only the primary records of unmapped reads are screened.
+        let flags = record.flags();
+        if !flags.is_unmapped()
+            || flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        self.metrics.unmapped_records += 1;
+        if self.metrics.screened_records >= SCREENED_READS {
+            return Ok(());
+        }
+        self.metrics.screened_records += 1;
+
+        // (2) Look up each of the read's sketched k-mers.
+        for hash in sketched_hashes(&sequenced_bases(record)) {
+            self.metrics.sampled_kmers += 1;
+
+            match self.sketches.hashes.get(&hash) {
+                Some(species) => {
+                    for (index, hits) in self.hits.iter_mut().enumerate() {
+                        if species & (1 << index) != 0 {
+                            *hits += 1;
+                        }
+                    }
+                }
+                None => self.metrics.unclassified_kmers += 1,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.species = self
+            .sketches
+            .species
+            .iter()
+            .zip(&self.hits)
+            .map(|(name, hits)| {
+                let hits = SpeciesHits {
+                    hits: *hits,
+                    hit_pct: None,
+                };
+                (name.clone(), hits)
+            })
+            .collect();
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.contamination = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::record::{Flags, Sequence};
+
+    use super::*;
+
+    /// Generates a pseudo-random sequence.
+    fn sequence(seed: u64, length: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..length)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                b"ACGT"[(state >> 62) as usize]
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn it_screens_unmapped_reads_against_the_sketches() -> anyhow::Result<()> {
+        let (bacterium, yeast, sample) = (
+            sequence(1, 200_000),
+            sequence(2, 200_000),
+            sequence(3, 200_000),
+        );
+
+        let mut sketches = SketchSet::default();
+        sketches.add("Escherichia coli", &bacterium[..100_000])?;
+        sketches.add("Escherichia coli", &bacterium[100_000..])?;
+        sketches.add("Saccharomyces cerevisiae", &yeast)?;
+        assert_eq!(sketches.species.len(), 2);
+
+        let mut facet = ContaminationFacet::new(sketches, &FacetOptions::default());
+        let unmapped = |bases: &[u8]| -> anyhow::Result<Record> {
+            Ok(Record::builder()
+                .set_flags(Flags::UNMAPPED)
+                .set_sequence(Sequence::try_from(bases.to_vec())?)
+                .build())
+        };
+
+        // Reads tiled along the bacterial genome and an unrelated genome.
+        for start in (0..200_000 - 150).step_by(100) {
+            facet.process(&unmapped(&bacterium[start..start + 150])?)?;
+            facet.process(&unmapped(&sample[start..start + 150])?)?;
+        }
+        // Mapped reads are not screened.
+        facet.process(&Record::builder().set_flags(Flags::empty()).build())?;
+
+        facet.summarize()?;
+        let metrics = &facet.metrics;
+        assert_eq!(metrics.unmapped_records, 2 * 1999);
+        assert_eq!(metrics.screened_records, 2 * 1999);
+        assert!(metrics.sampled_kmers > 0);
+
+        let summary = metrics.summary.as_ref().unwrap();
+        assert_eq!(summary.likely_contaminants, vec!["Escherichia coli"]);
+        let hit_pct = metrics.species["Escherichia coli"].hit_pct.unwrap();
+        assert!((40.0..60.0).contains(&hit_pct));
+        assert_eq!(metrics.species["Saccharomyces cerevisiae"].hits, 0);
+
+        Ok(())
+    }
+}
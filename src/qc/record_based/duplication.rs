@@ -0,0 +1,311 @@
+//! Functionality related to the Duplication quality control facet.
+//!
+//! Unlike the duplicate flag (`0x400`), which requires the records to have been
+//! aligned and marked, this facet estimates the level of duplication from the
+//! read sequences alone. Following the approach of FastQC, the first
+//! [`TRACKED_SEQUENCES`] distinct sequences are tracked, and every subsequent
+//! occurrence of a tracked sequence is counted. Sequences longer than
+//! [`TRUNCATE_LONGER_THAN`] are truncated to [`TRUNCATION_LENGTH`], as
+//! sequencing errors towards the end of long reads would otherwise make
+//! duplicates appear distinct.
+//!
+//! Because only the tracked sequences are counted, the results are an estimate.
+//! The results of sharded runs also report the tracked sequences themselves,
+//! so that a sequence seen in more than one shard is counted once when the
+//! results are merged.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::bail;
+use noodles::sam::alignment::Record;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, RecordBasedQualityControlFacet},
+    utils::{alignment::sequenced_bases, math::percentage, merge::Merge},
+};
+
+/// The number of distinct sequences that are tracked.
+pub const TRACKED_SEQUENCES: usize = 100_000;
+
+/// Sequences longer than this are truncated to [`TRUNCATION_LENGTH`].
+pub const TRUNCATE_LONGER_THAN: usize = 75;
+
+/// The length that long sequences are truncated to.
+pub const TRUNCATION_LENGTH: usize = 50;
+
+/// The duplication levels that distinct sequences are binned into, as the
+/// lowest number of occurrences within each bin and the name of the bin.
+const DUPLICATION_LEVELS: [(usize, &str); 16] = [
+    (1, "1"),
+    (2, "2"),
+    (3, "3"),
+    (4, "4"),
+    (5, "5"),
+    (6, "6"),
+    (7, "7"),
+    (8, "8"),
+    (9, "9"),
+    (10, ">10"),
+    (50, ">50"),
+    (100, ">100"),
+    (500, ">500"),
+    (1_000, ">1k"),
+    (5_000, ">5k"),
+    (10_000, ">10k"),
+];
+
+/// Gets the name of the duplication level bin for a sequence that occurred
+/// `count` times.
+fn duplication_level(count: usize) -> &'static str {
+    DUPLICATION_LEVELS
+        .iter()
+        .rev()
+        .find(|(lowest, _)| count >= *lowest)
+        .map(|(_, name)| *name)
+        .unwrap_or("1")
+}
+
+/// Bins the distinct sequences by the number of times each occurred.
+fn duplication_levels<'a>(counts: impl Iterator<Item = &'a usize>) -> BTreeMap<String, usize> {
+    let mut duplication_levels = BTreeMap::new();
+    for count in counts {
+        *duplication_levels
+            .entry(duplication_level(*count).to_string())
+            .or_insert(0) += 1;
+    }
+    duplication_levels
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// Summary statistics for the Duplication quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The estimated percentage of records that would remain if the file were
+    /// deduplicated.
+    pub remaining_if_deduplicated_pct: Option<f64>,
+
+    /// The estimated percentage of records that are duplicates.
+    pub duplicate_pct: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding sequence duplication.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DuplicationMetrics {
+    /// The number of records examined.
+    pub records: usize,
+
+    /// The number of records whose sequence was one of the tracked sequences.
+    pub tracked_records: usize,
+
+    /// The number of distinct sequences that were tracked.
+    pub distinct_sequences: usize,
+
+    /// The number of distinct tracked sequences at each duplication level.
+    pub duplication_levels: BTreeMap<String, usize>,
+
+    /// The number of occurrences of each tracked sequence. These are only
+    /// reported by sharded runs, as they are needed to merge the results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequences: Option<BTreeMap<String, usize>>,
+
+    /// Summary statistics for the Duplication quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl DuplicationMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let remaining = percentage(self.distinct_sequences, self.tracked_records);
+
+        self.summary = Some(SummaryMetrics {
+            remaining_if_deduplicated_pct: remaining,
+            duplicate_pct: remaining.map(|pct| 100.0 - pct),
+        });
+    }
+}
+
+impl Merge for DuplicationMetrics {
+    /// Merges the metrics by merging the tracked sequences of each, which are
+    /// only reported by sharded runs. As in a single pass, sequences are no
+    /// longer added once [`TRACKED_SEQUENCES`] are tracked.
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        let (sequences, other_sequences) = match (self.sequences.as_mut(), other.sequences) {
+            (Some(sequences), Some(other_sequences)) => (sequences, other_sequences),
+            _ => bail!(
+                "Cannot merge duplication results without their tracked \
+                sequences, which are only reported by sharded runs (--shard)."
+            ),
+        };
+
+        for (sequence, count) in other_sequences {
+            let is_full = sequences.len() >= TRACKED_SEQUENCES;
+            match sequences.get_mut(&sequence) {
+                Some(existing) => *existing += count,
+                None if !is_full => {
+                    sequences.insert(sequence, count);
+                }
+                None => {}
+            }
+        }
+
+        self.records += other.records;
+        self.tracked_records = sequences.values().sum();
+        self.distinct_sequences = sequences.len();
+        self.duplication_levels = duplication_levels(sequences.values());
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Duplication quality control facet.
+///
+/// Every record is considered regardless of whether it is marked as a
+/// duplicate, as estimating duplication is the purpose of this facet.
+#[derive(Default)]
+pub struct DuplicationFacet {
+    /// The main metric counting struct.
+    pub metrics: DuplicationMetrics,
+
+    /// The number of occurrences of each tracked sequence.
+    pub counts: HashMap<Vec<u8>, usize>,
+}
+
+impl RecordBasedQualityControlFacet for DuplicationFacet {
+    fn name(&self) -> &'static str {
+        "Duplication"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Moderate
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Each read is only counted once, so secondary and supplementary
+        // records are skipped.
+        let flags = record.flags();
+        if flags.is_secondary() || flags.is_supplementary() {
+            return Ok(());
+        }
+
+        self.metrics.records += 1;
+
+        // (2) Truncate long sequences and count the sequence if it is (or can
+        // become) one of the tracked sequences.
+        let mut bases = sequenced_bases(record);
+        if bases.len() > TRUNCATE_LONGER_THAN {
+            bases.truncate(TRUNCATION_LENGTH);
+        }
+
+        let is_full = self.counts.len() >= TRACKED_SEQUENCES;
+        match self.counts.get_mut(&bases) {
+            Some(count) => *count += 1,
+            None if !is_full => {
+                self.counts.insert(bases, 1);
+            }
+            None => return Ok(()),
+        }
+
+        self.metrics.tracked_records += 1;
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.distinct_sequences = self.counts.len();
+        self.metrics.duplication_levels = duplication_levels(self.counts.values());
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        let mut metrics = self.metrics.clone();
+
+        // The results of a shard will be merged with those of the other
+        // shards, which requires the tracked sequences.
+        if results.shard.is_some() {
+            metrics.sequences = Some(
+                self.counts
+                    .iter()
+                    .map(|(bases, count)| (String::from_utf8_lossy(bases).into_owned(), *count))
+                    .collect(),
+            );
+        }
+
+        results.duplication = Some(metrics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_estimates_duplication_from_sequences() -> anyhow::Result<()> {
+        let mut facet = DuplicationFacet::default();
+
+        for sequence in ["ACGT", "ACGT", "ACGT", "TTTT"] {
+            let record = Record::builder().set_sequence(sequence.parse()?).build();
+            facet.process(&record)?;
+        }
+        facet.summarize()?;
+
+        assert_eq!(facet.metrics.tracked_records, 4);
+        assert_eq!(facet.metrics.distinct_sequences, 2);
+        assert_eq!(facet.metrics.duplication_levels["1"], 1);
+        assert_eq!(facet.metrics.duplication_levels["3"], 1);
+
+        let summary = facet.metrics.summary.unwrap();
+        assert_eq!(summary.remaining_if_deduplicated_pct, Some(50.0));
+        assert_eq!(summary.duplicate_pct, Some(50.0));
+
+        assert_eq!(duplication_level(75), ">50");
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_merges_sequences_shared_between_shards() -> anyhow::Result<()> {
+        let shard = |sequences: &[&str]| -> anyhow::Result<DuplicationMetrics> {
+            let mut facet = DuplicationFacet::default();
+            for sequence in sequences {
+                let record = Record::builder().set_sequence(sequence.parse()?).build();
+                facet.process(&record)?;
+            }
+            facet.summarize()?;
+
+            let mut results = results::Results {
+                shard: Some("1/2".parse()?),
+                ..Default::default()
+            };
+            facet.aggregate(&mut results);
+            Ok(results.duplication.unwrap())
+        };
+
+        // `ACGT` occurs in both shards, so there are two distinct sequences
+        // rather than three.
+        let mut metrics = shard(&["ACGT", "TTTT"])?;
+        metrics.merge(shard(&["ACGT"])?)?;
+
+        assert_eq!(metrics.records, 3);
+        assert_eq!(metrics.tracked_records, 3);
+        assert_eq!(metrics.distinct_sequences, 2);
+        assert_eq!(metrics.duplication_levels["1"], 1);
+        assert_eq!(metrics.duplication_levels["2"], 1);
+        assert_eq!(
+            metrics.summary.unwrap().duplicate_pct.map(f64::round),
+            Some(33.0)
+        );
+
+        // Results without the tracked sequences cannot be merged.
+        let mut unsharded = DuplicationMetrics::default();
+        assert!(unsharded.merge(shard(&["ACGT"])?).is_err());
+
+        Ok(())
+    }
+}
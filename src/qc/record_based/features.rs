@@ -1,30 +1,43 @@
 //! Functionality related to the Features quality control facet.
 
-use std::{collections::HashMap, path::PathBuf, rc::Rc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{bail, Context};
 use noodles::sam;
 use rust_lapper::{Interval, Lapper};
 use sam::{alignment::Record, Header};
-use tracing::debug;
+use tracing::{debug, info};
 
+pub mod biotypes;
+pub mod counts;
+pub mod loader;
 pub mod metrics;
 pub mod utils;
 
 use crate::{
     qc::{
-        record_based::features::utils::Strand, results, ComputationalLoad,
-        RecordBasedQualityControlFacet,
+        record_based::features::{
+            biotypes::{BiotypeTally, BIOTYPE_ATTRIBUTES},
+            counts::{Gene, GeneCounter},
+            loader::{AnnotationMetrics, IncrementalIndex},
+            utils::{FeatureKind, Strand},
+        },
+        results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet,
     },
     utils::{
         formats,
-        genome::{get_primary_assembly, ReferenceGenome},
+        genome::{get_primary_assembly, normalize_sequence_name, ReferenceGenome},
     },
 };
 
 pub use self::{
     metrics::{Metrics, SummaryMetrics},
-    utils::FeatureNameStrand,
+    utils::Feature,
 };
 
 //=================//
@@ -76,31 +89,46 @@ impl FeatureNames {
 // Genomic Features Facet //
 //========================//
 
-/// Main struct for the Features quality control facet.
-pub struct GenomicFeaturesFacet<'a> {
-    /// Store of the cached exonic translation regions.
-    pub exonic_translation_regions: HashMap<String, Lapper<usize, FeatureNameStrand>>,
-
-    /// Store of the cached gene regions.
-    pub gene_regions: HashMap<String, Lapper<usize, FeatureNameStrand>>,
+/// The features for a single reference sequence, indexed for lookup.
+pub struct SequenceFeatures {
+    /// The exonic translation regions (five prime UTRs, three prime UTRs, and
+    /// coding sequences).
+    pub exonic_translation_regions: Lapper<usize, Feature>,
 
-    /// Feature names that correspond to the respective features in the gene
-    /// model. These are passed in on the command line.
-    pub feature_names: &'a FeatureNames,
+    /// The gene regions (genes and exons).
+    pub gene_regions: Lapper<usize, Feature>,
+}
 
-    /// The SAM header. Useful for looking up sequence ids for records during
-    /// processing.
-    pub header: &'a Header,
+/// Main struct for the Features quality control facet.
+pub struct GenomicFeaturesFacet {
+    /// The features for each reference sequence, indexed by the reference
+    /// sequence's id within the header. Sequences that are not part of the
+    /// primary assembly have no entry, as records aligned to them are ignored.
+    /// Building this index when the GFF is loaded means that each record only
+    /// requires a single lookup in each interval tree.
+    pub features: Vec<Option<SequenceFeatures>>,
 
     /// The main metric counting struct.
     pub metrics: Metrics,
 
-    /// Cached set of primary chromosomes (for ignoring records aligned to
-    /// non-primary sequences).
-    pub primary_chromosome_names: Vec<String>,
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+
+    /// The number of features intersected by the records that were looked up.
+    pub features_intersected: usize,
+
+    /// The total time spent looking up features.
+    pub lookup_duration: Duration,
+
+    /// Counts records for each gene, if per-gene counting is enabled.
+    pub gene_counter: Option<GeneCounter>,
+
+    /// Attributes records to the biotypes of the genes they overlap, if the
+    /// genes in the GFF are annotated with a biotype.
+    pub biotypes: Option<BiotypeTally>,
 }
 
-impl<'a> RecordBasedQualityControlFacet for GenomicFeaturesFacet<'a> {
+impl RecordBasedQualityControlFacet for GenomicFeaturesFacet {
     fn name(&self) -> &'static str {
         "Genomic Features"
     }
@@ -119,8 +147,9 @@ impl<'a> RecordBasedQualityControlFacet for GenomicFeaturesFacet<'a> {
         // (2) Parse the flags so we can see if the read is mapped.
         let flags = record.flags();
 
-        // (3) If the read is unmapped, just return—no need to throw an error.
-        if flags.is_unmapped() {
+        // (3) If the read is unmapped (or is a duplicate we aren't counting),
+        // just return—no need to throw an error.
+        if flags.is_unmapped() || (flags.is_duplicate() && !self.count_duplicates) {
             self.metrics.records.ignored_flags += 1;
             return Ok(());
         }
@@ -136,15 +165,15 @@ impl<'a> RecordBasedQualityControlFacet for GenomicFeaturesFacet<'a> {
             }
         };
 
-        // (5) Map the parsed reference sequence id to a reference sequence name.
-        let seq_name = match self
-            .header
-            .reference_sequences()
-            .get_index(id)
-            .map(|(_, rs)| Some(rs.name().as_str()))
-        {
-            Some(Some(name)) => name,
-            _ => {
+        // (5) Look up the features for the reference sequence. Sequences that
+        // are not in the primary assembly have no features.
+        let features = match self.features.get(id) {
+            Some(Some(features)) => features,
+            Some(None) => {
+                self.metrics.records.ignored_nonprimary_chromosome += 1;
+                return Ok(());
+            }
+            None => {
                 bail!(
                     "Could not map reference sequence id to header for read: {}",
                     read_name
@@ -152,15 +181,6 @@ impl<'a> RecordBasedQualityControlFacet for GenomicFeaturesFacet<'a> {
             }
         };
 
-        if !self
-            .primary_chromosome_names
-            .iter()
-            .any(|s: &String| s == seq_name)
-        {
-            self.metrics.records.ignored_nonprimary_chromosome += 1;
-            return Ok(());
-        }
-
         // (6) Calculate the start and end position of this read. This will
         // later be used for lookup within our feature map.
         let start = match record.alignment_start() {
@@ -173,86 +193,106 @@ impl<'a> RecordBasedQualityControlFacet for GenomicFeaturesFacet<'a> {
         let end = start + cigar.alignment_span();
 
         // (7) Tally up which features this record contributes to.
+        let lookup_start = Instant::now();
         let mut counted_as_five_prime_utr = false;
         let mut counted_as_three_prime_utr = false;
         let mut counted_as_coding_sequence = false;
 
         // (7a) Tally up exonic translations.
-        if let Some(utrs) = self.exonic_translation_regions.get(seq_name) {
-            for utr in utrs.find(start, end + 1) {
-                let f = &utr.val;
+        for utr in features.exonic_translation_regions.find(start, end + 1) {
+            self.features_intersected += 1;
 
-                if !counted_as_five_prime_utr
-                    && f.name() == self.feature_names.five_prime_utr_feature_name
-                {
+            match utr.val.kind() {
+                FeatureKind::FivePrimeUtr if !counted_as_five_prime_utr => {
                     counted_as_five_prime_utr = true;
                     self.metrics.exonic_translation_regions.utr_five_prime_count += 1;
-                } else if !counted_as_three_prime_utr
-                    && f.name() == self.feature_names.three_prime_utr_feature_name
-                {
+                }
+                FeatureKind::ThreePrimeUtr if !counted_as_three_prime_utr => {
                     counted_as_three_prime_utr = true;
                     self.metrics
                         .exonic_translation_regions
                         .utr_three_prime_count += 1;
-                } else if !counted_as_coding_sequence
-                    && f.name() == self.feature_names.coding_sequence_feature_name
-                {
+                }
+                FeatureKind::CodingSequence if !counted_as_coding_sequence => {
                     counted_as_coding_sequence = true;
                     self.metrics
                         .exonic_translation_regions
                         .coding_sequence_count += 1;
                 }
+                _ => {}
             }
         }
 
         // (7b) Tally up gene regions.
-        if let Some(genics) = self.gene_regions.get(seq_name) {
-            let mut has_gene = false;
-            let mut has_exon = false;
-            for gene in genics.find(start, end + 1) {
-                let f = &gene.val;
-
-                if f.name() == self.feature_names.gene_feature_name {
-                    has_gene = true;
-                } else if f.name() == self.feature_names.exon_feature_name {
-                    has_exon = true;
-                }
+        let mut has_gene = false;
+        let mut has_exon = false;
+        for gene in features.gene_regions.find(start, end + 1) {
+            self.features_intersected += 1;
+
+            match gene.val.kind() {
+                FeatureKind::Gene => has_gene = true,
+                FeatureKind::Exon => has_exon = true,
+                _ => {}
+            }
 
-                if has_gene && has_exon {
-                    break;
-                }
+            if has_gene && has_exon {
+                break;
             }
+        }
 
-            if has_gene {
-                if has_exon {
-                    self.metrics.gene_regions.exonic_count += 1;
-                } else {
-                    self.metrics.gene_regions.intronic_count += 1;
-                }
+        if has_gene {
+            if has_exon {
+                self.metrics.gene_regions.exonic_count += 1;
             } else {
-                self.metrics.gene_regions.intergenic_count += 1;
+                self.metrics.gene_regions.intronic_count += 1;
             }
+        } else {
+            self.metrics.gene_regions.intergenic_count += 1;
         }
 
+        // (7c) Assign the record to a gene and to a biotype. Each read is only
+        // counted once, so secondary and supplementary records are skipped.
+        if !flags.is_secondary() && !flags.is_supplementary() {
+            if let Some(counter) = &mut self.gene_counter {
+                counter.count(id, start, record);
+            }
+
+            if let Some(biotypes) = &mut self.biotypes {
+                biotypes.tally(id, start, end);
+            }
+        }
+
+        self.lookup_duration += lookup_start.elapsed();
         self.metrics.records.processed += 1;
         Ok(())
     }
 
     fn summarize(&mut self) -> anyhow::Result<()> {
-        self.metrics.summary = Some(SummaryMetrics {
-            ignored_flags_pct: (self.metrics.records.ignored_flags as f64
-                / (self.metrics.records.ignored_flags
-                    + self.metrics.records.ignored_nonprimary_chromosome
-                    + self.metrics.records.processed) as f64)
-                * 100.0,
-            ignored_nonprimary_chromosome_pct: (self.metrics.records.ignored_nonprimary_chromosome
-                as f64
-                / (self.metrics.records.ignored_flags
-                    + self.metrics.records.ignored_nonprimary_chromosome
-                    + self.metrics.records.processed) as f64)
-                * 100.0,
-        });
+        let lookups = self.metrics.records.processed;
+        let seconds = self.lookup_duration.as_secs_f64();
+
+        if lookups > 0 && seconds > 0.0 {
+            info!(
+                "Genomic Features: looked up {} records ({} features intersected) in {:.3}s \
+                ({:.0} records per second).",
+                lookups,
+                self.features_intersected,
+                seconds,
+                lookups as f64 / seconds
+            );
+        }
+
+        if let Some(counter) = &mut self.gene_counter {
+            counter.summarize();
+            self.metrics.gene_counts = Some(counter.metrics.clone());
+        }
 
+        if let Some(biotypes) = &mut self.biotypes {
+            biotypes.summarize();
+            self.metrics.biotypes = Some(biotypes.metrics.clone());
+        }
+
+        self.metrics.summarize();
         Ok(())
     }
 
@@ -261,93 +301,253 @@ impl<'a> RecordBasedQualityControlFacet for GenomicFeaturesFacet<'a> {
     }
 }
 
-impl<'a> GenomicFeaturesFacet<'a> {
+impl GenomicFeaturesFacet {
     /// Tries to create a [`GenomicFeaturesFacet`] from a set of provided
-    /// arguments. May fail if there are issues opening the GFF file.
+    /// arguments, configured by the provided [`FacetOptions`]. May fail if
+    /// there are issues opening the GFF file.
     pub fn try_from(
         src: PathBuf,
-        feature_names: &'a FeatureNames,
-        header: &'a Header,
+        feature_names: &FeatureNames,
+        header: &Header,
         reference_genome: Rc<Box<dyn ReferenceGenome>>,
+        options: &FacetOptions,
     ) -> anyhow::Result<Self> {
         let mut gff = formats::gff::open(&src)
             .with_context(|| format!("Could not open GFF: {}", src.display()))?;
 
-        let mut exonic_translations = HashMap::new();
-        let mut gene_regions = HashMap::new();
-
-        debug!("Reading all records in GFF.");
-        let mut gff_records = Vec::new();
-        for result in gff.records() {
-            let record = result.unwrap();
-            gff_records.push(record);
-        }
-
-        debug!("Tabulating GFF features.");
-        let primary_assembly_sequence_names: Vec<String> = get_primary_assembly(reference_genome)
+        // (1) Index the relevant features by the primary assembly sequence
+        // they are on in a single, streaming pass through the GFF (see
+        // [`loader`]). Only the sequences that are in the header (and
+        // analyzed) are retained, and the records on any other sequence are
+        // skipped without being parsed. The sequences in the header are
+        // matched by their normalized names (see [`normalize_sequence_name`]),
+        // so the features are found for pangenome sequence names as well.
+        let in_header: HashSet<&str> = header
+            .reference_sequences()
+            .keys()
+            .filter(|name| {
+                options
+                    .analyzed_sequences
+                    .as_ref()
+                    .is_none_or(|analyzed| analyzed.contains(name.as_str()))
+            })
+            .map(|name| normalize_sequence_name(name.as_str()))
+            .collect();
+        let retained: HashSet<String> = get_primary_assembly(reference_genome)
             .iter()
+            .filter(|s| in_header.contains(s.name()))
             .map(|s| String::from(s.name()))
             .collect();
+        let mut utr_features: IncrementalIndex<Feature> = IncrementalIndex::default();
+        let mut gene_region_features: IncrementalIndex<Feature> = IncrementalIndex::default();
+
+        // If per-gene counting is enabled, the exons are also grouped into
+        // genes by the configured attribute.
+        let gene_id_attribute = options
+            .gene_counting
+            .as_ref()
+            .map(|o| o.gene_id_attribute.as_str());
+        let mut genes: Vec<Gene> = Vec::new();
+        let mut gene_indices: HashMap<String, usize> = HashMap::new();
+        let mut exons_by_sequence: IncrementalIndex<usize> = IncrementalIndex::default();
+        let mut exons_without_gene_id = 0;
+
+        // Genes are grouped by their biotype, if they are annotated with one.
+        let mut biotypes: Vec<String> = Vec::new();
+        let mut biotype_indices: HashMap<String, usize> = HashMap::new();
+        let mut genes_by_sequence: IncrementalIndex<usize> = IncrementalIndex::default();
+
+        debug!(
+            "Reading and tabulating the features on {} sequences in GFF.",
+            retained.len()
+        );
+        let mut records = formats::gff::RetainedRecords::new(&mut gff, &retained);
+        let mut records_retained = 0;
+        for result in records.by_ref() {
+            let record = result?;
+            let name = record.reference_sequence_name();
+            records_retained += 1;
+
+            let kind = match FeatureKind::from_feature_type(record.ty(), feature_names) {
+                Some(kind) => kind,
+                None => continue,
+            };
+
+            let strand = record.strand().as_ref().parse::<Strand>()?;
+
+            if let (FeatureKind::Exon, Some(attribute)) = (kind, gene_id_attribute) {
+                match record.attributes().iter().find(|e| e.key() == attribute) {
+                    Some(entry) => {
+                        let gene = *gene_indices
+                            .entry(entry.value().to_string())
+                            .or_insert_with(|| {
+                                genes.push(Gene {
+                                    id: entry.value().to_string(),
+                                    strand: strand.clone(),
+                                });
+                                genes.len() - 1
+                            });
+
+                        exons_by_sequence.push(
+                            name,
+                            Interval {
+                                start: record.start().into(),
+                                stop: usize::from(record.end()) + 1,
+                                val: gene,
+                            },
+                        );
+                    }
+                    None => exons_without_gene_id += 1,
+                }
+            }
 
-        for parent_seq_name in primary_assembly_sequence_names.iter() {
-            let mut utr_features: Vec<Interval<usize, FeatureNameStrand>> = Vec::new();
-            let mut gene_region_features: Vec<Interval<usize, FeatureNameStrand>> = Vec::new();
-
-            for record in &gff_records {
-                let seq_name = record.reference_sequence_name();
-                if parent_seq_name == seq_name {
-                    let ty = record.ty();
-                    let strand = record.strand();
-                    let start: usize = record.start().into();
-                    let stop: usize = record.end().into();
-
-                    let feature_name_strand = FeatureNameStrand::new(
-                        ty.to_string(),
-                        strand.to_string().parse::<Strand>()?,
+            if kind == FeatureKind::Gene {
+                let biotype = BIOTYPE_ATTRIBUTES.iter().find_map(|attribute| {
+                    record
+                        .attributes()
+                        .iter()
+                        .find(|e| e.key() == *attribute)
+                        .map(|e| e.value())
+                });
+
+                if let Some(biotype) = biotype {
+                    let index = *biotype_indices
+                        .entry(biotype.to_string())
+                        .or_insert_with(|| {
+                            biotypes.push(biotype.to_string());
+                            biotypes.len() - 1
+                        });
+
+                    genes_by_sequence.push(
+                        name,
+                        Interval {
+                            start: record.start().into(),
+                            stop: usize::from(record.end()) + 1,
+                            val: index,
+                        },
                     );
-
-                    let interval = Interval {
-                        start,
-                        stop,
-                        val: feature_name_strand,
-                    };
-
-                    if ty == feature_names.five_prime_utr_feature_name
-                        || ty == feature_names.three_prime_utr_feature_name
-                        || ty == feature_names.coding_sequence_feature_name
-                    {
-                        utr_features.push(interval);
-                    } else if ty == feature_names.exon_feature_name
-                        || ty == feature_names.gene_feature_name
-                    {
-                        gene_region_features.push(interval);
-                    }
                 }
             }
 
-            debug!(
-                "{} has {} gene region features and {} exonic translation features.",
-                parent_seq_name,
-                gene_region_features.len(),
-                utr_features.len()
-            );
+            let interval: Interval<usize, Feature> = Interval {
+                start: record.start().into(),
+                stop: record.end().into(),
+                val: Feature::new(kind, strand),
+            };
 
-            exonic_translations.insert(parent_seq_name.to_string(), Lapper::new(utr_features));
-            gene_regions.insert(
-                parent_seq_name.to_string(),
-                Lapper::new(gene_region_features),
-            );
+            if kind.is_exonic_translation_region() {
+                utr_features.push(name, interval);
+            } else {
+                gene_region_features.push(name, interval);
+            }
+
+            let estimated_bytes = utr_features.estimated_bytes()
+                + gene_region_features.estimated_bytes()
+                + exons_by_sequence.estimated_bytes()
+                + genes_by_sequence.estimated_bytes();
+            loader::check_memory_cap(
+                estimated_bytes,
+                options.features_memory_cap,
+                records_retained,
+            )?;
         }
 
+        debug!(
+            "Skipped {} GFF records on sequences that are not analyzed.",
+            records.skipped()
+        );
+
+        let annotation = AnnotationMetrics {
+            intervals: utr_features.len()
+                + gene_region_features.len()
+                + exons_by_sequence.len()
+                + genes_by_sequence.len(),
+            estimated_bytes: utr_features.estimated_bytes()
+                + gene_region_features.estimated_bytes()
+                + exons_by_sequence.estimated_bytes()
+                + genes_by_sequence.estimated_bytes(),
+        };
+        info!(
+            "Loaded {} intervals from {} GFF records (~{:.1} MiB).",
+            annotation.intervals,
+            records_retained,
+            annotation.estimated_bytes as f64 / loader::BYTES_PER_MIB as f64
+        );
+
+        // (2) Index the features by each sequence's id within the header.
+        // Every retained sequence has features (even if there are none), as
+        // only the records on other sequences are ignored.
         debug!("Finalizing GFF features lookup.");
+        let mut utr_features = utr_features.finish();
+        let mut gene_region_features = gene_region_features.finish();
+        let features = header
+            .reference_sequences()
+            .keys()
+            .map(|name| {
+                let normalized = normalize_sequence_name(name.as_str());
+                retained.contains(normalized).then(|| {
+                    let empty = || Lapper::new(Vec::new());
+                    let features = SequenceFeatures {
+                        exonic_translation_regions: utr_features
+                            .remove(normalized)
+                            .unwrap_or_else(empty),
+                        gene_regions: gene_region_features
+                            .remove(normalized)
+                            .unwrap_or_else(empty),
+                    };
+
+                    debug!(
+                        "{} has {} gene region features and {} exonic translation features.",
+                        name,
+                        features.gene_regions.len(),
+                        features.exonic_translation_regions.len()
+                    );
+
+                    features
+                })
+            })
+            .collect();
+
+        let gene_counter = options.gene_counting.clone().map(|gene_counting| {
+            if exons_without_gene_id > 0 {
+                info!(
+                    "Skipped {} exons without a `{}` attribute for gene counting.",
+                    exons_without_gene_id, gene_counting.gene_id_attribute
+                );
+            }
+
+            let mut exons_by_sequence = exons_by_sequence.finish();
+            let exons = header
+                .reference_sequences()
+                .keys()
+                .map(|name| exons_by_sequence.remove(normalize_sequence_name(name.as_str())))
+                .collect();
+
+            GeneCounter::new(gene_counting, genes, exons)
+        });
+
+        let biotypes = (!biotypes.is_empty()).then(|| {
+            let mut genes_by_sequence = genes_by_sequence.finish();
+            let genes = header
+                .reference_sequences()
+                .keys()
+                .map(|name| genes_by_sequence.remove(normalize_sequence_name(name.as_str())))
+                .collect();
+
+            BiotypeTally::new(biotypes, genes)
+        });
 
         Ok(Self {
-            exonic_translation_regions: exonic_translations,
-            gene_regions,
-            feature_names,
-            header,
-            metrics: Metrics::default(),
-            primary_chromosome_names: primary_assembly_sequence_names,
+            features,
+            metrics: Metrics {
+                annotation: Some(annotation),
+                ..Default::default()
+            },
+            count_duplicates: options.count_duplicates,
+            features_intersected: 0,
+            lookup_duration: Duration::ZERO,
+            gene_counter,
+            biotypes,
         })
     }
 }
@@ -0,0 +1,229 @@
+//! Transcript biotype breakdown for the Features quality control facet.
+//!
+//! When the genes in the GFF are annotated with a biotype (via the `gene_type`
+//! attribute, as in GENCODE, or the `gene_biotype` attribute, as in Ensembl),
+//! each record is attributed to the biotype of the genes it overlaps. Records
+//! overlapping genes of more than one biotype are tallied as ambiguous, and
+//! records not overlapping any annotated gene are tallied as intergenic.
+//!
+//! In addition to the raw biotypes, the biotypes are grouped into the broad
+//! categories that are typically reported for RNA-seq libraries (see
+//! [`biotype_category`]). A high percentage of rRNA or mtRNA, for example,
+//! indicates poor rRNA depletion or a large fraction of mitochondrial reads.
+
+use std::collections::{BTreeMap, HashMap};
+
+use rust_lapper::Lapper;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{math::percentage, merge::Merge};
+
+/// The GFF attributes that genes' biotypes are read from, in order of
+/// preference.
+pub const BIOTYPE_ATTRIBUTES: [&str; 2] = ["gene_type", "gene_biotype"];
+
+/// Groups a biotype into a broad category.
+///
+/// ```
+/// use ngs::qc::record_based::features::biotypes::biotype_category;
+///
+/// assert_eq!(biotype_category("protein_coding"), "protein_coding");
+/// assert_eq!(biotype_category("lincRNA"), "lncRNA");
+/// assert_eq!(biotype_category("Mt_rRNA"), "mtRNA");
+/// assert_eq!(biotype_category("transcribed_unprocessed_pseudogene"), "pseudogene");
+/// assert_eq!(biotype_category("TEC"), "other");
+/// ```
+pub fn biotype_category(biotype: &str) -> &'static str {
+    match biotype {
+        "protein_coding" => "protein_coding",
+        "lncRNA"
+        | "lincRNA"
+        | "antisense"
+        | "sense_intronic"
+        | "sense_overlapping"
+        | "3prime_overlapping_ncRNA"
+        | "bidirectional_promoter_lncRNA"
+        | "macro_lncRNA" => "lncRNA",
+        "rRNA" => "rRNA",
+        "Mt_rRNA" | "Mt_tRNA" => "mtRNA",
+        "miRNA" | "misc_RNA" | "snRNA" | "snoRNA" | "scaRNA" | "sRNA" | "scRNA" | "vault_RNA"
+        | "ribozyme" => "small_RNA",
+        _ if biotype.ends_with("pseudogene") => "pseudogene",
+        _ => "other",
+    }
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// Summary statistics for the transcript biotype breakdown.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BiotypeSummaryMetrics {
+    /// The percentage of records attributed to each biotype category.
+    pub category_pct: BTreeMap<String, Option<f64>>,
+
+    /// The percentage of records that overlapped genes of more than one
+    /// biotype.
+    pub ambiguous_pct: Option<f64>,
+
+    /// The percentage of records that did not overlap any annotated gene.
+    pub intergenic_pct: Option<f64>,
+}
+
+/// Metrics related to the transcript biotype breakdown.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BiotypeMetrics {
+    /// The number of records that were attributed.
+    pub records: usize,
+
+    /// The number of records attributed to each biotype.
+    pub biotypes: BTreeMap<String, usize>,
+
+    /// The number of records that overlapped genes of more than one biotype.
+    pub ambiguous: usize,
+
+    /// The number of records that did not overlap any annotated gene.
+    pub intergenic: usize,
+
+    /// Summary statistics for the transcript biotype breakdown.
+    pub summary: Option<BiotypeSummaryMetrics>,
+}
+
+impl BiotypeMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let mut categories: HashMap<&'static str, usize> = HashMap::new();
+        for (biotype, count) in &self.biotypes {
+            *categories.entry(biotype_category(biotype)).or_insert(0) += count;
+        }
+
+        self.summary = Some(BiotypeSummaryMetrics {
+            category_pct: categories
+                .into_iter()
+                .map(|(category, count)| (category.to_string(), percentage(count, self.records)))
+                .collect(),
+            ambiguous_pct: percentage(self.ambiguous, self.records),
+            intergenic_pct: percentage(self.intergenic, self.records),
+        });
+    }
+}
+
+impl Merge for BiotypeMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records += other.records;
+        Merge::merge(&mut self.biotypes, other.biotypes)?;
+        self.ambiguous += other.ambiguous;
+        self.intergenic += other.intergenic;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=================//
+// Biotype tallier //
+//=================//
+
+/// Attributes records to the biotypes of the genes they overlap.
+pub struct BiotypeTally {
+    /// The distinct biotypes of the genes.
+    pub biotypes: Vec<String>,
+
+    /// The genes for each reference sequence (indexed by the reference
+    /// sequence's id within the header), valued by the index of the gene's
+    /// biotype.
+    pub genes: Vec<Option<Lapper<usize, usize>>>,
+
+    /// The biotype metrics.
+    pub metrics: BiotypeMetrics,
+}
+
+impl BiotypeTally {
+    /// Creates a new [`BiotypeTally`].
+    pub fn new(biotypes: Vec<String>, genes: Vec<Option<Lapper<usize, usize>>>) -> Self {
+        Self {
+            biotypes,
+            genes,
+            metrics: BiotypeMetrics::default(),
+        }
+    }
+
+    /// Attributes a record spanning the one-based range `[start, end)` on the
+    /// reference sequence with id `reference_sequence_id`.
+    pub fn tally(&mut self, reference_sequence_id: usize, start: usize, end: usize) {
+        self.metrics.records += 1;
+
+        let mut biotype = None;
+        if let Some(Some(genes)) = self.genes.get(reference_sequence_id) {
+            for gene in genes.find(start, end) {
+                match biotype {
+                    None => biotype = Some(gene.val),
+                    Some(existing) if existing != gene.val => {
+                        self.metrics.ambiguous += 1;
+                        return;
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        match biotype {
+            Some(index) => {
+                *self
+                    .metrics
+                    .biotypes
+                    .entry(self.biotypes[index].clone())
+                    .or_insert(0) += 1
+            }
+            None => self.metrics.intergenic += 1,
+        }
+    }
+
+    /// Computes the final metrics.
+    pub fn summarize(&mut self) {
+        self.metrics.summarize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_lapper::Interval;
+
+    use super::*;
+
+    #[test]
+    pub fn it_attributes_records_to_biotypes() {
+        let gene = |start, stop, val| Interval { start, stop, val };
+        let genes = Lapper::new(vec![
+            gene(100, 200, 0),
+            gene(150, 300, 1),
+            gene(400, 500, 2),
+        ]);
+        let mut tally = BiotypeTally::new(
+            vec![
+                String::from("protein_coding"),
+                String::from("lncRNA"),
+                String::from("Mt_rRNA"),
+            ],
+            vec![Some(genes), None],
+        );
+
+        tally.tally(0, 100, 120);
+        tally.tally(0, 160, 180);
+        tally.tally(0, 410, 420);
+        tally.tally(0, 600, 620);
+        tally.tally(1, 100, 120);
+        tally.summarize();
+
+        let metrics = &tally.metrics;
+        assert_eq!(metrics.records, 5);
+        assert_eq!(metrics.biotypes["protein_coding"], 1);
+        assert_eq!(metrics.biotypes["Mt_rRNA"], 1);
+        assert_eq!(metrics.ambiguous, 1);
+        assert_eq!(metrics.intergenic, 2);
+
+        let summary = metrics.summary.as_ref().unwrap();
+        assert_eq!(summary.category_pct["mtRNA"], Some(20.0));
+        assert_eq!(summary.intergenic_pct, Some(40.0));
+    }
+}
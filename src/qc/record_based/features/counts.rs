@@ -0,0 +1,452 @@
+//! Per-gene read counting for the Features quality control facet.
+//!
+//! Each record is assigned to a gene by intersecting its aligned blocks (the
+//! `M`, `=`, and `X` segments of its alignment) with the exons of each gene, in
+//! the manner of HTSeq and featureCounts:
+//!
+//! * In [`GeneCountingMode::Union`] mode, a record is compatible with every gene
+//!   that has an exon overlapping any of its aligned blocks.
+//! * In [`GeneCountingMode::Strict`] mode, a record is only compatible with the
+//!   genes that have an exon fully containing each of its aligned blocks.
+//!
+//! Records compatible with exactly one gene are assigned to that gene; records
+//! compatible with no genes or with more than one gene are tallied as
+//! `no_feature` or `ambiguous`, respectively. When the library is stranded,
+//! only genes on the strand the fragment originated from are considered.
+//! Each record is counted as a read (i.e., mates are counted separately).
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::bail;
+use noodles::sam::{alignment::Record, record::cigar::op::Kind};
+use rust_lapper::Lapper;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{intervals::Strand, math::percentage, merge::Merge};
+
+/// The default GFF attribute that exons are grouped into genes by.
+pub const DEFAULT_GENE_ID_ATTRIBUTE: &str = "gene_id";
+
+//=========//
+// Options //
+//=========//
+
+/// How records are assigned to genes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GeneCountingMode {
+    /// A record is compatible with any gene overlapping one of its aligned
+    /// blocks.
+    Union,
+
+    /// A record is compatible only with the genes that contain every one of
+    /// its aligned blocks.
+    Strict,
+}
+
+impl FromStr for GeneCountingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "union" => Ok(Self::Union),
+            "strict" => Ok(Self::Strict),
+            _ => bail!("invalid gene counting mode: {}", s),
+        }
+    }
+}
+
+/// The strandedness of the library.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strandedness {
+    /// Reads may originate from either strand.
+    Unstranded,
+
+    /// Read ones (and unpaired reads) are on the same strand as the gene.
+    Forward,
+
+    /// Read ones (and unpaired reads) are on the opposite strand of the gene
+    /// (e.g., dUTP-based protocols).
+    Reverse,
+}
+
+impl FromStr for Strandedness {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unstranded" => Ok(Self::Unstranded),
+            "forward" => Ok(Self::Forward),
+            "reverse" => Ok(Self::Reverse),
+            _ => bail!("invalid strandedness: {}", s),
+        }
+    }
+}
+
+/// Options for per-gene read counting.
+#[derive(Clone, Debug)]
+pub struct GeneCountingOptions {
+    /// How records are assigned to genes.
+    pub mode: GeneCountingMode,
+
+    /// The strandedness of the library.
+    pub strandedness: Strandedness,
+
+    /// The GFF attribute that exons are grouped into genes by.
+    pub gene_id_attribute: String,
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// Summary statistics for per-gene read counting.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GeneCountSummaryMetrics {
+    /// The percentage of counted records that were assigned to a gene.
+    pub assigned_pct: Option<f64>,
+
+    /// The percentage of assigned records that were assigned to the top 1% of
+    /// genes (by count). A high percentage indicates that the library is
+    /// dominated by a small number of highly expressed genes.
+    pub top_one_percent_genes_pct: Option<f64>,
+}
+
+/// Metrics related to per-gene read counting.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GeneCountMetrics {
+    /// The number of genes in the gene model.
+    pub genes: usize,
+
+    /// The number of genes with at least one assigned record.
+    pub genes_detected: usize,
+
+    /// The number of records assigned to exactly one gene.
+    pub assigned: usize,
+
+    /// The number of records that were not compatible with any gene.
+    pub no_feature: usize,
+
+    /// The number of records that were compatible with more than one gene.
+    pub ambiguous: usize,
+
+    /// The number of records assigned to each gene in the gene model.
+    pub counts: BTreeMap<String, usize>,
+
+    /// Summary statistics for per-gene read counting.
+    pub summary: Option<GeneCountSummaryMetrics>,
+}
+
+impl GeneCountMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        self.genes_detected = self.counts.values().filter(|count| **count > 0).count();
+
+        let mut counts: Vec<usize> = self.counts.values().copied().collect();
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        let top = self.genes.div_ceil(100).max(1);
+        let top_counts: usize = counts.iter().take(top).sum();
+
+        self.summary = Some(GeneCountSummaryMetrics {
+            assigned_pct: percentage(
+                self.assigned,
+                self.assigned + self.no_feature + self.ambiguous,
+            ),
+            top_one_percent_genes_pct: percentage(top_counts, self.assigned),
+        });
+    }
+
+    /// Writes the per-gene counts as a TSV (with a `gene_id` and `count`
+    /// column), sorted by gene id.
+    pub fn write_tsv(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "gene_id\tcount")?;
+
+        let counts: BTreeMap<&String, &usize> = self.counts.iter().collect();
+        for (id, count) in counts {
+            writeln!(writer, "{}\t{}", id, count)?;
+        }
+
+        writer.flush()
+    }
+}
+
+impl Merge for GeneCountMetrics {
+    /// Merges the metrics. Both sets of metrics are expected to have been
+    /// computed against the same gene model.
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.genes = self.genes.max(other.genes);
+        self.assigned += other.assigned;
+        self.no_feature += other.no_feature;
+        self.ambiguous += other.ambiguous;
+        Merge::merge(&mut self.counts, other.counts)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//==============//
+// Gene counter //
+//==============//
+
+/// A gene in the gene model.
+pub struct Gene {
+    /// The id of the gene.
+    pub id: String,
+
+    /// The strand of the gene.
+    pub strand: Strand,
+}
+
+/// Assigns records to genes and tallies the counts.
+pub struct GeneCounter {
+    /// The options for per-gene read counting.
+    pub options: GeneCountingOptions,
+
+    /// The genes in the gene model.
+    pub genes: Vec<Gene>,
+
+    /// The exons for each reference sequence (indexed by the reference
+    /// sequence's id within the header), valued by the index of the gene they
+    /// belong to.
+    pub exons: Vec<Option<Lapper<usize, usize>>>,
+
+    /// The number of records assigned to each gene, indexed by the gene.
+    pub counts: Vec<usize>,
+
+    /// The counting metrics.
+    pub metrics: GeneCountMetrics,
+}
+
+impl GeneCounter {
+    /// Creates a new [`GeneCounter`].
+    pub fn new(
+        options: GeneCountingOptions,
+        genes: Vec<Gene>,
+        exons: Vec<Option<Lapper<usize, usize>>>,
+    ) -> Self {
+        let counts = vec![0; genes.len()];
+
+        Self {
+            options,
+            metrics: GeneCountMetrics {
+                genes: genes.len(),
+                ..Default::default()
+            },
+            genes,
+            exons,
+            counts,
+        }
+    }
+
+    /// Gets the strand that the gene a record originated from must be on, if
+    /// the library is stranded.
+    fn required_strand(&self, record: &Record) -> Option<Strand> {
+        let flags = record.flags();
+        let is_forward = !flags.is_reverse_complemented();
+        let is_read_two = flags.is_segmented() && flags.is_last_segment();
+
+        let same_as_record = match self.options.strandedness {
+            Strandedness::Unstranded => return None,
+            Strandedness::Forward => !is_read_two,
+            Strandedness::Reverse => is_read_two,
+        };
+
+        Some(if is_forward == same_as_record {
+            Strand::Forward
+        } else {
+            Strand::Reverse
+        })
+    }
+
+    /// Assigns a record aligned to the reference sequence with id
+    /// `reference_sequence_id` to a gene (if possible) and tallies the result.
+    pub fn count(&mut self, reference_sequence_id: usize, start: usize, record: &Record) {
+        let exons = match self.exons.get(reference_sequence_id) {
+            Some(Some(exons)) => exons,
+            _ => {
+                self.metrics.no_feature += 1;
+                return;
+            }
+        };
+
+        let required_strand = self.required_strand(record);
+        let is_compatible = |gene: usize| {
+            required_strand
+                .as_ref()
+                .is_none_or(|s| &self.genes[gene].strand == s)
+        };
+
+        // (1) Find the genes compatible with each aligned block and combine
+        // them according to the mode.
+        let mut compatible: Option<Vec<usize>> = None;
+        let mut reference_position = start;
+
+        for op in record.cigar().iter() {
+            let len = op.len();
+
+            match op.kind() {
+                Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch => {
+                    let (block_start, block_end) = (reference_position, reference_position + len);
+
+                    let mut genes: Vec<usize> = exons
+                        .find(block_start, block_end)
+                        .filter(|exon| match self.options.mode {
+                            GeneCountingMode::Union => true,
+                            GeneCountingMode::Strict => {
+                                exon.start <= block_start && exon.stop >= block_end
+                            }
+                        })
+                        .map(|exon| exon.val)
+                        .filter(|gene| is_compatible(*gene))
+                        .collect();
+                    genes.sort_unstable();
+                    genes.dedup();
+
+                    compatible = Some(match (compatible, self.options.mode) {
+                        (None, _) => genes,
+                        (Some(mut existing), GeneCountingMode::Union) => {
+                            existing.extend(genes);
+                            existing.sort_unstable();
+                            existing.dedup();
+                            existing
+                        }
+                        (Some(existing), GeneCountingMode::Strict) => existing
+                            .into_iter()
+                            .filter(|gene| genes.binary_search(gene).is_ok())
+                            .collect(),
+                    });
+
+                    reference_position += len;
+                }
+                Kind::Deletion | Kind::Skip => reference_position += len,
+                _ => {}
+            }
+        }
+
+        // (2) Tally the result.
+        match compatible.as_deref() {
+            Some([gene]) => {
+                self.counts[*gene] += 1;
+                self.metrics.assigned += 1;
+            }
+            Some([]) | None => self.metrics.no_feature += 1,
+            Some(_) => self.metrics.ambiguous += 1,
+        }
+    }
+
+    /// Computes the final metrics.
+    pub fn summarize(&mut self) {
+        self.metrics.counts = self
+            .genes
+            .iter()
+            .zip(self.counts.iter())
+            .map(|(gene, count)| (gene.id.clone(), *count))
+            .collect();
+        self.metrics.summarize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{core::Position, sam::record::Flags};
+    use rust_lapper::Interval;
+
+    use super::*;
+
+    fn counter(mode: GeneCountingMode, strandedness: Strandedness) -> GeneCounter {
+        // Gene a: exons at [100, 200) and [300, 400) on the forward strand.
+        // Gene b: an exon at [150, 250) on the reverse strand.
+        let exon = |start, stop, val| Interval { start, stop, val };
+        let exons = Lapper::new(vec![
+            exon(100, 200, 0),
+            exon(300, 400, 0),
+            exon(150, 250, 1),
+        ]);
+
+        GeneCounter::new(
+            GeneCountingOptions {
+                mode,
+                strandedness,
+                gene_id_attribute: String::from(DEFAULT_GENE_ID_ATTRIBUTE),
+            },
+            vec![
+                Gene {
+                    id: String::from("a"),
+                    strand: Strand::Forward,
+                },
+                Gene {
+                    id: String::from("b"),
+                    strand: Strand::Reverse,
+                },
+            ],
+            vec![Some(exons)],
+        )
+    }
+
+    fn record(start: usize, cigar: &str, flags: Flags) -> anyhow::Result<Record> {
+        Ok(Record::builder()
+            .set_flags(flags)
+            .set_alignment_start(Position::try_from(start)?)
+            .set_cigar(cigar.parse()?)
+            .build())
+    }
+
+    #[test]
+    pub fn it_assigns_records_in_union_and_strict_modes() -> anyhow::Result<()> {
+        // A spliced record within gene a's exons, a record overlapping both
+        // genes, and a record hanging off the start of gene a's first exon.
+        let records = [
+            record(110, "20M170N20M", Flags::empty())?,
+            record(160, "20M", Flags::empty())?,
+            record(90, "20M", Flags::empty())?,
+        ];
+
+        let mut union = counter(GeneCountingMode::Union, Strandedness::Unstranded);
+        let mut strict = counter(GeneCountingMode::Strict, Strandedness::Unstranded);
+        for record in &records {
+            let start = usize::from(record.alignment_start().unwrap());
+            union.count(0, start, record);
+            strict.count(0, start, record);
+        }
+        union.summarize();
+        strict.summarize();
+
+        assert_eq!(union.metrics.assigned, 2);
+        assert_eq!(union.metrics.ambiguous, 1);
+        assert_eq!(union.metrics.counts["a"], 2);
+
+        assert_eq!(strict.metrics.assigned, 1);
+        assert_eq!(strict.metrics.ambiguous, 1);
+        assert_eq!(strict.metrics.no_feature, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_only_considers_genes_on_the_expected_strand() -> anyhow::Result<()> {
+        let mut forward = counter(GeneCountingMode::Union, Strandedness::Forward);
+        let mut reverse = counter(GeneCountingMode::Union, Strandedness::Reverse);
+
+        // Overlaps both genes; aligned to the forward strand.
+        let record = record(160, "20M", Flags::empty())?;
+        forward.count(0, 160, &record);
+        reverse.count(0, 160, &record);
+        forward.summarize();
+        reverse.summarize();
+
+        assert_eq!(forward.metrics.counts["a"], 1);
+        assert_eq!(reverse.metrics.counts["b"], 1);
+
+        let summary = forward.metrics.summary.unwrap();
+        assert_eq!(summary.assigned_pct, Some(100.0));
+        assert_eq!(summary.top_one_percent_genes_pct, Some(100.0));
+
+        Ok(())
+    }
+}
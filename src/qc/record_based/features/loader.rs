@@ -0,0 +1,203 @@
+//! Incremental loading of the features within a GFF file.
+//!
+//! Features are streamed from the GFF one record at a time and appended to
+//! the intervals of the sequence they are on. Annotations are conventionally
+//! grouped by sequence, so the interval tree for a sequence is built as soon
+//! as the GFF moves on to the next sequence, which keeps only one sequence's
+//! worth of unindexed intervals (and their spare capacity) in memory at a
+//! time. A sequence that reappears later in the GFF is reopened and indexed
+//! again once the GFF moves on from it.
+//!
+//! The memory used by the loaded features is estimated as they are loaded, so
+//! that loading can stop with a helpful error once it exceeds a cap rather
+//! than exhausting the memory of the machine.
+
+use std::{collections::HashMap, mem::size_of};
+
+use anyhow::bail;
+use rust_lapper::{Interval, Lapper};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::merge::Merge;
+
+/// The number of bytes within a mebibyte.
+pub const BYTES_PER_MIB: usize = 1024 * 1024;
+
+//=========//
+// Metrics //
+//=========//
+
+/// Metrics describing the features that were loaded from the GFF.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AnnotationMetrics {
+    /// The number of intervals loaded from the GFF (across the features used
+    /// for the gene regions, exonic translation regions, gene counts, and
+    /// biotypes).
+    pub intervals: usize,
+
+    /// The estimated memory used by the intervals (in bytes). When merged, this
+    /// is the largest estimate of any of the runs, as each run loads its own
+    /// features.
+    pub estimated_bytes: usize,
+}
+
+impl Merge for AnnotationMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.intervals += other.intervals;
+        self.estimated_bytes = self.estimated_bytes.max(other.estimated_bytes);
+        Ok(())
+    }
+}
+
+//================//
+// Interval index //
+//================//
+
+/// The intervals of each sequence, indexed into an interval tree as soon as
+/// the intervals of the next sequence start arriving.
+pub struct IncrementalIndex<T>
+where
+    T: Eq + Clone + Send + Sync,
+{
+    /// The sequence whose intervals are currently arriving and its intervals.
+    current: Option<(String, Vec<Interval<usize, T>>)>,
+
+    /// The interval trees of the sequences whose intervals have arrived.
+    finished: HashMap<String, Lapper<usize, T>>,
+
+    /// The total number of intervals.
+    intervals: usize,
+}
+
+impl<T> Default for IncrementalIndex<T>
+where
+    T: Eq + Clone + Send + Sync,
+{
+    fn default() -> Self {
+        Self {
+            current: None,
+            finished: HashMap::new(),
+            intervals: 0,
+        }
+    }
+}
+
+impl<T> IncrementalIndex<T>
+where
+    T: Eq + Clone + Send + Sync,
+{
+    /// The estimated memory used by each interval once it is indexed: the
+    /// interval itself and its start and stop within the interval tree.
+    pub const BYTES_PER_INTERVAL: usize = size_of::<Interval<usize, T>>() + 2 * size_of::<usize>();
+
+    /// Adds an interval on the named sequence.
+    pub fn push(&mut self, name: &str, interval: Interval<usize, T>) {
+        if self
+            .current
+            .as_ref()
+            .is_none_or(|(current, _)| current != name)
+        {
+            self.finish_current();
+
+            let intervals = self
+                .finished
+                .remove(name)
+                .map(|lapper| lapper.intervals)
+                .unwrap_or_default();
+            self.current = Some((name.to_string(), intervals));
+        }
+
+        if let Some((_, intervals)) = self.current.as_mut() {
+            intervals.push(interval);
+            self.intervals += 1;
+        }
+    }
+
+    /// Indexes the intervals of the current sequence (if any).
+    fn finish_current(&mut self) {
+        if let Some((name, mut intervals)) = self.current.take() {
+            intervals.shrink_to_fit();
+            self.finished.insert(name, Lapper::new(intervals));
+        }
+    }
+
+    /// The total number of intervals.
+    pub fn len(&self) -> usize {
+        self.intervals
+    }
+
+    /// Whether there are no intervals.
+    pub fn is_empty(&self) -> bool {
+        self.intervals == 0
+    }
+
+    /// The estimated memory used by the intervals (in bytes).
+    pub fn estimated_bytes(&self) -> usize {
+        self.intervals * Self::BYTES_PER_INTERVAL
+    }
+
+    /// Indexes any remaining intervals and returns the interval tree for each
+    /// sequence with at least one interval.
+    pub fn finish(mut self) -> HashMap<String, Lapper<usize, T>> {
+        self.finish_current();
+        self.finished
+    }
+}
+
+//========//
+// Budget //
+//========//
+
+/// Checks the estimated memory used by the loaded features against a cap.
+pub fn check_memory_cap(
+    estimated_bytes: usize,
+    cap: Option<usize>,
+    records: usize,
+) -> anyhow::Result<()> {
+    match cap {
+        Some(cap) if estimated_bytes > cap => bail!(
+            "The features loaded from the GFF exceeded the memory cap of {} MiB \
+            after {} records. Raise `--features-memory-cap` or limit the GFF to \
+            the sequences and feature types that are needed.",
+            cap / BYTES_PER_MIB,
+            records
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_indexes_each_sequence_incrementally() -> anyhow::Result<()> {
+        let interval = |start: usize, stop: usize| Interval {
+            start,
+            stop,
+            val: 0usize,
+        };
+
+        let mut index = IncrementalIndex::default();
+        index.push("chr1", interval(1, 10));
+        index.push("chr1", interval(5, 20));
+        index.push("chr2", interval(1, 5));
+        index.push("chr1", interval(30, 40));
+        assert_eq!(index.len(), 4);
+        assert_eq!(
+            index.estimated_bytes(),
+            4 * IncrementalIndex::<usize>::BYTES_PER_INTERVAL
+        );
+
+        let lappers = index.finish();
+        assert_eq!(lappers["chr1"].len(), 3);
+        assert_eq!(lappers["chr1"].find(8, 9).count(), 2);
+        assert_eq!(lappers["chr2"].len(), 1);
+
+        assert!(check_memory_cap(BYTES_PER_MIB, Some(2 * BYTES_PER_MIB), 10).is_ok());
+        assert!(check_memory_cap(BYTES_PER_MIB, None, 10).is_ok());
+        assert!(check_memory_cap(3 * BYTES_PER_MIB, Some(2 * BYTES_PER_MIB), 10).is_err());
+
+        Ok(())
+    }
+}
@@ -2,6 +2,9 @@
 
 use serde::{Deserialize, Serialize};
 
+use super::{biotypes::BiotypeMetrics, counts::GeneCountMetrics, loader::AnnotationMetrics};
+use crate::utils::{math::percentage, merge::Merge};
+
 /// Metrics related to the tallying of records in exonic translation regions
 /// (five prime UTR regions, three prime UTR regions, coding sequence regions).
 #[derive(Clone, Default, Serialize, Deserialize)]
@@ -55,10 +58,10 @@ pub struct RecordMetrics {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SummaryMetrics {
     /// The percentage of records that were ignored because of bad flags.
-    pub ignored_flags_pct: f64,
+    pub ignored_flags_pct: Option<f64>,
     /// The percentage of records that were ignored because they were not
     /// aligned to a primary chromosome.
-    pub ignored_nonprimary_chromosome_pct: f64,
+    pub ignored_nonprimary_chromosome_pct: Option<f64>,
 }
 
 /// Main metrics struct. This struct aggregates all of the minor metrics structs
@@ -74,6 +77,81 @@ pub struct Metrics {
     /// General record metrics.
     pub records: RecordMetrics,
 
+    /// Per-gene read counts, if gene counting was enabled (`--gene-counts`).
+    #[serde(default)]
+    pub gene_counts: Option<GeneCountMetrics>,
+
+    /// The breakdown of records by the biotype of the genes they overlap, if
+    /// the genes in the GFF are annotated with a biotype.
+    #[serde(default)]
+    pub biotypes: Option<BiotypeMetrics>,
+
+    /// The number of intervals loaded from the GFF and the memory they use.
+    #[serde(default)]
+    pub annotation: Option<AnnotationMetrics>,
+
     /// Summary statistics for the Features quality control facet.
     pub summary: Option<SummaryMetrics>,
 }
+
+impl Metrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let records = &self.records;
+        let total =
+            records.ignored_flags + records.ignored_nonprimary_chromosome + records.processed;
+
+        self.summary = Some(SummaryMetrics {
+            ignored_flags_pct: percentage(records.ignored_flags, total),
+            ignored_nonprimary_chromosome_pct: percentage(
+                records.ignored_nonprimary_chromosome,
+                total,
+            ),
+        });
+    }
+}
+
+//=========//
+// Merging //
+//=========//
+
+impl Merge for ExonicTranslationRegionMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.utr_five_prime_count += other.utr_five_prime_count;
+        self.utr_three_prime_count += other.utr_three_prime_count;
+        self.coding_sequence_count += other.coding_sequence_count;
+        Ok(())
+    }
+}
+
+impl Merge for GeneRegionMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.intergenic_count += other.intergenic_count;
+        self.exonic_count += other.exonic_count;
+        self.intronic_count += other.intronic_count;
+        Ok(())
+    }
+}
+
+impl Merge for RecordMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.processed += other.processed;
+        self.ignored_flags += other.ignored_flags;
+        self.ignored_nonprimary_chromosome += other.ignored_nonprimary_chromosome;
+        Ok(())
+    }
+}
+
+impl Merge for Metrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.exonic_translation_regions
+            .merge(other.exonic_translation_regions)?;
+        self.gene_regions.merge(other.gene_regions)?;
+        self.records.merge(other.records)?;
+        self.gene_counts.merge(other.gene_counts)?;
+        self.biotypes.merge(other.biotypes)?;
+        self.annotation.merge(other.annotation)?;
+        self.summarize();
+        Ok(())
+    }
+}
@@ -1,64 +1,103 @@
 //! Utilities for the Features quality control facet.
 
-use std::str::FromStr;
+pub use crate::utils::intervals::Strand;
 
-/// Strand orientation (forward or reverse). Useful when parsing a GFF or other
-/// features files.
-#[derive(Clone, PartialEq, Eq)]
-pub enum Strand {
-    /// Features that fall on the _forward_ strand.
-    Forward,
+use super::FeatureNames;
 
-    /// Features that fall of the _reverse_ strand.
-    Reverse,
-}
+/// The kinds of features in the gene model that records are tallied against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeatureKind {
+    /// A five prime UTR region.
+    FivePrimeUtr,
 
-/// Error that occurs when a strand cannot be parsed from a `&str`.
-#[derive(Debug)]
-pub struct StrandParseError(String);
+    /// A three prime UTR region.
+    ThreePrimeUtr,
 
-impl std::fmt::Display for StrandParseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "attempted to parse strand from value: {}", self.0)
-    }
-}
+    /// A coding sequence region.
+    CodingSequence,
 
-impl std::error::Error for StrandParseError {}
+    /// An exon region.
+    Exon,
 
-impl FromStr for Strand {
-    type Err = StrandParseError;
+    /// A gene region.
+    Gene,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "+" => Ok(Strand::Forward),
-            "-" => Ok(Strand::Reverse),
-            _ => Err(StrandParseError(String::from(s))),
+impl FeatureKind {
+    /// Gets the kind of a feature from its type in the GFF, if the type is one
+    /// of the provided [`FeatureNames`]. Resolving the kind once, when the GFF
+    /// is loaded, avoids comparing feature names for every record.
+    pub fn from_feature_type(ty: &str, feature_names: &FeatureNames) -> Option<Self> {
+        if ty == feature_names.five_prime_utr_feature_name {
+            Some(Self::FivePrimeUtr)
+        } else if ty == feature_names.three_prime_utr_feature_name {
+            Some(Self::ThreePrimeUtr)
+        } else if ty == feature_names.coding_sequence_feature_name {
+            Some(Self::CodingSequence)
+        } else if ty == feature_names.exon_feature_name {
+            Some(Self::Exon)
+        } else if ty == feature_names.gene_feature_name {
+            Some(Self::Gene)
+        } else {
+            None
         }
     }
+
+    /// Whether the feature is an exonic translation region (five prime UTR,
+    /// three prime UTR, or coding sequence).
+    pub fn is_exonic_translation_region(&self) -> bool {
+        matches!(
+            self,
+            Self::FivePrimeUtr | Self::ThreePrimeUtr | Self::CodingSequence
+        )
+    }
 }
 
-/// Utility struct that contains both a feature name and the strand that the
-/// feature is contained on. This is used when building the
+/// Utility struct that contains both the kind of a feature and the strand that
+/// the feature is contained on. This is used when building the
 /// [`rust_lapper::Lapper`] interval lookup.
-#[derive(Clone, PartialEq, Eq)]
-pub struct FeatureNameStrand {
-    name: String,
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Feature {
+    kind: FeatureKind,
     strand: Strand,
 }
 
-impl FeatureNameStrand {
-    /// Creates a new [`FeatureNameStrand`].
-    pub fn new(name: String, strand: Strand) -> Self {
-        FeatureNameStrand { name, strand }
+impl Feature {
+    /// Creates a new [`Feature`].
+    pub fn new(kind: FeatureKind, strand: Strand) -> Self {
+        Feature { kind, strand }
     }
 
-    /// Get a reference to the feature name strand's name.
-    pub fn name(&self) -> &str {
-        self.name.as_ref()
+    /// Get the feature's kind.
+    pub fn kind(&self) -> FeatureKind {
+        self.kind
     }
 
-    /// Get a reference to the feature name strand's strand.
+    /// Get a reference to the feature's strand.
     pub fn strand(&self) -> &Strand {
         &self.strand
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_resolves_feature_kinds_from_feature_names() {
+        let feature_names =
+            FeatureNames::new("five_prime_UTR", "three_prime_UTR", "CDS", "exon", "gene");
+
+        let kind = FeatureKind::from_feature_type("CDS", &feature_names).unwrap();
+        assert_eq!(kind, FeatureKind::CodingSequence);
+        assert!(kind.is_exonic_translation_region());
+
+        let kind = FeatureKind::from_feature_type("exon", &feature_names).unwrap();
+        assert!(!kind.is_exonic_translation_region());
+
+        assert_eq!(
+            FeatureKind::from_feature_type("transcript", &feature_names),
+            None
+        );
+    }
+}
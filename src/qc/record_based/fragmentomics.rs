@@ -0,0 +1,453 @@
+//! Functionality related to the Fragmentomics quality control facet.
+//!
+//! Cell-free DNA (cfDNA) is cut by nucleases between nucleosomes, so the
+//! lengths of its fragments peak at the length of DNA wrapped around a single
+//! nucleosome and its linker (~167 bp), with smaller peaks every ~10 bp below
+//! it (one for each turn of the helix) and at the di-nucleosome (~334 bp).
+//! Fragments from tumors tend to be shorter and to end in different motifs
+//! than those from healthy tissue, so liquid biopsy assays routinely check:
+//!
+//! * How prominent the mono-nucleosome peak is.
+//! * How strong the ~10 bp periodicity below the mono-nucleosome peak is.
+//! * The fraction of short (< 150 bp) fragments.
+//! * How often each 4-mer is found at the 5' ends of the fragments, and how
+//!   diverse those end motifs are.
+//!
+//! This facet is enabled with `--fragmentomics`. Each fragment's length is
+//! read from the template length of its first segment, and each read (the 5'
+//! end of which is an end of the fragment) contributes the first four bases
+//! it sequenced as an end motif.
+
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+use noodles::sam::alignment::Record;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet},
+    utils::{alignment::sequenced_bases, histogram::Histogram, math::percentage, merge::Merge},
+};
+
+/// The longest fragment tallied within the histogram of fragment lengths.
+pub const MAX_FRAGMENT_LENGTH: usize = 1000;
+
+/// Fragments shorter than this are considered short.
+pub const SHORT_FRAGMENT_LENGTH: usize = 150;
+
+/// The fragment lengths within which the mono-nucleosome peak is found.
+pub const MONONUCLEOSOME_RANGE: RangeInclusive<usize> = 120..=220;
+
+/// The fragment lengths within which the di-nucleosome peak is found.
+pub const DINUCLEOSOME_RANGE: RangeInclusive<usize> = 280..=400;
+
+/// The short fragment lengths compared against the long fragment lengths.
+pub const SHORT_RANGE: RangeInclusive<usize> = 100..=150;
+
+/// The long fragment lengths compared against the short fragment lengths.
+pub const LONG_RANGE: RangeInclusive<usize> = 151..=220;
+
+/// The fragment lengths over which the ~10 bp periodicity is measured. The
+/// range spans a whole number of periods.
+pub const PERIODICITY_RANGE: RangeInclusive<usize> = 100..=149;
+
+/// The period (in base pairs) of the periodicity in fragment lengths.
+pub const PERIOD: usize = 10;
+
+/// The length of the end motifs.
+pub const END_MOTIF_LENGTH: usize = 4;
+
+//=========//
+// Metrics //
+//=========//
+
+/// Summary statistics for the Fragmentomics quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The most common fragment length within the mono-nucleosome range (120
+    /// to 220 bp).
+    pub mononucleosome_peak: Option<usize>,
+
+    /// How far the mono-nucleosome peak rises above the lowest points between
+    /// it and either end of the mono-nucleosome range, as a fraction of its
+    /// height (i.e., its topographic prominence). This is zero if the lengths
+    /// only rise (or fall) across the range and approaches one for a sharp
+    /// peak.
+    pub mononucleosome_peak_prominence: Option<f64>,
+
+    /// The most common fragment length within the di-nucleosome range (280 to
+    /// 400 bp).
+    pub dinucleosome_peak: Option<usize>,
+
+    /// The percentage of fragments that are shorter than 150 bp.
+    pub short_fragment_pct: Option<f64>,
+
+    /// The ratio of short (100 to 150 bp) to long (151 to 220 bp) fragments.
+    pub short_to_long_ratio: Option<f64>,
+
+    /// The amplitude of the ~10 bp periodicity in the fragment lengths from 100
+    /// to 149 bp, relative to the mean number of fragments at those lengths
+    /// (once any linear trend is removed).
+    pub ten_bp_periodicity: Option<f64>,
+
+    /// The Shannon entropy of the end motifs, normalized to fall between zero
+    /// (a single motif) and one (every motif equally often).
+    pub end_motif_diversity: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding fragments.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FragmentomicsMetrics {
+    /// The distribution of fragment lengths up to 1,000 bp.
+    pub fragment_lengths: Histogram,
+
+    /// The number of fragments whose length was tallied.
+    pub fragments: usize,
+
+    /// The number of fragments that were longer than 1,000 bp.
+    pub fragments_too_long: usize,
+
+    /// The number of fragment ends whose end motif was tallied.
+    pub fragment_ends: usize,
+
+    /// The number of fragment ends with each end motif. Motifs containing
+    /// anything other than `A`, `C`, `G`, or `T` are not tallied.
+    pub end_motifs: BTreeMap<String, usize>,
+
+    /// Summary statistics for the Fragmentomics quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl Default for FragmentomicsMetrics {
+    fn default() -> Self {
+        Self {
+            fragment_lengths: Histogram::zero_based_with_capacity(MAX_FRAGMENT_LENGTH),
+            fragments: 0,
+            fragments_too_long: 0,
+            fragment_ends: 0,
+            end_motifs: BTreeMap::new(),
+            summary: None,
+        }
+    }
+}
+
+impl FragmentomicsMetrics {
+    /// Tallies the length of a fragment.
+    pub fn tally_length(&mut self, length: usize) {
+        match self.fragment_lengths.increment(length) {
+            Ok(()) => self.fragments += 1,
+            Err(_) => self.fragments_too_long += 1,
+        }
+    }
+
+    /// Tallies the end motif at the start of the bases sequenced from a
+    /// fragment end.
+    pub fn tally_end(&mut self, bases: &[u8]) {
+        let motif = match bases.get(..END_MOTIF_LENGTH) {
+            Some(motif) => motif,
+            None => return,
+        };
+
+        if motif
+            .iter()
+            .all(|base| matches!(base.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T'))
+        {
+            let motif = String::from_utf8_lossy(motif).to_ascii_uppercase();
+            *self.end_motifs.entry(motif).or_insert(0) += 1;
+            self.fragment_ends += 1;
+        }
+    }
+
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let lengths = &self.fragment_lengths;
+        let count_in = |range: RangeInclusive<usize>| range.map(|i| lengths.get(i)).sum::<usize>();
+
+        // (1) Find the nucleosome peaks.
+        let mononucleosome_peak = peak(lengths, MONONUCLEOSOME_RANGE);
+        let mononucleosome_peak_prominence =
+            mononucleosome_peak.map(|peak| prominence(lengths, MONONUCLEOSOME_RANGE, peak));
+        let dinucleosome_peak = peak(lengths, DINUCLEOSOME_RANGE);
+
+        // (2) Compare the short fragments with the rest.
+        let total = self.fragments + self.fragments_too_long;
+        let short = count_in(0..=SHORT_FRAGMENT_LENGTH - 1);
+        let long = count_in(LONG_RANGE);
+        let short_to_long_ratio = (long > 0).then(|| count_in(SHORT_RANGE) as f64 / long as f64);
+
+        // (3) Measure the periodicity and the diversity of the end motifs.
+        let periodicity = PERIODICITY_RANGE
+            .map(|i| lengths.get(i) as f64)
+            .collect::<Vec<_>>();
+
+        self.summary = Some(SummaryMetrics {
+            mononucleosome_peak,
+            mononucleosome_peak_prominence,
+            dinucleosome_peak,
+            short_fragment_pct: percentage(short, total),
+            short_to_long_ratio,
+            ten_bp_periodicity: relative_amplitude(&periodicity, PERIOD),
+            end_motif_diversity: normalized_entropy(
+                self.end_motifs.values().copied(),
+                4usize.pow(END_MOTIF_LENGTH as u32),
+            ),
+        });
+    }
+}
+
+/// Finds the most common length within a range of the histogram, if any
+/// length within the range was observed.
+fn peak(histogram: &Histogram, range: RangeInclusive<usize>) -> Option<usize> {
+    let mut peak = None;
+    let mut highest = 0;
+
+    for i in range {
+        if histogram.get(i) > highest {
+            highest = histogram.get(i);
+            peak = Some(i);
+        }
+    }
+
+    peak
+}
+
+/// Computes the topographic prominence of a peak within a range of the
+/// histogram as a fraction of its height.
+fn prominence(histogram: &Histogram, range: RangeInclusive<usize>, peak: usize) -> f64 {
+    let height = histogram.get(peak) as f64;
+    let lowest = |positions: RangeInclusive<usize>| {
+        positions.map(|i| histogram.get(i)).min().unwrap_or(0) as f64
+    };
+
+    let base = f64::max(lowest(*range.start()..=peak), lowest(peak..=*range.end()));
+    (height - base) / height
+}
+
+/// Computes the amplitude of the component of the values with the provided
+/// period (once any linear trend is removed), relative to their mean. `None`
+/// is returned if the mean is zero.
+fn relative_amplitude(values: &[f64], period: usize) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if mean == 0.0 {
+        return None;
+    }
+
+    // (1) Remove the least-squares linear trend.
+    let mean_x = (n - 1.0) / 2.0;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (x, y) in values.iter().enumerate() {
+        covariance += (x as f64 - mean_x) * (y - mean);
+        variance += (x as f64 - mean_x).powi(2);
+    }
+    let slope = if variance > 0.0 {
+        covariance / variance
+    } else {
+        0.0
+    };
+
+    // (2) Project the residuals onto the sinusoid with the provided period.
+    let (mut real, mut imaginary) = (0.0, 0.0);
+    for (x, y) in values.iter().enumerate() {
+        let residual = y - (mean + slope * (x as f64 - mean_x));
+        let angle = 2.0 * std::f64::consts::PI * x as f64 / period as f64;
+        real += residual * angle.cos();
+        imaginary += residual * angle.sin();
+    }
+
+    Some(2.0 * (real.powi(2) + imaginary.powi(2)).sqrt() / n / mean)
+}
+
+/// Computes the Shannon entropy of the counts, normalized by the entropy of
+/// `categories` equally common categories. `None` is returned if there are no
+/// counts.
+fn normalized_entropy(
+    counts: impl Iterator<Item = usize> + Clone,
+    categories: usize,
+) -> Option<f64> {
+    let total = counts.clone().sum::<usize>() as f64;
+    if total == 0.0 || categories < 2 {
+        return None;
+    }
+
+    let entropy = counts
+        .filter(|count| *count > 0)
+        .map(|count| {
+            let p = count as f64 / total;
+            -p * p.ln()
+        })
+        .sum::<f64>();
+
+    Some(entropy / (categories as f64).ln())
+}
+
+impl Merge for FragmentomicsMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.fragment_lengths.merge(other.fragment_lengths)?;
+        self.fragments += other.fragments;
+        self.fragments_too_long += other.fragments_too_long;
+        self.fragment_ends += other.fragment_ends;
+        Merge::merge(&mut self.end_motifs, other.end_motifs)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Fragmentomics quality control facet.
+#[derive(Default)]
+pub struct FragmentomicsFacet {
+    /// The main metric counting struct.
+    pub metrics: FragmentomicsMetrics,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+}
+
+impl FragmentomicsFacet {
+    /// Creates a new [`FragmentomicsFacet`] configured by the provided
+    /// [`FacetOptions`].
+    pub fn from_options(options: &FacetOptions) -> Self {
+        Self {
+            metrics: FragmentomicsMetrics::default(),
+            count_duplicates: options.count_duplicates,
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for FragmentomicsFacet {
+    fn name(&self) -> &'static str {
+        "Fragmentomics"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Only mapped, primary records are considered, so that each
+        // fragment end is only tallied once.
+        let flags = record.flags();
+        if flags.is_unmapped()
+            || flags.is_secondary()
+            || flags.is_supplementary()
+            || flags.is_qc_fail()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        // (2) The length of each fragment is tallied once, from its first
+        // segment. Templates whose mates are unmapped (or on another sequence)
+        // have no template length.
+        let template_length = record.template_length().unsigned_abs() as usize;
+        if flags.is_segmented()
+            && flags.is_first_segment()
+            && !flags.is_mate_unmapped()
+            && template_length > 0
+        {
+            self.metrics.tally_length(template_length);
+        }
+
+        // (3) The 5' end of every read is an end of its fragment.
+        self.metrics.tally_end(&sequenced_bases(record));
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.fragmentomics = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{
+        core::Position,
+        sam::record::{Flags, Sequence},
+    };
+
+    use super::*;
+
+    #[test]
+    pub fn it_summarizes_nucleosomal_fragments_and_end_motifs() -> anyhow::Result<()> {
+        let mut metrics = FragmentomicsMetrics::default();
+
+        // A mono-nucleosome peak at 167 bp that falls off to either side,
+        // with a ~10 bp periodicity below it.
+        for length in 100..=220usize {
+            let distance = length.abs_diff(167);
+            let mut count = 200usize.saturating_sub(distance * 3);
+            if length < 150 && length % 10 == 7 {
+                count += 40;
+            }
+
+            for _ in 0..count {
+                metrics.tally_length(length);
+            }
+        }
+        metrics.tally_length(334);
+        metrics.tally_length(5000);
+
+        for motif in ["CCCA", "CCCA", "ccca", "TGGA", "CCNA", "CC"] {
+            metrics.tally_end(motif.as_bytes());
+        }
+
+        metrics.summarize();
+        let summary = metrics.summary.as_ref().unwrap();
+        assert_eq!(summary.mononucleosome_peak, Some(167));
+        assert!(summary.mononucleosome_peak_prominence.unwrap() > 0.5);
+        assert_eq!(summary.dinucleosome_peak, Some(334));
+        assert!(summary.ten_bp_periodicity.unwrap() > 0.1);
+        assert_eq!(metrics.fragments_too_long, 1);
+        assert!(summary.short_fragment_pct.unwrap() > 0.0);
+
+        assert_eq!(metrics.fragment_ends, 4);
+        assert_eq!(metrics.end_motifs["CCCA"], 3);
+        let diversity = summary.end_motif_diversity.unwrap();
+        assert!(diversity > 0.0 && diversity < 0.2);
+
+        // Without any periodicity, the amplitude is negligible.
+        let flat = (0..50).map(|x| 100.0 + x as f64).collect::<Vec<_>>();
+        assert!(relative_amplitude(&flat, PERIOD).unwrap() < 1e-9);
+
+        // Only the first segment of each template contributes its length,
+        // but both contribute their end motifs.
+        let mut facet = FragmentomicsFacet::from_options(&FacetOptions::default());
+        for (flags, template_length, sequence) in [
+            (Flags::SEGMENTED | Flags::FIRST_SEGMENT, 167, "ACGTACGT"),
+            (
+                Flags::SEGMENTED | Flags::LAST_SEGMENT | Flags::REVERSE_COMPLEMENTED,
+                -167,
+                "ACGTAAAA",
+            ),
+        ] {
+            facet.process(
+                &Record::builder()
+                    .set_flags(flags)
+                    .set_reference_sequence_id(0)
+                    .set_alignment_start(Position::try_from(1)?)
+                    .set_template_length(template_length)
+                    .set_sequence(sequence.parse::<Sequence>()?)
+                    .build(),
+            )?;
+        }
+
+        assert_eq!(facet.metrics.fragments, 1);
+        assert_eq!(facet.metrics.fragment_lengths.get(167), 1);
+        assert_eq!(facet.metrics.end_motifs["ACGT"], 1);
+        assert_eq!(facet.metrics.end_motifs["TTTT"], 1);
+
+        Ok(())
+    }
+}
@@ -4,14 +4,17 @@ pub mod metrics;
 
 use noodles::sam;
 use rand::prelude::*;
-use sam::{alignment::Record, record::sequence::Base};
+use sam::alignment::Record;
 
 use crate::{
-    qc::{results, ComputationalLoad, RecordBasedQualityControlFacet},
-    utils::histogram::Histogram,
+    qc::{
+        results, strata::strand_stratum, ComputationalLoad, FacetOptions,
+        RecordBasedQualityControlFacet,
+    },
+    utils::{histogram::Histogram, vectorized::count_bases},
 };
 
-use self::metrics::{GCContentMetrics, SummaryMetrics};
+use self::metrics::GCContentMetrics;
 
 /// Truncates reads that are longer than this value by randomly selecting a
 /// substring of this size.
@@ -22,6 +25,33 @@ pub const TRUNCATION_LENGTH: usize = 100;
 pub struct GCContentFacet {
     /// The main metric counting struct.
     pub metrics: GCContentMetrics,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+
+    /// Whether the base composition should be reported for each combination
+    /// of read-in-pair and strand.
+    pub stratify_by_strand: bool,
+}
+
+impl GCContentFacet {
+    /// Creates a new [`GCContentFacet`].
+    pub fn new(count_duplicates: bool) -> Self {
+        Self {
+            metrics: GCContentMetrics::default(),
+            count_duplicates,
+            stratify_by_strand: false,
+        }
+    }
+
+    /// Creates a new [`GCContentFacet`] configured by the provided
+    /// [`FacetOptions`].
+    pub fn from_options(options: &FacetOptions) -> Self {
+        Self {
+            stratify_by_strand: options.stratify_by_strand,
+            ..Self::new(options.count_duplicates)
+        }
+    }
 }
 
 impl RecordBasedQualityControlFacet for GCContentFacet {
@@ -37,7 +67,7 @@ impl RecordBasedQualityControlFacet for GCContentFacet {
         // (1) Check the record's flags. If any of the flags aren't to our
         // liking, then we reject the record as an ignored flag record.
         let flags = record.flags();
-        if flags.is_duplicate() || flags.is_secondary() {
+        if (flags.is_duplicate() && !self.count_duplicates) || flags.is_secondary() {
             self.metrics.records.ignored_flags += 1;
             return Ok(());
         };
@@ -49,6 +79,18 @@ impl RecordBasedQualityControlFacet for GCContentFacet {
         let nucleobases = sequence.as_ref();
         let sequence_length = nucleobases.len();
 
+        // If requested, tally the base composition of the full record for its
+        // stratum. Unlike the GC content below, this isn't subject to the
+        // truncation length.
+        if self.stratify_by_strand {
+            self.metrics
+                .base_composition_by_strand
+                .get_or_insert_with(Default::default)
+                .entry(strand_stratum(flags).to_string())
+                .or_default()
+                .tally(nucleobases, flags.is_reverse_complemented());
+        }
+
         // (3) Checks whether the record is too short to check the GC bias for.
         // If the record is too short, it can only be a subset of the full range
         // of percentages from 0%-100%. We don't want that messing with our
@@ -63,7 +105,6 @@ impl RecordBasedQualityControlFacet for GCContentFacet {
         // so that we have a uniform chance of generating between 0%-100%. We
         // choose a random starting point within the record so as to not
         // introduce a bias towards the start of our record.
-        let mut gc_this_read = 0usize;
         let offset = if TRUNCATION_LENGTH < sequence_length {
             let max_offset = sequence_length - TRUNCATION_LENGTH;
             ThreadRng::default().gen_range(0..max_offset)
@@ -72,17 +113,11 @@ impl RecordBasedQualityControlFacet for GCContentFacet {
         };
 
         // (5) Count up the A's, C's, G's, and T's.
-        for i in 0..TRUNCATION_LENGTH {
-            let nucleobase = nucleobases[offset + i];
-            match nucleobase {
-                Base::C | Base::G => {
-                    gc_this_read += 1;
-                    self.metrics.nucleobases.total_gc_count += 1;
-                }
-                Base::A | Base::T => self.metrics.nucleobases.total_at_count += 1,
-                _ => self.metrics.nucleobases.total_other_count += 1,
-            }
-        }
+        let counts = count_bases(&nucleobases[offset..offset + TRUNCATION_LENGTH]);
+        let gc_this_read = counts.gc;
+        self.metrics.nucleobases.total_gc_count += counts.gc;
+        self.metrics.nucleobases.total_at_count += counts.at;
+        self.metrics.nucleobases.total_other_count += counts.other;
 
         // (6) Calculate the GC content for this read and increment the
         // histogram accordingly.
@@ -98,24 +133,7 @@ impl RecordBasedQualityControlFacet for GCContentFacet {
     }
 
     fn summarize(&mut self) -> anyhow::Result<()> {
-        self.metrics.summary = Some(SummaryMetrics {
-            gc_content_pct: (self.metrics.nucleobases.total_gc_count as f64
-                / (self.metrics.nucleobases.total_gc_count
-                    + self.metrics.nucleobases.total_at_count
-                    + self.metrics.nucleobases.total_other_count) as f64)
-                * 100.0,
-            ignored_flags_pct: (self.metrics.records.ignored_flags as f64
-                / (self.metrics.records.ignored_flags
-                    + self.metrics.records.ignored_too_short
-                    + self.metrics.records.processed) as f64)
-                * 100.0,
-            ignored_too_short_pct: (self.metrics.records.ignored_too_short as f64
-                / (self.metrics.records.ignored_flags
-                    + self.metrics.records.ignored_too_short
-                    + self.metrics.records.processed) as f64)
-                * 100.0,
-        });
-
+        self.metrics.summarize();
         Ok(())
     }
 
@@ -131,12 +149,13 @@ impl Default for GCContentMetrics {
             nucleobases: Default::default(),
             records: Default::default(),
             summary: Default::default(),
+            reference: Default::default(),
+            base_composition_by_strand: Default::default(),
         }
     }
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
 
@@ -147,4 +166,53 @@ mod tests {
         assert_eq!(default.metrics.histogram.range_stop(), 100);
         assert_eq!(default.metrics.histogram.range_len(), 101);
     }
+
+    #[test]
+    pub fn it_only_counts_duplicates_when_requested() -> anyhow::Result<()> {
+        let record = Record::builder()
+            .set_flags(sam::record::Flags::DUPLICATE)
+            .set_sequence("ACGT".repeat(25).parse()?)
+            .build();
+
+        let mut ignoring = GCContentFacet::new(false);
+        ignoring.process(&record)?;
+        assert_eq!(ignoring.metrics.records.ignored_flags, 1);
+        assert_eq!(ignoring.metrics.records.processed, 0);
+
+        let mut counting = GCContentFacet::new(true);
+        counting.process(&record)?;
+        assert_eq!(counting.metrics.records.ignored_flags, 0);
+        assert_eq!(counting.metrics.records.processed, 1);
+        assert_eq!(counting.metrics.histogram.get(50), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_stratifies_base_composition_by_strand() -> anyhow::Result<()> {
+        let mut facet = GCContentFacet::from_options(&FacetOptions {
+            stratify_by_strand: true,
+            ..Default::default()
+        });
+
+        let flags = sam::record::Flags::SEGMENTED | sam::record::Flags::FIRST_SEGMENT;
+        for flags in [flags, flags | sam::record::Flags::REVERSE_COMPLEMENTED] {
+            let record = Record::builder()
+                .set_flags(flags)
+                .set_sequence("AAGN".parse()?)
+                .build();
+            facet.process(&record)?;
+        }
+
+        let strata = facet.metrics.base_composition_by_strand.unwrap();
+        let forward = &strata["read_one_forward"];
+        assert_eq!((forward.a, forward.g, forward.other), (2, 1, 1));
+
+        // Reverse strand records are complemented back to the sequenced
+        // orientation.
+        let reverse = &strata["read_one_reverse"];
+        assert_eq!((reverse.t, reverse.c, reverse.other), (2, 1, 1));
+
+        Ok(())
+    }
 }
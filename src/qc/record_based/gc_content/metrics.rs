@@ -1,8 +1,11 @@
 //! Metrics related to the GC content quality control facet.
 
+use std::collections::BTreeMap;
+
+use noodles::sam::record::sequence::Base;
 use serde::{Deserialize, Serialize};
 
-use crate::utils::histogram::Histogram;
+use crate::utils::{histogram::Histogram, math::percentage, merge::Merge};
 
 /// Metrics related to nucleobase counting.
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
@@ -18,6 +21,45 @@ pub struct NucleobaseMetrics {
     pub total_other_count: usize,
 }
 
+/// Counts of each nucleobase, which are used to report the base composition
+/// of a stratum of records. Bases are counted in the orientation they were
+/// sequenced in (i.e., the bases of reverse strand records are complemented).
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct BaseCompositionMetrics {
+    /// Number of nucleobases read as an 'A'.
+    pub a: usize,
+
+    /// Number of nucleobases read as a 'C'.
+    pub c: usize,
+
+    /// Number of nucleobases read as a 'G'.
+    pub g: usize,
+
+    /// Number of nucleobases read as a 'T'.
+    pub t: usize,
+
+    /// Number of nucleobases which were not read as an 'A', a 'C', a 'G', or a
+    /// 'T'.
+    pub other: usize,
+}
+
+impl BaseCompositionMetrics {
+    /// Tallies the nucleobases of a record's sequence. If `reverse` is set, the
+    /// sequence is from a reverse strand record and each base is complemented.
+    pub fn tally(&mut self, sequence: &[Base], reverse: bool) {
+        for base in sequence {
+            let count = match (base, reverse) {
+                (Base::A, false) | (Base::T, true) => &mut self.a,
+                (Base::C, false) | (Base::G, true) => &mut self.c,
+                (Base::G, false) | (Base::C, true) => &mut self.g,
+                (Base::T, false) | (Base::A, true) => &mut self.t,
+                _ => &mut self.other,
+            };
+            *count += 1;
+        }
+    }
+}
+
 /// General metrics related to record counting.
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct RecordMetrics {
@@ -35,13 +77,13 @@ pub struct RecordMetrics {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SummaryMetrics {
     /// Mean GC content for the given sample.
-    pub gc_content_pct: f64,
+    pub gc_content_pct: Option<f64>,
 
     /// Percentage of records that were ignored because of flags.
-    pub ignored_flags_pct: f64,
+    pub ignored_flags_pct: Option<f64>,
 
     /// Percentage of records that were ignored because they were too short.
-    pub ignored_too_short_pct: f64,
+    pub ignored_too_short_pct: Option<f64>,
 }
 
 /// Primary struct used to compile stats regarding GC content.
@@ -64,4 +106,136 @@ pub struct GCContentMetrics {
 
     /// Summary statistics for the GC content control facet.
     pub summary: Option<SummaryMetrics>,
+
+    /// GC content of the reference windows that the records were aligned to,
+    /// as computed by the Reference GC Content facet. Comparing this
+    /// distribution to the distribution for the records themselves helps to
+    /// distinguish GC bias in the library from the composition of the genome.
+    #[serde(default)]
+    pub reference: Option<ReferenceGCContentMetrics>,
+
+    /// Base composition of the records for each combination of read-in-pair
+    /// and strand, if `--stratify-by-strand` was provided.
+    #[serde(default)]
+    pub base_composition_by_strand: Option<BTreeMap<String, BaseCompositionMetrics>>,
+}
+
+/// Metrics related to the GC content of the reference windows that records
+/// were aligned to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReferenceGCContentMetrics {
+    /// Histogram that represents the number of reference windows which have 0%
+    /// GC content all the way up to 100% GC content.
+    pub histogram: Histogram,
+
+    /// Struct holding all of the nucleobase metrics for the reference windows.
+    pub nucleobases: NucleobaseMetrics,
+
+    /// Struct containing all of the status of processed/ignored records.
+    pub records: RecordMetrics,
+
+    /// Summary statistics for the reference windows.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl Default for ReferenceGCContentMetrics {
+    fn default() -> Self {
+        Self {
+            histogram: Histogram::zero_based_with_capacity(100),
+            nucleobases: NucleobaseMetrics::default(),
+            records: RecordMetrics::default(),
+            summary: None,
+        }
+    }
+}
+
+impl SummaryMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    fn from_metrics(nucleobases: &NucleobaseMetrics, records: &RecordMetrics) -> Self {
+        let total_nucleobases =
+            nucleobases.total_gc_count + nucleobases.total_at_count + nucleobases.total_other_count;
+        let total_records = records.ignored_flags + records.ignored_too_short + records.processed;
+
+        Self {
+            gc_content_pct: percentage(nucleobases.total_gc_count, total_nucleobases),
+            ignored_flags_pct: percentage(records.ignored_flags, total_records),
+            ignored_too_short_pct: percentage(records.ignored_too_short, total_records),
+        }
+    }
+}
+
+impl GCContentMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        self.summary = Some(SummaryMetrics::from_metrics(
+            &self.nucleobases,
+            &self.records,
+        ));
+    }
+}
+
+impl ReferenceGCContentMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        self.summary = Some(SummaryMetrics::from_metrics(
+            &self.nucleobases,
+            &self.records,
+        ));
+    }
+}
+
+//=========//
+// Merging //
+//=========//
+
+impl Merge for NucleobaseMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.total_gc_count += other.total_gc_count;
+        self.total_at_count += other.total_at_count;
+        self.total_other_count += other.total_other_count;
+        Ok(())
+    }
+}
+
+impl Merge for RecordMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.processed += other.processed;
+        self.ignored_flags += other.ignored_flags;
+        self.ignored_too_short += other.ignored_too_short;
+        Ok(())
+    }
+}
+
+impl Merge for BaseCompositionMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.a += other.a;
+        self.c += other.c;
+        self.g += other.g;
+        self.t += other.t;
+        self.other += other.other;
+        Ok(())
+    }
+}
+
+impl Merge for ReferenceGCContentMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.histogram.merge(other.histogram)?;
+        self.nucleobases.merge(other.nucleobases)?;
+        self.records.merge(other.records)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+impl Merge for GCContentMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.histogram.merge(other.histogram)?;
+        self.nucleobases.merge(other.nucleobases)?;
+        self.records.merge(other.records)?;
+        self.reference.merge(other.reference)?;
+        self.base_composition_by_strand
+            .merge(other.base_composition_by_strand)?;
+        self.summarize();
+        Ok(())
+    }
 }
@@ -122,31 +122,7 @@ impl RecordBasedQualityControlFacet for GeneralMetricsFacet {
     }
 
     fn summarize(&mut self) -> anyhow::Result<()> {
-        let summary = SummaryMetrics {
-            duplication_pct: self.metrics.records.duplicate as f64
-                / self.metrics.records.total as f64
-                * 100.0,
-            mapped_pct: (1.0
-                - self.metrics.records.unmapped as f64 / self.metrics.records.total as f64)
-                * 100.0,
-            mate_reference_sequence_id_mismatch_pct: self
-                .metrics
-                .records
-                .mate_reference_sequence_id_mismatch
-                as f64
-                / self.metrics.records.total as f64
-                * 100.0,
-            mate_reference_sequence_id_mismatch_hq_pct: self
-                .metrics
-                .records
-                .mate_reference_sequence_id_mismatch_hq
-                as f64
-                / self.metrics.records.total as f64
-                * 100.0,
-        };
-
-        self.metrics.summary = Some(summary);
-
+        self.metrics.summarize();
         Ok(())
     }
 
@@ -154,3 +130,20 @@ impl RecordBasedQualityControlFacet for GeneralMetricsFacet {
         results.general = Some(self.metrics.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_summarizes_files_without_records() {
+        let mut facet = GeneralMetricsFacet::default();
+        facet.summarize().unwrap();
+
+        let summary = facet.metrics.summary.unwrap();
+        assert_eq!(summary.duplication_pct, None);
+        assert_eq!(summary.mapped_pct, None);
+        assert_eq!(summary.mate_reference_sequence_id_mismatch_pct, None);
+        assert_eq!(summary.mate_reference_sequence_id_mismatch_hq_pct, None);
+    }
+}
@@ -1,9 +1,11 @@
 //! Metrics related to the General quality control facet.
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use serde::{Deserialize, Serialize};
 
+use crate::utils::{math::percentage, merge::Merge};
+
 /// Metrics related to tallying read designations (primary, secondary,
 /// supplementary).
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -95,29 +97,29 @@ pub struct RecordMetrics {
 pub struct CigarMetrics {
     /// Count of each of the various CIGAR operations present within all read
     /// ones.
-    pub read_one_cigar_ops: HashMap<String, usize>,
+    pub read_one_cigar_ops: BTreeMap<String, usize>,
 
     /// Count of each of the various CIGAR operations present within all read
     /// twos.
-    pub read_two_cigar_ops: HashMap<String, usize>,
+    pub read_two_cigar_ops: BTreeMap<String, usize>,
 }
 
 /// Summary metrics related to the General quality control facet.
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct SummaryMetrics {
     /// Percentage of records that were marked as duplicate.
-    pub duplication_pct: f64,
+    pub duplication_pct: Option<f64>,
 
     /// Percentage of records that were marked as mapped.
-    pub mapped_pct: f64,
+    pub mapped_pct: Option<f64>,
 
     /// Percentage of records that qualified as
     /// `mate_reference_sequence_id_mismatch`.
-    pub mate_reference_sequence_id_mismatch_pct: f64,
+    pub mate_reference_sequence_id_mismatch_pct: Option<f64>,
 
     /// Percentage of records that qualified as
     /// `mate_reference_sequence_id_mismatch_hq`.
-    pub mate_reference_sequence_id_mismatch_hq_pct: f64,
+    pub mate_reference_sequence_id_mismatch_hq_pct: Option<f64>,
 }
 
 /// Aggregate struct for all minor metrics structs for the General quality
@@ -133,3 +135,71 @@ pub struct GeneralMetrics {
     /// Summary statistics for the General quality control facet.
     pub summary: Option<SummaryMetrics>,
 }
+
+impl GeneralMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let records = &self.records;
+        self.summary = Some(SummaryMetrics {
+            duplication_pct: percentage(records.duplicate, records.total),
+            mapped_pct: percentage(records.total - records.unmapped, records.total),
+            mate_reference_sequence_id_mismatch_pct: percentage(
+                records.mate_reference_sequence_id_mismatch,
+                records.total,
+            ),
+            mate_reference_sequence_id_mismatch_hq_pct: percentage(
+                records.mate_reference_sequence_id_mismatch_hq,
+                records.total,
+            ),
+        });
+    }
+}
+
+//=========//
+// Merging //
+//=========//
+
+impl Merge for ReadDesignationMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.primary += other.primary;
+        self.secondary += other.secondary;
+        self.supplementary += other.supplementary;
+        Ok(())
+    }
+}
+
+impl Merge for RecordMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.total += other.total;
+        self.unmapped += other.unmapped;
+        self.duplicate += other.duplicate;
+        self.designation.merge(other.designation)?;
+        self.primary_mapped += other.primary_mapped;
+        self.primary_duplicate += other.primary_duplicate;
+        self.paired += other.paired;
+        self.read_1 += other.read_1;
+        self.read_2 += other.read_2;
+        self.proper_pair += other.proper_pair;
+        self.singleton += other.singleton;
+        self.mate_mapped += other.mate_mapped;
+        self.mate_reference_sequence_id_mismatch += other.mate_reference_sequence_id_mismatch;
+        self.mate_reference_sequence_id_mismatch_hq += other.mate_reference_sequence_id_mismatch_hq;
+        Ok(())
+    }
+}
+
+impl Merge for CigarMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        Merge::merge(&mut self.read_one_cigar_ops, other.read_one_cigar_ops)?;
+        Merge::merge(&mut self.read_two_cigar_ops, other.read_two_cigar_ops)
+    }
+}
+
+impl Merge for GeneralMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records.merge(other.records)?;
+        self.cigar.merge(other.cigar)?;
+        self.summarize();
+        Ok(())
+    }
+}
@@ -0,0 +1,439 @@
+//! Functionality related to the Long Reads quality control facet.
+//!
+//! Long reads (PacBio or ONT) are judged by different metrics than short
+//! reads. This facet, which is enabled by `--profile long-read`, reports:
+//!
+//! * The distribution of alignment identities, from the gap-compressed
+//!   divergence (`de` tag, as written by minimap2) or, if that is absent, from
+//!   the edit distance (`NM` tag) over the alignment columns.
+//! * How often reads are split into a chain of supplementary alignments (from
+//!   the `SA` tag of each primary record), and how often that chain spans more
+//!   than one reference sequence.
+//! * The distribution of per-read mean qualities, from the `qs` tag (ONT) or
+//!   the read quality (`rq` tag, PacBio) converted to a Phred score.
+//!
+//! Only the primary record of each read is considered, so that every read is
+//! counted once.
+
+use noodles::sam::{
+    self,
+    alignment::Record,
+    record::{cigar::op::Kind, data::field::Tag},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet},
+    utils::{histogram::Histogram, math::percentage, merge::Merge},
+};
+
+/// The number of bins per percentage point within the identity histogram
+/// (i.e., identities are tallied to the nearest 0.1%).
+pub const IDENTITY_BINS_PER_PERCENT: usize = 10;
+
+/// The longest chain of alignments tallied within the histogram. Longer
+/// chains are tallied in the last bin.
+pub const MAX_CHAIN_LENGTH: usize = 64;
+
+/// The highest per-read quality tallied within the histogram. Higher
+/// qualities are tallied in the last bin.
+pub const MAX_READ_QUALITY: usize = 60;
+
+/// Gets the identity of an alignment (from zero to one) from its
+/// gap-compressed divergence or, if it has none, from its edit distance and
+/// the number of alignment columns (matches, mismatches, insertions, and
+/// deletions).
+pub fn alignment_identity(
+    divergence: Option<f64>,
+    edit_distance: Option<usize>,
+    columns: usize,
+) -> Option<f64> {
+    match (divergence, edit_distance) {
+        (Some(divergence), _) => Some((1.0 - divergence).clamp(0.0, 1.0)),
+        (None, Some(edits)) if columns > 0 => {
+            Some((1.0 - edits as f64 / columns as f64).clamp(0.0, 1.0))
+        }
+        _ => None,
+    }
+}
+
+/// Converts a read quality (the estimated fraction of correct bases, as in the
+/// `rq` tag) to a Phred-scaled quality, capped at [`MAX_READ_QUALITY`].
+pub fn phred_from_read_quality(read_quality: f64) -> f64 {
+    match read_quality >= 1.0 {
+        true => MAX_READ_QUALITY as f64,
+        false => f64::min(
+            -10.0 * (1.0 - read_quality.max(0.0)).log10(),
+            MAX_READ_QUALITY as f64,
+        ),
+    }
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// Summary statistics for the Long Reads quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The mean alignment identity (as a percentage).
+    pub mean_identity_pct: Option<f64>,
+
+    /// The median alignment identity (as a percentage).
+    pub median_identity_pct: Option<f64>,
+
+    /// The percentage of mapped reads whose alignment is split into a chain
+    /// of more than one alignment.
+    pub chained_pct: Option<f64>,
+
+    /// The percentage of chained reads whose chain spans more than one
+    /// reference sequence.
+    pub chains_across_sequences_pct: Option<f64>,
+
+    /// The mean number of alignments within each chain.
+    pub mean_chain_length: Option<f64>,
+
+    /// The mean per-read quality.
+    pub mean_read_quality: Option<f64>,
+
+    /// The median per-read quality.
+    pub median_read_quality: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding long reads.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LongReadMetrics {
+    /// The number of mapped reads.
+    pub mapped_reads: usize,
+
+    /// The distribution of alignment identities, in tenths of a percent.
+    pub identity: Histogram,
+
+    /// The number of mapped reads without a `de` or `NM` tag, whose identity
+    /// could not be computed.
+    pub identity_unavailable: usize,
+
+    /// The distribution of the number of alignments within each read's chain
+    /// (one for reads that are not split).
+    pub chain_length: Histogram,
+
+    /// The number of reads whose chain spans more than one reference
+    /// sequence.
+    pub chains_across_sequences: usize,
+
+    /// The distribution of per-read qualities (the integer part of the
+    /// Phred-scaled mean quality).
+    pub read_quality: Histogram,
+
+    /// The number of reads without a `qs` or `rq` tag.
+    pub read_quality_unavailable: usize,
+
+    /// Summary statistics for the Long Reads quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl Default for LongReadMetrics {
+    fn default() -> Self {
+        Self {
+            mapped_reads: 0,
+            identity: Histogram::zero_based_with_capacity(100 * IDENTITY_BINS_PER_PERCENT),
+            identity_unavailable: 0,
+            chain_length: Histogram::zero_based_with_capacity(MAX_CHAIN_LENGTH),
+            chains_across_sequences: 0,
+            read_quality: Histogram::zero_based_with_capacity(MAX_READ_QUALITY),
+            read_quality_unavailable: 0,
+            summary: None,
+        }
+    }
+}
+
+impl LongReadMetrics {
+    /// Tallies the identity of a mapped read's primary alignment.
+    pub fn tally_identity(&mut self, identity: Option<f64>) {
+        match identity {
+            Some(identity) => {
+                let bin = (identity * (100 * IDENTITY_BINS_PER_PERCENT) as f64).round() as usize;
+                // SAFETY: identities are clamped to fall between zero and one.
+                self.identity.increment(bin).unwrap();
+            }
+            None => self.identity_unavailable += 1,
+        }
+    }
+
+    /// Tallies the chain of a mapped read: the number of alignments within
+    /// the chain and whether it spans more than one reference sequence.
+    pub fn tally_chain(&mut self, alignments: usize, across_sequences: bool) {
+        // SAFETY: the chain length is clamped to the last bin.
+        self.chain_length
+            .increment(alignments.min(MAX_CHAIN_LENGTH))
+            .unwrap();
+        if across_sequences {
+            self.chains_across_sequences += 1;
+        }
+    }
+
+    /// Tallies the per-read quality of a read.
+    pub fn tally_read_quality(&mut self, quality: Option<f64>) {
+        match quality {
+            Some(quality) => {
+                let bin = (quality.max(0.0) as usize).min(MAX_READ_QUALITY);
+                // SAFETY: the quality is clamped to the last bin.
+                self.read_quality.increment(bin).unwrap();
+            }
+            None => self.read_quality_unavailable += 1,
+        }
+    }
+
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let to_pct = |bin: f64| bin / IDENTITY_BINS_PER_PERCENT as f64;
+        let chains = self.chain_length.sum();
+        let chained = chains - self.chain_length.get(1) - self.chain_length.get(0);
+
+        self.summary = Some(SummaryMetrics {
+            mean_identity_pct: self.identity.mean().map(to_pct),
+            median_identity_pct: self.identity.median().map(to_pct),
+            chained_pct: percentage(chained, chains),
+            chains_across_sequences_pct: percentage(self.chains_across_sequences, chained),
+            mean_chain_length: self.chain_length.mean(),
+            mean_read_quality: self.read_quality.mean(),
+            median_read_quality: self.read_quality.median(),
+        });
+    }
+}
+
+impl Merge for LongReadMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.mapped_reads += other.mapped_reads;
+        self.identity.merge(other.identity)?;
+        self.identity_unavailable += other.identity_unavailable;
+        self.chain_length.merge(other.chain_length)?;
+        self.chains_across_sequences += other.chains_across_sequences;
+        self.read_quality.merge(other.read_quality)?;
+        self.read_quality_unavailable += other.read_quality_unavailable;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Long Reads quality control facet.
+pub struct LongReadsFacet {
+    /// The main metric counting struct.
+    pub metrics: LongReadMetrics,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+
+    /// The names of the reference sequences within the header, by id.
+    reference_sequence_names: Vec<String>,
+
+    /// The tags for the gap-compressed divergence (`de`), the mean quality
+    /// (`qs`), and the read quality (`rq`).
+    tags: (Tag, Tag, Tag),
+}
+
+impl LongReadsFacet {
+    /// Creates a new [`LongReadsFacet`] configured by the provided
+    /// [`FacetOptions`]. The header, if provided, resolves the name of each
+    /// primary alignment's reference sequence.
+    pub fn new(header: Option<&sam::Header>, options: &FacetOptions) -> Self {
+        let reference_sequence_names = header
+            .map(|header| {
+                header
+                    .reference_sequences()
+                    .keys()
+                    .map(|name| name.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            metrics: LongReadMetrics::default(),
+            count_duplicates: options.count_duplicates,
+            reference_sequence_names,
+            // SAFETY: each of the tags is two characters long.
+            tags: (
+                Tag::try_from(*b"de").unwrap(),
+                Tag::try_from(*b"qs").unwrap(),
+                Tag::try_from(*b"rq").unwrap(),
+            ),
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for LongReadsFacet {
+    fn name(&self) -> &'static str {
+        "Long Reads"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Only the primary record of each read is considered.
+        let flags = record.flags();
+        if flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        let data = record.data();
+        let (divergence_tag, mean_quality_tag, read_quality_tag) = self.tags;
+        let number = |tag: Tag| {
+            data.get(tag).and_then(|field| {
+                let value = field.value();
+                value
+                    .as_float()
+                    .map(f64::from)
+                    .or_else(|| value.as_int().map(|v| v as f64))
+            })
+        };
+
+        // (2) Tally the per-read quality, which unaligned reads have as well.
+        let quality = number(mean_quality_tag)
+            .or_else(|| number(read_quality_tag).map(phred_from_read_quality));
+        self.metrics.tally_read_quality(quality);
+
+        if flags.is_unmapped() {
+            return Ok(());
+        }
+        self.metrics.mapped_reads += 1;
+
+        // (3) Tally the identity of the primary alignment.
+        let columns = record
+            .cigar()
+            .iter()
+            .filter(|op| {
+                matches!(
+                    op.kind(),
+                    Kind::Match
+                        | Kind::SequenceMatch
+                        | Kind::SequenceMismatch
+                        | Kind::Insertion
+                        | Kind::Deletion
+                )
+            })
+            .map(|op| op.len())
+            .sum();
+        let edit_distance = number(Tag::EditDistance).map(|edits| edits.max(0.0) as usize);
+        self.metrics.tally_identity(alignment_identity(
+            number(divergence_tag),
+            edit_distance,
+            columns,
+        ));
+
+        // (4) Tally the chain of alignments. Each entry of the `SA` tag is
+        // another alignment of the read, of the form
+        // `rname,pos,strand,CIGAR,mapQ,NM;`.
+        let others = data
+            .get(Tag::OtherAlignments)
+            .and_then(|field| field.value().as_str())
+            .unwrap_or_default();
+        let sequence_names = others
+            .split(';')
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| entry.split(',').next())
+            .collect::<Vec<_>>();
+
+        // The chain spans more than one sequence if any of its alignments are
+        // on a sequence other than that of the primary alignment (or, if the
+        // primary's sequence name is unknown, other than the first).
+        let primary = record
+            .reference_sequence_id()
+            .and_then(|id| self.reference_sequence_names.get(id))
+            .map(String::as_str)
+            .or_else(|| sequence_names.first().copied());
+        let across_sequences = sequence_names.iter().any(|name| Some(*name) != primary);
+
+        self.metrics
+            .tally_chain(1 + sequence_names.len(), across_sequences);
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.long_reads = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{
+        core::Position,
+        sam::record::{Data, Flags},
+    };
+
+    use super::*;
+
+    #[test]
+    pub fn it_tallies_identities_chains_and_read_qualities() -> anyhow::Result<()> {
+        assert_eq!(alignment_identity(Some(0.05), Some(10), 100), Some(0.95));
+        assert_eq!(alignment_identity(None, Some(10), 100), Some(0.9));
+        assert_eq!(alignment_identity(None, None, 100), None);
+        assert_eq!(phred_from_read_quality(0.999).round(), 30.0);
+        assert_eq!(phred_from_read_quality(1.0), 60.0);
+
+        let header: sam::Header = "@SQ\tSN:chr1\tLN:10000\n@SQ\tSN:chr2\tLN:10000\n".parse()?;
+        let mut facet = LongReadsFacet::new(Some(&header), &FacetOptions::default());
+
+        let record = |flags: Flags, cigar: &str, data: &str| -> anyhow::Result<Record> {
+            Ok(Record::builder()
+                .set_flags(flags)
+                .set_reference_sequence_id(0)
+                .set_alignment_start(Position::try_from(1)?)
+                .set_cigar(cigar.parse()?)
+                .set_data(data.parse::<Data>()?)
+                .build())
+        };
+
+        // Identity from the divergence, chained within the same sequence.
+        facet.process(&record(
+            Flags::empty(),
+            "100M",
+            "de:f:0.02\tqs:i:20\tSA:Z:chr1,5000,+,50S50M,60,0;",
+        )?)?;
+        // Identity from the edit distance, chained across sequences.
+        facet.process(&record(
+            Flags::empty(),
+            "90M5I5D",
+            "NM:i:10\trq:f:0.995\tSA:Z:chr2,1,-,50M50S,60,0;chr1,900,+,50M50S,60,0;",
+        )?)?;
+        // Without any of the tags.
+        facet.process(&record(Flags::empty(), "100M", "")?)?;
+        // Supplementary records are not considered.
+        facet.process(&record(Flags::SUPPLEMENTARY, "50M", "de:f:0.5")?)?;
+
+        facet.summarize()?;
+        let metrics = &facet.metrics;
+        assert_eq!(metrics.mapped_reads, 3);
+        assert_eq!(metrics.identity.get(980), 1);
+        assert_eq!(metrics.identity.get(900), 1);
+        assert_eq!(metrics.identity_unavailable, 1);
+        assert_eq!(metrics.chain_length.get(1), 1);
+        assert_eq!(metrics.chain_length.get(2), 1);
+        assert_eq!(metrics.chain_length.get(3), 1);
+        assert_eq!(metrics.chains_across_sequences, 1);
+        assert_eq!(metrics.read_quality.get(20), 1);
+        assert_eq!(metrics.read_quality.get(23), 1);
+        assert_eq!(metrics.read_quality_unavailable, 1);
+
+        let summary = metrics.summary.as_ref().unwrap();
+        assert_eq!(summary.mean_identity_pct, Some(94.0));
+        assert_eq!(summary.chains_across_sequences_pct, Some(50.0));
+        assert_eq!(summary.mean_chain_length, Some(2.0));
+        assert_eq!(summary.mean_read_quality, Some(21.5));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,313 @@
+//! Functionality related to the Mapping Quality quality control facet.
+//!
+//! Besides the distribution of mapping qualities, this facet reports the joint
+//! distribution of mapping quality and alignment score (the `AS` tag) when the
+//! aligner reports alignment scores. A well-configured aligner assigns lower
+//! mapping qualities to worse alignments, so the two should be positively
+//! correlated. Aligners that are misconfigured (or that do not compute mapping
+//! qualities) commonly report the same value for every record, such as 255
+//! (unavailable) or 60, which this facet flags.
+
+use std::collections::{BTreeMap, HashSet};
+
+use noodles::sam::{self, alignment::Record, record::data::field::Tag};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    qc::{results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet},
+    utils::{histogram::Histogram, math::percentage, merge::Merge},
+};
+
+//=========//
+// Metrics //
+//=========//
+
+/// Summary statistics for the Mapping Quality quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The mean mapping quality, excluding records whose mapping quality is
+    /// unavailable (255).
+    pub mean_mapping_quality: Option<f64>,
+
+    /// The percentage of records whose mapping quality is unavailable (255).
+    pub mapping_quality_unavailable_pct: Option<f64>,
+
+    /// The number of distinct mapping qualities observed.
+    pub distinct_mapping_qualities: usize,
+
+    /// The percentage of records that have an alignment score (`AS` tag).
+    pub alignment_score_pct: Option<f64>,
+
+    /// The Pearson correlation between the mapping quality and the alignment
+    /// score, for the records that have both. This is `None` if either is
+    /// constant.
+    pub mapping_quality_alignment_score_correlation: Option<f64>,
+
+    /// Whether the mapping qualities suggest that the aligner was
+    /// misconfigured: either every mapping quality is unavailable (255), or
+    /// the mapping quality is constant while the alignment score varies.
+    pub misconfiguration_suspected: bool,
+}
+
+/// Primary struct used to compile stats regarding mapping qualities.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MappingQualityMetrics {
+    /// The distribution of mapping qualities. Records without a mapping
+    /// quality are tallied as unavailable (255).
+    pub histogram: Histogram,
+
+    /// The number of records tallied within the histogram.
+    pub processed: usize,
+
+    /// The joint distribution of mapping quality and alignment score, keyed by
+    /// mapping quality and then by alignment score. This is empty if none of
+    /// the records have an alignment score.
+    pub alignment_score: BTreeMap<u8, BTreeMap<i64, usize>>,
+
+    /// The number of tallied records that did not have an alignment score.
+    pub without_alignment_score: usize,
+
+    /// Summary statistics for the Mapping Quality quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl Default for MappingQualityMetrics {
+    fn default() -> Self {
+        Self {
+            histogram: Histogram::zero_based_with_capacity(
+                sam::record::mapping_quality::MISSING as usize,
+            ),
+            processed: 0,
+            alignment_score: BTreeMap::new(),
+            without_alignment_score: 0,
+            summary: None,
+        }
+    }
+}
+
+impl MappingQualityMetrics {
+    /// Tallies the mapping quality (and alignment score, if any) of a record.
+    pub fn tally(&mut self, mapping_quality: u8, alignment_score: Option<i64>) {
+        // SAFETY: the histogram covers every possible mapping quality.
+        self.histogram.increment(mapping_quality as usize).unwrap();
+        self.processed += 1;
+
+        match alignment_score {
+            Some(score) => {
+                *self
+                    .alignment_score
+                    .entry(mapping_quality)
+                    .or_default()
+                    .entry(score)
+                    .or_insert(0) += 1
+            }
+            None => self.without_alignment_score += 1,
+        }
+    }
+
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let missing = sam::record::mapping_quality::MISSING;
+
+        // (1) Summarize the mapping qualities themselves.
+        let unavailable = self.histogram.get(missing as usize);
+        let available = self.processed - unavailable;
+        let mean_mapping_quality = (available > 0).then(|| {
+            let sum = (0..missing as usize)
+                .map(|mapq| mapq * self.histogram.get(mapq))
+                .sum::<usize>();
+            sum as f64 / available as f64
+        });
+
+        let qualities = self
+            .histogram
+            .values()
+            .iter()
+            .filter(|count| **count > 0)
+            .count();
+
+        // (2) Correlate the mapping quality with the alignment score for the
+        // records that have both.
+        let pairs = self
+            .alignment_score
+            .iter()
+            .filter(|(mapq, _)| **mapq != missing)
+            .flat_map(|(mapq, scores)| {
+                scores
+                    .iter()
+                    .map(move |(score, count)| (*mapq as f64, *score as f64, *count as f64))
+            })
+            .collect::<Vec<_>>();
+        let correlation = pearson_correlation(&pairs);
+
+        // (3) Check for signs of a misconfigured aligner.
+        let distinct_scores = self
+            .alignment_score
+            .values()
+            .flat_map(|scores| scores.keys())
+            .collect::<HashSet<_>>()
+            .len();
+        let misconfiguration_suspected = self.processed > 0
+            && (unavailable == self.processed || (qualities == 1 && distinct_scores > 1));
+
+        if misconfiguration_suspected {
+            warn!(
+                "The mapping qualities do not vary with alignment quality, which suggests \
+                that the aligner was misconfigured."
+            );
+        }
+
+        self.summary = Some(SummaryMetrics {
+            mean_mapping_quality,
+            mapping_quality_unavailable_pct: percentage(unavailable, self.processed),
+            distinct_mapping_qualities: qualities,
+            alignment_score_pct: percentage(
+                self.processed - self.without_alignment_score,
+                self.processed,
+            ),
+            mapping_quality_alignment_score_correlation: correlation,
+            misconfiguration_suspected,
+        });
+    }
+}
+
+/// Computes the Pearson correlation of weighted `(x, y, weight)` pairs. Returns
+/// `None` if there are no pairs or if either variable is constant.
+fn pearson_correlation(pairs: &[(f64, f64, f64)]) -> Option<f64> {
+    let n = pairs.iter().map(|(_, _, w)| w).sum::<f64>();
+    if n == 0.0 {
+        return None;
+    }
+
+    let mean_x = pairs.iter().map(|(x, _, w)| x * w).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|(_, y, w)| y * w).sum::<f64>() / n;
+
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for (x, y, w) in pairs {
+        covariance += w * (x - mean_x) * (y - mean_y);
+        variance_x += w * (x - mean_x).powi(2);
+        variance_y += w * (y - mean_y).powi(2);
+    }
+
+    if variance_x == 0.0 || variance_y == 0.0 {
+        return None;
+    }
+
+    Some(covariance / (variance_x * variance_y).sqrt())
+}
+
+impl Merge for MappingQualityMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.histogram.merge(other.histogram)?;
+        self.processed += other.processed;
+        Merge::merge(&mut self.alignment_score, other.alignment_score)?;
+        self.without_alignment_score += other.without_alignment_score;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Mapping Quality quality control facet.
+#[derive(Default)]
+pub struct MappingQualityFacet {
+    /// The main metric counting struct.
+    pub metrics: MappingQualityMetrics,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+}
+
+impl MappingQualityFacet {
+    /// Creates a new [`MappingQualityFacet`] configured by the provided
+    /// [`FacetOptions`].
+    pub fn from_options(options: &FacetOptions) -> Self {
+        Self {
+            metrics: MappingQualityMetrics::default(),
+            count_duplicates: options.count_duplicates,
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for MappingQualityFacet {
+    fn name(&self) -> &'static str {
+        "Mapping Quality"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // Only mapped, primary records are considered, as aligners assign a
+        // mapping quality of zero to most secondary records.
+        let flags = record.flags();
+        if flags.is_unmapped()
+            || flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        let mapping_quality = record
+            .mapping_quality()
+            .map(u8::from)
+            .unwrap_or(sam::record::mapping_quality::MISSING);
+        let alignment_score = record
+            .data()
+            .get(Tag::AlignmentScore)
+            .and_then(|field| field.value().as_int());
+
+        self.metrics.tally(mapping_quality, alignment_score);
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.mapping_quality = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_correlates_mapping_quality_with_alignment_score() {
+        let mut metrics = MappingQualityMetrics::default();
+        for (mapq, score) in [(60, 150), (60, 148), (30, 120), (0, 90)] {
+            metrics.tally(mapq, Some(score));
+        }
+        metrics.tally(60, None);
+        metrics.summarize();
+
+        let summary = metrics.summary.unwrap();
+        assert_eq!(summary.distinct_mapping_qualities, 3);
+        assert_eq!(summary.mean_mapping_quality, Some(42.0));
+        assert_eq!(summary.alignment_score_pct, Some(80.0));
+        assert!(summary.mapping_quality_alignment_score_correlation.unwrap() > 0.9);
+        assert!(!summary.misconfiguration_suspected);
+
+        // Every record has an unavailable mapping quality.
+        let mut metrics = MappingQualityMetrics::default();
+        for score in [150, 120] {
+            metrics.tally(255, Some(score));
+        }
+        metrics.summarize();
+
+        let summary = metrics.summary.unwrap();
+        assert_eq!(summary.mean_mapping_quality, None);
+        assert_eq!(summary.mapping_quality_unavailable_pct, Some(100.0));
+        assert_eq!(summary.mapping_quality_alignment_score_correlation, None);
+        assert!(summary.misconfiguration_suspected);
+    }
+}
@@ -0,0 +1,390 @@
+//! Functionality related to the Mate Consistency quality control facet.
+//!
+//! This facet samples templates and verifies that the information each mate
+//! records about the other is reciprocal: the `RNEXT`/`PNEXT` fields of one
+//! mate should agree with the `RNAME`/`POS` fields of the other, and the
+//! "mate unmapped" and "mate reverse complemented" flags of one mate should
+//! agree with the "unmapped" and "reverse complemented" flags of the other.
+//!
+//! Templates are sampled deterministically by hashing the read name, so both
+//! mates of a template are always either sampled or skipped together.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use anyhow::bail;
+use noodles::sam::alignment::Record;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{examples::Example, results, ComputationalLoad, RecordBasedQualityControlFacet},
+    utils::{math::percentage, merge::Merge},
+};
+
+/// By default, one in every `DEFAULT_TEMPLATE_SAMPLING_RATE` templates is
+/// checked for consistency.
+pub const DEFAULT_TEMPLATE_SAMPLING_RATE: u64 = 10;
+
+/// The maximum number of inconsistent templates that are reported as examples.
+pub const MAX_EXAMPLES: usize = 10;
+
+//=========//
+// Metrics //
+//=========//
+
+/// The number of inconsistencies found, broken down by kind. A single template
+/// may contribute to more than one kind.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InconsistencyCounts {
+    /// The `RNEXT` of one mate does not match the `RNAME` of the other.
+    pub mate_reference_sequence_mismatch: usize,
+
+    /// The `PNEXT` of one mate does not match the `POS` of the other.
+    pub mate_alignment_start_mismatch: usize,
+
+    /// The "mate unmapped" flag of one mate does not match the "unmapped" flag
+    /// of the other.
+    pub mate_unmapped_flag_mismatch: usize,
+
+    /// The "mate reverse complemented" flag of one mate does not match the
+    /// "reverse complemented" flag of the other.
+    pub mate_reverse_complemented_flag_mismatch: usize,
+}
+
+/// An example of a template whose mates were found to be inconsistent.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InconsistentTemplate {
+    /// The read name shared by the mates.
+    pub read_name: String,
+
+    /// The kinds of inconsistencies found for this template.
+    pub reasons: Vec<String>,
+}
+
+/// Summary statistics for the Mate Consistency quality control facet.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// Percentage of checked templates that were inconsistent.
+    pub inconsistent_templates_pct: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding mate consistency.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MateConsistencyMetrics {
+    /// One in every `sampling_rate` templates was checked.
+    pub sampling_rate: u64,
+
+    /// The number of templates where both mates were found and checked.
+    pub templates_checked: usize,
+
+    /// The number of checked templates with at least one inconsistency.
+    pub inconsistent_templates: usize,
+
+    /// The number of sampled records whose mate was never encountered.
+    pub records_without_mate: usize,
+
+    /// The number of inconsistencies found, broken down by kind.
+    pub inconsistencies: InconsistencyCounts,
+
+    /// Up to [`MAX_EXAMPLES`] examples of inconsistent templates.
+    pub examples: Vec<InconsistentTemplate>,
+
+    /// Summary statistics for the Mate Consistency quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl MateConsistencyMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        self.summary = Some(SummaryMetrics {
+            inconsistent_templates_pct: percentage(
+                self.inconsistent_templates,
+                self.templates_checked,
+            ),
+        });
+    }
+}
+
+impl Merge for InconsistencyCounts {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.mate_reference_sequence_mismatch += other.mate_reference_sequence_mismatch;
+        self.mate_alignment_start_mismatch += other.mate_alignment_start_mismatch;
+        self.mate_unmapped_flag_mismatch += other.mate_unmapped_flag_mismatch;
+        self.mate_reverse_complemented_flag_mismatch +=
+            other.mate_reverse_complemented_flag_mismatch;
+        Ok(())
+    }
+}
+
+impl Merge for MateConsistencyMetrics {
+    /// Merges the metrics. Note that a template whose mates were processed in
+    /// different results is counted once in each as a record without a mate.
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        if self.sampling_rate != other.sampling_rate {
+            bail!(
+                "Cannot merge mate consistency results with differing sampling \
+                rates ({} and {}).",
+                self.sampling_rate,
+                other.sampling_rate
+            );
+        }
+
+        self.templates_checked += other.templates_checked;
+        self.inconsistent_templates += other.inconsistent_templates;
+        self.records_without_mate += other.records_without_mate;
+        self.inconsistencies.merge(other.inconsistencies)?;
+
+        let remaining = MAX_EXAMPLES.saturating_sub(self.examples.len());
+        self.examples
+            .extend(other.examples.into_iter().take(remaining));
+
+        self.summarize();
+        Ok(())
+    }
+}
+
+//========================//
+// Mate Consistency Facet //
+//========================//
+
+/// The subset of a record's fields needed to check reciprocity with its mate.
+#[derive(Debug)]
+struct MateInformation {
+    reference_sequence_id: Option<usize>,
+    alignment_start: Option<usize>,
+    mate_reference_sequence_id: Option<usize>,
+    mate_alignment_start: Option<usize>,
+    is_unmapped: bool,
+    is_mate_unmapped: bool,
+    is_reverse_complemented: bool,
+    is_mate_reverse_complemented: bool,
+}
+
+impl From<&Record> for MateInformation {
+    fn from(record: &Record) -> Self {
+        let flags = record.flags();
+
+        Self {
+            reference_sequence_id: record.reference_sequence_id(),
+            alignment_start: record.alignment_start().map(usize::from),
+            mate_reference_sequence_id: record.mate_reference_sequence_id(),
+            mate_alignment_start: record.mate_alignment_start().map(usize::from),
+            is_unmapped: flags.is_unmapped(),
+            is_mate_unmapped: flags.is_mate_unmapped(),
+            is_reverse_complemented: flags.is_reverse_complemented(),
+            is_mate_reverse_complemented: flags.is_mate_reverse_complemented(),
+        }
+    }
+}
+
+/// Main struct for the Mate Consistency quality control facet.
+pub struct MateConsistencyFacet {
+    /// The main metric counting struct.
+    pub metrics: MateConsistencyMetrics,
+
+    /// Sampled records whose mate has not yet been encountered, keyed by read
+    /// name.
+    pending: HashMap<String, MateInformation>,
+}
+
+impl Default for MateConsistencyFacet {
+    fn default() -> Self {
+        Self::with_sampling_rate(DEFAULT_TEMPLATE_SAMPLING_RATE)
+    }
+}
+
+impl MateConsistencyFacet {
+    /// Creates a new [`MateConsistencyFacet`] that checks one in every
+    /// `sampling_rate` templates. A `sampling_rate` of zero is treated as one.
+    pub fn with_sampling_rate(sampling_rate: u64) -> Self {
+        Self {
+            metrics: MateConsistencyMetrics {
+                sampling_rate: sampling_rate.max(1),
+                ..Default::default()
+            },
+            pending: HashMap::new(),
+        }
+    }
+
+    fn is_sampled(&self, read_name: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        read_name.hash(&mut hasher);
+        hasher.finish().is_multiple_of(self.metrics.sampling_rate)
+    }
+
+    fn check(&mut self, read_name: String, a: &MateInformation, b: &MateInformation) {
+        let mut reasons = Vec::new();
+        let counts = &mut self.metrics.inconsistencies;
+
+        if a.mate_reference_sequence_id != b.reference_sequence_id
+            || b.mate_reference_sequence_id != a.reference_sequence_id
+        {
+            counts.mate_reference_sequence_mismatch += 1;
+            reasons.push(String::from("mate_reference_sequence_mismatch"));
+        }
+
+        if a.mate_alignment_start != b.alignment_start
+            || b.mate_alignment_start != a.alignment_start
+        {
+            counts.mate_alignment_start_mismatch += 1;
+            reasons.push(String::from("mate_alignment_start_mismatch"));
+        }
+
+        if a.is_mate_unmapped != b.is_unmapped || b.is_mate_unmapped != a.is_unmapped {
+            counts.mate_unmapped_flag_mismatch += 1;
+            reasons.push(String::from("mate_unmapped_flag_mismatch"));
+        }
+
+        if a.is_mate_reverse_complemented != b.is_reverse_complemented
+            || b.is_mate_reverse_complemented != a.is_reverse_complemented
+        {
+            counts.mate_reverse_complemented_flag_mismatch += 1;
+            reasons.push(String::from("mate_reverse_complemented_flag_mismatch"));
+        }
+
+        self.metrics.templates_checked += 1;
+
+        if !reasons.is_empty() {
+            self.metrics.inconsistent_templates += 1;
+
+            if self.metrics.examples.len() < MAX_EXAMPLES {
+                self.metrics
+                    .examples
+                    .push(InconsistentTemplate { read_name, reasons });
+            }
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for MateConsistencyFacet {
+    fn name(&self) -> &'static str {
+        "Mate Consistency"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        let flags = record.flags();
+
+        // Only the primary alignment of each mate in a paired template is
+        // considered. Secondary and supplementary alignments do not need to
+        // point back at one another.
+        if !flags.is_segmented() || flags.is_secondary() || flags.is_supplementary() {
+            return Ok(());
+        }
+
+        let read_name = match record.read_name() {
+            Some(name) => name.to_string(),
+            None => return Ok(()),
+        };
+
+        if !self.is_sampled(&read_name) {
+            return Ok(());
+        }
+
+        let information = MateInformation::from(record);
+
+        match self.pending.remove(&read_name) {
+            Some(mate) => self.check(read_name, &mate, &information),
+            None => {
+                self.pending.insert(read_name, information);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.records_without_mate = self.pending.len();
+        self.pending.clear();
+
+        self.metrics.summarize();
+
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.mate_consistency = Some(self.metrics.clone());
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        // The mates of an inconsistent template disagree with each other, so
+        // neither of their positions is reported.
+        self.metrics
+            .examples
+            .iter()
+            .flat_map(|template| {
+                template.reasons.iter().map(|reason| Example {
+                    problem: reason.clone(),
+                    read_name: template.read_name.clone(),
+                    reference_sequence_id: None,
+                    alignment_start: None,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{
+        core::Position,
+        sam::record::{Flags, ReadName},
+    };
+
+    use super::*;
+
+    fn mate(name: &str, flags: Flags, start: usize, mate_start: usize) -> anyhow::Result<Record> {
+        Ok(Record::builder()
+            .set_read_name(name.parse::<ReadName>()?)
+            .set_flags(Flags::SEGMENTED | flags)
+            .set_reference_sequence_id(0)
+            .set_alignment_start(Position::try_from(start)?)
+            .set_mate_reference_sequence_id(0)
+            .set_mate_alignment_start(Position::try_from(mate_start)?)
+            .build())
+    }
+
+    #[test]
+    pub fn it_reports_inconsistent_mates() -> anyhow::Result<()> {
+        let mut facet = MateConsistencyFacet::with_sampling_rate(1);
+
+        // Consistent template.
+        facet.process(&mate("a", Flags::MATE_REVERSE_COMPLEMENTED, 10, 20)?)?;
+        facet.process(&mate("a", Flags::REVERSE_COMPLEMENTED, 20, 10)?)?;
+
+        // The second mate's `PNEXT` points to the wrong position and its "mate
+        // reverse complemented" flag is not reciprocated.
+        facet.process(&mate("b", Flags::empty(), 30, 40)?)?;
+        facet.process(&mate("b", Flags::MATE_REVERSE_COMPLEMENTED, 40, 31)?)?;
+
+        // Mate never encountered.
+        facet.process(&mate("c", Flags::empty(), 50, 60)?)?;
+        facet.summarize()?;
+
+        let metrics = &facet.metrics;
+        assert_eq!(metrics.templates_checked, 2);
+        assert_eq!(metrics.inconsistent_templates, 1);
+        assert_eq!(metrics.records_without_mate, 1);
+        assert_eq!(metrics.inconsistencies.mate_alignment_start_mismatch, 1);
+        assert_eq!(
+            metrics
+                .inconsistencies
+                .mate_reverse_complemented_flag_mismatch,
+            1
+        );
+        assert_eq!(metrics.inconsistencies.mate_reference_sequence_mismatch, 0);
+        assert_eq!(metrics.examples.len(), 1);
+        assert_eq!(metrics.examples[0].read_name, "b");
+        assert_eq!(
+            metrics.summary.as_ref().unwrap().inconsistent_templates_pct,
+            Some(50.0)
+        );
+
+        Ok(())
+    }
+}
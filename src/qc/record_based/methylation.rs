@@ -0,0 +1,606 @@
+//! Functionality related to the Methylation quality control facet.
+//!
+//! Long-read basecallers (ONT and PacBio) and some enzymatic methyl-seq
+//! pipelines record base modifications within each record: the `MM` tag lists
+//! the modified bases (as skips between occurrences of the canonical base
+//! within the sequenced read) and the `ML` tag holds the probability of each
+//! call, scaled to `0..=255`. Tools that rewrite records (e.g., by trimming or
+//! hard clipping them) commonly leave these tags behind without updating them,
+//! which silently corrupts any downstream methylation analysis. Before that
+//! analysis, this facet reports:
+//!
+//! * The fraction of records with methylation calls.
+//! * The density of calls within each record (per kilobase of sequence).
+//! * The distribution of the call probabilities for each modification (e.g.,
+//!   `C+m` for 5mC).
+//! * The records whose tags are inconsistent: an `MM` tag without an `ML` tag
+//!   (or vice versa), a malformed `MM` tag, a number of probabilities that does
+//!   not match the number of calls, or calls beyond the end of the sequence.
+//!
+//! The draft `Mm` and `Ml` tags written by older basecallers are read if the
+//! standard tags are absent. The facet is only reported if at least one record
+//! has either tag.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context};
+use noodles::sam::{alignment::Record, record::data::field::Tag};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{
+        examples::{Example, Examples},
+        results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet,
+    },
+    utils::{alignment::sequenced_bases, histogram::Histogram, math::percentage, merge::Merge},
+};
+
+/// The largest probability recorded within an `ML` tag.
+pub const MAX_PROBABILITY: usize = u8::MAX as usize;
+
+/// The most calls per kilobase that a record can have (one for every base).
+pub const MAX_CALLS_PER_KILOBASE: usize = 1000;
+
+//=========//
+// Parsing //
+//=========//
+
+/// A single entry within an `MM` tag: the calls of one or more modifications
+/// of a canonical base on one strand.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ModificationEntry {
+    /// The canonical base that is modified (or `N` for any base).
+    pub base: u8,
+
+    /// The strand of the modification (`+` or `-`).
+    pub strand: char,
+
+    /// The modifications called (e.g., `m` for 5mC or a ChEBI id).
+    pub codes: Vec<String>,
+
+    /// The number of occurrences of the canonical base that are skipped before
+    /// each call.
+    pub skips: Vec<usize>,
+}
+
+impl ModificationEntry {
+    /// The names of the modifications within this entry (e.g., `C+m`).
+    pub fn names(&self) -> impl Iterator<Item = String> + '_ {
+        self.codes
+            .iter()
+            .map(|code| format!("{}{}{}", char::from(self.base), self.strand, code))
+    }
+
+    /// The number of probabilities expected within the `ML` tag for this
+    /// entry: one for each call of each modification.
+    pub fn probabilities(&self) -> usize {
+        self.skips.len() * self.codes.len()
+    }
+
+    /// The number of occurrences of the canonical base that the calls within
+    /// this entry span.
+    pub fn span(&self) -> usize {
+        self.skips.iter().map(|skip| skip + 1).sum()
+    }
+}
+
+/// Parses the entries within an `MM` tag, such as `C+m?,5,12,0;C+h?,5,12,0;`.
+pub fn parse_base_modifications(value: &str) -> anyhow::Result<Vec<ModificationEntry>> {
+    let mut entries = Vec::new();
+
+    for entry in value.split(';').filter(|entry| !entry.is_empty()) {
+        let mut fields = entry.split(',');
+        // SAFETY: splitting always yields at least one field.
+        let header = fields.next().unwrap().as_bytes();
+
+        // (1) Parse the canonical base and the strand.
+        let (base, strand) = match header {
+            [base, strand @ (b'+' | b'-'), ..] if b"ACGTUN".contains(base) => {
+                (*base, char::from(*strand))
+            }
+            _ => bail!("invalid base modification entry: {}", entry),
+        };
+
+        // (2) Parse the modification codes, dropping the optional skip mode
+        // (`.` or `?`). Codes are either single letters or a ChEBI id.
+        let codes = match &header[2..] {
+            [codes @ .., b'.' | b'?'] | codes => codes,
+        };
+        let codes: Vec<String> = match codes {
+            [] => bail!("base modification entry has no modifications: {}", entry),
+            _ if codes.iter().all(u8::is_ascii_digit) => {
+                vec![String::from_utf8_lossy(codes).to_string()]
+            }
+            _ if codes.iter().all(u8::is_ascii_alphabetic) => codes
+                .iter()
+                .map(|code| char::from(*code).to_string())
+                .collect(),
+            _ => bail!("invalid modification codes in entry: {}", entry),
+        };
+
+        // (3) Parse the skips before each call.
+        let skips = fields
+            .map(|skip| {
+                skip.parse::<usize>()
+                    .with_context(|| format!("invalid skip in base modification entry: {}", entry))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        entries.push(ModificationEntry {
+            base,
+            strand,
+            codes,
+            skips,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Counts the occurrences of the canonical base of an entry within the bases
+/// as they were sequenced. Modifications on the `-` strand are called on the
+/// complement of the canonical base.
+fn occurrences(bases: &[u8], entry: &ModificationEntry) -> usize {
+    let base = match entry.base {
+        b'N' => return bases.len(),
+        b'U' => b'T',
+        base => base,
+    };
+
+    let target = match (entry.strand, base) {
+        ('-', b'A') => b'T',
+        ('-', b'C') => b'G',
+        ('-', b'G') => b'C',
+        ('-', _) => b'A',
+        (_, base) => base,
+    };
+
+    bases
+        .iter()
+        .filter(|base| base.eq_ignore_ascii_case(&target))
+        .count()
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// The calls of a single modification (e.g., `C+m`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModificationMetrics {
+    /// The number of calls.
+    pub calls: usize,
+
+    /// The distribution of the probabilities (`0..=255`) of the calls.
+    pub probabilities: Histogram,
+
+    /// The mean probability of the calls (from zero to one).
+    pub mean_probability: Option<f64>,
+
+    /// The percentage of calls that are more likely modified than not (a
+    /// probability of at least 128).
+    pub modified_pct: Option<f64>,
+}
+
+impl Default for ModificationMetrics {
+    fn default() -> Self {
+        Self {
+            calls: 0,
+            probabilities: Histogram::zero_based_with_capacity(MAX_PROBABILITY),
+            mean_probability: None,
+            modified_pct: None,
+        }
+    }
+}
+
+impl ModificationMetrics {
+    /// Computes the summary statistics from the tallied calls.
+    pub fn summarize(&mut self) {
+        let midpoint = MAX_PROBABILITY.div_ceil(2);
+        let modified = (midpoint..=MAX_PROBABILITY)
+            .map(|probability| self.probabilities.get(probability))
+            .sum();
+
+        // Each probability `p` stands for the range `[p / 256, (p + 1) / 256)`.
+        self.mean_probability = self
+            .probabilities
+            .mean()
+            .map(|mean| (mean + 0.5) / (MAX_PROBABILITY + 1) as f64);
+        self.modified_pct = percentage(modified, self.calls);
+    }
+}
+
+impl Merge for ModificationMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.calls += other.calls;
+        self.probabilities.merge(other.probabilities)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+/// The records whose methylation tags are inconsistent.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct IntegrityMetrics {
+    /// The number of records with an `MM` tag but no `ML` tag.
+    pub missing_probabilities: usize,
+
+    /// The number of records with an `ML` tag but no `MM` tag.
+    pub missing_modifications: usize,
+
+    /// The number of records whose `MM` tag could not be parsed.
+    pub malformed: usize,
+
+    /// The number of records whose `ML` tag does not hold exactly one
+    /// probability for each call within the `MM` tag.
+    pub probability_count_mismatch: usize,
+
+    /// The number of records with calls beyond the end of the sequence (e.g.,
+    /// because the record was trimmed or hard clipped after it was called).
+    pub calls_beyond_sequence: usize,
+}
+
+impl IntegrityMetrics {
+    /// The number of records with inconsistent tags.
+    pub fn total(&self) -> usize {
+        self.missing_probabilities
+            + self.missing_modifications
+            + self.malformed
+            + self.probability_count_mismatch
+            + self.calls_beyond_sequence
+    }
+}
+
+impl Merge for IntegrityMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.missing_probabilities += other.missing_probabilities;
+        self.missing_modifications += other.missing_modifications;
+        self.malformed += other.malformed;
+        self.probability_count_mismatch += other.probability_count_mismatch;
+        self.calls_beyond_sequence += other.calls_beyond_sequence;
+        Ok(())
+    }
+}
+
+/// Summary statistics for the Methylation quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The percentage of records with at least one (valid) methylation call.
+    pub records_with_calls_pct: Option<f64>,
+
+    /// The percentage of records with methylation tags whose tags are
+    /// inconsistent.
+    pub invalid_tags_pct: Option<f64>,
+
+    /// The mean number of calls within each record with calls.
+    pub mean_calls_per_record: Option<f64>,
+
+    /// The median number of calls per kilobase of sequence within each record
+    /// with calls.
+    pub median_calls_per_kilobase: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding methylation calls.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MethylationMetrics {
+    /// The number of primary records considered.
+    pub records: usize,
+
+    /// The number of records with an `MM` or `ML` tag.
+    pub records_with_tags: usize,
+
+    /// The number of records with at least one valid methylation call.
+    pub records_with_calls: usize,
+
+    /// The number of valid calls across every modification.
+    pub calls: usize,
+
+    /// The distribution of calls per kilobase of sequence within each record
+    /// with calls.
+    pub calls_per_kilobase: Histogram,
+
+    /// The calls of each modification (e.g., `C+m`).
+    pub modifications: BTreeMap<String, ModificationMetrics>,
+
+    /// The records whose methylation tags are inconsistent.
+    pub integrity: IntegrityMetrics,
+
+    /// Summary statistics for the Methylation quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl Default for MethylationMetrics {
+    fn default() -> Self {
+        Self {
+            records: 0,
+            records_with_tags: 0,
+            records_with_calls: 0,
+            calls: 0,
+            calls_per_kilobase: Histogram::zero_based_with_capacity(MAX_CALLS_PER_KILOBASE),
+            modifications: BTreeMap::new(),
+            integrity: IntegrityMetrics::default(),
+            summary: None,
+        }
+    }
+}
+
+impl MethylationMetrics {
+    /// Tallies the methylation tags (if any) of a record, given the bases of
+    /// the record as they were sequenced. If the tags are inconsistent, the
+    /// kind of problem (e.g., `malformed`) is returned.
+    pub fn tally(
+        &mut self,
+        modifications: Option<&str>,
+        probabilities: Option<&[u8]>,
+        bases: &[u8],
+    ) -> Option<&'static str> {
+        self.records += 1;
+
+        // (1) Check that both tags are present.
+        let (modifications, probabilities) = match (modifications, probabilities) {
+            (None, None) => return None,
+            (Some(modifications), Some(probabilities)) => (modifications, probabilities),
+            (Some(_), None) => {
+                self.records_with_tags += 1;
+                self.integrity.missing_probabilities += 1;
+                return Some("missing_probabilities");
+            }
+            (None, Some(_)) => {
+                self.records_with_tags += 1;
+                self.integrity.missing_modifications += 1;
+                return Some("missing_modifications");
+            }
+        };
+        self.records_with_tags += 1;
+
+        // (2) Check that the tags agree with each other and with the sequence.
+        let entries = match parse_base_modifications(modifications) {
+            Ok(entries) => entries,
+            Err(_) => {
+                self.integrity.malformed += 1;
+                return Some("malformed");
+            }
+        };
+
+        let expected = entries
+            .iter()
+            .map(ModificationEntry::probabilities)
+            .sum::<usize>();
+        if expected != probabilities.len() {
+            self.integrity.probability_count_mismatch += 1;
+            return Some("probability_count_mismatch");
+        }
+
+        if entries
+            .iter()
+            .any(|entry| entry.span() > occurrences(bases, entry))
+        {
+            self.integrity.calls_beyond_sequence += 1;
+            return Some("calls_beyond_sequence");
+        }
+
+        // (3) Tally the calls. The probabilities of an entry are interleaved:
+        // for each call, there is one probability for each modification.
+        let mut offset = 0;
+        for entry in &entries {
+            let names = entry.names().collect::<Vec<_>>();
+            for (i, probability) in probabilities[offset..offset + entry.probabilities()]
+                .iter()
+                .enumerate()
+            {
+                let metrics = self
+                    .modifications
+                    .entry(names[i % names.len()].clone())
+                    .or_default();
+                metrics.calls += 1;
+                // SAFETY: the histogram covers every possible probability.
+                metrics
+                    .probabilities
+                    .increment(*probability as usize)
+                    .unwrap();
+            }
+            offset += entry.probabilities();
+        }
+
+        let calls = entries.iter().map(|entry| entry.skips.len()).sum::<usize>();
+        if calls > 0 {
+            self.records_with_calls += 1;
+            self.calls += calls;
+
+            let per_kilobase = (calls * 1000) / bases.len().max(1);
+            // SAFETY: records with more calls than bases (e.g., because they
+            // call several modifications) are clamped to the last bin.
+            self.calls_per_kilobase
+                .increment(per_kilobase.min(MAX_CALLS_PER_KILOBASE))
+                .unwrap();
+        }
+
+        None
+    }
+
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        for modification in self.modifications.values_mut() {
+            modification.summarize();
+        }
+
+        self.summary = Some(SummaryMetrics {
+            records_with_calls_pct: percentage(self.records_with_calls, self.records),
+            invalid_tags_pct: percentage(self.integrity.total(), self.records_with_tags),
+            mean_calls_per_record: (self.records_with_calls > 0)
+                .then(|| self.calls as f64 / self.records_with_calls as f64),
+            median_calls_per_kilobase: self.calls_per_kilobase.median(),
+        });
+    }
+}
+
+impl Merge for MethylationMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records += other.records;
+        self.records_with_tags += other.records_with_tags;
+        self.records_with_calls += other.records_with_calls;
+        self.calls += other.calls;
+        self.calls_per_kilobase.merge(other.calls_per_kilobase)?;
+        Merge::merge(&mut self.modifications, other.modifications)?;
+        self.integrity.merge(other.integrity)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Methylation quality control facet.
+pub struct MethylationFacet {
+    /// The main metric counting struct.
+    pub metrics: MethylationMetrics,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+
+    /// The draft tags for the modifications (`Mm`) and their probabilities
+    /// (`Ml`).
+    draft_tags: (Tag, Tag),
+
+    /// Examples of the records with inconsistent tags, if `--examples` was
+    /// provided.
+    examples: Examples,
+}
+
+impl MethylationFacet {
+    /// Creates a new [`MethylationFacet`] configured by the provided
+    /// [`FacetOptions`].
+    pub fn from_options(options: &FacetOptions) -> Self {
+        Self {
+            metrics: MethylationMetrics::default(),
+            count_duplicates: options.count_duplicates,
+            // SAFETY: both tags are two characters long.
+            draft_tags: (
+                Tag::try_from(*b"Mm").unwrap(),
+                Tag::try_from(*b"Ml").unwrap(),
+            ),
+            examples: Examples::new(options.examples),
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for MethylationFacet {
+    fn name(&self) -> &'static str {
+        "Methylation"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Secondary and supplementary records may be hard clipped, which
+        // would invalidate their calls, so only the primary records (aligned
+        // or not) are considered.
+        let flags = record.flags();
+        if flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        // (2) Prefer the standard tags, falling back to the draft tags.
+        let data = record.data();
+        let (draft_modifications, draft_probabilities) = self.draft_tags;
+        let modifications = data
+            .get(Tag::BaseModifications)
+            .or_else(|| data.get(draft_modifications))
+            .and_then(|field| field.value().as_str());
+        let probabilities = data
+            .get(Tag::BaseModificationProbabilities)
+            .or_else(|| data.get(draft_probabilities))
+            .and_then(|field| field.value().as_uint8_array());
+
+        let bases = match modifications {
+            Some(_) => sequenced_bases(record),
+            None => Vec::new(),
+        };
+
+        if let Some(problem) = self.metrics.tally(modifications, probabilities, &bases) {
+            self.examples.record(problem, record);
+        }
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        // The methylation calls are only reported if the file has any.
+        if self.metrics.records_with_tags > 0 {
+            results.methylation = Some(self.metrics.clone());
+        }
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        self.examples.examples().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_validates_and_tallies_methylation_calls() -> anyhow::Result<()> {
+        let entries = parse_base_modifications("C+mh?,1,0;A+a.,2;G-21839,0;")?;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].codes, ["m", "h"]);
+        assert_eq!(entries[0].probabilities(), 4);
+        assert_eq!(entries[1].names().collect::<Vec<_>>(), ["A+a"]);
+        assert_eq!(entries[2].codes, ["21839"]);
+        assert!(parse_base_modifications("X+m,1;").is_err());
+        assert!(parse_base_modifications("C+m,one;").is_err());
+
+        let mut metrics = MethylationMetrics::default();
+        let bases = b"ACGTCCGACG";
+
+        // Calls on the second and third C's, with 5mC and 5hmC probabilities
+        // interleaved.
+        let problem = metrics.tally(Some("C+mh?,1,0;"), Some(&[250, 5, 10, 200]), bases);
+        assert_eq!(problem, None);
+        // Calls past the fourth (and last) C.
+        let problem = metrics.tally(Some("C+m,4;"), Some(&[100]), bases);
+        assert_eq!(problem, Some("calls_beyond_sequence"));
+        // Too few probabilities, and no probabilities at all.
+        let problem = metrics.tally(Some("C+m,0,0;"), Some(&[100]), bases);
+        assert_eq!(problem, Some("probability_count_mismatch"));
+        let problem = metrics.tally(Some("C+m,0;"), None, bases);
+        assert_eq!(problem, Some("missing_probabilities"));
+        // No tags.
+        metrics.tally(None, None, bases);
+
+        metrics.summarize();
+        assert_eq!(metrics.records, 5);
+        assert_eq!(metrics.records_with_tags, 4);
+        assert_eq!(metrics.records_with_calls, 1);
+        assert_eq!(metrics.calls, 2);
+        assert_eq!(metrics.calls_per_kilobase.get(200), 1);
+        assert_eq!(metrics.integrity.calls_beyond_sequence, 1);
+        assert_eq!(metrics.integrity.probability_count_mismatch, 1);
+        assert_eq!(metrics.integrity.missing_probabilities, 1);
+
+        let methylated = &metrics.modifications["C+m"];
+        assert_eq!(methylated.calls, 2);
+        assert_eq!(methylated.modified_pct, Some(50.0));
+        assert_eq!(metrics.modifications["C+h"].probabilities.get(200), 1);
+
+        let summary = metrics.summary.unwrap();
+        assert_eq!(summary.records_with_calls_pct, Some(20.0));
+        assert_eq!(summary.invalid_tags_pct, Some(75.0));
+        assert_eq!(summary.mean_calls_per_record, Some(2.0));
+
+        Ok(())
+    }
+}
@@ -1,20 +1,134 @@
 //! Functionality related to the Quality Scores quality control facet.
 
-use std::collections::HashMap;
+use std::{collections::BTreeMap, num::NonZeroUsize};
 
 use noodles::sam::alignment::Record;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    qc::{results, ComputationalLoad, RecordBasedQualityControlFacet},
-    utils::histogram::Histogram,
+    qc::{results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet},
+    utils::{histogram::Histogram, merge::Merge},
 };
 
+//========//
+// Matrix //
+//========//
+
+/// The full position × quality score count matrix, which is suitable for
+/// producing heatmap-style visualizations. To keep the matrix compact, each
+/// row is run-length encoded as `[count, run]` pairs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QualityScoreMatrix {
+    /// The number of positions (rows) in the matrix. Positions are 1-based,
+    /// so the first row corresponds to position 1.
+    pub positions: usize,
+
+    /// The number of quality scores (columns) in the matrix. Columns start
+    /// at a quality score of 0.
+    pub scores: usize,
+
+    /// The run-length encoded counts for each position.
+    pub rows: Vec<Vec<(usize, usize)>>,
+}
+
+impl QualityScoreMatrix {
+    /// Creates a [`QualityScoreMatrix`] from the per-position histograms of
+    /// the Quality Scores facet. Positions that were never observed are
+    /// filled with zeros.
+    pub fn from_scores(scores: &BTreeMap<usize, Histogram>) -> Self {
+        let positions = scores.keys().max().copied().unwrap_or(0);
+        let last_score = scores
+            .values()
+            .map(|histogram| histogram.range_stop())
+            .max()
+            .unwrap_or(MAX_SCORE);
+        let mut rows = Vec::with_capacity(positions);
+
+        for position in 1..=positions {
+            let mut runs: Vec<(usize, usize)> = Vec::new();
+
+            for score in 0..=last_score {
+                let count = scores
+                    .get(&position)
+                    .map(|histogram| histogram.get(score))
+                    .unwrap_or(0);
+
+                match runs.last_mut() {
+                    Some((last, run)) if *last == count => *run += 1,
+                    _ => runs.push((count, 1)),
+                }
+            }
+
+            rows.push(runs);
+        }
+
+        Self {
+            positions,
+            scores: last_score + 1,
+            rows,
+        }
+    }
+
+    /// Decodes the matrix into dense rows, one per position.
+    pub fn to_dense(&self) -> Vec<Vec<usize>> {
+        self.rows
+            .iter()
+            .map(|runs| {
+                runs.iter()
+                    .flat_map(|(count, run)| std::iter::repeat_n(*count, *run))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
 /// Main struct for the Quality Scores quality control facet.
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct QualityScoreFacet {
     /// Distribution of quality scores for each position in the records observed.
-    pub scores: HashMap<usize, Histogram>,
+    pub scores: BTreeMap<usize, Histogram>,
+
+    /// The full position × quality score count matrix, if it was requested
+    /// with `--quality-score-matrix`.
+    #[serde(default)]
+    pub matrix: Option<QualityScoreMatrix>,
+
+    /// Whether records marked as duplicate should be counted.
+    #[serde(skip)]
+    pub count_duplicates: bool,
+
+    /// Whether the full count matrix should be reported.
+    #[serde(skip)]
+    pub emit_matrix: bool,
+
+    /// The number of bins in each histogram. If unset, every quality score
+    /// supported by the SAM specification has its own bin.
+    #[serde(skip)]
+    pub bins: Option<NonZeroUsize>,
+}
+
+impl QualityScoreFacet {
+    /// Creates a new [`QualityScoreFacet`].
+    pub fn new(count_duplicates: bool) -> Self {
+        Self {
+            count_duplicates,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new [`QualityScoreFacet`] configured by the provided
+    /// [`FacetOptions`].
+    pub fn from_options(options: &FacetOptions) -> Self {
+        Self {
+            emit_matrix: options.quality_score_matrix,
+            bins: Some(options.quality_bins),
+            ..Self::new(options.count_duplicates)
+        }
+    }
 }
 
 /// Maximum quality score supported by the SAM specification.
@@ -34,13 +148,20 @@ impl RecordBasedQualityControlFacet for QualityScoreFacet {
     }
 
     fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        if !self.count_duplicates && record.flags().is_duplicate() {
+            return Ok(());
+        }
+
+        let last_bin = self.bins.map(|bins| bins.get() - 1).unwrap_or(MAX_SCORE);
+
         for (i, val) in record.quality_scores().as_ref().iter().enumerate() {
             let histogram = self
                 .scores
                 .entry(i + 1) // indices are 0-based, we want this to be 1-based.
-                .or_insert_with(|| Histogram::zero_based_with_capacity(self::MAX_SCORE));
+                .or_insert_with(|| Histogram::zero_based_with_capacity(last_bin));
 
-            let score = u8::from(*val) as usize;
+            // Scores beyond the last bin are tallied within the last bin.
+            let score = usize::min(u8::from(*val) as usize, last_bin);
             histogram.increment(score).unwrap();
         }
 
@@ -48,8 +169,11 @@ impl RecordBasedQualityControlFacet for QualityScoreFacet {
     }
 
     fn summarize(&mut self) -> anyhow::Result<()> {
-        // Nothing to summarize here, as we simply report the histograms for
-        // each position.
+        // Beyond the optional count matrix, there is nothing to summarize
+        // here, as we simply report the histograms for each position.
+        if self.emit_matrix {
+            self.matrix = Some(QualityScoreMatrix::from_scores(&self.scores));
+        }
 
         Ok(())
     }
@@ -58,3 +182,75 @@ impl RecordBasedQualityControlFacet for QualityScoreFacet {
         results.quality_scores = Some(self.clone());
     }
 }
+
+impl Merge for QualityScoreFacet {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        let emit_matrix = self.matrix.is_some() || other.matrix.is_some();
+        Merge::merge(&mut self.scores, other.scores)?;
+
+        // The matrix is derived entirely from the histograms, so it is simply
+        // recomputed if either of the results reported it.
+        if emit_matrix {
+            self.matrix = Some(QualityScoreMatrix::from_scores(&self.scores));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::record::QualityScores;
+
+    use super::*;
+
+    #[test]
+    pub fn it_emits_a_run_length_encoded_matrix() -> anyhow::Result<()> {
+        let mut facet = QualityScoreFacet::from_options(&FacetOptions {
+            quality_score_matrix: true,
+            ..Default::default()
+        });
+
+        for scores in ["II#", "I5"] {
+            let record = Record::builder()
+                .set_quality_scores(scores.parse::<QualityScores>()?)
+                .build();
+            facet.process(&record)?;
+        }
+        facet.summarize()?;
+
+        let matrix = facet.matrix.as_ref().unwrap();
+        assert_eq!(matrix.positions, 3);
+        assert_eq!(matrix.scores, MAX_SCORE + 1);
+        assert_eq!(matrix.rows[0], vec![(0, 40), (2, 1), (0, 53)]);
+
+        let dense = matrix.to_dense();
+        assert_eq!(dense[1][20], 1);
+        assert_eq!(dense[1][40], 1);
+        assert_eq!(dense[2][2], 1);
+        assert_eq!(dense[2].iter().sum::<usize>(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_tallies_scores_beyond_the_last_bin_in_the_last_bin() -> anyhow::Result<()> {
+        let mut facet = QualityScoreFacet::from_options(&FacetOptions {
+            quality_bins: NonZeroUsize::new(42).unwrap(),
+            ..Default::default()
+        });
+
+        let record = Record::builder()
+            .set_quality_scores("#5IK".parse::<QualityScores>()?)
+            .build();
+        facet.process(&record)?;
+
+        assert_eq!(facet.scores[&1].get(2), 1);
+        assert_eq!(facet.scores[&2].get(20), 1);
+        assert_eq!(facet.scores[&3].get(40), 1);
+        assert_eq!(facet.scores[&4].get(41), 1);
+        assert_eq!(facet.scores[&4].range_stop(), 41);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,295 @@
+//! Functionality related to the Read Groups quality control facet.
+//!
+//! This facet audits the completeness of the read groups in a file: every
+//! record's `RG` tag should refer to a read group declared in the header,
+//! every read group declared in the header should be used by at least one
+//! record, and every read group should populate the `SM`, `LB`, and `PL`
+//! fields. Violations of these expectations are a common source of downstream
+//! failures (for instance, within GATK).
+
+use std::collections::BTreeMap;
+
+use noodles::sam;
+use sam::{alignment::Record, record::data::field::Tag, Header};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, RecordBasedQualityControlFacet},
+    utils::{math::percentage, merge::Merge},
+};
+
+//=========//
+// Metrics //
+//=========//
+
+/// Violations of the expectations for read groups within a file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ReadGroupViolations {
+    /// The number of records that do not have an `RG` tag.
+    pub records_without_read_group: usize,
+
+    /// The number of records that have an `RG` tag which does not match any
+    /// read group in the header, keyed by the read group that was found.
+    pub records_with_unknown_read_group: BTreeMap<String, usize>,
+
+    /// Read groups declared in the header that were not used by any record.
+    pub unused_read_groups: Vec<String>,
+
+    /// Read groups declared in the header that are missing one or more of the
+    /// `SM`, `LB`, or `PL` fields, keyed by the read group id.
+    pub incomplete_read_groups: BTreeMap<String, Vec<String>>,
+}
+
+/// Summary statistics for the Read Groups quality control facet.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// Whether any violations were found.
+    pub violations_found: bool,
+
+    /// Percentage of records that did not have an `RG` tag.
+    pub records_without_read_group_pct: Option<f64>,
+
+    /// Percentage of records that had an `RG` tag not declared in the header.
+    pub records_with_unknown_read_group_pct: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding read groups.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ReadGroupMetrics {
+    /// The total number of records examined.
+    pub total: usize,
+
+    /// The number of records for each read group declared in the header.
+    pub records_per_read_group: BTreeMap<String, usize>,
+
+    /// Violations of the expectations for read groups.
+    pub violations: ReadGroupViolations,
+
+    /// Summary statistics for the Read Groups quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl ReadGroupMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let violations = &self.violations;
+        let records_with_unknown_read_group: usize =
+            violations.records_with_unknown_read_group.values().sum();
+
+        self.summary = Some(SummaryMetrics {
+            violations_found: violations.records_without_read_group > 0
+                || records_with_unknown_read_group > 0
+                || !violations.unused_read_groups.is_empty()
+                || !violations.incomplete_read_groups.is_empty(),
+            records_without_read_group_pct: percentage(
+                violations.records_without_read_group,
+                self.total,
+            ),
+            records_with_unknown_read_group_pct: percentage(
+                records_with_unknown_read_group,
+                self.total,
+            ),
+        });
+    }
+}
+
+impl Merge for ReadGroupMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.total += other.total;
+        Merge::merge(
+            &mut self.records_per_read_group,
+            other.records_per_read_group,
+        )?;
+
+        let violations = &mut self.violations;
+        violations.records_without_read_group += other.violations.records_without_read_group;
+        Merge::merge(
+            &mut violations.records_with_unknown_read_group,
+            other.violations.records_with_unknown_read_group,
+        )?;
+
+        // Both results are expected to come from the same header, so the
+        // incomplete read groups should be identical. Any read groups only
+        // present in the other results are included all the same.
+        for (id, missing) in other.violations.incomplete_read_groups {
+            violations
+                .incomplete_read_groups
+                .entry(id)
+                .or_insert(missing);
+        }
+
+        // A read group is only unused if no records were found for it in
+        // either of the results.
+        let mut unused_read_groups = std::mem::take(&mut violations.unused_read_groups);
+        for id in other.violations.unused_read_groups {
+            if !unused_read_groups.contains(&id) {
+                unused_read_groups.push(id);
+            }
+        }
+        unused_read_groups.retain(|id| self.records_per_read_group.get(id) == Some(&0));
+        self.violations.unused_read_groups = unused_read_groups;
+
+        self.summarize();
+        Ok(())
+    }
+}
+
+//===================//
+// Read Groups Facet //
+//===================//
+
+/// Main struct for the Read Groups quality control facet.
+pub struct ReadGroupsFacet<'a> {
+    /// The SAM header. Useful for looking up the read groups declared within
+    /// the file.
+    pub header: &'a Header,
+
+    /// The main metric counting struct.
+    pub metrics: ReadGroupMetrics,
+}
+
+impl<'a> ReadGroupsFacet<'a> {
+    /// Creates a new [`ReadGroupsFacet`] for the read groups declared in the
+    /// provided header.
+    pub fn new(header: &'a Header) -> Self {
+        let mut metrics = ReadGroupMetrics::default();
+
+        for (id, read_group) in header.read_groups() {
+            metrics.records_per_read_group.insert(id.clone(), 0);
+
+            let mut missing = Vec::new();
+            if read_group.sample().is_none() {
+                missing.push(String::from("SM"));
+            }
+
+            if read_group.library().is_none() {
+                missing.push(String::from("LB"));
+            }
+
+            if read_group.platform().is_none() {
+                missing.push(String::from("PL"));
+            }
+
+            if !missing.is_empty() {
+                metrics
+                    .violations
+                    .incomplete_read_groups
+                    .insert(id.clone(), missing);
+            }
+        }
+
+        Self { header, metrics }
+    }
+}
+
+impl<'a> RecordBasedQualityControlFacet for ReadGroupsFacet<'a> {
+    fn name(&self) -> &'static str {
+        "Read Groups"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        self.metrics.total += 1;
+
+        let read_group = record
+            .data()
+            .get(Tag::ReadGroup)
+            .and_then(|field| field.value().as_str());
+
+        match read_group {
+            Some(id) => match self.metrics.records_per_read_group.get_mut(id) {
+                Some(count) => *count += 1,
+                None => {
+                    *self
+                        .metrics
+                        .violations
+                        .records_with_unknown_read_group
+                        .entry(id.to_string())
+                        .or_insert(0) += 1
+                }
+            },
+            None => self.metrics.violations.records_without_read_group += 1,
+        }
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        // Iterating through the header keeps the unused read groups in the
+        // order in which they were declared.
+        self.metrics.violations.unused_read_groups = self
+            .header
+            .read_groups()
+            .keys()
+            .filter(|id| self.metrics.records_per_read_group.get(*id) == Some(&0))
+            .cloned()
+            .collect();
+
+        self.metrics.summarize();
+
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.read_groups = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sam::{
+        header::record::value::{map::ReadGroup, Map},
+        record::data::field::{Field, Value},
+    };
+
+    use super::*;
+
+    fn record_with_read_group(id: &str) -> anyhow::Result<Record> {
+        let data = sam::record::Data::try_from(vec![Field::new(
+            Tag::ReadGroup,
+            Value::String(id.to_string()),
+        )])?;
+
+        Ok(Record::builder().set_data(data).build())
+    }
+
+    #[test]
+    pub fn it_reports_read_group_violations() -> anyhow::Result<()> {
+        let header = Header::builder()
+            .add_read_group(Map::<ReadGroup>::new("rg0"))
+            .add_read_group(Map::<ReadGroup>::new("rg1"))
+            .build();
+
+        let mut facet = ReadGroupsFacet::new(&header);
+        facet.process(&record_with_read_group("rg0")?)?;
+        facet.process(&record_with_read_group("rg2")?)?;
+        facet.process(&Record::default())?;
+        facet.summarize()?;
+
+        let metrics = &facet.metrics;
+        assert_eq!(metrics.records_per_read_group.get("rg0"), Some(&1));
+        assert_eq!(metrics.violations.records_without_read_group, 1);
+        assert_eq!(
+            metrics
+                .violations
+                .records_with_unknown_read_group
+                .get("rg2"),
+            Some(&1)
+        );
+        assert_eq!(metrics.violations.unused_read_groups, vec!["rg1"]);
+        assert_eq!(
+            metrics.violations.incomplete_read_groups.get("rg0"),
+            Some(&vec![
+                String::from("SM"),
+                String::from("LB"),
+                String::from("PL")
+            ])
+        );
+        assert!(metrics.summary.as_ref().unwrap().violations_found);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! Functionality related to the Read Length quality control facet.
+
+use noodles::sam::alignment::Record;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet},
+    utils::{histogram::Histogram, merge::Merge},
+};
+
+/// The initial capacity of the read length histogram. The histogram grows
+/// automatically to fit longer reads.
+pub const INITIAL_READ_LENGTH_CAPACITY: usize = 256;
+
+/// The maximum read length that the histogram will grow to. Anything beyond
+/// this is tallied as ignored.
+pub const MAX_READ_LENGTH: usize = 1_000_000;
+
+//=========//
+// Metrics //
+//=========//
+
+/// Summary statistics for the Read Length quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The shortest read length observed.
+    pub min_read_length: Option<usize>,
+
+    /// The longest read length observed.
+    pub max_read_length: Option<usize>,
+
+    /// The mean read length.
+    pub mean_read_length: Option<f64>,
+
+    /// The median read length.
+    pub median_read_length: Option<f64>,
+
+    /// The N50 read length: the length of the shortest read such that reads
+    /// at least that long contain half of the bases.
+    #[serde(default)]
+    pub n50_read_length: Option<usize>,
+}
+
+/// Primary struct used to compile stats regarding read lengths.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReadLengthMetrics {
+    /// The distribution of read lengths.
+    pub histogram: Histogram,
+
+    /// The number of records tallied within the histogram.
+    pub processed: usize,
+
+    /// The number of records that were longer than [`MAX_READ_LENGTH`].
+    pub ignored: usize,
+
+    /// Summary statistics for the Read Length quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl Default for ReadLengthMetrics {
+    fn default() -> Self {
+        Self {
+            histogram: Histogram::zero_based_with_capacity(INITIAL_READ_LENGTH_CAPACITY),
+            processed: 0,
+            ignored: 0,
+            summary: None,
+        }
+    }
+}
+
+impl ReadLengthMetrics {
+    /// Tallies a single read length, growing the histogram if needed.
+    pub fn tally(&mut self, length: usize) {
+        if !self.histogram.in_range(length) && length <= MAX_READ_LENGTH {
+            // Grow in powers of two so that we aren't reallocating for every
+            // slightly longer read.
+            let capacity = usize::min(length.next_power_of_two(), MAX_READ_LENGTH);
+            self.histogram.grow_to(capacity);
+        }
+
+        match self.histogram.increment(length) {
+            Ok(()) => self.processed += 1,
+            Err(_) => self.ignored += 1,
+        }
+    }
+
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let values = self.histogram.values();
+        let start = self.histogram.range_start();
+
+        let min_read_length = values.iter().position(|count| *count > 0);
+        let max_read_length = values.iter().rposition(|count| *count > 0);
+
+        // Accumulate the bases from the longest reads down until half of the
+        // bases are accounted for.
+        let bases = |(i, count): (usize, &usize)| (i + start) * count;
+        let total = values.iter().enumerate().map(bases).sum::<usize>();
+        let mut accumulated = 0;
+        let n50_read_length = values
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|&entry| {
+                accumulated += bases(entry);
+                total > 0 && accumulated * 2 >= total
+            })
+            .map(|(i, _)| i + start);
+
+        self.summary = Some(SummaryMetrics {
+            min_read_length: min_read_length.map(|i| i + start),
+            max_read_length: max_read_length.map(|i| i + start),
+            mean_read_length: self.histogram.mean(),
+            median_read_length: self.histogram.median(),
+            n50_read_length,
+        });
+    }
+}
+
+impl Merge for ReadLengthMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.histogram.merge(other.histogram)?;
+        self.processed += other.processed;
+        self.ignored += other.ignored;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Read Length quality control facet.
+#[derive(Default)]
+pub struct ReadLengthFacet {
+    /// The main metric counting struct.
+    pub metrics: ReadLengthMetrics,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+}
+
+impl ReadLengthFacet {
+    /// Creates a new [`ReadLengthFacet`] configured by the provided
+    /// [`FacetOptions`].
+    pub fn from_options(options: &FacetOptions) -> Self {
+        Self {
+            metrics: ReadLengthMetrics::default(),
+            count_duplicates: options.count_duplicates,
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for ReadLengthFacet {
+    fn name(&self) -> &'static str {
+        "Read Length"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // Secondary and supplementary records may be hard clipped, so they
+        // don't reliably report the length of the read.
+        let flags = record.flags();
+        if flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        self.metrics.tally(record.sequence().len());
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.read_length = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_summarizes_read_lengths_and_grows_for_long_reads() {
+        let mut metrics = ReadLengthMetrics::default();
+        for length in [100, 150, 150, 10_000] {
+            metrics.tally(length);
+        }
+        metrics.tally(MAX_READ_LENGTH + 1);
+        metrics.summarize();
+
+        assert_eq!(metrics.processed, 4);
+        assert_eq!(metrics.ignored, 1);
+
+        let summary = metrics.summary.unwrap();
+        assert_eq!(summary.min_read_length, Some(100));
+        assert_eq!(summary.max_read_length, Some(10_000));
+        assert_eq!(summary.median_read_length, Some(150.0));
+        assert_eq!(summary.n50_read_length, Some(10_000));
+
+        let mut metrics = ReadLengthMetrics::default();
+        for length in [2, 3, 4, 5, 6, 7, 8] {
+            metrics.tally(length);
+        }
+        metrics.summarize();
+        assert_eq!(metrics.summary.unwrap().n50_read_length, Some(6));
+    }
+}
@@ -0,0 +1,360 @@
+//! Functionality related to the Read Name Collisions quality control facet.
+//!
+//! Within a well-formed file, every record of the same read (that is, the
+//! same read name and segment) carries the same sequence. When files are
+//! merged incorrectly (e.g., two runs whose instruments reused the same read
+//! names), different reads end up sharing a name, which silently breaks
+//! anything that pairs records by name, such as duplicate marking or mate
+//! rescue. This facet samples reads and reports those whose name was already
+//! seen with a different sequence.
+//!
+//! Reads are sampled deterministically by hashing the read name, so every
+//! record of a sampled read is checked. The reads seen so far are kept in a
+//! pair of Bloom filters (one of read names, and one of read names with their
+//! sequences), so the memory used is fixed however many reads are sampled.
+//! The filters may report a read as seen when it was not, so the number of
+//! collisions is an estimate: the chance of a false collision is reported
+//! alongside it.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use anyhow::bail;
+use noodles::sam::alignment::Record;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{examples::Example, results, ComputationalLoad, RecordBasedQualityControlFacet},
+    utils::{alignment::sequenced_bases, math::percentage, merge::Merge},
+};
+
+/// By default, one in every `DEFAULT_READ_SAMPLING_RATE` reads is checked for
+/// collisions.
+pub const DEFAULT_READ_SAMPLING_RATE: u64 = 100;
+
+/// The maximum number of colliding read names that are reported as examples.
+pub const MAX_EXAMPLES: usize = 10;
+
+/// The number of bits in each Bloom filter (4 MiB each).
+const BLOOM_FILTER_BITS: usize = 1 << 25;
+
+/// The number of hash functions used by each Bloom filter.
+const BLOOM_FILTER_HASHES: u64 = 7;
+
+//==============//
+// Bloom Filter //
+//==============//
+
+/// A fixed-size Bloom filter.
+struct BloomFilter {
+    /// The bits of the filter.
+    bits: Vec<u64>,
+
+    /// The number of bits that are set.
+    set: usize,
+}
+
+impl BloomFilter {
+    /// Creates an empty [`BloomFilter`] of `len` bits (rounded up to a
+    /// multiple of 64).
+    fn new(len: usize) -> Self {
+        Self {
+            bits: vec![0; len.div_ceil(64)],
+            set: 0,
+        }
+    }
+
+    /// The number of bits in the filter.
+    fn len(&self) -> usize {
+        self.bits.len() * 64
+    }
+
+    /// The bit positions of a value within a filter of `len` bits, derived by
+    /// double hashing.
+    fn positions<T: Hash>(len: usize, value: &T) -> impl Iterator<Item = usize> {
+        let len = len as u64;
+
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let a = hasher.finish();
+        // The second hash must be odd so that the positions do not repeat.
+        a.hash(&mut hasher);
+        let b = hasher.finish() | 1;
+
+        (0..BLOOM_FILTER_HASHES).map(move |i| (a.wrapping_add(i.wrapping_mul(b)) % len) as usize)
+    }
+
+    /// Inserts a value, returning whether it was (possibly) already present.
+    fn insert<T: Hash>(&mut self, value: &T) -> bool {
+        let mut present = true;
+
+        for position in Self::positions(self.len(), value) {
+            let (word, bit) = (position / 64, 1 << (position % 64));
+            if self.bits[word] & bit == 0 {
+                self.bits[word] |= bit;
+                self.set += 1;
+                present = false;
+            }
+        }
+
+        present
+    }
+
+    /// The probability that a value that was never inserted is reported as
+    /// present.
+    fn false_positive_rate(&self) -> f64 {
+        let fill = self.set as f64 / self.len() as f64;
+        fill.powi(BLOOM_FILTER_HASHES as i32)
+    }
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// Summary statistics for the Read Name Collisions quality control facet.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The estimated percentage of checked reads whose name was already used
+    /// by a read with a different sequence.
+    pub estimated_collision_rate_pct: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding read name collisions.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ReadNameCollisionMetrics {
+    /// One in every `sampling_rate` reads was checked.
+    pub sampling_rate: u64,
+
+    /// The number of (primary) records that were checked.
+    pub records_checked: usize,
+
+    /// The number of checked records whose read name (and segment) was
+    /// already seen with a different sequence.
+    pub collisions: usize,
+
+    /// The probability that a checked record was counted as a collision
+    /// because of a false positive in the Bloom filters.
+    pub false_positive_rate: f64,
+
+    /// Up to [`MAX_EXAMPLES`] colliding read names.
+    pub examples: Vec<String>,
+
+    /// Summary statistics for the Read Name Collisions quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl ReadNameCollisionMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        self.summary = Some(SummaryMetrics {
+            estimated_collision_rate_pct: percentage(self.collisions, self.records_checked),
+        });
+    }
+}
+
+impl Merge for ReadNameCollisionMetrics {
+    /// Merges the metrics. Note that reads are only compared within each of
+    /// the results, so collisions between reads in different results are not
+    /// found.
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        if self.sampling_rate != other.sampling_rate {
+            bail!(
+                "Cannot merge read name collision results with differing sampling \
+                rates ({} and {}).",
+                self.sampling_rate,
+                other.sampling_rate
+            );
+        }
+
+        self.records_checked += other.records_checked;
+        self.collisions += other.collisions;
+        self.false_positive_rate = self.false_positive_rate.max(other.false_positive_rate);
+
+        let remaining = MAX_EXAMPLES.saturating_sub(self.examples.len());
+        self.examples
+            .extend(other.examples.into_iter().take(remaining));
+
+        self.summarize();
+        Ok(())
+    }
+}
+
+//============================//
+// Read Name Collisions Facet //
+//============================//
+
+/// Main struct for the Read Name Collisions quality control facet.
+pub struct ReadNameCollisionsFacet {
+    /// The main metric counting struct.
+    pub metrics: ReadNameCollisionMetrics,
+
+    /// The sampled read names (and segments) seen so far.
+    names: BloomFilter,
+
+    /// The sampled read names (and segments) seen so far, along with the
+    /// sequence they were seen with.
+    sequences: BloomFilter,
+}
+
+impl Default for ReadNameCollisionsFacet {
+    fn default() -> Self {
+        Self::with_sampling_rate(DEFAULT_READ_SAMPLING_RATE)
+    }
+}
+
+impl ReadNameCollisionsFacet {
+    /// Creates a new [`ReadNameCollisionsFacet`] that checks one in every
+    /// `sampling_rate` reads. A `sampling_rate` of zero is treated as one.
+    pub fn with_sampling_rate(sampling_rate: u64) -> Self {
+        Self {
+            metrics: ReadNameCollisionMetrics {
+                sampling_rate: sampling_rate.max(1),
+                ..Default::default()
+            },
+            names: BloomFilter::new(BLOOM_FILTER_BITS),
+            sequences: BloomFilter::new(BLOOM_FILTER_BITS),
+        }
+    }
+
+    fn is_sampled(&self, read_name: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        read_name.hash(&mut hasher);
+        hasher.finish().is_multiple_of(self.metrics.sampling_rate)
+    }
+}
+
+impl RecordBasedQualityControlFacet for ReadNameCollisionsFacet {
+    fn name(&self) -> &'static str {
+        "Read Name Collisions"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Only primary records are checked: secondary and supplementary
+        // records may omit (or clip) the sequence.
+        let flags = record.flags();
+        if flags.is_secondary() || flags.is_supplementary() {
+            return Ok(());
+        }
+
+        let read_name = match record.read_name() {
+            Some(name) => name.to_string(),
+            None => return Ok(()),
+        };
+
+        if !self.is_sampled(&read_name) {
+            return Ok(());
+        }
+
+        // (2) The mates of a template share a name but not a sequence, so
+        // each segment is checked on its own.
+        let key = (read_name, flags.is_first_segment(), flags.is_last_segment());
+        let sequence = sequenced_bases(record);
+
+        let seen = self.names.insert(&key);
+        let seen_with_sequence = self.sequences.insert(&(&key, sequence));
+
+        self.metrics.records_checked += 1;
+
+        // (3) A read that was seen before, but never with this sequence, is a
+        // collision.
+        if seen && !seen_with_sequence {
+            self.metrics.collisions += 1;
+
+            let (read_name, ..) = key;
+            if self.metrics.examples.len() < MAX_EXAMPLES
+                && !self.metrics.examples.contains(&read_name)
+            {
+                self.metrics.examples.push(read_name);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.false_positive_rate = self.names.false_positive_rate();
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.read_name_collisions = Some(self.metrics.clone());
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        // The colliding reads are different reads, so neither of their
+        // positions is reported.
+        self.metrics
+            .examples
+            .iter()
+            .map(|read_name| Example {
+                problem: String::from("read_name_collision"),
+                read_name: read_name.clone(),
+                reference_sequence_id: None,
+                alignment_start: None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::record::{Flags, ReadName, Sequence};
+
+    use super::*;
+
+    fn read(name: &str, flags: Flags, sequence: &str) -> anyhow::Result<Record> {
+        Ok(Record::builder()
+            .set_read_name(name.parse::<ReadName>()?)
+            .set_flags(Flags::UNMAPPED | flags)
+            .set_sequence(sequence.parse::<Sequence>()?)
+            .build())
+    }
+
+    #[test]
+    pub fn it_reports_reads_that_share_a_name_but_not_a_sequence() -> anyhow::Result<()> {
+        let mut facet = ReadNameCollisionsFacet::with_sampling_rate(1);
+
+        // The mates of a template are not collisions.
+        let first = Flags::SEGMENTED | Flags::FIRST_SEGMENT;
+        let last = Flags::SEGMENTED | Flags::LAST_SEGMENT;
+        facet.process(&read("a", first, "ACGT")?)?;
+        facet.process(&read("a", last, "TTTT")?)?;
+
+        // Nor is the same read seen twice.
+        facet.process(&read("b", Flags::empty(), "ACGT")?)?;
+        facet.process(&read("b", Flags::empty(), "ACGT")?)?;
+
+        // But two different reads by the same name are.
+        facet.process(&read("c", first, "ACGT")?)?;
+        facet.process(&read("c", first, "GGGG")?)?;
+
+        // Secondary records are not checked.
+        facet.process(&read("b", Flags::SECONDARY, "CCCC")?)?;
+        facet.summarize()?;
+
+        let metrics = &facet.metrics;
+        assert_eq!(metrics.records_checked, 6);
+        assert_eq!(metrics.collisions, 1);
+        assert_eq!(metrics.examples, ["c"]);
+        assert!(metrics.false_positive_rate < 1e-12);
+        assert_eq!(
+            metrics
+                .summary
+                .as_ref()
+                .unwrap()
+                .estimated_collision_rate_pct
+                .map(|pct| (pct * 100.0).round() / 100.0),
+            Some(16.67)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,569 @@
+//! Functionality related to the Repeats quality control facet.
+//!
+//! Reads within repeats (e.g., LINEs, SINEs, and simple repeats) often align
+//! equally well to several places in the genome, so aligners assign them low
+//! mapping qualities. A sample with an unusually large fraction of its reads in
+//! repeats will therefore look like it has a mapping quality problem. With a
+//! repeat annotation (`--repeats`), this facet reports the fraction of records
+//! and aligned bases that fall within each class of repeats, along with the
+//! mapping qualities of the records within and outside of repeats. The
+//! Coverage facet additionally summarizes the coverage within (`repeats`) and
+//! outside (`outside_repeats`) of the repeats.
+//!
+//! The class of each repeat is the name of its interval (the fourth column of
+//! a BED file) or, for GFF files, the type of its record. RepeatMasker-style
+//! names, such as `L1PA2#LINE/L1` or `LINE/L1`, are reduced to their class
+//! (`LINE`).
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use noodles::sam::{self, alignment::Record, record::cigar::op::Kind};
+use rust_lapper::{Interval, Lapper};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{
+        results,
+        sequence_based::coverage::territories::{merge_ranges, RangesBySequence, Territory},
+        ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet,
+    },
+    utils::{
+        cigar::consumes_reference,
+        intervals::{read_intervals_from_path, IntervalSet},
+        math::percentage,
+        merge::Merge,
+    },
+};
+
+/// The class of repeats whose intervals are not named.
+pub const UNCLASSIFIED: &str = "Unclassified";
+
+/// The name of the Coverage facet's territory within the repeats.
+pub const REPEATS_TERRITORY: &str = "repeats";
+
+/// The name of the Coverage facet's territory outside of the repeats.
+pub const OUTSIDE_REPEATS_TERRITORY: &str = "outside_repeats";
+
+/// Gets the class of a repeat from the name of its interval.
+///
+/// ```
+/// use ngs::qc::record_based::repeats::repeat_class;
+///
+/// assert_eq!(repeat_class(Some("L1PA2#LINE/L1")), "LINE");
+/// assert_eq!(repeat_class(Some("SINE/Alu")), "SINE");
+/// assert_eq!(repeat_class(Some("Simple_repeat")), "Simple_repeat");
+/// assert_eq!(repeat_class(None), "Unclassified");
+/// ```
+pub fn repeat_class(name: Option<&str>) -> &str {
+    let name = match name {
+        Some(name) => name.rsplit_once('#').map_or(name, |(_, class)| class),
+        None => return UNCLASSIFIED,
+    };
+
+    match name.split('/').next() {
+        Some(class) if !class.is_empty() => class,
+        _ => UNCLASSIFIED,
+    }
+}
+
+//============//
+// Annotation //
+//============//
+
+/// The repeats within a repeat annotation. The intervals of each class are
+/// merged, so that overlapping repeats of the same class only count each base
+/// once.
+#[derive(Debug, Default)]
+pub struct RepeatAnnotation {
+    /// The classes of repeats, indexed by the value of each interval.
+    classes: Vec<String>,
+
+    /// The repeats on each sequence. Each interval spans the half-open range
+    /// `[start, end + 1)` and holds the index of its class.
+    sequences: HashMap<String, Lapper<usize, usize>>,
+}
+
+impl RepeatAnnotation {
+    /// Reads the repeats from a BED, interval list, or GFF file.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self::from_intervals(&read_intervals_from_path(path, &[])?))
+    }
+
+    /// Creates a [`RepeatAnnotation`] from a set of intervals, classifying
+    /// each by its name.
+    pub fn from_intervals(intervals: &IntervalSet) -> Self {
+        let mut classes: Vec<String> = Vec::new();
+        let mut sequences = HashMap::new();
+
+        for name in intervals.sequence_names() {
+            // (1) Group the ranges on the sequence by their class.
+            let mut by_class: BTreeMap<usize, Vec<(usize, usize)>> = BTreeMap::new();
+            for interval in intervals.get(name).into_iter().flat_map(|l| l.iter()) {
+                let class = repeat_class(interval.val.name.as_deref());
+                let index = match classes.iter().position(|c| c == class) {
+                    Some(index) => index,
+                    None => {
+                        classes.push(class.to_string());
+                        classes.len() - 1
+                    }
+                };
+
+                by_class
+                    .entry(index)
+                    .or_default()
+                    .push((interval.start, interval.stop - 1));
+            }
+
+            // (2) Merge the ranges of each class and index them.
+            let repeats = by_class
+                .into_iter()
+                .flat_map(|(class, ranges)| {
+                    merge_ranges(ranges)
+                        .into_iter()
+                        .map(move |(start, end)| Interval {
+                            start,
+                            stop: end + 1,
+                            val: class,
+                        })
+                })
+                .collect();
+            sequences.insert(name.to_string(), Lapper::new(repeats));
+        }
+
+        Self { classes, sequences }
+    }
+
+    /// Gets the classes of repeats.
+    pub fn classes(&self) -> &[String] {
+        &self.classes
+    }
+
+    /// Gets the sorted, non-overlapping ranges covered by any repeat on each
+    /// sequence.
+    pub fn ranges(&self) -> RangesBySequence {
+        self.sequences
+            .iter()
+            .map(|(name, repeats)| {
+                let ranges = repeats.iter().map(|r| (r.start, r.stop - 1)).collect();
+                (name.clone(), merge_ranges(ranges))
+            })
+            .collect()
+    }
+
+    /// Gets the Coverage facet's territories within and outside of the
+    /// repeats.
+    pub fn territories(&self) -> Vec<Territory> {
+        let ranges = self.ranges();
+        vec![
+            Territory::within(REPEATS_TERRITORY, ranges.clone()),
+            Territory::outside(OUTSIDE_REPEATS_TERRITORY, ranges),
+        ]
+    }
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// The records and aligned bases within a class of repeats.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RepeatClassMetrics {
+    /// The number of records with at least one aligned base within the class.
+    pub records: usize,
+
+    /// The number of aligned bases within the class.
+    pub aligned_bases: usize,
+
+    /// The percentage of records with at least one aligned base within the
+    /// class.
+    pub records_pct: Option<f64>,
+
+    /// The percentage of aligned bases within the class.
+    pub aligned_bases_pct: Option<f64>,
+}
+
+impl Merge for RepeatClassMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records += other.records;
+        self.aligned_bases += other.aligned_bases;
+        Ok(())
+    }
+}
+
+/// A tally of the mapping qualities of a set of records. Records whose mapping
+/// quality is unavailable (255) are not tallied.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MappingQualityTally {
+    /// The number of records tallied.
+    pub records: usize,
+
+    /// The sum of the mapping qualities of the records tallied.
+    pub mapping_quality_sum: usize,
+
+    /// The number of records tallied with a mapping quality of zero.
+    pub mapping_quality_zero: usize,
+}
+
+impl MappingQualityTally {
+    /// Tallies the mapping quality of a record.
+    pub fn tally(&mut self, mapping_quality: u8) {
+        if mapping_quality == sam::record::mapping_quality::MISSING {
+            return;
+        }
+
+        self.records += 1;
+        self.mapping_quality_sum += mapping_quality as usize;
+        if mapping_quality == 0 {
+            self.mapping_quality_zero += 1;
+        }
+    }
+
+    /// The mean mapping quality of the records tallied.
+    pub fn mean(&self) -> Option<f64> {
+        (self.records > 0).then(|| self.mapping_quality_sum as f64 / self.records as f64)
+    }
+}
+
+impl Merge for MappingQualityTally {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records += other.records;
+        self.mapping_quality_sum += other.mapping_quality_sum;
+        self.mapping_quality_zero += other.mapping_quality_zero;
+        Ok(())
+    }
+}
+
+/// Summary statistics for the Repeats quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The percentage of records with at least one aligned base within a
+    /// repeat.
+    pub records_in_repeats_pct: Option<f64>,
+
+    /// The percentage of aligned bases within a repeat.
+    pub aligned_bases_in_repeats_pct: Option<f64>,
+
+    /// The mean mapping quality of the records within repeats.
+    pub mean_mapping_quality_in_repeats: Option<f64>,
+
+    /// The mean mapping quality of the records outside of repeats.
+    pub mean_mapping_quality_outside_repeats: Option<f64>,
+
+    /// The percentage of records within repeats with a mapping quality of
+    /// zero.
+    pub mapping_quality_zero_in_repeats_pct: Option<f64>,
+
+    /// The percentage of records outside of repeats with a mapping quality of
+    /// zero.
+    pub mapping_quality_zero_outside_repeats_pct: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding repeats.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RepeatMetrics {
+    /// The number of mapped, primary records considered.
+    pub records: usize,
+
+    /// The number of aligned bases within the records considered.
+    pub aligned_bases: usize,
+
+    /// The number of records with at least one aligned base within a repeat.
+    pub records_in_repeats: usize,
+
+    /// The number of aligned bases within a repeat (of any class). Bases
+    /// within repeats of more than one class are only counted once.
+    pub aligned_bases_in_repeats: usize,
+
+    /// The records and aligned bases within each class of repeats.
+    pub classes: BTreeMap<String, RepeatClassMetrics>,
+
+    /// The mapping qualities of the records within repeats.
+    pub mapping_quality_in_repeats: MappingQualityTally,
+
+    /// The mapping qualities of the records outside of repeats.
+    pub mapping_quality_outside_repeats: MappingQualityTally,
+
+    /// Summary statistics for the Repeats quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl RepeatMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        for class in self.classes.values_mut() {
+            class.records_pct = percentage(class.records, self.records);
+            class.aligned_bases_pct = percentage(class.aligned_bases, self.aligned_bases);
+        }
+
+        let inside = &self.mapping_quality_in_repeats;
+        let outside = &self.mapping_quality_outside_repeats;
+
+        self.summary = Some(SummaryMetrics {
+            records_in_repeats_pct: percentage(self.records_in_repeats, self.records),
+            aligned_bases_in_repeats_pct: percentage(
+                self.aligned_bases_in_repeats,
+                self.aligned_bases,
+            ),
+            mean_mapping_quality_in_repeats: inside.mean(),
+            mean_mapping_quality_outside_repeats: outside.mean(),
+            mapping_quality_zero_in_repeats_pct: percentage(
+                inside.mapping_quality_zero,
+                inside.records,
+            ),
+            mapping_quality_zero_outside_repeats_pct: percentage(
+                outside.mapping_quality_zero,
+                outside.records,
+            ),
+        });
+    }
+}
+
+impl Merge for RepeatMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records += other.records;
+        self.aligned_bases += other.aligned_bases;
+        self.records_in_repeats += other.records_in_repeats;
+        self.aligned_bases_in_repeats += other.aligned_bases_in_repeats;
+        Merge::merge(&mut self.classes, other.classes)?;
+        self.mapping_quality_in_repeats
+            .merge(other.mapping_quality_in_repeats)?;
+        self.mapping_quality_outside_repeats
+            .merge(other.mapping_quality_outside_repeats)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Repeats quality control facet.
+pub struct RepeatsFacet {
+    /// The main metric counting struct.
+    pub metrics: RepeatMetrics,
+
+    /// The classes of repeats, indexed by the value of each interval.
+    classes: Vec<String>,
+
+    /// The repeats on each reference sequence, indexed by the reference
+    /// sequence id.
+    repeats: Vec<Option<Lapper<usize, usize>>>,
+
+    /// Whether records marked as duplicate should be counted.
+    count_duplicates: bool,
+}
+
+impl RepeatsFacet {
+    /// Creates a new [`RepeatsFacet`] for the repeats on the reference
+    /// sequences within the header.
+    pub fn new(
+        mut annotation: RepeatAnnotation,
+        header: &sam::Header,
+        options: &FacetOptions,
+    ) -> Self {
+        let repeats = header
+            .reference_sequences()
+            .keys()
+            .map(|name| annotation.sequences.remove(name.as_str()))
+            .collect();
+
+        Self {
+            metrics: RepeatMetrics::default(),
+            classes: annotation.classes,
+            repeats,
+            count_duplicates: options.count_duplicates,
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for RepeatsFacet {
+    fn name(&self) -> &'static str {
+        "Repeats"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Moderate
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Only mapped, primary records are considered, as with the Mapping
+        // Quality facet.
+        let flags = record.flags();
+        if flags.is_unmapped()
+            || flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        let (id, mut position) = match (record.reference_sequence_id(), record.alignment_start()) {
+            (Some(id), Some(start)) => (id, usize::from(start)),
+            _ => return Ok(()),
+        };
+        let repeats = self.repeats.get(id).and_then(Option::as_ref);
+
+        // (2) Intersect each aligned block with the repeats.
+        let mut aligned_bases = 0;
+        let mut bases_in_repeats = 0;
+        let mut bases_by_class = vec![0; self.classes.len()];
+
+        for op in record.cigar().iter() {
+            let len = op.len();
+
+            if matches!(
+                op.kind(),
+                Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch
+            ) {
+                let (block_start, block_stop) = (position, position + len);
+                aligned_bases += len;
+
+                if let Some(repeats) = repeats {
+                    let mut overlaps = Vec::new();
+                    for repeat in repeats.find(block_start, block_stop) {
+                        let start = usize::max(repeat.start, block_start);
+                        let end = usize::min(repeat.stop, block_stop) - 1;
+                        bases_by_class[repeat.val] += end - start + 1;
+                        overlaps.push((start, end));
+                    }
+
+                    // Repeats of different classes may overlap, so the bases
+                    // within any repeat are only counted once.
+                    bases_in_repeats += merge_ranges(overlaps)
+                        .into_iter()
+                        .map(|(start, end)| end - start + 1)
+                        .sum::<usize>();
+                }
+            }
+
+            if consumes_reference(op.kind()) {
+                position += len;
+            }
+        }
+
+        // (3) Tally the record.
+        self.metrics.records += 1;
+        self.metrics.aligned_bases += aligned_bases;
+        self.metrics.aligned_bases_in_repeats += bases_in_repeats;
+
+        for (class, bases) in bases_by_class.into_iter().enumerate() {
+            if bases > 0 {
+                let metrics = self
+                    .metrics
+                    .classes
+                    .entry(self.classes[class].clone())
+                    .or_default();
+                metrics.records += 1;
+                metrics.aligned_bases += bases;
+            }
+        }
+
+        let mapping_quality = record
+            .mapping_quality()
+            .map(u8::from)
+            .unwrap_or(sam::record::mapping_quality::MISSING);
+        match bases_in_repeats > 0 {
+            true => {
+                self.metrics.records_in_repeats += 1;
+                self.metrics
+                    .mapping_quality_in_repeats
+                    .tally(mapping_quality);
+            }
+            false => self
+                .metrics
+                .mapping_quality_outside_repeats
+                .tally(mapping_quality),
+        }
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.repeats = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{
+        core::Position,
+        sam::record::{Flags, MappingQuality},
+    };
+
+    use crate::utils::intervals::Region;
+
+    use super::*;
+
+    #[test]
+    pub fn it_tallies_records_and_bases_within_each_class_of_repeats() -> anyhow::Result<()> {
+        let repeat = |start: usize, end: usize, name: &str| {
+            (
+                String::from("chr1"),
+                start,
+                end,
+                Region {
+                    name: Some(name.to_string()),
+                    strand: None,
+                },
+            )
+        };
+        let annotation = RepeatAnnotation::from_intervals(&IntervalSet::from_intervals(vec![
+            repeat(101, 200, "L1PA2#LINE/L1"),
+            repeat(151, 250, "L1HS#LINE/L1"),
+            repeat(181, 190, "(CA)n#Simple_repeat"),
+            repeat(1001, 1300, "AluY#SINE/Alu"),
+        ]));
+        assert_eq!(annotation.ranges()["chr1"], vec![(101, 250), (1001, 1300)]);
+
+        let header: sam::Header = "@SQ\tSN:chr1\tLN:10000\n".parse()?;
+        let mut facet = RepeatsFacet::new(annotation, &header, &FacetOptions::default());
+
+        let record = |start: usize, cigar: &str, mapq: u8| -> anyhow::Result<Record> {
+            Ok(Record::builder()
+                .set_flags(Flags::empty())
+                .set_reference_sequence_id(0)
+                .set_alignment_start(Position::try_from(start)?)
+                .set_cigar(cigar.parse()?)
+                .set_mapping_quality(MappingQuality::try_from(mapq)?)
+                .build())
+        };
+
+        // Within both LINEs and the simple repeat (positions 171 to 210).
+        facet.process(&record(171, "40M", 0)?)?;
+        // Spanning past the end of the LINEs (positions 241 to 260, 1000 bp
+        // skipped, then 1261 to 1280 within the SINE).
+        facet.process(&record(241, "20M1000N20M", 10)?)?;
+        // Outside of any repeat.
+        facet.process(&record(5001, "50M", 60)?)?;
+        // Unmapped records are not considered.
+        facet.process(&Record::builder().set_flags(Flags::UNMAPPED).build())?;
+
+        facet.summarize()?;
+        let metrics = &facet.metrics;
+        assert_eq!(metrics.records, 3);
+        assert_eq!(metrics.aligned_bases, 130);
+        assert_eq!(metrics.records_in_repeats, 2);
+        assert_eq!(metrics.aligned_bases_in_repeats, 40 + 10 + 20);
+
+        assert_eq!(metrics.classes["LINE"].records, 2);
+        assert_eq!(metrics.classes["LINE"].aligned_bases, 50);
+        assert_eq!(metrics.classes["Simple_repeat"].aligned_bases, 10);
+        assert_eq!(metrics.classes["SINE"].records, 1);
+
+        let summary = metrics.summary.as_ref().unwrap();
+        assert_eq!(summary.mean_mapping_quality_in_repeats, Some(5.0));
+        assert_eq!(summary.mean_mapping_quality_outside_repeats, Some(60.0));
+        assert_eq!(summary.mapping_quality_zero_in_repeats_pct, Some(50.0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+//! Functionality related to the Single Cell quality control facet.
+//!
+//! Single-cell BAMs (e.g., those produced by 10x Genomics Cell Ranger) carry
+//! the corrected cell barcode of each read in the `CB` tag and its corrected
+//! UMI in the `UB` tag. Reads whose barcode could not be matched to the
+//! whitelist keep their raw barcode (`CR` tag) but have no `CB` tag. This facet
+//! reports:
+//!
+//! * The fraction of reads with a valid (corrected) barcode.
+//! * The number of distinct barcodes and the distribution of reads per
+//!   barcode, from which the "knee plot" (reads per barcode by descending
+//!   rank) is derived.
+//! * The sequencing saturation: the fraction of reads (with a barcode and a
+//!   UMI) that are duplicates of an earlier read with the same barcode, UMI,
+//!   and gene (`GX` tag, if present).
+//!
+//! The facet is only reported for files with `CB` tags. Every primary record is
+//! considered regardless of whether it is marked as a duplicate, as UMI
+//! duplicates are what the saturation measures. Only the number of distinct
+//! barcodes and UMIs is reported (not the barcodes and UMIs themselves), so
+//! the results of sharded runs cannot be merged.
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use anyhow::bail;
+use noodles::sam::{alignment::Record, record::data::field::Tag};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, RecordBasedQualityControlFacet},
+    utils::{math::percentage, merge::Merge},
+};
+
+//=========//
+// Metrics //
+//=========//
+
+/// A point on the knee plot: the `rank`-th barcode (by descending number of
+/// reads) has `reads` reads. Only the last rank with each number of reads is
+/// reported, as the plot is a step function between these points.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct KneePoint {
+    /// The rank of the barcode (starting from one).
+    pub rank: usize,
+
+    /// The number of reads with the barcode.
+    pub reads: usize,
+}
+
+/// Summary statistics for the Single Cell quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The percentage of reads with a valid (corrected) cell barcode.
+    pub valid_barcode_pct: Option<f64>,
+
+    /// The mean number of reads per barcode.
+    pub mean_reads_per_barcode: Option<f64>,
+
+    /// The median number of reads per barcode.
+    pub median_reads_per_barcode: Option<f64>,
+
+    /// The estimated sequencing saturation (as a percentage): one minus the
+    /// ratio of distinct barcode, UMI, and gene combinations to reads.
+    pub umi_saturation_pct: Option<f64>,
+
+    /// The points of the knee plot.
+    pub knee: Vec<KneePoint>,
+}
+
+/// Primary struct used to compile stats regarding single-cell barcodes.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SingleCellMetrics {
+    /// The number of reads examined.
+    pub reads: usize,
+
+    /// The number of reads with a valid (corrected) cell barcode.
+    pub reads_with_valid_barcode: usize,
+
+    /// The number of reads with a raw cell barcode but no valid barcode.
+    pub reads_with_invalid_barcode: usize,
+
+    /// The number of reads with both a valid cell barcode and a UMI.
+    pub reads_with_umi: usize,
+
+    /// The number of distinct valid cell barcodes.
+    pub distinct_barcodes: usize,
+
+    /// The number of distinct barcode, UMI, and gene combinations.
+    pub distinct_umis: usize,
+
+    /// The number of barcodes with each number of reads.
+    pub reads_per_barcode: BTreeMap<usize, usize>,
+
+    /// Summary statistics for the Single Cell quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl SingleCellMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        // (1) Walk the barcodes from the most reads to the fewest, recording
+        // the last rank with each number of reads.
+        let mut knee = Vec::with_capacity(self.reads_per_barcode.len());
+        let mut rank = 0;
+        for (reads, barcodes) in self.reads_per_barcode.iter().rev() {
+            rank += barcodes;
+            knee.push(KneePoint {
+                rank,
+                reads: *reads,
+            });
+        }
+
+        // (2) The median is the middle of the ranks (or the mean of the two
+        // middle ranks).
+        let reads_at = |rank: usize| {
+            knee.iter()
+                .find(|point| point.rank >= rank)
+                .map(|point| point.reads as f64)
+        };
+        let median_reads_per_barcode = match rank {
+            0 => None,
+            n if n % 2 == 1 => reads_at(n / 2 + 1),
+            n => reads_at(n / 2)
+                .zip(reads_at(n / 2 + 1))
+                .map(|(a, b)| (a + b) / 2.0),
+        };
+
+        let barcoded_reads = self
+            .reads_per_barcode
+            .iter()
+            .map(|(reads, barcodes)| reads * barcodes)
+            .sum::<usize>();
+
+        self.summary = Some(SummaryMetrics {
+            valid_barcode_pct: percentage(self.reads_with_valid_barcode, self.reads),
+            mean_reads_per_barcode: (rank > 0).then(|| barcoded_reads as f64 / rank as f64),
+            median_reads_per_barcode,
+            umi_saturation_pct: percentage(self.distinct_umis, self.reads_with_umi)
+                .map(|pct| 100.0 - pct),
+            knee,
+        });
+    }
+}
+
+impl Merge for SingleCellMetrics {
+    /// Refuses to merge the metrics. A barcode (or UMI) seen in both results
+    /// would be counted as distinct in each, inflating the distinct counts,
+    /// the knee, and the saturation, so the metrics can only be computed over
+    /// the whole file.
+    fn merge(&mut self, _: Self) -> anyhow::Result<()> {
+        bail!(
+            "Cannot merge single-cell results, as barcodes and UMIs shared \
+            between them cannot be told apart. Compute the Single Cell facet \
+            over the whole file instead (with `--only \"Single Cell\"`)."
+        )
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Single Cell quality control facet.
+pub struct SingleCellFacet {
+    /// The main metric counting struct.
+    pub metrics: SingleCellMetrics,
+
+    /// The number of reads with each valid cell barcode.
+    pub barcodes: HashMap<String, usize>,
+
+    /// The hashes of the distinct barcode, UMI, and gene combinations.
+    pub umis: HashSet<u64>,
+
+    /// The tags for the corrected UMI (`UB`) and the gene (`GX`), which are
+    /// not part of the SAM specification.
+    tags: (Tag, Tag),
+}
+
+impl Default for SingleCellFacet {
+    fn default() -> Self {
+        Self {
+            metrics: SingleCellMetrics::default(),
+            barcodes: HashMap::new(),
+            umis: HashSet::new(),
+            // SAFETY: each of the tags is two characters long.
+            tags: (
+                Tag::try_from(*b"UB").unwrap(),
+                Tag::try_from(*b"GX").unwrap(),
+            ),
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for SingleCellFacet {
+    fn name(&self) -> &'static str {
+        "Single Cell"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Moderate
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Each read is only counted once, so secondary and supplementary
+        // records are skipped.
+        let flags = record.flags();
+        if flags.is_secondary() || flags.is_supplementary() {
+            return Ok(());
+        }
+
+        self.metrics.reads += 1;
+
+        let data = record.data();
+        let string = |tag: Tag| data.get(tag).and_then(|field| field.value().as_str());
+        let (umi_tag, gene_tag) = self.tags;
+
+        // (2) Tally the reads for the valid barcode, if there is one.
+        let barcode = match string(Tag::CellBarcodeId) {
+            Some(barcode) => barcode,
+            None => {
+                if data.get(Tag::CellBarcodeSequence).is_some() {
+                    self.metrics.reads_with_invalid_barcode += 1;
+                }
+                return Ok(());
+            }
+        };
+
+        self.metrics.reads_with_valid_barcode += 1;
+        match self.barcodes.get_mut(barcode) {
+            Some(reads) => *reads += 1,
+            None => {
+                self.barcodes.insert(barcode.to_string(), 1);
+            }
+        }
+
+        // (3) Track the distinct barcode, UMI, and gene combinations. Only
+        // their hashes are kept, as there can be hundreds of millions.
+        if let Some(umi) = string(umi_tag) {
+            self.metrics.reads_with_umi += 1;
+
+            let mut hasher = DefaultHasher::new();
+            (barcode, umi, string(gene_tag)).hash(&mut hasher);
+            self.umis.insert(hasher.finish());
+        }
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        let mut reads_per_barcode = BTreeMap::new();
+        for reads in self.barcodes.values() {
+            *reads_per_barcode.entry(*reads).or_insert(0) += 1;
+        }
+
+        self.metrics.distinct_barcodes = self.barcodes.len();
+        self.metrics.distinct_umis = self.umis.len();
+        self.metrics.reads_per_barcode = reads_per_barcode;
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        // Files without cell barcodes are not single-cell data, so the facet
+        // is only reported if any of the records had a barcode.
+        if self.metrics.reads_with_valid_barcode > 0 || self.metrics.reads_with_invalid_barcode > 0
+        {
+            results.single_cell = Some(self.metrics.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::record::{Data, Flags};
+
+    use super::*;
+
+    #[test]
+    pub fn it_tallies_barcodes_umis_and_the_knee() -> anyhow::Result<()> {
+        let mut facet = SingleCellFacet::default();
+
+        let record = |data: &str| -> anyhow::Result<Record> {
+            Ok(Record::builder()
+                .set_flags(Flags::UNMAPPED)
+                .set_data(data.parse::<Data>()?)
+                .build())
+        };
+
+        // Three reads for the first barcode, two of which share a UMI and a
+        // gene.
+        facet.process(&record("CB:Z:AAAC-1\tUB:Z:TTTT\tGX:Z:G1")?)?;
+        facet.process(&record("CB:Z:AAAC-1\tUB:Z:TTTT\tGX:Z:G1")?)?;
+        facet.process(&record("CB:Z:AAAC-1\tUB:Z:TTTT\tGX:Z:G2")?)?;
+        // One read each for two more barcodes, one without a UMI.
+        facet.process(&record("CB:Z:CCCA-1\tUB:Z:GGGG")?)?;
+        facet.process(&record("CB:Z:GGGT-1")?)?;
+        // A raw barcode that could not be corrected, and no barcode at all.
+        facet.process(&record("CR:Z:NNNN")?)?;
+        facet.process(&record("")?)?;
+
+        facet.summarize()?;
+        let metrics = &facet.metrics;
+        assert_eq!(metrics.reads, 7);
+        assert_eq!(metrics.reads_with_valid_barcode, 5);
+        assert_eq!(metrics.reads_with_invalid_barcode, 1);
+        assert_eq!(metrics.reads_with_umi, 4);
+        assert_eq!(metrics.distinct_barcodes, 3);
+        assert_eq!(metrics.distinct_umis, 3);
+
+        let summary = metrics.summary.as_ref().unwrap();
+        assert_eq!(
+            summary.knee,
+            vec![
+                KneePoint { rank: 1, reads: 3 },
+                KneePoint { rank: 3, reads: 1 }
+            ]
+        );
+        assert_eq!(summary.median_reads_per_barcode, Some(1.0));
+        assert_eq!(summary.mean_reads_per_barcode, Some(5.0 / 3.0));
+        assert_eq!(summary.umi_saturation_pct, Some(25.0));
+
+        let mut results = results::Results::default();
+        SingleCellFacet::default().aggregate(&mut results);
+        assert!(results.single_cell.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_refuses_to_merge_barcodes_seen_in_more_than_one_shard() -> anyhow::Result<()> {
+        let record = Record::builder()
+            .set_flags(Flags::UNMAPPED)
+            .set_data("CB:Z:AAAC-1\tUB:Z:TTTT".parse::<Data>()?)
+            .build();
+
+        // The same barcode and UMI in each shard is a single distinct barcode
+        // and UMI, which the counts alone cannot tell.
+        let mut shards = [SingleCellFacet::default(), SingleCellFacet::default()];
+        for facet in &mut shards {
+            facet.process(&record)?;
+            facet.summarize()?;
+        }
+
+        let [first, second] = shards;
+        let mut metrics = first.metrics;
+        assert!(metrics.merge(second.metrics).is_err());
+
+        // Results without the facet (i.e., without any barcodes) still merge.
+        let mut results = results::Results::default();
+        results.merge(results::Results {
+            single_cell: Some(metrics),
+            ..Default::default()
+        })?;
+        assert!(results.single_cell.is_some());
+
+        Ok(())
+    }
+}
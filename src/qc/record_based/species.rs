@@ -0,0 +1,325 @@
+//! Functionality related to the Species quality control facet.
+//!
+//! When reads are aligned to a hybrid genome (e.g., a combined human and mouse
+//! reference for patient-derived xenografts), the fraction of reads from each
+//! species is one of the most important metrics: it measures how much of the
+//! sample is graft and how much is host. This facet classifies each sequence
+//! in the header by the species it belongs to (see
+//! [`HybridGenome`](crate::utils::genome::hybrid::HybridGenome)) and reports,
+//! for each species, the number of mapped reads, the number of those that
+//! mapped ambiguously (with a mapping quality of zero, as reads from regions
+//! conserved between the species often do), and the aligned bases and mean
+//! coverage over its sequences. It also reports how many reads have a mate
+//! that is mapped to another species.
+
+use std::collections::BTreeMap;
+
+use noodles::sam::{self, alignment::Record, record::cigar::op::Kind};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet},
+    utils::{
+        genome::{normalize_sequence_name, ReferenceGenome},
+        math::percentage,
+        merge::Merge,
+    },
+};
+
+//=========//
+// Metrics //
+//=========//
+
+/// The mapping and coverage metrics for a single species.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SpeciesMetrics {
+    /// The number of sequences in the header that belong to the species.
+    pub sequences: usize,
+
+    /// The combined length of the sequences that belong to the species.
+    pub genome_length: usize,
+
+    /// The number of mapped (primary) records on the species' sequences.
+    pub records: usize,
+
+    /// The percentage of all mapped (primary) records that are on the
+    /// species' sequences.
+    pub records_pct: Option<f64>,
+
+    /// The number of mapped (primary) records on the species' sequences with
+    /// a mapping quality of zero.
+    pub mapq_zero_records: usize,
+
+    /// The number of bases aligned (as matches or mismatches) to the species'
+    /// sequences.
+    pub aligned_bases: usize,
+
+    /// The mean coverage over the species' sequences.
+    pub mean_coverage: Option<f64>,
+}
+
+impl SpeciesMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    fn summarize(&mut self, mapped_records: usize) {
+        self.records_pct = percentage(self.records, mapped_records);
+        self.mean_coverage =
+            (self.genome_length > 0).then(|| self.aligned_bases as f64 / self.genome_length as f64);
+    }
+}
+
+/// Summary statistics for the Species quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The species with the most mapped records.
+    pub predominant_species: Option<String>,
+
+    /// The percentage of mapped (primary) records whose mate is mapped to a
+    /// sequence of another species.
+    pub cross_species_records_pct: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding the species within a hybrid
+/// genome.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SpeciesSplitMetrics {
+    /// The number of mapped (primary) records.
+    pub mapped_records: usize,
+
+    /// The number of mapped (primary) records whose mate is mapped to a
+    /// sequence of another species.
+    pub cross_species_records: usize,
+
+    /// The metrics for each species.
+    pub species: BTreeMap<String, SpeciesMetrics>,
+
+    /// Summary statistics for the Species quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl SpeciesSplitMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        for metrics in self.species.values_mut() {
+            metrics.summarize(self.mapped_records);
+        }
+
+        self.summary = Some(SummaryMetrics {
+            predominant_species: self
+                .species
+                .iter()
+                .filter(|(_, metrics)| metrics.records > 0)
+                .max_by_key(|(_, metrics)| metrics.records)
+                .map(|(name, _)| name.clone()),
+            cross_species_records_pct: percentage(self.cross_species_records, self.mapped_records),
+        });
+    }
+}
+
+impl Merge for SpeciesSplitMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.mapped_records += other.mapped_records;
+        self.cross_species_records += other.cross_species_records;
+        for (name, metrics) in other.species {
+            let current = self.species.entry(name).or_default();
+            // The sequences come from the same reference genome.
+            current.sequences = current.sequences.max(metrics.sequences);
+            current.genome_length = current.genome_length.max(metrics.genome_length);
+            current.records += metrics.records;
+            current.mapq_zero_records += metrics.mapq_zero_records;
+            current.aligned_bases += metrics.aligned_bases;
+        }
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Species quality control facet.
+pub struct SpeciesFacet {
+    /// The main metric counting struct.
+    pub metrics: SpeciesSplitMetrics,
+
+    /// The names of the species, in the order of the reference genome.
+    species: Vec<String>,
+
+    /// The species (by index) that each reference sequence (by id) belongs to.
+    species_by_sequence: Vec<Option<usize>>,
+
+    /// The metrics for each species (by index), while they are being tallied.
+    tallies: Vec<SpeciesMetrics>,
+
+    /// Whether records marked as duplicate should be counted.
+    count_duplicates: bool,
+}
+
+impl SpeciesFacet {
+    /// Creates a new [`SpeciesFacet`], classifying each reference sequence in
+    /// the header by the species of the reference genome it belongs to.
+    pub fn new(
+        reference_genome: &dyn ReferenceGenome,
+        header: &sam::Header,
+        options: &FacetOptions,
+    ) -> Self {
+        let species = reference_genome.species();
+        let mut tallies = vec![SpeciesMetrics::default(); species.len()];
+
+        let species_by_sequence = header
+            .reference_sequences()
+            .iter()
+            .map(|(name, sequence)| {
+                let index = reference_genome
+                    .species_of(normalize_sequence_name(name.as_str()))
+                    .and_then(|s| species.iter().position(|x| *x == s))?;
+                tallies[index].sequences += 1;
+                tallies[index].genome_length += usize::from(sequence.length());
+                Some(index)
+            })
+            .collect();
+
+        Self {
+            metrics: SpeciesSplitMetrics::default(),
+            species,
+            species_by_sequence,
+            tallies,
+            count_duplicates: options.count_duplicates,
+        }
+    }
+
+    /// The species (by index) of a reference sequence (by id), if any.
+    fn species_of(&self, id: Option<usize>) -> Option<usize> {
+        id.and_then(|id| self.species_by_sequence.get(id).copied().flatten())
+    }
+}
+
+impl RecordBasedQualityControlFacet for SpeciesFacet {
+    fn name(&self) -> &'static str {
+        "Species"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Only the primary records of mapped reads are counted.
+        let flags = record.flags();
+        if flags.is_unmapped()
+            || flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        let species = match self.species_of(record.reference_sequence_id()) {
+            Some(species) => species,
+            None => return Ok(()),
+        };
+
+        // (2) Tally the record for its species.
+        self.metrics.mapped_records += 1;
+        let tally = &mut self.tallies[species];
+        tally.records += 1;
+        if record.mapping_quality().map(u8::from) == Some(0) {
+            tally.mapq_zero_records += 1;
+        }
+        tally.aligned_bases += record
+            .cigar()
+            .iter()
+            .filter(|op| {
+                matches!(
+                    op.kind(),
+                    Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch
+                )
+            })
+            .map(|op| op.len())
+            .sum::<usize>();
+
+        // (3) Check whether the mate is mapped to another species.
+        if flags.is_segmented() && !flags.is_mate_unmapped() {
+            if let Some(mate) = self.species_of(record.mate_reference_sequence_id()) {
+                if mate != species {
+                    self.metrics.cross_species_records += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.species = self
+            .species
+            .iter()
+            .cloned()
+            .zip(self.tallies.iter().cloned())
+            .collect();
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.species = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{
+        core::Position,
+        sam::record::{Cigar, Flags, MappingQuality},
+    };
+
+    use super::*;
+    use crate::utils::genome::hybrid::HybridGenome;
+
+    #[test]
+    pub fn it_splits_the_mapped_reads_by_species() -> anyhow::Result<()> {
+        let genome = HybridGenome::parse("human_GRCh38+mouse_GRCm39").unwrap();
+        let header: sam::Header = "@SQ\tSN:chr1\tLN:1000\n\
+            @SQ\tSN:chr2\tLN:1000\n\
+            @SQ\tSN:mouse_chr1\tLN:500\n"
+            .parse()?;
+        let mut facet = SpeciesFacet::new(&genome, &header, &FacetOptions::default());
+
+        let record = |id: usize, mate_id: usize, mapq: u8| -> anyhow::Result<Record> {
+            Ok(Record::builder()
+                .set_flags(Flags::SEGMENTED)
+                .set_reference_sequence_id(id)
+                .set_alignment_start(Position::try_from(1)?)
+                .set_cigar("50M".parse::<Cigar>()?)
+                .set_mapping_quality(MappingQuality::new(mapq).unwrap())
+                .set_mate_reference_sequence_id(mate_id)
+                .build())
+        };
+
+        facet.process(&record(0, 0, 60)?)?;
+        facet.process(&record(1, 2, 60)?)?;
+        facet.process(&record(2, 2, 0)?)?;
+        facet.process(&record(2, 1, 60)?)?;
+        facet.process(&Record::builder().set_flags(Flags::UNMAPPED).build())?;
+        facet.summarize()?;
+
+        let metrics = &facet.metrics;
+        assert_eq!(metrics.mapped_records, 4);
+        assert_eq!(metrics.cross_species_records, 2);
+
+        let human = &metrics.species["human"];
+        assert_eq!((human.sequences, human.genome_length), (2, 2000));
+        assert_eq!(human.records, 2);
+        assert_eq!(human.mean_coverage, Some(0.05));
+
+        let mouse = &metrics.species["mouse"];
+        assert_eq!((mouse.sequences, mouse.genome_length), (1, 500));
+        assert_eq!((mouse.records, mouse.mapq_zero_records), (2, 1));
+        assert_eq!(mouse.aligned_bases, 100);
+
+        let summary = metrics.summary.as_ref().unwrap();
+        assert_eq!(summary.cross_species_records_pct, Some(50.0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,491 @@
+//! Functionality related to the Spike-ins quality control facet.
+//!
+//! Many facilities add PhiX (to balance base composition on Illumina
+//! flowcells) or ERCC RNA spike-ins (as a control for quantification) to their
+//! libraries, and need to confirm how much of the sequencing the spike-ins
+//! took up. This facet reports the fraction of reads from each kind of
+//! spike-in.
+//!
+//! When the reference genome includes the spike-in sequences (PhiX as
+//! `phiX174`, `NC_001422.1`, or similar, and ERCC as `ERCC-00002` and so on),
+//! reads are attributed to the spike-ins by their alignment. For any kind of
+//! spike-in that is not present within the reference, the spike-in sequences
+//! can instead be provided as a FASTA (`--spike-ins`), and the unmapped reads
+//! are screened for their k-mers.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use noodles::sam::{self, alignment::Record};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet},
+    utils::{alignment::sequenced_bases, formats, kmers, math::percentage, merge::Merge},
+};
+
+/// The length of the k-mers used to screen reads for spike-in sequences.
+pub const KMER_LENGTH: usize = 31;
+
+/// The fraction of a read's k-mers that must be found within a spike-in
+/// sequence for the read to be attributed to it. This allows for sequencing
+/// errors, which each disrupt up to [`KMER_LENGTH`] k-mers.
+pub const MIN_KMER_HIT_FRACTION: f64 = 0.25;
+
+//===========//
+// Spike-ins //
+//===========//
+
+/// A kind of spike-in.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SpikeIn {
+    /// The PhiX control library.
+    PhiX,
+
+    /// The ERCC RNA spike-in mix.
+    Ercc,
+}
+
+impl SpikeIn {
+    /// Gets the kind of spike-in that a sequence (by its name) belongs to, if
+    /// any.
+    pub fn from_sequence_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+
+        if name.contains("phix") || name.starts_with("nc_001422") {
+            Some(Self::PhiX)
+        } else if name.starts_with("ercc-") || name.starts_with("ercc_") {
+            Some(Self::Ercc)
+        } else {
+            None
+        }
+    }
+}
+
+/// The k-mers of the spike-in sequences that reads are screened against.
+#[derive(Debug, Default)]
+pub struct KmerScreen {
+    /// The name and kind of each spike-in sequence.
+    pub sequences: Vec<(String, SpikeIn)>,
+
+    /// The spike-in sequence (by index) that each canonical k-mer belongs to.
+    pub kmers: HashMap<u64, usize>,
+}
+
+impl KmerScreen {
+    /// Builds a screen from the spike-in sequences, skipping those of the
+    /// given kinds (e.g., because the reference already contains them). Every
+    /// sequence must be recognizable as one of the kinds of spike-in.
+    pub fn from_sequences<'a>(
+        sequences: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+        skip: &[SpikeIn],
+    ) -> anyhow::Result<Self> {
+        let mut screen = Self::default();
+
+        for (name, bases) in sequences {
+            let kind = match SpikeIn::from_sequence_name(name) {
+                Some(kind) => kind,
+                None => bail!(
+                    "Spike-in sequence {} is not recognized as either PhiX or ERCC.",
+                    name
+                ),
+            };
+
+            if skip.contains(&kind) {
+                continue;
+            }
+
+            let index = screen.sequences.len();
+            screen.sequences.push((name.to_string(), kind));
+
+            for kmer in kmers::canonical(bases, KMER_LENGTH) {
+                screen.kmers.insert(kmer, index);
+            }
+        }
+
+        Ok(screen)
+    }
+
+    /// Reads a screen from a FASTA of spike-in sequences.
+    pub fn read<P>(src: P, skip: &[SpikeIn]) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let src = src.as_ref();
+        let mut reader = formats::fasta::open(src)
+            .with_context(|| format!("opening the spike-ins FASTA: {}", src.display()))?;
+
+        let records = reader
+            .records()
+            .collect::<std::io::Result<Vec<_>>>()
+            .with_context(|| format!("reading the spike-ins FASTA: {}", src.display()))?;
+
+        Self::from_sequences(
+            records
+                .iter()
+                .map(|record| (record.name(), record.sequence().as_ref())),
+            skip,
+        )
+    }
+
+    /// Whether there are no spike-in sequences to screen against.
+    pub fn is_empty(&self) -> bool {
+        self.sequences.is_empty()
+    }
+
+    /// Gets the spike-in sequence (by index) that a read is attributed to, if
+    /// enough of its k-mers were found within the spike-in sequences.
+    pub fn classify(&self, bases: &[u8]) -> Option<usize> {
+        let mut total = 0usize;
+        let mut hits: HashMap<usize, usize> = HashMap::new();
+
+        for kmer in kmers::canonical(bases, KMER_LENGTH) {
+            total += 1;
+            if let Some(index) = self.kmers.get(&kmer) {
+                *hits.entry(*index).or_insert(0) += 1;
+            }
+        }
+
+        let found = hits.values().sum::<usize>();
+        if total == 0 || (found as f64) < MIN_KMER_HIT_FRACTION * total as f64 {
+            return None;
+        }
+
+        hits.into_iter()
+            .max_by_key(|(index, count)| (*count, std::cmp::Reverse(*index)))
+            .map(|(index, _)| index)
+    }
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// The number of reads attributed to a kind of spike-in.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SpikeInCounts {
+    /// The reads aligned to the spike-in sequences within the reference.
+    pub aligned: usize,
+
+    /// The unmapped reads attributed to the spike-in sequences by their
+    /// k-mers.
+    pub screened: usize,
+}
+
+impl SpikeInCounts {
+    /// The total number of reads attributed to the spike-in.
+    pub fn total(&self) -> usize {
+        self.aligned + self.screened
+    }
+}
+
+impl Merge for SpikeInCounts {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.aligned += other.aligned;
+        self.screened += other.screened;
+        Ok(())
+    }
+}
+
+/// Summary statistics for the Spike-ins quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The percentage of reads from any spike-in.
+    pub spike_in_pct: Option<f64>,
+
+    /// The percentage of reads from PhiX.
+    pub phix_pct: Option<f64>,
+
+    /// The percentage of reads from the ERCC spike-ins.
+    pub ercc_pct: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding spike-ins.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SpikeInMetrics {
+    /// The number of reads examined.
+    pub records: usize,
+
+    /// The number of unmapped reads that were screened for spike-in k-mers.
+    pub screened_records: usize,
+
+    /// The reads attributed to PhiX.
+    pub phix: SpikeInCounts,
+
+    /// The reads attributed to the ERCC spike-ins.
+    pub ercc: SpikeInCounts,
+
+    /// The number of reads attributed to each spike-in sequence (e.g., each
+    /// ERCC transcript).
+    pub sequences: BTreeMap<String, usize>,
+
+    /// Summary statistics for the Spike-ins quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl SpikeInMetrics {
+    /// Tallies a read attributed to a spike-in sequence.
+    fn tally(&mut self, name: &str, kind: SpikeIn, aligned: bool) {
+        let counts = match kind {
+            SpikeIn::PhiX => &mut self.phix,
+            SpikeIn::Ercc => &mut self.ercc,
+        };
+
+        match aligned {
+            true => counts.aligned += 1,
+            false => counts.screened += 1,
+        }
+
+        match self.sequences.get_mut(name) {
+            Some(reads) => *reads += 1,
+            None => {
+                self.sequences.insert(name.to_string(), 1);
+            }
+        }
+    }
+
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let (phix, ercc) = (self.phix.total(), self.ercc.total());
+
+        self.summary = Some(SummaryMetrics {
+            spike_in_pct: percentage(phix + ercc, self.records),
+            phix_pct: percentage(phix, self.records),
+            ercc_pct: percentage(ercc, self.records),
+        });
+    }
+}
+
+impl Merge for SpikeInMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records += other.records;
+        self.screened_records += other.screened_records;
+        self.phix.merge(other.phix)?;
+        self.ercc.merge(other.ercc)?;
+        Merge::merge(&mut self.sequences, other.sequences)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Spike-ins quality control facet.
+pub struct SpikeInsFacet {
+    /// The main metric counting struct.
+    pub metrics: SpikeInMetrics,
+
+    /// The name and kind of spike-in of each reference sequence within the
+    /// header (by id), if it is a spike-in sequence.
+    pub aligned: Vec<Option<(String, SpikeIn)>>,
+
+    /// The k-mers to screen unmapped reads against, if any.
+    pub screen: Option<KmerScreen>,
+
+    /// Whether records marked as duplicate should be counted.
+    count_duplicates: bool,
+}
+
+impl SpikeInsFacet {
+    /// Creates a new [`SpikeInsFacet`] from the spike-in sequences within the
+    /// header and the spike-ins FASTA (if provided). If there are no spike-in
+    /// sequences to detect, `None` is returned.
+    pub fn try_new(
+        header: Option<&sam::Header>,
+        options: &FacetOptions,
+    ) -> anyhow::Result<Option<Self>> {
+        // (1) Find the spike-in sequences within the reference.
+        let aligned = header
+            .map(|header| {
+                header
+                    .reference_sequences()
+                    .keys()
+                    .map(|name| {
+                        SpikeIn::from_sequence_name(name.as_str())
+                            .map(|kind| (name.to_string(), kind))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        // (2) Screen for the kinds of spike-ins that the reference lacks.
+        let in_reference = aligned
+            .iter()
+            .flatten()
+            .map(|(_, kind)| *kind)
+            .collect::<Vec<_>>();
+        let screen = options
+            .spike_ins
+            .as_ref()
+            .map(|src| KmerScreen::read(src, &in_reference))
+            .transpose()?
+            .filter(|screen| !screen.is_empty());
+
+        if in_reference.is_empty() && screen.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            metrics: SpikeInMetrics::default(),
+            aligned,
+            screen,
+            count_duplicates: options.count_duplicates,
+        }))
+    }
+}
+
+impl RecordBasedQualityControlFacet for SpikeInsFacet {
+    fn name(&self) -> &'static str {
+        "Spike-ins"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Each read is only counted once, so secondary and supplementary
+        // records are skipped.
+        let flags = record.flags();
+        if flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        self.metrics.records += 1;
+
+        // (2) Attribute mapped reads by the sequence they are aligned to.
+        if !flags.is_unmapped() {
+            let spike_in = record
+                .reference_sequence_id()
+                .and_then(|id| self.aligned.get(id))
+                .and_then(|spike_in| spike_in.as_ref());
+
+            if let Some((name, kind)) = spike_in {
+                self.metrics.tally(name, *kind, true);
+            }
+
+            return Ok(());
+        }
+
+        // (3) Attribute unmapped reads by their k-mers, if screening.
+        if let Some(screen) = &self.screen {
+            self.metrics.screened_records += 1;
+
+            if let Some(index) = screen.classify(&sequenced_bases(record)) {
+                let (name, kind) = &screen.sequences[index];
+                self.metrics.tally(name, *kind, false);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.spike_ins = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{
+        core::Position,
+        sam::record::{Flags, Sequence},
+    };
+
+    use super::*;
+
+    #[test]
+    pub fn it_attributes_aligned_and_screened_reads_to_spike_ins() -> anyhow::Result<()> {
+        assert_eq!(SpikeIn::from_sequence_name("phiX174"), Some(SpikeIn::PhiX));
+        assert_eq!(
+            SpikeIn::from_sequence_name("NC_001422.1"),
+            Some(SpikeIn::PhiX)
+        );
+        assert_eq!(
+            SpikeIn::from_sequence_name("ERCC-00002"),
+            Some(SpikeIn::Ercc)
+        );
+        assert_eq!(SpikeIn::from_sequence_name("chr1"), None);
+
+        // A pseudo-random ERCC sequence, as the screen only looks at k-mers.
+        let mut state = 7u64;
+        let ercc = (0..200)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                b"ACGT"[(state >> 62) as usize]
+            })
+            .collect::<Vec<_>>();
+        let screen = KmerScreen::from_sequences(
+            [("ERCC-00002", &ercc[..]), ("phiX174", &b"ACGT"[..])],
+            &[SpikeIn::PhiX],
+        )?;
+        assert_eq!(screen.sequences.len(), 1);
+        assert!(KmerScreen::from_sequences([("chrM", &b"ACGT"[..])], &[]).is_err());
+
+        let header: sam::Header = "@SQ\tSN:chr1\tLN:10000\n@SQ\tSN:phiX174\tLN:5386\n".parse()?;
+        let mut facet = SpikeInsFacet::try_new(Some(&header), &FacetOptions::default())?.unwrap();
+        facet.screen = Some(screen);
+
+        let mapped = |id: usize| -> anyhow::Result<Record> {
+            Ok(Record::builder()
+                .set_flags(Flags::empty())
+                .set_reference_sequence_id(id)
+                .set_alignment_start(Position::try_from(1)?)
+                .set_cigar("10M".parse()?)
+                .build())
+        };
+        let unmapped = |bases: &[u8]| -> anyhow::Result<Record> {
+            Ok(Record::builder()
+                .set_flags(Flags::UNMAPPED)
+                .set_sequence(Sequence::try_from(bases.to_vec())?)
+                .build())
+        };
+
+        facet.process(&mapped(0)?)?;
+        facet.process(&mapped(1)?)?;
+        // From the ERCC sequence (reverse complemented, with an error).
+        let mut read = ercc[50..150]
+            .iter()
+            .rev()
+            .map(|base| match base {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                _ => b'A',
+            })
+            .collect::<Vec<_>>();
+        read[50] = if read[50] == b'A' { b'C' } else { b'A' };
+        facet.process(&unmapped(&read)?)?;
+        facet.process(&unmapped(&[b'A'; 100])?)?;
+
+        facet.summarize()?;
+        let metrics = &facet.metrics;
+        assert_eq!(metrics.records, 4);
+        assert_eq!(metrics.screened_records, 2);
+        assert_eq!(metrics.phix.aligned, 1);
+        assert_eq!(metrics.ercc.screened, 1);
+        assert_eq!(metrics.sequences["ERCC-00002"], 1);
+
+        let summary = metrics.summary.as_ref().unwrap();
+        assert_eq!(summary.spike_in_pct, Some(50.0));
+        assert_eq!(summary.phix_pct, Some(25.0));
+
+        let header: sam::Header = "@SQ\tSN:chr1\tLN:10000\n".parse()?;
+        assert!(SpikeInsFacet::try_new(Some(&header), &FacetOptions::default())?.is_none());
+
+        Ok(())
+    }
+}
@@ -4,19 +4,23 @@ use noodles::sam::alignment::Record;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    qc::{results, ComputationalLoad, RecordBasedQualityControlFacet},
-    utils::histogram::Histogram,
+    qc::{results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet},
+    utils::{histogram::Histogram, math::percentage, merge::Merge},
 };
 
+/// The maximum template length that the histogram will grow to when in
+/// long-read mode. Anything beyond this is tallied as ignored.
+pub const LONG_READ_MAX_TEMPLATE_LENGTH: usize = 1_000_000;
+
 /// Summary statistics for the Template Length quality control facet.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SummaryMetrics {
     /// Percentage of records for which the template length was unknown (zero).
-    pub template_length_unknown_pct: f64,
+    pub template_length_unknown_pct: Option<f64>,
 
     /// Percentage of records for which the template length was greater than our
     /// histogram could support.
-    pub template_length_out_of_range_pct: f64,
+    pub template_length_out_of_range_pct: Option<f64>,
 }
 
 /// General metrics regarding records collected in the quality control facet.
@@ -29,6 +33,17 @@ pub struct RecordMetrics {
     /// Number of records that were ignored (and, as such, had template lengths
     /// that fell outside of our histogram's range).
     pub ignored: usize,
+
+    /// Number of records that were skipped because they were marked as
+    /// duplicate and duplicates are not being counted.
+    #[serde(default)]
+    pub ignored_duplicates: usize,
+
+    /// Number of records that were skipped because they are the rightmost
+    /// record of their template (i.e., they have a negative template length),
+    /// as each template is tallied once by its leftmost record.
+    #[serde(default)]
+    pub ignored_rightmost: usize,
 }
 
 /// Main struct for the Template Length quality control facet.
@@ -38,6 +53,11 @@ pub struct RecordMetrics {
 /// that fall outside of that range are ignored (as tallied in the `ignored`
 /// field). Similarly, records that are processed are tallied in the `processed`
 /// field.
+///
+/// Template lengths are signed in the SAM specification (the leftmost record
+/// in a template is positive and the rightmost is negative). Each template is
+/// tallied once, by its leftmost record: records with a negative template
+/// length are skipped (as tallied in the `ignored_rightmost` field).
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TemplateLengthFacet {
     /// Histogram that represents the number of records that have a given
@@ -49,20 +69,52 @@ pub struct TemplateLengthFacet {
 
     /// Summary statistics for the Template Length quality control facet.
     pub summary: Option<SummaryMetrics>,
+
+    /// Whether records marked as duplicate should be counted.
+    #[serde(skip)]
+    pub count_duplicates: bool,
+
+    /// If set, the histogram grows automatically to fit template lengths up to
+    /// this value.
+    #[serde(skip)]
+    pub auto_grow_limit: Option<usize>,
+
+    /// Whether unpaired records contribute their read length as their template
+    /// length (useful for long-read data, where records are not paired).
+    #[serde(skip)]
+    pub unpaired_as_read_length: bool,
 }
 
 impl TemplateLengthFacet {
     /// Creates a new [`TemplateLengthFacet`] with a specified capacity and
     /// otherwise default values.
-    pub fn with_capacity(capacity: usize) -> Self {
+    pub fn with_capacity(capacity: usize, count_duplicates: bool) -> Self {
         Self {
             histogram: Histogram::zero_based_with_capacity(capacity),
             records: RecordMetrics {
                 processed: 0,
                 ignored: 0,
+                ignored_duplicates: 0,
+                ignored_rightmost: 0,
             },
             summary: None,
+            count_duplicates,
+            auto_grow_limit: None,
+            unpaired_as_read_length: false,
+        }
+    }
+
+    /// Creates a new [`TemplateLengthFacet`] configured by the provided
+    /// [`FacetOptions`].
+    pub fn from_options(options: &FacetOptions) -> Self {
+        let mut facet = Self::with_capacity(options.template_length_max, options.count_duplicates);
+
+        if options.long_reads {
+            facet.auto_grow_limit = Some(LONG_READ_MAX_TEMPLATE_LENGTH);
+            facet.unpaired_as_read_length = true;
         }
+
+        facet
     }
 }
 
@@ -76,7 +128,33 @@ impl RecordBasedQualityControlFacet for TemplateLengthFacet {
     }
 
     fn process(&mut self, record: &Record) -> anyhow::Result<()> {
-        let template_len = record.template_length() as usize;
+        if !self.count_duplicates && record.flags().is_duplicate() {
+            self.records.ignored_duplicates += 1;
+            return Ok(());
+        }
+
+        let template_len = if self.unpaired_as_read_length && !record.flags().is_segmented() {
+            record.sequence().len()
+        } else {
+            match usize::try_from(record.template_length()) {
+                Ok(template_len) => template_len,
+                // The template is tallied by its leftmost (positive) record.
+                Err(_) => {
+                    self.records.ignored_rightmost += 1;
+                    return Ok(());
+                }
+            }
+        };
+
+        if let Some(limit) = self.auto_grow_limit {
+            if !self.histogram.in_range(template_len) && template_len <= limit {
+                // Grow in powers of two so that we aren't reallocating for
+                // every slightly longer template.
+                let capacity = usize::min(template_len.next_power_of_two(), limit);
+                self.histogram.grow_to(capacity);
+            }
+        }
+
         match self.histogram.increment(template_len) {
             Ok(()) => self.records.processed += 1,
             Err(_) => self.records.ignored += 1,
@@ -86,13 +164,10 @@ impl RecordBasedQualityControlFacet for TemplateLengthFacet {
     }
 
     fn summarize(&mut self) -> anyhow::Result<()> {
+        let total = self.records.processed + self.records.ignored;
         self.summary = Some(SummaryMetrics {
-            template_length_unknown_pct: (self.histogram.get(0) as f64
-                / (self.records.processed as f64 + self.records.ignored as f64))
-                * 100.0,
-            template_length_out_of_range_pct: (self.records.ignored as f64
-                / (self.records.processed as f64 + self.records.ignored as f64))
-                * 100.0,
+            template_length_unknown_pct: percentage(self.histogram.get(0), total),
+            template_length_out_of_range_pct: percentage(self.records.ignored, total),
         });
 
         Ok(())
@@ -102,3 +177,72 @@ impl RecordBasedQualityControlFacet for TemplateLengthFacet {
         results.template_length = Some(self.clone());
     }
 }
+
+impl Merge for RecordMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.processed += other.processed;
+        self.ignored += other.ignored;
+        self.ignored_duplicates += other.ignored_duplicates;
+        self.ignored_rightmost += other.ignored_rightmost;
+        Ok(())
+    }
+}
+
+impl Merge for TemplateLengthFacet {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.histogram.merge(other.histogram)?;
+        self.records.merge(other.records)?;
+        self.summarize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::record::Flags;
+
+    use super::*;
+
+    #[test]
+    pub fn it_tallies_each_template_once() -> anyhow::Result<()> {
+        let mut facet = TemplateLengthFacet::with_capacity(1024, true);
+
+        // Both mates of a single template, which has a length of 300.
+        for (flags, template_length) in [
+            (Flags::FIRST_SEGMENT, 300),
+            (Flags::LAST_SEGMENT | Flags::REVERSE_COMPLEMENTED, -300),
+        ] {
+            let record = Record::builder()
+                .set_flags(Flags::SEGMENTED | flags)
+                .set_template_length(template_length)
+                .build();
+            facet.process(&record)?;
+        }
+
+        assert_eq!(facet.histogram.get(300), 1);
+        assert_eq!(facet.records.processed, 1);
+        assert_eq!(facet.records.ignored, 0);
+        assert_eq!(facet.records.ignored_rightmost, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_grows_and_uses_read_length_in_long_read_mode() -> anyhow::Result<()> {
+        let options = FacetOptions {
+            long_reads: true,
+            ..Default::default()
+        };
+
+        let mut facet = TemplateLengthFacet::from_options(&options);
+        let record = Record::builder()
+            .set_sequence("A".repeat(5000).parse()?)
+            .build();
+
+        facet.process(&record)?;
+        assert_eq!(facet.histogram.range_stop(), 8192);
+        assert_eq!(facet.histogram.get(5000), 1);
+        assert_eq!(facet.records.ignored, 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+//! Functionality related to the Tiles quality control facet.
+//!
+//! Illumina read names encode the flowcell, lane, and tile that each read was
+//! sequenced on (see [`IlluminaReadName`]). This facet tallies the quality
+//! scores and base composition of the records from each tile and flags tiles
+//! that stand out from the rest of the run—information that is otherwise only
+//! available from the InterOp files produced by the instrument.
+//!
+//! A tile is flagged when its mean quality score is more than
+//! [`FLAGGED_QUALITY_DROP`] below the median across all tiles, or when its rate
+//! of `N` bases is at least [`FLAGGED_N_RATE_FOLD`] times the median across all
+//! tiles (and above [`MINIMUM_FLAGGED_N_RATE`]).
+
+use std::collections::BTreeMap;
+
+use noodles::sam::{alignment::Record, record::sequence::Base};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    derive::instrument::reads::IlluminaReadName,
+    qc::{results, ComputationalLoad, RecordBasedQualityControlFacet},
+    utils::{
+        math::percentage,
+        merge::Merge,
+        vectorized::{count_bases, sum_quality_scores},
+    },
+};
+
+/// Tiles whose mean quality score is lower than the median across all tiles by
+/// more than this amount are flagged.
+pub const FLAGGED_QUALITY_DROP: f64 = 5.0;
+
+/// Tiles whose `N` rate is at least this many times the median across all
+/// tiles are flagged.
+pub const FLAGGED_N_RATE_FOLD: f64 = 5.0;
+
+/// Tiles are never flagged for their `N` rate if it is below this value.
+pub const MINIMUM_FLAGGED_N_RATE: f64 = 0.01;
+
+//=========//
+// Metrics //
+//=========//
+
+/// The tallies for the records sequenced on a single tile.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TileTallies {
+    /// The number of records from this tile.
+    pub records: usize,
+
+    /// The number of bases from this tile.
+    pub bases: usize,
+
+    /// The sum of the quality scores for the bases from this tile.
+    pub quality_sum: usize,
+
+    /// The number of bases reported as an 'N'.
+    pub n_bases: usize,
+
+    /// The number of bases reported as a 'G' or a 'C'.
+    pub gc_bases: usize,
+}
+
+/// Summary statistics for a single tile.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TileSummary {
+    /// The mean quality score of the bases from this tile.
+    pub mean_quality: Option<f64>,
+
+    /// The fraction of bases from this tile that are an 'N'.
+    pub n_rate: Option<f64>,
+
+    /// The percentage of bases from this tile that are a 'G' or a 'C'.
+    pub gc_content_pct: Option<f64>,
+
+    /// Whether this tile was flagged as an outlier.
+    pub flagged: bool,
+}
+
+/// Summary statistics for the Tiles quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The median of the mean quality scores across all tiles.
+    pub median_tile_mean_quality: Option<f64>,
+
+    /// The median of the `N` rates across all tiles.
+    pub median_tile_n_rate: Option<f64>,
+
+    /// The summary statistics for each tile.
+    pub tiles: BTreeMap<String, TileSummary>,
+
+    /// The tiles that were flagged as outliers, in sorted order.
+    pub flagged_tiles: Vec<String>,
+}
+
+/// Primary struct used to compile stats regarding tiles.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TileMetrics {
+    /// The tallies for each tile, keyed by `FLOWCELL:LANE:TILE` (or
+    /// `LANE:TILE` if the read names do not include the flowcell).
+    pub tiles: BTreeMap<String, TileTallies>,
+
+    /// The number of records whose names could not be parsed as Illumina read
+    /// names.
+    pub records_without_tile: usize,
+
+    /// Summary statistics for the Tiles quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+/// Computes the median of the provided values.
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+
+    if values.len().is_multiple_of(2) {
+        Some((values[mid - 1] + values[mid]) / 2.0)
+    } else {
+        Some(values[mid])
+    }
+}
+
+impl TileMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let rate = |count: usize, total: usize| (total > 0).then(|| count as f64 / total as f64);
+
+        let mut tiles: BTreeMap<String, TileSummary> = self
+            .tiles
+            .iter()
+            .map(|(tile, tallies)| {
+                let summary = TileSummary {
+                    mean_quality: rate(tallies.quality_sum, tallies.bases),
+                    n_rate: rate(tallies.n_bases, tallies.bases),
+                    gc_content_pct: percentage(tallies.gc_bases, tallies.bases),
+                    flagged: false,
+                };
+                (tile.clone(), summary)
+            })
+            .collect();
+
+        let median_tile_mean_quality =
+            median(tiles.values().filter_map(|t| t.mean_quality).collect());
+        let median_tile_n_rate = median(tiles.values().filter_map(|t| t.n_rate).collect());
+
+        let mut flagged_tiles = Vec::new();
+        for (name, tile) in tiles.iter_mut() {
+            let low_quality = matches!(
+                (tile.mean_quality, median_tile_mean_quality),
+                (Some(quality), Some(median)) if median - quality > FLAGGED_QUALITY_DROP
+            );
+            let high_n_rate = matches!(
+                (tile.n_rate, median_tile_n_rate),
+                (Some(rate), Some(median))
+                    if rate >= MINIMUM_FLAGGED_N_RATE && rate >= median * FLAGGED_N_RATE_FOLD
+            );
+
+            if low_quality || high_n_rate {
+                tile.flagged = true;
+                flagged_tiles.push(name.clone());
+            }
+        }
+        flagged_tiles.sort();
+
+        self.summary = Some(SummaryMetrics {
+            median_tile_mean_quality,
+            median_tile_n_rate,
+            tiles,
+            flagged_tiles,
+        });
+    }
+}
+
+impl Merge for TileTallies {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records += other.records;
+        self.bases += other.bases;
+        self.quality_sum += other.quality_sum;
+        self.n_bases += other.n_bases;
+        self.gc_bases += other.gc_bases;
+        Ok(())
+    }
+}
+
+impl Merge for TileMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        Merge::merge(&mut self.tiles, other.tiles)?;
+        self.records_without_tile += other.records_without_tile;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Tiles quality control facet.
+#[derive(Default)]
+pub struct TilesFacet {
+    /// The main metric counting struct.
+    pub metrics: TileMetrics,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+}
+
+impl TilesFacet {
+    /// Creates a new [`TilesFacet`].
+    pub fn new(count_duplicates: bool) -> Self {
+        Self {
+            metrics: TileMetrics::default(),
+            count_duplicates,
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for TilesFacet {
+    fn name(&self) -> &'static str {
+        "Tiles"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Moderate
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Each base is only counted once, so secondary and supplementary
+        // records are skipped, as are duplicates if they aren't being counted.
+        let flags = record.flags();
+        if flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        // (2) Determine the tile from the read name.
+        let read_name = match record
+            .read_name()
+            .and_then(|name| AsRef::<str>::as_ref(name).parse::<IlluminaReadName>().ok())
+        {
+            Some(read_name) => read_name,
+            None => {
+                self.metrics.records_without_tile += 1;
+                return Ok(());
+            }
+        };
+
+        let tile = match read_name.flowcell {
+            Some(flowcell) => format!("{}:{}:{}", flowcell, read_name.lane, read_name.tile),
+            None => format!("{}:{}", read_name.lane, read_name.tile),
+        };
+
+        // (3) Tally up the bases and quality scores.
+        let tallies = self.metrics.tiles.entry(tile).or_default();
+        tallies.records += 1;
+
+        let sequence = record.sequence();
+        let bases = sequence.as_ref();
+        tallies.bases += bases.len();
+        tallies.n_bases += bases.iter().filter(|base| **base == Base::N).count();
+        tallies.gc_bases += count_bases(bases).gc;
+        tallies.quality_sum += sum_quality_scores(record.quality_scores().as_ref());
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        results.tiles = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::record::{QualityScores, ReadName};
+
+    use super::*;
+
+    fn record(read_name: &str, sequence: &str, quality_scores: &str) -> anyhow::Result<Record> {
+        Ok(Record::builder()
+            .set_read_name(read_name.parse::<ReadName>()?)
+            .set_sequence(sequence.parse()?)
+            .set_quality_scores(quality_scores.parse::<QualityScores>()?)
+            .build())
+    }
+
+    #[test]
+    pub fn it_flags_tiles_with_low_quality_or_many_ns() -> anyhow::Result<()> {
+        let mut facet = TilesFacet::new(true);
+
+        for tile in ["1101", "1102", "1103"] {
+            let name = format!("M:1:FC:1:{}:1:1", tile);
+            facet.process(&record(&name, "ACGTACGTAC", "IIIIIIIIII")?)?;
+        }
+        facet.process(&record("M:1:FC:1:1104:1:1", "ACGTACGTAC", "++++++++++")?)?;
+        facet.process(&record("M:1:FC:2:1101:1:1", "NNNNACGTAC", "IIIIIIIIII")?)?;
+        facet.process(&record("not-illumina", "ACGT", "IIII")?)?;
+        facet.summarize()?;
+
+        assert_eq!(facet.metrics.records_without_tile, 1);
+        assert_eq!(facet.metrics.tiles.len(), 5);
+
+        let summary = facet.metrics.summary.as_ref().unwrap();
+        assert_eq!(summary.median_tile_mean_quality, Some(40.0));
+        assert_eq!(summary.tiles["FC:1:1104"].mean_quality, Some(10.0));
+        assert_eq!(summary.tiles["FC:2:1101"].n_rate, Some(0.4));
+        assert_eq!(summary.flagged_tiles, vec!["FC:1:1104", "FC:2:1101"]);
+
+        Ok(())
+    }
+}
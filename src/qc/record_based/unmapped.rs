@@ -0,0 +1,382 @@
+//! Functionality related to the Unmapped Reads quality control facet.
+//!
+//! A high unmapped rate is uninterpretable on its own: the reads might be too
+//! short (e.g., adapter dimers), of poor quality, low-complexity (e.g.,
+//! poly-G from two-color chemistry), or from a contaminant. This facet
+//! summarizes the unmapped reads separately from the mapped ones:
+//!
+//! * The distribution of their read lengths and per-read mean qualities.
+//! * Their adapter content (as in the Adapter Content facet).
+//! * The fraction that are low-complexity, by the DUST score of the read.
+//! * Where the mates of the unmapped reads are mapped, if they are.
+
+use std::collections::{BTreeMap, HashMap};
+
+use noodles::sam::{self, alignment::Record};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{
+        record_based::{
+            adapters::{AdapterContentFacet, AdapterMetrics},
+            read_length::ReadLengthMetrics,
+        },
+        results, ComputationalLoad, FacetOptions, RecordBasedQualityControlFacet,
+    },
+    utils::{alignment::sequenced_bases, histogram::Histogram, math::percentage, merge::Merge},
+};
+
+/// Reads with a DUST score above this are considered low-complexity.
+pub const LOW_COMPLEXITY_DUST_SCORE: f64 = 7.0;
+
+/// Gets the DUST score (Morgulis et al., 2006) of a sequence: for each
+/// trinucleotide occurring `c` times, `c * (c - 1) / 2` is summed, and the sum
+/// is divided by one less than the number of trinucleotides. Random sequence
+/// scores around one, while a homopolymer of length `n` scores `(n - 2) / 2`.
+/// Sequences with fewer than two trinucleotides have no score.
+pub fn dust_score(bases: &[u8]) -> Option<f64> {
+    let triplets = bases.len().checked_sub(2)?;
+    if triplets < 2 {
+        return None;
+    }
+
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for triplet in bases.windows(3) {
+        *counts.entry(triplet).or_insert(0) += 1;
+    }
+
+    let sum = counts
+        .values()
+        .map(|count| count * (count - 1) / 2)
+        .sum::<usize>();
+    Some(sum as f64 / (triplets - 1) as f64)
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// Summary statistics for the Unmapped Reads quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The percentage of reads that are unmapped.
+    pub unmapped_pct: Option<f64>,
+
+    /// The mean of the per-read mean qualities of the unmapped reads.
+    pub mean_read_quality: Option<f64>,
+
+    /// The percentage of unmapped reads that are low-complexity.
+    pub low_complexity_pct: Option<f64>,
+
+    /// The percentage of unmapped reads containing any of the adapters.
+    pub any_adapter_pct: Option<f64>,
+
+    /// The percentage of unmapped reads whose mate is mapped.
+    pub mate_mapped_pct: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding unmapped reads.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnmappedMetrics {
+    /// The number of reads examined (mapped or not).
+    pub records: usize,
+
+    /// The number of unmapped reads.
+    pub unmapped_records: usize,
+
+    /// The distribution of the read lengths of the unmapped reads.
+    pub read_length: ReadLengthMetrics,
+
+    /// The distribution of the per-read mean qualities of the unmapped reads
+    /// (rounded down). Qualities beyond the last bin are tallied in the last
+    /// bin.
+    pub mean_quality: Histogram,
+
+    /// The adapter content of the unmapped reads.
+    pub adapter_content: AdapterMetrics,
+
+    /// The number of unmapped reads that are low-complexity.
+    pub low_complexity: usize,
+
+    /// The number of unmapped reads whose mate is mapped.
+    pub mate_mapped: usize,
+
+    /// The number of unmapped reads whose mate is also unmapped.
+    pub mate_unmapped: usize,
+
+    /// The number of unmapped reads that are not paired.
+    pub unpaired: usize,
+
+    /// The number of unmapped reads whose mate is mapped to each reference
+    /// sequence.
+    pub mate_locations: BTreeMap<String, usize>,
+
+    /// Summary statistics for the Unmapped Reads quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl UnmappedMetrics {
+    /// Creates a new, empty [`UnmappedMetrics`] with the provided number of
+    /// bins for the mean quality histogram.
+    pub fn with_quality_bins(bins: usize) -> Self {
+        Self {
+            records: 0,
+            unmapped_records: 0,
+            read_length: ReadLengthMetrics::default(),
+            mean_quality: Histogram::zero_based_with_capacity(bins - 1),
+            adapter_content: AdapterMetrics::default(),
+            low_complexity: 0,
+            mate_mapped: 0,
+            mate_unmapped: 0,
+            unpaired: 0,
+            mate_locations: BTreeMap::new(),
+            summary: None,
+        }
+    }
+
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        self.read_length.summarize();
+        self.adapter_content.summarize();
+
+        self.summary = Some(SummaryMetrics {
+            unmapped_pct: percentage(self.unmapped_records, self.records),
+            mean_read_quality: self.mean_quality.mean(),
+            low_complexity_pct: percentage(self.low_complexity, self.unmapped_records),
+            any_adapter_pct: percentage(
+                self.adapter_content.records_with_adapter,
+                self.unmapped_records,
+            ),
+            mate_mapped_pct: percentage(self.mate_mapped, self.unmapped_records),
+        });
+    }
+}
+
+impl Merge for UnmappedMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records += other.records;
+        self.unmapped_records += other.unmapped_records;
+        self.read_length.merge(other.read_length)?;
+        self.mean_quality.merge(other.mean_quality)?;
+        self.adapter_content.merge(other.adapter_content)?;
+        self.low_complexity += other.low_complexity;
+        self.mate_mapped += other.mate_mapped;
+        self.mate_unmapped += other.mate_unmapped;
+        self.unpaired += other.unpaired;
+        Merge::merge(&mut self.mate_locations, other.mate_locations)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Unmapped Reads quality control facet.
+pub struct UnmappedFacet {
+    /// The main metric counting struct.
+    pub metrics: UnmappedMetrics,
+
+    /// The Adapter Content facet, which only sees the unmapped reads.
+    adapters: AdapterContentFacet,
+
+    /// The names of the reference sequences within the header, by id.
+    reference_sequence_names: Vec<String>,
+
+    /// Whether records marked as duplicate should be counted.
+    count_duplicates: bool,
+}
+
+impl UnmappedFacet {
+    /// Creates a new [`UnmappedFacet`] configured by the provided
+    /// [`FacetOptions`]. The header, if provided, resolves the names of the
+    /// reference sequences that mates are mapped to.
+    pub fn new(header: Option<&sam::Header>, options: &FacetOptions) -> Self {
+        let reference_sequence_names = header
+            .map(|header| {
+                header
+                    .reference_sequences()
+                    .keys()
+                    .map(|name| name.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            metrics: UnmappedMetrics::with_quality_bins(options.quality_bins.get()),
+            adapters: AdapterContentFacet::from_options(options),
+            reference_sequence_names,
+            count_duplicates: options.count_duplicates,
+        }
+    }
+}
+
+impl RecordBasedQualityControlFacet for UnmappedFacet {
+    fn name(&self) -> &'static str {
+        "Unmapped Reads"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Light
+    }
+
+    fn process(&mut self, record: &Record) -> anyhow::Result<()> {
+        // (1) Each read is only counted once, so secondary and supplementary
+        // records are skipped, as are duplicates if they aren't being counted.
+        let flags = record.flags();
+        if flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        self.metrics.records += 1;
+        if !flags.is_unmapped() {
+            return Ok(());
+        }
+        self.metrics.unmapped_records += 1;
+
+        // (2) Tally the length, mean quality, adapter content, and complexity
+        // of the read.
+        let bases = sequenced_bases(record);
+        self.metrics.read_length.tally(bases.len());
+
+        let scores = record.quality_scores();
+        if !scores.is_empty() {
+            let sum = scores
+                .as_ref()
+                .iter()
+                .map(|score| u8::from(*score) as usize)
+                .sum::<usize>();
+            let last_bin = self.metrics.mean_quality.values().len() - 1;
+            let bin = usize::min(sum / scores.len(), last_bin);
+            // SAFETY: the bin is clamped to the last bin.
+            self.metrics.mean_quality.increment(bin).unwrap();
+        }
+
+        self.adapters.process(record)?;
+
+        if dust_score(&bases).is_some_and(|score| score > LOW_COMPLEXITY_DUST_SCORE) {
+            self.metrics.low_complexity += 1;
+        }
+
+        // (3) Tally where the mate is mapped, if it is.
+        if !flags.is_segmented() {
+            self.metrics.unpaired += 1;
+        } else if flags.is_mate_unmapped() {
+            self.metrics.mate_unmapped += 1;
+        } else {
+            self.metrics.mate_mapped += 1;
+
+            let location = record.mate_reference_sequence_id().map(|id| {
+                self.reference_sequence_names
+                    .get(id)
+                    .cloned()
+                    .unwrap_or_else(|| id.to_string())
+            });
+            if let Some(location) = location {
+                *self.metrics.mate_locations.entry(location).or_insert(0) += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn summarize(&mut self) -> anyhow::Result<()> {
+        self.metrics.adapter_content = self.adapters.metrics.clone();
+        self.metrics.summarize();
+        Ok(())
+    }
+
+    fn aggregate(&self, results: &mut results::Results) {
+        // There is nothing to characterize if every read was mapped.
+        if self.metrics.unmapped_records > 0 {
+            results.unmapped = Some(self.metrics.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{
+        core::Position,
+        sam::record::{Flags, QualityScores, Sequence},
+    };
+
+    use super::*;
+
+    #[test]
+    pub fn it_characterizes_the_unmapped_reads() -> anyhow::Result<()> {
+        assert_eq!(dust_score(b"AAAAAAAAAA"), Some(4.0));
+        assert_eq!(dust_score(b"ACGTA"), Some(0.0));
+        assert_eq!(dust_score(b"ACG"), None);
+
+        let header: sam::Header = "@SQ\tSN:chr1\tLN:10000\n@SQ\tSN:chr2\tLN:10000\n".parse()?;
+        let mut facet = UnmappedFacet::new(Some(&header), &FacetOptions::default());
+
+        let unmapped = |flags: Flags, bases: &str, quality: &str| -> anyhow::Result<Record> {
+            let mut builder = Record::builder()
+                .set_flags(Flags::UNMAPPED | flags)
+                .set_sequence(bases.parse::<Sequence>()?)
+                .set_quality_scores(quality.parse::<QualityScores>()?);
+            if !flags.is_mate_unmapped() && flags.is_segmented() {
+                builder = builder
+                    .set_mate_reference_sequence_id(1)
+                    .set_mate_alignment_start(Position::try_from(100)?);
+            }
+            Ok(builder.build())
+        };
+
+        // A poly-G read whose mate is mapped to chr2.
+        let (poly_g, low_quality) = ("G".repeat(30), "#".repeat(30));
+        facet.process(&unmapped(Flags::SEGMENTED, &poly_g, &low_quality)?)?;
+        // An adapter dimer whose mate is unmapped as well.
+        facet.process(&unmapped(
+            Flags::SEGMENTED | Flags::MATE_UNMAPPED,
+            "AGATCGGAAGAGCA",
+            "IIIIIIIIIIIIII",
+        )?)?;
+        // An unpaired read.
+        facet.process(&unmapped(Flags::empty(), "ACGTTGCAAC", "5555555555")?)?;
+        // A mapped read is only counted towards the total.
+        facet.process(
+            &Record::builder()
+                .set_flags(Flags::empty())
+                .set_reference_sequence_id(0)
+                .set_alignment_start(Position::try_from(1)?)
+                .set_cigar("4M".parse()?)
+                .set_sequence("ACGT".parse()?)
+                .build(),
+        )?;
+
+        facet.summarize()?;
+        let metrics = &facet.metrics;
+        assert_eq!(metrics.records, 4);
+        assert_eq!(metrics.unmapped_records, 3);
+        assert_eq!(metrics.low_complexity, 1);
+        assert_eq!(metrics.mate_mapped, 1);
+        assert_eq!(metrics.mate_unmapped, 1);
+        assert_eq!(metrics.unpaired, 1);
+        assert_eq!(metrics.mate_locations["chr2"], 1);
+        assert_eq!(metrics.mean_quality.get(2), 1);
+        assert_eq!(metrics.mean_quality.get(40), 1);
+        assert_eq!(
+            metrics
+                .read_length
+                .summary
+                .as_ref()
+                .unwrap()
+                .max_read_length,
+            Some(30)
+        );
+
+        let summary = metrics.summary.as_ref().unwrap();
+        assert_eq!(summary.unmapped_pct, Some(75.0));
+        assert_eq!(summary.low_complexity_pct, percentage(1, 3));
+        assert_eq!(summary.any_adapter_pct, percentage(1, 3));
+
+        Ok(())
+    }
+}
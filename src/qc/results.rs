@@ -1,5 +1,9 @@
 //! Functionality related to the aggregation of results across all quality
 //! control facets.
+//!
+//! Summary statistics that cannot be computed (most commonly, percentages of
+//! a total that is zero) are reported as `null` rather than as `NaN`, so the
+//! results file is always valid JSON.
 
 use std::{
     fs::{self, File},
@@ -7,16 +11,105 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use anyhow::{bail, Context};
 use serde::{Deserialize, Serialize};
 
+use crate::utils::{checksum::InputChecksum, merge::Merge, outputs, provenance::Provenance};
+
 use super::{
-    record_based::{features, gc_content, general, quality_scores, template_length},
-    sequence_based::{coverage, edits},
+    container_based::cram,
+    estimate::EstimateMetrics,
+    record_based::{
+        adapters, alignment_summary, contamination, duplication, features, fragmentomics,
+        gc_content, general, long_reads, mapping_quality, mates, methylation, quality_scores,
+        read_groups, read_length, read_names, repeats, single_cell, species, spike_ins,
+        template_length, tiles, unmapped,
+    },
+    runtime::RuntimeMetrics,
+    sequence_based::{allele_balance, artifacts, coverage, edits, oxog},
+    shard::Shard,
 };
 
+/// Progress of an analysis that is still running.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Progress {
+    /// The reference sequences that have been completed in the second pass.
+    pub sequences_completed: Vec<String>,
+
+    /// The total number of reference sequences that will be processed in the
+    /// second pass.
+    pub sequences_total: usize,
+}
+
+/// Records that were excluded by a read-level filter expression.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FilterMetrics {
+    /// The filter expression that records were required to match.
+    pub expression: String,
+
+    /// The number of records that did not match the filter expression and, as
+    /// such, were not seen by any of the quality control facets.
+    pub records_excluded: usize,
+}
+
+impl Merge for FilterMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        if self.expression != other.expression {
+            bail!(
+                "results computed with different filters cannot be merged: \"{}\" and \"{}\"",
+                self.expression,
+                other.expression
+            );
+        }
+
+        self.records_excluded += other.records_excluded;
+        Ok(())
+    }
+}
+
 /// Main struct for collecting _all_ quality control facet results.
 #[derive(Default, Serialize, Deserialize)]
 pub struct Results {
+    /// The number of records that were processed. This is explicitly reported
+    /// so that results for empty (or header-only) files can be distinguished
+    /// from results that are simply missing.
+    #[serde(default)]
+    pub records_processed: usize,
+
+    /// The read-level filter expression that records were required to match,
+    /// if one was provided, and the number of records it excluded.
+    #[serde(default)]
+    pub filter: Option<FilterMetrics>,
+
+    /// Progress of the second pass, which is only present for partial results
+    /// written while the analysis is still running.
+    #[serde(default)]
+    pub progress: Option<Progress>,
+
+    /// The shard of the reference sequences these results were computed for,
+    /// if the analysis was sharded. Merged results are not associated with a
+    /// shard.
+    #[serde(default)]
+    pub shard: Option<Shard>,
+
+    /// The wall-clock time spent within (and the throughput of) each of the
+    /// quality control facets.
+    #[serde(default)]
+    pub runtime: Option<RuntimeMetrics>,
+
+    /// The checksums of the input files (only computed with `--checksums`).
+    #[serde(default)]
+    pub input_checksums: Option<Vec<InputChecksum>>,
+
+    /// The invocation of `ngs` that produced the results.
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+
+    /// The headline metrics extrapolated from a sample of the file, if the
+    /// analysis was run with `--estimate`.
+    #[serde(default)]
+    pub estimate: Option<EstimateMetrics>,
+
     /// The quality control results from the General facet.
     pub general: Option<general::metrics::GeneralMetrics>,
 
@@ -32,32 +125,295 @@ pub struct Results {
     /// The quality control results from the Quality Scores facet.
     pub quality_scores: Option<quality_scores::QualityScoreFacet>,
 
+    /// The quality control results from the Read Groups facet.
+    pub read_groups: Option<read_groups::ReadGroupMetrics>,
+
+    /// The quality control results from the Mate Consistency facet.
+    pub mate_consistency: Option<mates::MateConsistencyMetrics>,
+
+    /// The quality control results from the Tiles facet.
+    #[serde(default)]
+    pub tiles: Option<tiles::TileMetrics>,
+
+    /// The quality control results from the Read Length facet.
+    #[serde(default)]
+    pub read_length: Option<read_length::ReadLengthMetrics>,
+
+    /// The quality control results from the Adapter Content facet.
+    #[serde(default)]
+    pub adapter_content: Option<adapters::AdapterMetrics>,
+
+    /// The quality control results from the Duplication facet.
+    #[serde(default)]
+    pub duplication: Option<duplication::DuplicationMetrics>,
+
+    /// The quality control results from the Alignment Summary facet.
+    #[serde(default)]
+    pub alignment_summary: Option<alignment_summary::AlignmentSummaryMetrics>,
+
+    /// The quality control results from the Mapping Quality facet.
+    #[serde(default)]
+    pub mapping_quality: Option<mapping_quality::MappingQualityMetrics>,
+
+    /// The quality control results from the Repeats facet.
+    #[serde(default)]
+    pub repeats: Option<repeats::RepeatMetrics>,
+
+    /// The quality control results from the Fragmentomics facet.
+    #[serde(default)]
+    pub fragmentomics: Option<fragmentomics::FragmentomicsMetrics>,
+
+    /// The quality control results from the Methylation facet.
+    #[serde(default)]
+    pub methylation: Option<methylation::MethylationMetrics>,
+
+    /// The quality control results from the Long Reads facet.
+    #[serde(default)]
+    pub long_reads: Option<long_reads::LongReadMetrics>,
+
+    /// The quality control results from the Single Cell facet.
+    #[serde(default)]
+    pub single_cell: Option<single_cell::SingleCellMetrics>,
+
+    /// The quality control results from the Species facet.
+    #[serde(default)]
+    pub species: Option<species::SpeciesSplitMetrics>,
+
+    /// The quality control results from the Spike-ins facet.
+    #[serde(default)]
+    pub spike_ins: Option<spike_ins::SpikeInMetrics>,
+
+    /// The quality control results from the Contamination facet.
+    #[serde(default)]
+    pub contamination: Option<contamination::ContaminationMetrics>,
+
+    /// The quality control results from the Unmapped Reads facet.
+    #[serde(default)]
+    pub unmapped: Option<unmapped::UnmappedMetrics>,
+
+    /// The quality control results from the Read Name Collisions facet.
+    #[serde(default)]
+    pub read_name_collisions: Option<read_names::ReadNameCollisionMetrics>,
+
     /// The quality control results from the Coverage facet.
     pub coverage: Option<coverage::CoverageMetrics>,
 
     /// The quality control results from the Edits facet.
     pub edits: Option<edits::EditMetrics>,
+
+    /// The quality control results from the OxoG facet.
+    #[serde(default)]
+    pub oxog: Option<oxog::OxoGMetrics>,
+
+    /// The quality control results from the Sequencing Artifacts facet.
+    #[serde(default)]
+    pub sequencing_artifacts: Option<artifacts::SequencingArtifactMetrics>,
+
+    /// The quality control results from the Allele Balance facet.
+    #[serde(default)]
+    pub allele_balance: Option<allele_balance::AlleleBalanceMetrics>,
+
+    /// The quality control results from the CRAM facet.
+    #[serde(default)]
+    pub cram: Option<cram::CramMetrics>,
+}
+
+/// Gets the path of the partial results file for an output prefix.
+fn partial_filepath(output_prefix: &str, directory: &Path) -> PathBuf {
+    let mut filepath = PathBuf::from(directory);
+    filepath.push(format!("{}.partial.results.json", output_prefix));
+    filepath
 }
 
 impl Results {
     /// Attempts to write the [`Results`] struct to a file within the specified
-    /// directory.
+    /// directory. Any partial results for the same output prefix are removed.
     pub fn write(&self, output_prefix: String, directory: &Path) -> Result<(), io::Error> {
-        let features_filename = output_prefix + ".results.json";
+        let partial = partial_filepath(&output_prefix, directory);
+
         let mut features_filepath = PathBuf::from(directory);
-        features_filepath.push(features_filename);
+        features_filepath.push(format!("{}.results.json", output_prefix));
+
+        self.write_atomically(&features_filepath)?;
+        outputs::record(&features_filepath, "results");
+
+        // Per-gene counts, if they were computed, are also written as a TSV
+        // alongside the results.
+        if let Some(gene_counts) = self.features.as_ref().and_then(|f| f.gene_counts.as_ref()) {
+            let mut gene_counts_filepath = PathBuf::from(directory);
+            gene_counts_filepath.push(format!("{}.gene_counts.tsv", output_prefix));
+            gene_counts.write_tsv(&gene_counts_filepath)?;
+            outputs::record(&gene_counts_filepath, "gene-counts");
+        }
+
+        if partial.exists() {
+            fs::remove_file(partial)?;
+        }
 
-        let mut file = File::create(features_filepath)?;
+        Ok(())
+    }
+
+    /// Attempts to write the [`Results`] struct to a partial results file
+    /// (`<prefix>.partial.results.json`) within the specified directory. This
+    /// is intended to be called periodically while the analysis is running.
+    pub fn write_partial(&self, output_prefix: &str, directory: &Path) -> Result<(), io::Error> {
+        self.write_atomically(&partial_filepath(output_prefix, directory))
+    }
+
+    /// Writes the results to a temporary file and then renames it into place,
+    /// so that readers never observe a partially written file.
+    fn write_atomically(&self, filepath: &Path) -> Result<(), io::Error> {
+        let mut temporary = filepath.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+
+        let mut file = File::create(&temporary)?;
         let output = serde_json::to_string_pretty(&self).unwrap();
         file.write_all(output.as_bytes())?;
+        file.sync_all()?;
 
-        Ok(())
+        fs::rename(temporary, filepath)
     }
 
-    /// Attempts to read a [`Results`] struct from a file.
+    /// Attempts to read a [`Results`] struct from a file, such as one written
+    /// by [`Results::write`] or [`Results::write_partial`]. Results written by
+    /// this version of `ngs` are guaranteed to round-trip: reading them back
+    /// and writing them again produces the same document. Facets (and other
+    /// sections) that are missing from the file are read as `None`.
     pub fn read(filepath: impl AsRef<Path>) -> anyhow::Result<Results> {
         let path = filepath.as_ref();
-        let contents = fs::read_to_string(path)?;
-        Ok(serde_json::from_str(&contents)?)
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read results file: {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Could not parse results file: {}", path.display()))
+    }
+}
+
+impl Merge for Results {
+    /// Merges results that were computed over different portions of the same
+    /// input (e.g., per chromosome). Summary statistics are recomputed from the
+    /// merged counts.
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.records_processed += other.records_processed;
+        self.filter.merge(other.filter)?;
+        self.shard = None;
+        self.progress = None;
+        self.provenance = None;
+        // Estimates are extrapolated from a single sample and are not merged.
+        self.estimate = None;
+        self.runtime.merge(other.runtime)?;
+        // Checksums describe the whole input rather than a portion of it.
+        if self.input_checksums.is_none() {
+            self.input_checksums = other.input_checksums;
+        }
+        self.general.merge(other.general)?;
+        self.features.merge(other.features)?;
+        self.gc_content.merge(other.gc_content)?;
+        self.template_length.merge(other.template_length)?;
+        self.quality_scores.merge(other.quality_scores)?;
+        self.read_groups.merge(other.read_groups)?;
+        self.mate_consistency.merge(other.mate_consistency)?;
+        self.tiles.merge(other.tiles)?;
+        self.read_length.merge(other.read_length)?;
+        self.adapter_content.merge(other.adapter_content)?;
+        self.duplication.merge(other.duplication)?;
+        self.alignment_summary.merge(other.alignment_summary)?;
+        self.mapping_quality.merge(other.mapping_quality)?;
+        self.repeats.merge(other.repeats)?;
+        self.fragmentomics.merge(other.fragmentomics)?;
+        self.methylation.merge(other.methylation)?;
+        self.long_reads.merge(other.long_reads)?;
+        self.single_cell.merge(other.single_cell)?;
+        self.species.merge(other.species)?;
+        self.spike_ins.merge(other.spike_ins)?;
+        self.contamination.merge(other.contamination)?;
+        self.unmapped.merge(other.unmapped)?;
+        self.read_name_collisions
+            .merge(other.read_name_collisions)?;
+        self.coverage.merge(other.coverage)?;
+        self.edits.merge(other.edits)?;
+        self.oxog.merge(other.oxog)?;
+        self.sequencing_artifacts
+            .merge(other.sequencing_artifacts)?;
+        self.allele_balance.merge(other.allele_balance)?;
+        self.cram.merge(other.cram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_merges_results_and_recomputes_summaries() -> anyhow::Result<()> {
+        let mut first = general::metrics::GeneralMetrics::default();
+        first.records.total = 10;
+        first.records.duplicate = 5;
+
+        let mut second = general::metrics::GeneralMetrics::default();
+        second.records.total = 30;
+        second.records.duplicate = 5;
+
+        let mut results = Results {
+            records_processed: 10,
+            general: Some(first),
+            ..Default::default()
+        };
+        let mut coverage = coverage::CoverageMetrics::default();
+        coverage.mean_coverage.insert(String::from("chr1"), 30.0);
+
+        results.merge(Results {
+            records_processed: 30,
+            general: Some(second),
+            coverage: Some(coverage.clone()),
+            ..Default::default()
+        })?;
+
+        assert_eq!(results.records_processed, 40);
+        let general = results.general.as_ref().unwrap();
+        assert_eq!(general.records.total, 40);
+        assert_eq!(
+            general.summary.as_ref().unwrap().duplication_pct,
+            Some(25.0)
+        );
+        assert!(results.coverage.is_some());
+
+        // Coverage for the same sequence cannot be merged.
+        let overlapping = Results {
+            coverage: Some(coverage),
+            ..Default::default()
+        };
+        assert!(results.merge(overlapping).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_replaces_partial_results_with_the_final_results() -> anyhow::Result<()> {
+        let directory =
+            std::env::temp_dir().join(format!("ngs-partial-results-{}", std::process::id()));
+        fs::create_dir_all(&directory)?;
+
+        let partial = Results {
+            progress: Some(Progress {
+                sequences_completed: vec![String::from("chr1")],
+                sequences_total: 2,
+            }),
+            ..Default::default()
+        };
+        partial.write_partial("sample", &directory)?;
+
+        let partial_path = directory.join("sample.partial.results.json");
+        let read = Results::read(&partial_path)?;
+        assert_eq!(read.progress.unwrap().sequences_completed, vec!["chr1"]);
+
+        Results::default().write(String::from("sample"), &directory)?;
+        assert!(!partial_path.exists());
+        assert!(Results::read(directory.join("sample.results.json"))?
+            .progress
+            .is_none());
+
+        fs::remove_dir_all(directory)?;
+        Ok(())
     }
 }
@@ -0,0 +1,173 @@
+//! Functionality related to timing the quality control facets.
+//!
+//! The wall-clock time spent within each facet (and the number of records the
+//! facet processed) is reported in the `runtime` section of the results, so
+//! that the facets responsible for slow runs can be identified and disabled.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::Span;
+
+use crate::utils::{merge::Merge, metrics};
+
+use super::facet_span;
+
+//=========//
+// Metrics //
+//=========//
+
+/// The runtime of a single quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FacetRuntime {
+    /// The wall-clock time spent within the facet, in seconds.
+    pub seconds: f64,
+
+    /// The number of records processed by the facet.
+    pub records: usize,
+
+    /// The number of records processed by the facet per second spent within
+    /// the facet.
+    pub records_per_second: Option<f64>,
+}
+
+impl FacetRuntime {
+    /// Creates a new [`FacetRuntime`], computing the throughput.
+    pub fn new(seconds: f64, records: usize) -> Self {
+        let records_per_second = (seconds > 0.0).then(|| records as f64 / seconds);
+
+        Self {
+            seconds,
+            records,
+            records_per_second,
+        }
+    }
+}
+
+impl Merge for FacetRuntime {
+    /// Merges the runtimes. When the results being merged were computed in
+    /// parallel (e.g., for each shard), the merged time is the total time
+    /// spent within the facet across all of them rather than the elapsed time.
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        *self = Self::new(self.seconds + other.seconds, self.records + other.records);
+        Ok(())
+    }
+}
+
+/// The runtime of each of the quality control facets that were run.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RuntimeMetrics {
+    /// The runtime of each facet, keyed by the name of the facet.
+    pub facets: BTreeMap<String, FacetRuntime>,
+}
+
+impl RuntimeMetrics {
+    /// Adds the runtime of each facet to the operational metrics (see
+    /// [`metrics`]).
+    pub fn publish(&self) {
+        for (name, facet) in &self.facets {
+            metrics::facet_runtime(name, facet.seconds, facet.records);
+        }
+    }
+}
+
+impl Merge for RuntimeMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        Merge::merge(&mut self.facets, other.facets)
+    }
+}
+
+//=============//
+// Facet timer //
+//=============//
+
+/// Times the work done by a quality control facet. The work is also done
+/// within the facet's span (see [`facet_span`]), so that anything the facet
+/// logs is attributed to it.
+pub struct FacetTimer {
+    /// The name of the facet.
+    name: &'static str,
+
+    /// The span the facet's work is done within.
+    span: Span,
+
+    /// The total time spent within the facet.
+    elapsed: Duration,
+
+    /// The number of records processed by the facet.
+    records: usize,
+}
+
+impl FacetTimer {
+    /// Creates a new [`FacetTimer`] for the facet with the provided name.
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            span: facet_span(name),
+            elapsed: Duration::ZERO,
+            records: 0,
+        }
+    }
+
+    /// Does some work for the facet, timing it.
+    pub fn time<T>(&mut self, work: impl FnOnce() -> T) -> T {
+        let _entered = self.span.enter();
+        let start = Instant::now();
+        let result = work();
+        self.elapsed += start.elapsed();
+        result
+    }
+
+    /// Processes a record with the facet, timing it and counting the record.
+    pub fn time_record<T>(&mut self, work: impl FnOnce() -> T) -> T {
+        self.records += 1;
+        self.time(work)
+    }
+
+    /// Adds the runtime of the facet to the provided runtime metrics.
+    pub fn aggregate(&self, runtime: &mut RuntimeMetrics) {
+        runtime.facets.insert(
+            self.name.to_string(),
+            FacetRuntime::new(self.elapsed.as_secs_f64(), self.records),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_times_facets_and_merges_runtimes() -> anyhow::Result<()> {
+        let mut timer = FacetTimer::new("General");
+        for _ in 0..3 {
+            timer.time_record(|| std::thread::sleep(Duration::from_millis(1)));
+        }
+        timer.time(|| {});
+
+        let mut runtime = RuntimeMetrics::default();
+        timer.aggregate(&mut runtime);
+
+        let general = &runtime.facets["General"];
+        assert_eq!(general.records, 3);
+        assert!(general.seconds >= 0.003);
+        assert!(general.records_per_second.unwrap() > 0.0);
+
+        let mut other = RuntimeMetrics::default();
+        other
+            .facets
+            .insert(String::from("General"), FacetRuntime::new(1.0, 7));
+        runtime.merge(other)?;
+
+        let general = &runtime.facets["General"];
+        assert_eq!(general.records, 10);
+        assert!(general.seconds >= 1.003);
+
+        assert_eq!(FacetRuntime::new(0.0, 0).records_per_second, None);
+
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
 //! All sequence-based quality control facets.
 
+pub mod allele_balance;
+pub mod artifacts;
 pub mod coverage;
 pub mod edits;
+pub mod gc_content;
+pub mod oxog;
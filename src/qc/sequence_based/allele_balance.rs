@@ -0,0 +1,458 @@
+//! Functionality related to the Allele Balance quality control facet.
+//!
+//! Given a VCF of known sites (e.g., common SNPs), this facet counts the
+//! reference and alternate alleles observed at each site. At heterozygous sites
+//! in a clean, diploid sample, the fraction of alternate alleles (the allele
+//! balance) is centered at 50%. Contamination with another sample shows up as
+//! a low level of the minor allele at otherwise homozygous sites, whereas
+//! mosaicism (or copy number changes) skews the allele balance at heterozygous
+//! sites away from 50%.
+//!
+//! Genotypes are estimated from the allele fraction at each site with at least
+//! [`MINIMUM_SITE_DEPTH`] reads: sites with an alternate allele fraction at or
+//! below [`HOMOZYGOUS_ALLELE_FRACTION`] (or at or above one minus it) are
+//! considered homozygous, and all other sites are considered heterozygous.
+
+use std::collections::{BTreeMap, HashMap};
+
+use noodles::sam::{
+    alignment::Record,
+    header::record::value::{map::ReferenceSequence, Map},
+    record::cigar::op::Kind,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, FacetOptions, SequenceBasedQualityControlFacet},
+    utils::{
+        formats::vcf::Site,
+        histogram::Histogram,
+        merge::{merge_disjoint, Merge},
+    },
+};
+
+/// The minimum base quality for a base to be counted, unless
+/// `--min-base-quality` is provided.
+pub const MINIMUM_BASE_QUALITY: u8 = 20;
+
+/// The minimum mapping quality for a record to be counted.
+pub const MINIMUM_MAPPING_QUALITY: u8 = 20;
+
+/// The minimum number of reference and alternate alleles observed at a site
+/// for its genotype to be estimated.
+pub const MINIMUM_SITE_DEPTH: usize = 10;
+
+/// Sites with an alternate allele fraction at or below this value (or at or
+/// above one minus this value) are considered homozygous.
+pub const HOMOZYGOUS_ALLELE_FRACTION: f64 = 0.15;
+
+//=========//
+// Metrics //
+//=========//
+
+/// The alleles observed at a single site.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SiteCounts {
+    /// The one-based position of the site.
+    pub position: usize,
+
+    /// The reference base.
+    pub reference: char,
+
+    /// The alternate base.
+    pub alternate: char,
+
+    /// The number of reads supporting the reference allele.
+    pub reference_depth: usize,
+
+    /// The number of reads supporting the alternate allele.
+    pub alternate_depth: usize,
+
+    /// The number of reads supporting neither allele.
+    pub other_depth: usize,
+}
+
+/// Summary statistics for the Allele Balance quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The mean allele balance (percentage of alternate alleles) at
+    /// heterozygous sites.
+    pub mean_het_allele_balance_pct: Option<f64>,
+
+    /// The median allele balance (percentage of alternate alleles) at
+    /// heterozygous sites.
+    pub median_het_allele_balance_pct: Option<f64>,
+
+    /// The percentage of alleles at homozygous sites that are the minor allele,
+    /// which is an estimate of the level of contamination.
+    pub hom_site_minor_allele_pct: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding allele balance.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AlleleBalanceMetrics {
+    /// The number of sites that were loaded for the sequences processed.
+    pub sites_total: usize,
+
+    /// The number of sites with at least [`MINIMUM_SITE_DEPTH`] reads.
+    pub sites_with_sufficient_depth: usize,
+
+    /// The number of sites estimated to be homozygous for the reference
+    /// allele.
+    pub hom_ref_sites: usize,
+
+    /// The number of sites estimated to be heterozygous.
+    pub het_sites: usize,
+
+    /// The number of sites estimated to be homozygous for the alternate allele.
+    pub hom_alt_sites: usize,
+
+    /// The number of minor alleles observed at homozygous sites.
+    pub hom_site_minor_alleles: usize,
+
+    /// The number of alleles observed at homozygous sites.
+    pub hom_site_alleles: usize,
+
+    /// The distribution of allele balance (the percentage of alternate alleles,
+    /// rounded to the nearest whole percent) at heterozygous sites.
+    pub het_allele_balance: Histogram,
+
+    /// The alleles observed at each covered site, for each sequence.
+    pub sites: BTreeMap<String, Vec<SiteCounts>>,
+
+    /// Summary statistics for the Allele Balance quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl AlleleBalanceMetrics {
+    /// Creates a new, empty [`AlleleBalanceMetrics`].
+    pub fn new() -> Self {
+        Self {
+            het_allele_balance: Histogram::zero_based_with_capacity(100),
+            ..Default::default()
+        }
+    }
+
+    /// Tallies the genotype of a site from its allele counts.
+    pub fn tally_site(&mut self, site: &SiteCounts) {
+        let depth = site.reference_depth + site.alternate_depth;
+        if depth < MINIMUM_SITE_DEPTH {
+            return;
+        }
+
+        self.sites_with_sufficient_depth += 1;
+        let fraction = site.alternate_depth as f64 / depth as f64;
+
+        if fraction <= HOMOZYGOUS_ALLELE_FRACTION {
+            self.hom_ref_sites += 1;
+            self.hom_site_minor_alleles += site.alternate_depth;
+            self.hom_site_alleles += depth;
+        } else if fraction >= 1.0 - HOMOZYGOUS_ALLELE_FRACTION {
+            self.hom_alt_sites += 1;
+            self.hom_site_minor_alleles += site.reference_depth;
+            self.hom_site_alleles += depth;
+        } else {
+            self.het_sites += 1;
+            self.het_allele_balance
+                .increment((fraction * 100.0).round() as usize)
+                .unwrap();
+        }
+    }
+
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        self.summary = Some(SummaryMetrics {
+            mean_het_allele_balance_pct: self.het_allele_balance.mean(),
+            median_het_allele_balance_pct: self.het_allele_balance.median(),
+            hom_site_minor_allele_pct: crate::utils::math::percentage(
+                self.hom_site_minor_alleles,
+                self.hom_site_alleles,
+            ),
+        });
+    }
+}
+
+impl Merge for AlleleBalanceMetrics {
+    /// Merges the metrics. Sites are tallied in their entirety for each
+    /// sequence, so the results being merged must cover different sequences.
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.sites_total += other.sites_total;
+        self.sites_with_sufficient_depth += other.sites_with_sufficient_depth;
+        self.hom_ref_sites += other.hom_ref_sites;
+        self.het_sites += other.het_sites;
+        self.hom_alt_sites += other.hom_alt_sites;
+        self.hom_site_minor_alleles += other.hom_site_minor_alleles;
+        self.hom_site_alleles += other.hom_site_alleles;
+        self.het_allele_balance.merge(other.het_allele_balance)?;
+        merge_disjoint(&mut self.sites, other.sites, "allele balance sites")?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Allele Balance quality control facet.
+pub struct AlleleBalanceFacet {
+    /// Metrics related to the Allele Balance quality control facet.
+    pub metrics: AlleleBalanceMetrics,
+
+    /// The sites for each sequence.
+    pub sites: HashMap<String, Vec<Site>>,
+
+    /// The sites for the sequence currently being processed.
+    pub current_sites: Vec<Site>,
+
+    /// The alleles observed at each of the current sites.
+    pub current_counts: Vec<SiteCounts>,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+
+    /// The minimum base quality for a base to be counted.
+    pub min_base_quality: u8,
+}
+
+impl AlleleBalanceFacet {
+    /// Creates a new [`AlleleBalanceFacet`] for the provided sites, configured
+    /// by the provided [`FacetOptions`].
+    pub fn new(sites: HashMap<String, Vec<Site>>, options: &FacetOptions) -> Self {
+        Self {
+            metrics: AlleleBalanceMetrics::new(),
+            sites,
+            current_sites: Vec::new(),
+            current_counts: Vec::new(),
+            count_duplicates: options.count_duplicates,
+            min_base_quality: options.min_base_quality.unwrap_or(MINIMUM_BASE_QUALITY),
+        }
+    }
+
+    /// Tallies the base observed at one of the current sites.
+    fn tally(&mut self, index: usize, base: u8) {
+        let site = &self.current_sites[index];
+        let counts = &mut self.current_counts[index];
+
+        if base == site.reference {
+            counts.reference_depth += 1;
+        } else if base == site.alternate {
+            counts.alternate_depth += 1;
+        } else {
+            counts.other_depth += 1;
+        }
+    }
+}
+
+impl SequenceBasedQualityControlFacet for AlleleBalanceFacet {
+    fn name(&self) -> &'static str {
+        "Allele Balance"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Moderate
+    }
+
+    fn supports_sequence_name(&self, name: &str) -> bool {
+        self.sites.contains_key(name)
+    }
+
+    fn setup(&mut self, sequence: &Map<ReferenceSequence>) -> anyhow::Result<()> {
+        self.current_sites = self
+            .sites
+            .get(sequence.name().as_str())
+            .cloned()
+            .unwrap_or_default();
+        self.current_counts = self
+            .current_sites
+            .iter()
+            .map(|site| SiteCounts {
+                position: site.position,
+                reference: char::from(site.reference),
+                alternate: char::from(site.alternate),
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    fn process(&mut self, _: &Map<ReferenceSequence>, record: &Record) -> anyhow::Result<()> {
+        // (1) Only confidently mapped, primary records are considered.
+        let flags = record.flags();
+        if flags.is_unmapped()
+            || flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        match record.mapping_quality() {
+            Some(mapq) if u8::from(mapq) >= MINIMUM_MAPPING_QUALITY => {}
+            _ => return Ok(()),
+        }
+
+        let start = match record.alignment_start() {
+            Some(start) => usize::from(start),
+            None => return Ok(()),
+        };
+
+        // (2) Step through the alignment alongside the sites, which are
+        // sorted by position.
+        let sequence = record.sequence();
+        let quality_scores = record.quality_scores();
+        let mut index = self
+            .current_sites
+            .partition_point(|site| site.position < start);
+        let mut reference_position = start;
+        let mut record_position = 0;
+
+        for op in record.cigar().iter() {
+            if index >= self.current_sites.len() {
+                break;
+            }
+
+            match op.kind() {
+                Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch => {
+                    for _ in 0..op.len() {
+                        while index < self.current_sites.len()
+                            && self.current_sites[index].position < reference_position
+                        {
+                            index += 1;
+                        }
+
+                        let at_site = index < self.current_sites.len()
+                            && self.current_sites[index].position == reference_position;
+
+                        if at_site {
+                            let quality = quality_scores
+                                .as_ref()
+                                .get(record_position)
+                                .map(|score| u8::from(*score))
+                                .unwrap_or(0);
+
+                            if quality >= self.min_base_quality {
+                                let base = char::from(sequence.as_ref()[record_position]) as u8;
+                                self.tally(index, base);
+                            }
+                        }
+
+                        reference_position += 1;
+                        record_position += 1;
+                    }
+                }
+                Kind::Insertion | Kind::SoftClip => record_position += op.len(),
+                Kind::Deletion | Kind::Skip => reference_position += op.len(),
+                Kind::HardClip | Kind::Pad => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn teardown(&mut self, sequence: &Map<ReferenceSequence>) -> anyhow::Result<()> {
+        self.current_sites.clear();
+        self.metrics.sites_total += self.current_counts.len();
+
+        let covered: Vec<SiteCounts> = std::mem::take(&mut self.current_counts)
+            .into_iter()
+            .filter(|c| c.reference_depth + c.alternate_depth + c.other_depth > 0)
+            .collect();
+
+        for site in &covered {
+            self.metrics.tally_site(site);
+        }
+
+        if !covered.is_empty() {
+            self.metrics
+                .sites
+                .insert(sequence.name().to_string(), covered);
+        }
+
+        Ok(())
+    }
+
+    fn aggregate(&mut self, results: &mut results::Results) {
+        self.metrics.summarize();
+        results.allele_balance = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site(reference_depth: usize, alternate_depth: usize) -> SiteCounts {
+        SiteCounts {
+            reference_depth,
+            alternate_depth,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    pub fn it_estimates_genotypes_and_allele_balance() {
+        let mut metrics = AlleleBalanceMetrics::new();
+        for (reference, alternate) in [(20, 20), (30, 10), (49, 1), (2, 48), (1, 1)] {
+            metrics.tally_site(&site(reference, alternate));
+        }
+        metrics.summarize();
+
+        assert_eq!(metrics.sites_with_sufficient_depth, 4);
+        assert_eq!(metrics.het_sites, 2);
+        assert_eq!(metrics.hom_ref_sites, 1);
+        assert_eq!(metrics.hom_alt_sites, 1);
+        assert_eq!(metrics.het_allele_balance.get(50), 1);
+        assert_eq!(metrics.het_allele_balance.get(25), 1);
+
+        let summary = metrics.summary.unwrap();
+        assert_eq!(summary.mean_het_allele_balance_pct, Some(37.5));
+        assert_eq!(summary.hom_site_minor_allele_pct, Some(3.0));
+    }
+
+    #[test]
+    pub fn it_counts_alleles_at_sites() -> anyhow::Result<()> {
+        use noodles::sam::record::{Flags, MappingQuality, QualityScores};
+
+        let sites = HashMap::from([(
+            String::from("chr1"),
+            vec![
+                Site {
+                    position: 3,
+                    reference: b'G',
+                    alternate: b'A',
+                },
+                Site {
+                    position: 6,
+                    reference: b'T',
+                    alternate: b'C',
+                },
+            ],
+        )]);
+        let mut facet = AlleleBalanceFacet::new(sites, &FacetOptions::default());
+        let sequence = Map::<ReferenceSequence>::new("chr1".parse()?, 10)?;
+
+        assert!(facet.supports_sequence_name("chr1"));
+        facet.setup(&sequence)?;
+
+        // Aligned at position 1: ACA|TT (deletion of one base) then C at 6.
+        let record = Record::builder()
+            .set_flags(Flags::empty())
+            .set_alignment_start(noodles::core::Position::try_from(1)?)
+            .set_mapping_quality(MappingQuality::try_from(60)?)
+            .set_cigar("3M1D2M".parse()?)
+            .set_sequence("ACATC".parse()?)
+            .set_quality_scores("IIIII".parse::<QualityScores>()?)
+            .build();
+        facet.process(&sequence, &record)?;
+        facet.teardown(&sequence)?;
+
+        let counts = &facet.metrics.sites["chr1"];
+        assert_eq!(counts[0].alternate_depth, 1);
+        assert_eq!(counts[1].alternate_depth, 1);
+        assert_eq!(facet.metrics.sites_total, 2);
+
+        Ok(())
+    }
+}
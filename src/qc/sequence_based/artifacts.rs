@@ -0,0 +1,483 @@
+//! Functionality related to the Sequencing Artifacts quality control facet.
+//!
+//! Damage to the DNA before the adapters are ligated (e.g., oxidation of
+//! guanine, or deamination of cytosine in FFPE samples) causes substitutions
+//! that are only observed in one read orientation, in the same way that the OxoG
+//! facet describes for G→T substitutions. Following the approach of Picard's
+//! `CollectSequencingArtifactMetrics` (the "pre-adapter" metrics), this facet
+//! generalizes that analysis to every substitution within every three-base
+//! reference context: for each context, the reference and alternate bases at
+//! the middle position are tallied in the damage-prone ("pro") and the
+//! unaffected ("con") orientations. The excess of alternate bases in the pro
+//! orientation is an estimate of the artifact rate, which is reported as a
+//! Phred-scaled score for each substitution (overall and in its worst context).
+
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use anyhow::{bail, Context};
+use fasta::record::Sequence;
+use noodles::fasta;
+use noodles::sam::{
+    alignment::Record,
+    header::record::value::{map::ReferenceSequence, Map},
+    record::cigar::op::Kind,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{
+        results,
+        sequence_based::oxog::{MINIMUM_BASE_QUALITY, MINIMUM_MAPPING_QUALITY},
+        ComputationalLoad, FacetOptions, SequenceBasedQualityControlFacet,
+    },
+    utils::{
+        cigar::{consumes_reference, consumes_sequence},
+        formats,
+        merge::Merge,
+    },
+};
+
+/// The bases that contexts and substitutions are composed of, in the order in
+/// which alternate bases are reported.
+pub const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+/// The maximum Phred-scaled score that is reported.
+pub const MAX_Q_SCORE: f64 = 100.0;
+
+/// Substitutions caused by well-known pre-adapter artifacts.
+pub const KNOWN_ARTIFACTS: [(u8, u8, &str); 2] =
+    [(b'G', b'T', "OxoG"), (b'C', b'T', "Deamination")];
+
+/// Gets the index of a base within [`BASES`].
+fn base_index(base: u8) -> Option<usize> {
+    BASES.iter().position(|b| *b == base.to_ascii_uppercase())
+}
+
+/// Computes the artifact rate from the pro and con tallies. If no excess is
+/// observed, a single error is assumed so that the rate is never zero.
+fn error_rate(pro_ref: usize, pro_alt: usize, con_ref: usize, con_alt: usize) -> Option<f64> {
+    let total = pro_ref + pro_alt + con_ref + con_alt;
+    (total > 0).then(|| pro_alt.saturating_sub(con_alt).max(1) as f64 / total as f64)
+}
+
+/// Phred-scales an artifact rate.
+fn q_score(rate: f64) -> f64 {
+    f64::min(-10.0 * rate.log10(), MAX_Q_SCORE)
+}
+
+//=========//
+// Metrics //
+//=========//
+
+/// The bases observed at the middle position of a single reference context.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ContextCounts {
+    /// The three-base reference context (on the forward strand).
+    pub context: String,
+
+    /// Reference bases observed in the pro orientation.
+    pub pro_ref_bases: usize,
+
+    /// Reference bases observed in the con orientation.
+    pub con_ref_bases: usize,
+
+    /// Alternate bases observed in the pro orientation, for each of `A`, `C`,
+    /// `G`, and `T` respectively.
+    pub pro_alt_bases: [usize; 4],
+
+    /// Alternate bases observed in the con orientation, for each of `A`, `C`,
+    /// `G`, and `T` respectively.
+    pub con_alt_bases: [usize; 4],
+}
+
+impl ContextCounts {
+    /// The artifact rate for the substitution to `alt` within this context.
+    fn error_rate(&self, alt: usize) -> Option<f64> {
+        error_rate(
+            self.pro_ref_bases,
+            self.pro_alt_bases[alt],
+            self.con_ref_bases,
+            self.con_alt_bases[alt],
+        )
+    }
+}
+
+/// Summary statistics for a single substitution.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SubstitutionSummary {
+    /// The substitution (e.g., `G>T`).
+    pub substitution: String,
+
+    /// The well-known artifact that causes this substitution, if any.
+    pub artifact: Option<String>,
+
+    /// The artifact rate across all contexts.
+    pub error_rate: Option<f64>,
+
+    /// The Phred-scaled artifact rate across all contexts. Higher is better.
+    pub q_score: Option<f64>,
+
+    /// The context with the highest artifact rate.
+    pub worst_context: Option<String>,
+
+    /// The Phred-scaled artifact rate within the worst context.
+    pub worst_context_q_score: Option<f64>,
+}
+
+/// Summary statistics for the Sequencing Artifacts quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// Summary statistics for each of the twelve substitutions.
+    pub substitutions: Vec<SubstitutionSummary>,
+}
+
+/// Primary struct used to compile stats regarding sequencing artifacts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SequencingArtifactMetrics {
+    /// The bases observed within each of the 64 reference contexts.
+    pub contexts: Vec<ContextCounts>,
+
+    /// Summary statistics for the Sequencing Artifacts quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl Default for SequencingArtifactMetrics {
+    fn default() -> Self {
+        let mut contexts = Vec::with_capacity(64);
+        for first in BASES {
+            for middle in BASES {
+                for last in BASES {
+                    contexts.push(ContextCounts {
+                        context: String::from_utf8(vec![first, middle, last]).unwrap(),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        Self {
+            contexts,
+            summary: None,
+        }
+    }
+}
+
+impl SequencingArtifactMetrics {
+    /// Gets the counts for a three-base reference context, if the context is
+    /// composed only of `A`, `C`, `G`, and `T`.
+    fn context_mut(&mut self, context: &[u8]) -> Option<&mut ContextCounts> {
+        let index = context
+            .iter()
+            .try_fold(0, |index, base| Some(index * 4 + base_index(*base)?))?;
+        self.contexts.get_mut(index)
+    }
+
+    /// Tallies a single aligned base at the middle position of a reference
+    /// context, in either the pro or the con orientation.
+    pub fn tally(&mut self, context: &[u8], record_base: u8, pro: bool) {
+        let reference_base = context[1].to_ascii_uppercase();
+        let alt = match base_index(record_base) {
+            Some(alt) => alt,
+            None => return,
+        };
+
+        let counts = match self.context_mut(context) {
+            Some(counts) => counts,
+            None => return,
+        };
+
+        match (pro, BASES[alt] == reference_base) {
+            (true, true) => counts.pro_ref_bases += 1,
+            (false, true) => counts.con_ref_bases += 1,
+            (true, false) => counts.pro_alt_bases[alt] += 1,
+            (false, false) => counts.con_alt_bases[alt] += 1,
+        }
+    }
+
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let mut substitutions = Vec::new();
+
+        for (reference, reference_base) in BASES.iter().enumerate() {
+            // Contexts are ordered by their first, then middle, then last base.
+            let contexts = self
+                .contexts
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| (index / 4) % 4 == reference)
+                .map(|(_, counts)| counts)
+                .collect::<Vec<_>>();
+
+            for (alt, alt_base) in BASES.iter().enumerate() {
+                if alt == reference {
+                    continue;
+                }
+
+                let sum = |f: &dyn Fn(&ContextCounts) -> usize| contexts.iter().map(|c| f(c)).sum();
+                let rate = error_rate(
+                    sum(&|c| c.pro_ref_bases),
+                    sum(&|c| c.pro_alt_bases[alt]),
+                    sum(&|c| c.con_ref_bases),
+                    sum(&|c| c.con_alt_bases[alt]),
+                );
+
+                let worst = contexts
+                    .iter()
+                    .filter_map(|c| c.error_rate(alt).map(|rate| (c, rate)))
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+                let artifact = KNOWN_ARTIFACTS
+                    .iter()
+                    .find(|(r, a, _)| r == reference_base && a == alt_base)
+                    .map(|(_, _, name)| name.to_string());
+
+                substitutions.push(SubstitutionSummary {
+                    substitution: format!("{}>{}", *reference_base as char, *alt_base as char),
+                    artifact,
+                    error_rate: rate,
+                    q_score: rate.map(q_score),
+                    worst_context: worst.map(|(c, _)| c.context.clone()),
+                    worst_context_q_score: worst.map(|(_, rate)| q_score(rate)),
+                });
+            }
+        }
+
+        self.summary = Some(SummaryMetrics { substitutions });
+    }
+}
+
+impl Merge for SequencingArtifactMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        if self.contexts.len() != other.contexts.len() {
+            bail!("sequencing artifact metrics with different contexts cannot be merged");
+        }
+
+        for (counts, other) in self.contexts.iter_mut().zip(other.contexts) {
+            if counts.context != other.context {
+                bail!(
+                    "sequencing artifact metrics with different contexts cannot be merged: {} and {}",
+                    counts.context,
+                    other.context
+                );
+            }
+
+            counts.pro_ref_bases += other.pro_ref_bases;
+            counts.con_ref_bases += other.con_ref_bases;
+            for alt in 0..BASES.len() {
+                counts.pro_alt_bases[alt] += other.pro_alt_bases[alt];
+                counts.con_alt_bases[alt] += other.con_alt_bases[alt];
+            }
+        }
+
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the Sequencing Artifacts quality control facet.
+pub struct SequencingArtifactsFacet {
+    /// Metrics related to the Sequencing Artifacts quality control facet.
+    pub metrics: SequencingArtifactMetrics,
+
+    /// The FASTA reader, which is used to cache the current sequence being
+    /// reviewed as processing occurs.
+    pub fasta: fasta::Reader<BufReader<File>>,
+
+    /// The sequence currently being processed by the quality control facet.
+    pub current_sequence: Option<Sequence>,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+}
+
+impl SequencingArtifactsFacet {
+    /// Tries to create a [`SequencingArtifactsFacet`] from a reference FASTA
+    /// file, configured by the provided [`FacetOptions`].
+    pub fn try_from(reference_fasta: PathBuf, options: &FacetOptions) -> anyhow::Result<Self> {
+        let fasta = formats::fasta::open(&reference_fasta).with_context(|| {
+            format!(
+                "Error opening reference FASTA file: {}.",
+                reference_fasta.display()
+            )
+        })?;
+
+        Ok(Self {
+            metrics: SequencingArtifactMetrics::default(),
+            fasta,
+            current_sequence: None,
+            count_duplicates: options.count_duplicates,
+        })
+    }
+}
+
+impl SequenceBasedQualityControlFacet for SequencingArtifactsFacet {
+    fn name(&self) -> &'static str {
+        "Sequencing Artifacts"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Moderate
+    }
+
+    fn supports_sequence_name(&self, _: &str) -> bool {
+        true
+    }
+
+    fn setup(&mut self, sequence: &Map<ReferenceSequence>) -> anyhow::Result<()> {
+        let seq_name = sequence.name().as_str();
+
+        for result in self.fasta.records() {
+            let record = result?;
+            if seq_name == record.name() {
+                self.current_sequence = Some(record.sequence().clone());
+                return Ok(());
+            }
+        }
+
+        bail!("Sequence {} not found in reference FASTA.", seq_name)
+    }
+
+    fn process(&mut self, _: &Map<ReferenceSequence>, record: &Record) -> anyhow::Result<()> {
+        // (1) Only confidently mapped, primary records are considered.
+        let flags = record.flags();
+        if flags.is_unmapped()
+            || flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        match record.mapping_quality() {
+            Some(mapq) if u8::from(mapq) >= MINIMUM_MAPPING_QUALITY => {}
+            _ => return Ok(()),
+        }
+
+        let reference_start = match record.alignment_start() {
+            Some(start) => usize::from(start) - 1,
+            None => return Ok(()),
+        };
+
+        // (2) Determine the orientation of the record for contexts on the
+        // forward strand, as the OxoG facet does. Unpaired records are treated
+        // as read one.
+        let is_read_one = !flags.is_segmented() || flags.is_first_segment();
+        let pro = is_read_one != flags.is_reverse_complemented();
+
+        // (3) Step through the alignment, tallying each aligned base of
+        // sufficient quality within its reference context.
+        let reference_seq = match &self.current_sequence {
+            Some(sequence) => sequence.as_ref(),
+            None => bail!("Sequencing Artifacts facet was not set up for this sequence."),
+        };
+
+        let sequence = record.sequence();
+        let quality_scores = record.quality_scores();
+        let mut reference_ptr = reference_start;
+        let mut record_ptr = 0;
+
+        for op in record.cigar().iter() {
+            let kind = op.kind();
+            for _ in 0..op.len() {
+                if matches!(
+                    kind,
+                    Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch
+                ) {
+                    let quality = quality_scores
+                        .as_ref()
+                        .get(record_ptr)
+                        .map(|score| u8::from(*score))
+                        .unwrap_or(0);
+
+                    let context = reference_ptr
+                        .checked_sub(1)
+                        .and_then(|start| reference_seq.get(start..reference_ptr + 2));
+
+                    if let (true, Some(context)) = (quality >= MINIMUM_BASE_QUALITY, context) {
+                        let record_base = char::from(sequence.as_ref()[record_ptr]) as u8;
+                        self.metrics.tally(context, record_base, pro);
+                    }
+                }
+
+                if consumes_reference(kind) {
+                    reference_ptr += 1;
+                }
+
+                if consumes_sequence(kind) {
+                    record_ptr += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn teardown(&mut self, _: &Map<ReferenceSequence>) -> anyhow::Result<()> {
+        self.current_sequence = None;
+        Ok(())
+    }
+
+    fn aggregate(&mut self, results: &mut results::Results) {
+        self.metrics.summarize();
+        results.sequencing_artifacts = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_reports_the_worst_context_for_each_substitution() {
+        let mut metrics = SequencingArtifactMetrics::default();
+
+        // G→T in the CGG context is observed far more often in the pro
+        // orientation, whereas G→T in the AGA context is not. The CCG context
+        // (with a C at the middle position) has no substitutions at all.
+        for _ in 0..990 {
+            metrics.tally(b"CCG", b'C', true);
+            metrics.tally(b"CCG", b'C', false);
+            metrics.tally(b"AGA", b'G', true);
+            metrics.tally(b"AGA", b'G', false);
+        }
+        for _ in 0..10 {
+            metrics.tally(b"CGG", b'T', true);
+            metrics.tally(b"AGA", b'T', true);
+            metrics.tally(b"AGA", b'T', false);
+        }
+        for _ in 0..990 {
+            metrics.tally(b"CGG", b'G', true);
+            metrics.tally(b"CGG", b'G', false);
+        }
+
+        // Bases outside of A, C, G, and T are ignored.
+        metrics.tally(b"CNG", b'T', true);
+        metrics.tally(b"CGG", b'N', true);
+
+        metrics.summarize();
+        let summary = metrics.summary.unwrap();
+        assert_eq!(summary.substitutions.len(), 12);
+
+        let g_to_t = summary
+            .substitutions
+            .iter()
+            .find(|s| s.substitution == "G>T")
+            .unwrap();
+        assert_eq!(g_to_t.artifact.as_deref(), Some("OxoG"));
+        assert_eq!(g_to_t.worst_context.as_deref(), Some("CGG"));
+        assert!((g_to_t.worst_context_q_score.unwrap() - 23.0).abs() < 0.1);
+
+        // The contexts with a C at the middle position have no excess.
+        let c_to_t = summary
+            .substitutions
+            .iter()
+            .find(|s| s.substitution == "C>T")
+            .unwrap();
+        assert_eq!(c_to_t.artifact.as_deref(), Some("Deamination"));
+        assert!((c_to_t.q_score.unwrap() - 10.0 * 1980f64.log10()).abs() < 1e-9);
+    }
+}
@@ -1,22 +1,43 @@
 //! Functionality related to the Coverage quality control facet.
 
-use std::{collections::HashMap, num::NonZeroUsize, rc::Rc};
+pub mod territories;
 
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufWriter, Write},
+    num::NonZeroUsize,
+    path::Path,
+    rc::Rc,
+};
+
+use anyhow::bail;
 use noodles::sam::{
     alignment::Record,
     header::record::value::{map::ReferenceSequence, Map},
+    record::cigar::op::Kind,
 };
 use serde::{Deserialize, Serialize};
-use tracing::error;
+use tracing::{error, warn};
 
 use crate::{
-    qc::{results, ComputationalLoad, SequenceBasedQualityControlFacet},
+    qc::{
+        examples::{Example, Examples},
+        results,
+        strata::strand_stratum,
+        ComputationalLoad, FacetOptions, SequenceBasedQualityControlFacet,
+    },
     utils::{
-        genome::{get_primary_assembly, ReferenceGenome, Sequence},
-        histogram::Histogram,
+        cigar::{consumes_reference, consumes_sequence},
+        genome::{get_primary_assembly, get_viral_sequences, ReferenceGenome, Sequence},
+        histogram::{run_length_encoded, Histogram},
+        math::percentage,
+        merge::{merge_disjoint, Merge},
     },
 };
 
+use self::territories::{complement, intersect, ranges_on, Range, RangesBySequence, Territory};
+
 //=========//
 // Metrics //
 //=========//
@@ -30,7 +51,242 @@ pub struct IgnoredMetrics {
 
     /// The number of positions which had coverage that was too high such that
     /// it did not fit into our histogram.
-    pub pileup_too_large_positions: HashMap<String, usize>,
+    pub pileup_too_large_positions: BTreeMap<String, usize>,
+}
+
+/// Metrics comparing the raw coverage to the deduplicated coverage.
+///
+/// These are computed from the total number of aligned bases for each sequence
+/// and, as such, are cheap enough to always calculate.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct DuplicateCoverageMetrics {
+    /// Hashmap containing the mean coverage for each sequence in the reference
+    /// genome, including records marked as duplicate.
+    pub raw_mean_coverage: BTreeMap<String, f64>,
+
+    /// Hashmap containing the mean coverage for each sequence in the reference
+    /// genome, excluding records marked as duplicate.
+    pub dedup_mean_coverage: BTreeMap<String, f64>,
+}
+
+/// The mean coverage within a window (bin) of a sequence.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CoverageBin {
+    /// The name of the sequence.
+    pub seqname: String,
+
+    /// The one-based start position of the bin (inclusive).
+    pub start: usize,
+
+    /// The one-based end position of the bin (inclusive).
+    pub end: usize,
+
+    /// The mean coverage within the bin.
+    pub mean: f64,
+}
+
+/// Single-number summaries of how uniform the coverage of a sequence is,
+/// computed from its coverage distribution. A metric is reported as `null` if
+/// it is undefined for the sequence (e.g., if the sequence has no coverage).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EvennessMetrics {
+    /// The Gini coefficient of the coverage across positions, which ranges
+    /// from zero (every position has the same coverage) to one (all of the
+    /// coverage is at a single position).
+    pub gini_coefficient: Option<f64>,
+
+    /// The evenness score of Oexle (2012), which ranges from zero to one
+    /// (every position has at least the mean coverage, rounded).
+    pub evenness_score: Option<f64>,
+
+    /// The interquartile range of the coverage divided by the median coverage.
+    pub iqr_over_median: Option<f64>,
+}
+
+impl EvennessMetrics {
+    /// Computes the evenness metrics from a coverage distribution (the number
+    /// of positions with each coverage).
+    pub fn from_distribution(distribution: &Histogram) -> Self {
+        let coverages = || {
+            (distribution.range_start()..=distribution.range_stop())
+                .map(|coverage| (coverage, distribution.get(coverage)))
+                .filter(|(_, positions)| *positions > 0)
+        };
+
+        let positions: usize = coverages().map(|(_, positions)| positions).sum();
+        let total: usize = coverages()
+            .map(|(coverage, positions)| coverage * positions)
+            .sum();
+
+        // (1) The Gini coefficient, using the positions sorted by coverage:
+        // G = 2 * Σ(i * x_i) / (n * Σx_i) - (n + 1) / n. Each bin covers the
+        // ranks from `rank + 1` to `rank + positions`.
+        let gini_coefficient = (total > 0).then(|| {
+            let n = positions as f64;
+            let mut rank = 0.0;
+            let mut weighted = 0.0;
+            for (coverage, count) in coverages() {
+                let count = count as f64;
+                let ranks = count * rank + count * (count + 1.0) / 2.0;
+                weighted += ranks * coverage as f64;
+                rank += count;
+            }
+
+            2.0 * weighted / (n * total as f64) - (n + 1.0) / n
+        });
+
+        // (2) The evenness score: E = 1 - (|D2| - Σ D2 / C) / n, where C is
+        // the mean coverage (rounded) and D2 are the positions with coverage
+        // at or below C.
+        let rounded_mean = distribution.mean().map_or(0, |mean| mean.round() as usize);
+        let evenness_score = (rounded_mean > 0).then(|| {
+            let (below, below_total) = coverages()
+                .filter(|(coverage, _)| *coverage <= rounded_mean)
+                .fold((0, 0), |(below, below_total), (coverage, count)| {
+                    (below + count, below_total + coverage * count)
+                });
+
+            1.0 - (below as f64 - below_total as f64 / rounded_mean as f64) / positions as f64
+        });
+
+        // (3) The interquartile range over the median.
+        let iqr_over_median = match (distribution.interquartile_range(), distribution.median()) {
+            (Some(iqr), Some(median)) if median > 0.0 => Some(iqr / median),
+            _ => None,
+        };
+
+        Self {
+            gini_coefficient,
+            evenness_score,
+            iqr_over_median,
+        }
+    }
+}
+
+/// Coverage metrics for a viral (or other microbial) sequence, which are
+/// reported separately from those of the host genome.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ViralCoverageMetrics {
+    /// The length of the sequence.
+    pub length: usize,
+
+    /// The number of bases aligned to the sequence.
+    pub aligned_bases: usize,
+
+    /// The mean coverage across the sequence.
+    pub mean_coverage: f64,
+
+    /// The percentage of positions in the sequence that are covered by at
+    /// least one record.
+    pub covered_pct: Option<f64>,
+}
+
+/// Coverage metrics summarized over a territory (e.g., the whole genome or
+/// only the autosomes), which combine the sequences within the territory so
+/// that the headline numbers do not need to be recomputed by weighting the
+/// per-sequence metrics. Every position of a sequence without any records
+/// counts as a position with zero coverage.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TerritoryCoverageMetrics {
+    /// The number of positions within the territory.
+    pub territory: usize,
+
+    /// The total number of aligned bases that were counted towards the
+    /// coverage.
+    pub aligned_bases: usize,
+
+    /// The mean coverage across the territory, or `null` if the territory is
+    /// empty.
+    pub mean_coverage: Option<f64>,
+
+    /// The median coverage across the territory, or `null` if the territory is
+    /// empty. Positions with coverage greater than the
+    /// coverage cap are excluded (as they are from each sequence's
+    /// distribution).
+    pub median_coverage: Option<f64>,
+
+    /// The number of positions in the territory with each coverage.
+    #[serde(with = "run_length_encoded")]
+    pub coverage_distribution: Histogram,
+}
+
+impl TerritoryCoverageMetrics {
+    /// Creates an empty [`TerritoryCoverageMetrics`] whose distribution
+    /// tracks coverage up to `coverage_cap`.
+    pub fn new(coverage_cap: usize) -> Self {
+        Self {
+            coverage_distribution: Histogram::zero_based_with_capacity(coverage_cap),
+            ..Default::default()
+        }
+    }
+
+    /// Tallies the coverage at each position within the (one-based,
+    /// inclusive) ranges of a sequence. If no records were aligned to the
+    /// sequence, `positions` is `None` and every position has zero coverage.
+    fn tally(&mut self, positions: Option<&Histogram>, ranges: &[Range]) {
+        for (start, end) in ranges {
+            self.territory += end - start + 1;
+
+            match positions {
+                Some(positions) => {
+                    for i in *start..=*end {
+                        let coverage = positions.get(i);
+                        self.aligned_bases += coverage;
+                        // Positions beyond the coverage cap are excluded from
+                        // the distribution.
+                        let _ = self.coverage_distribution.increment(coverage);
+                    }
+                }
+                None => self
+                    .coverage_distribution
+                    .increment_by(0, end - start + 1)
+                    .unwrap(),
+            }
+        }
+    }
+
+    /// Recomputes the mean and median coverage from the totals and the
+    /// distribution.
+    pub fn summarize(&mut self) {
+        self.mean_coverage =
+            (self.territory > 0).then(|| self.aligned_bases as f64 / self.territory as f64);
+        self.median_coverage = match self.territory {
+            0 => None,
+            _ => self.coverage_distribution.median(),
+        };
+    }
+}
+
+/// The thresholds that the aligned bases must pass to contribute to the
+/// usable depth.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UsableDepthThresholds {
+    /// The minimum mapping quality for a record to be counted.
+    pub min_mapping_quality: u8,
+
+    /// The minimum base quality for a base to be counted.
+    pub min_base_quality: u8,
+}
+
+/// The "usable" depth: the coverage from only the aligned bases that pass
+/// both a mapping quality and a base quality threshold, as reported alongside
+/// the raw coverage when `--usable-depth` is provided.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct UsableDepthMetrics {
+    /// The minimum mapping quality for a record to contribute to the usable
+    /// depth.
+    pub min_mapping_quality: u8,
+
+    /// The minimum base quality for a base to contribute to the usable depth.
+    pub min_base_quality: u8,
+
+    /// Hashmap containing the mean usable depth for each sequence in the
+    /// reference genome.
+    pub mean_coverage: BTreeMap<String, f64>,
+
+    /// Hashmap containing the mean usable depth for each bin within each
+    /// sequence, using the same bins as the raw coverage.
+    pub mean_coverage_per_bin: BTreeMap<String, Vec<CoverageBin>>,
 }
 
 /// Primary struct used to compile stats regarding coverage.
@@ -38,25 +294,288 @@ pub struct IgnoredMetrics {
 pub struct CoverageMetrics {
     /// Hashmap containing the mean coverage for each sequence in the reference
     /// genome.
-    pub mean_coverage: HashMap<String, f64>,
+    pub mean_coverage: BTreeMap<String, f64>,
 
     /// Hashmap containing the mean coverage for each bin within this sequence.
-    pub mean_coverage_per_bin: HashMap<String, Vec<f64>>,
+    /// The last bin of each sequence may be shorter than the bin size.
+    pub mean_coverage_per_bin: BTreeMap<String, Vec<CoverageBin>>,
 
     /// Hashmap containing the median coverage for each sequence in the
     /// reference genome.
-    pub median_coverage: HashMap<String, f64>,
+    pub median_coverage: BTreeMap<String, f64>,
 
     /// Hashmap containing the median over mean coverage for each sequence in
     /// the reference genome.
-    pub median_over_mean_coverage: HashMap<String, f64>,
+    pub median_over_mean_coverage: BTreeMap<String, f64>,
+
+    /// Hashmap containing the evenness metrics (Gini coefficient, evenness
+    /// score, and IQR over median coverage) for each sequence in the reference
+    /// genome.
+    #[serde(default)]
+    pub evenness: BTreeMap<String, EvennessMetrics>,
 
     /// Metrics recording various records or positions that were ignored during
     /// the analysis.
     pub ignored: IgnoredMetrics,
 
+    /// Mean coverage for each sequence computed both with and without records
+    /// marked as duplicate, regardless of whether duplicates are being counted.
+    #[serde(default)]
+    pub duplicates: DuplicateCoverageMetrics,
+
     /// Coverage distribution as a histogram per sequence.
-    pub coverage_distribution_per_sequence: HashMap<String, Histogram>,
+    pub coverage_distribution_per_sequence: BTreeMap<String, Histogram>,
+
+    /// Hashmap containing the mean coverage for each sequence in the reference
+    /// genome for each combination of read-in-pair and strand, if
+    /// `--stratify-by-strand` was provided.
+    #[serde(default)]
+    pub mean_coverage_by_strand: Option<BTreeMap<String, BTreeMap<String, f64>>>,
+
+    /// The usable depth (counting only the bases that pass the mapping and
+    /// base quality thresholds) for each sequence and bin, if `--usable-depth`
+    /// was provided.
+    #[serde(default)]
+    pub usable_depth: Option<UsableDepthMetrics>,
+
+    /// The coverage across every sequence analyzed.
+    #[serde(default)]
+    pub genome_wide: TerritoryCoverageMetrics,
+
+    /// The coverage across each of the territories requested with
+    /// `--coverage-territory` (or `--coverage-territory-intervals`).
+    #[serde(default)]
+    pub territories: BTreeMap<String, TerritoryCoverageMetrics>,
+
+    /// The number of positions within gaps that were masked from the coverage
+    /// statistics for each sequence, if gaps were masked (with `--mask-gaps`
+    /// or `--gap-intervals`).
+    #[serde(default)]
+    pub masked_gap_positions: BTreeMap<String, usize>,
+
+    /// The coverage of each viral (or other microbial) sequence in the
+    /// reference genome (e.g., `chrEBV`) that is present in the header. Unless
+    /// the reference genome is made up of only such sequences, these are
+    /// excluded from the genome-wide and territory coverage.
+    #[serde(default)]
+    pub viral: BTreeMap<String, ViralCoverageMetrics>,
+}
+
+impl CoverageMetrics {
+    /// Writes the mean coverage for each bin as a BED file (with the mean
+    /// coverage as the fourth column), sorted by sequence name and position.
+    pub fn write_bed(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let mut names: Vec<&String> = self.mean_coverage_per_bin.keys().collect();
+        names.sort();
+
+        for name in names {
+            for bin in &self.mean_coverage_per_bin[name] {
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}\t{}",
+                    bin.seqname,
+                    bin.start - 1,
+                    bin.end,
+                    bin.mean
+                )?;
+            }
+        }
+
+        writer.flush()
+    }
+}
+
+//=========//
+// Merging //
+//=========//
+
+impl Merge for IgnoredMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.nonsensical_records += other.nonsensical_records;
+        merge_disjoint(
+            &mut self.pileup_too_large_positions,
+            other.pileup_too_large_positions,
+            "pileup too large positions",
+        )
+    }
+}
+
+impl Merge for DuplicateCoverageMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        merge_disjoint(
+            &mut self.raw_mean_coverage,
+            other.raw_mean_coverage,
+            "raw mean coverage",
+        )?;
+        merge_disjoint(
+            &mut self.dedup_mean_coverage,
+            other.dedup_mean_coverage,
+            "deduplicated mean coverage",
+        )
+    }
+}
+
+impl Merge for TerritoryCoverageMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.territory += other.territory;
+        self.aligned_bases += other.aligned_bases;
+        self.coverage_distribution
+            .merge(other.coverage_distribution)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
+impl Merge for UsableDepthMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        if self.min_mapping_quality != other.min_mapping_quality
+            || self.min_base_quality != other.min_base_quality
+        {
+            bail!("usable depth computed with different quality thresholds cannot be merged");
+        }
+
+        merge_disjoint(
+            &mut self.mean_coverage,
+            other.mean_coverage,
+            "usable mean coverage",
+        )?;
+        merge_disjoint(
+            &mut self.mean_coverage_per_bin,
+            other.mean_coverage_per_bin,
+            "usable mean coverage per bin",
+        )
+    }
+}
+
+impl Merge for CoverageMetrics {
+    /// Merges the metrics. Coverage is computed in its entirety for each
+    /// sequence, so the results being merged must cover different sequences.
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        merge_disjoint(
+            &mut self.mean_coverage,
+            other.mean_coverage,
+            "mean coverage",
+        )?;
+        merge_disjoint(
+            &mut self.mean_coverage_per_bin,
+            other.mean_coverage_per_bin,
+            "mean coverage per bin",
+        )?;
+        merge_disjoint(
+            &mut self.median_coverage,
+            other.median_coverage,
+            "median coverage",
+        )?;
+        merge_disjoint(
+            &mut self.median_over_mean_coverage,
+            other.median_over_mean_coverage,
+            "median over mean coverage",
+        )?;
+        merge_disjoint(&mut self.evenness, other.evenness, "coverage evenness")?;
+        self.ignored.merge(other.ignored)?;
+        self.duplicates.merge(other.duplicates)?;
+        merge_disjoint(
+            &mut self.coverage_distribution_per_sequence,
+            other.coverage_distribution_per_sequence,
+            "coverage distribution",
+        )?;
+
+        self.usable_depth.merge(other.usable_depth)?;
+        self.genome_wide.merge(other.genome_wide)?;
+        Merge::merge(&mut self.territories, other.territories)?;
+        merge_disjoint(
+            &mut self.masked_gap_positions,
+            other.masked_gap_positions,
+            "masked gap positions",
+        )?;
+        merge_disjoint(&mut self.viral, other.viral, "viral coverage")?;
+
+        match (
+            &mut self.mean_coverage_by_strand,
+            other.mean_coverage_by_strand,
+        ) {
+            (Some(strata), Some(other)) => merge_disjoint(strata, other, "mean coverage by strand"),
+            (None, Some(other)) => {
+                self.mean_coverage_by_strand = Some(other);
+                Ok(())
+            }
+            (_, None) => Ok(()),
+        }
+    }
+}
+
+//================//
+// Coverage Facet //
+//================//
+
+/// Calculates the mean coverage within each bin of a sequence from the
+/// coverage at each position. Positions are one-based, so position zero is not
+/// part of a bin. The last bin ends at the end of the sequence, so it may be
+/// shorter than the bin size.
+fn coverage_bins(
+    positions: &Histogram,
+    seqname: &str,
+    length: usize,
+    bin_size: usize,
+) -> Vec<CoverageBin> {
+    let mut bins = Vec::with_capacity(length.div_ceil(bin_size));
+    let mut bin_start = 1;
+    let mut total_coverage_for_bin = 0;
+
+    for i in 1..=usize::min(length, positions.range_stop()) {
+        total_coverage_for_bin += positions.get(i);
+        let bin_length = i - bin_start + 1;
+        if bin_length == bin_size || i == length {
+            bins.push(CoverageBin {
+                seqname: seqname.to_string(),
+                start: bin_start,
+                end: i,
+                mean: total_coverage_for_bin as f64 / bin_length as f64,
+            });
+            bin_start = i + 1;
+            total_coverage_for_bin = 0;
+        }
+    }
+
+    bins
+}
+
+/// Gets the (one-based) reference positions of the aligned bases of a record
+/// that have at least the provided base quality. Deletions and skipped regions
+/// have no base quality and, as such, are never included.
+fn positions_passing_base_quality(record: &Record, start: usize, min: u8) -> Vec<usize> {
+    let quality_scores = record.quality_scores();
+    let quality_scores = quality_scores.as_ref();
+    let mut positions = Vec::new();
+    let mut reference_position = start;
+    let mut record_position = 0;
+
+    for op in record.cigar().iter() {
+        let kind = op.kind();
+        for _ in 0..op.len() {
+            if matches!(
+                kind,
+                Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch
+            ) && quality_scores
+                .get(record_position)
+                .is_some_and(|score| u8::from(*score) >= min)
+            {
+                positions.push(reference_position);
+            }
+
+            if consumes_reference(kind) {
+                reference_position += 1;
+            }
+
+            if consumes_sequence(kind) {
+                record_position += 1;
+            }
+        }
+    }
+
+    positions
 }
 
 /// Main struct for the Coverage quality control facet.
@@ -73,20 +592,110 @@ pub struct CoverageFacet {
     /// assembly.
     primary_assembly: Vec<Sequence>,
 
+    /// Struct for caching all of the viral (and other microbial) sequences.
+    viral_sequences: Vec<Sequence>,
+
     /// Size of bins within which to calculate mean coverage
     bin_size: NonZeroUsize,
+
+    /// The maximum coverage tracked in the coverage distributions.
+    coverage_cap: usize,
+
+    /// Whether records marked as duplicate should be counted.
+    count_duplicates: bool,
+
+    /// The minimum base quality for a base to be counted towards the
+    /// coverage, if `--min-base-quality` was provided.
+    min_base_quality: Option<u8>,
+
+    /// The thresholds for the usable depth, if `--usable-depth` was provided.
+    usable_depth: Option<UsableDepthThresholds>,
+
+    /// Data structure for tallying up the usable depth across position, which
+    /// is kept alongside [`Self::coverage_per_position`] when the usable depth
+    /// is being computed.
+    usable_depth_per_position: HashMap<String, Histogram>,
+
+    /// Number of aligned bases contributing to the usable depth for the
+    /// sequence currently being processed.
+    usable_aligned_bases: usize,
+
+    /// Number of aligned bases for the sequence currently being processed,
+    /// including duplicates.
+    raw_aligned_bases: usize,
+
+    /// Number of aligned bases for the sequence currently being processed,
+    /// excluding duplicates.
+    dedup_aligned_bases: usize,
+
+    /// If stratifying by strand, the number of aligned bases for the sequence
+    /// currently being processed for each combination of read-in-pair and
+    /// strand.
+    aligned_bases_by_strand: Option<HashMap<&'static str, usize>>,
+
+    /// The territories (in addition to the whole genome) over which the
+    /// coverage is summarized.
+    territories: Vec<Territory>,
+
+    /// The gaps on each sequence that are masked from the coverage
+    /// statistics, if gaps are being masked.
+    gaps: Option<RangesBySequence>,
+
+    /// Examples of the nonsensical records, if `--examples` was provided.
+    examples: Examples,
 }
 
 impl CoverageFacet {
     /// Creates a new [`CoverageFacet`].
-    pub fn new(reference_genome: Rc<Box<dyn ReferenceGenome>>, bin_size: NonZeroUsize) -> Self {
+    pub fn new(
+        reference_genome: Rc<Box<dyn ReferenceGenome>>,
+        bin_size: NonZeroUsize,
+        options: &FacetOptions,
+    ) -> Self {
         Self {
             coverage_per_position: HashMap::default(),
-            metrics: CoverageMetrics::default(),
-            primary_assembly: get_primary_assembly(reference_genome),
+            metrics: CoverageMetrics {
+                genome_wide: TerritoryCoverageMetrics::new(options.coverage_cap),
+                ..Default::default()
+            },
+            primary_assembly: get_primary_assembly(reference_genome.clone()),
+            viral_sequences: get_viral_sequences(reference_genome),
             bin_size,
+            coverage_cap: options.coverage_cap,
+            count_duplicates: options.count_duplicates,
+            min_base_quality: options.min_base_quality,
+            usable_depth: options.usable_depth,
+            usable_depth_per_position: HashMap::default(),
+            usable_aligned_bases: 0,
+            raw_aligned_bases: 0,
+            dedup_aligned_bases: 0,
+            aligned_bases_by_strand: options.stratify_by_strand.then(HashMap::default),
+            territories: Vec::new(),
+            gaps: None,
+            examples: Examples::new(options.examples),
         }
     }
+
+    /// Masks the gaps on each sequence from the means and medians (and the
+    /// coverage distributions), as the territory of Picard's
+    /// `CollectWgsMetrics` does.
+    pub fn with_gaps(mut self, gaps: RangesBySequence) -> Self {
+        self.gaps = Some(gaps);
+        self
+    }
+
+    /// Additionally summarizes the coverage over each of the territories.
+    pub fn with_territories(mut self, territories: Vec<Territory>) -> Self {
+        for territory in &territories {
+            self.metrics.territories.insert(
+                territory.name.clone(),
+                TerritoryCoverageMetrics::new(self.coverage_cap),
+            );
+        }
+
+        self.territories = territories;
+        self
+    }
 }
 
 impl SequenceBasedQualityControlFacet for CoverageFacet {
@@ -101,19 +710,22 @@ impl SequenceBasedQualityControlFacet for CoverageFacet {
     fn supports_sequence_name(&self, name: &str) -> bool {
         self.primary_assembly
             .iter()
+            .chain(self.viral_sequences.iter())
             .map(|s| s.name())
             .any(|x| x == name)
     }
 
     fn setup(&mut self, _: &Map<ReferenceSequence>) -> anyhow::Result<()> {
+        self.raw_aligned_bases = 0;
+        self.dedup_aligned_bases = 0;
+        self.usable_aligned_bases = 0;
+        if let Some(strata) = &mut self.aligned_bases_by_strand {
+            strata.clear();
+        }
         Ok(())
     }
 
-    fn process<'b>(
-        &mut self,
-        seq: &'b Map<ReferenceSequence>,
-        record: &Record,
-    ) -> anyhow::Result<()> {
+    fn process(&mut self, seq: &Map<ReferenceSequence>, record: &Record) -> anyhow::Result<()> {
         let h = self
             .coverage_per_position
             .entry(seq.name().to_string())
@@ -122,7 +734,66 @@ impl SequenceBasedQualityControlFacet for CoverageFacet {
         let record_start = usize::from(record.alignment_start().unwrap());
         let record_end = usize::from(record.alignment_end().unwrap());
 
-        for i in record_start..=record_end {
+        // (1) Tally up the aligned bases for the raw and deduplicated coverage,
+        // which are always reported. If a minimum base quality was provided,
+        // only the aligned bases of sufficient quality are counted; otherwise,
+        // every position spanned by the record is.
+        let covered = self
+            .min_base_quality
+            .map(|min| positions_passing_base_quality(record, record_start, min));
+        let aligned_bases = covered
+            .as_ref()
+            .map_or(record_end - record_start + 1, Vec::len);
+        let is_duplicate = record.flags().is_duplicate();
+
+        self.raw_aligned_bases += aligned_bases;
+        if !is_duplicate {
+            self.dedup_aligned_bases += aligned_bases;
+        }
+
+        // (2) If we aren't counting duplicates, then duplicates go no further.
+        if is_duplicate && !self.count_duplicates {
+            return Ok(());
+        }
+
+        if let Some(strata) = &mut self.aligned_bases_by_strand {
+            *strata.entry(strand_stratum(record.flags())).or_default() += aligned_bases;
+        }
+
+        // (3) If computing the usable depth, tally only the aligned bases that
+        // pass both the mapping quality and the base quality thresholds.
+        if let Some(thresholds) = self.usable_depth {
+            let passes_mapping_quality = record
+                .mapping_quality()
+                .is_some_and(|mapq| u8::from(mapq) >= thresholds.min_mapping_quality);
+
+            if passes_mapping_quality {
+                let usable = self
+                    .usable_depth_per_position
+                    .entry(seq.name().to_string())
+                    .or_insert_with(|| {
+                        Histogram::zero_based_with_capacity(usize::from(seq.length()))
+                    });
+
+                for i in positions_passing_base_quality(
+                    record,
+                    record_start,
+                    thresholds.min_base_quality,
+                ) {
+                    if usable.increment(i).is_ok() {
+                        self.usable_aligned_bases += 1;
+                    }
+                }
+            }
+        }
+
+        let positions: Box<dyn Iterator<Item = usize>> = match &covered {
+            Some(covered) => Box::new(covered.iter().copied()),
+            None => Box::new(record_start..=record_end),
+        };
+
+        let mut is_nonsensical = false;
+        for i in positions {
             if h.increment(i).is_err() {
                 error!(
                     "Record crosses the sequence boundaries in an expected way. \
@@ -136,14 +807,108 @@ impl SequenceBasedQualityControlFacet for CoverageFacet {
                     record.cigar()
                 );
                 self.metrics.ignored.nonsensical_records += 1;
+                is_nonsensical = true;
             }
         }
 
+        if is_nonsensical {
+            self.examples.record("nonsensical_record", record);
+        }
+
         Ok(())
     }
 
     fn teardown(&mut self, sequence: &Map<ReferenceSequence>) -> anyhow::Result<()> {
-        let positions = match self.coverage_per_position.get(sequence.name().as_str()) {
+        let name = sequence.name().as_str();
+        let length = usize::from(sequence.length());
+
+        // If gaps are being masked, the means and medians are computed over
+        // only the positions outside of the gaps. A sequence that is entirely
+        // masked is reported as having no coverage.
+        let unmasked = match &self.gaps {
+            Some(gaps) => complement(ranges_on(gaps, name), length),
+            None => vec![(1, length)],
+        };
+        let unmasked_length: usize = unmasked.iter().map(|(start, end)| end - start + 1).sum();
+        if self.gaps.is_some() {
+            self.metrics
+                .masked_gap_positions
+                .insert(name.to_string(), length - unmasked_length);
+        }
+        let sequence_length = usize::max(unmasked_length, 1) as f64;
+        self.metrics.duplicates.raw_mean_coverage.insert(
+            sequence.name().to_string(),
+            self.raw_aligned_bases as f64 / sequence_length,
+        );
+        self.metrics.duplicates.dedup_mean_coverage.insert(
+            sequence.name().to_string(),
+            self.dedup_aligned_bases as f64 / sequence_length,
+        );
+
+        if let Some(thresholds) = self.usable_depth {
+            self.metrics
+                .usable_depth
+                .get_or_insert_with(|| UsableDepthMetrics {
+                    min_mapping_quality: thresholds.min_mapping_quality,
+                    min_base_quality: thresholds.min_base_quality,
+                    ..Default::default()
+                })
+                .mean_coverage
+                .insert(
+                    sequence.name().to_string(),
+                    self.usable_aligned_bases as f64 / sequence_length,
+                );
+        }
+
+        if let Some(strata) = &self.aligned_bases_by_strand {
+            let mean_coverage = strata
+                .iter()
+                .map(|(stratum, bases)| (stratum.to_string(), *bases as f64 / sequence_length))
+                .collect();
+            self.metrics
+                .mean_coverage_by_strand
+                .get_or_insert_with(BTreeMap::default)
+                .insert(sequence.name().to_string(), mean_coverage);
+        }
+
+        let positions = self.coverage_per_position.get(name);
+
+        // Viral sequences are reported on their own and, unless there is no
+        // host genome, are excluded from the genome-wide coverage so that a
+        // highly covered virus does not inflate it.
+        let is_viral = self.viral_sequences.iter().any(|s| s.name() == name);
+        if is_viral {
+            let coverages = (1..=length).map(|i| positions.map_or(0, |p| p.get(i)));
+            let (aligned_bases, covered) = coverages.fold((0, 0), |(bases, covered), coverage| {
+                (bases + coverage, covered + usize::from(coverage > 0))
+            });
+            self.metrics.viral.insert(
+                name.to_string(),
+                ViralCoverageMetrics {
+                    length,
+                    aligned_bases,
+                    mean_coverage: aligned_bases as f64 / usize::max(length, 1) as f64,
+                    covered_pct: percentage(covered, length),
+                },
+            );
+        }
+
+        // Tally the coverage within the whole genome and within each of the
+        // territories. Every position of a sequence without any records has
+        // zero coverage.
+        if !is_viral || self.primary_assembly.is_empty() {
+            self.metrics.genome_wide.tally(positions, &unmasked);
+            for territory in &self.territories {
+                let ranges = intersect(&territory.ranges(name, length), &unmasked);
+                self.metrics
+                    .territories
+                    .get_mut(&territory.name)
+                    .unwrap()
+                    .tally(positions, &ranges);
+            }
+        }
+
+        let positions = match positions {
             Some(s) => s,
             // In the None case, no records were inserted for this sequence.
             // This may be because the file is a mini-SAM/BAM/CRAM. If that's
@@ -151,49 +916,65 @@ impl SequenceBasedQualityControlFacet for CoverageFacet {
             None => return Ok(()),
         };
 
-        let mut coverages = Histogram::zero_based_with_capacity(1024);
+        let mut coverages = Histogram::zero_based_with_capacity(self.coverage_cap);
         let mut ignored = 0;
 
-        let mut total_coverage_for_bin = 0;
-        let coverage_per_bin_vec = self
-            .metrics
-            .mean_coverage_per_bin
-            .entry(sequence.name().to_string())
-            .or_default();
-
-        for i in positions.range_start()..=positions.range_stop() {
-            let coverage_at_position = positions.get(i);
+        let bin_size = usize::from(self.bin_size);
 
-            // (a) increment the coverage histogram for the coverage found at
-            // this position.
-            if coverages.increment(coverage_at_position).is_err() {
+        // (a) increment the coverage histogram for the coverage found at each
+        // position (outside of the gaps, if they are being masked).
+        let counted = match self.gaps {
+            Some(_) => unmasked,
+            None => vec![(positions.range_start(), positions.range_stop())],
+        };
+        for i in counted.into_iter().flat_map(|(start, end)| start..=end) {
+            if coverages.increment(positions.get(i)).is_err() {
                 ignored += 1;
             }
+        }
 
-            // (b) calculate the coverage for the current bin we are within.
-            total_coverage_for_bin += coverage_at_position;
-            if i % self.bin_size == 0 {
-                let mean = total_coverage_for_bin as f64 / usize::from(self.bin_size) as f64;
-                coverage_per_bin_vec.push(mean);
-                total_coverage_for_bin = 0;
-            }
+        // (b) calculate the mean coverage for each bin (for both the raw
+        // coverage and, if it is being computed, the usable depth).
+        self.metrics.mean_coverage_per_bin.insert(
+            sequence.name().to_string(),
+            coverage_bins(positions, sequence.name().as_str(), length, bin_size),
+        );
+
+        if let Some(usable_depth) = &mut self.metrics.usable_depth {
+            let bins = match self
+                .usable_depth_per_position
+                .remove(sequence.name().as_str())
+            {
+                Some(usable) => coverage_bins(&usable, sequence.name().as_str(), length, bin_size),
+                // No records passed the thresholds for this sequence.
+                None => coverage_bins(
+                    &Histogram::zero_based_with_capacity(length),
+                    sequence.name().as_str(),
+                    length,
+                    bin_size,
+                ),
+            };
+            usable_depth
+                .mean_coverage_per_bin
+                .insert(sequence.name().to_string(), bins);
         }
 
-        // If the position is not divisible by the bin size, then we still need
-        // to add the last bit of the mean coverage bins to the vec.
-        let modulo = positions.range_stop() % self.bin_size;
-        if modulo != 0 {
-            let mean = total_coverage_for_bin as f64 / modulo as f64;
-            println!(
-                "Modulo: {}, Total: {}, Mean: {}",
-                modulo, total_coverage_for_bin, mean
+        if ignored > 0 {
+            warn!(
+                "{} positions on {} had coverage greater than the coverage cap ({}) \
+                and were excluded from the coverage distribution. Consider raising \
+                `--coverage-cap`.",
+                ignored,
+                sequence.name(),
+                self.coverage_cap
             );
-            coverage_per_bin_vec.push(mean);
         }
 
-        let mean = coverages.mean();
+        // Every position of the sequence is within the distribution, so it is
+        // never empty.
+        let mean = coverages.mean().unwrap();
         let median = coverages.median().unwrap();
-        let median_over_mean = median / mean;
+        let median_over_mean = if mean == 0.0 { 0.0 } else { median / mean };
 
         // Removed to save memory.
         self.coverage_per_position.remove(sequence.name().as_str());
@@ -208,6 +989,10 @@ impl SequenceBasedQualityControlFacet for CoverageFacet {
         self.metrics
             .median_over_mean_coverage
             .insert(sequence.name().to_string(), median_over_mean);
+        self.metrics.evenness.insert(
+            sequence.name().to_string(),
+            EvennessMetrics::from_distribution(&coverages),
+        );
         self.metrics
             .coverage_distribution_per_sequence
             .insert(sequence.name().to_string(), coverages);
@@ -220,6 +1005,362 @@ impl SequenceBasedQualityControlFacet for CoverageFacet {
     }
 
     fn aggregate(&mut self, results: &mut results::Results) {
+        self.metrics.genome_wide.summarize();
+        for territory in self.metrics.territories.values_mut() {
+            territory.summarize();
+        }
         results.coverage = Some(self.metrics.clone());
     }
+
+    fn examples(&self) -> Vec<Example> {
+        self.examples.examples().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::record::{Flags, MappingQuality};
+
+    use crate::utils::genome::ncbi::grch38_no_alt::GRCh38NoAltAnalysisSet;
+
+    use super::{territories::TerritoryDefinition, *};
+
+    #[test]
+    pub fn it_computes_coverage_bins_keyed_by_coordinates() -> anyhow::Result<()> {
+        let reference_genome: Rc<Box<dyn ReferenceGenome>> =
+            Rc::new(Box::new(GRCh38NoAltAnalysisSet));
+        let mut facet = CoverageFacet::new(
+            reference_genome,
+            NonZeroUsize::new(4).unwrap(),
+            &FacetOptions::default(),
+        );
+        let sequence = Map::<ReferenceSequence>::new("chr1".parse()?, 10)?;
+
+        facet.setup(&sequence)?;
+        let record = Record::builder()
+            .set_flags(Flags::empty())
+            .set_alignment_start(noodles::core::Position::try_from(3)?)
+            .set_cigar("8M".parse()?)
+            .set_sequence("ACGTACGT".parse()?)
+            .build();
+        facet.process(&sequence, &record)?;
+        facet.teardown(&sequence)?;
+
+        let bins = &facet.metrics.mean_coverage_per_bin["chr1"];
+        let coordinates: Vec<_> = bins.iter().map(|b| (b.start, b.end, b.mean)).collect();
+        assert_eq!(coordinates, vec![(1, 4, 0.5), (5, 8, 1.0), (9, 10, 1.0)]);
+        assert!(bins.iter().all(|b| b.seqname == "chr1"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_combines_sequences_into_genome_wide_coverage() -> anyhow::Result<()> {
+        let reference_genome: Rc<Box<dyn ReferenceGenome>> =
+            Rc::new(Box::new(GRCh38NoAltAnalysisSet));
+        let mut facet = CoverageFacet::new(
+            reference_genome,
+            NonZeroUsize::new(10).unwrap(),
+            &FacetOptions::default(),
+        );
+        let chr1 = Map::<ReferenceSequence>::new("chr1".parse()?, 10)?;
+        let chr2 = Map::<ReferenceSequence>::new("chr2".parse()?, 30)?;
+
+        facet.setup(&chr1)?;
+        for start in [1, 3] {
+            let record = Record::builder()
+                .set_flags(Flags::empty())
+                .set_alignment_start(noodles::core::Position::try_from(start)?)
+                .set_cigar("8M".parse()?)
+                .set_sequence("ACGTACGT".parse()?)
+                .build();
+            facet.process(&chr1, &record)?;
+        }
+        facet.teardown(&chr1)?;
+
+        // No records aligned to the second sequence.
+        facet.setup(&chr2)?;
+        facet.teardown(&chr2)?;
+
+        let mut results = results::Results::default();
+        facet.aggregate(&mut results);
+        let genome_wide = results.coverage.unwrap().genome_wide;
+
+        assert_eq!(genome_wide.territory, 40);
+        assert_eq!(genome_wide.aligned_bases, 16);
+        assert_eq!(genome_wide.mean_coverage, Some(0.4));
+        assert_eq!(genome_wide.median_coverage, Some(0.0));
+        assert_eq!(genome_wide.coverage_distribution.get(0), 30);
+        assert_eq!(genome_wide.coverage_distribution.get(1), 4);
+        assert_eq!(genome_wide.coverage_distribution.get(2), 6);
+
+        // Merging the results for another portion of the genome recomputes the
+        // summaries.
+        let mut merged = genome_wide.clone();
+        merged.merge(genome_wide)?;
+        assert_eq!(merged.territory, 80);
+        assert_eq!(merged.mean_coverage, Some(0.4));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_reports_viral_sequences_apart_from_the_genome() -> anyhow::Result<()> {
+        let reference_genome: Rc<Box<dyn ReferenceGenome>> =
+            Rc::new(Box::new(GRCh38NoAltAnalysisSet));
+        let mut facet = CoverageFacet::new(
+            reference_genome,
+            NonZeroUsize::new(10).unwrap(),
+            &FacetOptions::default(),
+        );
+        let chr1 = Map::<ReferenceSequence>::new("chr1".parse()?, 10)?;
+        let ebv = Map::<ReferenceSequence>::new("chrEBV".parse()?, 20)?;
+        assert!(facet.supports_sequence_name("chrEBV"));
+
+        facet.setup(&chr1)?;
+        facet.teardown(&chr1)?;
+
+        facet.setup(&ebv)?;
+        let record = Record::builder()
+            .set_flags(Flags::empty())
+            .set_alignment_start(noodles::core::Position::try_from(1)?)
+            .set_cigar("5M".parse()?)
+            .set_sequence("ACGTA".parse()?)
+            .build();
+        facet.process(&ebv, &record)?;
+        facet.teardown(&ebv)?;
+
+        let mut results = results::Results::default();
+        facet.aggregate(&mut results);
+        let coverage = results.coverage.unwrap();
+
+        assert_eq!(coverage.genome_wide.territory, 10);
+        assert_eq!(coverage.genome_wide.aligned_bases, 0);
+
+        let viral = &coverage.viral["chrEBV"];
+        assert_eq!((viral.length, viral.aligned_bases), (20, 5));
+        assert_eq!(viral.mean_coverage, 0.25);
+        assert_eq!(viral.covered_pct, Some(25.0));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_summarizes_coverage_over_territories() -> anyhow::Result<()> {
+        let reference_genome: Rc<Box<dyn ReferenceGenome>> =
+            Rc::new(Box::new(GRCh38NoAltAnalysisSet));
+        let autosomes = TerritoryDefinition::Autosomes.resolve(&GRCh38NoAltAnalysisSet, None)?;
+        let mut facet = CoverageFacet::new(
+            reference_genome,
+            NonZeroUsize::new(10).unwrap(),
+            &FacetOptions::default(),
+        )
+        .with_territories(vec![autosomes]);
+        let chr1 = Map::<ReferenceSequence>::new("chr1".parse()?, 10)?;
+        let chr_x = Map::<ReferenceSequence>::new("chrX".parse()?, 30)?;
+
+        facet.setup(&chr1)?;
+        let record = Record::builder()
+            .set_flags(Flags::empty())
+            .set_alignment_start(noodles::core::Position::try_from(1)?)
+            .set_cigar("8M".parse()?)
+            .set_sequence("ACGTACGT".parse()?)
+            .build();
+        facet.process(&chr1, &record)?;
+        facet.process(&chr1, &record)?;
+        facet.teardown(&chr1)?;
+
+        // The sex chromosomes are only part of the genome-wide summary.
+        facet.setup(&chr_x)?;
+        facet.teardown(&chr_x)?;
+
+        let mut results = results::Results::default();
+        facet.aggregate(&mut results);
+        let coverage = results.coverage.unwrap();
+
+        assert_eq!(coverage.genome_wide.territory, 40);
+        assert_eq!(coverage.genome_wide.mean_coverage, Some(0.4));
+
+        let autosomes = &coverage.territories["autosomes"];
+        assert_eq!(autosomes.territory, 10);
+        assert_eq!(autosomes.aligned_bases, 16);
+        assert_eq!(autosomes.mean_coverage, Some(1.6));
+        assert_eq!(autosomes.median_coverage, Some(2.0));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_masks_gaps_from_the_coverage_statistics() -> anyhow::Result<()> {
+        let reference_genome: Rc<Box<dyn ReferenceGenome>> =
+            Rc::new(Box::new(GRCh38NoAltAnalysisSet));
+        let mut facet = CoverageFacet::new(
+            reference_genome,
+            NonZeroUsize::new(10).unwrap(),
+            &FacetOptions::default(),
+        )
+        .with_gaps(HashMap::from([(String::from("chr1"), vec![(7, 10)])]));
+        let sequence = Map::<ReferenceSequence>::new("chr1".parse()?, 10)?;
+
+        facet.setup(&sequence)?;
+        let record = Record::builder()
+            .set_flags(Flags::empty())
+            .set_alignment_start(noodles::core::Position::try_from(1)?)
+            .set_cigar("6M".parse()?)
+            .set_sequence("ACGTAC".parse()?)
+            .build();
+        facet.process(&sequence, &record)?;
+        facet.teardown(&sequence)?;
+
+        let mut results = results::Results::default();
+        facet.aggregate(&mut results);
+        let coverage = results.coverage.unwrap();
+
+        // Only the six positions outside of the gap are counted.
+        assert_eq!(coverage.masked_gap_positions["chr1"], 4);
+        assert_eq!(coverage.mean_coverage["chr1"], 1.0);
+        assert_eq!(coverage.median_coverage["chr1"], 1.0);
+        assert_eq!(coverage.duplicates.raw_mean_coverage["chr1"], 1.0);
+        assert_eq!(coverage.genome_wide.territory, 6);
+        assert_eq!(coverage.genome_wide.mean_coverage, Some(1.0));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_reports_raw_and_deduplicated_coverage_together() -> anyhow::Result<()> {
+        let reference_genome: Rc<Box<dyn ReferenceGenome>> =
+            Rc::new(Box::new(GRCh38NoAltAnalysisSet));
+        let options = FacetOptions {
+            count_duplicates: false,
+            ..Default::default()
+        };
+        let mut facet =
+            CoverageFacet::new(reference_genome, NonZeroUsize::new(10).unwrap(), &options);
+        let sequence = Map::<ReferenceSequence>::new("chr1".parse()?, 10)?;
+
+        facet.setup(&sequence)?;
+        for flags in [Flags::empty(), Flags::DUPLICATE] {
+            let record = Record::builder()
+                .set_flags(flags)
+                .set_alignment_start(noodles::core::Position::try_from(1)?)
+                .set_cigar("5M".parse()?)
+                .set_sequence("ACGTA".parse()?)
+                .build();
+            facet.process(&sequence, &record)?;
+        }
+        facet.teardown(&sequence)?;
+
+        // Whether or not duplicates are counted, both the raw and the
+        // deduplicated coverage are reported.
+        let duplicates = &facet.metrics.duplicates;
+        assert_eq!(duplicates.raw_mean_coverage["chr1"], 1.0);
+        assert_eq!(duplicates.dedup_mean_coverage["chr1"], 0.5);
+        assert_eq!(facet.metrics.mean_coverage_per_bin["chr1"][0].mean, 0.5);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_excludes_bases_below_the_minimum_base_quality() -> anyhow::Result<()> {
+        let reference_genome: Rc<Box<dyn ReferenceGenome>> =
+            Rc::new(Box::new(GRCh38NoAltAnalysisSet));
+        let options = FacetOptions {
+            min_base_quality: Some(20),
+            ..Default::default()
+        };
+        let mut facet =
+            CoverageFacet::new(reference_genome, NonZeroUsize::new(10).unwrap(), &options);
+        let sequence = Map::<ReferenceSequence>::new("chr1".parse()?, 10)?;
+
+        facet.setup(&sequence)?;
+        let record = Record::builder()
+            .set_flags(Flags::empty())
+            .set_alignment_start(noodles::core::Position::try_from(1)?)
+            .set_cigar("3M2D3M".parse()?)
+            .set_sequence("ACGTAC".parse()?)
+            .set_quality_scores("II#III".parse()?)
+            .build();
+        facet.process(&sequence, &record)?;
+        facet.teardown(&sequence)?;
+
+        // Positions 1-2 and 6-8 are covered: position 3 has a low base quality
+        // and positions 4-5 are deleted.
+        assert_eq!(facet.metrics.mean_coverage_per_bin["chr1"][0].mean, 0.5);
+        assert_eq!(facet.metrics.duplicates.raw_mean_coverage["chr1"], 0.5);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_reports_the_usable_depth_alongside_the_raw_coverage() -> anyhow::Result<()> {
+        let reference_genome: Rc<Box<dyn ReferenceGenome>> =
+            Rc::new(Box::new(GRCh38NoAltAnalysisSet));
+        let options = FacetOptions {
+            usable_depth: Some(UsableDepthThresholds {
+                min_mapping_quality: 20,
+                min_base_quality: 20,
+            }),
+            ..Default::default()
+        };
+        let mut facet =
+            CoverageFacet::new(reference_genome, NonZeroUsize::new(5).unwrap(), &options);
+        let sequence = Map::<ReferenceSequence>::new("chr1".parse()?, 10)?;
+
+        let record = |mapq: u8, quality_scores: &str| -> anyhow::Result<Record> {
+            Ok(Record::builder()
+                .set_flags(Flags::empty())
+                .set_alignment_start(noodles::core::Position::try_from(1)?)
+                .set_cigar("5M".parse()?)
+                .set_mapping_quality(MappingQuality::try_from(mapq)?)
+                .set_sequence("ACGTA".parse()?)
+                .set_quality_scores(quality_scores.parse()?)
+                .build())
+        };
+
+        facet.setup(&sequence)?;
+        facet.process(&sequence, &record(60, "III##")?)?;
+        facet.process(&sequence, &record(10, "IIIII")?)?;
+        facet.teardown(&sequence)?;
+
+        let raw = &facet.metrics.mean_coverage_per_bin["chr1"];
+        assert_eq!(raw[0].mean, 2.0);
+
+        let usable = facet.metrics.usable_depth.as_ref().unwrap();
+        assert_eq!(usable.mean_coverage["chr1"], 0.3);
+        let bins: Vec<_> = usable.mean_coverage_per_bin["chr1"]
+            .iter()
+            .map(|b| (b.start, b.end, b.mean))
+            .collect();
+        assert_eq!(bins, vec![(1, 5, 0.6), (6, 10, 0.0)]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_computes_evenness_from_the_coverage_distribution() {
+        let distribution = |coverages: &[usize]| {
+            let mut histogram = Histogram::zero_based_with_capacity(16);
+            for coverage in coverages {
+                histogram.increment(*coverage).unwrap();
+            }
+            histogram
+        };
+
+        // Perfectly even coverage.
+        let even = EvennessMetrics::from_distribution(&distribution(&[4, 4, 4, 4]));
+        assert_eq!(even.gini_coefficient, Some(0.0));
+        assert_eq!(even.evenness_score, Some(1.0));
+        assert_eq!(even.iqr_over_median, Some(0.0));
+
+        // All of the coverage at a single position.
+        let uneven = EvennessMetrics::from_distribution(&distribution(&[0, 0, 0, 8]));
+        assert_eq!(uneven.gini_coefficient, Some(0.75));
+        assert_eq!(uneven.evenness_score, Some(0.25));
+        assert_eq!(uneven.iqr_over_median, None);
+
+        // No coverage at all.
+        let empty = EvennessMetrics::from_distribution(&distribution(&[0, 0]));
+        assert_eq!(empty, EvennessMetrics::default());
+    }
 }
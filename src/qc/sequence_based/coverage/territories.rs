@@ -0,0 +1,378 @@
+//! Territories over which the Coverage facet summarizes coverage.
+//!
+//! The genome-wide summary includes every sequence in the primary assembly,
+//! which is not always what is wanted: chrY (for female samples) and long runs
+//! of `N`s skew the mean coverage, and targeted assays are only interested in
+//! their targets. A territory restricts the summary to a set of positions:
+//!
+//! * `autosomes`: the autosomes of the reference genome.
+//! * `primary-assembly-without-gaps`: the primary assembly, excluding the gaps
+//!   (runs of `N`) in the reference FASTA.
+//! * The intervals within a BED file or interval list.
+//!
+//! Separately, the gaps themselves can be masked from every coverage
+//! statistic (see [`GapSource`]), matching the genome territory of Picard's
+//! `CollectWgsMetrics`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::bail;
+
+use crate::utils::{formats, genome::ReferenceGenome, intervals::read_intervals_from_path};
+
+/// A one-based, inclusive range of positions on a sequence.
+pub type Range = (usize, usize);
+
+/// The sorted, non-overlapping ranges on each sequence.
+pub type RangesBySequence = HashMap<String, Vec<Range>>;
+
+//============//
+// Definition //
+//============//
+
+/// A territory as requested on the command line, before it is resolved
+/// against the reference genome (and, if needed, the reference FASTA).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TerritoryDefinition {
+    /// The autosomes of the reference genome.
+    Autosomes,
+
+    /// The primary assembly of the reference genome, excluding the gaps (runs
+    /// of `N`) in the reference FASTA.
+    PrimaryAssemblyWithoutGaps,
+
+    /// The intervals within a BED file or interval list.
+    Intervals(PathBuf),
+}
+
+impl FromStr for TerritoryDefinition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "autosomes" => Ok(Self::Autosomes),
+            "primary-assembly-without-gaps" => Ok(Self::PrimaryAssemblyWithoutGaps),
+            _ => bail!("invalid coverage territory: {}", s),
+        }
+    }
+}
+
+impl TerritoryDefinition {
+    /// The name the territory is reported under. Intervals are reported under
+    /// the name of the file they were read from.
+    pub fn name(&self) -> String {
+        match self {
+            Self::Autosomes => String::from("autosomes"),
+            Self::PrimaryAssemblyWithoutGaps => String::from("primary_assembly_without_gaps"),
+            Self::Intervals(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.display().to_string()),
+        }
+    }
+
+    /// Resolves the territory into the ranges it spans on each sequence.
+    pub fn resolve(
+        &self,
+        reference_genome: &dyn ReferenceGenome,
+        reference_fasta: Option<&Path>,
+    ) -> anyhow::Result<Territory> {
+        let spans = match self {
+            Self::Autosomes => match reference_genome.autosomes() {
+                Some(autosomes) => {
+                    Spans::Sequences(autosomes.iter().map(|s| s.name().to_string()).collect())
+                }
+                None => bail!(
+                    "The {} reference genome does not define any autosomes.",
+                    reference_genome.name()
+                ),
+            },
+            Self::PrimaryAssemblyWithoutGaps => match reference_fasta {
+                Some(path) => Spans::Excluding(find_gaps(path)?),
+                None => bail!(
+                    "The primary-assembly-without-gaps coverage territory requires \
+                    --reference-fasta."
+                ),
+            },
+            Self::Intervals(path) => Spans::Intervals(read_ranges(path)?),
+        };
+
+        Ok(Territory {
+            name: self.name(),
+            spans,
+        })
+    }
+}
+
+//======//
+// Gaps //
+//======//
+
+/// Where the gaps that are masked from the coverage statistics are read from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GapSource {
+    /// The runs of `N` within the reference FASTA.
+    ReferenceFasta,
+
+    /// The intervals within a BED file or interval list (e.g., the gap table
+    /// published alongside the reference genome).
+    Intervals(PathBuf),
+}
+
+impl GapSource {
+    /// Reads the gaps on each sequence.
+    pub fn resolve(&self, reference_fasta: Option<&Path>) -> anyhow::Result<RangesBySequence> {
+        match self {
+            Self::ReferenceFasta => match reference_fasta {
+                Some(path) => find_gaps(path),
+                None => bail!("--mask-gaps requires --reference-fasta (or --gap-intervals)."),
+            },
+            Self::Intervals(path) => read_ranges(path),
+        }
+    }
+}
+
+//===========//
+// Territory //
+//===========//
+
+/// How a territory spans each sequence.
+#[derive(Debug)]
+enum Spans {
+    /// The entirety of each of the sequences.
+    Sequences(HashSet<String>),
+
+    /// The entirety of every sequence analyzed (i.e., the primary assembly),
+    /// except for the ranges on each sequence (e.g., the gaps).
+    Excluding(RangesBySequence),
+
+    /// The ranges on each sequence.
+    Intervals(RangesBySequence),
+}
+
+/// A territory, resolved into the ranges it spans on each sequence.
+#[derive(Debug)]
+pub struct Territory {
+    /// The name the territory is reported under.
+    pub name: String,
+
+    /// How the territory spans each sequence.
+    spans: Spans,
+}
+
+impl Territory {
+    /// Creates a territory that spans the ranges on each sequence.
+    pub fn within(name: &str, ranges: RangesBySequence) -> Self {
+        Self {
+            name: name.to_string(),
+            spans: Spans::Intervals(ranges),
+        }
+    }
+
+    /// Creates a territory that spans every sequence analyzed, except for the
+    /// ranges on each sequence.
+    pub fn outside(name: &str, ranges: RangesBySequence) -> Self {
+        Self {
+            name: name.to_string(),
+            spans: Spans::Excluding(ranges),
+        }
+    }
+
+    /// Gets the sorted, non-overlapping ranges the territory spans on a
+    /// sequence of the provided length. Ranges beyond the end of the sequence
+    /// are clipped.
+    pub fn ranges(&self, sequence: &str, length: usize) -> Vec<Range> {
+        match &self.spans {
+            Spans::Sequences(sequences) => match sequences.contains(sequence) {
+                true if length > 0 => vec![(1, length)],
+                _ => Vec::new(),
+            },
+            Spans::Excluding(excluded) => complement(ranges_on(excluded, sequence), length),
+            Spans::Intervals(intervals) => clip(ranges_on(intervals, sequence), length),
+        }
+    }
+}
+
+//=========//
+// Helpers //
+//=========//
+
+/// Sorts the ranges and merges any that overlap (or are adjacent).
+pub fn merge_ranges(mut ranges: Vec<Range>) -> Vec<Range> {
+    ranges.sort_unstable();
+
+    let mut merged: Vec<Range> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= *last_end + 1 => *last_end = usize::max(*last_end, end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+/// Gets the ranges on a sequence (which are empty if the sequence has none).
+pub fn ranges_on<'a>(ranges: &'a RangesBySequence, sequence: &str) -> &'a [Range] {
+    ranges.get(sequence).map_or(&[], Vec::as_slice)
+}
+
+/// Clips sorted, non-overlapping ranges to a sequence of the provided length.
+pub fn clip(ranges: &[Range], length: usize) -> Vec<Range> {
+    ranges
+        .iter()
+        .filter(|(start, _)| *start <= length)
+        .map(|(start, end)| (*start, usize::min(*end, length)))
+        .collect()
+}
+
+/// Gets the positions of a sequence of the provided length that are not
+/// within any of the sorted, non-overlapping ranges.
+pub fn complement(ranges: &[Range], length: usize) -> Vec<Range> {
+    let mut complement = Vec::new();
+    let mut start = 1;
+
+    for (range_start, range_end) in clip(ranges, length) {
+        if range_start > start {
+            complement.push((start, range_start - 1));
+        }
+        start = range_end + 1;
+    }
+
+    if start <= length {
+        complement.push((start, length));
+    }
+
+    complement
+}
+
+/// Gets the positions that are within both sets of sorted, non-overlapping
+/// ranges.
+pub fn intersect(a: &[Range], b: &[Range]) -> Vec<Range> {
+    let mut intersection = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() && j < b.len() {
+        let start = usize::max(a[i].0, b[j].0);
+        let end = usize::min(a[i].1, b[j].1);
+        if start <= end {
+            intersection.push((start, end));
+        }
+
+        match a[i].1 < b[j].1 {
+            true => i += 1,
+            false => j += 1,
+        }
+    }
+
+    intersection
+}
+
+/// Reads the intervals from a BED file or interval list into sorted,
+/// non-overlapping ranges on each sequence.
+pub fn read_ranges(path: &Path) -> anyhow::Result<RangesBySequence> {
+    let intervals = read_intervals_from_path(path, &[])?;
+
+    Ok(intervals
+        .sequence_names()
+        .map(|name| {
+            let ranges = intervals
+                .get(name)
+                .unwrap()
+                .iter()
+                .map(|interval| (interval.start, interval.stop - 1))
+                .collect();
+            (name.to_string(), merge_ranges(ranges))
+        })
+        .collect())
+}
+
+/// Finds the gaps (runs of `N`) within each sequence of a reference FASTA.
+pub fn find_gaps(reference_fasta: &Path) -> anyhow::Result<RangesBySequence> {
+    let mut reader = formats::fasta::open(reference_fasta)?;
+    let mut gaps = HashMap::new();
+
+    for result in reader.records() {
+        let record = result?;
+        gaps.insert(
+            record.name().to_string(),
+            gaps_in_sequence(record.sequence().as_ref()),
+        );
+    }
+
+    Ok(gaps)
+}
+
+/// Finds the (one-based, inclusive) ranges of `N` (or `n`) within a sequence.
+fn gaps_in_sequence(sequence: &[u8]) -> Vec<Range> {
+    let mut gaps = Vec::new();
+    let mut start = None;
+
+    for (i, base) in sequence.iter().enumerate() {
+        match (base.eq_ignore_ascii_case(&b'N'), start) {
+            (true, None) => start = Some(i + 1),
+            (false, Some(s)) => {
+                gaps.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(s) = start {
+        gaps.push((s, sequence.len()));
+    }
+
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_resolves_the_ranges_of_each_kind_of_territory() {
+        let autosomes = Territory {
+            name: String::from("autosomes"),
+            spans: Spans::Sequences(HashSet::from([String::from("chr1")])),
+        };
+        assert_eq!(autosomes.ranges("chr1", 100), vec![(1, 100)]);
+        assert_eq!(autosomes.ranges("chrY", 100), vec![]);
+
+        let without_gaps = Territory {
+            name: String::from("primary_assembly_without_gaps"),
+            spans: Spans::Excluding(HashMap::from([(
+                String::from("chr1"),
+                gaps_in_sequence(b"NNACGTnnACGTN"),
+            )])),
+        };
+        assert_eq!(without_gaps.ranges("chr1", 13), vec![(3, 6), (9, 12)]);
+        assert_eq!(without_gaps.ranges("chr2", 10), vec![(1, 10)]);
+
+        let intervals = Territory {
+            name: String::from("targets.bed"),
+            spans: Spans::Intervals(HashMap::from([(
+                String::from("chr1"),
+                merge_ranges(vec![(50, 60), (1, 10), (11, 20), (55, 200)]),
+            )])),
+        };
+        assert_eq!(intervals.ranges("chr1", 100), vec![(1, 20), (50, 100)]);
+        assert_eq!(intervals.ranges("chr2", 100), vec![]);
+    }
+
+    #[test]
+    pub fn it_intersects_ranges() {
+        let a = vec![(1, 10), (20, 30), (40, 50)];
+        let b = vec![(5, 25), (28, 45)];
+        assert_eq!(
+            intersect(&a, &b),
+            vec![(5, 10), (20, 25), (28, 30), (40, 45)]
+        );
+        assert_eq!(intersect(&a, &[]), vec![]);
+        assert_eq!(complement(&a, 45), vec![(11, 19), (31, 39)]);
+    }
+}
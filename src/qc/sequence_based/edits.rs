@@ -1,6 +1,11 @@
 //! Functionality related to the Edits quality control facet.
 
-use std::{fs::File, io::BufReader, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::BufReader,
+    path::PathBuf,
+};
 
 use anyhow::{bail, Context};
 use fasta::record::Sequence;
@@ -12,8 +17,16 @@ use noodles::sam::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    qc::{results, ComputationalLoad, SequenceBasedQualityControlFacet},
-    utils::{alignment::ReferenceRecordStepThrough, formats, histogram::Histogram},
+    qc::{
+        results, strata::strand_stratum, ComputationalLoad, FacetOptions,
+        SequenceBasedQualityControlFacet,
+    },
+    utils::{
+        alignment::{EditCounts, ReferenceRecordStepThrough, Substitution},
+        formats::{self, vcf::Sites},
+        histogram::Histogram,
+        merge::Merge,
+    },
 };
 
 //=========//
@@ -23,27 +36,241 @@ use crate::{
 /// Summary statistics for the Edits quality control facet.
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct EditMetricsSummary {
-    /// Mean number of edits for the read ones in the file.
-    pub mean_edits_read_one: f64,
+    /// Mean number of edits for the read ones in the file, if any read ones
+    /// were observed.
+    pub mean_edits_read_one: Option<f64>,
+
+    /// Mean number of edits for the read twos in the file, if any read twos
+    /// were observed.
+    pub mean_edits_read_two: Option<f64>,
+
+    /// Substituted bases per aligned base.
+    #[serde(default)]
+    pub substitution_rate_per_base: Option<f64>,
+
+    /// Inserted bases per aligned base.
+    #[serde(default)]
+    pub insertion_rate_per_base: Option<f64>,
+
+    /// Deleted bases per aligned base.
+    #[serde(default)]
+    pub deletion_rate_per_base: Option<f64>,
+
+    /// Mean number of substituted bases per read.
+    #[serde(default)]
+    pub substitutions_per_read: Option<f64>,
+
+    /// Mean number of inserted bases per read.
+    #[serde(default)]
+    pub insertions_per_read: Option<f64>,
+
+    /// Mean number of deleted bases per read.
+    #[serde(default)]
+    pub deletions_per_read: Option<f64>,
+
+    /// Substituted bases per aligned base for each combination of read-in-pair
+    /// and strand, if `--stratify-by-strand` was provided.
+    #[serde(default)]
+    pub substitution_rate_per_base_by_strand: Option<BTreeMap<String, f64>>,
+
+    /// The transition/transversion ratio of the substitutions at known sites,
+    /// if `--sites-vcf` was provided.
+    #[serde(default)]
+    pub ti_tv_at_known_sites: Option<f64>,
+
+    /// The transition/transversion ratio of the substitutions outside of known
+    /// sites, if `--sites-vcf` was provided.
+    #[serde(default)]
+    pub ti_tv_elsewhere: Option<f64>,
+}
+
+/// Totals for each type of edit across all of the reads that were reviewed.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EditTypeMetrics {
+    /// The number of reads that were reviewed.
+    pub reads: usize,
+
+    /// The number of bases aligned to the reference (`M`, `=`, and `X`).
+    pub aligned_bases: usize,
+
+    /// The number of aligned bases that differ from the reference.
+    pub substitutions: usize,
+
+    /// The number of bases inserted relative to the reference.
+    pub insertions: usize,
+
+    /// The number of reference bases deleted from the reads. Skipped regions
+    /// (`N`) are not counted as deletions.
+    pub deletions: usize,
+}
+
+impl EditTypeMetrics {
+    /// Tallies the edits for a single read.
+    pub fn tally(&mut self, edits: &EditCounts) {
+        self.reads += 1;
+        self.aligned_bases += edits.aligned_bases;
+        self.substitutions += edits.substitutions;
+        self.insertions += edits.insertions;
+        self.deletions += edits.deletions;
+    }
+}
+
+impl Merge for EditTypeMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.reads += other.reads;
+        self.aligned_bases += other.aligned_bases;
+        self.substitutions += other.substitutions;
+        self.insertions += other.insertions;
+        self.deletions += other.deletions;
+        Ok(())
+    }
+}
+
+/// The number of transitions and transversions among a set of substitutions.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TiTvCounts {
+    /// The number of transitions (`A<->G` and `C<->T`).
+    pub transitions: usize,
+
+    /// The number of transversions (all other substitutions between `A`, `C`,
+    /// `G`, and `T`).
+    pub transversions: usize,
+}
+
+impl TiTvCounts {
+    /// Tallies a single substitution. Substitutions involving a base other
+    /// than `A`, `C`, `G`, or `T` (e.g., `N`) are ignored.
+    pub fn tally(&mut self, substitution: &Substitution) {
+        let is_purine = |base: u8| match base {
+            b'A' | b'G' => Some(true),
+            b'C' | b'T' => Some(false),
+            _ => None,
+        };
+
+        match (
+            is_purine(substitution.reference_base),
+            is_purine(substitution.record_base),
+        ) {
+            (Some(a), Some(b)) if a == b => self.transitions += 1,
+            (Some(_), Some(_)) => self.transversions += 1,
+            _ => {}
+        }
+    }
+
+    /// The transition/transversion ratio, if any transversions were observed.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.transversions > 0).then(|| self.transitions as f64 / self.transversions as f64)
+    }
+}
+
+impl Merge for TiTvCounts {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.transitions += other.transitions;
+        self.transversions += other.transversions;
+        Ok(())
+    }
+}
+
+/// The transitions and transversions among the substitutions, split by whether
+/// the substitution occurred at a known polymorphic site. Genuine variation is
+/// concentrated at known sites and has a much higher Ti/Tv ratio than
+/// sequencing errors, which dominate the substitutions elsewhere.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SubstitutionClassMetrics {
+    /// Substitutions at known sites.
+    pub at_known_sites: TiTvCounts,
 
-    /// Mean number of edits for the read twos in the file.
-    pub mean_edits_read_two: f64,
+    /// Substitutions outside of known sites.
+    pub elsewhere: TiTvCounts,
+}
+
+impl Merge for SubstitutionClassMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.at_known_sites.merge(other.at_known_sites)?;
+        self.elsewhere.merge(other.elsewhere)
+    }
 }
 
 /// Primary metrics struct that is comprised of all of the minor metrics structs
 /// for this quality control facet.
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct EditMetrics {
-    /// The distribution of edit counts for all read ones in the file.
+    /// The distribution of edit distances for all read ones in the file.
     pub read_one_edits: Histogram,
 
-    /// The distribution of edit counts for all read twos in the file.
+    /// The distribution of edit distances for all read twos in the file.
     pub read_two_edits: Histogram,
 
+    /// Totals for each type of edit.
+    #[serde(default)]
+    pub edit_types: EditTypeMetrics,
+
+    /// Totals for each type of edit for each combination of read-in-pair and
+    /// strand, if `--stratify-by-strand` was provided.
+    #[serde(default)]
+    pub edit_types_by_strand: Option<BTreeMap<String, EditTypeMetrics>>,
+
+    /// Transitions and transversions at and outside of known sites, if
+    /// `--sites-vcf` was provided.
+    #[serde(default)]
+    pub substitution_classes: Option<SubstitutionClassMetrics>,
+
     /// Summary statistics for the Edits quality control facet.
     pub summary: Option<EditMetricsSummary>,
 }
 
+impl EditMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let types = &self.edit_types;
+        let rate = |count: usize, total: usize| (total > 0).then(|| count as f64 / total as f64);
+
+        self.summary = Some(EditMetricsSummary {
+            mean_edits_read_one: self.read_one_edits.mean(),
+            mean_edits_read_two: self.read_two_edits.mean(),
+            substitution_rate_per_base: rate(types.substitutions, types.aligned_bases),
+            insertion_rate_per_base: rate(types.insertions, types.aligned_bases),
+            deletion_rate_per_base: rate(types.deletions, types.aligned_bases),
+            substitutions_per_read: rate(types.substitutions, types.reads),
+            insertions_per_read: rate(types.insertions, types.reads),
+            deletions_per_read: rate(types.deletions, types.reads),
+            substitution_rate_per_base_by_strand: self.edit_types_by_strand.as_ref().map(
+                |strata| {
+                    strata
+                        .iter()
+                        .filter_map(|(stratum, types)| {
+                            rate(types.substitutions, types.aligned_bases)
+                                .map(|rate| (stratum.clone(), rate))
+                        })
+                        .collect()
+                },
+            ),
+            ti_tv_at_known_sites: self
+                .substitution_classes
+                .as_ref()
+                .and_then(|classes| classes.at_known_sites.ratio()),
+            ti_tv_elsewhere: self
+                .substitution_classes
+                .as_ref()
+                .and_then(|classes| classes.elsewhere.ratio()),
+        });
+    }
+}
+
+impl Merge for EditMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.read_one_edits.merge(other.read_one_edits)?;
+        self.read_two_edits.merge(other.read_two_edits)?;
+        self.edit_types.merge(other.edit_types)?;
+        self.edit_types_by_strand
+            .merge(other.edit_types_by_strand)?;
+        self.substitution_classes
+            .merge(other.substitution_classes)?;
+        self.summarize();
+        Ok(())
+    }
+}
+
 /// Primary struct used to compile stats regarding edits.
 pub struct EditsFacet {
     /// Metrics related to the Edits quality control facet.
@@ -60,11 +287,37 @@ pub struct EditsFacet {
     /// [`setup`](../../trait.SequenceBasedQualityControlFacet.html#tymethod.setup)
     /// is called.
     pub current_sequence: Option<Sequence>,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+
+    /// Whether the edits should be reported for each combination of
+    /// read-in-pair and strand.
+    pub stratify_by_strand: bool,
+
+    /// The minimum base quality for an aligned base to be considered, if
+    /// `--min-base-quality` was provided.
+    pub min_base_quality: Option<u8>,
+
+    /// The (sorted) positions of the known sites for each sequence, if a sites
+    /// VCF was provided.
+    pub known_sites: Option<HashMap<String, Vec<usize>>>,
+
+    /// The positions of the known sites for the sequence currently being
+    /// processed.
+    pub current_known_sites: Vec<usize>,
 }
 
 impl EditsFacet {
-    /// Tries to create an [`EditsFacet`] from a reference FASTA file.
-    pub fn try_from(reference_fasta: PathBuf) -> anyhow::Result<Self> {
+    /// Tries to create an [`EditsFacet`] from a reference FASTA file,
+    /// configured by the provided [`FacetOptions`]. If known sites are
+    /// provided, the substitutions at and outside of those sites are also
+    /// classified as transitions or transversions.
+    pub fn try_from(
+        reference_fasta: PathBuf,
+        known_sites: Option<&Sites>,
+        options: &FacetOptions,
+    ) -> anyhow::Result<Self> {
         let fasta = formats::fasta::open(&reference_fasta).with_context(|| {
             format!(
                 "Error opening reference FASTA file: {}.",
@@ -72,10 +325,28 @@ impl EditsFacet {
             )
         })?;
 
+        let known_sites = known_sites.map(|sites| {
+            sites
+                .sequences
+                .iter()
+                .map(|(name, sites)| (name.clone(), sites.iter().map(|s| s.position).collect()))
+                .collect()
+        });
+
         Ok(EditsFacet {
-            metrics: EditMetrics::default(),
+            metrics: EditMetrics {
+                substitution_classes: known_sites
+                    .as_ref()
+                    .map(|_| SubstitutionClassMetrics::default()),
+                ..Default::default()
+            },
             fasta,
             current_sequence: None,
+            count_duplicates: options.count_duplicates,
+            stratify_by_strand: options.stratify_by_strand,
+            min_base_quality: options.min_base_quality,
+            known_sites,
+            current_known_sites: Vec::new(),
         })
     }
 }
@@ -96,6 +367,10 @@ impl SequenceBasedQualityControlFacet for EditsFacet {
     fn setup(&mut self, sequence: &Map<ReferenceSequence>) -> anyhow::Result<()> {
         let seq_name = sequence.name().as_str();
 
+        if let Some(known_sites) = &self.known_sites {
+            self.current_known_sites = known_sites.get(seq_name).cloned().unwrap_or_default();
+        }
+
         for result in self.fasta.records() {
             let record = result?;
             if seq_name == record.name() {
@@ -107,14 +382,12 @@ impl SequenceBasedQualityControlFacet for EditsFacet {
         bail!("Sequence {} not found in reference FASTA.", seq_name)
     }
 
-    fn process<'b>(
-        &mut self,
-        _: &'b Map<ReferenceSequence>,
-        record: &Record,
-    ) -> anyhow::Result<()> {
+    fn process(&mut self, _: &Map<ReferenceSequence>, record: &Record) -> anyhow::Result<()> {
         // (1) First, if the read is unmapped, we need to ignore it for this
-        // analysis because there is no reference to compare it to.
-        if record.flags().is_unmapped() {
+        // analysis because there is no reference to compare it to. Similarly,
+        // duplicates are ignored if we aren't counting them.
+        let flags = record.flags();
+        if flags.is_unmapped() || (flags.is_duplicate() && !self.count_duplicates) {
             return Ok(());
         }
 
@@ -141,14 +414,48 @@ impl SequenceBasedQualityControlFacet for EditsFacet {
             let record_seq_sequence = record.sequence();
             let record_seq = record_seq_sequence.as_ref();
 
-            let rrs = ReferenceRecordStepThrough::new(reference_seq, record_seq, cigar.clone());
-            let edits = rrs.edits()?;
+            let quality_scores = record.quality_scores();
+            let mut rrs = ReferenceRecordStepThrough::new(reference_seq, record_seq, cigar.clone());
+            if let Some(min) = self.min_base_quality {
+                rrs = rrs.with_min_base_quality(quality_scores.as_ref(), min);
+            }
+            let edits = rrs.edit_counts()?;
+            self.metrics.edit_types.tally(&edits);
+
+            if self.stratify_by_strand {
+                self.metrics
+                    .edit_types_by_strand
+                    .get_or_insert_with(Default::default)
+                    .entry(strand_stratum(record.flags()).to_string())
+                    .or_default()
+                    .tally(&edits);
+            }
 
-            if record.flags().is_first_segment() {
-                self.metrics.read_one_edits.increment(edits).unwrap();
+            if let Some(classes) = &mut self.metrics.substitution_classes {
+                let start = usize::from(reference_start);
+                for substitution in rrs.substitutions()? {
+                    let position = start + substitution.reference_offset;
+                    if self.current_known_sites.binary_search(&position).is_ok() {
+                        classes.at_known_sites.tally(&substitution);
+                    } else {
+                        classes.elsewhere.tally(&substitution);
+                    }
+                }
+            }
+
+            let histogram = if record.flags().is_first_segment() {
+                &mut self.metrics.read_one_edits
             } else {
-                self.metrics.read_two_edits.increment(edits).unwrap();
+                &mut self.metrics.read_two_edits
+            };
+
+            // Long reads can have many more edits than the histogram starts
+            // out with, so it grows as needed.
+            let edit_distance = edits.total();
+            if !histogram.in_range(edit_distance) {
+                histogram.grow_to(edit_distance.next_power_of_two());
             }
+            histogram.increment(edit_distance).unwrap();
         }
 
         Ok(())
@@ -156,15 +463,40 @@ impl SequenceBasedQualityControlFacet for EditsFacet {
 
     fn teardown(&mut self, _: &Map<ReferenceSequence>) -> anyhow::Result<()> {
         self.current_sequence = None;
+        self.current_known_sites.clear();
         Ok(())
     }
 
     fn aggregate(&mut self, results: &mut results::Results) {
-        self.metrics.summary = Some(EditMetricsSummary {
-            mean_edits_read_one: self.metrics.read_one_edits.mean(),
-            mean_edits_read_two: self.metrics.read_two_edits.mean(),
-        });
-
+        self.metrics.summarize();
         results.edits = Some(self.metrics.clone());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn substitution(reference_base: u8, record_base: u8) -> Substitution {
+        Substitution {
+            reference_offset: 0,
+            reference_base,
+            record_base,
+        }
+    }
+
+    #[test]
+    pub fn it_classifies_transitions_and_transversions() {
+        let mut counts = TiTvCounts::default();
+        assert_eq!(counts.ratio(), None);
+
+        for (reference, record) in [(b'A', b'G'), (b'C', b'T'), (b'T', b'C'), (b'A', b'C')] {
+            counts.tally(&substitution(reference, record));
+        }
+        counts.tally(&substitution(b'A', b'N'));
+
+        assert_eq!(counts.transitions, 3);
+        assert_eq!(counts.transversions, 1);
+        assert_eq!(counts.ratio(), Some(3.0));
+    }
+}
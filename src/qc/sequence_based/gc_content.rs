@@ -0,0 +1,234 @@
+//! Functionality related to the Reference GC Content quality control facet.
+
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use anyhow::{bail, Context};
+use fasta::record::Sequence;
+use noodles::fasta;
+use noodles::sam::{
+    alignment::Record,
+    header::record::value::{map::ReferenceSequence, Map},
+};
+use rand::prelude::*;
+
+use crate::{
+    qc::{
+        record_based::gc_content::{
+            metrics::{GCContentMetrics, ReferenceGCContentMetrics},
+            TRUNCATION_LENGTH,
+        },
+        results, ComputationalLoad, SequenceBasedQualityControlFacet,
+    },
+    utils::formats,
+};
+
+/// Main struct for the Reference GC Content quality control facet. Where the
+/// GC Content facet looks at the nucleobases of each record, this facet looks
+/// at the nucleobases of the reference window that each record was aligned to.
+pub struct ReferenceGCContentFacet {
+    /// The main metric counting struct.
+    pub metrics: ReferenceGCContentMetrics,
+
+    /// The FASTA reader, which is used to cache the current sequence being
+    /// reviewed as processing occurs.
+    pub fasta: fasta::Reader<BufReader<File>>,
+
+    /// The sequence currently being processed by the quality control facet.
+    pub current_sequence: Option<Sequence>,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+}
+
+impl ReferenceGCContentFacet {
+    /// Tries to create a [`ReferenceGCContentFacet`] from a reference FASTA
+    /// file.
+    pub fn try_from(reference_fasta: PathBuf, count_duplicates: bool) -> anyhow::Result<Self> {
+        let fasta = formats::fasta::open(&reference_fasta).with_context(|| {
+            format!(
+                "Error opening reference FASTA file: {}.",
+                reference_fasta.display()
+            )
+        })?;
+
+        Ok(Self {
+            metrics: ReferenceGCContentMetrics::default(),
+            fasta,
+            current_sequence: None,
+            count_duplicates,
+        })
+    }
+
+    /// Tallies the nucleobases of a reference window and increments the
+    /// histogram with the window's GC content.
+    fn tally_window(&mut self, window: &[u8]) {
+        let mut gc_this_window = 0usize;
+
+        for nucleobase in window {
+            match nucleobase.to_ascii_uppercase() {
+                b'C' | b'G' => {
+                    gc_this_window += 1;
+                    self.metrics.nucleobases.total_gc_count += 1;
+                }
+                b'A' | b'T' => self.metrics.nucleobases.total_at_count += 1,
+                _ => self.metrics.nucleobases.total_other_count += 1,
+            }
+        }
+
+        let gc_content_this_window_pct =
+            ((gc_this_window as f64 / window.len() as f64) * 100.0).round() as usize;
+        self.metrics
+            .histogram
+            .increment(gc_content_this_window_pct)
+            .unwrap();
+        self.metrics.records.processed += 1;
+    }
+}
+
+impl SequenceBasedQualityControlFacet for ReferenceGCContentFacet {
+    fn name(&self) -> &'static str {
+        "Reference GC Content"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Moderate
+    }
+
+    fn supports_sequence_name(&self, _: &str) -> bool {
+        true
+    }
+
+    fn setup(&mut self, sequence: &Map<ReferenceSequence>) -> anyhow::Result<()> {
+        let seq_name = sequence.name().as_str();
+
+        for result in self.fasta.records() {
+            let record = result?;
+            if seq_name == record.name() {
+                self.current_sequence = Some(record.sequence().clone());
+                return Ok(());
+            }
+        }
+
+        bail!("Sequence {} not found in reference FASTA.", seq_name)
+    }
+
+    fn process(&mut self, _: &Map<ReferenceSequence>, record: &Record) -> anyhow::Result<()> {
+        // (1) Check the record's flags. Unmapped records have no reference
+        // window, and the remaining flags mirror the GC Content facet so that
+        // the two distributions are directly comparable.
+        let flags = record.flags();
+        if flags.is_unmapped()
+            || flags.is_secondary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            self.metrics.records.ignored_flags += 1;
+            return Ok(());
+        }
+
+        let reference_start = match record.alignment_start() {
+            Some(start) => start,
+            None => {
+                self.metrics.records.ignored_flags += 1;
+                return Ok(());
+            }
+        };
+
+        // (2) Look up the reference window that the record was aligned to.
+        let alignment_span = record.cigar().alignment_span();
+        let reference_end = reference_start.checked_add(alignment_span).unwrap();
+
+        let current_sequence = match &self.current_sequence {
+            Some(sequence) => sequence,
+            None => bail!("Reference GC Content facet was not set up for this sequence."),
+        };
+
+        let reference_window = match current_sequence.get(reference_start..reference_end) {
+            Some(window) => window,
+            None => bail!(
+                "Could not lookup reference sequence for read: {}",
+                record
+                    .read_name()
+                    .map(|name| name.to_string())
+                    .unwrap_or_default()
+            ),
+        };
+
+        // (3) As with the GC Content facet, windows shorter than
+        // TRUNCATION_LENGTH are ignored and longer windows are truncated by
+        // choosing a random starting point so that every window has a fair
+        // chance of generating between 0% to 100% GC content.
+        let window_length = reference_window.len();
+        if window_length < TRUNCATION_LENGTH {
+            self.metrics.records.ignored_too_short += 1;
+            return Ok(());
+        }
+
+        let offset = if TRUNCATION_LENGTH < window_length {
+            ThreadRng::default().gen_range(0..window_length - TRUNCATION_LENGTH)
+        } else {
+            0
+        };
+
+        let window = reference_window[offset..offset + TRUNCATION_LENGTH].to_vec();
+        self.tally_window(&window);
+
+        Ok(())
+    }
+
+    fn teardown(&mut self, _: &Map<ReferenceSequence>) -> anyhow::Result<()> {
+        self.current_sequence = None;
+        Ok(())
+    }
+
+    fn aggregate(&mut self, results: &mut results::Results) {
+        self.metrics.summarize();
+
+        // The GC Content facet is record-based, so its metrics are normally
+        // aggregated first. If it was not run, the reference metrics are
+        // reported on their own.
+        results
+            .gc_content
+            .get_or_insert_with(GCContentMetrics::default)
+            .reference = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    pub fn it_tallies_reference_windows() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "ngs-reference-gc-content-{}.fa",
+            std::process::id()
+        ));
+        let mut file = File::create(&path)?;
+        writeln!(file, ">chr1\nACGT")?;
+        drop(file);
+
+        let mut facet = ReferenceGCContentFacet::try_from(path.clone(), false)?;
+        facet.tally_window(&[b'g'; TRUNCATION_LENGTH]);
+        facet.tally_window(&[b'A'; TRUNCATION_LENGTH]);
+
+        let mut window = vec![b'C'; TRUNCATION_LENGTH / 2];
+        window.extend(vec![b'N'; TRUNCATION_LENGTH / 2]);
+        facet.tally_window(&window);
+
+        assert_eq!(facet.metrics.histogram.get(100), 1);
+        assert_eq!(facet.metrics.histogram.get(0), 1);
+        assert_eq!(facet.metrics.histogram.get(50), 1);
+        assert_eq!(facet.metrics.nucleobases.total_other_count, 50);
+
+        let mut results = results::Results::default();
+        facet.aggregate(&mut results);
+        let reference = results.gc_content.unwrap().reference.unwrap();
+        assert_eq!(reference.records.processed, 3);
+        assert_eq!(reference.summary.unwrap().gc_content_pct, Some(50.0));
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+//! Functionality related to the OxoG quality control facet.
+//!
+//! Oxidation of guanine to 8-oxoguanine during library preparation (common in
+//! FFPE samples and older libraries) causes G→T substitutions that are only
+//! observed in one read orientation—when the damaged base is read as the first
+//! read of a pair on the forward strand (or, equivalently, the second read of a
+//! pair on the reverse strand). Following the approach of Picard's
+//! `CollectOxoGMetrics`, this facet tallies the reference and alternate bases at
+//! G (and C) reference positions in the damage-prone ("pro") and the unaffected
+//! ("con") orientations. True variants are observed equally in both
+//! orientations, so the excess of alternate bases in the pro orientation is an
+//! estimate of the oxidation error rate, which is reported as a Phred-scaled
+//! OxoQ score.
+
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use anyhow::{bail, Context};
+use fasta::record::Sequence;
+use noodles::fasta;
+use noodles::sam::{
+    alignment::Record,
+    header::record::value::{map::ReferenceSequence, Map},
+    record::{cigar::op::Kind, sequence::Base},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    qc::{results, ComputationalLoad, FacetOptions, SequenceBasedQualityControlFacet},
+    utils::{
+        cigar::{consumes_reference, consumes_sequence},
+        formats,
+        merge::Merge,
+    },
+};
+
+/// The minimum base quality for a base to be considered.
+pub const MINIMUM_BASE_QUALITY: u8 = 20;
+
+/// The minimum mapping quality for a record to be considered.
+pub const MINIMUM_MAPPING_QUALITY: u8 = 30;
+
+//=========//
+// Metrics //
+//=========//
+
+/// Summary statistics for the OxoG quality control facet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SummaryMetrics {
+    /// The estimated rate of oxidation errors (the excess of alternate bases in
+    /// the pro orientation over the con orientation, per base). If no excess is
+    /// observed, a single error is assumed so that the rate is never zero.
+    pub oxidation_error_rate: Option<f64>,
+
+    /// The Phred-scaled oxidation error rate. Higher is better: scores below
+    /// ~30 are usually indicative of oxidative damage.
+    pub oxidation_q: Option<f64>,
+}
+
+/// Primary struct used to compile stats regarding oxidative damage.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct OxoGMetrics {
+    /// Reference bases observed at G/C reference positions in the orientation
+    /// in which oxidative damage would be observed.
+    pub pro_ref_bases: usize,
+
+    /// Alternate bases (G→T or C→A) observed in the orientation in which
+    /// oxidative damage would be observed.
+    pub pro_alt_bases: usize,
+
+    /// Reference bases observed at G/C reference positions in the orientation
+    /// in which oxidative damage would not be observed.
+    pub con_ref_bases: usize,
+
+    /// Alternate bases (G→T or C→A) observed in the orientation in which
+    /// oxidative damage would not be observed.
+    pub con_alt_bases: usize,
+
+    /// Summary statistics for the OxoG quality control facet.
+    pub summary: Option<SummaryMetrics>,
+}
+
+impl OxoGMetrics {
+    /// Computes the summary statistics from the tallied metrics.
+    pub fn summarize(&mut self) {
+        let total =
+            self.pro_ref_bases + self.pro_alt_bases + self.con_ref_bases + self.con_alt_bases;
+
+        let oxidation_error_rate = (total > 0).then(|| {
+            let excess = self.pro_alt_bases.saturating_sub(self.con_alt_bases).max(1);
+            excess as f64 / total as f64
+        });
+
+        self.summary = Some(SummaryMetrics {
+            oxidation_error_rate,
+            oxidation_q: oxidation_error_rate.map(|rate| -10.0 * rate.log10()),
+        });
+    }
+}
+
+impl Merge for OxoGMetrics {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        self.pro_ref_bases += other.pro_ref_bases;
+        self.pro_alt_bases += other.pro_alt_bases;
+        self.con_ref_bases += other.con_ref_bases;
+        self.con_alt_bases += other.con_alt_bases;
+        self.summarize();
+        Ok(())
+    }
+}
+
+//=======//
+// Facet //
+//=======//
+
+/// Main struct for the OxoG quality control facet.
+pub struct OxoGFacet {
+    /// Metrics related to the OxoG quality control facet.
+    pub metrics: OxoGMetrics,
+
+    /// The FASTA reader, which is used to cache the current sequence being
+    /// reviewed as processing occurs.
+    pub fasta: fasta::Reader<BufReader<File>>,
+
+    /// The sequence currently being processed by the quality control facet.
+    pub current_sequence: Option<Sequence>,
+
+    /// Whether records marked as duplicate should be counted.
+    pub count_duplicates: bool,
+}
+
+impl OxoGFacet {
+    /// Tries to create an [`OxoGFacet`] from a reference FASTA file, configured
+    /// by the provided [`FacetOptions`].
+    pub fn try_from(reference_fasta: PathBuf, options: &FacetOptions) -> anyhow::Result<Self> {
+        let fasta = formats::fasta::open(&reference_fasta).with_context(|| {
+            format!(
+                "Error opening reference FASTA file: {}.",
+                reference_fasta.display()
+            )
+        })?;
+
+        Ok(Self {
+            metrics: OxoGMetrics::default(),
+            fasta,
+            current_sequence: None,
+            count_duplicates: options.count_duplicates,
+        })
+    }
+
+    /// Tallies a single aligned base. `pro_if_g` is whether a G reference base
+    /// would be in the pro orientation for this record; C reference bases are
+    /// on the opposite strand and, as such, in the opposite orientation.
+    fn tally(&mut self, reference_base: u8, record_base: Base, pro_if_g: bool) {
+        let (pro, alt) = match reference_base.to_ascii_uppercase() {
+            b'G' => (pro_if_g, Base::T),
+            b'C' => (!pro_if_g, Base::A),
+            _ => return,
+        };
+
+        let is_ref = char::from(record_base) == reference_base.to_ascii_uppercase() as char;
+        let is_alt = record_base == alt;
+
+        match (pro, is_ref, is_alt) {
+            (true, true, _) => self.metrics.pro_ref_bases += 1,
+            (true, _, true) => self.metrics.pro_alt_bases += 1,
+            (false, true, _) => self.metrics.con_ref_bases += 1,
+            (false, _, true) => self.metrics.con_alt_bases += 1,
+            _ => {}
+        }
+    }
+}
+
+impl SequenceBasedQualityControlFacet for OxoGFacet {
+    fn name(&self) -> &'static str {
+        "OxoG"
+    }
+
+    fn computational_load(&self) -> ComputationalLoad {
+        ComputationalLoad::Moderate
+    }
+
+    fn supports_sequence_name(&self, _: &str) -> bool {
+        true
+    }
+
+    fn setup(&mut self, sequence: &Map<ReferenceSequence>) -> anyhow::Result<()> {
+        let seq_name = sequence.name().as_str();
+
+        for result in self.fasta.records() {
+            let record = result?;
+            if seq_name == record.name() {
+                self.current_sequence = Some(record.sequence().clone());
+                return Ok(());
+            }
+        }
+
+        bail!("Sequence {} not found in reference FASTA.", seq_name)
+    }
+
+    fn process(&mut self, _: &Map<ReferenceSequence>, record: &Record) -> anyhow::Result<()> {
+        // (1) Only confidently mapped, primary records are considered.
+        let flags = record.flags();
+        if flags.is_unmapped()
+            || flags.is_secondary()
+            || flags.is_supplementary()
+            || (flags.is_duplicate() && !self.count_duplicates)
+        {
+            return Ok(());
+        }
+
+        match record.mapping_quality() {
+            Some(mapq) if u8::from(mapq) >= MINIMUM_MAPPING_QUALITY => {}
+            _ => return Ok(()),
+        }
+
+        let reference_start = match record.alignment_start() {
+            Some(start) => start,
+            None => return Ok(()),
+        };
+
+        // (2) Determine the orientation of the record. Unpaired records are
+        // treated as read one.
+        let is_read_one = !flags.is_segmented() || flags.is_first_segment();
+        let pro_if_g = is_read_one != flags.is_reverse_complemented();
+
+        // (3) Step through the alignment, tallying each aligned base of
+        // sufficient quality.
+        let reference_end = reference_start
+            .checked_add(record.cigar().alignment_span())
+            .unwrap();
+        let reference_seq = match &self.current_sequence {
+            Some(sequence) => match sequence.get(reference_start..reference_end) {
+                Some(window) => window.to_vec(),
+                None => bail!(
+                    "Could not lookup reference sequence for read: {}",
+                    record
+                        .read_name()
+                        .map(|name| name.to_string())
+                        .unwrap_or_default()
+                ),
+            },
+            None => bail!("OxoG facet was not set up for this sequence."),
+        };
+
+        let sequence = record.sequence();
+        let quality_scores = record.quality_scores();
+        let mut reference_ptr = 0;
+        let mut record_ptr = 0;
+
+        for op in record.cigar().iter() {
+            let kind = op.kind();
+            for _ in 0..op.len() {
+                if matches!(
+                    kind,
+                    Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch
+                ) {
+                    let quality = quality_scores
+                        .as_ref()
+                        .get(record_ptr)
+                        .map(|score| u8::from(*score))
+                        .unwrap_or(0);
+
+                    if quality >= MINIMUM_BASE_QUALITY {
+                        let record_base = sequence.as_ref()[record_ptr];
+                        self.tally(reference_seq[reference_ptr], record_base, pro_if_g);
+                    }
+                }
+
+                if consumes_reference(kind) {
+                    reference_ptr += 1;
+                }
+
+                if consumes_sequence(kind) {
+                    record_ptr += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn teardown(&mut self, _: &Map<ReferenceSequence>) -> anyhow::Result<()> {
+        self.current_sequence = None;
+        Ok(())
+    }
+
+    fn aggregate(&mut self, results: &mut results::Results) {
+        self.metrics.summarize();
+        results.oxog = Some(self.metrics.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_computes_the_oxidation_q() {
+        let mut metrics = OxoGMetrics {
+            pro_ref_bases: 4900,
+            pro_alt_bases: 60,
+            con_ref_bases: 4990,
+            con_alt_bases: 50,
+            summary: None,
+        };
+        metrics.summarize();
+
+        let summary = metrics.summary.unwrap();
+        assert_eq!(summary.oxidation_error_rate, Some(0.001));
+        assert!((summary.oxidation_q.unwrap() - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn it_assigns_reference_strands_to_orientations() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("ngs-oxog-{}.fa", std::process::id()));
+        std::fs::write(&path, ">chr1\nACGT\n")?;
+        let mut facet = OxoGFacet::try_from(path.clone(), &FacetOptions::default())?;
+
+        // A read one on the forward strand: G→T is in the pro orientation and
+        // C→A is in the con orientation.
+        facet.tally(b'G', Base::T, true);
+        facet.tally(b'c', Base::A, true);
+        facet.tally(b'G', Base::G, true);
+        facet.tally(b'A', Base::T, true);
+
+        assert_eq!(facet.metrics.pro_alt_bases, 1);
+        assert_eq!(facet.metrics.con_alt_bases, 1);
+        assert_eq!(facet.metrics.pro_ref_bases, 1);
+        assert_eq!(facet.metrics.con_ref_bases, 0);
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
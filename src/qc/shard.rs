@@ -0,0 +1,143 @@
+//! Functionality related to sharding the `ngs qc` subcommand.
+//!
+//! When running on a cluster, the quality control analysis for a single file
+//! can be split up across array jobs, each of which handles a shard of the
+//! reference sequences in the file. Reference sequences are assigned to the
+//! shards round-robin (in the order they appear in the header) so that the
+//! large sequences at the start of most headers are spread across the shards.
+//! Unmapped records that are not placed on any reference sequence are handled
+//! by the last shard. The partial results from each shard can then be
+//! combined with `ngs qc merge`.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+/// A shard of the reference sequences for the `ngs qc` subcommand.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Shard {
+    /// The one-based index of this shard.
+    index: usize,
+
+    /// The total number of shards.
+    total: usize,
+}
+
+impl Shard {
+    /// Creates a new [`Shard`]. The `index` is one-based and must not be
+    /// greater than `total`.
+    ///
+    /// ```
+    /// use ngs::qc::shard::Shard;
+    ///
+    /// assert!(Shard::try_new(1, 4).is_ok());
+    /// assert!(Shard::try_new(0, 4).is_err());
+    /// assert!(Shard::try_new(5, 4).is_err());
+    /// ```
+    pub fn try_new(index: usize, total: usize) -> anyhow::Result<Self> {
+        if total == 0 {
+            bail!("The total number of shards must be greater than zero.");
+        }
+
+        if index == 0 || index > total {
+            bail!(
+                "The shard index must be between 1 and the total number of shards ({}).",
+                total
+            );
+        }
+
+        Ok(Self { index, total })
+    }
+
+    /// Gets the one-based index of this shard.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Gets the total number of shards.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Whether this is the last shard (which also handles unplaced, unmapped
+    /// records).
+    pub fn is_last(&self) -> bool {
+        self.index == self.total
+    }
+
+    /// Whether the reference sequence at the zero-based `position` within the
+    /// header belongs to this shard.
+    ///
+    /// ```
+    /// use ngs::qc::shard::Shard;
+    /// let shard = Shard::try_new(2, 3).unwrap();
+    ///
+    /// assert!(!shard.contains_sequence(0));
+    /// assert!(shard.contains_sequence(1));
+    /// assert!(shard.contains_sequence(4));
+    /// ```
+    pub fn contains_sequence(&self, position: usize) -> bool {
+        position % self.total == self.index - 1
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.total)
+    }
+}
+
+impl FromStr for Shard {
+    type Err = anyhow::Error;
+
+    /// Parses a shard in the form `i/N`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, total) = match s.split_once('/') {
+            Some(parts) => parts,
+            None => bail!("Shards must be specified in the form `i/N` (e.g., `1/4`)."),
+        };
+
+        let index = index
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| anyhow::anyhow!("Shard index is not a whole number: {}", index))?;
+        let total = total.trim().parse::<usize>().map_err(|_| {
+            anyhow::anyhow!("Total number of shards is not a whole number: {}", total)
+        })?;
+
+        Self::try_new(index, total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_parses_shards() {
+        let shard = "3/4".parse::<Shard>().unwrap();
+        assert_eq!(shard.index(), 3);
+        assert_eq!(shard.total(), 4);
+        assert!(!shard.is_last());
+        assert_eq!(shard.to_string(), "3/4");
+
+        assert!("4".parse::<Shard>().is_err());
+        assert!("a/4".parse::<Shard>().is_err());
+        assert!("0/4".parse::<Shard>().is_err());
+        assert!("1/0".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    pub fn it_assigns_every_sequence_to_exactly_one_shard() {
+        let shards: Vec<Shard> = (1..=3).map(|i| Shard::try_new(i, 3).unwrap()).collect();
+
+        for position in 0..10 {
+            let owners = shards
+                .iter()
+                .filter(|shard| shard.contains_sequence(position))
+                .count();
+            assert_eq!(owners, 1);
+        }
+    }
+}
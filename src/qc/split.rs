@@ -0,0 +1,276 @@
+//! Functionality related to splitting the results of the `ngs qc` subcommand.
+//!
+//! Files containing records from multiple samples (e.g., multiplexed or
+//! merged-cohort files) can be split by sample with `--split-by sample`. Each
+//! record is attributed to a sample through the `SM` field of its read group,
+//! and a separate results document is produced for each sample from a single
+//! run over the file.
+//!
+//! Merged files can also be split by lane with `--split-by lane`, which
+//! attributes each record to the flowcell lane in its (Illumina) read name.
+//! This allows a failing lane to be spotted even when the read groups do not
+//! encode the lane. Lanes are discovered as the records are read, and only the
+//! facets in [`LANE_FACETS`] are run for each lane.
+
+use std::collections::HashMap;
+
+use anyhow::bail;
+use noodles::sam::{self, alignment::Record, record::data::field::Tag};
+
+use crate::derive::instrument::reads::IlluminaReadName;
+
+/// The facets that are run for each lane when splitting by lane.
+pub const LANE_FACETS: [&str; 3] = ["Quality Score", "GC Content", "Duplication"];
+
+/// Routes records to the split (sample or lane) they belong to.
+#[derive(Debug)]
+pub enum Router {
+    /// Splits records by the sample of their read group.
+    Sample(SampleRouter),
+
+    /// Splits records by the flowcell lane in their read name.
+    Lane(LaneRouter),
+}
+
+impl Router {
+    /// Creates a new [`Router`] for the stratum provided to `--split-by`.
+    pub fn try_new(stratum: &str, header: &sam::Header) -> anyhow::Result<Self> {
+        match stratum {
+            "sample" => SampleRouter::try_from_header(header).map(Router::Sample),
+            "lane" => Ok(Router::Lane(LaneRouter::default())),
+            _ => bail!("Unsupported stratum for --split-by: {}", stratum),
+        }
+    }
+
+    /// Gets the name of the stratum the records are split by.
+    pub fn stratum(&self) -> &'static str {
+        match self {
+            Router::Sample(_) => "sample",
+            Router::Lane(_) => "lane",
+        }
+    }
+
+    /// Gets the names of the splits that are currently known.
+    pub fn names(&self) -> &[String] {
+        match self {
+            Router::Sample(router) => router.samples(),
+            Router::Lane(router) => router.lanes(),
+        }
+    }
+
+    /// Gets the index (within [`Router::names`]) of the split that a record
+    /// belongs to, if any. Splits that are discovered while routing are added
+    /// to the end of the names.
+    pub fn route(&mut self, record: &Record) -> Option<usize> {
+        match self {
+            Router::Sample(router) => router.route(record),
+            Router::Lane(router) => router.route(record),
+        }
+    }
+}
+
+/// Routes records to the sample of their read group.
+#[derive(Debug)]
+pub struct SampleRouter {
+    /// The distinct samples found in the header, in the order they were first
+    /// encountered.
+    samples: Vec<String>,
+
+    /// The index of the sample for each read group id.
+    read_groups: HashMap<String, usize>,
+}
+
+impl SampleRouter {
+    /// Creates a new [`SampleRouter`] from the read groups in the header. An
+    /// error is returned if none of the read groups have a sample.
+    pub fn try_from_header(header: &sam::Header) -> anyhow::Result<Self> {
+        let mut samples: Vec<String> = Vec::new();
+        let mut read_groups = HashMap::new();
+
+        for (id, read_group) in header.read_groups() {
+            if let Some(sample) = read_group.sample() {
+                let index = match samples.iter().position(|s| s == sample) {
+                    Some(index) => index,
+                    None => {
+                        samples.push(sample.to_string());
+                        samples.len() - 1
+                    }
+                };
+                read_groups.insert(id.to_string(), index);
+            }
+        }
+
+        if samples.is_empty() {
+            bail!(
+                "Cannot split by sample: none of the read groups in the header \
+                have a sample (SM) field."
+            );
+        }
+
+        Ok(Self {
+            samples,
+            read_groups,
+        })
+    }
+
+    /// Gets the distinct samples found in the header.
+    pub fn samples(&self) -> &[String] {
+        &self.samples
+    }
+
+    /// Gets the index (within [`SampleRouter::samples`]) of the sample that a
+    /// record belongs to, if the record's read group has a sample.
+    pub fn route(&self, record: &Record) -> Option<usize> {
+        record
+            .data()
+            .get(Tag::ReadGroup)
+            .and_then(|field| field.value().as_str())
+            .and_then(|id| self.read_groups.get(id))
+            .copied()
+    }
+}
+
+/// Routes records to the flowcell lane encoded in their read name.
+#[derive(Debug, Default)]
+pub struct LaneRouter {
+    /// The distinct lanes, keyed by `FLOWCELL:LANE` (or `LANE` if the read
+    /// names do not include the flowcell), in the order they were first
+    /// encountered.
+    lanes: Vec<String>,
+}
+
+impl LaneRouter {
+    /// Gets the distinct lanes encountered so far.
+    pub fn lanes(&self) -> &[String] {
+        &self.lanes
+    }
+
+    /// Gets the index (within [`LaneRouter::lanes`]) of the lane that a record
+    /// belongs to, if the record has a parseable Illumina read name.
+    pub fn route(&mut self, record: &Record) -> Option<usize> {
+        let read_name = record
+            .read_name()
+            .and_then(|name| AsRef::<str>::as_ref(name).parse::<IlluminaReadName>().ok())?;
+
+        let lane = match read_name.flowcell {
+            Some(flowcell) => format!("{}:{}", flowcell, read_name.lane),
+            None => read_name.lane,
+        };
+
+        match self.lanes.iter().position(|l| *l == lane) {
+            Some(index) => Some(index),
+            None => {
+                self.lanes.push(lane);
+                Some(self.lanes.len() - 1)
+            }
+        }
+    }
+}
+
+/// Converts a sample name into a form that is safe to include in a file name.
+///
+/// ```
+/// use ngs::qc::split::file_name_safe;
+///
+/// assert_eq!(file_name_safe("NA12878"), "NA12878");
+/// assert_eq!(file_name_safe("cohort/sample 1"), "cohort_sample_1");
+/// ```
+pub fn file_name_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::{
+        header::record::value::{map::ReadGroup, Map},
+        record::{
+            data::field::{Field, Value},
+            Data,
+        },
+    };
+
+    use super::*;
+
+    fn record_with_read_group(id: &str) -> anyhow::Result<Record> {
+        let data = Data::try_from(vec![Field::new(
+            Tag::ReadGroup,
+            Value::String(id.to_string()),
+        )])?;
+
+        Ok(Record::builder().set_data(data).build())
+    }
+
+    #[test]
+    pub fn it_routes_records_to_samples() -> anyhow::Result<()> {
+        let header = sam::Header::builder()
+            .add_read_group(
+                Map::<ReadGroup>::builder()
+                    .set_id("rg0")
+                    .set_sample("S1")
+                    .build()?,
+            )
+            .add_read_group(
+                Map::<ReadGroup>::builder()
+                    .set_id("rg1")
+                    .set_sample("S2")
+                    .build()?,
+            )
+            .add_read_group(
+                Map::<ReadGroup>::builder()
+                    .set_id("rg2")
+                    .set_sample("S1")
+                    .build()?,
+            )
+            .add_read_group(Map::<ReadGroup>::new("rg3"))
+            .build();
+
+        let router = SampleRouter::try_from_header(&header)?;
+        assert_eq!(router.samples(), &["S1", "S2"]);
+        assert_eq!(router.route(&record_with_read_group("rg0")?), Some(0));
+        assert_eq!(router.route(&record_with_read_group("rg1")?), Some(1));
+        assert_eq!(router.route(&record_with_read_group("rg2")?), Some(0));
+        assert_eq!(router.route(&record_with_read_group("rg3")?), None);
+        assert_eq!(router.route(&Record::default()), None);
+
+        let header = sam::Header::builder()
+            .add_read_group(Map::<ReadGroup>::new("rg0"))
+            .build();
+        assert!(SampleRouter::try_from_header(&header).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_routes_records_to_lanes() -> anyhow::Result<()> {
+        let record_with_name = |name: &str| -> anyhow::Result<Record> {
+            Ok(Record::builder().set_read_name(name.parse()?).build())
+        };
+
+        let mut router = Router::try_new("lane", &sam::Header::default())?;
+        assert_eq!(router.stratum(), "lane");
+        assert!(router.names().is_empty());
+
+        let first = record_with_name("M1:7:FC1:1:1101:1000:2000")?;
+        let second = record_with_name("M1:7:FC1:2:1101:1000:2000")?;
+        assert_eq!(router.route(&first), Some(0));
+        assert_eq!(router.route(&second), Some(1));
+        assert_eq!(router.route(&first), Some(0));
+        assert_eq!(
+            router.route(&record_with_name("M1:3:1101:1000:2000")?),
+            Some(2)
+        );
+        assert_eq!(router.route(&record_with_name("read1")?), None);
+        assert_eq!(router.route(&Record::default()), None);
+        assert_eq!(router.names(), &["FC1:1", "FC1:2", "3"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+//! Functionality related to stratifying quality control metrics.
+//!
+//! Some sequencing chemistry artifacts (for instance, oxidative damage) only
+//! affect one read of a pair in one orientation, so they are invisible when
+//! metrics are pooled across all records. When `--stratify-by-strand` is
+//! provided, facets that support it additionally report their metrics for each
+//! combination of read-in-pair and strand, keyed by [`strand_stratum`].
+
+use noodles::sam::record::Flags;
+
+/// The strata reported when stratifying by strand, in a stable order.
+pub const STRAND_STRATA: [&str; 6] = [
+    "read_one_forward",
+    "read_one_reverse",
+    "read_two_forward",
+    "read_two_reverse",
+    "unpaired_forward",
+    "unpaired_reverse",
+];
+
+/// Gets the name of the stratum for a record with the given flags, combining
+/// the read-in-pair (read one, read two, or unpaired) with the strand.
+///
+/// ```
+/// use noodles::sam::record::Flags;
+/// use ngs::qc::strata::strand_stratum;
+///
+/// let flags = Flags::SEGMENTED | Flags::LAST_SEGMENT | Flags::REVERSE_COMPLEMENTED;
+/// assert_eq!(strand_stratum(flags), "read_two_reverse");
+/// assert_eq!(strand_stratum(Flags::empty()), "unpaired_forward");
+/// ```
+pub fn strand_stratum(flags: Flags) -> &'static str {
+    // Segmented records that are neither the first nor the last segment are
+    // treated as unpaired.
+    let read_offset = if flags.is_segmented() && flags.is_first_segment() {
+        0
+    } else if flags.is_segmented() && flags.is_last_segment() {
+        2
+    } else {
+        4
+    };
+
+    STRAND_STRATA[read_offset + usize::from(flags.is_reverse_complemented())]
+}
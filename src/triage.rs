@@ -0,0 +1,11 @@
+//! Functionality related to the `ngs triage` command.
+//!
+//! `ngs triage` answers "what is this BAM?" quickly: it reads a sample of the
+//! records at the start of the file once and, from that single pass, reports
+//! the flag counts, the endedness, the quality score encoding, the instrument
+//! and read-naming convention (as `ngs derive` would), and the light
+//! record-based quality control facets (as `ngs qc` would) in one combined
+//! JSON document.
+
+pub mod command;
+pub mod profile;
@@ -0,0 +1,190 @@
+//! Functionality related to the `ngs triage` command itself.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs::File,
+    io::{self, BufWriter, Write},
+    num::NonZeroUsize,
+    path::PathBuf,
+    time::Instant,
+};
+
+use anyhow::Context;
+use clap::Args;
+use noodles::bam;
+use tracing::{info, warn};
+
+use crate::{
+    derive::{
+        instrument::{compute, platform_units, reads::IlluminaReadName},
+        readname_format::{DerivedReadNameFormatResult, ReadNameClassifier},
+    },
+    qc::{self, results::Results, FacetOptions},
+    utils::{
+        args::{parse_num_records, NumberOfRecords},
+        checksum, display,
+        formats::sam::parse_header,
+        metrics, outputs,
+        provenance::Provenance,
+    },
+};
+
+use super::profile::{Endedness, QualityEncoding, TriageProfile, TriageRuntime};
+
+/// The number of records profiled by default. On whole-genome data, this is
+/// enough to characterize the file while finishing well within a minute.
+pub const DEFAULT_TRIAGE_RECORDS: &str = "1000000";
+
+/// Clap arguments for the `ngs triage` subcommand.
+#[derive(Args)]
+pub struct TriageArgs {
+    /// Source BAM.
+    #[arg(value_name = "BAM")]
+    src: PathBuf,
+
+    /// Only profile the first n records in the file.
+    #[arg(short, long, value_name = "USIZE", value_parser = parse_num_records)]
+    #[arg(default_value = DEFAULT_TRIAGE_RECORDS)]
+    num_records: NonZeroUsize,
+
+    /// Writes the profile to the provided path rather than to stdout.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+/// Main function for the `ngs triage` subcommand.
+pub fn triage(args: TriageArgs) -> anyhow::Result<()> {
+    info!("Starting triage subcommand.");
+    let start = Instant::now();
+    let num_records = NumberOfRecords::Some(args.num_records);
+
+    let (input, input_checksum) = checksum::open(&args.src)
+        .with_context(|| format!("Could not open BAM file: {}", args.src.display()))?;
+    let mut reader = bam::Reader::new(input);
+    let header = parse_header(reader.read_header()?)?;
+    reader.read_reference_sequences()?;
+
+    let mut facets = qc::get_triage_qc_facets(&header, &FacetOptions::default());
+    let mut endedness = Endedness::default();
+    let mut quality_encoding = QualityEncoding::default();
+    let classifier = ReadNameClassifier::new();
+    let mut readname_format = DerivedReadNameFormatResult::default();
+
+    // (1) Collect flowcell names from the platform unit of each read group in
+    // the header, as `ngs derive instrument` does.
+    let platform_unit_flowcells = platform_units::flowcells_from_header(&header);
+    let mut instrument_names = HashSet::new();
+    let mut flowcell_names: HashSet<String> = platform_unit_flowcells
+        .iter()
+        .map(|(_, flowcell)| flowcell.clone())
+        .collect();
+
+    // (2) Profile the sampled records within a single pass. Read names that
+    // are not Illumina-formatted are common (e.g., for SRA or long reads), so
+    // they only mean that the instrument cannot be derived from them.
+    let mut records_processed = 0;
+    let mut sampled = false;
+
+    for result in reader.records() {
+        let record = result?;
+
+        records_processed += 1;
+
+        for facet in facets.iter_mut() {
+            facet.process(&record)?;
+        }
+
+        endedness.tally(&record);
+        quality_encoding.tally(&record);
+
+        readname_format.records_processed += 1;
+        match record.read_name() {
+            Some(read_name) => {
+                let name: &str = read_name.as_ref();
+                readname_format.update(classifier.classify(name), name);
+
+                if let Ok(read) = name.parse::<IlluminaReadName>() {
+                    instrument_names.insert(read.instrument_name);
+                    if let Some(flowcell) = read.flowcell {
+                        flowcell_names.insert(flowcell);
+                    }
+                }
+            }
+            None => readname_format.records_without_read_name += 1,
+        }
+
+        if num_records.is_reached(records_processed) {
+            sampled = true;
+            break;
+        }
+    }
+
+    info!("Profiled {} records.", display::count(records_processed));
+
+    if records_processed == 0 {
+        warn!("No records were found in the file: it appears to be empty or header-only.");
+    }
+
+    // (3) Summarize each part of the profile.
+    let mut qc = Results {
+        records_processed,
+        ..Default::default()
+    };
+    for facet in facets.iter_mut() {
+        facet.summarize()?;
+        facet.aggregate(&mut qc);
+    }
+
+    endedness.summarize();
+    quality_encoding.summarize();
+    readname_format.summarize();
+
+    let mut instrument = compute::predict(instrument_names, flowcell_names, false);
+    compute::calibrate_confidence(&mut instrument, records_processed);
+    instrument.records_processed = records_processed;
+    instrument.evidence.flowcell_ids_from_platform_units = platform_unit_flowcells
+        .into_iter()
+        .map(|(_, flowcell)| flowcell)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let elapsed_seconds = start.elapsed().as_secs_f64();
+    let profile = TriageProfile {
+        records_processed,
+        sampled,
+        endedness,
+        quality_encoding,
+        instrument,
+        readname_format,
+        qc,
+        runtime: TriageRuntime {
+            elapsed_seconds,
+            records_per_second: records_processed as f64 / elapsed_seconds.max(f64::EPSILON),
+        },
+        input_checksum: input_checksum.finish()?,
+        provenance: Some(Provenance::now()),
+    };
+
+    // (4) Write the combined profile.
+    match args.output {
+        Some(path) => {
+            let mut out = BufWriter::new(
+                File::create(&path)
+                    .with_context(|| format!("Could not create file: {}", path.display()))?,
+            );
+            serde_json::to_writer_pretty(&mut out, &profile)?;
+            out.flush()?;
+            outputs::record(&path, "triage");
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            serde_json::to_writer_pretty(&mut out, &profile)?;
+            out.flush()?;
+        }
+    }
+
+    metrics::file_processed();
+    Ok(())
+}
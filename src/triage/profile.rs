@@ -0,0 +1,222 @@
+//! The profile of a file reported by `ngs triage`.
+
+use noodles::sam::alignment::Record;
+use serde::Serialize;
+
+use crate::{
+    derive::{instrument::compute::DerivedInstrumentResult, readname_format},
+    qc::results::Results,
+    utils::{checksum::InputChecksum, provenance::Provenance},
+};
+
+//===========//
+// Endedness //
+//===========//
+
+/// The endedness of a file, inferred from the flags of its primary records.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Endedness {
+    /// The number of primary records flagged as the first segment.
+    pub read_one: usize,
+
+    /// The number of primary records flagged as the last segment.
+    pub read_two: usize,
+
+    /// The number of primary records that are not segmented.
+    pub unsegmented: usize,
+
+    /// The inferred endedness: `Paired-End`, `Single-End`, or `Unknown` when
+    /// the records are a mixture (or there are none).
+    pub call: String,
+}
+
+impl Endedness {
+    /// Tallies a single record. Secondary and supplementary records are not
+    /// tallied, so that each template is only counted once.
+    pub fn tally(&mut self, record: &Record) {
+        let flags = record.flags();
+
+        if flags.is_secondary() || flags.is_supplementary() {
+            return;
+        }
+
+        if !flags.is_segmented() {
+            self.unsegmented += 1;
+        } else if flags.is_first_segment() {
+            self.read_one += 1;
+        } else if flags.is_last_segment() {
+            self.read_two += 1;
+        }
+    }
+
+    /// Infers the endedness from the records tallied so far.
+    pub fn summarize(&mut self) {
+        let paired = self.read_one + self.read_two;
+
+        self.call = match (paired, self.unsegmented) {
+            (0, 0) => "Unknown",
+            (0, _) => "Single-End",
+            (_, 0) if self.read_one > 0 && self.read_two > 0 => "Paired-End",
+            _ => "Unknown",
+        }
+        .to_string();
+    }
+}
+
+//==================//
+// Quality encoding //
+//==================//
+
+/// The lowest quality score that Phred+64 encoded qualities (Solexa and
+/// Illumina 1.3 to 1.7) have when they are stored without being converted.
+const PHRED_64_UNCONVERTED_MIN: u8 = 26;
+
+/// The highest quality score that Illumina 1.8+ (Phred+33) short reads have.
+const PHRED_33_SHORT_READ_MAX: u8 = 41;
+
+/// The quality score encoding of a file, inferred from the range of its
+/// quality scores.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct QualityEncoding {
+    /// The lowest quality score observed, if any.
+    pub min: Option<u8>,
+
+    /// The highest quality score observed, if any.
+    pub max: Option<u8>,
+
+    /// The inferred encoding: `Phred+33` or, when every quality score is
+    /// shifted up as though Phred+64 qualities were stored without being
+    /// converted, `Phred+64`. This is `Unknown` if no quality scores were
+    /// observed.
+    pub call: String,
+}
+
+impl QualityEncoding {
+    /// Tallies the quality scores of a single record.
+    pub fn tally(&mut self, record: &Record) {
+        for score in record.quality_scores().as_ref() {
+            let score = u8::from(*score);
+            self.min = Some(self.min.map_or(score, |min| min.min(score)));
+            self.max = Some(self.max.map_or(score, |max| max.max(score)));
+        }
+    }
+
+    /// Infers the encoding from the quality scores tallied so far.
+    pub fn summarize(&mut self) {
+        self.call = match (self.min, self.max) {
+            (Some(min), Some(max))
+                if min >= PHRED_64_UNCONVERTED_MIN && max > PHRED_33_SHORT_READ_MAX =>
+            {
+                "Phred+64"
+            }
+            (Some(_), Some(_)) => "Phred+33",
+            _ => "Unknown",
+        }
+        .to_string();
+    }
+}
+
+//=========//
+// Profile //
+//=========//
+
+/// The time spent profiling the file.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct TriageRuntime {
+    /// The wall-clock time spent profiling the file (in seconds).
+    pub elapsed_seconds: f64,
+
+    /// The number of records profiled per second.
+    pub records_per_second: f64,
+}
+
+/// Struct holding the final results for an `ngs triage` subcommand call.
+#[derive(Serialize)]
+pub struct TriageProfile {
+    /// The number of records that were profiled.
+    pub records_processed: usize,
+
+    /// Whether only a sample of the file was profiled (i.e., the file has more
+    /// records than were requested).
+    pub sampled: bool,
+
+    /// The endedness of the file.
+    pub endedness: Endedness,
+
+    /// The quality score encoding of the file.
+    pub quality_encoding: QualityEncoding,
+
+    /// The instrument used to produce the file, as `ngs derive instrument`
+    /// would report it.
+    pub instrument: DerivedInstrumentResult,
+
+    /// The read-naming convention(s) used within the file, as `ngs derive
+    /// readname-format` would report them.
+    pub readname_format: readname_format::DerivedReadNameFormatResult,
+
+    /// The results of the light record-based quality control facets (the
+    /// General facet holds the flag counts).
+    pub qc: Results,
+
+    /// The time spent profiling the file.
+    pub runtime: TriageRuntime,
+
+    /// The checksum of the file (only computed with `--checksums`).
+    pub input_checksum: Option<InputChecksum>,
+
+    /// The invocation of `ngs` that produced the results.
+    pub provenance: Option<Provenance>,
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::record::{Flags, QualityScores};
+
+    use super::*;
+
+    #[test]
+    pub fn it_infers_the_endedness_and_quality_encoding() -> anyhow::Result<()> {
+        let record = |flags: Flags, qualities: &str| -> anyhow::Result<Record> {
+            Ok(Record::builder()
+                .set_flags(flags)
+                .set_quality_scores(qualities.parse::<QualityScores>()?)
+                .build())
+        };
+
+        let mut endedness = Endedness::default();
+        let mut encoding = QualityEncoding::default();
+        for flags in [
+            Flags::SEGMENTED | Flags::FIRST_SEGMENT,
+            Flags::SEGMENTED | Flags::LAST_SEGMENT,
+            Flags::SEGMENTED | Flags::FIRST_SEGMENT | Flags::SECONDARY,
+        ] {
+            let record = record(flags, "#+5?I")?;
+            endedness.tally(&record);
+            encoding.tally(&record);
+        }
+        endedness.summarize();
+        encoding.summarize();
+
+        assert_eq!((endedness.read_one, endedness.read_two), (1, 1));
+        assert_eq!(endedness.call, "Paired-End");
+        assert_eq!((encoding.min, encoding.max), (Some(2), Some(40)));
+        assert_eq!(encoding.call, "Phred+33");
+
+        let mut endedness = Endedness::default();
+        let mut encoding = QualityEncoding::default();
+        let record = record(Flags::empty(), "@Ph")?;
+        endedness.tally(&record);
+        encoding.tally(&record);
+        endedness.summarize();
+        encoding.summarize();
+
+        assert_eq!(endedness.call, "Single-End");
+        assert_eq!(encoding.call, "Phred+64");
+
+        let mut encoding = QualityEncoding::default();
+        encoding.summarize();
+        assert_eq!(encoding.call, "Unknown");
+
+        Ok(())
+    }
+}
@@ -1,9 +1,28 @@
 //! Utilities that are used across the `ngs` subcommands.
 
 pub mod alignment;
+#[cfg(test)]
+pub mod arbitrary;
+pub mod args;
+#[cfg(feature = "async-io")]
+pub mod bridge;
+pub mod checksum;
 pub mod cigar;
 pub mod display;
+pub mod filter;
 pub mod formats;
 pub mod genome;
 pub mod histogram;
+pub mod intervals;
+pub mod kmers;
+pub mod logging;
+pub mod math;
+pub mod merge;
+pub mod metrics;
+pub mod outputs;
 pub mod pathbuf;
+pub mod pileup;
+pub mod progress;
+pub mod provenance;
+pub mod threads;
+pub mod vectorized;
@@ -1,7 +1,10 @@
 //! Utilities related to alignment of sequences.
 
 use anyhow::bail;
-use noodles::sam::record::{cigar::op::Kind, sequence::Base, Cigar};
+use noodles::sam::{
+    alignment::Record,
+    record::{cigar::op::Kind, quality_scores::Score, sequence::Base, Cigar},
+};
 
 use super::cigar::{consumes_reference, consumes_sequence};
 
@@ -23,12 +26,76 @@ pub fn flatten(cigar: Cigar) -> Vec<Kind> {
     result
 }
 
+/// Gets the sequence of a record in the orientation it was sequenced in (i.e.,
+/// reverse complemented back if the record is aligned to the reverse strand).
+/// This is useful for analyses that search for sequences that are only
+/// present in the original orientation, such as adapters.
+pub fn sequenced_bases(record: &Record) -> Vec<u8> {
+    let bases = record.sequence();
+    let bases = bases.as_ref().iter().map(|base| char::from(*base) as u8);
+
+    if record.flags().is_reverse_complemented() {
+        bases
+            .rev()
+            .map(|base| match base {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                b'T' => b'A',
+                other => other,
+            })
+            .collect()
+    } else {
+        bases.collect()
+    }
+}
+
+/// The edits between a record and the reference, broken down by type.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct EditCounts {
+    /// Number of aligned bases that differ from the reference.
+    pub substitutions: usize,
+
+    /// Number of bases inserted relative to the reference.
+    pub insertions: usize,
+
+    /// Number of reference bases deleted from the record. Skipped regions
+    /// (`N`), such as introns, are not deletions and are not counted.
+    pub deletions: usize,
+
+    /// Number of bases aligned to the reference (`M`, `=`, and `X`).
+    pub aligned_bases: usize,
+}
+
+impl EditCounts {
+    /// The total edit distance (substitutions, insertions, and deletions).
+    /// Soft-clipped bases are not considered edits.
+    pub fn total(&self) -> usize {
+        self.substitutions + self.insertions + self.deletions
+    }
+}
+
+/// A single aligned base that differs from the reference.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Substitution {
+    /// The (zero-based) offset of the substitution from the start of the
+    /// reference sequence that was stepped through.
+    pub reference_offset: usize,
+
+    /// The (uppercase) reference base.
+    pub reference_base: u8,
+
+    /// The record base.
+    pub record_base: u8,
+}
+
 /// Utility struct for stepping through a reference sequence, and record
 /// sequence, and a Cigar string in unison.
 pub struct ReferenceRecordStepThrough<'a> {
     reference_seq: &'a [u8],
     record_seq: &'a [Base],
     cigar: Vec<Kind>,
+    min_base_quality: Option<(&'a [Score], u8)>,
 }
 
 impl<'a> ReferenceRecordStepThrough<'a> {
@@ -38,25 +105,61 @@ impl<'a> ReferenceRecordStepThrough<'a> {
             reference_seq,
             record_seq,
             cigar: flatten(cigar),
+            min_base_quality: None,
+        }
+    }
+
+    /// Only considers the aligned bases with at least the provided base
+    /// quality. Aligned bases below that quality (or without a quality score)
+    /// are neither counted as aligned nor as substitutions.
+    pub fn with_min_base_quality(mut self, quality_scores: &'a [Score], min: u8) -> Self {
+        self.min_base_quality = Some((quality_scores, min));
+        self
+    }
+
+    /// Whether the aligned base at `record_ptr` has a sufficient base quality
+    /// to be considered.
+    fn passes_base_quality(&self, record_ptr: usize) -> bool {
+        match self.min_base_quality {
+            Some((quality_scores, min)) => quality_scores
+                .get(record_ptr)
+                .is_some_and(|score| u8::from(*score) >= min),
+            None => true,
         }
     }
 
-    /// Calculates the number of edits in the [`ReferenceRecordStepThrough`] by
-    /// stepping through the genome and counting up all of the mismatched M's.
-    /// Errors can occur if the reference or the sequence are not all the way
-    /// consumed.
+    /// Calculates the total edit distance in the [`ReferenceRecordStepThrough`].
+    /// See [`Self::edit_counts`] for more information.
     pub fn edits(&self) -> anyhow::Result<usize> {
-        let mut edits = 0;
+        Ok(self.edit_counts()?.total())
+    }
+
+    /// Calculates the edits in the [`ReferenceRecordStepThrough`] by stepping
+    /// through the genome and counting up all of the mismatched aligned bases,
+    /// inserted bases, and deleted bases. Errors can occur if the reference or
+    /// the sequence are not all the way consumed.
+    pub fn edit_counts(&self) -> anyhow::Result<EditCounts> {
+        let mut edits = EditCounts::default();
         let mut record_ptr = 0;
         let mut reference_ptr = 0;
 
         for kind in self.cigar.iter().copied() {
-            if kind == Kind::Match {
-                let ref_base = self.reference_seq[reference_ptr] as char;
-                let record_base: char = self.record_seq[record_ptr].into();
-                if ref_base != record_base {
-                    edits += 1;
+            match kind {
+                Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch
+                    if self.passes_base_quality(record_ptr) =>
+                {
+                    let ref_base = self.reference_seq[reference_ptr].to_ascii_uppercase() as char;
+                    let record_base: char = self.record_seq[record_ptr].into();
+                    if ref_base != record_base {
+                        edits.substitutions += 1;
+                    }
+                    edits.aligned_bases += 1;
                 }
+                Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch => {}
+                Kind::Insertion => edits.insertions += 1,
+                Kind::Deletion => edits.deletions += 1,
+                // Skips, soft clips, hard clips, and padding are not edits.
+                _ => {}
             }
 
             if consumes_reference(kind) {
@@ -76,4 +179,129 @@ impl<'a> ReferenceRecordStepThrough<'a> {
 
         Ok(edits)
     }
+
+    /// Gets each of the substitutions in the [`ReferenceRecordStepThrough`]
+    /// along with its offset within the reference sequence. Errors can occur if
+    /// the reference or the sequence are not all the way consumed.
+    pub fn substitutions(&self) -> anyhow::Result<Vec<Substitution>> {
+        let mut substitutions = Vec::new();
+        let mut record_ptr = 0;
+        let mut reference_ptr = 0;
+
+        for kind in self.cigar.iter().copied() {
+            if matches!(
+                kind,
+                Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch
+            ) && self.passes_base_quality(record_ptr)
+            {
+                let reference_base = self.reference_seq[reference_ptr].to_ascii_uppercase();
+                let record_base = char::from(self.record_seq[record_ptr]) as u8;
+                if reference_base != record_base {
+                    substitutions.push(Substitution {
+                        reference_offset: reference_ptr,
+                        reference_base,
+                        record_base,
+                    });
+                }
+            }
+
+            if consumes_reference(kind) {
+                reference_ptr += 1;
+            }
+
+            if consumes_sequence(kind) {
+                record_ptr += 1;
+            }
+        }
+
+        if self.reference_seq.len() != reference_ptr {
+            bail!("reference sequence was not fully consumed");
+        } else if self.record_seq.len() != record_ptr {
+            bail!("record sequence was not fully consumed");
+        }
+
+        Ok(substitutions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::record::{QualityScores, Sequence};
+
+    use super::*;
+
+    fn edit_counts(reference: &str, record: &str, cigar: &str) -> anyhow::Result<EditCounts> {
+        let record: Sequence = record.parse()?;
+        let rrs =
+            ReferenceRecordStepThrough::new(reference.as_bytes(), record.as_ref(), cigar.parse()?);
+        rrs.edit_counts()
+    }
+
+    #[test]
+    pub fn it_counts_substitutions_insertions_and_deletions() -> anyhow::Result<()> {
+        let edits = edit_counts("ACGTACGT", "ATGTTTCGA", "4M2I1D3X")?;
+        assert_eq!(edits.substitutions, 2);
+        assert_eq!(edits.insertions, 2);
+        assert_eq!(edits.deletions, 1);
+        assert_eq!(edits.aligned_bases, 7);
+        assert_eq!(edits.total(), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_locates_substitutions_within_the_reference() -> anyhow::Result<()> {
+        let record: Sequence = "ATGTTTCGA".parse()?;
+        let rrs =
+            ReferenceRecordStepThrough::new(b"acgtACGT", record.as_ref(), "4M2I1D3X".parse()?);
+
+        assert_eq!(
+            rrs.substitutions()?,
+            vec![
+                Substitution {
+                    reference_offset: 1,
+                    reference_base: b'C',
+                    record_base: b'T',
+                },
+                Substitution {
+                    reference_offset: 7,
+                    reference_base: b'T',
+                    record_base: b'A',
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_excludes_aligned_bases_below_the_minimum_base_quality() -> anyhow::Result<()> {
+        let record: Sequence = "ATGTTTCGA".parse()?;
+        let quality_scores: QualityScores = "I#IIIII#I".parse()?;
+        let rrs =
+            ReferenceRecordStepThrough::new(b"ACGTACGT", record.as_ref(), "4M2I1D3X".parse()?)
+                .with_min_base_quality(quality_scores.as_ref(), 20);
+
+        let edits = rrs.edit_counts()?;
+        assert_eq!(edits.substitutions, 1);
+        assert_eq!(edits.aligned_bases, 5);
+        assert_eq!(edits.insertions, 2);
+        assert_eq!(rrs.substitutions()?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_ignores_skips_and_soft_clips() -> anyhow::Result<()> {
+        let edits = edit_counts("acgtTTTTTacgt", "GGACGTACGT", "2S4M5N4M")?;
+        assert_eq!(
+            edits,
+            EditCounts {
+                aligned_bases: 8,
+                ..Default::default()
+            }
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,254 @@
+//! Generators for property-based tests of the parsers.
+//!
+//! Each property is checked against [`CASES`] inputs drawn from a seeded
+//! random number generator, so failures are reproducible: the failing input is
+//! included in the panic message. The generators produce well-formed read
+//! names for each of the supported conventions, as well as malformed variants
+//! of them (like the truncated, doubled, or otherwise mangled names found in
+//! public data).
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+/// The number of inputs each property is checked against.
+pub const CASES: usize = 2_000;
+
+/// Checks `property` against [`CASES`] inputs from `generate`. The seed is
+/// fixed so that every run checks the same inputs.
+pub fn check<T, G, P>(seed: u64, mut generate: G, mut property: P)
+where
+    T: std::fmt::Debug,
+    G: FnMut(&mut StdRng) -> T,
+    P: FnMut(&T) -> Result<(), String>,
+{
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    for case in 0..CASES {
+        let input = generate(&mut rng);
+        if let Err(reason) = property(&input) {
+            panic!(
+                "property failed for case {} ({:?}): {}",
+                case, input, reason
+            );
+        }
+    }
+}
+
+/// A string of `len` characters drawn from `alphabet`.
+fn string_from(rng: &mut StdRng, alphabet: &[u8], len: usize) -> String {
+    (0..len)
+        .map(|_| *alphabet.choose(rng).unwrap() as char)
+        .collect()
+}
+
+/// A non-negative integer as a string.
+fn number(rng: &mut StdRng, max: u32) -> String {
+    rng.gen_range(0..=max).to_string()
+}
+
+/// An Illumina instrument name (e.g., `A00123` or `HWI-ST1234`).
+pub fn instrument_name(rng: &mut StdRng) -> String {
+    match rng.gen_range(0..3) {
+        0 => format!("A{:05}", rng.gen_range(0..100_000)),
+        1 => format!("HWI-ST{}", rng.gen_range(0..10_000)),
+        _ => {
+            let len = rng.gen_range(1..12);
+            string_from(rng, b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_", len)
+        }
+    }
+}
+
+/// An Illumina flowcell ID (e.g., `H7KJCBGXX`).
+pub fn flowcell_id(rng: &mut StdRng) -> String {
+    let len = rng.gen_range(5..10);
+    string_from(rng, b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789", len)
+}
+
+/// An Illumina 1.4 read name: `INSTRUMENT:LANE:TILE:X:Y[#INDEX][/READ]`.
+pub fn illumina_1_4_name(rng: &mut StdRng) -> String {
+    let mut name = format!(
+        "{}:{}:{}:{}:{}",
+        instrument_name(rng),
+        number(rng, 8),
+        number(rng, 2_000),
+        number(rng, 30_000),
+        number(rng, 30_000)
+    );
+
+    if rng.gen_bool(0.5) {
+        name.push('#');
+        let len = rng.gen_range(0..9);
+        name.push_str(&string_from(rng, b"ACGTN0", len));
+    }
+
+    if rng.gen_bool(0.5) {
+        name.push_str(["/1", "/2"].choose(rng).unwrap());
+    }
+
+    name
+}
+
+/// An Illumina 1.8 read name: `INSTRUMENT:RUN:FLOWCELL:LANE:TILE:X:Y`,
+/// optionally followed by a comment (`READ:FILTERED:CONTROL[:INDEX]`).
+pub fn illumina_1_8_name(rng: &mut StdRng) -> String {
+    let mut name = format!(
+        "{}:{}:{}:{}:{}:{}:{}",
+        instrument_name(rng),
+        number(rng, 2_000),
+        flowcell_id(rng),
+        number(rng, 8),
+        number(rng, 3_000),
+        number(rng, 30_000),
+        number(rng, 30_000)
+    );
+
+    if rng.gen_bool(0.5) {
+        let len = rng.gen_range(0..13);
+        name.push_str(&format!(
+            " {}:{}:{}:{}",
+            rng.gen_range(1..=2),
+            ["Y", "N"].choose(rng).unwrap(),
+            number(rng, 100),
+            string_from(rng, b"ACGTN+", len)
+        ));
+    }
+
+    name
+}
+
+/// A PacBio read name: `MOVIE/HOLE[/ccs|/START_END]`.
+pub fn pacbio_name(rng: &mut StdRng) -> String {
+    let len = rng.gen_range(1..30);
+    let mut name = format!(
+        "m{}/{}",
+        string_from(rng, b"abcdefghijklmnopqrstuvwxyz0123456789_", len),
+        number(rng, 1_000_000)
+    );
+
+    match rng.gen_range(0..3) {
+        0 => name.push_str("/ccs"),
+        1 => name.push_str(&format!("/{}_{}", number(rng, 1_000), number(rng, 30_000))),
+        _ => {}
+    }
+
+    name
+}
+
+/// An ONT read name (a lowercase UUID).
+pub fn ont_name(rng: &mut StdRng) -> String {
+    let hex = b"0123456789abcdef";
+    [8, 4, 4, 4, 12]
+        .iter()
+        .map(|len| string_from(rng, hex, *len))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// A malformed read name: a well-formed name of any convention that has been
+/// mangled, or arbitrary (possibly non-ASCII) text.
+pub fn malformed_name(rng: &mut StdRng) -> String {
+    let mut name = match rng.gen_range(0..5) {
+        0 => illumina_1_4_name(rng),
+        1 => illumina_1_8_name(rng),
+        2 => pacbio_name(rng),
+        3 => ont_name(rng),
+        _ => String::new(),
+    };
+
+    for _ in 0..rng.gen_range(1..4) {
+        let mut chars: Vec<char> = name.chars().collect();
+        let i = rng.gen_range(0..=chars.len());
+
+        match rng.gen_range(0..6) {
+            // Truncation.
+            0 => chars.truncate(i),
+            // Doubled or missing separators.
+            1 => chars.insert(i, ':'),
+            2 => chars.retain(|c| *c != ':' || rng.gen_bool(0.5)),
+            // Stray whitespace and control characters.
+            3 => chars.insert(i, *[' ', '\t', '\0', '\n'].choose(rng).unwrap()),
+            // Non-ASCII characters.
+            4 => chars.insert(i, *['é', 'µ', '\u{FFFD}', '𝔸'].choose(rng).unwrap()),
+            // Arbitrary text.
+            _ => {
+                let len = rng.gen_range(0..40);
+                chars = (0..len).map(|_| rng.gen::<char>()).collect();
+            }
+        }
+
+        name = chars.into_iter().collect();
+    }
+
+    name
+}
+
+/// A SAM header with a random selection of `@HD`, `@SQ`, `@RG`, `@PG`, and
+/// `@CO` lines. Platforms are written in a random case, as in public data.
+pub fn sam_header(rng: &mut StdRng) -> String {
+    let mut header = String::new();
+
+    if rng.gen_bool(0.8) {
+        let order = ["unknown", "unsorted", "queryname", "coordinate"];
+        header.push_str(&format!("@HD\tVN:1.6\tSO:{}\n", order.choose(rng).unwrap()));
+    }
+
+    for i in 0..rng.gen_range(0..4) {
+        header.push_str(&format!(
+            "@SQ\tSN:chr{}\tLN:{}\n",
+            i + 1,
+            rng.gen_range(1..1_000_000)
+        ));
+    }
+
+    for i in 0..rng.gen_range(0..3) {
+        let platform: String = "illumina"
+            .chars()
+            .map(|c| match rng.gen_bool(0.5) {
+                true => c.to_ascii_uppercase(),
+                false => c,
+            })
+            .collect();
+        let len = rng.gen_range(1..10);
+        header.push_str(&format!(
+            "@RG\tID:rg{}\tPL:{}\tSM:{}\tPU:{}.{}\n",
+            i,
+            platform,
+            string_from(rng, b"abcdefghijklmnopqrstuvwxyz", len),
+            flowcell_id(rng),
+            rng.gen_range(1..9)
+        ));
+    }
+
+    if rng.gen_bool(0.5) {
+        header.push_str("@PG\tID:ngs\tPN:ngs\tVN:0.3.0\n");
+    }
+
+    if rng.gen_bool(0.3) {
+        header.push_str("@CO\ta comment with spaces\n");
+    }
+
+    header
+}
+
+/// A malformed SAM header: a well-formed header with mangled characters,
+/// lines, or fields.
+pub fn malformed_sam_header(rng: &mut StdRng) -> String {
+    let mut chars: Vec<char> = sam_header(rng).chars().collect();
+
+    for _ in 0..rng.gen_range(1..4) {
+        let i = rng.gen_range(0..=chars.len());
+
+        match rng.gen_range(0..5) {
+            0 => chars.truncate(i),
+            1 => chars.insert(i, *['\t', '\n', ':', '@'].choose(rng).unwrap()),
+            2 => {
+                if i < chars.len() {
+                    chars.remove(i);
+                }
+            }
+            3 => chars.insert(i, *['é', '\0', '\u{FFFD}'].choose(rng).unwrap()),
+            _ => chars.splice(i..i, "@RG\tID:rg0\n".chars()).for_each(drop),
+        }
+    }
+
+    chars.into_iter().collect()
+}
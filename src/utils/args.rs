@@ -0,0 +1,77 @@
+//! Utilities related to the parsing of command line arguments that are shared
+//! across multiple `ngs` subcommands.
+
+use std::num::NonZeroUsize;
+
+//===================//
+// Number of records //
+//===================//
+
+/// Utility enum to designate whether we are reviewing all records in the file
+/// or just some of them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NumberOfRecords {
+    /// Designates that we should review _all_ of the records in the file.
+    All,
+
+    /// Designates that we should review _some_ of the records in the file. The
+    /// exact count of records is stored in the [`NonZeroUsize`].
+    Some(NonZeroUsize),
+}
+
+impl NumberOfRecords {
+    /// Returns whether `records_seen` records are enough to stop reading.
+    pub fn is_reached(&self, records_seen: usize) -> bool {
+        match self {
+            NumberOfRecords::All => false,
+            NumberOfRecords::Some(n) => records_seen >= n.get(),
+        }
+    }
+}
+
+impl From<Option<NonZeroUsize>> for NumberOfRecords {
+    fn from(value: Option<NonZeroUsize>) -> Self {
+        match value {
+            Some(n) => NumberOfRecords::Some(n),
+            None => NumberOfRecords::All,
+        }
+    }
+}
+
+/// Parses the value of a `--num-records` argument. Only positive whole numbers
+/// are accepted: to review all of the records in a file, the argument should
+/// be omitted entirely.
+pub fn parse_num_records(num_records_raw: &str) -> Result<NonZeroUsize, String> {
+    match num_records_raw.parse::<i128>() {
+        Ok(n) if n < 1 => Err(format!(
+            "{} is not a positive number of records. To process all records, \
+            omit the option entirely.",
+            num_records_raw
+        )),
+        Ok(_) => num_records_raw
+            .parse::<NonZeroUsize>()
+            .map_err(|_| format!("{} is too large a number of records", num_records_raw)),
+        Err(_) => Err(format!(
+            "{} isn't a whole number of records",
+            num_records_raw
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_parses_the_number_of_records() {
+        assert_eq!(parse_num_records("10"), Ok(NonZeroUsize::new(10).unwrap()));
+        assert!(parse_num_records("0").is_err());
+        assert!(parse_num_records("-1").is_err());
+        assert!(parse_num_records("ten").is_err());
+
+        let num_records = NumberOfRecords::from(NonZeroUsize::new(2));
+        assert!(!num_records.is_reached(1));
+        assert!(num_records.is_reached(2));
+        assert!(!NumberOfRecords::from(None).is_reached(usize::MAX));
+    }
+}
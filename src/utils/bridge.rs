@@ -0,0 +1,142 @@
+//! Functionality related to bridging asynchronous inputs to the (synchronous)
+//! quality control facets.
+//!
+//! Reading from network-backed inputs is dominated by latency, so the reads
+//! are better overlapped with the computation done by the facets. The
+//! [`BufferedBridge`] reads from an asynchronous source on a tokio runtime,
+//! buffering a bounded number of chunks ahead of the consumer, and exposes the
+//! chunks through [`std::io::Read`] so that the existing (synchronous) readers
+//! and facets can be used unchanged.
+//!
+//! This module is only available with the `async-io` feature.
+
+use std::{io, path::Path};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    runtime::Handle,
+    sync::mpsc,
+};
+
+/// The default size (in bytes) of each chunk read from the source.
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// The default number of chunks that are buffered ahead of the consumer.
+pub const DEFAULT_CHUNKS_IN_FLIGHT: usize = 16;
+
+/// A synchronous reader over an asynchronous source.
+#[derive(Debug)]
+pub struct BufferedBridge {
+    /// The chunks read from the source (or the error that ended the reads).
+    receiver: mpsc::Receiver<io::Result<Vec<u8>>>,
+
+    /// The chunk that is currently being consumed.
+    chunk: Vec<u8>,
+
+    /// The position of the consumer within the current chunk.
+    position: usize,
+}
+
+impl BufferedBridge {
+    /// Spawns a task on the runtime that reads the source in chunks of
+    /// `chunk_size` bytes, buffering at most `chunks_in_flight` chunks ahead
+    /// of the consumer.
+    pub fn spawn<R>(
+        handle: &Handle,
+        mut source: R,
+        chunk_size: usize,
+        chunks_in_flight: usize,
+    ) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(chunks_in_flight);
+
+        handle.spawn(async move {
+            loop {
+                let mut chunk = vec![0; chunk_size];
+                match source.read(&mut chunk).await {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        chunk.truncate(n);
+
+                        // The consumer has gone away, so there is no reason
+                        // to keep reading.
+                        if sender.send(Ok(chunk)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = sender.send(Err(e)).await;
+                        break;
+                    }
+                }
+            }
+        });
+
+        Self {
+            receiver,
+            chunk: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Opens a local file through the bridge with the default chunk size and
+    /// number of chunks in flight.
+    pub fn open<P: AsRef<Path>>(handle: &Handle, src: P) -> io::Result<Self> {
+        let file = tokio::fs::File::from_std(std::fs::File::open(src)?);
+        Ok(Self::spawn(
+            handle,
+            file,
+            DEFAULT_CHUNK_SIZE,
+            DEFAULT_CHUNKS_IN_FLIGHT,
+        ))
+    }
+}
+
+impl io::Read for BufferedBridge {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // (1) Wait for the next chunk if the current one is exhausted. The
+        // channel closing signals the end of the source.
+        while self.position == self.chunk.len() {
+            match self.receiver.blocking_recv() {
+                Some(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Some(Err(e)) => return Err(e),
+                None => return Ok(0),
+            }
+        }
+
+        // (2) Copy as much of the current chunk as fits.
+        let n = usize::min(buf.len(), self.chunk.len() - self.position);
+        buf[..n].copy_from_slice(&self.chunk[self.position..self.position + n]);
+        self.position += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+
+    #[test]
+    pub fn it_reads_the_whole_source_through_the_bridge() -> anyhow::Result<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .build()?;
+
+        let data = (0..10_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut bridge = BufferedBridge::spawn(runtime.handle(), Cursor::new(data.clone()), 64, 2);
+
+        let mut buf = Vec::new();
+        bridge.read_to_end(&mut buf)?;
+        assert_eq!(buf, data);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,447 @@
+//! Checksums (MD5 and SHA-256) of input files, computed as the files are
+//! streamed.
+//!
+//! Clinical pipelines often need to prove exactly which file a result was
+//! computed from. Rather than reading each input a second time, the file is
+//! wrapped in a [`ChecksumReader`] which digests every byte as it passes
+//! through on the way to the parser. Once the command is done with the file,
+//! [`Checksum::finish`] reads whatever the command did not need (for
+//! instance, when only the first n records were examined) so that the
+//! checksum always covers the whole file.
+//!
+//! Checksums are only computed when they are enabled for the process (with
+//! the global `--checksums` option), as digesting the input is not free.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Whether checksums are computed for the inputs of this process.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables (or disables) the computation of checksums for every input opened
+/// from this point onward.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether checksums are computed for the inputs of this process.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+//=========//
+// SHA-256 //
+//=========//
+
+/// The round constants of SHA-256.
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// A streaming SHA-256 digest (FIPS 180-4).
+///
+/// No SHA-256 crate is available to the build (unlike MD5, for which the
+/// `md-5` crate is used), so the digest is implemented here; it should be
+/// replaced by the `sha2` crate once that can be depended upon. It is tested
+/// against the NIST example vectors of FIPS 180-2 (Appendix B): the one-block
+/// message `abc`, the two-block 448-bit message `abcdbcde...nopq`, and one
+/// million repetitions of `a` (along with the empty message).
+#[derive(Clone, Debug)]
+pub struct Sha256 {
+    /// The intermediate hash value.
+    state: [u32; 8],
+
+    /// The bytes that do not yet fill a block.
+    buffer: [u8; 64],
+
+    /// The number of bytes in the buffer.
+    buffered: usize,
+
+    /// The total number of bytes digested.
+    length: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            buffer: [0; 64],
+            buffered: 0,
+            length: 0,
+        }
+    }
+}
+
+impl Sha256 {
+    /// Digests the provided bytes.
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+
+        if self.buffered > 0 {
+            let n = usize::min(64 - self.buffered, data.len());
+            self.buffer[self.buffered..self.buffered + n].copy_from_slice(&data[..n]);
+            self.buffered += n;
+            data = &data[n..];
+
+            if self.buffered < 64 {
+                return;
+            }
+
+            let block = self.buffer;
+            self.compress(&block);
+            self.buffered = 0;
+        }
+
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.compress(block.try_into().unwrap());
+        }
+
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    /// Pads the message and returns the digest.
+    pub fn finalize(mut self) -> [u8; 32] {
+        let bits = self.length.wrapping_mul(8);
+
+        let mut padding = vec![0x80];
+        let zeros = (64 + 56 - (self.buffered + 1) % 64) % 64;
+        padding.resize(1 + zeros, 0);
+        padding.extend_from_slice(&bits.to_be_bytes());
+        self.update(&padding);
+
+        let mut digest = [0; 32];
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    /// Processes a single 64 byte block.
+    fn compress(&mut self, block: &[u8; 64]) {
+        let mut w = [0u32; 64];
+        for (i, chunk) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes(chunk.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+/// Formats a digest as lowercase hexadecimal.
+fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+//===========//
+// Checksums //
+//===========//
+
+/// The checksums of an input file, as recorded in the output of a command.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputChecksum {
+    /// The path to the file.
+    pub path: PathBuf,
+
+    /// The size of the file in bytes.
+    pub bytes: u64,
+
+    /// The MD5 of the file.
+    pub md5: String,
+
+    /// The SHA-256 of the file.
+    pub sha256: String,
+}
+
+/// The digests of the bytes read so far.
+#[derive(Default)]
+struct Digests {
+    md5: Md5,
+    sha256: Sha256,
+    bytes: u64,
+}
+
+impl Digests {
+    /// Finishes the digests of the file at the provided path.
+    fn finish(self, path: PathBuf) -> InputChecksum {
+        InputChecksum {
+            path,
+            bytes: self.bytes,
+            md5: to_hex(&self.md5.finalize()),
+            sha256: to_hex(&self.sha256.finalize()),
+        }
+    }
+}
+
+/// The state shared between a [`ChecksumReader`] and its [`Checksum`].
+struct Shared<R> {
+    /// The input being digested.
+    inner: R,
+
+    /// The digests of the input so far, or `None` if checksums are disabled or
+    /// the input was not read from start to finish (i.e., it was seeked).
+    digests: Option<Digests>,
+}
+
+/// Reads the remainder of an input, so that its checksums can be finished
+/// regardless of the type of the input.
+trait Drain: Send {
+    /// Reads the rest of the input, returning the final digests (if any).
+    fn drain(&mut self) -> io::Result<Option<Digests>>;
+}
+
+impl<R: Read + Send> Drain for Shared<R> {
+    fn drain(&mut self) -> io::Result<Option<Digests>> {
+        if self.digests.is_some() {
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                match self.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        Ok(self.digests.take())
+    }
+}
+
+impl<R: Read> Read for Shared<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+
+        if let Some(digests) = &mut self.digests {
+            digests.md5.update(&buf[..n]);
+            digests.sha256.update(&buf[..n]);
+            digests.bytes += n as u64;
+        }
+
+        Ok(n)
+    }
+}
+
+/// Wraps an input, digesting every byte that is read from it.
+pub struct ChecksumReader<R> {
+    shared: Arc<Mutex<Shared<R>>>,
+}
+
+/// The handle used to retrieve the checksums of a [`ChecksumReader`] once the
+/// command is done with it (including after the reader has been moved into a
+/// parser or onto another thread).
+pub struct Checksum {
+    path: PathBuf,
+    enabled: bool,
+    shared: Arc<Mutex<dyn Drain>>,
+}
+
+impl<R: Read + Send + 'static> ChecksumReader<R> {
+    /// Wraps an input read from the provided path. The checksums are only
+    /// computed if they are enabled for the process (see [`set_enabled`]).
+    pub fn new<P: AsRef<Path>>(inner: R, path: P) -> (Self, Checksum) {
+        Self::with_enabled(inner, path, is_enabled())
+    }
+
+    /// Wraps an input read from the provided path, computing the checksums
+    /// only if `enabled` (regardless of whether they are enabled for the
+    /// process).
+    pub fn with_enabled<P: AsRef<Path>>(inner: R, path: P, enabled: bool) -> (Self, Checksum) {
+        let shared = Arc::new(Mutex::new(Shared {
+            inner,
+            digests: enabled.then(Digests::default),
+        }));
+
+        let checksum = Checksum {
+            path: path.as_ref().to_path_buf(),
+            enabled,
+            shared: shared.clone(),
+        };
+
+        (Self { shared }, checksum)
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.shared.lock().unwrap().read(buf)
+    }
+}
+
+impl<R: Seek> Seek for ChecksumReader<R> {
+    /// Seeking means the input is no longer read from start to finish, so the
+    /// checksums are abandoned (unless the position does not change, as when
+    /// the current position is requested).
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let mut shared = self.shared.lock().unwrap();
+        let before = shared.inner.stream_position()?;
+        let after = shared.inner.seek(pos)?;
+
+        if after != before {
+            shared.digests = None;
+        }
+
+        Ok(after)
+    }
+}
+
+/// Opens a file for reading, digesting it as it is read.
+pub fn open<P: AsRef<Path>>(src: P) -> io::Result<(ChecksumReader<File>, Checksum)> {
+    let file = File::open(src.as_ref())?;
+    Ok(ChecksumReader::new(file, src))
+}
+
+/// Computes the checksums of a file by reading it from start to finish
+/// (whether or not checksums are enabled for the process).
+pub fn compute<P: AsRef<Path>>(src: P) -> io::Result<InputChecksum> {
+    let path = src.as_ref();
+    let mut shared = Shared {
+        inner: File::open(path)?,
+        digests: Some(Digests::default()),
+    };
+
+    let digests = shared.drain()?.unwrap_or_default();
+    Ok(digests.finish(path.to_path_buf()))
+}
+
+impl Checksum {
+    /// Reads the rest of the input and returns its checksums. `None` is
+    /// returned if checksums were disabled when the input was opened or the
+    /// input was not read from start to finish.
+    pub fn finish(self) -> io::Result<Option<InputChecksum>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        match self.shared.lock().unwrap().drain()? {
+            Some(digests) => Ok(Some(digests.finish(self.path))),
+            None => {
+                warn!(
+                    "No checksums were computed for {}: the file was not read \
+                    from start to finish (e.g., only a shard was read).",
+                    self.path.display()
+                );
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_computes_checksums_while_streaming() -> anyhow::Result<()> {
+        let sha256 = |data: &[u8]| {
+            let mut digest = Sha256::default();
+            digest.update(data);
+            to_hex(&digest.finalize())
+        };
+
+        assert_eq!(
+            sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            sha256(&b"a".repeat(1_000_000)),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+
+        // The digest does not depend on how the input is split up.
+        let data = b"ACGT".repeat(1000);
+        let mut digest = Sha256::default();
+        for chunk in data.chunks(37) {
+            digest.update(chunk);
+        }
+        assert_eq!(to_hex(&digest.finalize()), sha256(&data));
+
+        // Only a portion of the input is read, but the checksum still covers
+        // all of it. Checksums are enabled for this reader alone, as the
+        // setting for the process is shared with the other tests.
+        let abc = || io::Cursor::new(b"abc".to_vec());
+        let (mut reader, checksum) = ChecksumReader::with_enabled(abc(), "abc", true);
+        let mut first = [0; 1];
+        reader.read_exact(&mut first)?;
+
+        let checksum = checksum.finish()?.unwrap();
+        assert_eq!(checksum.bytes, 3);
+        assert_eq!(checksum.md5, "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(checksum.sha256, sha256(b"abc"));
+
+        // Seeking abandons the checksum.
+        let (mut reader, checksum) = ChecksumReader::with_enabled(abc(), "abc", true);
+        reader.seek(SeekFrom::Start(2))?;
+        assert_eq!(checksum.finish()?, None);
+
+        // Nor is anything computed when checksums are disabled.
+        let (_, checksum) = ChecksumReader::with_enabled(abc(), "abc", false);
+        assert_eq!(checksum.finish()?, None);
+
+        Ok(())
+    }
+}
@@ -1,6 +1,44 @@
 //! Utilities related to displaying things.
 
-use std::fmt;
+use std::{fmt, sync::RwLock};
+
+use anyhow::bail;
+use num_format::{Locale, ToFormattedString};
+
+/// The locale that counts are formatted with in the logs, or `None` if they
+/// are written as plain digits.
+static LOCALE: RwLock<Option<Locale>> = RwLock::new(Some(Locale::en));
+
+/// Sets the locale that counts are formatted with in the logs from this point
+/// onward, or (with `None`) writes them as plain digits.
+pub fn set_locale(locale: Option<Locale>) {
+    *LOCALE.write().unwrap() = locale;
+}
+
+/// Parses the name of a locale (e.g., `en` or `de`), where `none` means that
+/// counts are written as plain digits.
+pub fn parse_locale(name: &str) -> anyhow::Result<Option<Locale>> {
+    match name {
+        "none" => Ok(None),
+        _ => match Locale::from_name(name) {
+            Ok(locale) => Ok(Some(locale)),
+            Err(_) => bail!(
+                "unknown locale: {} (use `none` for plain digits, or a name such as `en`, `de`, or `fr`)",
+                name
+            ),
+        },
+    }
+}
+
+/// Formats a count for the logs with the locale's digit grouping (e.g.,
+/// `1,000,000` for `en`), or as plain digits if no locale is set (see
+/// [`set_locale`]).
+pub fn count<T: ToFormattedString + fmt::Display>(n: T) -> String {
+    match *LOCALE.read().unwrap() {
+        Some(locale) => n.to_formatted_string(&locale),
+        None => n.to_string(),
+    }
+}
 
 /// Utility struct for displays percentages. The first item in the struct is the
 /// numerator and the second item in the struct is the denominator.
@@ -0,0 +1,473 @@
+//! Read-level filter expressions.
+//!
+//! A filter expression is compiled once into a predicate that is then applied
+//! to each record, so that metrics can be computed on a well-defined subset of
+//! the records (e.g., `--filter "mapq>=20 && !secondary && rg=='L1'"`).
+//!
+//! # Syntax
+//!
+//! Expressions combine the following terms with `&&` (and), `||` (or), `!`
+//! (not), and parentheses. `&&` binds more tightly than `||`.
+//!
+//! * Flags: `paired`, `proper_pair`, `unmapped`, `mate_unmapped`, `reverse`,
+//!   `mate_reverse`, `read1`, `read2`, `secondary`, `qcfail`, `duplicate`, and
+//!   `supplementary`.
+//! * Numeric comparisons (`==`, `!=`, `<`, `<=`, `>`, or `>=` against a whole
+//!   number): `mapq` (the mapping quality), `tlen` (the absolute template
+//!   length), `len` (the read length), and `start` (the one-based alignment
+//!   start).
+//! * String comparisons (`==` or `!=` against a single or double quoted
+//!   string): `rg` (the read group), `ref` (the name of the reference
+//!   sequence), and `name` (the read name).
+//!
+//! Comparisons against a value that is missing from the record (e.g., the
+//! mapping quality of a record with a mapping quality of 255) are false.
+//!
+//! ```
+//! use ngs::utils::filter::ReadFilter;
+//!
+//! assert!("mapq>=20 && !secondary && rg=='L1'".parse::<ReadFilter>().is_ok());
+//! assert!("(duplicate || qcfail) && len < 50".parse::<ReadFilter>().is_ok());
+//! assert!("mapq >= 'twenty'".parse::<ReadFilter>().is_err());
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, bail};
+use noodles::sam::{
+    self,
+    alignment::Record,
+    record::{data::field::Tag, Flags},
+};
+
+//========//
+// Tokens //
+//========//
+
+/// A token within a filter expression.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// An identifier (a flag or a field).
+    Identifier(String),
+
+    /// A whole number.
+    Number(i64),
+
+    /// A quoted string.
+    String(String),
+
+    /// A comparison operator.
+    Comparison(Comparison),
+
+    /// `&&`
+    And,
+
+    /// `||`
+    Or,
+
+    /// `!`
+    Not,
+
+    /// `(`
+    OpenParenthesis,
+
+    /// `)`
+    CloseParenthesis,
+}
+
+/// Splits a filter expression into tokens.
+fn tokenize(expression: &str) -> anyhow::Result<Vec<Token>> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        let (token, width) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Comparison(Comparison::Equal), 2),
+            ('!', Some('=')) => (Token::Comparison(Comparison::NotEqual), 2),
+            ('<', Some('=')) => (Token::Comparison(Comparison::LessOrEqual), 2),
+            ('>', Some('=')) => (Token::Comparison(Comparison::GreaterOrEqual), 2),
+            ('<', _) => (Token::Comparison(Comparison::Less), 1),
+            ('>', _) => (Token::Comparison(Comparison::Greater), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::OpenParenthesis, 1),
+            (')', _) => (Token::CloseParenthesis, 1),
+            ('\'' | '"', _) => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|x| *x == c)
+                    .ok_or_else(|| anyhow!("unterminated string starting at position {}", i))?;
+                let value: String = chars[i + 1..i + 1 + end].iter().collect();
+                (Token::String(value), end + 2)
+            }
+            (c, _) if c.is_ascii_digit() || c == '-' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|x| !x.is_ascii_digit())
+                    .map_or(chars.len(), |p| i + 1 + p);
+                let value: String = chars[i..end].iter().collect();
+                let number = value
+                    .parse()
+                    .map_err(|_| anyhow!("invalid number \"{}\"", value))?;
+                (Token::Number(number), end - i)
+            }
+            (c, _) if c.is_ascii_alphabetic() || c == '_' => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|x| !(x.is_ascii_alphanumeric() || *x == '_'))
+                    .map_or(chars.len(), |p| i + p);
+                let value: String = chars[i..end].iter().collect();
+                (Token::Identifier(value), end - i)
+            }
+            (c, _) => bail!("unexpected character '{}' at position {}", c, i),
+        };
+
+        tokens.push(token);
+        i += width;
+    }
+
+    Ok(tokens)
+}
+
+//=============//
+// Expressions //
+//=============//
+
+/// A comparison operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// Applies the comparison.
+    fn apply<T: PartialOrd + ?Sized>(&self, a: &T, b: &T) -> bool {
+        match self {
+            Comparison::Equal => a == b,
+            Comparison::NotEqual => a != b,
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::Greater => a > b,
+            Comparison::GreaterOrEqual => a >= b,
+        }
+    }
+}
+
+/// A numeric field of a record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NumericField {
+    MappingQuality,
+    TemplateLength,
+    ReadLength,
+    AlignmentStart,
+}
+
+impl NumericField {
+    /// Gets the value of the field for a record, if it is present.
+    fn get(&self, record: &Record) -> Option<i64> {
+        match self {
+            NumericField::MappingQuality => {
+                record.mapping_quality().map(|q| i64::from(u8::from(q)))
+            }
+            NumericField::TemplateLength => {
+                Some(i64::from(record.template_length().unsigned_abs()))
+            }
+            NumericField::ReadLength => Some(record.sequence().len() as i64),
+            NumericField::AlignmentStart => record.alignment_start().map(|p| usize::from(p) as i64),
+        }
+    }
+}
+
+/// A string field of a record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StringField {
+    ReadGroup,
+    ReferenceSequence,
+    ReadName,
+}
+
+impl StringField {
+    /// Gets the value of the field for a record, if it is present.
+    fn get<'a>(&self, record: &'a Record, header: &'a sam::Header) -> Option<&'a str> {
+        match self {
+            StringField::ReadGroup => record
+                .data()
+                .get(Tag::ReadGroup)
+                .and_then(|field| field.value().as_str()),
+            StringField::ReferenceSequence => record
+                .reference_sequence_id()
+                .and_then(|id| header.reference_sequences().get_index(id))
+                .map(|(name, _)| name.as_str()),
+            StringField::ReadName => record.read_name().map(|name| name.as_ref()),
+        }
+    }
+}
+
+/// A compiled filter expression.
+#[derive(Clone, Debug, PartialEq)]
+enum Expression {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Flag(Flags),
+    Numeric(NumericField, Comparison, i64),
+    String(StringField, Comparison, String),
+}
+
+impl Expression {
+    /// Evaluates the expression for a record.
+    fn evaluate(&self, record: &Record, header: &sam::Header) -> bool {
+        match self {
+            Expression::And(a, b) => a.evaluate(record, header) && b.evaluate(record, header),
+            Expression::Or(a, b) => a.evaluate(record, header) || b.evaluate(record, header),
+            Expression::Not(a) => !a.evaluate(record, header),
+            Expression::Flag(flag) => record.flags().contains(*flag),
+            Expression::Numeric(field, comparison, value) => field
+                .get(record)
+                .is_some_and(|actual| comparison.apply(&actual, value)),
+            Expression::String(field, comparison, value) => field
+                .get(record, header)
+                .is_some_and(|actual| comparison.apply(actual, value.as_str())),
+        }
+    }
+}
+
+/// Gets the flag with the provided name, if it exists.
+fn flag(name: &str) -> Option<Flags> {
+    let flag = match name {
+        "paired" => Flags::SEGMENTED,
+        "proper_pair" => Flags::PROPERLY_ALIGNED,
+        "unmapped" => Flags::UNMAPPED,
+        "mate_unmapped" => Flags::MATE_UNMAPPED,
+        "reverse" => Flags::REVERSE_COMPLEMENTED,
+        "mate_reverse" => Flags::MATE_REVERSE_COMPLEMENTED,
+        "read1" => Flags::FIRST_SEGMENT,
+        "read2" => Flags::LAST_SEGMENT,
+        "secondary" => Flags::SECONDARY,
+        "qcfail" => Flags::QC_FAIL,
+        "duplicate" => Flags::DUPLICATE,
+        "supplementary" => Flags::SUPPLEMENTARY,
+        _ => return None,
+    };
+
+    Some(flag)
+}
+
+//========//
+// Parser //
+//========//
+
+/// A recursive descent parser for filter expressions.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// `or := and ('||' and)*`
+    fn or(&mut self) -> anyhow::Result<Expression> {
+        let mut expression = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    /// `and := unary ('&&' unary)*`
+    fn and(&mut self) -> anyhow::Result<Expression> {
+        let mut expression = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expression = Expression::And(Box::new(expression), Box::new(self.unary()?));
+        }
+        Ok(expression)
+    }
+
+    /// `unary := '!' unary | '(' or ')' | flag | field comparison value`
+    fn unary(&mut self) -> anyhow::Result<Expression> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expression::Not(Box::new(self.unary()?))),
+            Some(Token::OpenParenthesis) => {
+                let expression = self.or()?;
+                match self.next() {
+                    Some(Token::CloseParenthesis) => Ok(expression),
+                    _ => bail!("expected ')'"),
+                }
+            }
+            Some(Token::Identifier(name)) => {
+                if let Some(flag) = flag(&name) {
+                    return Ok(Expression::Flag(flag));
+                }
+
+                let comparison = match self.next() {
+                    Some(Token::Comparison(comparison)) => comparison,
+                    _ => bail!("expected a comparison after \"{}\"", name),
+                };
+
+                let numeric = match name.as_str() {
+                    "mapq" => Some(NumericField::MappingQuality),
+                    "tlen" => Some(NumericField::TemplateLength),
+                    "len" => Some(NumericField::ReadLength),
+                    "start" => Some(NumericField::AlignmentStart),
+                    _ => None,
+                };
+
+                if let Some(field) = numeric {
+                    return match self.next() {
+                        Some(Token::Number(value)) => {
+                            Ok(Expression::Numeric(field, comparison, value))
+                        }
+                        _ => bail!("\"{}\" must be compared with a whole number", name),
+                    };
+                }
+
+                let field = match name.as_str() {
+                    "rg" => StringField::ReadGroup,
+                    "ref" => StringField::ReferenceSequence,
+                    "name" => StringField::ReadName,
+                    _ => bail!("unknown flag or field \"{}\"", name),
+                };
+
+                if !matches!(comparison, Comparison::Equal | Comparison::NotEqual) {
+                    bail!("\"{}\" can only be compared with == or !=", name);
+                }
+
+                match self.next() {
+                    Some(Token::String(value)) => Ok(Expression::String(field, comparison, value)),
+                    _ => bail!("\"{}\" must be compared with a quoted string", name),
+                }
+            }
+            Some(token) => bail!("unexpected {:?}", token),
+            None => bail!("unexpected end of expression"),
+        }
+    }
+}
+
+//=============//
+// Read filter //
+//=============//
+
+/// A compiled read-level filter expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadFilter {
+    /// The filter expression as provided.
+    source: String,
+
+    /// The compiled expression.
+    expression: Expression,
+}
+
+impl ReadFilter {
+    /// Whether a record passes the filter. The header is used to resolve the
+    /// names of reference sequences.
+    pub fn matches(&self, record: &Record, header: &sam::Header) -> bool {
+        self.expression.evaluate(record, header)
+    }
+}
+
+impl FromStr for ReadFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s).map_err(|e| anyhow!("invalid filter \"{}\": {}", s, e))?,
+            position: 0,
+        };
+
+        let expression = parser
+            .or()
+            .and_then(|expression| match parser.peek() {
+                None => Ok(expression),
+                Some(token) => bail!("unexpected {:?}", token),
+            })
+            .map_err(|e| anyhow!("invalid filter \"{}\": {}", s, e))?;
+
+        Ok(Self {
+            source: s.to_string(),
+            expression,
+        })
+    }
+}
+
+impl fmt::Display for ReadFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::sam::{
+        header::record::value::{map::ReferenceSequence, Map},
+        record::{
+            data::field::{Field, Value},
+            Data, MappingQuality,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    pub fn it_filters_records() -> anyhow::Result<()> {
+        let header = sam::Header::builder()
+            .add_reference_sequence(Map::<ReferenceSequence>::new("chr1".parse()?, 1000)?)
+            .build();
+
+        let data = Data::try_from(vec![Field::new(
+            Tag::ReadGroup,
+            Value::String(String::from("L1")),
+        )])?;
+        let record = Record::builder()
+            .set_flags(Flags::SEGMENTED | Flags::DUPLICATE)
+            .set_reference_sequence_id(0)
+            .set_mapping_quality(MappingQuality::try_from(30)?)
+            .set_template_length(-250)
+            .set_sequence("ACGT".parse()?)
+            .set_data(data)
+            .build();
+
+        let matches = |expression: &str| -> anyhow::Result<bool> {
+            Ok(expression.parse::<ReadFilter>()?.matches(&record, &header))
+        };
+
+        assert!(matches("mapq>=20 && !secondary && rg=='L1'")?);
+        assert!(!matches("mapq > 30")?);
+        assert!(matches("tlen == 250 && len < 5")?);
+        assert!(matches("ref == \"chr1\" && (secondary || duplicate)")?);
+        assert!(!matches("!paired || rg != 'L1'")?);
+        assert!(matches("secondary || paired && duplicate")?);
+
+        assert!("mapq >=".parse::<ReadFilter>().is_err());
+        assert!("rg > 'L1'".parse::<ReadFilter>().is_err());
+        assert!("unknown".parse::<ReadFilter>().is_err());
+        assert!("(paired".parse::<ReadFilter>().is_err());
+        assert!("paired secondary".parse::<ReadFilter>().is_err());
+
+        Ok(())
+    }
+}
@@ -2,10 +2,16 @@
 
 use std::{fmt::Display, path::PathBuf};
 
+pub mod alignment;
+pub mod bai;
+pub mod bam;
+pub mod cram;
+pub mod detect;
 pub mod fasta;
 pub mod fastq;
 pub mod gff;
 pub mod sam;
+pub mod vcf;
 
 /// Represents all of the supported bioinformatics file formats that can be
 /// detected by the extension of the filename.
@@ -56,6 +62,12 @@ pub enum BioinformaticsFileFormat {
 
     /// A BED file.
     BED,
+
+    /// A Gzipped BED file.
+    BED_GZ,
+
+    /// A Picard-style interval list.
+    INTERVAL_LIST,
 }
 
 impl Display for BioinformaticsFileFormat {
@@ -76,6 +88,8 @@ impl Display for BioinformaticsFileFormat {
             BioinformaticsFileFormat::GTF => write!(f, "GTF"),
             BioinformaticsFileFormat::GTF_GZ => write!(f, "Gzipped GTF"),
             BioinformaticsFileFormat::BED => write!(f, "BED"),
+            BioinformaticsFileFormat::BED_GZ => write!(f, "Gzipped BED"),
+            BioinformaticsFileFormat::INTERVAL_LIST => write!(f, "Interval List"),
         }
     }
 }
@@ -86,53 +100,56 @@ impl BioinformaticsFileFormat {
     pub fn try_detect(filepath: impl Into<PathBuf>) -> Option<BioinformaticsFileFormat> {
         let path: PathBuf = filepath.into();
 
-        if let Some(ext) = path.extension() {
-            // (1) First, check if the extension is "gz". If it is, then we need
-            // a different set of matches.
-
-            if ext.eq_ignore_ascii_case("gz") {
-                let path_as_str = path.to_str().expect("path to be convertible to &str");
-
-                if path_as_str.ends_with("fasta.gz")
-                    || path_as_str.ends_with("fna.gz")
-                    || path_as_str.ends_with("fa.gz")
-                {
-                    return Some(Self::FASTA_GZ);
-                } else if path_as_str.ends_with("fq.gz") || path_as_str.ends_with("fastq.gz") {
-                    return Some(Self::FASTQ_GZ);
-                } else if path_as_str.ends_with("vcf.gz") {
-                    return Some(Self::VCF_GZ);
-                } else if path_as_str.ends_with("gff.gz") || path_as_str.ends_with("gff3.gz") {
-                    return Some(Self::GFF_GZ);
-                } else if path_as_str.ends_with("gtf.gz") {
-                    return Some(Self::GTF_GZ);
-                } else {
-                    return None;
-                }
-            }
-
-            // (2) Next, if the extension is _not_ "gz", then we can match using
-            // a simple `match` statement.
-
-            match ext
-                .to_ascii_lowercase()
-                .to_str()
-                .expect("extension to be convertible to &str")
-            {
-                "fasta" | "fna" | "fa" => Some(Self::FASTA),
-                "fastq" | "fq" => Some(Self::FASTQ),
-                "sam" => Some(Self::SAM),
-                "bam" => Some(Self::BAM),
-                "cram" => Some(Self::CRAM),
-                "vcf" => Some(Self::VCF),
-                "bcf" => Some(Self::BCF),
-                "gff" | "gff3" => Some(Self::GFF),
-                "gtf" => Some(Self::GTF),
-                "bed" => Some(Self::BED),
-                _ => None,
-            }
-        } else {
-            None
+        // Only the file name is examined (and case is ignored), so detection
+        // does not depend on the path separators of the platform. File names
+        // that are not valid UTF-8 (possible on both Unix and Windows) are
+        // compared lossily rather than causing a panic.
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+
+        // (1) First, check if the extension is "gz". If it is, then we need
+        // a different set of matches.
+
+        if ext == "gz" {
+            let ends_with = |suffixes: &[&str]| {
+                suffixes
+                    .iter()
+                    .any(|suffix| name.ends_with(&format!(".{}.gz", suffix)))
+            };
+
+            return if ends_with(&["fasta", "fna", "fa"]) {
+                Some(Self::FASTA_GZ)
+            } else if ends_with(&["fq", "fastq"]) {
+                Some(Self::FASTQ_GZ)
+            } else if ends_with(&["vcf"]) {
+                Some(Self::VCF_GZ)
+            } else if ends_with(&["gff", "gff3"]) {
+                Some(Self::GFF_GZ)
+            } else if ends_with(&["gtf"]) {
+                Some(Self::GTF_GZ)
+            } else if ends_with(&["bed"]) {
+                Some(Self::BED_GZ)
+            } else {
+                None
+            };
+        }
+
+        // (2) Next, if the extension is _not_ "gz", then we can match using
+        // a simple `match` statement.
+
+        match ext.as_str() {
+            "fasta" | "fna" | "fa" => Some(Self::FASTA),
+            "fastq" | "fq" => Some(Self::FASTQ),
+            "sam" => Some(Self::SAM),
+            "bam" => Some(Self::BAM),
+            "cram" => Some(Self::CRAM),
+            "vcf" => Some(Self::VCF),
+            "bcf" => Some(Self::BCF),
+            "gff" | "gff3" => Some(Self::GFF),
+            "gtf" => Some(Self::GTF),
+            "bed" => Some(Self::BED),
+            "interval_list" => Some(Self::INTERVAL_LIST),
+            _ => None,
         }
     }
 }
@@ -263,5 +280,38 @@ mod tests {
             BioinformaticsFileFormat::try_detect("sample.bed"),
             Some(BioinformaticsFileFormat::BED)
         );
+        assert_eq!(
+            BioinformaticsFileFormat::try_detect("sample.bed.gz"),
+            Some(BioinformaticsFileFormat::BED_GZ)
+        );
+    }
+
+    #[test]
+    fn it_correctly_identifies_interval_list_files() {
+        assert_eq!(
+            BioinformaticsFileFormat::try_detect("targets.interval_list"),
+            Some(BioinformaticsFileFormat::INTERVAL_LIST)
+        );
+    }
+
+    #[test]
+    fn it_identifies_files_regardless_of_case_or_encoding() {
+        assert_eq!(
+            BioinformaticsFileFormat::try_detect("SAMPLE.FASTQ.GZ"),
+            Some(BioinformaticsFileFormat::FASTQ_GZ)
+        );
+        assert_eq!(BioinformaticsFileFormat::try_detect("sample"), None);
+        assert_eq!(BioinformaticsFileFormat::try_detect("sample.txt.gz"), None);
+
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+            let name = OsStr::from_bytes(b"sample\xff.bam");
+            assert_eq!(
+                BioinformaticsFileFormat::try_detect(name),
+                Some(BioinformaticsFileFormat::BAM)
+            );
+        }
     }
 }
@@ -0,0 +1,400 @@
+//! Utilities for reading alignment files (SAM, BAM, or CRAM) through a single
+//! interface.
+//!
+//! The format of an alignment file is detected by its contents (see
+//! [`detect`](super::detect)), so misnamed files are still read correctly.
+//! SAM files may be uncompressed or bgzipped. The records of a CRAM file are
+//! decoded against the reference FASTA (provided with `--reference-fasta`)
+//! and converted to the same alignment records that are read from a BAM file,
+//! so everything downstream of the reader is unaware of the format.
+
+use std::{
+    fmt::Display,
+    io::{self, BufRead, BufReader, Read, Seek},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use noodles::{
+    bam::{self, bai},
+    bgzf,
+    core::Region,
+    cram::{self, crai},
+    fasta::{self, repository::adapters::IndexedReader},
+    sam::{self, alignment::Record, header::ReferenceSequences},
+};
+
+use crate::utils::{
+    formats::{
+        bam::{for_each_query_record, for_each_record, intersects},
+        detect::GZIP_MAGIC_NUMBER,
+        sam::parse_header,
+    },
+    pathbuf::AppendExtension,
+};
+
+/// An iterator over the records of an alignment file, whatever its format.
+pub type Records<'a> = Box<dyn Iterator<Item = anyhow::Result<Record>> + 'a>;
+
+//========//
+// Format //
+//========//
+
+/// The format of an alignment file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AlignmentFormat {
+    /// A SAM file (uncompressed or bgzipped).
+    Sam,
+
+    /// A BAM file.
+    Bam,
+
+    /// A CRAM file.
+    Cram,
+}
+
+impl Display for AlignmentFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlignmentFormat::Sam => write!(f, "SAM"),
+            AlignmentFormat::Bam => write!(f, "BAM"),
+            AlignmentFormat::Cram => write!(f, "CRAM"),
+        }
+    }
+}
+
+//=======//
+// Index //
+//=======//
+
+/// The index of an alignment file.
+pub enum AlignmentIndex {
+    /// The index of a BAM file.
+    Bai(bai::Index),
+
+    /// The index of a CRAM file.
+    Crai(crai::Index),
+}
+
+impl AlignmentIndex {
+    /// Reads the index of an alignment file (`<src>.bai` or `<src>.crai`,
+    /// replacing the extension of `src`). SAM files are not indexed.
+    pub fn read(src: &Path, format: AlignmentFormat) -> anyhow::Result<Self> {
+        match format {
+            AlignmentFormat::Sam => bail!("SAM files cannot be indexed: convert the file to BAM."),
+            AlignmentFormat::Bam => bai::read(src.with_extension("bam.bai"))
+                .map(Self::Bai)
+                .with_context(|| "bam index"),
+            AlignmentFormat::Cram => crai::read(src.with_extension("cram.crai"))
+                .map(Self::Crai)
+                .with_context(|| "cram index"),
+        }
+    }
+}
+
+//========//
+// Reader //
+//========//
+
+/// Opens the repository of reference sequences that CRAM records are decoded
+/// against. The reference FASTA must be indexed.
+pub fn open_repository(reference_fasta: &Path) -> anyhow::Result<fasta::Repository> {
+    // noodles reports an unindexed FASTA as "unsupported", so the index is
+    // checked for up front with a more helpful message.
+    let fai_filepath = reference_fasta.to_path_buf().append_extension("fai")?;
+    if !fai_filepath.exists() {
+        bail!(
+            "couldn't find an index for your reference FASTA: is the FASTA indexed? \
+            Run `ngs index [FASTA]` to index the FASTA file."
+        )
+    }
+
+    fasta::reader::Builder::default()
+        .build_from_path(reference_fasta)
+        .map(IndexedReader::new)
+        .map(fasta::Repository::new)
+        .with_context(|| "building FASTA repository")
+}
+
+/// The (possibly bgzipped) stream that the records of a SAM file are read
+/// from.
+pub enum SamInput<R> {
+    /// An uncompressed SAM file.
+    Plain(BufReader<R>),
+
+    /// A bgzipped SAM file.
+    Bgzf(bgzf::Reader<BufReader<R>>),
+}
+
+impl<R: Read> SamInput<R> {
+    /// Wraps the stream, decompressing it if it starts with the gzip magic
+    /// number.
+    pub fn new(inner: R) -> io::Result<Self> {
+        let mut reader = BufReader::new(inner);
+
+        if reader.fill_buf()?.starts_with(GZIP_MAGIC_NUMBER) {
+            Ok(Self::Bgzf(bgzf::Reader::new(reader)))
+        } else {
+            Ok(Self::Plain(reader))
+        }
+    }
+}
+
+impl<R: Read> Read for SamInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(reader) => reader.read(buf),
+            Self::Bgzf(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl<R: Read> BufRead for SamInput<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            Self::Plain(reader) => reader.fill_buf(),
+            Self::Bgzf(reader) => reader.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            Self::Plain(reader) => reader.consume(amt),
+            Self::Bgzf(reader) => reader.consume(amt),
+        }
+    }
+}
+
+/// Reads a single record from a SAM file, returning the number of bytes read
+/// (zero at the end of the file).
+///
+/// The SAM parser of noodles only parses the first of the optional fields of
+/// a record (it stops at the next tab), so the optional fields are parsed
+/// again from the line as a whole.
+fn read_sam_record<R: BufRead>(
+    reader: &mut R,
+    header: &sam::Header,
+    record: &mut Record,
+    buf: &mut Vec<u8>,
+) -> anyhow::Result<usize> {
+    buf.clear();
+    let read = reader.read_until(b'\n', buf)?;
+    if read == 0 {
+        return Ok(0);
+    }
+
+    let line = buf.strip_suffix(b"\n").unwrap_or(buf);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    sam::Reader::new(line).read_record(header, record)?;
+
+    if let Some(data) = line.splitn(12, |&b| b == b'\t').nth(11) {
+        *record.data_mut() = std::str::from_utf8(data)?
+            .parse()
+            .with_context(|| "parsing the optional fields of a SAM record")?;
+    }
+
+    Ok(read)
+}
+
+/// A reader of the records within an alignment file.
+pub enum AlignmentReader<R> {
+    /// A reader of a SAM file.
+    Sam(sam::Reader<SamInput<R>>),
+
+    /// A reader of a BAM file.
+    Bam(bam::Reader<bgzf::Reader<R>>),
+
+    /// A reader of a CRAM file, along with the reference sequences its
+    /// records are decoded against.
+    Cram(cram::Reader<R>, fasta::Repository),
+}
+
+impl<R: Read> AlignmentReader<R> {
+    /// Creates a reader of a CRAM file. The reference FASTA is required to
+    /// decode the records.
+    pub fn cram(inner: R, reference_fasta: Option<&Path>) -> anyhow::Result<Self> {
+        let reference_fasta = match reference_fasta {
+            Some(path) => path,
+            None => bail!("A reference FASTA (--reference-fasta) is required to read CRAM files."),
+        };
+
+        Ok(Self::Cram(
+            cram::Reader::new(inner),
+            open_repository(reference_fasta)?,
+        ))
+    }
+
+    /// Creates a reader of an alignment file in the provided format.
+    pub fn new(
+        inner: R,
+        format: AlignmentFormat,
+        reference_fasta: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        match format {
+            AlignmentFormat::Sam => Ok(Self::Sam(sam::Reader::new(SamInput::new(inner)?))),
+            AlignmentFormat::Bam => Ok(Self::Bam(bam::Reader::new(inner))),
+            AlignmentFormat::Cram => Self::cram(inner, reference_fasta),
+        }
+    }
+
+    /// Reads the header of the file, along with its reference sequences. For
+    /// BAM files, the reference sequences are those of the binary header
+    /// (which follows the text header).
+    pub fn read_header(&mut self) -> anyhow::Result<(sam::Header, ReferenceSequences)> {
+        match self {
+            Self::Sam(reader) => {
+                let header = parse_header(reader.read_header()?)?;
+                let reference_sequences = header.reference_sequences().clone();
+                Ok((header, reference_sequences))
+            }
+            Self::Bam(reader) => {
+                let header = parse_header(reader.read_header()?)?;
+                let reference_sequences = reader.read_reference_sequences()?;
+                Ok((header, reference_sequences))
+            }
+            Self::Cram(reader, _) => {
+                reader.read_file_definition()?;
+                let header = parse_header(reader.read_file_header()?)?;
+                let reference_sequences = header.reference_sequences().clone();
+                Ok((header, reference_sequences))
+            }
+        }
+    }
+
+    /// Returns an iterator over the remaining records in the file. Each record
+    /// is allocated anew.
+    pub fn records<'a>(&'a mut self, header: &'a sam::Header) -> Records<'a> {
+        match self {
+            Self::Sam(reader) => {
+                let mut buf = Vec::new();
+                Box::new(std::iter::from_fn(move || {
+                    let mut record = Record::default();
+                    match read_sam_record(reader.get_mut(), header, &mut record, &mut buf) {
+                        Ok(0) => None,
+                        Ok(_) => Some(Ok(record)),
+                        Err(e) => Some(Err(e)),
+                    }
+                }))
+            }
+            Self::Bam(reader) => Box::new(reader.records().map(|r| r.map_err(Into::into))),
+            Self::Cram(reader, repository) => Box::new(
+                reader
+                    .records(repository, header)
+                    .map(|r| Ok(r?.try_into_alignment_record(header)?)),
+            ),
+        }
+    }
+
+    /// Calls `f` with each of the remaining records in the file until it
+    /// returns `true` (see [`for_each_record`]). The records of a CRAM file
+    /// are always allocated anew, as they are converted as they are decoded.
+    pub fn for_each_record<F>(
+        &mut self,
+        header: &sam::Header,
+        reuse: bool,
+        mut f: F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(&Record) -> anyhow::Result<bool>,
+    {
+        match self {
+            Self::Bam(reader) => for_each_record(reader, reuse, f),
+            Self::Sam(reader) if reuse => {
+                let (mut record, mut buf) = (Record::default(), Vec::new());
+                while read_sam_record(reader.get_mut(), header, &mut record, &mut buf)? != 0 {
+                    if f(&record)? {
+                        break;
+                    }
+                }
+
+                Ok(())
+            }
+            _ => {
+                for result in self.records(header) {
+                    if f(&result?)? {
+                        break;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> AlignmentReader<R> {
+    /// Calls `f` with each of the records overlapping the region until it
+    /// returns `true` (see [`for_each_query_record`]).
+    pub fn for_each_query_record<F>(
+        &mut self,
+        header: &sam::Header,
+        index: &AlignmentIndex,
+        region: &Region,
+        reuse: bool,
+        mut f: F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(&Record) -> anyhow::Result<bool>,
+    {
+        match (self, index) {
+            (Self::Bam(reader), AlignmentIndex::Bai(index)) => for_each_query_record(
+                reader,
+                header.reference_sequences(),
+                index,
+                region,
+                reuse,
+                f,
+            ),
+            (Self::Cram(reader, repository), AlignmentIndex::Crai(index)) => {
+                // (The slices of a CRAM file may hold the records of more
+                // than one reference sequence, so the records outside of the
+                // region are skipped.)
+                let reference_sequence_id = header
+                    .reference_sequences()
+                    .get_index_of(region.name())
+                    .with_context(|| {
+                        format!("sequence {} not found in the header", region.name())
+                    })?;
+
+                for result in reader.query(repository, header, index, region)? {
+                    let record = result?.try_into_alignment_record(header)?;
+                    if !intersects(&record, reference_sequence_id, region.interval()) {
+                        continue;
+                    }
+
+                    if f(&record)? {
+                        break;
+                    }
+                }
+
+                Ok(())
+            }
+            _ => bail!("The index does not match the format of the alignment file."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    pub fn it_reads_the_header_of_an_alignment_file() -> anyhow::Result<()> {
+        // Without any records, the reader still reads the header.
+        let bam = PathBuf::from("tests/fixtures/paired.bam");
+        let (input, _) = crate::utils::checksum::open(&bam)?;
+        let mut reader = AlignmentReader::new(input, AlignmentFormat::Bam, None)?;
+        let (header, reference_sequences) = reader.read_header()?;
+        assert_eq!(
+            reference_sequences.len(),
+            header.reference_sequences().len()
+        );
+
+        assert!(AlignmentReader::cram(std::io::empty(), None).is_err());
+        assert!(AlignmentIndex::read(&bam, AlignmentFormat::Sam).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+//! Utilities related to detecting stale BAM indexes.
+//!
+//! A BAM index that no longer describes its BAM file (e.g., because the BAM
+//! file was rewritten without reindexing it) is still accepted by the readers,
+//! but queries through it silently return the wrong records, which produces
+//! subtly wrong results in the second pass of `ngs qc`. Before the index is
+//! used, it is compared against the header, size, and modification time of
+//! the BAM file and, once the whole file has been read, against the number of
+//! records in the file.
+
+use std::{fs, io, path::Path, str::FromStr};
+
+use anyhow::bail;
+use noodles::{
+    bam::bai,
+    csi::{binning_index::ReferenceSequenceExt, BinningIndex},
+    sam,
+};
+use tracing::warn;
+
+/// The width (in base pairs) of each window of the linear index.
+const LINEAR_INDEX_WINDOW: usize = 1 << 14;
+
+//========//
+// Policy //
+//========//
+
+/// What to do when the BAM index appears to be stale.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum StaleIndexPolicy {
+    /// Warn about each problem with the index, but use it anyway.
+    #[default]
+    Warn,
+
+    /// Refuse to use the index.
+    Fail,
+
+    /// Use the index without checking it.
+    Ignore,
+}
+
+impl FromStr for StaleIndexPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "fail" => Ok(Self::Fail),
+            "ignore" => Ok(Self::Ignore),
+            _ => bail!("unknown stale index policy: {}", s),
+        }
+    }
+}
+
+impl StaleIndexPolicy {
+    /// Whether the index should be checked at all.
+    pub fn checks(&self) -> bool {
+        *self != Self::Ignore
+    }
+
+    /// Warns about (or fails because of) the problems found with the index.
+    pub fn enforce(&self, bai: &Path, problems: &[String]) -> anyhow::Result<()> {
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        match self {
+            Self::Warn => {
+                for problem in problems {
+                    warn!(
+                        "The index {} appears to be stale: {}.",
+                        bai.display(),
+                        problem
+                    );
+                }
+
+                Ok(())
+            }
+            Self::Fail => bail!(
+                "The index {} appears to be stale: {}. Reindex the BAM file (or \
+                rerun with `--stale-index warn` to use the index anyway).",
+                bai.display(),
+                problems.join("; ")
+            ),
+            Self::Ignore => Ok(()),
+        }
+    }
+}
+
+//========//
+// Checks //
+//========//
+
+/// Compares the index against the header, size, and modification time of the
+/// BAM file, returning a description of each problem that was found.
+pub fn structural_problems(
+    index: &bai::Index,
+    header: &sam::Header,
+    src: &Path,
+    bai: &Path,
+) -> io::Result<Vec<String>> {
+    let mut problems = Vec::new();
+
+    // (1) The index has an entry for each reference sequence in the header.
+    let indexed = index.reference_sequences().len();
+    let declared = header.reference_sequences().len();
+    if indexed != declared {
+        problems.push(format!(
+            "it describes {} reference sequences, but the header declares {}",
+            indexed, declared
+        ));
+    }
+
+    // (2) The index was written after the BAM file.
+    let bam_metadata = fs::metadata(src)?;
+    let bai_metadata = fs::metadata(bai)?;
+    if let (Ok(bam_modified), Ok(bai_modified)) = (bam_metadata.modified(), bai_metadata.modified())
+    {
+        if bai_modified < bam_modified {
+            problems.push(String::from("it was last modified before the BAM file"));
+        }
+    }
+
+    // (3) The index only refers to offsets within the BAM file, and only
+    // covers as much of each reference sequence as the header declares.
+    let size = bam_metadata.len();
+    let beyond_end = index
+        .reference_sequences()
+        .iter()
+        .any(|reference_sequence| {
+            reference_sequence
+                .intervals()
+                .iter()
+                .copied()
+                .chain(reference_sequence.metadata().map(|m| m.end_position()))
+                .any(|offset| offset.compressed() > size)
+        });
+    if beyond_end {
+        problems.push(format!(
+            "it refers to offsets beyond the end of the BAM file ({} bytes)",
+            size
+        ));
+    }
+
+    for ((name, sequence), reference_sequence) in header
+        .reference_sequences()
+        .iter()
+        .zip(index.reference_sequences())
+    {
+        let windows = usize::from(sequence.length()).div_ceil(LINEAR_INDEX_WINDOW);
+        if reference_sequence.intervals().len() > windows {
+            problems.push(format!(
+                "it covers more of {} than the {} bp declared by the header",
+                name,
+                usize::from(sequence.length())
+            ));
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Compares the number of records the index accounts for against the number
+/// of records in the BAM file. `None` is returned if the counts agree or if
+/// the index does not record them (e.g., it was written by an indexer that
+/// omits the metadata pseudo-bins).
+pub fn record_count_problem(index: &bai::Index, records_in_file: u64) -> Option<String> {
+    let metadata: Vec<_> = index
+        .reference_sequences()
+        .iter()
+        .filter_map(|reference_sequence| reference_sequence.metadata())
+        .collect();
+    let unplaced = index.unplaced_unmapped_record_count();
+
+    if metadata.is_empty() && unplaced.is_none() {
+        return None;
+    }
+
+    let indexed = metadata
+        .iter()
+        .map(|m| m.mapped_record_count() + m.unmapped_record_count())
+        .sum::<u64>()
+        + unplaced.unwrap_or_default();
+
+    (indexed != records_in_file).then(|| {
+        format!(
+            "it accounts for {} records, but the BAM file has {}",
+            indexed, records_in_file
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    use noodles::{bgzf::VirtualPosition, csi::index::reference_sequence::Metadata};
+
+    use super::*;
+
+    #[test]
+    pub fn it_detects_stale_indexes() -> anyhow::Result<()> {
+        let directory = std::env::temp_dir().join(format!("ngs-stale-{}", std::process::id()));
+        fs::create_dir_all(&directory)?;
+        let src = directory.join("sample.bam");
+        let bai = directory.join("sample.bam.bai");
+        fs::write(&src, vec![0; 1024])?;
+        fs::write(&bai, b"")?;
+
+        let header: sam::Header = "@SQ\tSN:chr1\tLN:20000\n".parse()?;
+        let index = |end: u64, windows: usize| {
+            let metadata = Metadata::new(
+                VirtualPosition::from(0),
+                VirtualPosition::from(end << 16),
+                90,
+                10,
+            );
+            let reference_sequence = bai::index::ReferenceSequence::new(
+                Vec::new(),
+                vec![VirtualPosition::from(0); windows],
+                Some(metadata),
+            );
+            bai::Index::new(vec![reference_sequence], Some(5))
+        };
+
+        // A current index has no problems.
+        let current = index(512, 2);
+        assert!(structural_problems(&current, &header, &src, &bai)?.is_empty());
+        assert_eq!(record_count_problem(&current, 105), None);
+
+        // An index for a longer file (and sequence) and with other records.
+        let stale = index(4096, 3);
+        let problems = structural_problems(&stale, &header, &src, &bai)?;
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("beyond the end"));
+        assert!(problems[1].contains("more of chr1"));
+        assert_eq!(
+            record_count_problem(&stale, 200).as_deref(),
+            Some("it accounts for 105 records, but the BAM file has 200")
+        );
+
+        // An index that is older than the BAM file.
+        File::options()
+            .write(true)
+            .open(&bai)?
+            .set_modified(SystemTime::now() - Duration::from_secs(60))?;
+        let problems = structural_problems(&current, &header, &src, &bai)?;
+        assert_eq!(problems, ["it was last modified before the BAM file"]);
+
+        assert!(StaleIndexPolicy::Warn.enforce(&bai, &problems).is_ok());
+        assert!(StaleIndexPolicy::Fail.enforce(&bai, &problems).is_err());
+        assert!(StaleIndexPolicy::Fail.enforce(&bai, &[]).is_ok());
+        assert!("sometimes".parse::<StaleIndexPolicy>().is_err());
+
+        fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
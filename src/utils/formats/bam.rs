@@ -0,0 +1,246 @@
+//! Utilities related to reading BAM files.
+//!
+//! The record iterators provided by noodles allocate a new record for every
+//! record that is read. In the hot loops of `ngs qc`, that allocator pressure
+//! is significant, so these utilities instead decode each record into a
+//! record (and its buffers) that is reused across iterations. The callbacks
+//! receive a reference to the reused record, which is only valid until the
+//! callback returns.
+
+use std::io::{self, Read, Seek};
+
+use anyhow::Context;
+use noodles::{
+    bam::{self, bai},
+    bgzf::{self, VirtualPosition},
+    core::{region::Interval, Region},
+    csi::{index::reference_sequence::bin::Chunk, BinningIndex},
+    sam::{alignment::Record, header::ReferenceSequences},
+};
+
+use crate::utils::threads;
+
+/// Creates a BAM reader for reading the file from start to finish, whose BGZF
+/// blocks are decompressed by a pool of workers if more than one thread was
+/// requested (see [`threads::bgzf_worker_count`]).
+///
+/// The workers read ahead of the records being decoded, so the reader must
+/// not be seeked (e.g., to query a region through the index).
+pub fn sequential_reader<R: Read>(inner: R) -> bam::Reader<bgzf::Reader<R>> {
+    bam::Reader::from(
+        bgzf::reader::Builder::default()
+            .set_worker_count(threads::bgzf_worker_count())
+            .build_from_reader(inner),
+    )
+}
+
+/// Reads the records overlapping a region through the index, decoding each
+/// into a record provided by the caller.
+pub struct QueryCursor<'a, R: Read + Seek> {
+    /// The reader the records are read from.
+    reader: &'a mut bam::Reader<bgzf::Reader<R>>,
+
+    /// The chunks of the file that (may) contain records in the region.
+    chunks: std::vec::IntoIter<Chunk>,
+
+    /// The end of the chunk currently being read, if any.
+    chunk_end: Option<VirtualPosition>,
+
+    /// The index of the reference sequence of the region.
+    reference_sequence_id: usize,
+
+    /// The interval of the region.
+    interval: Interval,
+}
+
+impl<'a, R: Read + Seek> QueryCursor<'a, R> {
+    /// Creates a new [`QueryCursor`] for the region.
+    pub fn try_new(
+        reader: &'a mut bam::Reader<bgzf::Reader<R>>,
+        reference_sequences: &ReferenceSequences,
+        index: &bai::Index,
+        region: &Region,
+    ) -> anyhow::Result<Self> {
+        let reference_sequence_id = reference_sequences
+            .get_index_of(region.name())
+            .with_context(|| format!("sequence {} not found in the header", region.name()))?;
+        let chunks = index.query(reference_sequence_id, region.interval())?;
+
+        Ok(Self {
+            reader,
+            chunks: chunks.into_iter(),
+            chunk_end: None,
+            reference_sequence_id,
+            interval: region.interval(),
+        })
+    }
+
+    /// Decodes the next record that overlaps the region into `record`.
+    /// Returns `false` once there are no more records.
+    pub fn read_record(&mut self, record: &mut Record) -> io::Result<bool> {
+        loop {
+            // (1) Seek to the next chunk if no chunk is being read.
+            let chunk_end = match self.chunk_end {
+                Some(end) => end,
+                None => match self.chunks.next() {
+                    Some(chunk) => {
+                        self.reader.seek(chunk.start())?;
+                        self.chunk_end = Some(chunk.end());
+                        chunk.end()
+                    }
+                    None => return Ok(false),
+                },
+            };
+
+            // (2) Read the next record, moving on to the next chunk at the end
+            // of this one.
+            if self.reader.read_record(record)? == 0 {
+                self.chunk_end = None;
+                continue;
+            }
+
+            if self.reader.virtual_position() >= chunk_end {
+                self.chunk_end = None;
+            }
+
+            // (3) Chunks may contain records outside of the region.
+            if intersects(record, self.reference_sequence_id, self.interval) {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Whether the record is aligned to the reference sequence within the
+/// interval.
+pub(crate) fn intersects(
+    record: &Record,
+    reference_sequence_id: usize,
+    interval: Interval,
+) -> bool {
+    match (
+        record.reference_sequence_id(),
+        record.alignment_start(),
+        record.alignment_end(),
+    ) {
+        (Some(id), Some(start), Some(end)) => {
+            id == reference_sequence_id && interval.intersects((start..=end).into())
+        }
+        _ => false,
+    }
+}
+
+/// Calls `f` with each of the remaining records in the file until it returns
+/// `true`. If `reuse` is `false`, a new record is allocated for every record
+/// (e.g., to rule out record reuse when debugging).
+pub fn for_each_record<R, F>(
+    reader: &mut bam::Reader<R>,
+    reuse: bool,
+    mut f: F,
+) -> anyhow::Result<()>
+where
+    R: Read,
+    F: FnMut(&Record) -> anyhow::Result<bool>,
+{
+    if !reuse {
+        for result in reader.records() {
+            if f(&result?)? {
+                break;
+            }
+        }
+
+        return Ok(());
+    }
+
+    let mut record = Record::default();
+    while reader.read_record(&mut record)? != 0 {
+        if f(&record)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Calls `f` with each of the records overlapping the region until it returns
+/// `true`. If `reuse` is `false`, a new record is allocated for every record.
+pub fn for_each_query_record<R, F>(
+    reader: &mut bam::Reader<bgzf::Reader<R>>,
+    reference_sequences: &ReferenceSequences,
+    index: &bai::Index,
+    region: &Region,
+    reuse: bool,
+    mut f: F,
+) -> anyhow::Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(&Record) -> anyhow::Result<bool>,
+{
+    if !reuse {
+        for result in reader.query(reference_sequences, index, region)? {
+            if f(&result?)? {
+                break;
+            }
+        }
+
+        return Ok(());
+    }
+
+    let mut cursor = QueryCursor::try_new(reader, reference_sequences, index, region)?;
+    let mut record = Record::default();
+    while cursor.read_record(&mut record)? {
+        if f(&record)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use noodles::sam::{self, record::ReadName};
+
+    use super::*;
+
+    #[test]
+    pub fn it_reads_the_same_records_with_and_without_reuse() -> anyhow::Result<()> {
+        let header = sam::Header::default();
+        let mut writer = bam::Writer::new(Vec::new());
+        writer.write_header(&header)?;
+        writer.write_reference_sequences(header.reference_sequences())?;
+
+        // An unnamed record in between the named records ensures that nothing
+        // from the previous record is left behind in the reused record.
+        for name in [Some("r1"), None, Some("r3")] {
+            let mut builder = Record::builder();
+            if let Some(name) = name {
+                builder = builder.set_read_name(name.parse::<ReadName>()?);
+            }
+            writer.write_record(&header, &builder.build())?;
+        }
+
+        let data = writer.into_inner().finish()?;
+
+        let names = |reuse: bool| -> anyhow::Result<Vec<Option<String>>> {
+            let mut reader = bam::Reader::new(Cursor::new(data.clone()));
+            reader.read_header()?;
+            reader.read_reference_sequences()?;
+
+            let mut names = Vec::new();
+            for_each_record(&mut reader, reuse, |record| {
+                names.push(record.read_name().map(|name| name.to_string()));
+                Ok(false)
+            })?;
+            Ok(names)
+        };
+
+        let expected = vec![Some(String::from("r1")), None, Some(String::from("r3"))];
+        assert_eq!(names(true)?, expected);
+        assert_eq!(names(false)?, expected);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,535 @@
+//! Utilities related to reading the container structure of CRAM files.
+//!
+//! noodles decodes the records within a CRAM file, but it does not expose how
+//! they are stored: the containers, the slices within each container, the
+//! blocks the data series are written to (and how each block is compressed),
+//! and the encoding of each data series. These utilities walk that structure
+//! directly, per the CRAM 3.x specification, without decoding any records.
+
+use std::io::{self, Read};
+
+use flate2::read::MultiGzDecoder;
+
+/// The magic number at the start of every CRAM file.
+const MAGIC_NUMBER: &[u8; 4] = b"CRAM";
+
+//=========//
+// Numbers //
+//=========//
+
+/// Builds an [`io::Error`] for malformed data.
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads a single byte.
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+/// Reads a little-endian 32-bit integer.
+fn read_i32<R: Read>(reader: &mut R) -> io::Result<i32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+/// Reads an ITF-8 integer: a variable-length integer of up to five bytes,
+/// where the number of leading ones in the first byte is the number of bytes
+/// that follow.
+pub fn read_itf8<R: Read>(reader: &mut R) -> io::Result<i32> {
+    let b0 = read_u8(reader)? as i32;
+
+    let value = match (b0 as u8).leading_ones() {
+        0 => b0,
+        1 => (b0 & 0x7f) << 8 | read_u8(reader)? as i32,
+        2 => {
+            let mut buf = [0; 2];
+            reader.read_exact(&mut buf)?;
+            (b0 & 0x3f) << 16 | (buf[0] as i32) << 8 | buf[1] as i32
+        }
+        3 => {
+            let mut buf = [0; 3];
+            reader.read_exact(&mut buf)?;
+            (b0 & 0x1f) << 24 | (buf[0] as i32) << 16 | (buf[1] as i32) << 8 | buf[2] as i32
+        }
+        _ => {
+            let mut buf = [0; 4];
+            reader.read_exact(&mut buf)?;
+            (b0 & 0x0f) << 28
+                | (buf[0] as i32) << 20
+                | (buf[1] as i32) << 12
+                | (buf[2] as i32) << 4
+                | (buf[3] as i32 & 0x0f)
+        }
+    };
+
+    Ok(value)
+}
+
+/// Reads an LTF-8 integer: a variable-length integer of up to nine bytes,
+/// where the number of leading ones in the first byte is the number of bytes
+/// that follow.
+pub fn read_ltf8<R: Read>(reader: &mut R) -> io::Result<i64> {
+    let b0 = read_u8(reader)?;
+    let len = b0.leading_ones() as usize;
+
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf[..len])?;
+
+    // The bits of the first byte after its leading ones are the most
+    // significant bits of the value (for the eight- and nine-byte forms, the
+    // first byte contributes no bits).
+    let mut value = match len {
+        0..=6 => i64::from(b0 & (0xff >> (len + 1))),
+        _ => 0,
+    };
+
+    for byte in &buf[..len] {
+        value = value << 8 | i64::from(*byte);
+    }
+
+    Ok(value)
+}
+
+/// Reads an ITF-8 integer that is expected to be a non-negative size.
+fn read_size<R: Read>(reader: &mut R) -> io::Result<usize> {
+    usize::try_from(read_itf8(reader)?).map_err(|_| invalid_data("invalid negative size"))
+}
+
+//=================//
+// File definition //
+//=================//
+
+/// The version of the CRAM format a file was written with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Version {
+    /// The major version.
+    pub major: u8,
+
+    /// The minor version.
+    pub minor: u8,
+}
+
+//=======//
+// Block //
+//=======//
+
+/// The method a block is compressed with.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum CompressionMethod {
+    /// Not compressed.
+    Raw,
+    /// gzip.
+    Gzip,
+    /// bzip2.
+    Bzip2,
+    /// LZMA.
+    Lzma,
+    /// rANS (order 0 or 1, 4x8).
+    Rans4x8,
+    /// rANS (Nx16), CRAM 3.1.
+    RansNx16,
+    /// The adaptive arithmetic coder, CRAM 3.1.
+    AdaptiveArithmetic,
+    /// fqzcomp (for quality scores), CRAM 3.1.
+    Fqzcomp,
+    /// The name tokenizer (for read names), CRAM 3.1.
+    NameTokenizer,
+    /// A method not defined by the specification.
+    Unknown(u8),
+}
+
+impl From<u8> for CompressionMethod {
+    fn from(method: u8) -> Self {
+        match method {
+            0 => Self::Raw,
+            1 => Self::Gzip,
+            2 => Self::Bzip2,
+            3 => Self::Lzma,
+            4 => Self::Rans4x8,
+            5 => Self::RansNx16,
+            6 => Self::AdaptiveArithmetic,
+            7 => Self::Fqzcomp,
+            8 => Self::NameTokenizer,
+            n => Self::Unknown(n),
+        }
+    }
+}
+
+impl std::fmt::Display for CompressionMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Raw => write!(f, "raw"),
+            Self::Gzip => write!(f, "gzip"),
+            Self::Bzip2 => write!(f, "bzip2"),
+            Self::Lzma => write!(f, "lzma"),
+            Self::Rans4x8 => write!(f, "rans4x8"),
+            Self::RansNx16 => write!(f, "ransNx16"),
+            Self::AdaptiveArithmetic => write!(f, "arith"),
+            Self::Fqzcomp => write!(f, "fqzcomp"),
+            Self::NameTokenizer => write!(f, "tok3"),
+            Self::Unknown(n) => write!(f, "unknown({})", n),
+        }
+    }
+}
+
+/// The kind of content stored within a block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ContentType {
+    /// The SAM header.
+    FileHeader,
+    /// The compression header of a container.
+    CompressionHeader,
+    /// The header of a slice.
+    SliceHeader,
+    /// Data series (or tag values) written to an external block.
+    ExternalData,
+    /// The core data series of a slice.
+    CoreData,
+    /// A content type not defined by the specification.
+    Other(u8),
+}
+
+impl From<u8> for ContentType {
+    fn from(content_type: u8) -> Self {
+        match content_type {
+            0 => Self::FileHeader,
+            1 => Self::CompressionHeader,
+            2 => Self::SliceHeader,
+            4 => Self::ExternalData,
+            5 => Self::CoreData,
+            n => Self::Other(n),
+        }
+    }
+}
+
+/// A block within a container.
+#[derive(Debug)]
+pub struct Block {
+    /// The method the block is compressed with.
+    pub method: CompressionMethod,
+
+    /// The kind of content stored within the block.
+    pub content_type: ContentType,
+
+    /// The ID of the content (for external blocks, the ID that data series
+    /// are written to).
+    pub content_id: i32,
+
+    /// The size of the block after decompression.
+    pub uncompressed_size: usize,
+
+    /// The (compressed) data of the block.
+    pub data: Vec<u8>,
+}
+
+impl Block {
+    /// Reads a block.
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let method = CompressionMethod::from(read_u8(reader)?);
+        let content_type = ContentType::from(read_u8(reader)?);
+        let content_id = read_itf8(reader)?;
+        let compressed_size = read_size(reader)?;
+        let uncompressed_size = read_size(reader)?;
+
+        let mut data = vec![0; compressed_size];
+        reader.read_exact(&mut data)?;
+        let _crc32 = read_i32(reader)?;
+
+        Ok(Self {
+            method,
+            content_type,
+            content_id,
+            uncompressed_size,
+            data,
+        })
+    }
+
+    /// Decompresses the block. Header blocks are nearly always raw or gzipped,
+    /// so only those methods are supported: `None` is returned for the others.
+    pub fn decompress(&self) -> io::Result<Option<Vec<u8>>> {
+        match self.method {
+            CompressionMethod::Raw => Ok(Some(self.data.clone())),
+            CompressionMethod::Gzip => {
+                let mut data = Vec::with_capacity(self.uncompressed_size);
+                MultiGzDecoder::new(&self.data[..]).read_to_end(&mut data)?;
+                Ok(Some(data))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+//===========//
+// Container //
+//===========//
+
+/// A container: the unit a CRAM file is written in.
+#[derive(Debug)]
+pub struct Container {
+    /// The reference sequence ID of the records in the container (`-1` for
+    /// unmapped records and `-2` for records on multiple sequences).
+    pub reference_sequence_id: i32,
+
+    /// The number of records in the container.
+    pub record_count: i32,
+
+    /// The number of bases in the container.
+    pub base_count: i64,
+
+    /// The number of slices in the container.
+    pub slice_count: usize,
+
+    /// The blocks within the container.
+    pub blocks: Vec<Block>,
+}
+
+impl Container {
+    /// Whether the container is the end-of-file container that terminates
+    /// every complete CRAM 3.x file.
+    pub fn is_eof(&self) -> bool {
+        self.reference_sequence_id == -1 && self.record_count == 0 && self.slice_count == 0
+    }
+
+    /// Whether the container holds the SAM header rather than records.
+    pub fn is_file_header(&self) -> bool {
+        self.blocks
+            .first()
+            .is_some_and(|block| block.content_type == ContentType::FileHeader)
+    }
+}
+
+/// The information stored in the compression header of a container that is
+/// relevant to quality control.
+#[derive(Debug, Default)]
+pub struct CompressionHeader {
+    /// Whether the reference sequence is required to decode the records
+    /// (i.e., bases are stored as differences from the reference).
+    pub reference_required: bool,
+
+    /// The two-letter name of each data series and the ID of the codec it is
+    /// encoded with.
+    pub data_series_encodings: Vec<(String, i32)>,
+}
+
+impl CompressionHeader {
+    /// Parses the (decompressed) content of a compression header block.
+    pub fn parse(mut data: &[u8]) -> io::Result<Self> {
+        let reader = &mut data;
+        let mut header = Self {
+            reference_required: true,
+            ..Default::default()
+        };
+
+        // (1) The preservation map. Reference-based compression is the
+        // default when `RR` is absent.
+        let mut map = vec![0; read_size(reader)?];
+        reader.read_exact(&mut map)?;
+        let mut map = &map[..];
+        for _ in 0..read_size(&mut map)? {
+            let mut key = [0; 2];
+            map.read_exact(&mut key)?;
+            match &key {
+                b"RN" | b"AP" => {
+                    read_u8(&mut map)?;
+                }
+                b"RR" => header.reference_required = read_u8(&mut map)? != 0,
+                b"SM" => map.read_exact(&mut [0; 5])?,
+                b"TD" => {
+                    let len = read_size(&mut map)?;
+                    io::copy(&mut (&mut map).take(len as u64), &mut io::sink())?;
+                }
+                _ => break,
+            }
+        }
+
+        // (2) The data series encoding map. The parameters of each encoding
+        // are skipped.
+        let mut map = vec![0; read_size(reader)?];
+        reader.read_exact(&mut map)?;
+        let mut map = &map[..];
+        for _ in 0..read_size(&mut map)? {
+            let mut key = [0; 2];
+            map.read_exact(&mut key)?;
+            let codec = read_itf8(&mut map)?;
+            let len = read_size(&mut map)?;
+            io::copy(&mut (&mut map).take(len as u64), &mut io::sink())?;
+
+            header
+                .data_series_encodings
+                .push((String::from_utf8_lossy(&key).to_string(), codec));
+        }
+
+        Ok(header)
+    }
+}
+
+/// The information stored in a slice header that is relevant to quality
+/// control.
+#[derive(Debug)]
+pub struct SliceHeader {
+    /// The reference sequence ID of the records in the slice (`-1` for
+    /// unmapped records and `-2` for records on multiple sequences).
+    pub reference_sequence_id: i32,
+
+    /// The number of records in the slice.
+    pub record_count: i32,
+
+    /// The content ID of the block holding the reference bases embedded in
+    /// the slice, or `-1` if the reference is not embedded.
+    pub embedded_reference_content_id: i32,
+
+    /// The MD5 checksum of the reference bases spanned by the slice (all
+    /// zeros when not applicable).
+    pub reference_md5: [u8; 16],
+}
+
+impl SliceHeader {
+    /// Parses the (decompressed) content of a slice header block.
+    pub fn parse(mut data: &[u8]) -> io::Result<Self> {
+        let reader = &mut data;
+
+        let reference_sequence_id = read_itf8(reader)?;
+        let _alignment_start = read_itf8(reader)?;
+        let _alignment_span = read_itf8(reader)?;
+        let record_count = read_itf8(reader)?;
+        let _record_counter = read_ltf8(reader)?;
+        let _block_count = read_itf8(reader)?;
+        for _ in 0..read_size(reader)? {
+            read_itf8(reader)?;
+        }
+        let embedded_reference_content_id = read_itf8(reader)?;
+        let mut reference_md5 = [0; 16];
+        reader.read_exact(&mut reference_md5)?;
+
+        Ok(Self {
+            reference_sequence_id,
+            record_count,
+            embedded_reference_content_id,
+            reference_md5,
+        })
+    }
+}
+
+//========//
+// Reader //
+//========//
+
+/// Reads the containers of a CRAM file.
+pub struct Reader<R> {
+    /// The underlying reader.
+    inner: R,
+}
+
+impl<R: Read> Reader<R> {
+    /// Creates a new [`Reader`].
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+
+    /// Reads the file definition at the start of the file. Only CRAM 3.x
+    /// files are supported.
+    pub fn read_file_definition(&mut self) -> io::Result<Version> {
+        let mut buf = [0; 26];
+        self.inner.read_exact(&mut buf)?;
+
+        if &buf[..4] != MAGIC_NUMBER {
+            return Err(invalid_data("invalid CRAM magic number"));
+        }
+
+        let version = Version {
+            major: buf[4],
+            minor: buf[5],
+        };
+
+        if version.major != 3 {
+            return Err(invalid_data(&format!(
+                "CRAM {}.{} is not supported (only CRAM 3.x is)",
+                version.major, version.minor
+            )));
+        }
+
+        Ok(version)
+    }
+
+    /// Reads the next container, returning `None` at the end of the file.
+    /// Files that were truncated at a container boundary (i.e., that are
+    /// missing the end-of-file container) simply end.
+    pub fn read_container(&mut self) -> io::Result<Option<Container>> {
+        // (1) The container header.
+        let length = match read_i32(&mut self.inner) {
+            Ok(length) => {
+                usize::try_from(length).map_err(|_| invalid_data("invalid container length"))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let reference_sequence_id = read_itf8(&mut self.inner)?;
+        let _alignment_start = read_itf8(&mut self.inner)?;
+        let _alignment_span = read_itf8(&mut self.inner)?;
+        let record_count = read_itf8(&mut self.inner)?;
+        let _record_counter = read_ltf8(&mut self.inner)?;
+        let base_count = read_ltf8(&mut self.inner)?;
+        let _block_count = read_itf8(&mut self.inner)?;
+        let slice_count = read_size(&mut self.inner)?;
+        for _ in 0..slice_count {
+            read_itf8(&mut self.inner)?;
+        }
+        let _crc32 = read_i32(&mut self.inner)?;
+
+        // (2) The blocks, which are read until the body of the container is
+        // exhausted.
+        let mut body = vec![0; length];
+        self.inner.read_exact(&mut body)?;
+
+        let mut blocks = Vec::new();
+        let mut body = &body[..];
+        while !body.is_empty() {
+            blocks.push(Block::read(&mut body)?);
+        }
+
+        Ok(Some(Container {
+            reference_sequence_id,
+            record_count,
+            base_count,
+            slice_count,
+            blocks,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_reads_variable_length_integers() -> io::Result<()> {
+        let itf8: &[(&[u8], i32)] = &[
+            (&[0x00], 0),
+            (&[0x7f], 127),
+            (&[0x80, 0xff], 255),
+            (&[0xc0, 0xff, 0xff], 65_535),
+            (&[0xe0, 0xff, 0xff, 0xff], 16_777_215),
+            (&[0xff, 0xff, 0xff, 0xff, 0x0f], -1),
+        ];
+        for (mut data, expected) in itf8.iter().copied() {
+            assert_eq!(read_itf8(&mut data)?, expected);
+        }
+
+        let ltf8: &[(&[u8], i64)] = &[
+            (&[0x00], 0),
+            (&[0x80, 0xff], 255),
+            (&[0xc0, 0xff, 0xff], 65_535),
+            (&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], -1),
+        ];
+        for (mut data, expected) in ltf8.iter().copied() {
+            assert_eq!(read_ltf8(&mut data)?, expected);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+//! Detection of the format of alignment files (SAM, BAM, or CRAM).
+//!
+//! Alignment files are often misnamed (e.g., a SAM file written by a quick
+//! conversion into a file named `.bam`, or a file read from a pipe that has no
+//! extension at all), so the format is sniffed from the first bytes of the
+//! file rather than trusted from its name:
+//!
+//! * CRAM files start with the `CRAM` magic number.
+//! * BAM files are BGZF compressed and start with the `BAM\1` magic number
+//!   once decompressed. A BGZF compressed file whose contents do not is taken
+//!   to be a bgzipped SAM file.
+//! * SAM files start with their header (`@`).
+//!
+//! Files that cannot be sniffed (e.g., a SAM file without a header) fall back to
+//! their extension.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use noodles::bgzf;
+
+use crate::utils::formats::{alignment::AlignmentFormat, BioinformaticsFileFormat};
+
+/// The magic number at the start of every CRAM file.
+const CRAM_MAGIC_NUMBER: &[u8; 4] = b"CRAM";
+
+/// The magic number at the start of every gzip (and thus BGZF) file.
+pub(crate) const GZIP_MAGIC_NUMBER: &[u8; 2] = &[0x1f, 0x8b];
+
+/// The magic number at the start of every (decompressed) BAM file.
+const BAM_MAGIC_NUMBER: &[u8; 4] = b"BAM\x01";
+
+/// Reads up to `buf.len()` bytes, stopping early only at the end of the
+/// stream.
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(read)
+}
+
+/// Sniffs the format of an alignment file from its first bytes, if possible.
+pub fn sniff<R: Read>(mut reader: R) -> io::Result<Option<AlignmentFormat>> {
+    let mut magic = [0; 4];
+    let read = read_up_to(&mut reader, &mut magic)?;
+    let magic = &magic[..read];
+
+    // (1) CRAM files are recognized by their magic number.
+    if magic == CRAM_MAGIC_NUMBER {
+        return Ok(Some(AlignmentFormat::Cram));
+    }
+
+    // (2) BGZF compressed files are either BAM or bgzipped SAM files,
+    // depending on their decompressed contents.
+    if magic.starts_with(GZIP_MAGIC_NUMBER) {
+        let mut decompressed = bgzf::Reader::new(magic.chain(reader));
+        let mut magic = [0; 4];
+        let read = read_up_to(&mut decompressed, &mut magic)?;
+
+        return Ok(match &magic[..read] {
+            m if m == BAM_MAGIC_NUMBER => Some(AlignmentFormat::Bam),
+            [b'@', ..] => Some(AlignmentFormat::Sam),
+            _ => None,
+        });
+    }
+
+    // (3) Uncompressed SAM files are recognized by their header.
+    Ok(magic.starts_with(b"@").then_some(AlignmentFormat::Sam))
+}
+
+/// Detects the format of an alignment file from its contents or, failing
+/// that, from its extension.
+pub fn detect(src: &Path) -> anyhow::Result<AlignmentFormat> {
+    let file = File::open(src).with_context(|| format!("opening {}", src.display()))?;
+
+    if let Some(format) =
+        sniff(file).with_context(|| format!("reading the start of {}", src.display()))?
+    {
+        return Ok(format);
+    }
+
+    match BioinformaticsFileFormat::try_detect(src) {
+        Some(BioinformaticsFileFormat::SAM) => Ok(AlignmentFormat::Sam),
+        Some(BioinformaticsFileFormat::BAM) => Ok(AlignmentFormat::Bam),
+        Some(BioinformaticsFileFormat::CRAM) => Ok(AlignmentFormat::Cram),
+        _ => bail!(
+            "Could not detect the format of {}: expected a SAM, BAM, or CRAM file.",
+            src.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, path::PathBuf};
+
+    use crate::utils::formats::alignment::AlignmentReader;
+
+    use super::*;
+
+    const SAM: &[u8] = b"@HD\tVN:1.6\n\
+        @SQ\tSN:chr1\tLN:100\n\
+        r1\t0\tchr1\t1\t60\t4M\t*\t0\t0\tACGT\tIIII\tNM:i:0\tAS:i:4\n\
+        r2\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII\n";
+
+    #[test]
+    pub fn it_detects_and_reads_alignment_files_by_their_contents() -> anyhow::Result<()> {
+        let bam = PathBuf::from("tests/fixtures/paired.bam");
+        assert_eq!(detect(&bam)?, AlignmentFormat::Bam);
+
+        // Misnamed files are detected by their contents.
+        let misnamed = std::env::temp_dir().join(format!("ngs-{}.bam", std::process::id()));
+        std::fs::write(&misnamed, b"CRAM\x03\x00")?;
+        assert_eq!(detect(&misnamed)?, AlignmentFormat::Cram);
+        std::fs::write(&misnamed, SAM)?;
+        assert_eq!(detect(&misnamed)?, AlignmentFormat::Sam);
+
+        let mut writer = bgzf::Writer::new(Vec::new());
+        writer.write_all(SAM)?;
+        let bgzipped = writer.finish()?;
+        assert_eq!(sniff(&bgzipped[..])?, Some(AlignmentFormat::Sam));
+        assert_eq!(sniff(&b""[..])?, None);
+
+        // Plain and bgzipped SAM files are read alike.
+        for data in [SAM.to_vec(), bgzipped] {
+            let mut reader = AlignmentReader::new(&data[..], AlignmentFormat::Sam, None)?;
+            let (header, reference_sequences) = reader.read_header()?;
+            assert_eq!(reference_sequences.len(), 1);
+
+            let records = reader
+                .records(&header)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0].read_name().map(|name| name.as_ref()), Some("r1"));
+            // Every optional field is read, not just the first.
+            assert_eq!(records[0].data().len(), 2);
+        }
+
+        std::fs::remove_file(&misnamed)?;
+
+        Ok(())
+    }
+}
@@ -26,13 +26,9 @@ where
             Ok(file.map(BufReader::new).map(fasta::Reader::new)?)
         }
         Some(format) => bail!("incompatible formats: required FASTA, found {}", format),
-        None => {
-            let ext = path
-                .extension()
-                .expect("file extension to exist")
-                .to_str()
-                .expect("extension to be convertible to &str");
-            bail!("Not able to determine filetype for extension: {}", ext)
-        }
+        None => bail!(
+            "Not able to determine filetype from the extension of: {}",
+            path.display()
+        ),
     }
 }
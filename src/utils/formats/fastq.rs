@@ -2,16 +2,95 @@
 
 use std::{
     fs::File,
-    io::{BufWriter, Write},
+    io::{BufRead, BufReader, BufWriter, Write},
     path::Path,
 };
 
-use anyhow::bail;
-use flate2::{write::GzEncoder, Compression};
-use noodles::fastq;
+use anyhow::{bail, Context};
+use flate2::{bufread::MultiGzDecoder, write::GzEncoder, Compression};
+use noodles::{
+    fastq,
+    sam::{
+        alignment::Record,
+        record::{Flags, QualityScores, ReadName, Sequence},
+    },
+};
+
+use crate::utils::{
+    checksum::{self, Checksum},
+    outputs,
+};
 
 use super::BioinformaticsFileFormat;
 
+/// Attempts to open a FASTQ file (optionally gzipped) for reading from a given
+/// source. The (compressed) file is checksummed as it is read.
+pub fn open<P>(src: P) -> anyhow::Result<(fastq::Reader<Box<dyn BufRead>>, Checksum)>
+where
+    P: AsRef<Path>,
+{
+    let path = src.as_ref();
+    let (file, checksum) = checksum::open(path)
+        .with_context(|| format!("Could not open FASTQ file: {}", path.display()))?;
+    let file = BufReader::new(file);
+
+    match BioinformaticsFileFormat::try_detect(path) {
+        Some(BioinformaticsFileFormat::FASTQ_GZ) => {
+            let reader = BufReader::new(MultiGzDecoder::new(file));
+            Ok((fastq::Reader::new(Box::new(reader)), checksum))
+        }
+        Some(BioinformaticsFileFormat::FASTQ) => Ok((fastq::Reader::new(Box::new(file)), checksum)),
+        Some(format) => bail!("incompatible formats: required FASTQ, found {}", format),
+        None => bail!(
+            "Not able to determine filetype for FASTQ file: {}",
+            path.display()
+        ),
+    }
+}
+
+/// Converts a FASTQ record into an unmapped SAM record so that it can be
+/// processed by the record-based quality control facets. Quality scores are
+/// expected to be Phred+33 encoded. Anything in the name following the first
+/// whitespace character (i.e., the comment) is dropped, as it is not permitted
+/// within SAM read names.
+pub fn to_unmapped_record(record: &fastq::Record) -> anyhow::Result<Record> {
+    let name = String::from_utf8_lossy(record.name());
+    let name = name
+        .split(char::is_whitespace)
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    let quality_scores = record
+        .quality_scores()
+        .iter()
+        .map(|score| score.checked_sub(b'!'))
+        .collect::<Option<Vec<u8>>>()
+        .with_context(|| format!("Invalid quality scores for read: {}", name))?;
+
+    let sequence = record
+        .sequence()
+        .iter()
+        .map(|base| base.to_ascii_uppercase())
+        .collect::<Vec<u8>>();
+
+    Ok(Record::builder()
+        .set_read_name(
+            name.parse::<ReadName>()
+                .with_context(|| format!("Invalid read name: {}", name))?,
+        )
+        .set_flags(Flags::UNMAPPED)
+        .set_sequence(
+            Sequence::try_from(sequence)
+                .with_context(|| format!("Invalid sequence for read: {}", name))?,
+        )
+        .set_quality_scores(
+            QualityScores::try_from(quality_scores)
+                .with_context(|| format!("Invalid quality scores for read: {}", name))?,
+        )
+        .build())
+}
+
 /// Attempts to open a FASTQ file from a given source.
 pub fn writer<P>(src: P) -> anyhow::Result<fastq::Writer<Box<dyn Write>>>
 where
@@ -19,6 +98,9 @@ where
 {
     let path = src.as_ref();
     let file = File::create(path);
+    if file.is_ok() {
+        outputs::record(path, "fastq");
+    }
 
     match BioinformaticsFileFormat::try_detect(path) {
         Some(BioinformaticsFileFormat::FASTQ_GZ) => {
@@ -32,13 +114,37 @@ where
             Ok(fastq::Writer::new(Box::new(writer)))
         }
         Some(format) => bail!("incompatible formats: required FASTQ, found {}", format),
-        None => {
-            let ext = path
-                .extension()
-                .expect("file extension to exist")
-                .to_str()
-                .expect("extension to be convertible to &str");
-            bail!("Not able to determine filetype for extension: {}", ext)
-        }
+        None => bail!(
+            "Not able to determine filetype from the extension of: {}",
+            path.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_converts_fastq_records_to_unmapped_records() -> anyhow::Result<()> {
+        let fastq = fastq::Record::new("r0 1:N:0:ACGT", "acgN", "!+5I");
+        let record = to_unmapped_record(&fastq)?;
+
+        assert!(record.flags().is_unmapped());
+        assert_eq!(
+            record.read_name().map(|name| name.to_string()).unwrap(),
+            "r0"
+        );
+        assert_eq!(record.sequence().to_string(), "ACGN");
+
+        let scores: Vec<u8> = record
+            .quality_scores()
+            .as_ref()
+            .iter()
+            .map(|score| u8::from(*score))
+            .collect();
+        assert_eq!(scores, vec![0, 10, 20, 40]);
+
+        Ok(())
     }
 }
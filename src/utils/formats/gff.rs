@@ -1,9 +1,10 @@
 //! Utilities related to opening and manipulating GFF files.
 
-use anyhow::bail;
+use anyhow::{bail, Context};
 use flate2::read::MultiGzDecoder;
 use noodles::gff;
 use std::{
+    collections::HashSet,
     fs::File,
     io::{BufRead, BufReader},
     path::Path,
@@ -29,13 +30,116 @@ where
             Ok(gff::Reader::new(Box::new(reader)))
         }
         Some(format) => bail!("incompatible formats: required GFF, found {}", format),
-        None => {
-            let ext = path
-                .extension()
-                .expect("file extension to exist")
-                .to_str()
-                .expect("extension to be convertible to &str");
-            bail!("Not able to determine filetype for extension: {}", ext)
+        None => bail!(
+            "Not able to determine filetype from the extension of: {}",
+            path.display()
+        ),
+    }
+}
+
+/// An iterator over the records of a GFF file that are on one of a set of
+/// retained reference sequences. The reference sequence name is the first
+/// column of each line, so the records on other sequences are skipped without
+/// being parsed, which is much faster than parsing (and then discarding) every
+/// record of a full annotation (e.g., GENCODE) when only some of its sequences
+/// are needed.
+pub struct RetainedRecords<'a, R> {
+    /// The GFF reader.
+    reader: &'a mut gff::Reader<R>,
+
+    /// The names of the reference sequences whose records are retained.
+    retained: &'a HashSet<String>,
+
+    /// The buffer that each line is read into.
+    line: String,
+
+    /// The number of records that were skipped.
+    skipped: usize,
+}
+
+impl<'a, R: BufRead> RetainedRecords<'a, R> {
+    /// Creates a new [`RetainedRecords`].
+    pub fn new(reader: &'a mut gff::Reader<R>, retained: &'a HashSet<String>) -> Self {
+        Self {
+            reader,
+            retained,
+            line: String::new(),
+            skipped: 0,
+        }
+    }
+
+    /// The number of records (so far) that were skipped because they are on a
+    /// reference sequence that is not retained.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+impl<R: BufRead> Iterator for RetainedRecords<'_, R> {
+    type Item = anyhow::Result<gff::Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e.into())),
+            }
+
+            // Comments and directives are skipped, and no records follow the
+            // embedded FASTA (if any).
+            if self.line.starts_with('#') {
+                if self.line.trim_end() == "##FASTA" {
+                    return None;
+                }
+
+                continue;
+            }
+
+            let name = self.line.split('\t').next().unwrap_or_default();
+            if name.trim().is_empty() {
+                continue;
+            }
+
+            if !self.retained.contains(name) {
+                self.skipped += 1;
+                continue;
+            }
+
+            return Some(
+                self.line
+                    .parse::<gff::Record>()
+                    .with_context(|| format!("Could not parse GFF record: {}", self.line)),
+            );
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_only_parses_records_on_retained_sequences() -> anyhow::Result<()> {
+        let data = b"##gff-version 3\n\
+            chr1\tHAVANA\tgene\t1\t100\t.\t+\t.\tgene_id=g1\n\
+            # a comment\n\
+            chrUn\tHAVANA\tgene\t1\t100\tnot a score\t+\t.\tgene_id=g2\n\
+            chr2\tHAVANA\texon\t5\t50\t.\t-\t.\tgene_id=g3\n\
+            ##FASTA\n\
+            >chr1\n";
+        let mut reader = gff::Reader::new(&data[..]);
+        let retained = HashSet::from([String::from("chr1"), String::from("chr2")]);
+
+        let mut records = RetainedRecords::new(&mut reader, &retained);
+        let names = records
+            .by_ref()
+            .map(|record| record.map(|r| r.reference_sequence_name().to_string()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        assert_eq!(names, ["chr1", "chr2"]);
+        assert_eq!(records.skipped(), 1);
+        Ok(())
+    }
+}
@@ -1,15 +1,17 @@
 //! Utilities related to opening and manipulating SAM files.
 
+use anyhow::Context;
 use noodles::sam;
 use regex::{Captures, Regex};
 
 /// Corrects common header mistakes. See the inline comments for the things that
 /// are automatically corrected.
 pub fn correct_common_header_mistakes(header: String) -> String {
-    // (1) Corrects any lowercase platform units in the read group to be all
+    // (1) Corrects any lowercase platforms in the read group to be all
     // uppercase. This is especially important for data that contains 'illumina'
-    // instead of the correct 'ILLUMINA'.
-    let pattern = Regex::new("(\tPL:)(.+)").unwrap();
+    // instead of the correct 'ILLUMINA'. Only the value of the `PL` field is
+    // corrected, not any of the fields that follow it.
+    let pattern = Regex::new("(\tPL:)([^\t\n]+)").unwrap();
     let replaced = pattern.replace_all(&header, |c: &Captures<'_>| {
         format!("{}{}", &c[1], c[2].to_uppercase())
     });
@@ -19,15 +21,17 @@ pub fn correct_common_header_mistakes(header: String) -> String {
 
 /// Parses a SAM/BAM/CRAM header from a string while also correcting common
 /// header mistakes.
-pub fn parse_header(header: String) -> sam::Header {
+pub fn parse_header(header: String) -> anyhow::Result<sam::Header> {
     correct_common_header_mistakes(header)
         .parse()
-        .expect("Could not parse SAM/BAM/CRAM header.")
+        .with_context(|| "Could not parse SAM/BAM/CRAM header.")
 }
 
 #[cfg(test)]
 mod tests {
 
+    use crate::utils::arbitrary::{self, check};
+
     use super::*;
 
     #[test]
@@ -37,4 +41,36 @@ mod tests {
 
         assert_eq!(correct_common_header_mistakes(data.to_string()), expected);
     }
+
+    #[test]
+    pub fn it_only_corrects_the_platform_of_read_groups() {
+        check(0, arbitrary::sam_header, |header| {
+            let corrected = correct_common_header_mistakes(header.clone());
+
+            for (before, after) in header.lines().zip(corrected.lines()) {
+                for (a, b) in before.split('\t').zip(after.split('\t')) {
+                    let expected = match a.strip_prefix("PL:") {
+                        Some(platform) => format!("PL:{}", platform.to_uppercase()),
+                        None => a.to_string(),
+                    };
+
+                    if b != expected {
+                        return Err(format!("{:?} was corrected to {:?}", a, b));
+                    }
+                }
+            }
+
+            parse_header(header.clone()).map_err(|e| e.to_string())?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    pub fn it_returns_errors_rather_than_panicking_on_malformed_headers() {
+        check(1, arbitrary::malformed_sam_header, |header| {
+            // Either outcome is acceptable, as long as it is not a panic.
+            let _ = parse_header(header.clone());
+            Ok(())
+        });
+    }
 }
@@ -0,0 +1,185 @@
+//! Utilities related to opening and reading sites from VCF files.
+//!
+//! Only the fixed columns needed to describe a site (`CHROM`, `POS`, `REF`, and
+//! `ALT`) are read, so no VCF parsing library is required. Only biallelic
+//! single nucleotide variants are supported as sites; all other records are
+//! skipped.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use flate2::read::MultiGzDecoder;
+
+use super::BioinformaticsFileFormat;
+
+/// A biallelic single nucleotide variant site.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Site {
+    /// The one-based position of the site.
+    pub position: usize,
+
+    /// The (uppercase) reference base.
+    pub reference: u8,
+
+    /// The (uppercase) alternate base.
+    pub alternate: u8,
+}
+
+/// The sites read from a VCF file, sorted by position within each sequence.
+#[derive(Debug, Default)]
+pub struct Sites {
+    /// The sites for each sequence.
+    pub sequences: HashMap<String, Vec<Site>>,
+
+    /// The number of records that were skipped because they were not biallelic
+    /// single nucleotide variants.
+    pub skipped_records: usize,
+}
+
+/// Attempts to open a VCF file (optionally compressed with gzip or BGZF) from
+/// a given source.
+pub fn open<P>(src: P) -> anyhow::Result<Box<dyn BufRead>>
+where
+    P: AsRef<Path>,
+{
+    let path = src.as_ref();
+    let file =
+        File::open(path).with_context(|| format!("Could not open VCF file: {}", path.display()))?;
+
+    match BioinformaticsFileFormat::try_detect(path) {
+        Some(BioinformaticsFileFormat::VCF_GZ) => {
+            Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+        }
+        Some(BioinformaticsFileFormat::VCF) => Ok(Box::new(BufReader::new(file))),
+        Some(BioinformaticsFileFormat::BCF) => bail!(
+            "BCF files are not yet supported. Please convert your file to VCF \
+            (e.g., with `bcftools view`) and try again."
+        ),
+        Some(format) => bail!("incompatible formats: required VCF, found {}", format),
+        None => bail!(
+            "Not able to determine filetype for VCF file: {}",
+            path.display()
+        ),
+    }
+}
+
+/// Opens a VCF file and reads all of the biallelic single nucleotide variant
+/// sites from it, logging the number of records that were skipped.
+pub fn read_sites_from_path<P>(src: P) -> anyhow::Result<Sites>
+where
+    P: AsRef<Path>,
+{
+    let path = src.as_ref();
+    let sites = read_sites(open(path)?)
+        .with_context(|| format!("Error reading sites from VCF file: {}", path.display()))?;
+
+    if sites.skipped_records > 0 {
+        tracing::info!(
+            "Skipped {} records in {} that are not biallelic SNVs.",
+            sites.skipped_records,
+            path.display()
+        );
+    }
+
+    Ok(sites)
+}
+
+/// Reads all of the biallelic single nucleotide variant sites from a VCF.
+pub fn read_sites<R: BufRead>(reader: R) -> anyhow::Result<Sites> {
+    let mut sites = Sites::default();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+
+        let mut columns = line.split('\t');
+        let (chrom, pos, reference, alternate) = match (
+            columns.next(),
+            columns.next(),
+            columns.nth(1),
+            columns.next(),
+        ) {
+            (Some(chrom), Some(pos), Some(reference), Some(alternate)) => {
+                (chrom, pos, reference, alternate)
+            }
+            _ => bail!("Invalid VCF record on line {}: too few columns.", i + 1),
+        };
+
+        let position = pos
+            .parse::<usize>()
+            .with_context(|| format!("Invalid VCF position on line {}: {}", i + 1, pos))?;
+
+        let is_base =
+            |s: &str| s.len() == 1 && matches!(s.as_bytes()[0], b'A' | b'C' | b'G' | b'T');
+        let (reference, alternate) = (
+            reference.to_ascii_uppercase(),
+            alternate.to_ascii_uppercase(),
+        );
+
+        if !is_base(&reference) || !is_base(&alternate) {
+            sites.skipped_records += 1;
+            continue;
+        }
+
+        sites
+            .sequences
+            .entry(chrom.to_string())
+            .or_default()
+            .push(Site {
+                position,
+                reference: reference.as_bytes()[0],
+                alternate: alternate.as_bytes()[0],
+            });
+    }
+
+    for sequence in sites.sequences.values_mut() {
+        sequence.sort_by_key(|site| site.position);
+        sequence.dedup_by_key(|site| site.position);
+    }
+
+    Ok(sites)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_reads_biallelic_snv_sites() -> anyhow::Result<()> {
+        let vcf = "##fileformat=VCFv4.2\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+            chr1\t200\trs2\tc\tT\t.\t.\t.\n\
+            chr1\t100\trs1\tA\tG\t.\t.\t.\n\
+            chr1\t300\t.\tA\tG,T\t.\t.\t.\n\
+            chr2\t50\t.\tAC\tA\t.\t.\t.\n";
+
+        let sites = read_sites(vcf.as_bytes())?;
+        assert_eq!(sites.skipped_records, 2);
+        assert_eq!(
+            sites.sequences["chr1"],
+            vec![
+                Site {
+                    position: 100,
+                    reference: b'A',
+                    alternate: b'G'
+                },
+                Site {
+                    position: 200,
+                    reference: b'C',
+                    alternate: b'T'
+                },
+            ]
+        );
+        assert!(!sites.sequences.contains_key("chr2"));
+
+        assert!(read_sites("chr1\t100\n".as_bytes()).is_err());
+        Ok(())
+    }
+}
@@ -1,13 +1,19 @@
 //! Utilities related to reference genomes.
 
+pub mod gencode;
+pub mod hybrid;
+pub mod microbial;
 pub mod microsoft;
 pub mod ncbi;
 pub mod one_thousand_genomes;
 pub mod t2t_consortium;
 
 use std::fmt;
-use std::{fmt::Debug, rc::Rc, str::FromStr};
+use std::{borrow::Cow, fmt::Debug, rc::Rc, str::FromStr};
 
+use self::gencode::grcm39::GRCm39;
+use self::hybrid::HybridGenome;
+use self::microbial::get_all_microbial_genomes;
 use self::microsoft::hg38m1x::HG38M1X;
 use self::ncbi::grch38_no_alt::GRCh38NoAltAnalysisSet;
 use self::one_thousand_genomes::hs37d5::HS37D5;
@@ -20,19 +26,33 @@ use self::t2t_consortium::t2t_chm13::T2T_CHM13;
 /// Gets all of the supported reference genomes for the tool. When new reference
 /// genomes are added, this needs to be updated.
 pub fn get_all_reference_genomes() -> Vec<Box<dyn ReferenceGenome>> {
-    vec![
+    let mut genomes: Vec<Box<dyn ReferenceGenome>> = vec![
         Box::new(HS37D5),
         Box::new(GRCh38NoAltAnalysisSet),
         Box::new(HG38M1X),
         Box::new(T2T_CHM13),
-    ]
+        Box::new(GRCm39),
+    ];
+
+    for genome in get_all_microbial_genomes() {
+        genomes.push(Box::new(genome));
+    }
+
+    genomes
 }
 
 /// Utility method to map a string (generally passed on the command line) to a
 /// reference genome. I considered implementing FromStr for ReferenceGenome, but
 /// that solution felt a lot less clean considering we have the names stored in
 /// `.name()` and want to do the lowercase matching.
+///
+/// Names containing a `+` are parsed as a [`HybridGenome`] (e.g.,
+/// `human_GRCh38+mouse_GRCm39`).
 pub fn get_reference_genome(s: &str) -> Option<Box<dyn ReferenceGenome>> {
+    if s.contains('+') {
+        return HybridGenome::parse(s).map(|genome| Box::new(genome) as Box<dyn ReferenceGenome>);
+    }
+
     get_all_reference_genomes()
         .into_iter()
         .find(|genome| s.eq_ignore_ascii_case(genome.name()))
@@ -65,6 +85,11 @@ pub fn get_primary_assembly(reference_genome: Rc<Box<dyn ReferenceGenome>>) -> V
 
 /// Gets all sequences for the given assembly.
 pub fn get_all_sequences(reference_genome: Rc<Box<dyn ReferenceGenome>>) -> Vec<Sequence> {
+    all_sequences(reference_genome.as_ref().as_ref())
+}
+
+/// Gets all sequences for the given assembly (see [`get_all_sequences`]).
+fn all_sequences<G: ReferenceGenome + ?Sized>(reference_genome: &G) -> Vec<Sequence> {
     let mut all: Vec<Sequence> = Vec::new();
 
     if let Some(autosomes) = reference_genome.autosomes() {
@@ -95,9 +120,42 @@ pub fn get_all_sequences(reference_genome: Rc<Box<dyn ReferenceGenome>>) -> Vec<
         all.extend(decoy_sequences);
     }
 
+    if let Some(viral_sequences) = reference_genome.viral_sequences() {
+        all.extend(viral_sequences);
+    }
+
     all
 }
 
+/// Gets the viral (and other microbial) sequences for a given reference
+/// genome, including the Epstein-Barr virus if the genome has it.
+pub fn get_viral_sequences(reference_genome: Rc<Box<dyn ReferenceGenome>>) -> Vec<Sequence> {
+    let mut viral: Vec<Sequence> = Vec::new();
+
+    if let Some(ebv_chromosome) = reference_genome.ebv_chromosome() {
+        viral.push(ebv_chromosome);
+    }
+
+    if let Some(viral_sequences) = reference_genome.viral_sequences() {
+        viral.extend(viral_sequences);
+    }
+
+    viral
+}
+
+/// Normalizes the name of a sequence within a header before it is looked up
+/// in a reference genome or an annotation. Pangenome-aligned BAMs (e.g.,
+/// those projected onto a reference path by `vg giraffe`) name their
+/// sequences by the PanSN convention, `sample#haplotype#contig` (e.g.,
+/// `GRCh38#0#chr1`), of which only the contig is kept. Any other name is
+/// returned as-is.
+pub fn normalize_sequence_name(name: &str) -> &str {
+    match name.rfind('#') {
+        Some(i) => &name[i + 1..],
+        None => name,
+    }
+}
+
 //====================//
 // Types of sequences //
 //====================//
@@ -127,6 +185,10 @@ pub enum SequenceKind {
     /// typically inserted to clean up results in variant calling and remove
     /// contaminants.
     Decoy,
+
+    /// A sequence representing a viral (or other microbial) genome, such as
+    /// SARS-CoV-2 or PhiX.
+    Viral,
 }
 
 impl FromStr for SequenceKind {
@@ -140,6 +202,7 @@ impl FromStr for SequenceKind {
             "unlocalized" => Ok(SequenceKind::Unlocalized),
             "unplaced" => Ok(SequenceKind::Unplaced),
             "decoy" => Ok(SequenceKind::Decoy),
+            "viral" => Ok(SequenceKind::Viral),
             s => Err(format!("Unknown sequence kind: {}", s)),
         }
     }
@@ -153,7 +216,7 @@ impl FromStr for SequenceKind {
 /// kind of the sequence.
 #[derive(Clone)]
 pub struct Sequence {
-    name: &'static str,
+    name: Cow<'static, str>,
     #[allow(dead_code)]
     kind: SequenceKind,
 }
@@ -161,12 +224,24 @@ pub struct Sequence {
 impl Sequence {
     /// Creates a new [`Sequence`].
     pub fn new(name: &'static str, kind: SequenceKind) -> Self {
-        Self { name, kind }
+        Self {
+            name: Cow::Borrowed(name),
+            kind,
+        }
     }
 
     /// Gives the name of the [`Sequence`].
     pub fn name(&self) -> &str {
-        self.name
+        &self.name
+    }
+
+    /// Creates a copy of the [`Sequence`] whose name is prefixed by a label
+    /// (e.g., `mouse_chr1`), as within a [`HybridGenome`].
+    pub fn prefixed(&self, label: &str) -> Self {
+        Self {
+            name: Cow::Owned(format!("{}_{}", label, self.name)),
+            kind: self.kind.clone(),
+        }
     }
 }
 
@@ -196,6 +271,30 @@ pub enum GenomeBasis {
 
     /// T2T-based reference genomes
     T2tChm13,
+
+    /// GRCm39-based (mouse) reference genomes
+    GRCm39,
+
+    /// Viral and microbial genomes, which are based on a single RefSeq
+    /// sequence
+    Microbial {
+        /// The species (or strain) of the genome.
+        species: &'static str,
+
+        /// The RefSeq accession of the genome's sequence.
+        accession: &'static str,
+    },
+}
+
+impl GenomeBasis {
+    /// The species that reference genomes with this basis belong to.
+    pub fn species(&self) -> &'static str {
+        match self {
+            Self::GRCh37 | Self::GRCh38 | Self::T2tChm13 => "human",
+            Self::GRCm39 => "mouse",
+            Self::Microbial { species, .. } => species,
+        }
+    }
 }
 
 impl fmt::Display for GenomeBasis {
@@ -204,6 +303,8 @@ impl fmt::Display for GenomeBasis {
             Self::GRCh37 => write!(f, "GRCh37"),
             Self::GRCh38 => write!(f, "GRCh38"),
             Self::T2tChm13 => write!(f, "T2tChm13"),
+            Self::GRCm39 => write!(f, "GRCm39"),
+            Self::Microbial { accession, .. } => write!(f, "{}", accession),
         }
     }
 }
@@ -216,7 +317,7 @@ impl fmt::Display for GenomeBasis {
 /// supported by this tool.
 pub trait ReferenceGenome: Debug {
     /// Name of the reference genome.
-    fn name(&self) -> &'static str;
+    fn name(&self) -> &str;
 
     /// If available, the version of the reference genome.
     fn version(&self) -> Option<&'static str>;
@@ -289,4 +390,42 @@ pub trait ReferenceGenome: Debug {
 
     /// If available, any decoy sequences included in this reference genome.
     fn decoy_sequences(&self) -> Option<Vec<Sequence>>;
+
+    /// If available, any viral (or other microbial) sequences included in
+    /// this reference genome, other than the Epstein-Barr virus.
+    fn viral_sequences(&self) -> Option<Vec<Sequence>> {
+        None
+    }
+
+    /// The species that make up this reference genome. Only a
+    /// [`HybridGenome`] has more than one.
+    fn species(&self) -> Vec<String> {
+        vec![self.basis().species().to_string()]
+    }
+
+    /// The species that a sequence (by name) belongs to, or `None` if the
+    /// sequence is not part of this reference genome.
+    fn species_of(&self, sequence: &str) -> Option<String> {
+        all_sequences(self)
+            .iter()
+            .any(|s| s.name() == sequence)
+            .then(|| self.basis().species().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_normalizes_pangenome_sequence_names() {
+        assert_eq!(normalize_sequence_name("GRCh38#0#chr1"), "chr1");
+        assert_eq!(normalize_sequence_name("CHM13#chrX"), "chrX");
+        assert_eq!(normalize_sequence_name("chr1"), "chr1");
+
+        let genome = get_reference_genome("GRCh38_no_alt_AnalysisSet").unwrap();
+        assert!(genome
+            .species_of(normalize_sequence_name("GRCh38#0#chrEBV"))
+            .is_some());
+    }
 }
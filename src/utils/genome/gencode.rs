@@ -0,0 +1,3 @@
+//! Reference genomes provided by GENCODE.
+
+pub mod grcm39;
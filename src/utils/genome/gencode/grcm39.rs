@@ -0,0 +1,132 @@
+//! This reference genome represents the primary assembly of the Mouse Genome
+//! version 39 as distributed by GENCODE (GCA_000001635.9). It is most often
+//! used alongside a human reference genome as part of a hybrid genome (e.g.,
+//! `GRCh38_no_alt_AnalysisSet+mouse_GRCm39`) for patient-derived xenografts.
+//!
+//! Only the chromosomes are included; the unlocalized and unplaced scaffolds
+//! of the primary assembly are not.
+//!
+//! Link: <https://ftp.ebi.ac.uk/pub/databases/gencode/Gencode_mouse/release_M33/GRCm39.primary_assembly.genome.fa.gz>
+
+use crate::{
+    sequence,
+    utils::genome::{GenomeBasis, ReferenceGenome, Sequence},
+};
+
+/// Main struct for the GRCm39 reference genome.
+#[derive(Debug)]
+pub struct GRCm39;
+
+impl ReferenceGenome for GRCm39 {
+    fn name(&self) -> &'static str {
+        "GRCm39"
+    }
+
+    fn version(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn source(&self) -> &'static str {
+        "GENCODE"
+    }
+
+    fn basis(&self) -> GenomeBasis {
+        GenomeBasis::GRCm39
+    }
+
+    fn patch(&self) -> Option<usize> {
+        None
+    }
+
+    fn url(&self) -> Option<&'static str> {
+        Some("https://ftp.ebi.ac.uk/pub/databases/gencode/Gencode_mouse/release_M33/GRCm39.primary_assembly.genome.fa.gz")
+    }
+
+    fn autosomes(&self) -> Option<Vec<Sequence>> {
+        Some(vec![
+            sequence!("chr1", "chromosome"),
+            sequence!("chr2", "chromosome"),
+            sequence!("chr3", "chromosome"),
+            sequence!("chr4", "chromosome"),
+            sequence!("chr5", "chromosome"),
+            sequence!("chr6", "chromosome"),
+            sequence!("chr7", "chromosome"),
+            sequence!("chr8", "chromosome"),
+            sequence!("chr9", "chromosome"),
+            sequence!("chr10", "chromosome"),
+            sequence!("chr11", "chromosome"),
+            sequence!("chr12", "chromosome"),
+            sequence!("chr13", "chromosome"),
+            sequence!("chr14", "chromosome"),
+            sequence!("chr15", "chromosome"),
+            sequence!("chr16", "chromosome"),
+            sequence!("chr17", "chromosome"),
+            sequence!("chr18", "chromosome"),
+            sequence!("chr19", "chromosome"),
+        ])
+    }
+
+    fn sex_chromosomes(&self) -> Option<Vec<Sequence>> {
+        Some(vec![
+            sequence!("chrX", "chromosome"),
+            sequence!("chrY", "chromosome"),
+        ])
+    }
+
+    fn mitochondrion_chromosome(&self) -> Option<Sequence> {
+        Some(sequence!("chrM", "mitochondrion"))
+    }
+
+    fn ebv_chromosome(&self) -> Option<Sequence> {
+        None
+    }
+
+    fn unlocalized_sequences(&self) -> Option<Vec<Sequence>> {
+        None
+    }
+
+    fn unplaced_sequences(&self) -> Option<Vec<Sequence>> {
+        None
+    }
+
+    fn decoy_sequences(&self) -> Option<Vec<Sequence>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::utils::genome::get_primary_assembly;
+
+    use super::*;
+
+    #[test]
+    pub fn it_has_the_correct_number_of_autosomes() {
+        assert_eq!(GRCm39.autosomes().unwrap().len(), 19);
+    }
+
+    #[test]
+    pub fn it_has_the_correct_number_of_sex_chromosomes() {
+        assert_eq!(GRCm39.sex_chromosomes().unwrap().len(), 2);
+    }
+
+    #[test]
+    pub fn it_has_the_correct_number_of_sequence_in_the_primary_assembly() {
+        let grcm39: Rc<Box<dyn ReferenceGenome>> = Rc::new(Box::new(GRCm39));
+        assert_eq!(get_primary_assembly(grcm39).len(), 21);
+    }
+
+    #[test]
+    pub fn it_contains_the_mitochodrion_chromosome() {
+        assert_eq!(GRCm39.mitochondrion_chromosome().unwrap().name(), "chrM");
+    }
+
+    #[test]
+    pub fn it_is_a_mouse_genome() {
+        assert_eq!(GRCm39.species(), ["mouse"]);
+        assert_eq!(GRCm39.species_of("chr19").as_deref(), Some("mouse"));
+        assert_eq!(GRCm39.species_of("chr20"), None);
+    }
+}
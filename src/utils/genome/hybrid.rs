@@ -0,0 +1,258 @@
+//! Hybrid reference genomes, which combine the reference genomes of more than
+//! one species (e.g., human and mouse for patient-derived xenografts).
+//!
+//! A hybrid genome is named by its components joined with `+`, where each
+//! component is a supported reference genome that is optionally labeled by its
+//! species (e.g., `human_GRCh38_no_alt_AnalysisSet+mouse_GRCm39`). A component
+//! may also be named by its basis (e.g., `human_GRCh38`), in which case the
+//! first supported reference genome with that basis is used. Components
+//! without a label are labeled by the species of their basis.
+//!
+//! Within the hybrid genome, each sequence of a component may be named either
+//! as-is (e.g., `chr1`) or prefixed by the label of its component (e.g.,
+//! `mouse_chr1`), as is common when references are combined. A sequence named
+//! as-is that is found in more than one component (e.g., `chr1`) belongs to
+//! the first of them.
+
+use std::collections::HashSet;
+
+use super::{get_all_reference_genomes, GenomeBasis, ReferenceGenome, Sequence};
+
+/// One of the reference genomes that make up a [`HybridGenome`].
+#[derive(Debug)]
+pub struct Component {
+    /// The label of the component (generally, its species).
+    pub label: String,
+
+    /// The reference genome of the component.
+    pub genome: Box<dyn ReferenceGenome>,
+}
+
+impl Component {
+    /// Resolves a component from its name (see the [module-level
+    /// documentation](self)).
+    fn parse(s: &str) -> Option<Self> {
+        // (1) The component is a reference genome without a label.
+        if let Some(genome) = find(s) {
+            return Some(Self {
+                label: genome.basis().species().to_string(),
+                genome,
+            });
+        }
+
+        // (2) The component is a labeled reference genome. Labels may contain
+        // underscores themselves, so each split is tried in turn.
+        s.match_indices('_').find_map(|(i, _)| {
+            let (label, name) = (&s[..i], &s[i + 1..]);
+            find(name).map(|genome| Self {
+                label: label.to_string(),
+                genome,
+            })
+        })
+    }
+
+    /// Strips the label of this component from the name of a sequence, if
+    /// the name is prefixed by it.
+    fn strip<'a>(&self, sequence: &'a str) -> Option<&'a str> {
+        sequence
+            .strip_prefix(self.label.as_str())
+            .and_then(|rest| rest.strip_prefix('_'))
+    }
+}
+
+/// Finds a supported (non-hybrid) reference genome by its name or, failing
+/// that, by its basis.
+fn find(s: &str) -> Option<Box<dyn ReferenceGenome>> {
+    get_all_reference_genomes()
+        .into_iter()
+        .find(|genome| s.eq_ignore_ascii_case(genome.name()))
+        .or_else(|| {
+            get_all_reference_genomes()
+                .into_iter()
+                .find(|genome| s.eq_ignore_ascii_case(&genome.basis().to_string()))
+        })
+}
+
+/// A reference genome that combines the reference genomes of more than one
+/// species.
+#[derive(Debug)]
+pub struct HybridGenome {
+    /// The name of the hybrid genome.
+    name: String,
+
+    /// The components of the hybrid genome, in the order they were provided.
+    components: Vec<Component>,
+}
+
+impl HybridGenome {
+    /// Parses a hybrid genome from its name (e.g.,
+    /// `human_GRCh38+mouse_GRCm39`). Returns `None` unless there are at least
+    /// two components, each component is a supported reference genome, and
+    /// the labels of the components are distinct.
+    pub fn parse(s: &str) -> Option<Self> {
+        let components = s
+            .split('+')
+            .map(Component::parse)
+            .collect::<Option<Vec<_>>>()?;
+
+        let labels = components
+            .iter()
+            .map(|component| component.label.as_str())
+            .collect::<HashSet<_>>();
+        if components.len() < 2 || labels.len() != components.len() {
+            return None;
+        }
+
+        Some(Self {
+            name: s.to_string(),
+            components,
+        })
+    }
+
+    /// Gets the components of the hybrid genome.
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
+
+    /// Combines the sequences of each component (as selected by `f`), both
+    /// as-is and prefixed by the label of their component.
+    fn combine<F>(&self, f: F) -> Option<Vec<Sequence>>
+    where
+        F: Fn(&dyn ReferenceGenome) -> Option<Vec<Sequence>>,
+    {
+        let mut seen = HashSet::new();
+        let mut combined = Vec::new();
+
+        for component in &self.components {
+            for sequence in f(component.genome.as_ref()).unwrap_or_default() {
+                for sequence in [sequence.prefixed(&component.label), sequence] {
+                    if seen.insert(sequence.name().to_string()) {
+                        combined.push(sequence);
+                    }
+                }
+            }
+        }
+
+        (!combined.is_empty()).then_some(combined)
+    }
+}
+
+impl ReferenceGenome for HybridGenome {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn source(&self) -> &'static str {
+        "Hybrid"
+    }
+
+    fn basis(&self) -> GenomeBasis {
+        self.components[0].genome.basis()
+    }
+
+    fn patch(&self) -> Option<usize> {
+        self.components[0].genome.patch()
+    }
+
+    fn url(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn autosomes(&self) -> Option<Vec<Sequence>> {
+        self.combine(|genome| genome.autosomes())
+    }
+
+    fn sex_chromosomes(&self) -> Option<Vec<Sequence>> {
+        self.combine(|genome| genome.sex_chromosomes())
+    }
+
+    fn mitochondrion_chromosome(&self) -> Option<Sequence> {
+        // Only a single mitochondrion can be reported, so this is the
+        // mitochondrion of the first component. The others are still
+        // recognized by `species_of()`.
+        self.components[0].genome.mitochondrion_chromosome()
+    }
+
+    fn ebv_chromosome(&self) -> Option<Sequence> {
+        self.components[0].genome.ebv_chromosome()
+    }
+
+    fn unlocalized_sequences(&self) -> Option<Vec<Sequence>> {
+        self.combine(|genome| genome.unlocalized_sequences())
+    }
+
+    fn unplaced_sequences(&self) -> Option<Vec<Sequence>> {
+        self.combine(|genome| genome.unplaced_sequences())
+    }
+
+    fn decoy_sequences(&self) -> Option<Vec<Sequence>> {
+        self.combine(|genome| genome.decoy_sequences())
+    }
+
+    fn viral_sequences(&self) -> Option<Vec<Sequence>> {
+        self.combine(|genome| genome.viral_sequences())
+    }
+
+    fn species(&self) -> Vec<String> {
+        self.components
+            .iter()
+            .map(|component| component.label.clone())
+            .collect()
+    }
+
+    fn species_of(&self, sequence: &str) -> Option<String> {
+        // (1) Sequences prefixed by the label of a component belong to it.
+        for component in &self.components {
+            if let Some(name) = component.strip(sequence) {
+                if component.genome.species_of(name).is_some() {
+                    return Some(component.label.clone());
+                }
+            }
+        }
+
+        // (2) Otherwise, the sequence belongs to the first component that has
+        // a sequence by that name.
+        self.components
+            .iter()
+            .find(|component| component.genome.species_of(sequence).is_some())
+            .map(|component| component.label.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_parses_and_classifies_the_sequences_of_a_hybrid_genome() {
+        let genome = HybridGenome::parse("human_GRCh38+mouse_GRCm39").unwrap();
+        assert_eq!(genome.species(), ["human", "mouse"]);
+        assert_eq!(
+            genome.components()[0].genome.name(),
+            "GRCh38_no_alt_AnalysisSet"
+        );
+
+        assert_eq!(genome.species_of("chr1").as_deref(), Some("human"));
+        assert_eq!(genome.species_of("human_chr1").as_deref(), Some("human"));
+        assert_eq!(genome.species_of("mouse_chr1").as_deref(), Some("mouse"));
+        assert_eq!(genome.species_of("mouse_chrM").as_deref(), Some("mouse"));
+        assert_eq!(genome.species_of("mouse_chr22"), None);
+        assert_eq!(genome.species_of("chr23"), None);
+
+        // Each sequence is included both as-is and prefixed, but the mouse
+        // autosomes named as-is are the human autosomes.
+        assert_eq!(genome.autosomes().unwrap().len(), 22 * 2 + 19);
+
+        // Unlabeled components are labeled by their species.
+        let genome = HybridGenome::parse("T2T-CHM13+GRCm39").unwrap();
+        assert_eq!(genome.species(), ["human", "mouse"]);
+
+        assert!(HybridGenome::parse("GRCm39").is_none());
+        assert!(HybridGenome::parse("GRCh38+hs37d5").is_none());
+        assert!(HybridGenome::parse("human_GRCh38+mouse_GRCm40").is_none());
+    }
+}
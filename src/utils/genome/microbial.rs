@@ -0,0 +1,180 @@
+//! Small viral and microbial genomes that are commonly sequenced on their own
+//! (e.g., SARS-CoV-2), included within a reference genome (e.g., the
+//! Epstein-Barr virus in GRCh38), or added to one as a supplemental reference
+//! (e.g., `GRCh38_no_alt_AnalysisSet+HPV16`, see
+//! [`HybridGenome`](super::hybrid::HybridGenome)).
+//!
+//! Each genome is a single sequence, which may be named by its RefSeq
+//! accession or by any of its common aliases (e.g., `chrEBV` or `phiX174`).
+
+use super::{GenomeBasis, ReferenceGenome, Sequence, SequenceKind};
+
+/// A small viral or microbial genome made up of a single sequence.
+#[derive(Debug)]
+pub struct MicrobialGenome {
+    /// The name of the genome.
+    name: &'static str,
+
+    /// The species (or strain) of the genome.
+    species: &'static str,
+
+    /// The RefSeq accession of the genome's sequence.
+    accession: &'static str,
+
+    /// The other names that the genome's sequence commonly goes by.
+    aliases: &'static [&'static str],
+
+    /// The url where the genome's sequence is available.
+    url: &'static str,
+}
+
+/// The Epstein-Barr virus (human gammaherpesvirus 4).
+pub const EBV: MicrobialGenome = MicrobialGenome {
+    name: "EBV",
+    species: "EBV",
+    accession: "NC_007605.1",
+    aliases: &["chrEBV", "AJ507799.2"],
+    url: "https://www.ncbi.nlm.nih.gov/nuccore/NC_007605.1",
+};
+
+/// The hepatitis B virus.
+pub const HBV: MicrobialGenome = MicrobialGenome {
+    name: "HBV",
+    species: "HBV",
+    accession: "NC_003977.2",
+    aliases: &[],
+    url: "https://www.ncbi.nlm.nih.gov/nuccore/NC_003977.2",
+};
+
+/// Human papillomavirus type 16.
+pub const HPV16: MicrobialGenome = MicrobialGenome {
+    name: "HPV16",
+    species: "HPV16",
+    accession: "NC_001526.4",
+    aliases: &[],
+    url: "https://www.ncbi.nlm.nih.gov/nuccore/NC_001526.4",
+};
+
+/// Human papillomavirus type 18.
+pub const HPV18: MicrobialGenome = MicrobialGenome {
+    name: "HPV18",
+    species: "HPV18",
+    accession: "NC_001357.1",
+    aliases: &[],
+    url: "https://www.ncbi.nlm.nih.gov/nuccore/NC_001357.1",
+};
+
+/// Severe acute respiratory syndrome coronavirus 2 (the Wuhan-Hu-1 isolate).
+pub const SARS_COV_2: MicrobialGenome = MicrobialGenome {
+    name: "SARS-CoV-2",
+    species: "SARS-CoV-2",
+    accession: "NC_045512.2",
+    aliases: &["MN908947.3"],
+    url: "https://www.ncbi.nlm.nih.gov/nuccore/NC_045512.2",
+};
+
+/// The bacteriophage PhiX174, which is used as a sequencing control.
+pub const PHIX: MicrobialGenome = MicrobialGenome {
+    name: "PhiX",
+    species: "PhiX",
+    accession: "NC_001422.1",
+    aliases: &["phiX174", "phix"],
+    url: "https://www.ncbi.nlm.nih.gov/nuccore/NC_001422.1",
+};
+
+/// Gets all of the supported viral and microbial genomes.
+pub fn get_all_microbial_genomes() -> Vec<MicrobialGenome> {
+    vec![EBV, HBV, HPV16, HPV18, SARS_COV_2, PHIX]
+}
+
+impl ReferenceGenome for MicrobialGenome {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn version(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn source(&self) -> &'static str {
+        "NCBI RefSeq"
+    }
+
+    fn basis(&self) -> GenomeBasis {
+        GenomeBasis::Microbial {
+            species: self.species,
+            accession: self.accession,
+        }
+    }
+
+    fn patch(&self) -> Option<usize> {
+        None
+    }
+
+    fn url(&self) -> Option<&'static str> {
+        Some(self.url)
+    }
+
+    fn autosomes(&self) -> Option<Vec<Sequence>> {
+        None
+    }
+
+    fn sex_chromosomes(&self) -> Option<Vec<Sequence>> {
+        None
+    }
+
+    fn mitochondrion_chromosome(&self) -> Option<Sequence> {
+        None
+    }
+
+    fn ebv_chromosome(&self) -> Option<Sequence> {
+        None
+    }
+
+    fn unlocalized_sequences(&self) -> Option<Vec<Sequence>> {
+        None
+    }
+
+    fn unplaced_sequences(&self) -> Option<Vec<Sequence>> {
+        None
+    }
+
+    fn decoy_sequences(&self) -> Option<Vec<Sequence>> {
+        None
+    }
+
+    fn viral_sequences(&self) -> Option<Vec<Sequence>> {
+        Some(
+            std::iter::once(self.accession)
+                .chain(self.aliases.iter().copied())
+                .map(|name| Sequence::new(name, SequenceKind::Viral))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::utils::genome::{get_primary_assembly, get_reference_genome};
+
+    use super::*;
+
+    #[test]
+    pub fn it_recognizes_microbial_genomes_by_accession_and_alias() {
+        let genome = get_reference_genome("sars-cov-2").unwrap();
+        assert_eq!(genome.species(), ["SARS-CoV-2"]);
+        assert_eq!(genome.triplet_id(), "SARS-CoV-2-none-NC_045512.2");
+        assert_eq!(
+            genome.species_of("MN908947.3").as_deref(),
+            Some("SARS-CoV-2")
+        );
+        assert_eq!(genome.viral_sequences().unwrap().len(), 2);
+        assert!(get_primary_assembly(Rc::new(genome)).is_empty());
+
+        assert_eq!(PHIX.species_of("phiX174").as_deref(), Some("PhiX"));
+        assert_eq!(EBV.species_of("chrEBV").as_deref(), Some("EBV"));
+        assert_eq!(HPV16.species_of("NC_001357.1"), None);
+    }
+}
@@ -116,6 +116,15 @@
 //! - Find the third quartile of the distribution ([`third_quartile`][Histogram::third_quartile]).
 //! - Find the interquartile range of the distribution ([`interquartile_range`][Histogram::interquartile_range]).
 //! - Find the sum of all counts within the distribution ([`sum`][Histogram::sum]).
+//! - Find the most common value within the distribution ([`mode`][Histogram::mode]).
+//! - Compute the cumulative distribution ([`cumulative`][Histogram::cumulative]).
+//!
+//! ## Run-length encoding
+//!
+//! Histograms serialize as a dense list of values by default. For sparse
+//! histograms with large ranges (e.g., coverage), the
+//! [`run_length_encoded`] module can be used with `#[serde(with = "...")]` to
+//! serialize the values as `[value, count]` runs instead.
 //!
 //! [Histograms]: https://en.wikipedia.org/wiki/Histogram
 //!
@@ -142,6 +151,8 @@
 use anyhow::bail;
 use serde::{Deserialize, Serialize};
 
+use crate::utils::merge::Merge;
+
 /// Histogram used as the basis for statistics counting in many quality control
 /// facets. For more in depth information, please see the [module-level
 /// documentation].
@@ -176,6 +187,29 @@ impl Histogram {
         }
     }
 
+    /// Grows the histogram so that its range stops at `capacity`. If the
+    /// histogram's range already extends to or beyond `capacity`, this is a
+    /// no-op.
+    ///
+    /// ```
+    /// use ngs::utils::histogram::Histogram;
+    /// let mut hist = Histogram::zero_based_with_capacity(10);
+    /// hist.increment(10).unwrap();
+    ///
+    /// hist.grow_to(100);
+    /// assert_eq!(hist.range_stop(), 100);
+    /// assert_eq!(hist.get(10), 1);
+    /// assert!(hist.increment(100).is_ok());
+    /// ```
+    pub fn grow_to(&mut self, capacity: usize) {
+        if capacity <= self.range_stop {
+            return;
+        }
+
+        self.values.resize(capacity - self.range_start + 1, 0);
+        self.range_stop = capacity;
+    }
+
     //=================================//
     // Getting and incrementing values //
     //=================================//
@@ -211,8 +245,14 @@ impl Histogram {
     }
 
     /// Normalizes the values so that they sum to one and returns them as a Vec.
+    /// If the histogram is empty, all of the normalized values are zero.
     pub fn values_normalized(&self) -> Vec<f64> {
         let total = self.sum() as f64;
+
+        if total == 0.0 {
+            return vec![0.0; self.values.len()];
+        }
+
         self.values.iter().map(|x| *x as f64 / total).collect()
     }
 
@@ -253,8 +293,9 @@ impl Histogram {
     // Numerical computations //
     //========================//
 
-    /// Computes the mean of all values within the histogram.
-    pub fn mean(&self) -> f64 {
+    /// Computes the mean of all values within the histogram. If the histogram
+    /// is empty, the mean is undefined and `None` is returned.
+    pub fn mean(&self) -> Option<f64> {
         let mut sum = 0.0;
         let mut denominator = 0.0;
 
@@ -264,7 +305,11 @@ impl Histogram {
             sum += (bin_value * i) as f64;
         }
 
-        sum / denominator
+        if denominator == 0.0 {
+            return None;
+        }
+
+        Some(sum / denominator)
     }
 
     /// Computes the value of the nth percentile based on an exhaustive search.
@@ -317,17 +362,18 @@ impl Histogram {
             // items, then we have a runoff! Technically, the right way to
             // handle this is to find the next item which has a nonzero count
             // and take the middle of the two (even though that doesn't appear
-            // in the set necessarily). So that's what we do here!
-            if collected_items == needed_items {
+            // in the set necessarily). So that's what we do here! If there is
+            // no next item (i.e., for the 100th percentile), the current bin is
+            // the answer. Empty bins are skipped so that the 0th percentile is
+            // the lowest observed value.
+            if collected_items == needed_items && self.get(index) > 0 {
                 let lowest = index;
-                index += 1;
-
-                while self.get(index) == 0 {
-                    index += 1;
-                }
+                let next = (index + 1..=self.range_stop).find(|i| self.get(*i) != 0);
 
-                let highest = index;
-                return Ok(Some(lowest as f64 + ((highest - lowest) as f64 / 2.0)));
+                return match next {
+                    Some(highest) => Ok(Some(lowest as f64 + ((highest - lowest) as f64 / 2.0))),
+                    None => Ok(Some(lowest as f64)),
+                };
             }
 
             // (5e) Increment the bin we are looking at by one
@@ -365,6 +411,193 @@ impl Histogram {
     pub fn sum(&self) -> usize {
         self.values.iter().sum()
     }
+
+    /// Finds the most common bin within the distribution. Ties are broken in
+    /// favor of the lowest bin. If the histogram is empty, there is no mode.
+    ///
+    /// ```
+    /// use ngs::utils::histogram::Histogram;
+    /// let mut hist = Histogram::zero_based_with_capacity(10);
+    /// assert_eq!(hist.mode(), None);
+    ///
+    /// hist.increment_by(3, 2).unwrap();
+    /// hist.increment_by(7, 2).unwrap();
+    /// hist.increment(9).unwrap();
+    /// assert_eq!(hist.mode(), Some(3));
+    /// ```
+    pub fn mode(&self) -> Option<usize> {
+        let mut mode = None;
+        let mut highest = 0;
+
+        for i in self.range_start..=self.range_stop {
+            let bin_value = self.get(i);
+            if bin_value > highest {
+                highest = bin_value;
+                mode = Some(i);
+            }
+        }
+
+        mode
+    }
+
+    /// Computes the cumulative distribution of the histogram: the value at
+    /// each position is the fraction of all counts that fall in that bin or
+    /// any bin before it. If the histogram is empty, all of the values are
+    /// zero.
+    ///
+    /// ```
+    /// use ngs::utils::histogram::Histogram;
+    /// let mut hist = Histogram::zero_based_with_capacity(3);
+    ///
+    /// hist.increment_by(0, 2).unwrap();
+    /// hist.increment(2).unwrap();
+    /// hist.increment(3).unwrap();
+    ///
+    /// assert_eq!(hist.cumulative(), [0.5, 0.5, 0.75, 1.0]);
+    /// ```
+    pub fn cumulative(&self) -> Vec<f64> {
+        let mut running_total = 0.0;
+
+        self.values_normalized()
+            .into_iter()
+            .map(|value| {
+                running_total += value;
+                running_total
+            })
+            .collect()
+    }
+}
+
+/// Serializes a [`Histogram`] with its values run-length encoded. Use this
+/// module with `#[serde(with = "ngs::utils::histogram::run_length_encoded")]`.
+///
+/// ```
+/// use ngs::utils::histogram::{run_length_encoded, Histogram};
+/// let mut hist = Histogram::zero_based_with_capacity(5);
+/// hist.increment_by(5, 2).unwrap();
+///
+/// let mut output = Vec::new();
+/// run_length_encoded::serialize(&hist, &mut serde_json::Serializer::new(&mut output)).unwrap();
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     r#"{"range_start":0,"range_stop":5,"runs":[[0,5],[2,1]]}"#
+/// );
+/// ```
+pub mod run_length_encoded {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Histogram;
+
+    /// The run-length encoded representation of a [`Histogram`]. Each run is
+    /// a `(value, count)` pair.
+    #[derive(Serialize, Deserialize)]
+    struct RunLengthEncodedHistogram {
+        range_start: usize,
+        range_stop: usize,
+        runs: Vec<(usize, usize)>,
+    }
+
+    /// Serializes the [`Histogram`] with its values run-length encoded.
+    pub fn serialize<S: Serializer>(
+        histogram: &Histogram,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+
+        for value in &histogram.values {
+            match runs.last_mut() {
+                Some((last, count)) if last == value => *count += 1,
+                _ => runs.push((*value, 1)),
+            }
+        }
+
+        RunLengthEncodedHistogram {
+            range_start: histogram.range_start,
+            range_stop: histogram.range_stop,
+            runs,
+        }
+        .serialize(serializer)
+    }
+
+    /// Deserializes a [`Histogram`] from its run-length encoded values.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Histogram, D::Error> {
+        let encoded = RunLengthEncodedHistogram::deserialize(deserializer)?;
+        let mismatch =
+            || D::Error::custom("run-length encoded values do not match the histogram range");
+
+        // (1) Check that the runs cover the range exactly before expanding
+        // them, so that a malformed file cannot force a huge allocation
+        // through its run counts.
+        let len = encoded
+            .range_stop
+            .checked_sub(encoded.range_start)
+            .and_then(|len| len.checked_add(1))
+            .ok_or_else(mismatch)?;
+        let total = encoded
+            .runs
+            .iter()
+            .try_fold(0usize, |total, (_, count)| total.checked_add(*count))
+            .ok_or_else(mismatch)?;
+
+        if total != len {
+            return Err(mismatch());
+        }
+
+        // (2) Even a consistent range may be too large to allocate, which is
+        // reported as an error rather than aborting.
+        let mut values = Vec::new();
+        values
+            .try_reserve_exact(len)
+            .map_err(|_| D::Error::custom("histogram range is too large to allocate"))?;
+        values.extend(
+            encoded
+                .runs
+                .iter()
+                .flat_map(|(value, count)| std::iter::repeat_n(*value, *count)),
+        );
+
+        Ok(Histogram {
+            values,
+            range_start: encoded.range_start,
+            range_stop: encoded.range_stop,
+        })
+    }
+}
+
+impl Merge for Histogram {
+    /// Sums the histograms bin-by-bin. If the ranges differ, the merged
+    /// histogram spans the larger of the two ranges.
+    ///
+    /// ```
+    /// use ngs::utils::{histogram::Histogram, merge::Merge};
+    /// let mut a = Histogram::zero_based_with_capacity(10);
+    /// a.increment(5).unwrap();
+    ///
+    /// let mut b = Histogram::zero_based_with_capacity(20);
+    /// b.increment_by(5, 2).unwrap();
+    /// b.increment(20).unwrap();
+    ///
+    /// a.merge(b).unwrap();
+    /// assert_eq!(a.range_stop(), 20);
+    /// assert_eq!(a.get(5), 3);
+    /// assert_eq!(a.get(20), 1);
+    /// ```
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        if self.range_start != other.range_start {
+            bail!(
+                "Cannot merge histograms with differing starting ranges ({} and {}).",
+                self.range_start,
+                other.range_start
+            );
+        }
+
+        self.grow_to(other.range_stop);
+        for (bin, value) in other.values.into_iter().enumerate() {
+            self.values[bin] += value;
+        }
+
+        Ok(())
+    }
 }
 
 impl Default for Histogram {
@@ -399,13 +632,19 @@ mod tests {
         assert_eq!(s.get(75), 3);
         assert_eq!(s.get(100), 5);
 
-        assert_eq!(s.mean(), 80.0);
+        assert_eq!(s.mean(), Some(80.0));
         assert_eq!(s.first_quartile().unwrap(), 75.0);
         assert_eq!(s.median().unwrap(), 87.5);
         assert_eq!(s.third_quartile().unwrap(), 100.0);
         assert_eq!(s.interquartile_range().unwrap(), 25.0);
     }
 
+    #[test]
+    pub fn test_mean_on_empty_histogram() {
+        let s = Histogram::zero_based_with_capacity(100);
+        assert_eq!(s.mean(), None);
+    }
+
     #[test]
     pub fn test_median_on_empty_histogram() {
         let s = Histogram::zero_based_with_capacity(5000);
@@ -438,6 +677,59 @@ mod tests {
         assert_eq!(median.unwrap(), 200.0);
     }
 
+    #[test]
+    pub fn test_percentiles_at_the_bounds() {
+        let mut s = Histogram::zero_based_with_capacity(100);
+        s.increment_by(10, 5).unwrap();
+        s.increment_by(90, 5).unwrap();
+
+        assert_eq!(s.percentile(0.0).unwrap(), Some(10.0));
+        assert_eq!(s.percentile(0.05).unwrap(), Some(10.0));
+        assert_eq!(s.percentile(0.95).unwrap(), Some(90.0));
+        assert_eq!(s.percentile(1.0).unwrap(), Some(90.0));
+        assert!(s.percentile(1.5).is_err());
+    }
+
+    #[test]
+    pub fn test_run_length_encoded_round_trip() {
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper {
+            #[serde(with = "run_length_encoded")]
+            histogram: Histogram,
+        }
+
+        let mut histogram = Histogram::zero_based_with_capacity(1000);
+        histogram.increment_by(0, 4).unwrap();
+        histogram.increment_by(500, 2).unwrap();
+
+        let serialized = serde_json::to_string(&Wrapper { histogram }).unwrap();
+        let deserialized: Wrapper = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.histogram.range_stop(), 1000);
+        assert_eq!(deserialized.histogram.get(0), 4);
+        assert_eq!(deserialized.histogram.get(500), 2);
+        assert_eq!(deserialized.histogram.sum(), 6);
+
+        let invalid = r#"{"histogram":{"range_start":0,"range_stop":10,"runs":[[0,3]]}}"#;
+        assert!(serde_json::from_str::<Wrapper>(invalid).is_err());
+
+        // Oversized runs are rejected without being expanded.
+        let oversized = format!(
+            r#"{{"histogram":{{"range_start":0,"range_stop":10,"runs":[[0,{}]]}}}}"#,
+            usize::MAX
+        );
+        assert!(serde_json::from_str::<Wrapper>(&oversized).is_err());
+        let overflowing = format!(
+            r#"{{"histogram":{{"range_start":0,"range_stop":10,"runs":[[0,{}],[1,2]]}}}}"#,
+            usize::MAX
+        );
+        assert!(serde_json::from_str::<Wrapper>(&overflowing).is_err());
+        let unbounded = format!(
+            r#"{{"histogram":{{"range_start":0,"range_stop":{},"runs":[[0,1]]}}}}"#,
+            usize::MAX
+        );
+        assert!(serde_json::from_str::<Wrapper>(&unbounded).is_err());
+    }
+
     #[test]
     pub fn test_invalid_increments() {
         let mut s = Histogram::zero_based_with_capacity(100);
@@ -0,0 +1,431 @@
+//! Utilities related to genomic intervals.
+//!
+//! Intervals can be read from Picard-style interval lists (`.interval_list`),
+//! BED files, or the records of a GFF file, and are all stored in the same
+//! representation: an [`IntervalSet`], which holds an interval tree
+//! ([`rust_lapper::Lapper`]) for each reference sequence.
+//!
+//! Regardless of the source format, positions within an [`IntervalSet`] are
+//! one-based and the interval trees span each interval as the half-open range
+//! `[start, end + 1)`, where `end` is the (inclusive) last position of the
+//! interval.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use flate2::read::MultiGzDecoder;
+use noodles::gff;
+use rust_lapper::{Interval, Lapper};
+
+use super::formats::{self, BioinformaticsFileFormat};
+
+//========//
+// Strand //
+//========//
+
+/// Strand orientation (forward or reverse). Useful when parsing a GFF or other
+/// features files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Strand {
+    /// Features that fall on the _forward_ strand.
+    Forward,
+
+    /// Features that fall of the _reverse_ strand.
+    Reverse,
+}
+
+/// Error that occurs when a strand cannot be parsed from a `&str`.
+#[derive(Debug)]
+pub struct StrandParseError(String);
+
+impl std::fmt::Display for StrandParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "attempted to parse strand from value: {}", self.0)
+    }
+}
+
+impl std::error::Error for StrandParseError {}
+
+impl FromStr for Strand {
+    type Err = StrandParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "+" => Ok(Strand::Forward),
+            "-" => Ok(Strand::Reverse),
+            _ => Err(StrandParseError(String::from(s))),
+        }
+    }
+}
+
+/// Parses an optional strand, where `.` (or an empty value) denotes that the
+/// strand is unknown or not applicable.
+fn parse_optional_strand(s: &str) -> anyhow::Result<Option<Strand>> {
+    match s {
+        "." | "" => Ok(None),
+        _ => Ok(Some(s.parse()?)),
+    }
+}
+
+//==============//
+// Interval set //
+//==============//
+
+/// The information attached to each interval.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Region {
+    /// The name of the interval, if one was provided.
+    pub name: Option<String>,
+
+    /// The strand of the interval, if one was provided.
+    pub strand: Option<Strand>,
+}
+
+/// A set of intervals, indexed for fast overlap lookups on each reference
+/// sequence.
+#[derive(Debug, Default)]
+pub struct IntervalSet {
+    sequences: HashMap<String, Lapper<usize, Region>>,
+}
+
+impl IntervalSet {
+    /// Creates an [`IntervalSet`] from `(sequence, start, end, region)`
+    /// tuples, where `start` and `end` are one-based and inclusive.
+    ///
+    /// ```
+    /// use ngs::utils::intervals::{IntervalSet, Region};
+    ///
+    /// let set = IntervalSet::from_intervals(vec![
+    ///     (String::from("chr1"), 100, 200, Region::default()),
+    ///     (String::from("chr1"), 150, 300, Region::default()),
+    /// ]);
+    ///
+    /// assert_eq!(set.len(), 2);
+    /// assert!(set.overlaps("chr1", 300, 400));
+    /// assert!(!set.overlaps("chr1", 301, 400));
+    /// assert_eq!(set.covered_bases(), 201);
+    /// ```
+    pub fn from_intervals<I>(intervals: I) -> Self
+    where
+        I: IntoIterator<Item = (String, usize, usize, Region)>,
+    {
+        let mut by_sequence: HashMap<String, Vec<Interval<usize, Region>>> = HashMap::new();
+
+        for (sequence, start, end, region) in intervals {
+            by_sequence.entry(sequence).or_default().push(Interval {
+                start,
+                stop: end + 1,
+                val: region,
+            });
+        }
+
+        Self {
+            sequences: by_sequence
+                .into_iter()
+                .map(|(sequence, intervals)| (sequence, Lapper::new(intervals)))
+                .collect(),
+        }
+    }
+
+    /// Gets the total number of intervals.
+    pub fn len(&self) -> usize {
+        self.sequences.values().map(|lapper| lapper.len()).sum()
+    }
+
+    /// Whether the set contains no intervals.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the names of the reference sequences that have intervals.
+    pub fn sequence_names(&self) -> impl Iterator<Item = &str> {
+        self.sequences.keys().map(|name| name.as_str())
+    }
+
+    /// Gets the interval tree for a reference sequence, if the sequence has
+    /// intervals.
+    pub fn get(&self, sequence: &str) -> Option<&Lapper<usize, Region>> {
+        self.sequences.get(sequence)
+    }
+
+    /// Finds all intervals overlapping the one-based, inclusive range
+    /// `start..=end` on a reference sequence.
+    pub fn find<'a>(
+        &'a self,
+        sequence: &str,
+        start: usize,
+        end: usize,
+    ) -> impl Iterator<Item = &'a Interval<usize, Region>> + 'a {
+        self.sequences
+            .get(sequence)
+            .into_iter()
+            .flat_map(move |lapper| lapper.find(start, end + 1))
+    }
+
+    /// Whether any interval overlaps the one-based, inclusive range
+    /// `start..=end` on a reference sequence.
+    pub fn overlaps(&self, sequence: &str, start: usize, end: usize) -> bool {
+        self.find(sequence, start, end).next().is_some()
+    }
+
+    /// Whether any interval contains a one-based position on a reference
+    /// sequence.
+    pub fn contains(&self, sequence: &str, position: usize) -> bool {
+        self.overlaps(sequence, position, position)
+    }
+
+    /// Gets the number of bases covered by at least one interval (i.e.,
+    /// overlapping intervals are only counted once).
+    pub fn covered_bases(&self) -> usize {
+        self.sequences.values().map(|lapper| lapper.cov()).sum()
+    }
+}
+
+//=========//
+// Parsing //
+//=========//
+
+/// Reads the intervals from a Picard-style interval list. The SAM-style
+/// header (lines starting with `@`) is skipped, and each remaining line is
+/// expected to contain the sequence, the one-based start and end positions
+/// (inclusive), the strand, and the name of the interval.
+pub fn read_interval_list<R: BufRead>(reader: R) -> anyhow::Result<IntervalSet> {
+    let mut intervals = Vec::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('@') || line.trim().is_empty() {
+            continue;
+        }
+
+        let columns: Vec<&str> = line.split('\t').collect();
+        if columns.len() < 3 {
+            bail!(
+                "Invalid interval list record on line {}: too few columns.",
+                i + 1
+            );
+        }
+
+        let (start, end) = parse_range(columns[1], columns[2], i + 1)?;
+        if start == 0 {
+            bail!(
+                "Invalid interval list record on line {}: positions are one-based.",
+                i + 1
+            );
+        }
+
+        let region = Region {
+            strand: columns
+                .get(3)
+                .map(|s| parse_optional_strand(s))
+                .transpose()?
+                .flatten(),
+            name: columns.get(4).map(|s| s.to_string()),
+        };
+
+        intervals.push((columns[0].to_string(), start, end, region));
+    }
+
+    Ok(IntervalSet::from_intervals(intervals))
+}
+
+/// Reads the intervals from a BED file. Track, browser, and comment lines are
+/// skipped. The start position is zero-based and the end position is
+/// exclusive, as is standard for BED. The name (fourth column) and strand
+/// (sixth column) are read if present.
+pub fn read_bed<R: BufRead>(reader: R) -> anyhow::Result<IntervalSet> {
+    let mut intervals = Vec::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+            || line.trim().is_empty()
+        {
+            continue;
+        }
+
+        let columns: Vec<&str> = line.split('\t').collect();
+        if columns.len() < 3 {
+            bail!("Invalid BED record on line {}: too few columns.", i + 1);
+        }
+
+        let (start, end) = parse_range(columns[1], columns[2], i + 1)?;
+        if start == end {
+            // Empty intervals cover no bases.
+            continue;
+        }
+
+        let region = Region {
+            name: columns.get(3).map(|s| s.to_string()),
+            strand: columns
+                .get(5)
+                .map(|s| parse_optional_strand(s))
+                .transpose()?
+                .flatten(),
+        };
+
+        intervals.push((columns[0].to_string(), start + 1, end, region));
+    }
+
+    Ok(IntervalSet::from_intervals(intervals))
+}
+
+/// Reads the intervals from the records of a GFF file whose type is one of
+/// `feature_types` (or all records, if `feature_types` is empty). Each interval
+/// is named after the type of the record it was derived from.
+pub fn read_gff<R: BufRead>(
+    mut reader: gff::Reader<R>,
+    feature_types: &[String],
+) -> anyhow::Result<IntervalSet> {
+    let mut intervals = Vec::new();
+
+    for result in reader.records() {
+        let record = result?;
+        if !feature_types.is_empty() && !feature_types.iter().any(|ty| ty == record.ty()) {
+            continue;
+        }
+
+        let region = Region {
+            name: Some(record.ty().to_string()),
+            strand: parse_optional_strand(record.strand().as_ref())?,
+        };
+
+        intervals.push((
+            record.reference_sequence_name().to_string(),
+            usize::from(record.start()),
+            usize::from(record.end()),
+            region,
+        ));
+    }
+
+    Ok(IntervalSet::from_intervals(intervals))
+}
+
+/// Reads the intervals from an interval list, BED, or GFF file (optionally
+/// gzipped, for BED and GFF files), detected by the extension of the file. For
+/// GFF files, only the records whose type is one of `gff_feature_types` are
+/// read (or all records, if `gff_feature_types` is empty).
+pub fn read_intervals_from_path<P>(
+    src: P,
+    gff_feature_types: &[String],
+) -> anyhow::Result<IntervalSet>
+where
+    P: AsRef<Path>,
+{
+    let path = src.as_ref();
+    let open = || {
+        File::open(path)
+            .with_context(|| format!("Could not open intervals file: {}", path.display()))
+    };
+
+    let intervals = match BioinformaticsFileFormat::try_detect(path) {
+        Some(BioinformaticsFileFormat::INTERVAL_LIST) => {
+            read_interval_list(BufReader::new(open()?))
+        }
+        Some(BioinformaticsFileFormat::BED) => read_bed(BufReader::new(open()?)),
+        Some(BioinformaticsFileFormat::BED_GZ) => {
+            read_bed(BufReader::new(MultiGzDecoder::new(open()?)))
+        }
+        Some(BioinformaticsFileFormat::GFF) | Some(BioinformaticsFileFormat::GFF_GZ) => {
+            read_gff(formats::gff::open(path)?, gff_feature_types)
+        }
+        Some(format) => bail!(
+            "incompatible formats: required an interval list, BED, or GFF, found {}",
+            format
+        ),
+        None => bail!(
+            "Not able to determine filetype for intervals file: {}",
+            path.display()
+        ),
+    };
+
+    intervals.with_context(|| format!("Error reading intervals from: {}", path.display()))
+}
+
+/// Parses the start and end columns of an interval.
+fn parse_range(start: &str, end: &str, line: usize) -> anyhow::Result<(usize, usize)> {
+    let parse = |value: &str| {
+        value
+            .parse::<usize>()
+            .with_context(|| format!("Invalid position on line {}: {}", line, value))
+    };
+
+    let (start, end) = (parse(start)?, parse(end)?);
+    if end < start {
+        bail!(
+            "Invalid interval on line {}: end ({}) is before start ({}).",
+            line,
+            end,
+            start
+        );
+    }
+
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_reads_interval_lists_and_beds_into_the_same_representation() -> anyhow::Result<()> {
+        let interval_list = "@HD\tVN:1.6\n\
+            @SQ\tSN:chr1\tLN:1000\n\
+            chr1\t101\t200\t+\ttarget_1\n\
+            chr1\t301\t400\t-\ttarget_2\n";
+        let bed = "track name=targets\n\
+            chr1\t100\t200\ttarget_1\t0\t+\n\
+            chr1\t300\t400\ttarget_2\t0\t-\n";
+
+        for set in [
+            read_interval_list(interval_list.as_bytes())?,
+            read_bed(bed.as_bytes())?,
+        ] {
+            assert_eq!(set.len(), 2);
+            assert_eq!(set.covered_bases(), 200);
+            assert!(set.contains("chr1", 101));
+            assert!(set.contains("chr1", 200));
+            assert!(!set.contains("chr1", 100));
+            assert!(!set.contains("chr1", 201));
+            assert!(!set.contains("chr2", 150));
+
+            let found: Vec<&Interval<usize, Region>> = set.find("chr1", 350, 350).collect();
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0].val.name.as_deref(), Some("target_2"));
+            assert_eq!(found[0].val.strand, Some(Strand::Reverse));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_reads_gff_records_of_the_requested_types() -> anyhow::Result<()> {
+        let gff = "##gff-version 3\n\
+            chr1\t.\tgene\t1000\t2000\t.\t+\t.\tID=g1\n\
+            chr1\t.\texon\t1000\t1100\t.\t+\t.\tParent=g1\n\
+            chr2\t.\texon\t50\t60\t.\t.\t.\tID=e2\n";
+
+        let set = read_gff(gff::Reader::new(gff.as_bytes()), &[String::from("exon")])?;
+        assert_eq!(set.len(), 2);
+        assert!(set.contains("chr1", 1050));
+        assert!(!set.contains("chr1", 1500));
+        assert_eq!(set.find("chr2", 55, 55).next().unwrap().val.strand, None);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_rejects_invalid_intervals() {
+        assert!(read_bed("chr1\t200\t100\n".as_bytes()).is_err());
+        assert!(read_bed("chr1\t100\n".as_bytes()).is_err());
+        assert!(read_interval_list("chr1\t0\t100\t+\tx\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+//! Utilities related to the k-mers of a sequence.
+
+/// Encodes a base into two bits, if it is one of `A`, `C`, `G`, or `T`.
+fn encode(base: u8) -> Option<u64> {
+    match base.to_ascii_uppercase() {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+/// Gets the canonical k-mers of a sequence: each k-mer is encoded into two
+/// bits per base, and the lesser of the k-mer and its reverse complement is
+/// yielded, so that a sequence and its reverse complement share their k-mers.
+/// Any k-mer that contains a base other than `A`, `C`, `G`, or `T` is skipped.
+///
+/// # Panics
+///
+/// Panics if `k` is zero or greater than 32 (the most bases that fit within a
+/// `u64`).
+pub fn canonical(bases: &[u8], k: usize) -> impl Iterator<Item = u64> + '_ {
+    assert!(k > 0 && k <= 32, "k must be between 1 and 32");
+
+    let mask = u64::MAX >> (64 - 2 * k);
+    let shift = 2 * (k - 1);
+    let (mut forward, mut reverse) = (0u64, 0u64);
+    let mut valid = 0usize;
+
+    bases.iter().filter_map(move |base| {
+        let code = match encode(*base) {
+            Some(code) => code,
+            None => {
+                valid = 0;
+                return None;
+            }
+        };
+
+        forward = ((forward << 2) | code) & mask;
+        reverse = (reverse >> 2) | ((3 - code) << shift);
+        valid += 1;
+        (valid >= k).then(|| forward.min(reverse))
+    })
+}
+
+/// Mixes the bits of an encoded k-mer (with the finalizer of MurmurHash3) so
+/// that hashes are uniformly distributed, even for similar k-mers.
+pub fn hash(kmer: u64) -> u64 {
+    let mut hash = kmer;
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^= hash >> 33;
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_yields_the_same_canonical_kmers_for_either_strand() {
+        // ACG (0b000110) is the reverse complement of CGT (0b011011).
+        assert_eq!(canonical(b"ACGT", 3).collect::<Vec<_>>(), [0b000110; 2]);
+        assert_eq!(canonical(b"AAN", 2).count(), 1);
+        assert_eq!(canonical(b"ANA", 2).count(), 0);
+
+        let forward = b"GATTACAGGCTTAACC";
+        let reverse = b"GGTTAAGCCTGTAATC";
+        let mut a = canonical(forward, 5).collect::<Vec<_>>();
+        let mut b = canonical(reverse, 5).collect::<Vec<_>>();
+        a.sort_unstable();
+        b.sort_unstable();
+        assert_eq!(a, b);
+        assert_eq!(canonical(forward, 32).count(), 0);
+    }
+}
@@ -0,0 +1,158 @@
+//! Utilities related to the format of the logs.
+//!
+//! By default, the logs are written as human-readable text. With
+//! `--log-format json`, each log event is instead written as a JSON object per
+//! line (with the `time`, `level`, `target`, and the fields of the event,
+//! including its `message`) so that log aggregators can parse them. In this
+//! mode, counts within the messages are always written as plain digits,
+//! regardless of `--locale`.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+use chrono::Utc;
+use serde_json::{Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields},
+    registry::LookupSpan,
+};
+
+/// The format that the logs are written in.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LogFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+
+    /// A JSON object per line.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("unknown log format: {}", s),
+        }
+    }
+}
+
+/// Collects the fields of a log event into a JSON object. Integers and
+/// booleans are kept as JSON numbers and booleans; everything else is written
+/// as a string.
+#[derive(Default)]
+struct JsonFields(Map<String, Value>);
+
+impl Visit for JsonFields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(
+            field.name().to_string(),
+            Value::from(format!("{:?}", value)),
+        );
+    }
+}
+
+/// Formats each log event as a JSON object on its own line.
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+
+        let mut object = Map::new();
+        object.insert(String::from("time"), Value::from(Utc::now().to_rfc3339()));
+        object.insert(
+            String::from("level"),
+            Value::from(metadata.level().to_string()),
+        );
+        object.insert(String::from("target"), Value::from(metadata.target()));
+
+        let mut fields = JsonFields::default();
+        event.record(&mut fields);
+        object.extend(fields.0);
+
+        writeln!(writer, "{}", Value::Object(object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    /// A writer that captures the logs in memory.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn it_formats_log_events_as_json() -> anyhow::Result<()> {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt::Subscriber::builder()
+            .with_writer(move || writer.clone())
+            .event_format(JsonFormat)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(records = 1_000_000u64, "Processed {} records.", 1_000_000);
+        });
+
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone())?;
+        let event: Value = serde_json::from_str(logs.trim_end())?;
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["records"], 1_000_000);
+        assert_eq!(event["message"], "Processed 1000000 records.");
+
+        assert_eq!("json".parse::<LogFormat>()?, LogFormat::Json);
+        assert!("xml".parse::<LogFormat>().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,19 @@
+//! Utilities related to common mathematical operations.
+
+/// Computes `numerator` as a percentage of `denominator`. If the denominator
+/// is zero (for instance, when a file contains no records), the percentage is
+/// undefined and [`None`] is returned rather than `NaN`.
+///
+/// ```
+/// use ngs::utils::math::percentage;
+///
+/// assert_eq!(percentage(1, 4), Some(25.0));
+/// assert_eq!(percentage(0, 0), None);
+/// ```
+pub fn percentage(numerator: usize, denominator: usize) -> Option<f64> {
+    if denominator == 0 {
+        return None;
+    }
+
+    Some(numerator as f64 / denominator as f64 * 100.0)
+}
@@ -0,0 +1,112 @@
+//! Utilities for combining results that were computed separately.
+//!
+//! # Overview
+//!
+//! When work is split up (for instance, when processing a file per chromosome
+//! or across multiple nodes), each piece produces its own partial results. The
+//! [`Merge`] trait describes how two partial results are combined into one.
+//! Counts are summed, histograms are summed bin-by-bin, and any summary
+//! statistics are recomputed from the combined counts.
+//!
+//! ```
+//! use std::collections::BTreeMap;
+//! use ngs::utils::merge::Merge;
+//!
+//! let mut a = BTreeMap::from([("M", 10usize), ("I", 2)]);
+//! let b = BTreeMap::from([("M", 5usize), ("D", 1)]);
+//!
+//! a.merge(b).unwrap();
+//! assert_eq!(a.get("M"), Some(&15));
+//! assert_eq!(a.get("I"), Some(&2));
+//! assert_eq!(a.get("D"), Some(&1));
+//! ```
+
+use std::{
+    collections::{btree_map, hash_map, BTreeMap, HashMap},
+    fmt::Display,
+    hash::Hash,
+};
+
+use anyhow::bail;
+
+/// A type is [`Merge`] if two values of that type, computed over disjoint
+/// portions of the same input, can be combined into the value that would have
+/// been computed over both portions at once.
+pub trait Merge {
+    /// Merges `other` into `self`.
+    fn merge(&mut self, other: Self) -> anyhow::Result<()>;
+}
+
+impl Merge for usize {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        *self += other;
+        Ok(())
+    }
+}
+
+impl<K: Eq + Hash, V: Merge> Merge for HashMap<K, V> {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        for (key, value) in other {
+            match self.entry(key) {
+                hash_map::Entry::Occupied(mut entry) => entry.get_mut().merge(value)?,
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<K: Ord, V: Merge> Merge for BTreeMap<K, V> {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        for (key, value) in other {
+            match self.entry(key) {
+                btree_map::Entry::Occupied(mut entry) => entry.get_mut().merge(value)?,
+                btree_map::Entry::Vacant(entry) => {
+                    entry.insert(value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Merge> Merge for Option<T> {
+    fn merge(&mut self, other: Self) -> anyhow::Result<()> {
+        match (self.as_mut(), other) {
+            (Some(value), Some(other)) => value.merge(other)?,
+            (None, Some(other)) => *self = Some(other),
+            (_, None) => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Merges two maps whose keys are expected to be disjoint. This is useful for
+/// values which are computed in their entirety for each key (e.g., statistics
+/// computed per reference sequence) and, as such, cannot be combined. If a key
+/// is present in both maps, an error is returned.
+pub fn merge_disjoint<K: Ord + Display, V>(
+    map: &mut BTreeMap<K, V>,
+    other: BTreeMap<K, V>,
+    description: &str,
+) -> anyhow::Result<()> {
+    for (key, value) in other {
+        match map.entry(key) {
+            btree_map::Entry::Occupied(entry) => bail!(
+                "Cannot merge {}: `{}` is present in both results.",
+                description,
+                entry.key()
+            ),
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+        }
+    }
+
+    Ok(())
+}
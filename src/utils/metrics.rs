@@ -0,0 +1,241 @@
+//! Operational metrics in the Prometheus text exposition format.
+//!
+//! Counters for the number of files processed, the number of failures, and
+//! the time spent within (and the records processed by) each quality control
+//! facet are kept for the lifetime of the process. With the global
+//! `--metrics-addr` option, they are served at `/metrics` on the provided
+//! address for as long as `ngs` runs, so that long-running invocations can be
+//! scraped by Prometheus.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use tracing::warn;
+
+/// The number of input files that were processed successfully.
+static FILES_PROCESSED: AtomicU64 = AtomicU64::new(0);
+
+/// The number of subcommands that failed.
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// The total time spent within (in seconds) and the records processed by each
+/// facet, keyed by the name of the facet.
+static FACETS: Mutex<BTreeMap<String, (f64, u64)>> = Mutex::new(BTreeMap::new());
+
+/// How long a connection may sit idle while reading the request or writing
+/// the response before it is dropped.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+//==========//
+// Counters //
+//==========//
+
+/// Records that an input file was processed successfully.
+pub fn file_processed() {
+    FILES_PROCESSED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records that a subcommand failed.
+pub fn failure() {
+    FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records the time spent within (and the records processed by) a facet.
+pub fn facet_runtime(name: &str, seconds: f64, records: usize) {
+    let mut facets = FACETS.lock().unwrap();
+    let entry = facets.entry(name.to_string()).or_default();
+    entry.0 += seconds;
+    entry.1 += records as u64;
+}
+
+//========//
+// Render //
+//========//
+
+/// Renders the metrics in the Prometheus text exposition format.
+pub fn render() -> String {
+    let mut out = String::new();
+
+    let mut counter = |name: &str, help: &str, samples: Vec<(Option<&str>, String)>| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (facet, value) in samples {
+            match facet {
+                Some(facet) => {
+                    let _ = writeln!(out, "{}{{facet=\"{}\"}} {}", name, escape(facet), value);
+                }
+                None => {
+                    let _ = writeln!(out, "{} {}", name, value);
+                }
+            }
+        }
+    };
+
+    counter(
+        "ngs_files_processed_total",
+        "Input files processed successfully.",
+        vec![(None, FILES_PROCESSED.load(Ordering::Relaxed).to_string())],
+    );
+    counter(
+        "ngs_failures_total",
+        "Subcommands that failed.",
+        vec![(None, FAILURES.load(Ordering::Relaxed).to_string())],
+    );
+
+    let facets = FACETS.lock().unwrap().clone();
+    counter(
+        "ngs_facet_seconds_total",
+        "Wall-clock time spent within each quality control facet.",
+        facets
+            .iter()
+            .map(|(name, (seconds, _))| (Some(name.as_str()), seconds.to_string()))
+            .collect(),
+    );
+    counter(
+        "ngs_facet_records_total",
+        "Records processed by each quality control facet.",
+        facets
+            .iter()
+            .map(|(name, (_, records))| (Some(name.as_str()), records.to_string()))
+            .collect(),
+    );
+
+    out
+}
+
+/// Escapes a label value as required by the exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+//=======//
+// Serve //
+//=======//
+
+/// Serves the metrics at `/metrics` on the provided address from a background
+/// thread, returning the address that was bound (which differs from the
+/// provided address when port 0 is requested).
+///
+/// Each connection is answered on its own thread, so a client that connects
+/// but never sends a request cannot hold up other scrapes.
+pub fn serve(addr: SocketAddr) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let bound = listener.local_addr()?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    thread::spawn(move || {
+                        if let Err(e) = respond(stream) {
+                            warn!("Could not serve metrics: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Could not serve metrics: {}", e),
+            }
+        }
+    });
+
+    Ok(bound)
+}
+
+/// Responds to a single HTTP request.
+fn respond(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // The headers are not needed, but are read so that the client does not
+    // see the connection reset before it has finished sending its request.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            render(),
+        ),
+        _ => ("404 Not Found", "text/plain", String::from("Not Found\n")),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    pub fn it_serves_the_metrics() -> anyhow::Result<()> {
+        file_processed();
+        facet_runtime("Metrics \"Test\"", 1.5, 10);
+        facet_runtime("Metrics \"Test\"", 0.5, 5);
+
+        let addr = serve("127.0.0.1:0".parse()?)?;
+        let get = |path: &str| -> anyhow::Result<String> {
+            let mut stream = TcpStream::connect(addr)?;
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path)?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        };
+
+        let response = get("/metrics")?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("# TYPE ngs_files_processed_total counter\n"));
+        assert!(response.contains("ngs_facet_seconds_total{facet=\"Metrics \\\"Test\\\"\"} 2\n"));
+        assert!(response.contains("ngs_facet_records_total{facet=\"Metrics \\\"Test\\\"\"} 15\n"));
+
+        assert!(get("/")?.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn it_serves_the_metrics_while_a_connection_is_idle() -> anyhow::Result<()> {
+        let addr = serve("127.0.0.1:0".parse()?)?;
+
+        // Connects but never sends a request.
+        let _idle = TcpStream::connect(addr)?;
+
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+        write!(stream, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        Ok(())
+    }
+}
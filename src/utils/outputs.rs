@@ -0,0 +1,146 @@
+//! A manifest of the files written by `ngs`.
+//!
+//! The set of files a subcommand writes depends on the options it was given
+//! (for instance, `ngs qc` only writes per-gene counts when the Features facet
+//! runs), which makes them awkward for workflow languages such as WDL or
+//! Nextflow to glob. Every file that is written is recorded here, and, with
+//! the global `--outputs-json` option, a manifest listing each of them (along
+//! with its type and checksums) is written once the subcommand succeeds.
+
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::{checksum, provenance::Provenance};
+
+/// The files written so far by this process, along with their types.
+static WRITTEN: Mutex<Vec<(PathBuf, &'static str)>> = Mutex::new(Vec::new());
+
+/// Records that a file of the provided type (e.g., `results`) was written.
+pub fn record<P: AsRef<Path>>(path: P, kind: &'static str) {
+    WRITTEN
+        .lock()
+        .unwrap()
+        .push((path.as_ref().to_path_buf(), kind));
+}
+
+/// A file written by `ngs`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputFile {
+    /// The (absolute) path to the file.
+    pub path: PathBuf,
+
+    /// The type of the file (e.g., `results` or `per-base-depth`).
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    /// The size of the file in bytes.
+    pub bytes: u64,
+
+    /// The MD5 of the file.
+    pub md5: String,
+
+    /// The SHA-256 of the file.
+    pub sha256: String,
+}
+
+/// The manifest written with `--outputs-json`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutputManifest {
+    /// Every file that was written, in the order they were first written.
+    pub outputs: Vec<OutputFile>,
+
+    /// The invocation of `ngs` that wrote the files.
+    pub provenance: Provenance,
+}
+
+/// Describes the files that were written. Files that were written more than
+/// once are only listed once, and files that no longer exist are skipped.
+pub fn manifest() -> anyhow::Result<OutputManifest> {
+    let written = WRITTEN.lock().unwrap().clone();
+    let mut outputs: Vec<OutputFile> = Vec::new();
+
+    for (path, kind) in written {
+        if !path.exists() {
+            continue;
+        }
+
+        let path = fs::canonicalize(&path)
+            .with_context(|| format!("Could not resolve output: {}", path.display()))?;
+        if outputs.iter().any(|output| output.path == path) {
+            continue;
+        }
+
+        let checksum = checksum::compute(&path)
+            .with_context(|| format!("Could not read output: {}", path.display()))?;
+        outputs.push(OutputFile {
+            path,
+            kind: kind.to_string(),
+            bytes: checksum.bytes,
+            md5: checksum.md5,
+            sha256: checksum.sha256,
+        });
+    }
+
+    Ok(OutputManifest {
+        outputs,
+        provenance: Provenance::now(),
+    })
+}
+
+/// Writes the manifest of the files that were written as JSON.
+pub fn write_manifest<P: AsRef<Path>>(dst: P) -> anyhow::Result<()> {
+    let path = dst.as_ref();
+    let manifest = manifest()?;
+
+    let file = File::create(path)
+        .with_context(|| format!("Could not create outputs manifest: {}", path.display()))?;
+    serde_json::to_writer_pretty(file, &manifest)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_lists_each_output_once() -> anyhow::Result<()> {
+        let directory = std::env::temp_dir().join(format!("ngs-outputs-{}", std::process::id()));
+        fs::create_dir_all(&directory)?;
+
+        let results = directory.join("sample.results.json");
+        let removed = directory.join("sample.partial.results.json");
+        fs::write(&results, b"abc")?;
+        fs::write(&removed, b"{}")?;
+
+        record(&removed, "partial-results");
+        record(&results, "results");
+        record(&results, "results");
+        fs::remove_file(&removed)?;
+
+        let manifest = manifest()?;
+        let outputs: Vec<_> = manifest
+            .outputs
+            .iter()
+            .filter(|output| {
+                output
+                    .path
+                    .starts_with(fs::canonicalize(&directory).unwrap())
+            })
+            .collect();
+
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].kind, "results");
+        assert_eq!(outputs[0].bytes, 3);
+        assert_eq!(outputs[0].md5, "900150983cd24fb0d6963f7d28e17f72");
+
+        fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
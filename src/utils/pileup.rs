@@ -0,0 +1,278 @@
+//! A streaming engine for computing the depth at each position of a sequence.
+//!
+//! Rather than incrementing a counter for every aligned base, each aligned
+//! block of a record only marks where the depth rises and falls (a difference
+//! array, as in mosdepth). The depth at each position is resolved in a single
+//! pass once every record on the sequence has been added, so the cost of each
+//! record is proportional to the number of operations in its CIGAR string
+//! rather than to its length.
+
+use noodles::sam::{alignment::Record, record::cigar::op::Kind};
+
+use super::cigar::consumes_reference;
+
+/// Accumulates the depth of the records aligned to one sequence.
+#[derive(Debug)]
+pub struct Pileup {
+    /// The change in depth at each (zero-based) position. The final element
+    /// absorbs the decrements of blocks that end at the end of the sequence.
+    deltas: Vec<i32>,
+}
+
+impl Pileup {
+    /// Creates a new [`Pileup`] for a sequence of the provided length.
+    pub fn new(length: usize) -> Self {
+        Self {
+            deltas: vec![0; length + 1],
+        }
+    }
+
+    /// The length of the sequence.
+    pub fn len(&self) -> usize {
+        self.deltas.len() - 1
+    }
+
+    /// Whether the sequence is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds one to the depth of the one-based, inclusive range of positions.
+    /// Any part of the range beyond the end of the sequence is ignored.
+    pub fn add(&mut self, start: usize, end: usize) {
+        let end = usize::min(end, self.len());
+        if start == 0 || start > end {
+            return;
+        }
+
+        self.deltas[start - 1] += 1;
+        self.deltas[end] -= 1;
+    }
+
+    /// Adds the aligned bases of a record. Only the matches and mismatches in
+    /// the CIGAR string contribute to the depth: deletions and skipped regions
+    /// (e.g., introns) do not. Returns `false` (and adds nothing) for records
+    /// that are not aligned.
+    pub fn add_record(&mut self, record: &Record) -> bool {
+        let mut position = match record.alignment_start() {
+            Some(start) => usize::from(start),
+            None => return false,
+        };
+
+        for op in record.cigar().iter() {
+            let len = op.len();
+
+            if matches!(
+                op.kind(),
+                Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch
+            ) {
+                self.add(position, position + len - 1);
+            }
+
+            if consumes_reference(op.kind()) {
+                position += len;
+            }
+        }
+
+        true
+    }
+
+    /// Resolves the depth at each position.
+    pub fn finish(self) -> Depths {
+        let mut depth = 0;
+        let mut values = self.deltas;
+        values.pop();
+
+        for value in values.iter_mut() {
+            depth += *value;
+            *value = depth;
+        }
+
+        Depths {
+            values: values.into_iter().map(|v| v.max(0) as u32).collect(),
+        }
+    }
+}
+
+/// A run of consecutive positions with the same depth.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Run {
+    /// The one-based start position of the run (inclusive).
+    pub start: usize,
+
+    /// The one-based end position of the run (inclusive).
+    pub end: usize,
+
+    /// The depth at each position within the run.
+    pub depth: u32,
+}
+
+/// A summary of the depth over a range of positions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthSummary {
+    /// The number of positions summarized.
+    pub length: usize,
+
+    /// The sum of the depth at each position (i.e., the number of aligned
+    /// bases).
+    pub bases: u64,
+
+    /// The minimum depth at any position.
+    pub min: u32,
+
+    /// The maximum depth at any position.
+    pub max: u32,
+}
+
+impl DepthSummary {
+    /// The mean depth over the positions, or zero if there are none.
+    pub fn mean(&self) -> f64 {
+        match self.length {
+            0 => 0.0,
+            length => self.bases as f64 / length as f64,
+        }
+    }
+}
+
+/// The resolved depth at each position of a sequence.
+#[derive(Debug)]
+pub struct Depths {
+    /// The depth at each position, indexed by the zero-based position.
+    values: Vec<u32>,
+}
+
+impl Depths {
+    /// The length of the sequence.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether the sequence is empty.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Gets the depth at a one-based position.
+    pub fn get(&self, position: usize) -> Option<u32> {
+        position
+            .checked_sub(1)
+            .and_then(|i| self.values.get(i).copied())
+    }
+
+    /// The depth at each position of a one-based, inclusive range, clipped to
+    /// the sequence.
+    fn slice(&self, start: usize, end: usize) -> &[u32] {
+        let end = usize::min(end, self.len());
+        match start {
+            0 => &[],
+            start if start > end => &[],
+            start => &self.values[start - 1..end],
+        }
+    }
+
+    /// Iterates over the runs of consecutive positions with the same depth,
+    /// which together span the entire sequence.
+    pub fn runs(&self) -> impl Iterator<Item = Run> + '_ {
+        let mut start = 1;
+
+        std::iter::from_fn(move || {
+            let depth = *self.values.get(start - 1)?;
+            let length = self.values[start - 1..]
+                .iter()
+                .take_while(|value| **value == depth)
+                .count();
+
+            let run = Run {
+                start,
+                end: start + length - 1,
+                depth,
+            };
+            start += length;
+
+            Some(run)
+        })
+    }
+
+    /// Summarizes the depth over a one-based, inclusive range of positions.
+    pub fn summarize(&self, start: usize, end: usize) -> DepthSummary {
+        let values = self.slice(start, end);
+
+        DepthSummary {
+            length: values.len(),
+            bases: values.iter().map(|v| u64::from(*v)).sum(),
+            min: values.iter().copied().min().unwrap_or(0),
+            max: values.iter().copied().max().unwrap_or(0),
+        }
+    }
+
+    /// Counts the positions within a one-based, inclusive range that have at
+    /// least each of the depth thresholds.
+    pub fn positions_at_or_above(
+        &self,
+        start: usize,
+        end: usize,
+        thresholds: &[u32],
+    ) -> Vec<usize> {
+        let values = self.slice(start, end);
+
+        thresholds
+            .iter()
+            .map(|threshold| values.iter().filter(|v| **v >= *threshold).count())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles::{
+        core::Position,
+        sam::{self, record::Cigar},
+    };
+
+    use super::*;
+
+    #[test]
+    pub fn it_resolves_the_depth_of_overlapping_blocks() -> anyhow::Result<()> {
+        let mut pileup = Pileup::new(10);
+        pileup.add(1, 4);
+        pileup.add(3, 6);
+        pileup.add(9, 20);
+
+        // 2M2D2M: the deletion does not contribute to the depth.
+        let record = sam::alignment::Record::builder()
+            .set_alignment_start(Position::try_from(2)?)
+            .set_cigar("2M2D2M".parse::<Cigar>()?)
+            .build();
+        assert!(pileup.add_record(&record));
+        assert!(!pileup.add_record(&sam::alignment::Record::default()));
+
+        let depths = pileup.finish();
+        let runs: Vec<_> = depths.runs().map(|r| (r.start, r.end, r.depth)).collect();
+        assert_eq!(
+            runs,
+            vec![
+                (1, 1, 1),
+                (2, 2, 2),
+                (3, 3, 3),
+                (4, 4, 2),
+                (5, 5, 1),
+                (6, 6, 2),
+                (7, 7, 1),
+                (8, 8, 0),
+                (9, 10, 1)
+            ]
+        );
+
+        let summary = depths.summarize(1, 10);
+        assert_eq!((summary.bases, summary.min, summary.max), (14, 0, 3));
+        assert_eq!(summary.mean(), 1.4);
+        assert_eq!(
+            depths.positions_at_or_above(1, 100, &[1, 2, 3]),
+            vec![9, 4, 1]
+        );
+        assert_eq!(depths.get(0), None);
+        assert_eq!(depths.get(3), Some(3));
+
+        Ok(())
+    }
+}
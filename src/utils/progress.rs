@@ -0,0 +1,148 @@
+//! Machine-parsable progress events.
+//!
+//! With the global `--progress-events` option, `ngs` writes a JSON object per
+//! line to the provided path (typically a named pipe or, on Linux,
+//! `/dev/fd/N`) each time it makes notable progress, so that GUIs and workflow
+//! monitors can display progress without scraping the logs. Each event
+//! describes the phase of the subcommand, the number of records processed
+//! within it, the reference sequence being processed (if any), and how
+//! complete the phase is (when it is known).
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Where progress events are written, if anywhere.
+static SINK: Mutex<Option<BufWriter<File>>> = Mutex::new(None);
+
+/// Writes progress events to the provided path.
+pub fn set_sink<P: AsRef<Path>>(path: P) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let file = File::create(path)
+        .with_context(|| format!("Could not open progress events: {}", path.display()))?;
+    *SINK.lock().unwrap() = Some(BufWriter::new(file));
+    Ok(())
+}
+
+/// A single progress event.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProgressEvent {
+    /// The time at which the event occurred.
+    pub time: DateTime<Utc>,
+
+    /// The phase of the subcommand (e.g., `first-pass`).
+    pub phase: String,
+
+    /// The number of records processed so far within the phase (or, if a
+    /// reference sequence is being processed, within the sequence).
+    pub records_processed: usize,
+
+    /// The reference sequence being processed, if any.
+    pub sequence: Option<String>,
+
+    /// How complete the phase is (from 0 to 100), if it is known.
+    pub percent_complete: Option<f64>,
+}
+
+impl ProgressEvent {
+    /// Creates a new [`ProgressEvent`] occurring now.
+    pub fn new(phase: &str, records_processed: usize) -> Self {
+        Self {
+            time: Utc::now(),
+            phase: phase.to_string(),
+            records_processed,
+            sequence: None,
+            percent_complete: None,
+        }
+    }
+
+    /// Sets the reference sequence being processed.
+    pub fn sequence(mut self, sequence: &str) -> Self {
+        self.sequence = Some(sequence.to_string());
+        self
+    }
+
+    /// Sets how complete the phase is from the amount of work done out of the
+    /// total, if the total is known.
+    pub fn complete(mut self, done: usize, total: Option<usize>) -> Self {
+        self.percent_complete = match total {
+            Some(0) => Some(100.0),
+            Some(total) => Some((done.min(total) as f64 / total as f64) * 100.0),
+            None => None,
+        };
+        self
+    }
+
+    /// Writes the event, if progress events were requested. Progress events
+    /// are a courtesy to whatever is monitoring `ngs`, so a failure to write
+    /// one (e.g., because the reader of the pipe went away) disables any
+    /// further events rather than failing the subcommand.
+    pub fn emit(self) {
+        let mut sink = SINK.lock().unwrap();
+
+        if let Some(out) = sink.as_mut() {
+            let written = serde_json::to_writer(&mut *out, &self)
+                .map_err(std::io::Error::from)
+                .and_then(|_| writeln!(out))
+                .and_then(|_| out.flush());
+
+            if let Err(e) = written {
+                warn!(
+                    "Could not write progress event, disabling progress events: {}",
+                    e
+                );
+                *sink = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    pub fn it_writes_one_event_per_line() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("ngs-progress-{}.jsonl", std::process::id()));
+        set_sink(&path)?;
+
+        ProgressEvent::new("progress-test", 10)
+            .complete(10, Some(40))
+            .emit();
+        ProgressEvent::new("progress-test", 20)
+            .sequence("chr1")
+            .complete(1, None)
+            .emit();
+
+        // Other tests may emit events while this one runs, so only the events
+        // of this test are examined.
+        let events: Vec<ProgressEvent> = fs::read_to_string(&path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<ProgressEvent>, _>>()?
+            .into_iter()
+            .filter(|event| event.phase == "progress-test")
+            .collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].records_processed, 10);
+        assert_eq!(events[0].percent_complete, Some(25.0));
+        assert_eq!(events[1].sequence.as_deref(), Some("chr1"));
+        assert_eq!(events[1].percent_complete, None);
+        assert!(events[0].time <= events[1].time);
+
+        *SINK.lock().unwrap() = None;
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+//! The provenance of the documents written by `ngs`.
+//!
+//! Every output document carries a `provenance` block describing the exact
+//! invocation that produced it: the full command line, the version of `ngs`
+//! (including the commit it was built from), the host it ran on, and when it
+//! started and finished. This allows results to be traced back to the run
+//! that produced them long after the fact. Configuration files that change the
+//! outcome of a run (such as the `--thresholds` for `ngs qc`) are recorded
+//! alongside, with the SHA-256 of their contents.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use chrono::{DateTime, Utc};
+use git_testament::{git_testament, render_testament};
+use serde::{Deserialize, Serialize};
+
+use super::checksum;
+
+git_testament!(TESTAMENT);
+
+/// The time at which the process started.
+static STARTED: OnceLock<DateTime<Utc>> = OnceLock::new();
+
+/// The configuration files read by the process.
+static CONFIG: Mutex<Vec<ConfigFile>> = Mutex::new(Vec::new());
+
+/// Records the time at which the process started. This should be called as
+/// early as possible: if it is never called, the start time is the first time
+/// that provenance is requested.
+pub fn start() {
+    STARTED.get_or_init(Utc::now);
+}
+
+/// Records that a configuration file was read, so that it is included in the
+/// provenance of every document written from then on.
+pub fn config<P: AsRef<Path>>(kind: &str, path: P) -> io::Result<()> {
+    let config = ConfigFile::read(kind, path)?;
+    CONFIG.lock().unwrap().push(config);
+    Ok(())
+}
+
+/// A configuration file that was read by `ngs`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigFile {
+    /// What the file configures (e.g., `thresholds`).
+    pub kind: String,
+
+    /// The path to the file.
+    pub path: PathBuf,
+
+    /// The SHA-256 of the contents of the file.
+    pub sha256: String,
+}
+
+impl ConfigFile {
+    /// Reads the configuration file at the provided path, digesting its
+    /// contents.
+    pub fn read<P: AsRef<Path>>(kind: &str, path: P) -> io::Result<Self> {
+        let checksum = checksum::compute(path)?;
+
+        Ok(Self {
+            kind: kind.to_string(),
+            path: checksum.path,
+            sha256: checksum.sha256,
+        })
+    }
+}
+
+/// The provenance of an output document.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Provenance {
+    /// The full command line, including the name of the executable.
+    pub command_line: Vec<String>,
+
+    /// The version of `ngs`, including the commit it was built from.
+    pub version: String,
+
+    /// The name of the host that `ngs` ran on, if it could be determined.
+    pub hostname: Option<String>,
+
+    /// The time at which `ngs` started.
+    pub started: DateTime<Utc>,
+
+    /// The time at which the document was written.
+    pub finished: DateTime<Utc>,
+
+    /// The configuration files that were read.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub config: Vec<ConfigFile>,
+}
+
+impl Provenance {
+    /// Describes the current invocation, finishing now.
+    pub fn now() -> Self {
+        Self::with_config(CONFIG.lock().unwrap().clone())
+    }
+
+    /// Describes the current invocation, finishing now, with the provided
+    /// configuration files.
+    pub fn with_config(config: Vec<ConfigFile>) -> Self {
+        let started = *STARTED.get_or_init(Utc::now);
+
+        Self {
+            command_line: std::env::args_os()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            version: render_testament!(TESTAMENT),
+            hostname: hostname(),
+            started,
+            finished: Utc::now(),
+            config,
+        }
+    }
+}
+
+/// Determines the name of the host without resorting to platform-specific
+/// calls: the kernel's record of it is used where available (Linux), falling
+/// back to the environment otherwise.
+fn hostname() -> Option<String> {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .chain(
+            ["HOSTNAME", "COMPUTERNAME"]
+                .iter()
+                .filter_map(|key| std::env::var(key).ok()),
+        )
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_describes_the_current_invocation() -> anyhow::Result<()> {
+        start();
+        let provenance = Provenance::now();
+
+        assert!(!provenance.command_line.is_empty());
+        assert!(!provenance.version.is_empty());
+        assert!(provenance.started <= provenance.finished);
+
+        // Later documents share the same start time.
+        let later = Provenance::now();
+        assert_eq!(later.started, provenance.started);
+        assert!(later.finished >= provenance.finished);
+
+        // Configuration files are recorded with the digest of their contents.
+        let path = std::env::temp_dir().join(format!("ngs-config-{}.json", std::process::id()));
+        std::fs::write(&path, "abc")?;
+        let config = ConfigFile::read("thresholds", &path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(config.path, path);
+        assert_eq!(
+            config.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let provenance = Provenance::with_config(vec![config.clone()]);
+        let value = serde_json::to_value(&provenance)?;
+        assert_eq!(value["config"][0]["kind"], "thresholds");
+        assert_eq!(
+            serde_json::from_value::<Provenance>(value)?.config,
+            vec![config]
+        );
+
+        // Nothing is written when no configuration files were read.
+        let value = serde_json::to_value(Provenance::with_config(Vec::new()))?;
+        assert!(value.get("config").is_none());
+
+        Ok(())
+    }
+}
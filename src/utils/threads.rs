@@ -0,0 +1,71 @@
+//! Utilities related to the number of threads used by `ngs`.
+//!
+//! The number of threads is set once for the process with the global
+//! `--threads` option. Each subcommand decides how to use them: for example,
+//! `ngs qc` decompresses the BGZF blocks of its sequential first pass with a
+//! pool of that many workers, and `ngs derive instrument` sizes its runtime
+//! with it.
+
+use std::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// The number of threads requested for this process, or zero if none was
+/// requested.
+static THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the number of threads requested for this process.
+pub fn set_threads(threads: Option<NonZeroUsize>) {
+    THREADS.store(threads.map_or(0, usize::from), Ordering::Relaxed);
+}
+
+/// The number of threads requested for this process, if any.
+pub fn requested() -> Option<NonZeroUsize> {
+    NonZeroUsize::new(THREADS.load(Ordering::Relaxed))
+}
+
+/// The number of threads requested for this process or, if none was
+/// requested, the number of threads available on the machine.
+pub fn requested_or_available() -> anyhow::Result<NonZeroUsize> {
+    or_available(requested())
+}
+
+/// The provided number of threads or, if none, the number of threads
+/// available on the machine.
+fn or_available(threads: Option<NonZeroUsize>) -> anyhow::Result<NonZeroUsize> {
+    match threads {
+        Some(threads) => Ok(threads),
+        None => Ok(thread::available_parallelism()?),
+    }
+}
+
+/// The number of workers that decompress BGZF blocks. Unless more threads
+/// were requested, the blocks are decompressed on the thread reading them.
+pub fn bgzf_worker_count() -> NonZeroUsize {
+    bgzf_workers(requested())
+}
+
+/// The number of workers that decompress BGZF blocks for the provided number
+/// of threads.
+fn bgzf_workers(threads: Option<NonZeroUsize>) -> NonZeroUsize {
+    threads.unwrap_or(NonZeroUsize::MIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_defaults_to_a_single_bgzf_worker() {
+        assert_eq!(bgzf_workers(None).get(), 1);
+        assert_eq!(bgzf_workers(NonZeroUsize::new(4)).get(), 4);
+
+        assert_eq!(or_available(NonZeroUsize::new(4)).unwrap().get(), 4);
+        assert_eq!(
+            or_available(None).unwrap(),
+            thread::available_parallelism().unwrap()
+        );
+    }
+}
@@ -34,7 +34,7 @@ pub fn view(src: PathBuf, query: Option<String>, show_header: bool) -> anyhow::R
     }
 
     // (4) Parse the header and reference sequences.
-    let header = parse_header(ht);
+    let header = parse_header(ht)?;
     reader
         .read_reference_sequences()
         .with_context(|| "reading reference sequences")?;
@@ -65,7 +65,7 @@ pub fn view(
     }
 
     // (4) Parse the header.
-    let header = parse_header(ht);
+    let header = parse_header(ht)?;
 
     let mut writer = sam::Writer::new(handle);
     if let Some(query) = query {
@@ -0,0 +1,490 @@
+//! Golden-file integration tests.
+//!
+//! Each test runs a full `ngs` subcommand over the mini-BAM (and CRAM and
+//! FASTQ) fixtures in `tests/fixtures` and compares the JSON it emits against the
+//! golden output in `tests/golden`. Numbers are compared with a small
+//! tolerance (so that, e.g., summing in a different order does not fail the
+//! test), while everything else must match exactly. Fields that vary between
+//! runs (such as the timings in `runtime` and the `provenance` of the run) are
+//! ignored.
+//!
+//! When a change to the results is intended, regenerate the golden outputs
+//! with `NGS_UPDATE_GOLDEN=1 cargo test --test golden` and review the diff.
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use ngs::qc::results::Results;
+use serde_json::Value;
+
+/// The absolute difference below which two numbers are considered equal.
+const ABSOLUTE_TOLERANCE: f64 = 1e-9;
+
+/// The relative difference below which two numbers are considered equal.
+const RELATIVE_TOLERANCE: f64 = 1e-6;
+
+/// Keys whose values vary between runs and are not compared.
+const IGNORED_KEYS: &[&str] = &["runtime", "provenance"];
+
+/// The reference genome the fixtures are analyzed against.
+const REFERENCE_GENOME: &str = "GRCh38_no_alt_AnalysisSet";
+
+/// The results of `ngs qc` that are computed in the second pass, which
+/// requires an index (as JSON pointers).
+const SECOND_PASS_RESULTS: &[&str] = &[
+    "/coverage",
+    "/edits",
+    "/gc_content/reference",
+    "/oxog",
+    "/sequencing_artifacts",
+    "/allele_balance",
+];
+
+//=========//
+// Harness //
+//=========//
+
+/// The path to a fixture.
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+/// A temporary directory that is unique to this test and process.
+fn temp_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ngs-golden-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+/// Runs `ngs` with the arguments and returns its stdout, failing the test if
+/// the command does not succeed.
+fn ngs<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let output = Command::new(env!("CARGO_BIN_EXE_ngs"))
+        .arg("--quiet")
+        .args(args)
+        .output()
+        .expect("ngs to run");
+
+    assert!(
+        output.status.success(),
+        "ngs failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8(output.stdout).expect("stdout to be UTF-8")
+}
+
+/// Runs `ngs qc` over an alignment fixture (with any extra arguments) and
+/// returns the results document.
+fn qc(name: &str, bam: &str, extra: &[&str]) -> Value {
+    let directory = temp_dir(name);
+    let mut args = vec![
+        String::from("qc"),
+        fixture(bam).display().to_string(),
+        String::from(REFERENCE_GENOME),
+        String::from("-o"),
+        directory.display().to_string(),
+    ];
+    args.extend(extra.iter().map(|arg| arg.to_string()));
+    ngs(&args);
+
+    let results = directory.join(format!("{}.results.json", bam));
+    let contents = std::fs::read_to_string(&results).expect("results to be written");
+    std::fs::remove_dir_all(&directory).unwrap();
+    serde_json::from_str(&contents).expect("results to be valid JSON")
+}
+
+/// The path to the golden output with the given name.
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.json", name))
+}
+
+/// Reads the golden output with the given name.
+fn read_golden(name: &str) -> Value {
+    let path = golden_path(name);
+    let contents = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "golden output {} is missing: run with NGS_UPDATE_GOLDEN=1 to create it",
+            path.display()
+        )
+    });
+    serde_json::from_str(&contents).expect("golden output to be valid JSON")
+}
+
+/// Compares the output against the golden output with the given name, or
+/// overwrites the golden output if `NGS_UPDATE_GOLDEN` is set.
+fn assert_golden(name: &str, actual: &Value) {
+    if std::env::var_os("NGS_UPDATE_GOLDEN").is_some() {
+        let mut contents = serde_json::to_string_pretty(&strip(actual)).unwrap();
+        contents.push('\n');
+        std::fs::write(golden_path(name), contents).unwrap();
+        return;
+    }
+
+    assert_matches(name, &read_golden(name), actual);
+}
+
+/// Compares the output against the expected output (read from the golden
+/// output with the given name).
+fn assert_matches(name: &str, expected: &Value, actual: &Value) {
+    let path = golden_path(name);
+    let mut differences = Vec::new();
+    compare("$", expected, &strip(actual), &mut differences);
+
+    assert!(
+        differences.is_empty(),
+        "output differs from {} in {} place(s):\n{}",
+        path.display(),
+        differences.len(),
+        differences
+            .iter()
+            .take(20)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+}
+
+/// Removes the ignored keys from the value.
+fn strip(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(key, _)| !IGNORED_KEYS.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), strip(value)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.iter().map(strip).collect()),
+        _ => value.clone(),
+    }
+}
+
+/// Records the differences between the expected and actual values, using a
+/// JSONPath-like path to locate them.
+fn compare(path: &str, expected: &Value, actual: &Value, differences: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, value) in expected {
+                match actual.get(key) {
+                    Some(other) => compare(&format!("{}.{}", path, key), value, other, differences),
+                    None => differences.push(format!("{}.{}: missing", path, key)),
+                }
+            }
+
+            for key in actual.keys().filter(|key| !expected.contains_key(*key)) {
+                differences.push(format!("{}.{}: unexpected", path, key));
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) if expected.len() == actual.len() => {
+            for (i, (value, other)) in expected.iter().zip(actual).enumerate() {
+                compare(&format!("{}[{}]", path, i), value, other, differences);
+            }
+        }
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (a.as_f64().unwrap(), b.as_f64().unwrap());
+            let difference = (a - b).abs();
+            if difference > ABSOLUTE_TOLERANCE && difference > RELATIVE_TOLERANCE * a.abs() {
+                differences.push(format!("{}: expected {}, found {}", path, a, b));
+            }
+        }
+        _ if expected == actual => {}
+        _ => differences.push(format!("{}: expected {}, found {}", path, expected, actual)),
+    }
+}
+
+//=======//
+// Tests //
+//=======//
+
+#[test]
+fn qc_paired_with_reference_and_sites() {
+    let fasta = fixture("paired.fa").display().to_string();
+    let vcf = fixture("paired.sites.vcf").display().to_string();
+    let results = qc(
+        "qc-paired",
+        "paired.bam",
+        &["-r", &fasta, "--sites-vcf", &vcf],
+    );
+    assert_golden("qc-paired", &results);
+}
+
+#[test]
+fn qc_paired_without_prefetching_or_reuse() {
+    // Reading strategies must not change the results.
+    let fasta = fixture("paired.fa").display().to_string();
+    let vcf = fixture("paired.sites.vcf").display().to_string();
+    let results = qc(
+        "qc-paired-no-prefetch",
+        "paired.bam",
+        &[
+            "-r",
+            &fasta,
+            "--sites-vcf",
+            &vcf,
+            "--no-prefetch",
+            "--no-reuse",
+        ],
+    );
+
+    if std::env::var_os("NGS_UPDATE_GOLDEN").is_none() {
+        assert_golden("qc-paired", &results);
+    }
+}
+
+#[test]
+fn qc_cram_matches_bam() {
+    // A CRAM copy of the BAM fixture (decoded against the reference FASTA)
+    // must produce the same results, apart from the CRAM facet itself.
+    let fasta = fixture("paired.fa").display().to_string();
+    let vcf = fixture("paired.sites.vcf").display().to_string();
+    let mut results = qc(
+        "qc-paired-cram",
+        "paired.cram",
+        &["-r", &fasta, "--sites-vcf", &vcf],
+    );
+
+    // The records of both sequences share a single (multi-reference) slice,
+    // so each sequence of the second pass must skip those of the other.
+    let cram = std::mem::take(&mut results["cram"]);
+    assert_eq!(cram["records"], 1200);
+    assert_eq!(cram["multi_reference_slices"], 1);
+
+    if std::env::var_os("NGS_UPDATE_GOLDEN").is_none() {
+        assert_golden("qc-paired", &results);
+    }
+}
+
+#[test]
+fn qc_sam_matches_bam() {
+    // A SAM copy of the BAM fixture must produce the same results. Every
+    // record has more than one tag (`RG`, `NM`, and `AS`), all of which must
+    // be read for, e.g., the Mapping Quality facet to match.
+    let fasta = fixture("paired.fa").display().to_string();
+    let vcf = fixture("paired.sites.vcf").display().to_string();
+    let results = qc(
+        "qc-paired-sam",
+        "paired.sam",
+        &["-r", &fasta, "--sites-vcf", &vcf],
+    );
+
+    if std::env::var_os("NGS_UPDATE_GOLDEN").is_some() {
+        return;
+    }
+
+    // SAM files cannot be indexed, so the second pass is skipped and only
+    // the results of the first pass are compared.
+    let mut expected = read_golden("qc-paired");
+    for pointer in SECOND_PASS_RESULTS {
+        assert_eq!(results.pointer(pointer), Some(&Value::Null), "{}", pointer);
+        *expected.pointer_mut(pointer).unwrap() = Value::Null;
+    }
+
+    assert_matches("qc-paired", &expected, &results);
+}
+
+#[test]
+fn qc_diff_against_the_golden_results() {
+    if std::env::var_os("NGS_UPDATE_GOLDEN").is_some() {
+        return;
+    }
+
+    // The counts match the golden results, and, with `--quiet`, nothing is
+    // written to stdout. The baseline is recorded in the provenance.
+    let baseline = golden_path("qc-paired");
+    let directory = temp_dir("qc-paired-diff");
+    let stdout = ngs([
+        OsStr::new("qc"),
+        fixture("paired.bam").as_os_str(),
+        OsStr::new(REFERENCE_GENOME),
+        OsStr::new("-o"),
+        directory.as_os_str(),
+        OsStr::new("--diff"),
+        baseline.as_os_str(),
+    ]);
+    assert_eq!(stdout, "");
+
+    let contents = std::fs::read_to_string(directory.join("paired.bam.results.json"))
+        .expect("results to be written");
+    std::fs::remove_dir_all(&directory).unwrap();
+    let results: Value = serde_json::from_str(&contents).expect("results to be valid JSON");
+
+    let config = &results["provenance"]["config"][0];
+    assert_eq!(config["kind"], "baseline");
+    assert_eq!(config["path"], baseline.display().to_string());
+    assert_eq!(config["sha256"].as_str().map(str::len), Some(64));
+}
+
+#[test]
+fn qc_output_is_byte_stable_across_runs() {
+    // Each run is a separate process (and so has differently seeded hashers):
+    // the serialized keys must come out in the same order every time.
+    let first = qc("qc-stable-first", "sra.bam", &[]);
+    let second = qc("qc-stable-second", "sra.bam", &[]);
+
+    assert_eq!(
+        serde_json::to_string(&strip(&first)).unwrap(),
+        serde_json::to_string(&strip(&second)).unwrap()
+    );
+}
+
+#[test]
+fn qc_results_round_trip_through_the_typed_structs() {
+    let fasta = fixture("paired.fa").display().to_string();
+    let vcf = fixture("paired.sites.vcf").display().to_string();
+    let paired = qc(
+        "qc-round-trip-paired",
+        "paired.bam",
+        &["-r", &fasta, "--sites-vcf", &vcf],
+    );
+    let sra = qc("qc-round-trip-sra", "sra.bam", &[]);
+    // Only CRAM files have results for the CRAM facet.
+    let cram = qc("qc-round-trip-cram", "paired.cram", &["-r", &fasta]);
+
+    for expected in [paired, sra, cram] {
+        let results: Results = serde_json::from_value(expected.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&results).unwrap(), expected);
+    }
+}
+
+#[test]
+fn qc_sra_gradient() {
+    let results = qc("qc-sra", "sra.bam", &[]);
+    assert_golden("qc-sra", &results);
+}
+
+#[test]
+fn derive_instrument() {
+    let output = ngs([
+        "derive",
+        "instrument",
+        &fixture("paired.bam").display().to_string(),
+    ]);
+    assert_golden("derive-instrument", &serde_json::from_str(&output).unwrap());
+}
+
+#[test]
+fn derive_instrument_from_cram() {
+    let output = ngs([
+        "derive",
+        "instrument",
+        &fixture("paired.cram").display().to_string(),
+        "-r",
+        &fixture("paired.fa").display().to_string(),
+    ]);
+
+    if std::env::var_os("NGS_UPDATE_GOLDEN").is_none() {
+        assert_golden("derive-instrument", &serde_json::from_str(&output).unwrap());
+    }
+}
+
+#[test]
+fn derive_instrument_stops_early() {
+    // Stopping early takes more records than the fixtures have, so a larger
+    // file (of 12,000 records from a single flowcell) is generated.
+    let directory = temp_dir("derive-instrument-early-stop");
+    let bam = directory.join("early-stop.bam").display().to_string();
+    ngs([
+        "generate",
+        "synthetic",
+        "--bam",
+        &bam,
+        "--sequences",
+        "1",
+        "--sequence-length",
+        "20000",
+        "--read-length",
+        "100",
+        "--coverage",
+        "60",
+        "--seed",
+        "4",
+    ]);
+
+    let derive = |extra: &[&str]| -> Value {
+        let mut args = vec!["derive", "instrument", &bam];
+        args.extend(extra);
+        serde_json::from_str(&ngs(&args)).unwrap()
+    };
+    let whole = derive(&[]);
+    let stopped = derive(&["--early-stop", "1000"]);
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(whole["records_processed"], 12_000);
+    assert_eq!(whole["stopped_early"], false);
+
+    // The evidence is stable from the first record, but at least 10,000
+    // records are examined so that the confidence is not lowered.
+    assert_eq!(stopped["records_processed"], 10_000);
+    assert_eq!(stopped["stopped_early"], true);
+    for key in ["succeeded", "instruments", "confidence", "conflict"] {
+        assert_eq!(stopped[key], whole[key], "{}", key);
+    }
+}
+
+#[test]
+fn derive_readname_format() {
+    let output = ngs([
+        "derive",
+        "readname-format",
+        &fixture("sra.bam").display().to_string(),
+    ]);
+    assert_golden(
+        "derive-readname-format",
+        &serde_json::from_str(&output).unwrap(),
+    );
+}
+
+#[test]
+fn qc_fastq_pairs() {
+    let output = ngs([
+        "qc",
+        "fastq-pairs",
+        &fixture("paired_R1.fastq").display().to_string(),
+        &fixture("paired_R2.fastq").display().to_string(),
+    ]);
+    assert_golden("qc-fastq-pairs", &serde_json::from_str(&output).unwrap());
+}
+
+#[test]
+fn triage() {
+    let output = ngs(["triage", &fixture("sra.bam").display().to_string()]);
+    assert_golden("triage-sra", &serde_json::from_str(&output).unwrap());
+}
+
+#[test]
+fn it_compares_numbers_with_a_tolerance() {
+    let expected: Value = serde_json::from_str(r#"{"a": 1.0, "b": [1, 2], "c": "x"}"#).unwrap();
+    let close: Value =
+        serde_json::from_str(r#"{"a": 1.0000000001, "b": [1, 2], "c": "x", "runtime": 5}"#)
+            .unwrap();
+    let far: Value = serde_json::from_str(r#"{"a": 1.1, "b": [1], "d": "x"}"#).unwrap();
+
+    let mut differences = Vec::new();
+    compare("$", &expected, &strip(&close), &mut differences);
+    assert!(differences.is_empty(), "{:?}", differences);
+
+    compare("$", &expected, &strip(&far), &mut differences);
+    assert_eq!(
+        differences,
+        vec![
+            "$.a: expected 1, found 1.1",
+            "$.b: expected [1,2], found [1]",
+            "$.c: missing",
+            "$.d: unexpected",
+        ]
+    );
+}